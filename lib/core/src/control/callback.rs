@@ -120,7 +120,7 @@ impl CallbackRegistry {
 /// handle lifetimes are strictly connected. As soon a handle is dropped, the callback is removed
 /// as well.
 #[derive(Derivative)]
-#[derivative(Debug, Default)]
+#[derivative(Debug, Default(bound=""))]
 pub struct CallbackRegistry1<T:Copy> {
     #[derivative(Debug="ignore")]
     callback_list: Vec<(Guard, CallbackMut1<T>)>
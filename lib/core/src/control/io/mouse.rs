@@ -32,7 +32,9 @@ pub struct EventListener<T:?Sized> {
 }
 
 impl<T:?Sized> EventListener<T> {
-    fn new<Str:AsRef<str>>(target:EventTarget, name:Str, callback:Closure<T>) -> Self {
+    /// Constructor. The `callback` should already be registered as the `name` event listener of
+    /// the `target`.
+    pub fn new<Str:AsRef<str>>(target:EventTarget, name:Str, callback:Closure<T>) -> Self {
         let name = name.as_ref().to_string();
         Self { target,name,callback }
     }
@@ -11,6 +11,7 @@ use crate::prelude::*;
 use crate::data::function::callback::*;
 use rustc_hash::FxHashSet;
 use std::hash::Hash;
+use std::cmp::Ordering;
 use std::mem;
 use std::ops;
 
@@ -374,6 +375,146 @@ impl<Ix:RangeIx> Display for RangeData<Ix> {
 
 
 
+// ================
+// === RangeSet ===
+// ================
+
+/// Dirty flag which keeps a sorted set of disjoint ranges of dirty indexes. Ranges separated by at
+/// most `max_gap` clean indexes are merged, as it is often cheaper to process a few clean items
+/// than to process every range separately. The `max_gap` of 0 merges only adjacent ranges.
+
+pub type  RangeSet       <OnMut> = DirtyFlag       <RangeSetData,OnMut>;
+pub type  SharedRangeSet <OnMut> = SharedDirtyFlag <RangeSetData,OnMut>;
+pub trait RangeSetCtx    <OnMut> = where OnMut:Function0;
+
+/// The default value of `RangeSetData::max_gap`.
+pub const DEFAULT_MAX_GAP : usize = 16;
+
+#[derive(Debug)]
+pub struct RangeSetData {
+    ranges  : Vec<ops::RangeInclusive<usize>>,
+    max_gap : usize,
+}
+
+impl Default for RangeSetData {
+    fn default() -> Self {
+        let ranges  = default();
+        let max_gap = DEFAULT_MAX_GAP;
+        Self {ranges,max_gap}
+    }
+}
+
+impl RangeSetData {
+    /// The dirty ranges, sorted and disjoint.
+    pub fn ranges(&self) -> &[ops::RangeInclusive<usize>] {
+        &self.ranges
+    }
+
+    /// Removes all the ranges and returns them. Unlike `DirtyFlag::take`, it keeps the `max_gap`.
+    pub fn take_ranges(&mut self) -> Vec<ops::RangeInclusive<usize>> {
+        mem::take(&mut self.ranges)
+    }
+
+    /// The maximum number of clean indexes between two ranges which get merged.
+    pub fn max_gap(&self) -> usize {
+        self.max_gap
+    }
+
+    /// Sets the maximum number of clean indexes between two ranges which get merged. It affects
+    /// only the ranges set after this call.
+    pub fn set_max_gap(&mut self, max_gap:usize) {
+        self.max_gap = max_gap;
+    }
+
+    /// Index of the first range which may absorb the `ix` index.
+    fn lower_bound(&self, ix:usize) -> usize {
+        let gap = self.max_gap;
+        let cmp = |r:&ops::RangeInclusive<usize>|
+            if r.end().saturating_add(gap).saturating_add(1) < ix { Ordering::Less }
+            else                                                  { Ordering::Greater };
+        self.ranges.binary_search_by(cmp).unwrap_err()
+    }
+
+    /// Index of the range containing the `ix` index.
+    fn position(&self, ix:usize) -> Option<usize> {
+        let cmp = |r:&ops::RangeInclusive<usize>|
+            if      *r.end()   < ix { Ordering::Less    }
+            else if *r.start() > ix { Ordering::Greater }
+            else                    { Ordering::Equal   };
+        self.ranges.binary_search_by(cmp).ok()
+    }
+}
+
+impl HasArg      for RangeSetData { type Arg = usize; }
+impl HasCheckAll for RangeSetData { fn check_all(&self) -> bool { !self.ranges.is_empty() } }
+impl HasUnsetAll for RangeSetData { fn unset_all(&mut self)     {  self.ranges.clear()    } }
+
+impl HasCheck1 for RangeSetData {
+    fn check(&self, ix:&usize) -> bool {
+        self.position(*ix).is_some()
+    }
+}
+
+impl HasSet1 for RangeSetData {
+    fn set(&mut self, ix:usize) {
+        let gap     = self.max_gap;
+        let index   = self.lower_bound(ix);
+        let reach   = |end:usize| end.saturating_add(gap).saturating_add(1);
+        let current = self.ranges.get(index).cloned().filter(|r| *r.start() <= reach(ix));
+        match current {
+            None    => self.ranges.insert(index, ix ..= ix),
+            Some(r) => {
+                let start = ix.min(*r.start());
+                let end   = ix.max(*r.end());
+                let next  = self.ranges.get(index + 1).map(|n| n.clone().into_inner());
+                let end   = match next {
+                    Some((next_start,next_end)) if next_start <= reach(end) => {
+                        self.ranges.remove(index + 1);
+                        next_end
+                    }
+                    _ => end
+                };
+                self.ranges[index] = start ..= end;
+            }
+        }
+    }
+}
+
+impl HasUnset1 for RangeSetData {
+    fn unset(&mut self, ix:&usize) {
+        let ix = *ix;
+        if let Some(index) = self.position(ix) {
+            let (start,end) = self.ranges[index].clone().into_inner();
+            let left        = if ix > start { Some(start ..= ix - 1) } else { None };
+            let right       = if ix < end   { Some(ix + 1 ..= end)   } else { None };
+            self.ranges.splice(index ..= index, left.into_iter().chain(right));
+        }
+    }
+}
+
+impl Display for RangeSetData {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        if self.ranges.is_empty() { write!(f,"false") } else {
+            let ranges = self.ranges.iter().map(|r| format!("[{}...{}]",r.start(),r.end()));
+            write!(f,"{}",ranges.collect_vec().join(","))
+        }
+    }
+}
+
+impl<OnMut> SharedRangeSet<OnMut> {
+    /// Removes all the ranges and returns them. It keeps the `max_gap` setting.
+    pub fn take_ranges(&self) -> Vec<ops::RangeInclusive<usize>> {
+        self.rc.borrow_mut().data.take_ranges()
+    }
+
+    /// Sets the maximum number of clean indexes between two ranges which get merged.
+    pub fn set_max_gap(&self, max_gap:usize) {
+        self.rc.borrow_mut().data.set_max_gap(max_gap)
+    }
+}
+
+
+
 // ===========
 // === Set ===
 // ===========
@@ -497,4 +638,62 @@ impl<Prim:EnumBase,T:EnumElem> Display for EnumData<Prim,T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f,"{}",self.check_all())
     }
-}
\ No newline at end of file
+}
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range_set(max_gap:usize, ixs:&[usize]) -> RangeSetData {
+        let mut data = RangeSetData::default();
+        data.set_max_gap(max_gap);
+        ixs.iter().for_each(|ix| data.set(*ix));
+        data
+    }
+
+    #[test]
+    fn range_set_disjoint_ranges() {
+        let data = range_set(0,&[0,1_000_000,5]);
+        assert_eq!(data.ranges(),&[0..=0,5..=5,1_000_000..=1_000_000]);
+        assert!( data.check(&5));
+        assert!(!data.check(&4));
+    }
+
+    #[test]
+    fn range_set_merges_adjacent() {
+        assert_eq!(range_set(0,&[3,1,2]).ranges(),&[1..=3]);
+        assert_eq!(range_set(0,&[1,5,3,2,4]).ranges(),&[1..=5]);
+        assert_eq!(range_set(0,&[2,2,2]).ranges(),&[2..=2]);
+    }
+
+    #[test]
+    fn range_set_merges_within_gap() {
+        assert_eq!(range_set(2,&[0,3]).ranges(),&[0..=3]);
+        assert_eq!(range_set(2,&[0,4]).ranges(),&[0..=0,4..=4]);
+        assert_eq!(range_set(2,&[0,10,8]).ranges(),&[0..=0,8..=10]);
+        assert_eq!(range_set(2,&[0,6,3]).ranges(),&[0..=6]);
+    }
+
+    #[test]
+    fn range_set_unset() {
+        let mut data = range_set(0,&[1,2,3,4,8]);
+        data.unset(&3);
+        assert_eq!(data.ranges(),&[1..=2,4..=4,8..=8]);
+        data.unset(&8);
+        data.unset(&1);
+        assert_eq!(data.ranges(),&[2..=2,4..=4]);
+    }
+
+    #[test]
+    fn range_set_take_keeps_gap() {
+        let mut data = range_set(7,&[1,20]);
+        assert_eq!(data.take_ranges(),vec![1..=1,20..=20]);
+        assert!(!data.check_all());
+        assert_eq!(data.max_gap(),7);
+    }
+}
@@ -50,6 +50,15 @@ IndexMut<Ix> for Observable<T,OnMut,OnResize> {
     }
 }
 
+impl<T,OnMut,OnResize:Function0>
+Observable<Vec<T>,OnMut,OnResize> {
+    /// Shortens the vector, keeping the first `len` elements.
+    pub fn truncate(&mut self, len:usize) {
+        self.on_resize.call();
+        self.data.truncate(len)
+    }
+}
+
 impl <T:Extend<S>,S,OnMut,OnResize:Function0>
 Extend<S> for Observable<T,OnMut,OnResize> {
     #[inline]
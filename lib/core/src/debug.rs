@@ -1,5 +1,6 @@
 //! This is the root module of debug utilities, including realtime rendering statistics.
 
+pub mod capture;
 pub mod monitor;
 pub mod stats;
 
@@ -0,0 +1,443 @@
+//! This module implements frame capture. When a capture is requested, every call issued through
+//! the `GpuContext` implementation of the WebGL context during the next frame is recorded. The
+//! calls are replayed on a `MockContext`, so they are logged in the same format the native tests
+//! use. WebGL objects, like programs and buffers, are replaced by identifiers stable between
+//! frames, so captures of different frames can be serialized to JSON and diffed offline.
+
+use crate::prelude::*;
+
+use crate::system::gpu::Context;
+use crate::system::gpu::context::mock::Call;
+use crate::system::gpu::context::mock::MockContext;
+use crate::system::gpu::context::mock::MockId;
+use crate::system::gpu::context::mock::MockUniformLocation;
+use crate::system::web;
+
+use js_sys::Array;
+use js_sys::Object;
+use js_sys::WeakMap;
+use serde::Serialize;
+use shapely::shared;
+use std::cell::Cell;
+use std::mem;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::JsValue;
+use web_sys::WebGlProgram;
+use web_sys::WebGlUniformLocation;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// The default name of a downloaded capture file.
+pub const DEFAULT_FILE_NAME : &str = "frame_capture.json";
+
+/// Time in milliseconds after which the URL of a downloaded capture is revoked. Some browsers
+/// cancel the download if the URL is revoked before it starts.
+const URL_REVOKE_DELAY_MS : i32 = 1000;
+
+
+
+// ====================
+// === FrameCapture ===
+// ====================
+
+/// All GPU calls issued during a single frame, in the order they were issued.
+#[derive(Clone,Debug,Default,PartialEq,Serialize)]
+pub struct FrameCapture {
+    /// The time of the frame in milliseconds since the world was created.
+    pub time  : f64,
+    /// The recorded calls.
+    pub calls : Vec<Call>,
+}
+
+impl FrameCapture {
+    /// Constructor.
+    pub fn new(time:f64, calls:Vec<Call>) -> Self {
+        Self {time,calls}
+    }
+
+    /// Iterator over the recorded draw calls.
+    pub fn draw_calls(&self) -> impl Iterator<Item=&Call> {
+        self.calls.iter().filter(|call| call.is_draw())
+    }
+
+    /// Serializes the capture to JSON. Every argument is placed in a separate line, which makes
+    /// the result easy to diff.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Saves the capture as a JSON file, using the browser download mechanism.
+    pub fn download(&self, file_name:&str) -> Result<(),JsValue> {
+        let json        = self.to_json().map_err(|err| JsValue::from(err.to_string()))?;
+        let parts       = Array::of1(&json.into());
+        let mut options = web_sys::BlobPropertyBag::new();
+        options.type_("application/json");
+        let blob = web_sys::Blob::new_with_str_sequence_and_options(&parts,&options)?;
+        let url  = web_sys::Url::create_object_url_with_blob(&blob)?;
+        let link = web::create_element("a").map_err(|err| JsValue::from(err.to_string()))?;
+        link.set_attribute("href",&url)?;
+        link.set_attribute("download",file_name)?;
+        link.unchecked_ref::<web_sys::HtmlElement>().click();
+        let revoke  = move || { web_sys::Url::revoke_object_url(&url).ok(); };
+        let revoke  = Closure::once_into_js(revoke);
+        let timeout = URL_REVOKE_DELAY_MS;
+        web::window().set_timeout_with_callback_and_timeout_and_arguments_0
+            (revoke.unchecked_ref(),timeout)?;
+        Ok(())
+    }
+}
+
+
+
+// =================
+// === ObjectIds ===
+// =================
+
+/// Identifiers of the WebGL objects seen by a recorder. Objects are held weakly, so they can be
+/// garbage collected after they are deleted.
+#[derive(Debug)]
+pub struct ObjectIds {
+    ids       : WeakMap,
+    locations : WeakMap,
+    next_id   : Cell<usize>,
+}
+
+impl Default for ObjectIds {
+    fn default() -> Self {
+        let ids       = WeakMap::new();
+        let locations = WeakMap::new();
+        let next_id   = default();
+        Self {ids,locations,next_id}
+    }
+}
+
+impl ObjectIds {
+    /// The identifier of the object. A new one is assigned if the object was not seen yet.
+    pub fn get<T:AsRef<JsValue>>(&self, object:&T) -> MockId {
+        let object = object.as_ref().unchecked_ref::<Object>();
+        match self.ids.get(object).as_f64() {
+            Some(id) => MockId(id as usize),
+            None     => {
+                let id = self.next_id.get();
+                self.next_id.set(id + 1);
+                self.ids.set(object,&(id as f64).into());
+                MockId(id)
+            }
+        }
+    }
+
+    /// The identifier of the object, if any.
+    pub fn get_opt<T:AsRef<JsValue>>(&self, object:Option<&T>) -> Option<MockId> {
+        object.map(|object| self.get(object))
+    }
+
+    /// Remembers the program and the name the uniform location was queried for.
+    pub fn set_location(&self, location:&WebGlUniformLocation, program:&WebGlProgram, name:&str) {
+        let program = self.get(program).0 as f64;
+        let entry   = Array::of2(&program.into(),&name.into());
+        self.locations.set(location,&entry);
+    }
+
+    /// The recorded representation of the uniform location. Locations queried before the recorder
+    /// was created are identified by their own identifier and an empty name.
+    pub fn location(&self, location:&WebGlUniformLocation) -> MockUniformLocation {
+        let entry = self.locations.get(location);
+        if entry.is_undefined() {
+            let program = self.get(location);
+            let name    = default();
+            MockUniformLocation {program,name}
+        } else {
+            let entry   = entry.unchecked_into::<Array>();
+            let program = MockId(entry.get(0).as_f64().unwrap_or_default() as usize);
+            let name    = entry.get(1).as_string().unwrap_or_default();
+            MockUniformLocation {program,name}
+        }
+    }
+
+    /// The recorded representation of the uniform location, if any.
+    pub fn location_opt(&self, location:Option<&WebGlUniformLocation>)
+    -> Option<MockUniformLocation> {
+        location.map(|location| self.location(location))
+    }
+}
+
+
+
+// ================
+// === Recorder ===
+// ================
+
+shared! { Recorder
+
+/// Records the calls issued to the WebGL context through its `GpuContext` implementation. The
+/// calls are replayed on the `log` context while a recording is in progress. Recorders are
+/// registered per thread, see `record`.
+#[derive(Debug)]
+pub struct RecorderData {
+    context   : Context,
+    log       : MockContext,
+    ids       : ObjectIds,
+    recording : bool,
+}
+
+impl {
+    /// Constructor.
+    pub fn new(context:&Context) -> Self {
+        let context   = context.clone();
+        let log       = default();
+        let ids       = default();
+        let recording = false;
+        Self {context,log,ids,recording}
+    }
+
+    /// Checks whether a recording is in progress.
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Starts recording the calls.
+    pub fn start(&mut self) {
+        self.log.clear();
+        self.recording = true;
+    }
+
+    /// Stops recording and returns the recorded calls.
+    pub fn finish(&mut self) -> Vec<Call> {
+        self.recording = false;
+        self.log.take_calls()
+    }
+}}
+
+thread_local! {
+    static RECORDERS : RefCell<Vec<Recorder>> = default();
+}
+
+impl Recorder {
+    /// Registers the recorder, so the calls issued to its context are reported to it.
+    fn register(&self) {
+        RECORDERS.with(|recorders| recorders.borrow_mut().push(self.clone_ref()));
+    }
+
+    /// Removes the recorder from the registered ones.
+    fn unregister(&self) {
+        RECORDERS.with(|recorders| {
+            recorders.borrow_mut().retain(|recorder| !Rc::ptr_eq(&recorder.rc,&self.rc))
+        });
+    }
+}
+
+/// Reports the call issued to the WebGL context. The `f` function replays the call on the log of
+/// the recorder of the context, if a recording is in progress. It is cheap when nothing is being
+/// recorded, as the contexts are compared only for the recorders which are recording.
+pub fn record<F:FnOnce(&MockContext,&ObjectIds)>(context:&Context, f:F) {
+    RECORDERS.with(|recorders| {
+        let recorders = recorders.borrow();
+        let recorder  = recorders.iter().find(|recorder| {
+            let recorder = recorder.rc.borrow();
+            recorder.recording && &recorder.context == context
+        });
+        if let Some(recorder) = recorder {
+            let recorder = recorder.rc.borrow();
+            f(&recorder.log,&recorder.ids)
+        }
+    })
+}
+
+/// Reports the uniform location queried from the WebGL context, so the uniform calls can be
+/// recorded with the uniform name. Locations are reported even if nothing is being recorded, as
+/// they are usually queried once, when the program is created.
+pub fn record_uniform_location
+(context:&Context, location:&WebGlUniformLocation, program:&WebGlProgram, name:&str) {
+    RECORDERS.with(|recorders| {
+        for recorder in recorders.borrow().iter() {
+            let recorder = recorder.rc.borrow();
+            if &recorder.context == context {
+                recorder.ids.set_location(location,program,name)
+            }
+        }
+    })
+}
+
+
+
+// =====================
+// === FrameCapturer ===
+// =====================
+
+/// Callback receiving a finished capture.
+pub type CaptureCallback = Box<dyn FnOnce(&FrameCapture)>;
+
+/// Manages capture requests. A capture requested in the middle of a frame starts with the next
+/// one. Finished captures are kept until they are taken by `take_finished`, so their callbacks
+/// can be run outside of the world update.
+#[derive(Clone,Debug)]
+pub struct FrameCapturer {
+    rc: Rc<RefCell<FrameCapturerData>>,
+}
+
+impl FrameCapturer {
+    /// Constructor. The calls issued to the context are recorded from now on, whenever a capture
+    /// is requested.
+    pub fn new(context:&Context, logger:Logger) -> Self {
+        let rc = Rc::new(RefCell::new(FrameCapturerData::new(context,logger)));
+        Self {rc}
+    }
+
+    /// Requests a capture of the next frame. The callback is run once the frame is finished.
+    pub fn request<F:FnOnce(&FrameCapture)+'static>(&self, callback:F) {
+        self.rc.borrow_mut().requests.push(Box::new(callback));
+    }
+
+    /// Checks whether a capture was requested or is in progress.
+    pub fn is_capturing(&self) -> bool {
+        self.rc.borrow().is_capturing()
+    }
+
+    /// Starts recording if a capture was requested. Should be called before the frame is rendered.
+    pub fn begin_frame(&self) {
+        self.rc.borrow_mut().begin_frame()
+    }
+
+    /// Finishes recording, if it was started in `begin_frame`.
+    pub fn end_frame(&self, time:f64) {
+        self.rc.borrow_mut().end_frame(time)
+    }
+
+    /// Takes the finished captures together with their callbacks.
+    pub fn take_finished(&self) -> Vec<(CaptureCallback,Rc<FrameCapture>)> {
+        mem::take(&mut self.rc.borrow_mut().finished)
+    }
+}
+
+impl CloneRef for FrameCapturer {
+    fn clone_ref(&self) -> Self {
+        self.clone()
+    }
+}
+
+/// The internal state of the `FrameCapturer`.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct FrameCapturerData {
+    #[derivative(Debug="ignore")]
+    requests : Vec<CaptureCallback>,
+    #[derivative(Debug="ignore")]
+    active   : Vec<CaptureCallback>,
+    #[derivative(Debug="ignore")]
+    finished : Vec<(CaptureCallback,Rc<FrameCapture>)>,
+    recorder : Recorder,
+    logger   : Logger,
+}
+
+impl FrameCapturerData {
+    /// Constructor.
+    pub fn new(context:&Context, logger:Logger) -> Self {
+        let requests = default();
+        let active   = default();
+        let finished = default();
+        let recorder = Recorder::new(context);
+        recorder.register();
+        Self {requests,active,finished,recorder,logger}
+    }
+
+    /// Checks whether a capture was requested or is in progress.
+    pub fn is_capturing(&self) -> bool {
+        !self.requests.is_empty() || self.recorder.is_recording()
+    }
+
+    /// Starts recording if a capture was requested.
+    pub fn begin_frame(&mut self) {
+        if !self.requests.is_empty() && !self.recorder.is_recording() {
+            self.logger.info("Capturing the frame.");
+            self.active = mem::take(&mut self.requests);
+            self.recorder.start();
+        }
+    }
+
+    /// Finishes recording, if it was started in `begin_frame`.
+    pub fn end_frame(&mut self, time:f64) {
+        if self.recorder.is_recording() {
+            let capture = Rc::new(FrameCapture::new(time,self.recorder.finish()));
+            let count   = capture.calls.len();
+            self.logger.info(|| format!("Captured {} call(s).",count));
+            for callback in mem::take(&mut self.active) {
+                self.finished.push((callback,capture.clone()));
+            }
+        }
+    }
+}
+
+impl Drop for FrameCapturerData {
+    fn drop(&mut self) {
+        self.recorder.unregister();
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::system::gpu::context::GpuContext;
+    use crate::system::gpu::context::mock::UniformData;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    use wasm_bindgen_test::wasm_bindgen_test_configure;
+    use web_sys::HtmlCanvasElement;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn capture_to_json() {
+        let program  = Some(MockId(0));
+        let location = Some(MockUniformLocation {program:MockId(0), name:"time".into()});
+        let data     = UniformData::Float {components:1, data:vec![0.5]};
+        let calls    = vec![
+            Call::UseProgram {program},
+            Call::Uniform    {location,data},
+            Call::DrawArrays {mode:4, first:0, count:3},
+        ];
+        let capture = FrameCapture::new(16.5,calls);
+        let json    = capture.to_json().unwrap();
+        let value   = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        let uniform = &value["calls"][1]["Uniform"];
+        assert_eq!(value["time"], 16.5);
+        assert_eq!(value["calls"][0]["UseProgram"]["program"], 0);
+        assert_eq!(uniform["location"]["name"], "time");
+        assert_eq!(uniform["data"]["Float"]["data"][0], 0.5);
+        assert_eq!(capture.draw_calls().count(), 1);
+    }
+
+    #[wasm_bindgen_test]
+    fn only_the_calls_of_the_captured_frame_are_recorded() {
+        let canvas    = web::create_element("canvas").unwrap();
+        let canvas    = canvas.dyn_into::<HtmlCanvasElement>().unwrap();
+        let context   = web::get_webgl2_context(&canvas).unwrap();
+        let capturer  = FrameCapturer::new(&context,Logger::new("Test"));
+        let captures  = Rc::new(RefCell::new(Vec::<Vec<Call>>::new()));
+        let captures2 = captures.clone();
+        let buffer    = GpuContext::create_buffer(&context).unwrap();
+        capturer.request(move |capture| captures2.borrow_mut().push(capture.calls.clone()));
+        capturer.begin_frame();
+        GpuContext::bind_buffer(&context,Context::ARRAY_BUFFER,Some(&buffer));
+        GpuContext::clear(&context,Context::COLOR_BUFFER_BIT);
+        capturer.end_frame(0.0);
+        GpuContext::clear(&context,Context::COLOR_BUFFER_BIT);
+        for (callback,capture) in capturer.take_finished() { callback(&capture) }
+        let target = Context::ARRAY_BUFFER;
+        let mask   = Context::COLOR_BUFFER_BIT;
+        assert_eq!(*captures.borrow(), vec![vec!
+            [ Call::BindBuffer {target, buffer:Some(MockId(0))}
+            , Call::Clear      {mask}
+            ]]);
+    }
+}
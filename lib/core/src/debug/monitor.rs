@@ -723,13 +723,15 @@ macro_rules! stats_sampler {
 
 const MB:f64 = (1024 * 1024) as f64;
 
-stats_sampler!("GPU memory usage (Mb)"  , GpuMemoryUsage     , gpu_memory_usage     , 100.0     , 500.0     , 2 , MB);
-stats_sampler!("Draw call count"        , DrawCallCount      , draw_call_count      , 100.0     , 500.0     , 0 , 1.0);
-stats_sampler!("Buffer count"           , BufferCount        , buffer_count         , 100.0     , 500.0     , 0 , 1.0);
-stats_sampler!("Data upload count"      , DataUploadCount    , data_upload_count    , 100.0     , 500.0     , 0 , 1.0);
-stats_sampler!("Data upload size (Mb)"  , DataUploadSize     , data_upload_size     ,   1.0     ,  10.0     , 2 , MB);
-stats_sampler!("Sprite system count"    , SpriteSystemCount  , sprite_system_count  , 100.0     , 500.0     , 0 , 1.0);
-stats_sampler!("Symbol count"           , SymbolCount        , symbol_count         , 100.0     , 500.0     , 0 , 1.0);
-stats_sampler!("Sprite count"           , SpriteCount        , sprite_count         , 100_000.0 , 500_000.0 , 0 , 1.0);
-stats_sampler!("Shader count"           , ShaderCount        , shader_count         , 100.0     , 500.0     , 0 , 1.0);
-stats_sampler!("Shader compile count"   , ShaderCompileCount , shader_compile_count , 10.0      , 100.0     , 0 , 1.0);
+stats_sampler!("GPU memory usage (Mb)"  , GpuMemoryUsage      , gpu_memory_usage      , 100.0     , 500.0     , 2 , MB);
+stats_sampler!("Draw call count"        , DrawCallCount       , draw_call_count       , 100.0     , 500.0     , 0 , 1.0);
+stats_sampler!("Buffer count"           , BufferCount         , buffer_count          , 100.0     , 500.0     , 0 , 1.0);
+stats_sampler!("Data upload count"      , DataUploadCount     , data_upload_count     , 100.0     , 500.0     , 0 , 1.0);
+stats_sampler!("Data upload size (Mb)"  , DataUploadSize      , data_upload_size      ,   1.0     ,  10.0     , 2 , MB);
+stats_sampler!("Sprite system count"    , SpriteSystemCount   , sprite_system_count   , 100.0     , 500.0     , 0 , 1.0);
+stats_sampler!("Symbol count"           , SymbolCount         , symbol_count          , 100.0     , 500.0     , 0 , 1.0);
+stats_sampler!("Sprite count"           , SpriteCount         , sprite_count          , 100_000.0 , 500_000.0 , 0 , 1.0);
+stats_sampler!("Shader count"           , ShaderCount         , shader_count          , 100.0     , 500.0     , 0 , 1.0);
+stats_sampler!("Shader compile count"   , ShaderCompileCount  , shader_compile_count  , 10.0      , 100.0     , 0 , 1.0);
+stats_sampler!("Culled symbol count"    , CulledSymbolCount   , culled_symbol_count   , 100.0     , 500.0     , 0 , 1.0);
+stats_sampler!("Culled instance count"  , CulledInstanceCount , culled_instance_count , 100_000.0 , 500_000.0 , 0 , 1.0);
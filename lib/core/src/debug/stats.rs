@@ -76,25 +76,33 @@ macro_rules! gen_stats {
 }
 
 gen_stats!{
-    gpu_memory_usage     : u32,
-    draw_call_count      : usize,
-    buffer_count         : usize,
-    data_upload_count    : usize,
-    data_upload_size     : u32,
-    sprite_system_count  : usize,
-    sprite_count         : usize,
-    symbol_count         : usize,
-    mesh_count           : usize,
-    shader_count         : usize,
-    shader_compile_count : usize,
+    gpu_memory_usage         : u32,
+    draw_call_count          : usize,
+    buffer_count             : usize,
+    data_upload_count        : usize,
+    data_upload_size         : u32,
+    sprite_system_count      : usize,
+    sprite_count             : usize,
+    symbol_count             : usize,
+    mesh_count               : usize,
+    shader_count             : usize,
+    shader_compile_count     : usize,
+    culled_symbol_count      : usize,
+    culled_instance_count    : usize,
+    program_cache_hit_count  : usize,
+    program_cache_miss_count : usize,
+    cached_program_count     : usize,
+    compiling_program_count  : usize,
 }
 
 impl StatsData {
     fn reset_per_frame_statistics(&mut self) {
-        self.draw_call_count      = 0;
-        self.shader_compile_count = 0;
-        self.data_upload_count    = 0;
-        self.data_upload_size     = 0;
+        self.draw_call_count          = 0;
+        self.shader_compile_count     = 0;
+        self.data_upload_count        = 0;
+        self.data_upload_size         = 0;
+        self.culled_symbol_count      = 0;
+        self.culled_instance_count    = 0;
     }
 }
 
@@ -508,6 +508,31 @@ impl CloneRef for DisplayObjectData {
 }
 
 
+// === Weak Reference ===
+
+impl DisplayObjectData {
+    /// A weak reference to this object. It does not keep the object alive.
+    pub fn downgrade(&self) -> WeakDisplayObjectData {
+        let weak = Rc::downgrade(&self.rc);
+        WeakDisplayObjectData {weak}
+    }
+}
+
+/// Weak reference to a `DisplayObjectData`. It allows referring to objects owned by someone else,
+/// without preventing them from being dropped.
+#[derive(Clone,Debug)]
+pub struct WeakDisplayObjectData {
+    weak : Weak<RefCell<DisplayObjectDataMut>>,
+}
+
+impl WeakDisplayObjectData {
+    /// The object, if it is still alive.
+    pub fn upgrade(&self) -> Option<DisplayObjectData> {
+        self.weak.upgrade().map(|rc| DisplayObjectData {rc})
+    }
+}
+
+
 
 // =====================
 // === DisplayObject ===
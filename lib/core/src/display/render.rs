@@ -1,4 +1,19 @@
-#![allow(missing_docs)]
+//! Root module for rendering utilities. It defines the render pipeline, an ordered list of render
+//! passes drawing the scene, the render targets the passes draw into, and the post-processing
+//! effects applied to the targets.
 
-#[warn(missing_docs)]
-pub mod css3d;
+pub mod effect;
+pub mod pass;
+pub mod pipeline;
+pub mod post_process;
+pub mod target;
+
+
+// =================
+// === Reexports ===
+// =================
+
+pub use pass::*;
+pub use pipeline::*;
+pub use post_process::*;
+pub use target::*;
@@ -0,0 +1,536 @@
+//! This module defines post-processing effects. An effect is a full-screen fragment `Material`
+//! applied to the color texture of a `Framebuffer`. The material can refer to the following
+//! variables:
+//!   - `input_source`, the `sampler2D` of the processed texture,
+//!   - `input_source_size`, the dimensions of the processed texture in pixels,
+//!   - `input_uv`, the texture coordinates of the current fragment,
+//!   - any of its inputs, bound to the effect parameters or to the global variables by name.
+
+use crate::prelude::*;
+
+use crate::display::render::target::Framebuffer;
+use crate::display::symbol::UniformBinding;
+use crate::display::symbol::VertexArrayObject;
+use crate::display::symbol::material::Material;
+use crate::display::symbol::material::VarDecl;
+use crate::display::symbol::shader::builder::CodeTemplete;
+use crate::display::symbol::shader::builder::ShaderBuilder;
+use crate::display::symbol::shader::builder::ShaderConfig;
+use crate::display::symbol::shader::builder;
+use crate::system::gpu::context::GpuContext;
+use crate::system::gpu::data::uniform::AnyUniform;
+use crate::system::gpu::data::uniform::IntoUniformValueImpl;
+use crate::system::gpu::data::uniform::Uniform;
+use crate::system::gpu::data::uniform::UniformScope;
+use crate::system::gpu::shader::*;
+
+use nalgebra::Vector2;
+use nalgebra::Vector3;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// Name of the variable keeping the processed texture.
+pub const SOURCE : &str = "source";
+
+/// Name of the variable keeping the dimensions of the processed texture.
+pub const SOURCE_SIZE : &str = "source_size";
+
+/// Name of the variable keeping the texture coordinates of the current fragment.
+pub const UV : &str = "uv";
+
+/// Vertex shader drawing a single triangle covering the whole viewport. It does not need any
+/// vertex buffers, as the positions are computed from `gl_VertexID`.
+const VERTEX_MAIN : &str = "
+    vec2 position = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2));
+    input_uv      = position;
+    gl_Position   = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+    ";
+
+
+
+// ==============
+// === Effect ===
+// ==============
+
+/// A single full-screen effect. The effect is (re-)compiled lazily, the first time it is drawn
+/// after its material or the set of its parameters has changed.
+#[derive(Debug)]
+pub struct Effect<C:GpuContext=Context> {
+    material   : Material,
+    parameters : UniformScope<C>,
+    globals    : UniformScope<C>,
+    program    : Option<EffectProgram<C>>,
+    dirty      : bool,
+    context    : C,
+    logger     : Logger,
+}
+
+/// Compiled effect program with all of its bindings. The program is deleted when it is dropped.
+#[derive(Debug)]
+struct EffectProgram<C:GpuContext> {
+    context     : C,
+    program     : C::Program,
+    vao         : VertexArrayObject<C>,
+    uniforms    : Vec<UniformBinding<C>>,
+    source      : Option<C::UniformLocation>,
+    source_size : Option<C::UniformLocation>,
+}
+
+impl<C:GpuContext> Effect<C> {
+    /// Constructor. The `globals` scope is used to bind material inputs which are not effect
+    /// parameters, like `time` or `pixel_ratio`.
+    pub fn new(logger:Logger, context:&C, globals:&UniformScope<C>) -> Self {
+        let material   = default();
+        let parameters = UniformScope::new(logger.sub("parameters"),context);
+        let globals    = globals.clone_ref();
+        let program    = default();
+        let dirty      = true;
+        let context    = context.clone();
+        Self {material,parameters,globals,program,dirty,context,logger}
+    }
+
+    /// Sets the GLSL code of the `main` function of the effect material.
+    pub fn set_main<S:Str>(&mut self, main:S) {
+        self.material.set_main(main);
+        self.dirty = true;
+    }
+
+    /// Sets the GLSL code placed before the `main` function, like helper function definitions.
+    pub fn set_before_main<S:Str>(&mut self, code:S) {
+        self.material.set_before_main(code);
+        self.dirty = true;
+    }
+
+    /// Replaces the material of this effect.
+    pub fn set_material<M:Into<Material>>(&mut self, material:M) {
+        self.material = material.into();
+        self.dirty    = true;
+    }
+
+    /// Adds a new parameter of the effect. It becomes an input of the material and an uniform
+    /// which can be modified at runtime. Panics if the name is already in use.
+    pub fn add_parameter<T>(&mut self, name:&str, value:T) -> Uniform<T>
+    where T          : Into<VarDecl>+IntoUniformValueImpl<C,Result=T>+Clone,
+          Uniform<T> : Into<AnyUniform<C>> {
+        self.material.add_input(name,value.clone());
+        self.dirty = true;
+        self.parameters.add_or_panic(name,value)
+    }
+
+    /// Scope of all the effect parameters.
+    pub fn parameters(&self) -> &UniformScope<C> {
+        &self.parameters
+    }
+
+    /// The material of this effect.
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    /// Forgets the program after the context was lost and restored. It will be re-compiled during
+    /// the next draw.
+    pub fn restore_context(&mut self) {
+        self.parameters.restore_context();
+        self.program = None;
+        self.dirty   = true;
+    }
+
+    /// Draws the effect into the currently bound target, using the `source` color texture as the
+    /// input.
+    pub fn draw(&mut self, source:&Framebuffer<C>) {
+        if self.dirty {
+            self.program = self.compile();
+            self.dirty   = false;
+        }
+        if let Some(program) = &self.program {
+            let context = &self.context;
+            let target  = Context::TEXTURE_2D;
+            context.use_program(Some(&program.program));
+            program.vao.with(|| {
+                for binding in &program.uniforms {
+                    binding.upload(context);
+                }
+                context.active_texture(Context::TEXTURE0);
+                context.bind_texture(target,Some(&source.color_texture()));
+                if let Some(location) = &program.source {
+                    context.uniform_iv(Some(location),1,&[0]);
+                }
+                if let Some(location) = &program.source_size {
+                    let width  = source.width()  as f32;
+                    let height = source.height() as f32;
+                    context.uniform_fv(Some(location),2,&[width,height]);
+                }
+                context.draw_arrays(Context::TRIANGLES,0,3);
+                context.bind_texture(target,None);
+            });
+            context.use_program(None);
+        }
+    }
+}
+
+
+// === Private API ===
+
+impl<C:GpuContext> Effect<C> {
+    fn lookup(&self, name:&str) -> Option<AnyUniform<C>> {
+        self.parameters.get(name).or_else(|| self.globals.get(name))
+    }
+
+    /// Generates the shader code and compiles the program. Material inputs which are neither
+    /// parameters nor global variables are defined as constants initialized to their default
+    /// values.
+    fn compile(&self) -> Option<EffectProgram<C>> {
+        group!(self.logger, "Compiling.", {
+            let mut cfg   = ShaderConfig::new();
+            let mut bound = Vec::new();
+            for (name,decl) in self.material.inputs() {
+                if let Some(struct_decl) = &decl.struct_decl {
+                    cfg.add_struct(struct_decl);
+                }
+                match (self.lookup(name),&decl.default) {
+                    (Some(uniform),_) => {
+                        cfg.add_uniform(name,&decl.tp);
+                        bound.push((name.clone(),uniform));
+                    }
+                    (None,Some(default)) => cfg.add_constant(name,&decl.tp,default),
+                    (None,None) => {
+                        let msg = || format!("Unable to bind variable '{}' to a value.", name);
+                        self.logger.warning(msg);
+                    }
+                }
+            }
+            cfg.add_uniform(SOURCE,glsl::PrimType::Sampler2d);
+            cfg.add_uniform(SOURCE_SIZE,glsl::PrimType::Vec2);
+            cfg.add_shared_attribute(UV,glsl::PrimType::Vec2);
+            cfg.add_output("color",glsl::PrimType::Vec4);
+
+            let vertex_code   = CodeTemplete::from_main(VERTEX_MAIN);
+            let fragment_code = self.material.code().clone();
+
+            let mut shader_builder = ShaderBuilder::new();
+            shader_builder.compute(&cfg,vertex_code,fragment_code);
+            let shader = shader_builder.build();
+            match self.build_program(&shader.vertex,&shader.fragment) {
+                Err(err) => {
+                    self.logger.error(|| format!("{}",err));
+                    None
+                }
+                Ok(program) => Some(self.init_program(program,bound))
+            }
+        })
+    }
+
+    /// Compiles the shaders and links them into a program. The shaders are deleted afterwards,
+    /// whether the build succeeded or not, as the linked program does not need them anymore.
+    fn build_program(&self, vertex:&str, fragment:&str) -> Result<C::Program,Error> {
+        let context = &self.context;
+        let vert    = start_shader_compilation(context,Context::VERTEX_SHADER,vertex)?;
+        let frag    = start_shader_compilation(context,Context::FRAGMENT_SHADER,fragment);
+        let program = frag.and_then(|frag| {
+            let program = self.link(&vert,vertex,&frag,fragment);
+            context.delete_shader(Some(&frag));
+            program
+        });
+        context.delete_shader(Some(&vert));
+        program
+    }
+
+    /// Checks the compilation status of both shaders and links them. The program is deleted if
+    /// the linking failed.
+    fn link
+    (&self, vert:&C::Shader, vertex:&str, frag:&C::Shader, fragment:&str)
+    -> Result<C::Program,Error> {
+        let context = &self.context;
+        check_shader(context,Context::VERTEX_SHADER,vert.clone(),vertex)?;
+        check_shader(context,Context::FRAGMENT_SHADER,frag.clone(),fragment)?;
+        let program = start_program_linking(context,vert,frag)?;
+        check_program(context,program.clone()).map_err(|err| {
+            context.delete_program(Some(&program));
+            err
+        })
+    }
+
+    fn init_program
+    (&self, program:C::Program, bound:Vec<(String,AnyUniform<C>)>) -> EffectProgram<C> {
+        let context     = &self.context;
+        let vao         = VertexArrayObject::new(context);
+        let location    = |name:&str| {
+            context.get_uniform_location(&program,&builder::mk_uniform_name(name))
+        };
+        let source      = location(SOURCE);
+        let source_size = location(SOURCE_SIZE);
+        let mut uniforms = Vec::new();
+        for (name,uniform) in bound {
+            match (location(&name),uniform) {
+                (_,AnyUniform::Struct(uniform)) => {
+                    for (field,uniform) in uniform.fields() {
+                        let field_name = format!("{}.{}",name,field);
+                        if let Some(loc) = location(&field_name) {
+                            uniforms.push(UniformBinding::new(field_name,loc,uniform));
+                        }
+                    }
+                }
+                (Some(loc),AnyUniform::Prim(uniform)) =>
+                    uniforms.push(UniformBinding::new(name,loc,uniform)),
+                (None,_) => {}
+                (_,AnyUniform::Texture(_)) => {
+                    let msg = || format!("Texture parameter '{}' is not supported.", name);
+                    self.logger.warning(msg);
+                }
+            }
+        }
+        let context = context.clone();
+        EffectProgram {context,program,vao,uniforms,source,source_size}
+    }
+}
+
+
+impl<C:GpuContext> Drop for EffectProgram<C> {
+    fn drop(&mut self) {
+        self.context.delete_program(Some(&self.program));
+    }
+}
+
+
+
+// =======================
+// === Builtin Effects ===
+// =======================
+
+/// Parameters of the two-pass gaussian blur. The radius is expressed in pixels of the processed
+/// texture and it is limited to 16 pixels.
+#[derive(Clone,Debug)]
+pub struct GaussianBlurParams {
+    radii : Vec<Uniform<f32>>,
+}
+
+impl GaussianBlurParams {
+    /// Constructor. Accepts the radius parameters of all passes of the blur.
+    pub fn new(radii:Vec<Uniform<f32>>) -> Self {
+        Self {radii}
+    }
+
+    /// Sets the blur radius of all passes.
+    pub fn set_radius(&self, radius:f32) {
+        for uniform in &self.radii {
+            uniform.set(radius)
+        }
+    }
+}
+
+/// Parameters of the color grading effect.
+#[derive(Clone,Debug)]
+pub struct ColorGradingParams {
+    /// Value added to every color channel. `0.0` leaves the image unchanged.
+    pub brightness : Uniform<f32>,
+    /// Contrast multiplier. `1.0` leaves the image unchanged.
+    pub contrast   : Uniform<f32>,
+    /// Saturation multiplier. `1.0` leaves the image unchanged, `0.0` produces a grayscale image.
+    pub saturation : Uniform<f32>,
+    /// Color the image is multiplied by. White leaves the image unchanged.
+    pub tint       : Uniform<Vector3<f32>>,
+}
+
+/// Parameters of the vignette effect.
+#[derive(Clone,Debug)]
+pub struct VignetteParams {
+    /// How much the corners are darkened, in range `0.0 ..= 1.0`.
+    pub intensity  : Uniform<f32>,
+    /// Distance from the center, in texture coordinates, at which the darkening starts.
+    pub radius     : Uniform<f32>,
+    /// Length of the transition between the clear and the darkened area.
+    pub smoothness : Uniform<f32>,
+}
+
+/// Makes the effect a single, one-dimensional pass of the separable gaussian blur and returns its
+/// radius parameter. Blurring in both dimensions requires two effects, one with the horizontal and
+/// one with the vertical direction.
+pub fn gaussian_blur(effect:&mut Effect, direction:Vector2<f32>) -> Uniform<f32> {
+    let radius = effect.add_parameter("radius",4.0);
+    effect.add_parameter("direction",direction);
+    effect.set_main("
+        vec2  texel = input_direction / input_source_size;
+        float sigma = max(input_radius / 2.0, 0.0001);
+        vec4  sum   = vec4(0.0);
+        float total = 0.0;
+        for (int i = -16; i <= 16; i++) {
+            float x = float(i);
+            if (abs(x) > input_radius) { continue; }
+            float weight = exp(-0.5 * x * x / (sigma * sigma));
+            sum   += texture(input_source, input_uv + texel * x) * weight;
+            total += weight;
+        }
+        output_color = sum / total;
+    ");
+    radius
+}
+
+/// Makes the effect a color grading effect.
+pub fn color_grading(effect:&mut Effect) -> ColorGradingParams {
+    let brightness = effect.add_parameter("brightness",0.0);
+    let contrast   = effect.add_parameter("contrast",1.0);
+    let saturation = effect.add_parameter("saturation",1.0);
+    let tint       = effect.add_parameter("tint",Vector3::new(1.0,1.0,1.0));
+    effect.set_main("
+        vec4  color = texture(input_source, input_uv);
+        vec3  rgb   = color.rgb + input_brightness;
+        rgb         = (rgb - 0.5) * input_contrast + 0.5;
+        float luma  = dot(rgb, vec3(0.2126, 0.7152, 0.0722));
+        rgb         = mix(vec3(luma), rgb, input_saturation) * input_tint;
+        output_color = vec4(clamp(rgb, 0.0, 1.0), color.a);
+    ");
+    ColorGradingParams {brightness,contrast,saturation,tint}
+}
+
+/// Makes the effect a vignette effect, darkening the corners of the image.
+pub fn vignette(effect:&mut Effect) -> VignetteParams {
+    let intensity  = effect.add_parameter("intensity",0.5);
+    let radius     = effect.add_parameter("radius",0.5);
+    let smoothness = effect.add_parameter("smoothness",0.3);
+    effect.set_main("
+        vec4  color    = texture(input_source, input_uv);
+        float dist     = distance(input_uv, vec2(0.5));
+        float edge     = input_radius + input_smoothness;
+        float darkness = smoothstep(input_radius, edge, dist) * input_intensity;
+        output_color   = vec4(color.rgb * (1.0 - darkness), color.a);
+    ");
+    VignetteParams {intensity,radius,smoothness}
+}
+
+/// Makes the effect a fast approximate anti-aliasing (FXAA) effect.
+pub fn fxaa(effect:&mut Effect) {
+    effect.set_before_main("
+        #define FXAA_REDUCE_MIN (1.0 / 128.0)
+        #define FXAA_REDUCE_MUL (1.0 / 8.0)
+        #define FXAA_SPAN_MAX   8.0
+    ");
+    effect.set_main("
+        vec2  inv     = 1.0 / input_source_size;
+        vec3  luma    = vec3(0.299, 0.587, 0.114);
+        vec4  rgba_m  = texture(input_source, input_uv);
+        float luma_nw = dot(texture(input_source, input_uv + vec2(-1.0,-1.0) * inv).rgb, luma);
+        float luma_ne = dot(texture(input_source, input_uv + vec2( 1.0,-1.0) * inv).rgb, luma);
+        float luma_sw = dot(texture(input_source, input_uv + vec2(-1.0, 1.0) * inv).rgb, luma);
+        float luma_se = dot(texture(input_source, input_uv + vec2( 1.0, 1.0) * inv).rgb, luma);
+        float luma_m  = dot(rgba_m.rgb, luma);
+        float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+        float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+        vec2 dir;
+        dir.x = -((luma_nw + luma_ne) - (luma_sw + luma_se));
+        dir.y =  ((luma_nw + luma_sw) - (luma_ne + luma_se));
+        float dir_reduce  = max((luma_nw + luma_ne + luma_sw + luma_se) * (0.25 * FXAA_REDUCE_MUL),
+                                FXAA_REDUCE_MIN);
+        float rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+        dir = clamp(dir * rcp_dir_min, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * inv;
+
+        vec3 rgb_a = 0.5 * (texture(input_source, input_uv + dir * (1.0/3.0 - 0.5)).rgb
+                          + texture(input_source, input_uv + dir * (2.0/3.0 - 0.5)).rgb);
+        vec3 rgb_b = rgb_a * 0.5 + 0.25 * (texture(input_source, input_uv - dir * 0.5).rgb
+                                         + texture(input_source, input_uv + dir * 0.5).rgb);
+        float luma_b = dot(rgb_b, luma);
+        bool  use_a  = luma_b < luma_min || luma_b > luma_max;
+        output_color = vec4(use_a ? rgb_a : rgb_b, rgba_m.a);
+    ");
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::system::gpu::context::mock::Call;
+    use crate::system::gpu::context::mock::MockContext;
+    use crate::system::gpu::context::mock::UniformData;
+
+    #[test]
+    fn draw_through_the_context() {
+        let context    = MockContext::new();
+        let logger     = Logger::new("test");
+        let globals    = UniformScope::new(logger.sub("globals"),&context);
+        let mut effect = Effect::new(logger,&context,&globals);
+        let source     = Framebuffer::new(&context,4,2,false);
+        let radius     = effect.add_parameter("radius",2.0);
+        effect.set_main("output_color = texture(input_source, input_uv) * input_radius;");
+        context.clear();
+        effect.draw(&source);
+        assert!(context.calls().iter().any(|call| match call {
+            Call::LinkProgram {..} => true,
+            _                      => false,
+        }));
+        let texture = Some(source.color_texture());
+        assert!(context.calls().contains(&Call::BindTexture {target:Context::TEXTURE_2D,texture}));
+        let draw = Call::DrawArrays {mode:Context::TRIANGLES, first:0, count:3};
+        assert_eq!(context.draw_calls(),vec![draw.clone()]);
+        let values = context.uniform_calls().into_iter().filter_map(|call| match call {
+            Call::Uniform {data,..} => Some(data),
+            _                       => None,
+        }).collect_vec();
+        assert!(values.contains(&UniformData::Int   {components:1, data:vec![0]}));
+        assert!(values.contains(&UniformData::Float {components:1, data:vec![2.0]}));
+        assert!(values.contains(&UniformData::Float {components:2, data:vec![4.0,2.0]}));
+
+        context.clear();
+        radius.set(3.0);
+        effect.draw(&source);
+        assert!(context.calls().iter().all(|call| match call {
+            Call::LinkProgram {..} => false,
+            _                      => true,
+        }));
+        assert_eq!(context.draw_calls(),vec![draw]);
+    }
+
+    fn count<F:Fn(&Call)->bool>(context:&MockContext, f:F) -> usize {
+        context.calls().iter().filter(|call| f(call)).count()
+    }
+
+    fn is_delete_shader(call:&Call) -> bool {
+        match call {
+            Call::DeleteShader {..} => true,
+            _                       => false,
+        }
+    }
+
+    #[test]
+    fn recompilation_releases_the_previous_program() {
+        let context    = MockContext::new();
+        let logger     = Logger::new("test");
+        let globals    = UniformScope::new(logger.sub("globals"),&context);
+        let mut effect = Effect::new(logger,&context,&globals);
+        let source     = Framebuffer::new(&context,4,2,false);
+        effect.set_main("output_color = texture(input_source, input_uv);");
+        effect.draw(&source);
+        let program = effect.program.as_ref().map(|program| program.program);
+        assert!(program.is_some());
+        assert_eq!(count(&context,is_delete_shader),2);
+
+        context.clear();
+        effect.set_main("output_color = vec4(1.0);");
+        effect.draw(&source);
+        assert!(context.calls().contains(&Call::DeleteProgram {program}));
+        assert_eq!(count(&context,is_delete_shader),2);
+
+        context.clear();
+        effect.set_main("#error broken");
+        effect.draw(&source);
+        assert!(effect.program.is_none());
+        assert_eq!(count(&context,is_delete_shader),2);
+        assert!(context.draw_calls().is_empty());
+
+        effect.set_main("output_color = vec4(1.0);");
+        effect.draw(&source);
+        let program = effect.program.as_ref().map(|program| program.program);
+        context.clear();
+        drop(effect);
+        assert!(context.calls().contains(&Call::DeleteProgram {program}));
+    }
+}
@@ -0,0 +1,455 @@
+//! This module defines render passes. A pass is a single step of the render pipeline. Before a pass
+//! is run, the pipeline binds its target and applies its clear, blending and depth settings.
+
+use crate::prelude::*;
+
+use crate::display::render::target::Framebuffer;
+use crate::display::render::target::RenderTarget;
+use crate::display::shape::text::TextComponent;
+use crate::display::shape::text::font::Fonts;
+use crate::display::symbol::registry::SymbolId;
+use crate::display::symbol::registry::SymbolRegistry;
+use crate::display::world::workspace::ShapeData;
+use crate::system::gpu::context::GpuContext;
+use crate::system::gpu::shader::Context;
+
+use nalgebra::Vector4;
+
+
+
+// =====================
+// === ClearSettings ===
+// =====================
+
+/// Describes which buffers of the target are cleared before the pass is run and to which values.
+#[derive(Clone,Debug,Default)]
+pub struct ClearSettings {
+    /// The color the target is cleared to, if any.
+    pub color : Option<Vector4<f32>>,
+    /// The depth value the target is cleared to, if any.
+    pub depth : Option<f32>,
+}
+
+impl ClearSettings {
+    /// Settings which do not clear anything.
+    pub fn none() -> Self {
+        default()
+    }
+
+    /// Settings clearing the color buffer to the provided color.
+    pub fn color(r:f32, g:f32, b:f32, a:f32) -> Self {
+        let color = Some(Vector4::new(r,g,b,a));
+        let depth = None;
+        Self {color,depth}
+    }
+
+    /// Adds clearing of the depth buffer to the provided value.
+    pub fn with_depth(mut self, depth:f32) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// Clears the currently bound target.
+    pub fn apply<C:GpuContext>(&self, context:&C) {
+        let mut mask = 0;
+        if let Some(color) = &self.color {
+            context.clear_color(color.x,color.y,color.z,color.w);
+            mask |= Context::COLOR_BUFFER_BIT;
+        }
+        if let Some(depth) = self.depth {
+            context.clear_depth(depth);
+            mask |= Context::DEPTH_BUFFER_BIT;
+        }
+        if mask != 0 {
+            context.clear(mask);
+        }
+    }
+}
+
+
+
+// ==================
+// === BlendState ===
+// ==================
+
+/// Blending configuration of a pass. See the following link to learn more:
+/// https://developer.mozilla.org/en-US/docs/Web/API/WebGLRenderingContext/blendFunc
+#[derive(Clone,Copy,Debug)]
+pub enum BlendState {
+    /// Blending is disabled.
+    Disabled,
+    /// Blending is enabled with the provided equation and factors.
+    Enabled { equation:u32, src:u32, dst:u32 },
+}
+
+impl Default for BlendState {
+    fn default() -> Self {
+        Self::Disabled
+    }
+}
+
+impl BlendState {
+    /// Additive blending with the `FUNC_ADD` equation and the provided factors.
+    pub fn new(src:u32, dst:u32) -> Self {
+        let equation = Context::FUNC_ADD;
+        Self::Enabled {equation,src,dst}
+    }
+
+    /// Standard alpha blending of non-premultiplied colors.
+    pub fn alpha() -> Self {
+        Self::new(Context::SRC_ALPHA,Context::ONE_MINUS_SRC_ALPHA)
+    }
+
+    /// Applies the settings to the context.
+    pub fn apply<C:GpuContext>(&self, context:&C) {
+        match self {
+            Self::Disabled => context.disable(Context::BLEND),
+            Self::Enabled {equation,src,dst} => {
+                context.enable(Context::BLEND);
+                context.blend_equation(*equation);
+                context.blend_func(*src,*dst);
+            }
+        }
+    }
+}
+
+
+
+// ==================
+// === DepthState ===
+// ==================
+
+/// Depth test configuration of a pass.
+#[derive(Clone,Copy,Debug)]
+pub struct DepthState {
+    /// Whether the depth test is enabled.
+    pub test  : bool,
+    /// Whether fragments passing the test write to the depth buffer.
+    pub write : bool,
+    /// The depth comparison function, like `Context::LESS`.
+    pub func  : u32,
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        let test  = false;
+        let write = true;
+        let func  = Context::LESS;
+        Self {test,write,func}
+    }
+}
+
+impl DepthState {
+    /// Depth test with the `LESS` comparison function.
+    pub fn enabled() -> Self {
+        let test = true;
+        Self {test,..default()}
+    }
+
+    /// Applies the settings to the context.
+    pub fn apply<C:GpuContext>(&self, context:&C) {
+        if self.test { context.enable(Context::DEPTH_TEST) } else {
+            context.disable(Context::DEPTH_TEST)
+        }
+        context.depth_mask(self.write);
+        context.depth_func(self.func);
+    }
+}
+
+
+
+// ====================
+// === PassSettings ===
+// ====================
+
+/// Per-pass GPU state. It is applied by the pipeline before the pass is run.
+#[derive(Clone,Debug,Default)]
+pub struct PassSettings {
+    /// The place the pass draws into.
+    pub target : RenderTarget,
+    /// Clearing performed before the pass is run.
+    pub clear  : ClearSettings,
+    /// Blending used by the pass.
+    pub blend  : BlendState,
+    /// Depth test used by the pass.
+    pub depth  : DepthState,
+}
+
+impl PassSettings {
+    /// Binds the target and applies all the settings.
+    pub fn apply(&self, context:&Context, screen:&ShapeData) {
+        self.target.bind(context,screen);
+        self.depth.apply(context);
+        self.blend.apply(context);
+        self.clear.apply(context);
+    }
+}
+
+
+
+// ===================
+// === PassContext ===
+// ===================
+
+/// Everything a pass can access while it is run.
+#[derive(Debug)]
+pub struct PassContext<'t> {
+    /// The WebGL context.
+    pub context         : &'t Context,
+    /// The canvas dimensions.
+    pub screen          : &'t ShapeData,
+    /// The target of the pass. It is already bound when the pass is run.
+    pub target          : &'t RenderTarget,
+    /// All symbols of the workspace.
+    pub symbols         : &'t SymbolRegistry,
+    /// All text components of the workspace.
+    pub text_components : &'t mut [TextComponent],
+    /// Fonts used by the text components.
+    pub fonts           : &'t mut Fonts,
+}
+
+
+
+// ==================
+// === RenderPass ===
+// ==================
+
+/// A single step of the render pipeline. Implement this trait in order to define custom passes.
+pub trait RenderPass : Debug {
+    /// Runs the pass. The target is bound and the pass settings are applied at this point.
+    fn run(&mut self, ctx:&mut PassContext);
+
+    /// Re-creates the GPU resources owned by the pass after the context was lost and restored.
+    fn restore_context(&mut self) {}
+}
+
+
+// === ClearPass ===
+
+/// A pass which does nothing but clearing its target according to the pass settings.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct ClearPass;
+
+impl RenderPass for ClearPass {
+    fn run(&mut self, _ctx:&mut PassContext) {}
+}
+
+
+// === SymbolsPass ===
+
+/// Selection of symbols drawn by a `SymbolsPass`.
+#[derive(Clone,Debug)]
+pub enum SymbolSelection {
+    /// All symbols, in the layer order.
+    All,
+    /// Only the provided symbols, in the provided order.
+    Only(Vec<SymbolId>),
+    /// All symbols except the provided ones, in the layer order.
+    Except(Vec<SymbolId>),
+    /// All symbols belonging to the provided layers, in the layer order.
+    Layers(Vec<String>),
+}
+
+impl Default for SymbolSelection {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+/// A pass drawing a selection of symbols. It is used both for the main scene and for overlays,
+/// which are symbols drawn on top of everything else, like tooltips or debug helpers.
+#[derive(Clone,Debug,Default)]
+pub struct SymbolsPass {
+    /// Symbols drawn by this pass.
+    pub selection : SymbolSelection,
+}
+
+impl SymbolsPass {
+    /// A pass drawing all the symbols.
+    pub fn all() -> Self {
+        default()
+    }
+
+    /// A pass drawing only the provided symbols.
+    pub fn only(ids:Vec<SymbolId>) -> Self {
+        let selection = SymbolSelection::Only(ids);
+        Self {selection}
+    }
+
+    /// A pass drawing all symbols except the provided ones.
+    pub fn except(ids:Vec<SymbolId>) -> Self {
+        let selection = SymbolSelection::Except(ids);
+        Self {selection}
+    }
+
+    /// A pass drawing all symbols belonging to the provided layers.
+    pub fn layers<S:Str>(layers:Vec<S>) -> Self {
+        let layers    = layers.into_iter().map(|layer| layer.into()).collect();
+        let selection = SymbolSelection::Layers(layers);
+        Self {selection}
+    }
+}
+
+impl RenderPass for SymbolsPass {
+    fn run(&mut self, ctx:&mut PassContext) {
+        match &self.selection {
+            SymbolSelection::All         => ctx.symbols.render_all(),
+            SymbolSelection::Only(ids)   => ctx.symbols.render_by_ids(ids),
+            SymbolSelection::Except(ids) => {
+                let ids = ctx.symbols.sorted_ids().into_iter().filter(|id| !ids.contains(id));
+                ctx.symbols.render_by_ids(&ids.collect_vec())
+            }
+            SymbolSelection::Layers(layers) => ctx.symbols.render_layers(layers),
+        }
+    }
+}
+
+
+// === TextPass ===
+
+/// A pass drawing all text components.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct TextPass;
+
+impl RenderPass for TextPass {
+    fn run(&mut self, ctx:&mut PassContext) {
+        for text_component in ctx.text_components.iter_mut() {
+            text_component.display(ctx.fonts);
+        }
+    }
+}
+
+
+// === PostProcessPass ===
+
+/// Full-screen processing of an offscreen framebuffer, drawing the result into the pass target.
+pub trait PostProcessor : Debug {
+    /// Draws the processed `source` into the currently bound target.
+    fn process(&mut self, ctx:&mut PassContext, source:&Framebuffer);
+
+    /// Re-creates the GPU resources owned by the processor after the context was lost and
+    /// restored.
+    fn restore_context(&mut self) {}
+}
+
+/// A pass processing the content of an offscreen framebuffer, rendered by earlier passes.
+#[derive(Debug)]
+pub struct PostProcessPass {
+    /// The framebuffer being processed.
+    pub source    : Framebuffer,
+    /// The processing implementation.
+    pub processor : Box<dyn PostProcessor>,
+}
+
+impl PostProcessPass {
+    /// Constructor.
+    pub fn new<P:PostProcessor+'static>(source:&Framebuffer, processor:P) -> Self {
+        let source    = source.clone_ref();
+        let processor = Box::new(processor);
+        Self {source,processor}
+    }
+
+    /// A pass copying the source to the target without any modifications.
+    pub fn copy(source:&Framebuffer) -> Self {
+        Self::new(source,CopyProcessor)
+    }
+}
+
+impl RenderPass for PostProcessPass {
+    fn run(&mut self, ctx:&mut PassContext) {
+        self.processor.process(ctx,&self.source)
+    }
+
+    fn restore_context(&mut self) {
+        self.source.restore_context();
+        self.processor.restore_context();
+    }
+}
+
+/// Post processor copying the source to the target with the `blitFramebuffer` operation.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct CopyProcessor;
+
+impl PostProcessor for CopyProcessor {
+    fn process(&mut self, ctx:&mut PassContext, source:&Framebuffer) {
+        let (width,height) = ctx.target.size(ctx.screen);
+        let src_width      = source.width();
+        let src_height     = source.height();
+        let mask           = Context::COLOR_BUFFER_BIT;
+        let filter         = Context::LINEAR;
+        let gl_source      = source.gl_framebuffer();
+        let context        = ctx.context;
+        GpuContext::bind_framebuffer(context,Context::READ_FRAMEBUFFER,Some(&gl_source));
+        GpuContext::blit_framebuffer
+            (context,0,0,src_width,src_height,0,0,width,height,mask,filter);
+        ctx.target.bind(ctx.context,ctx.screen);
+    }
+}
+
+
+// === FnPass ===
+
+/// A pass defined by a closure. It is the simplest way to define a custom pass.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct FnPass {
+    #[derivative(Debug="ignore")]
+    f : Box<dyn FnMut(&mut PassContext)>,
+}
+
+impl FnPass {
+    /// Constructor.
+    pub fn new<F:FnMut(&mut PassContext)+'static>(f:F) -> Self {
+        let f = Box::new(f);
+        Self {f}
+    }
+}
+
+impl RenderPass for FnPass {
+    fn run(&mut self, ctx:&mut PassContext) {
+        (self.f)(ctx)
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::system::gpu::context::mock::Call;
+    use crate::system::gpu::context::mock::MockContext;
+
+    #[test]
+    fn settings_are_applied_through_the_context() {
+        let context = MockContext::new();
+        ClearSettings::none().apply(&context);
+        assert!(context.take_calls().is_empty());
+        ClearSettings::color(0.0,0.5,1.0,1.0).with_depth(1.0).apply(&context);
+        assert_eq!(context.take_calls(),vec!
+            [ Call::ClearColor {r:0.0, g:0.5, b:1.0, a:1.0}
+            , Call::ClearDepth {depth:1.0}
+            , Call::Clear      {mask:Context::COLOR_BUFFER_BIT | Context::DEPTH_BUFFER_BIT}
+            ]);
+
+        BlendState::alpha().apply(&context);
+        BlendState::Disabled.apply(&context);
+        assert_eq!(context.take_calls(),vec!
+            [ Call::Enable        {cap:Context::BLEND}
+            , Call::BlendEquation {mode:Context::FUNC_ADD}
+            , Call::BlendFunc     {src:Context::SRC_ALPHA, dst:Context::ONE_MINUS_SRC_ALPHA}
+            , Call::Disable       {cap:Context::BLEND}
+            ]);
+
+        DepthState::enabled().apply(&context);
+        assert_eq!(context.take_calls(),vec!
+            [ Call::Enable    {cap:Context::DEPTH_TEST}
+            , Call::DepthMask {flag:true}
+            , Call::DepthFunc {func:Context::LESS}
+            ]);
+    }
+}
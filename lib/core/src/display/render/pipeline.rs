@@ -0,0 +1,330 @@
+//! This module defines the render pipeline, an ordered list of named render passes. The pipeline
+//! replaces a hard-coded rendering sequence, so applications can insert their own passes, like UI
+//! overlays or debug views, between the default ones.
+
+use crate::prelude::*;
+
+use crate::display::render::pass::*;
+use crate::display::render::target::Framebuffer;
+use crate::display::render::target::RenderTarget;
+use crate::display::render::target::WeakFramebuffer;
+use crate::display::shape::text::TextComponent;
+use crate::display::shape::text::font::Fonts;
+use crate::display::symbol::registry::SymbolRegistry;
+use crate::display::world::workspace::ShapeData;
+use crate::system::gpu::shader::Context;
+
+
+
+// =================
+// === PassNames ===
+// =================
+
+/// Names of the passes defined by the default pipeline.
+pub mod pass_name {
+    /// Clears the screen.
+    pub const CLEAR   : &str = "clear";
+    /// Draws all symbols.
+    pub const SYMBOLS : &str = "symbols";
+    /// Draws all text components.
+    pub const TEXT    : &str = "text";
+}
+
+
+
+// =============
+// === Error ===
+// =============
+
+/// Error raised when referring to a pass which is not registered in the pipeline.
+#[derive(Debug,Fail)]
+#[fail(display="Render pass '{}' not found.",name)]
+pub struct MissingPass {
+    /// The name of the missing pass.
+    pub name: String,
+}
+
+
+
+// =====================
+// === RenderPassDef ===
+// =====================
+
+/// Definition of a pass registered in the pipeline. It binds the pass implementation with its name
+/// and settings.
+#[derive(Debug)]
+pub struct RenderPassDef {
+    /// Name of the pass. Used to refer to the pass when modifying the pipeline.
+    pub name     : String,
+    /// GPU state applied before the pass is run.
+    pub settings : PassSettings,
+    /// Disabled passes are skipped.
+    pub enabled  : bool,
+    /// The pass implementation.
+    pub pass     : Box<dyn RenderPass>,
+}
+
+impl RenderPassDef {
+    /// Constructor. The pass draws to the screen and uses the default settings.
+    pub fn new<Name:Str,P:RenderPass+'static>(name:Name, pass:P) -> Self {
+        let name     = name.into();
+        let settings = default();
+        let enabled  = true;
+        let pass     = Box::new(pass);
+        Self {name,settings,enabled,pass}
+    }
+
+    /// Constructor of a pass drawing the provided symbols on top of the current target content.
+    pub fn overlay<Name:Str>(name:Name, pass:SymbolsPass) -> Self {
+        let mut def = Self::new(name,pass);
+        def.settings.blend = BlendState::alpha();
+        def
+    }
+
+    /// Sets the target of the pass.
+    pub fn with_target<T:Into<RenderTarget>>(mut self, target:T) -> Self {
+        self.settings.target = target.into();
+        self
+    }
+
+    /// Sets the clear settings of the pass.
+    pub fn with_clear(mut self, clear:ClearSettings) -> Self {
+        self.settings.clear = clear;
+        self
+    }
+
+    /// Sets the blending settings of the pass.
+    pub fn with_blend(mut self, blend:BlendState) -> Self {
+        self.settings.blend = blend;
+        self
+    }
+
+    /// Sets the depth test settings of the pass.
+    pub fn with_depth(mut self, depth:DepthState) -> Self {
+        self.settings.depth = depth;
+        self
+    }
+}
+
+
+
+// ======================
+// === RenderPipeline ===
+// ======================
+
+/// Ordered list of render passes run every frame. The pipeline tracks the screen-sized targets it
+/// created only weakly, so their GPU resources are released as soon as the passes and the user
+/// code drop them.
+#[derive(Debug)]
+pub struct RenderPipeline {
+    passes  : Vec<RenderPassDef>,
+    targets : Vec<WeakFramebuffer>,
+    context : Context,
+    logger  : Logger,
+}
+
+impl RenderPipeline {
+    /// Constructor of an empty pipeline.
+    pub fn new(logger:Logger, context:&Context) -> Self {
+        let passes  = default();
+        let targets = default();
+        let context = context.clone();
+        Self {passes,targets,context,logger}
+    }
+
+    /// Constructor of the default pipeline. It clears the screen to black, draws all symbols and
+    /// then draws all text components.
+    pub fn new_default(logger:Logger, context:&Context) -> Self {
+        let mut this = Self::new(logger,context);
+        // FIXME: use correct blending function and rethink premultiplying the alpha.
+        let blend    = BlendState::new(Context::SRC_ALPHA,Context::ONE);
+        let clear    = ClearSettings::color(0.0,0.0,0.0,1.0);
+        this.add(RenderPassDef::new(pass_name::CLEAR,ClearPass).with_clear(clear));
+        this.add(RenderPassDef::new(pass_name::SYMBOLS,SymbolsPass::all()).with_blend(blend));
+        this.add(RenderPassDef::new(pass_name::TEXT,TextPass).with_blend(blend));
+        this
+    }
+
+    /// Adds the pass at the end of the pipeline.
+    pub fn add(&mut self, def:RenderPassDef) {
+        self.passes.push(def);
+    }
+
+    /// Inserts the pass just before the pass with the `anchor` name.
+    pub fn insert_before(&mut self, anchor:&str, def:RenderPassDef) -> Result<(),MissingPass> {
+        let index = self.index_of(anchor)?;
+        self.passes.insert(index,def);
+        Ok(())
+    }
+
+    /// Inserts the pass just after the pass with the `anchor` name.
+    pub fn insert_after(&mut self, anchor:&str, def:RenderPassDef) -> Result<(),MissingPass> {
+        let index = self.index_of(anchor)?;
+        self.passes.insert(index+1,def);
+        Ok(())
+    }
+
+    /// Removes the pass with the provided name.
+    pub fn remove(&mut self, name:&str) -> Result<RenderPassDef,MissingPass> {
+        let index = self.index_of(name)?;
+        Ok(self.passes.remove(index))
+    }
+
+    /// Gets the pass definition by its name.
+    pub fn get(&self, name:&str) -> Option<&RenderPassDef> {
+        self.passes.iter().find(|def| def.name == name)
+    }
+
+    /// Gets the mutable pass definition by its name.
+    pub fn get_mut(&mut self, name:&str) -> Option<&mut RenderPassDef> {
+        self.passes.iter_mut().find(|def| def.name == name)
+    }
+
+    /// Enables or disables the pass with the provided name.
+    pub fn set_enabled(&mut self, name:&str, enabled:bool) -> Result<(),MissingPass> {
+        let index = self.index_of(name)?;
+        self.passes[index].enabled = enabled;
+        Ok(())
+    }
+
+    /// Names of all the passes in the order they are run.
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.passes.iter().map(|def| def.name.as_str()).collect()
+    }
+
+    /// Creates a new offscreen framebuffer which follows the dimensions of the canvas. The
+    /// framebuffer is released when the last reference to it is dropped, for example when the
+    /// pass drawing into it is removed.
+    pub fn new_screen_sized_target(&mut self, screen:&ShapeData, with_depth:bool) -> Framebuffer {
+        let width       = screen.width  as i32;
+        let height      = screen.height as i32;
+        let framebuffer = Framebuffer::new(&self.context,width,height,with_depth);
+        self.targets.retain(|target| target.upgrade().is_some());
+        self.targets.push(framebuffer.downgrade());
+        framebuffer
+    }
+
+    /// The screen-sized targets which are still in use.
+    pub fn screen_sized_targets(&mut self) -> Vec<Framebuffer> {
+        let targets = self.targets.iter().filter_map(|target| target.upgrade()).collect_vec();
+        self.targets = targets.iter().map(|target| target.downgrade()).collect();
+        targets
+    }
+
+    /// Resizes all screen-sized targets. Should be called whenever the canvas is resized.
+    pub fn resize(&mut self, screen:&ShapeData) {
+        for target in self.screen_sized_targets() {
+            target.resize(screen.width as i32, screen.height as i32);
+        }
+    }
+
+    /// Re-creates all targets and GPU resources owned by the passes after the context was lost and
+    /// restored.
+    pub fn restore_context(&mut self) {
+        group!(self.logger, "Restoring the context.", {
+            for target in self.screen_sized_targets() {
+                target.restore_context();
+            }
+            for def in &mut self.passes {
+                if let RenderTarget::Offscreen(framebuffer) = &def.settings.target {
+                    framebuffer.restore_context();
+                }
+                def.pass.restore_context();
+            }
+        })
+    }
+
+    /// Runs all enabled passes in order. The screen is bound when the function returns.
+    pub fn run
+    ( &mut self
+    , screen          : &ShapeData
+    , symbols         : &SymbolRegistry
+    , text_components : &mut [TextComponent]
+    , fonts           : &mut Fonts
+    ) {
+        group!(self.logger, "Running render passes.", {
+            let context = &self.context;
+            for def in self.passes.iter_mut().filter(|def| def.enabled) {
+                group!(self.logger, "Running pass '{def.name}'.", {
+                    def.settings.apply(context,screen);
+                    let target          = &def.settings.target;
+                    let text_components = &mut *text_components;
+                    let fonts           = &mut *fonts;
+                    let mut ctx = PassContext {context,screen,target,symbols,text_components,fonts};
+                    def.pass.run(&mut ctx);
+                })
+            }
+            RenderTarget::Screen.bind(context,screen);
+        })
+    }
+}
+
+
+// === Private API ===
+
+impl RenderPipeline {
+    fn index_of(&self, name:&str) -> Result<usize,MissingPass> {
+        let index = self.passes.iter().position(|def| def.name == name);
+        index.ok_or_else(|| MissingPass {name:name.into()})
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::system::web;
+
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    use wasm_bindgen_test::wasm_bindgen_test_configure;
+    use web_sys::HtmlCanvasElement;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn new_pipeline() -> RenderPipeline {
+        let canvas  = web::create_element("canvas").unwrap();
+        let canvas  = canvas.dyn_into::<HtmlCanvasElement>().unwrap();
+        let context = web::get_webgl2_context(&canvas).unwrap();
+        RenderPipeline::new_default(Logger::new("Test"),&context)
+    }
+
+    #[wasm_bindgen_test]
+    fn modifying_passes() {
+        let mut pipeline = new_pipeline();
+        let overlay      = RenderPassDef::overlay("overlay",SymbolsPass::all());
+        let debug        = RenderPassDef::new("debug",ClearPass);
+        pipeline.insert_after(pass_name::SYMBOLS,overlay).unwrap();
+        pipeline.insert_before(pass_name::CLEAR,debug).unwrap();
+        assert_eq!(pipeline.pass_names(),vec!["debug","clear","symbols","overlay","text"]);
+        pipeline.set_enabled("debug",false).unwrap();
+        assert!(!pipeline.get("debug").unwrap().enabled);
+        assert_eq!(pipeline.remove("debug").unwrap().name,"debug");
+        assert!(pipeline.remove("debug").is_err());
+        assert!(pipeline.insert_after("missing",RenderPassDef::new("x",ClearPass)).is_err());
+        assert_eq!(pipeline.pass_names(),vec!["clear","symbols","overlay","text"]);
+    }
+
+    #[wasm_bindgen_test]
+    fn releasing_screen_sized_targets() {
+        let mut pipeline = new_pipeline();
+        let mut screen   = ShapeData::default();
+        let kept         = pipeline.new_screen_sized_target(&screen,false);
+        let dropped      = pipeline.new_screen_sized_target(&screen,true);
+        let pass         = RenderPassDef::new("offscreen",ClearPass).with_target(&dropped);
+        pipeline.add(pass);
+        drop(dropped);
+        assert_eq!(pipeline.screen_sized_targets().len(),2);
+        pipeline.remove("offscreen").unwrap();
+        assert_eq!(pipeline.screen_sized_targets(),vec![kept.clone_ref()]);
+        screen.set_screen_dimension(200.0,50.0);
+        pipeline.resize(&screen);
+        assert_eq!((kept.width(),kept.height()),(200,50));
+    }
+}
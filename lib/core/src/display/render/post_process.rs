@@ -0,0 +1,302 @@
+//! This module defines the post-processing stack, an ordered list of named full-screen effects
+//! applied to the content of an offscreen framebuffer. Effects can be added, removed, toggled and
+//! parametrised at runtime.
+
+use crate::prelude::*;
+
+use crate::display::render::effect::ColorGradingParams;
+use crate::display::render::effect::Effect;
+use crate::display::render::effect::GaussianBlurParams;
+use crate::display::render::effect::VignetteParams;
+use crate::display::render::effect;
+use crate::display::render::pass::CopyProcessor;
+use crate::display::render::pass::PassContext;
+use crate::display::render::pass::PostProcessor;
+use crate::display::render::pipeline::MissingPass;
+use crate::display::render::target::Framebuffer;
+use crate::system::gpu::data::uniform::UniformScope;
+use crate::system::gpu::shader::Context;
+
+use nalgebra::Vector2;
+use shapely::shared;
+
+
+
+// =================
+// === EffectDef ===
+// =================
+
+/// Definition of an effect registered in the stack.
+#[derive(Debug)]
+pub struct EffectDef {
+    /// Name of the effect. Used to refer to the effect when modifying the stack.
+    pub name    : String,
+    /// Disabled effects are skipped.
+    pub enabled : bool,
+    /// The effect implementation.
+    pub effect  : Effect,
+}
+
+
+
+// ================
+// === Schedule ===
+// ================
+
+/// A framebuffer read or written by a step of the stack.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+enum Slot {
+    /// The framebuffer processed by the stack.
+    Source,
+    /// One of the two intermediate framebuffers.
+    Buffer(usize),
+    /// The target of the pass.
+    Target,
+}
+
+/// A single step of applying the stack.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+enum Step {
+    /// Copies the source to the target unchanged.
+    Copy,
+    /// Draws the effect at the provided index of the stack.
+    Draw { effect:usize, input:Slot, output:Slot },
+}
+
+/// Computes the steps applying the effects of the stack, provided as the list of their enabled
+/// flags. Every enabled effect reads the output of the previous one, and the intermediate results
+/// alternate between the two buffers, so no effect reads and writes the same framebuffer. The last
+/// enabled effect draws into the target. If no effect is enabled, the source is copied.
+fn schedule(enabled:&[bool]) -> Vec<Step> {
+    let effects = enabled.iter().enumerate().filter(|(_,enabled)| **enabled).map(|(ix,_)| ix);
+    let effects = effects.collect_vec();
+    let count   = effects.len();
+    if count == 0 { return vec![Step::Copy] }
+    let mut input = Slot::Source;
+    effects.into_iter().enumerate().map(|(index,effect)| {
+        let is_last = index + 1 == count;
+        let output  = if is_last { Slot::Target } else { Slot::Buffer(index % 2) };
+        let step    = Step::Draw {effect,input,output};
+        input = output;
+        step
+    }).collect()
+}
+
+
+
+// ========================
+// === PostProcessStack ===
+// ========================
+
+shared! { PostProcessStack
+
+/// Ordered list of full-screen effects. Every effect reads the output of the previous one, so the
+/// intermediate results are kept in two framebuffers used alternately. The last enabled effect
+/// draws directly into the target of the pass. When no effect is enabled, the source is copied to
+/// the target unchanged.
+///
+/// The stack is a shared handle, so it can be modified after it was registered in the pipeline as
+/// a `PostProcessPass`.
+#[derive(Debug)]
+pub struct PostProcessStackData {
+    effects : Vec<EffectDef>,
+    buffers : Vec<Framebuffer>,
+    globals : UniformScope,
+    context : Context,
+    logger  : Logger,
+}
+
+impl {
+    /// Constructor. The `globals` scope is used to bind the effect inputs to global variables.
+    pub fn new(logger:Logger, context:&Context, globals:&UniformScope) -> Self {
+        let effects = default();
+        let buffers = default();
+        let globals = globals.clone_ref();
+        let context = context.clone();
+        Self {effects,buffers,globals,context,logger}
+    }
+
+    /// Creates a new empty effect which can be registered in this stack.
+    pub fn new_effect(&self) -> Effect {
+        Effect::new(self.logger.sub("effect"),&self.context,&self.globals)
+    }
+
+    /// Adds the effect at the end of the stack.
+    pub fn add(&mut self, name:&str, effect:Effect) {
+        let name    = name.into();
+        let enabled = true;
+        self.effects.push(EffectDef {name,enabled,effect});
+    }
+
+    /// Inserts the effect just before the effect with the `anchor` name.
+    pub fn insert_before
+    (&mut self, anchor:&str, name:&str, effect:Effect) -> Result<(),MissingPass> {
+        let index   = self.index_of(anchor)?;
+        let name    = name.into();
+        let enabled = true;
+        self.effects.insert(index,EffectDef {name,enabled,effect});
+        Ok(())
+    }
+
+    /// Removes the effect with the provided name.
+    pub fn remove(&mut self, name:&str) -> Result<Effect,MissingPass> {
+        let index = self.index_of(name)?;
+        Ok(self.effects.remove(index).effect)
+    }
+
+    /// Enables or disables the effect with the provided name.
+    pub fn set_enabled(&mut self, name:&str, enabled:bool) -> Result<(),MissingPass> {
+        let index = self.index_of(name)?;
+        self.effects[index].enabled = enabled;
+        Ok(())
+    }
+
+    /// Checks whether the stack contains an effect with the provided name.
+    pub fn contains(&self, name:&str) -> bool {
+        self.index_of(name).is_ok()
+    }
+
+    /// Names of all the effects in the order they are applied.
+    pub fn effect_names(&self) -> Vec<String> {
+        self.effects.iter().map(|def| def.name.clone()).collect()
+    }
+
+    /// Adds a two-pass gaussian blur. The effects are registered as `{name}_horizontal` and
+    /// `{name}_vertical`.
+    pub fn add_gaussian_blur(&mut self, name:&str) -> GaussianBlurParams {
+        let mut horizontal = self.new_effect();
+        let mut vertical   = self.new_effect();
+        let h_radius       = effect::gaussian_blur(&mut horizontal,Vector2::new(1.0,0.0));
+        let v_radius       = effect::gaussian_blur(&mut vertical,Vector2::new(0.0,1.0));
+        self.add(&format!("{}_horizontal",name),horizontal);
+        self.add(&format!("{}_vertical",name),vertical);
+        GaussianBlurParams::new(vec![h_radius,v_radius])
+    }
+
+    /// Adds a color grading effect.
+    pub fn add_color_grading(&mut self, name:&str) -> ColorGradingParams {
+        let mut effect = self.new_effect();
+        let params     = effect::color_grading(&mut effect);
+        self.add(name,effect);
+        params
+    }
+
+    /// Adds a vignette effect.
+    pub fn add_vignette(&mut self, name:&str) -> VignetteParams {
+        let mut effect = self.new_effect();
+        let params     = effect::vignette(&mut effect);
+        self.add(name,effect);
+        params
+    }
+
+    /// Adds a fast approximate anti-aliasing effect.
+    pub fn add_fxaa(&mut self, name:&str) {
+        let mut effect = self.new_effect();
+        effect::fxaa(&mut effect);
+        self.add(name,effect);
+    }
+}}
+
+
+// === Private API ===
+
+impl PostProcessStackData {
+    fn index_of(&self, name:&str) -> Result<usize,MissingPass> {
+        let index = self.effects.iter().position(|def| def.name == name);
+        index.ok_or_else(|| MissingPass {name:name.into()})
+    }
+
+    /// Makes sure there are two intermediate buffers with the dimensions of the source.
+    fn prepare_buffers(&mut self, source:&Framebuffer) {
+        let width  = source.width();
+        let height = source.height();
+        while self.buffers.len() < 2 {
+            self.buffers.push(Framebuffer::new(&self.context,width,height,false));
+        }
+        for buffer in &self.buffers {
+            buffer.resize(width,height);
+        }
+    }
+
+    /// The intermediate buffers are dropped, as they are re-created on demand.
+    fn restore_context(&mut self) {
+        self.buffers.clear();
+        for def in &mut self.effects {
+            def.effect.restore_context();
+        }
+    }
+
+    fn process(&mut self, ctx:&mut PassContext, source:&Framebuffer) {
+        let enabled = self.effects.iter().map(|def| def.enabled).collect_vec();
+        let steps   = schedule(&enabled);
+        if let [Step::Copy] = steps.as_slice() {
+            CopyProcessor.process(ctx,source);
+            return
+        }
+        let count = steps.len();
+        group!(self.logger, "Applying {count} post-processing effects.", {
+            self.prepare_buffers(source);
+            for step in steps {
+                if let Step::Draw {effect,input,output} = step {
+                    let input = match input {
+                        Slot::Buffer(index) => self.buffers[index].clone_ref(),
+                        _                   => source.clone_ref(),
+                    };
+                    match output {
+                        Slot::Buffer(index) => self.buffers[index].bind(),
+                        _                   => ctx.target.bind(ctx.context,ctx.screen),
+                    }
+                    self.effects[effect].effect.draw(&input);
+                }
+            }
+        })
+    }
+}
+
+impl PostProcessor for PostProcessStack {
+    fn process(&mut self, ctx:&mut PassContext, source:&Framebuffer) {
+        self.rc.borrow_mut().process(ctx,source)
+    }
+
+    fn restore_context(&mut self) {
+        self.rc.borrow_mut().restore_context()
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(effect:usize, input:Slot, output:Slot) -> Step {
+        Step::Draw {effect,input,output}
+    }
+
+    #[test]
+    fn empty_stack_copies_the_source() {
+        assert_eq!(schedule(&[]),vec![Step::Copy]);
+        assert_eq!(schedule(&[false,false]),vec![Step::Copy]);
+    }
+
+    #[test]
+    fn single_effect_draws_into_the_target() {
+        assert_eq!(schedule(&[true]),vec![draw(0,Slot::Source,Slot::Target)]);
+        assert_eq!(schedule(&[false,true]),vec![draw(1,Slot::Source,Slot::Target)]);
+    }
+
+    #[test]
+    fn effects_are_applied_in_order_with_ping_pong_buffers() {
+        let steps = schedule(&[true,true,false,true,true]);
+        assert_eq!(steps,vec!
+            [ draw(0,Slot::Source    ,Slot::Buffer(0))
+            , draw(1,Slot::Buffer(0) ,Slot::Buffer(1))
+            , draw(3,Slot::Buffer(1) ,Slot::Buffer(0))
+            , draw(4,Slot::Buffer(0) ,Slot::Target)
+            ]);
+    }
+}
@@ -0,0 +1,347 @@
+//! This module defines render targets. A render target is the place render passes draw into. It is
+//! either the screen (the default framebuffer of the canvas) or an offscreen framebuffer with its
+//! own color texture, which can be sampled by the following passes.
+
+use crate::prelude::*;
+
+use crate::display::world::workspace::ShapeData;
+use crate::system::gpu::context::GpuContext;
+use crate::system::gpu::shader::Context;
+
+
+
+// ===================
+// === Framebuffer ===
+// ===================
+
+/// Offscreen framebuffer with a RGBA color texture attachment and an optional depth attachment.
+/// The GPU resources are released as soon as the last reference to the framebuffer is dropped.
+#[derive(Clone,Debug)]
+pub struct Framebuffer<C:GpuContext=Context> {
+    rc: Rc<RefCell<FramebufferData<C>>>,
+}
+
+impl<C:GpuContext> Framebuffer<C> {
+    /// Constructor.
+    pub fn new(context:&C, width:i32, height:i32, with_depth:bool) -> Self {
+        let data = FramebufferData::new(context,width,height,with_depth);
+        let rc   = Rc::new(RefCell::new(data));
+        Self {rc}
+    }
+
+    /// Cheap clone of the structure. Implemented as the `Rc::clone` under the hood.
+    pub fn clone_ref(&self) -> Self {
+        self.clone()
+    }
+
+    /// Width of the attachments in pixels.
+    pub fn width(&self) -> i32 {
+        self.rc.borrow().width()
+    }
+
+    /// Height of the attachments in pixels.
+    pub fn height(&self) -> i32 {
+        self.rc.borrow().height()
+    }
+
+    /// The texture keeping the color attachment of this framebuffer.
+    pub fn color_texture(&self) -> C::Texture {
+        self.rc.borrow().color_texture()
+    }
+
+    /// The underlying WebGL framebuffer.
+    pub fn gl_framebuffer(&self) -> C::Framebuffer {
+        self.rc.borrow().gl_framebuffer()
+    }
+
+    /// Re-allocates the attachments with the new dimensions. Does nothing if the dimensions did not
+    /// change. The previous content is lost.
+    pub fn resize(&self, width:i32, height:i32) {
+        self.rc.borrow_mut().resize(width,height)
+    }
+
+    /// Re-creates the framebuffer and its attachments after the context was lost and restored.
+    /// Does nothing if the framebuffer is still valid, so it is safe to call it multiple times.
+    pub fn restore_context(&self) {
+        self.rc.borrow_mut().restore_context()
+    }
+
+    /// Binds the framebuffer as both read and draw target and sets the viewport to cover it.
+    pub fn bind(&self) {
+        self.rc.borrow().bind()
+    }
+}
+
+/// The internal state of the `Framebuffer`.
+#[derive(Debug)]
+pub struct FramebufferData<C:GpuContext=Context> {
+    context        : C,
+    gl_framebuffer : C::Framebuffer,
+    color          : C::Texture,
+    depth          : Option<C::Renderbuffer>,
+    width          : i32,
+    height         : i32,
+}
+
+impl<C:GpuContext> FramebufferData<C> {
+    /// Constructor.
+    pub fn new(context:&C, width:i32, height:i32, with_depth:bool) -> Self {
+        let context        = context.clone();
+        let gl_framebuffer = context.create_framebuffer().unwrap();
+        let color          = context.create_texture().unwrap();
+        let depth          = if with_depth { context.create_renderbuffer() } else { None };
+        let mut this       = Self {context,gl_framebuffer,color,depth,width,height};
+        this.allocate();
+        this
+    }
+
+    /// Width of the attachments in pixels.
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    /// Height of the attachments in pixels.
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// The texture keeping the color attachment of this framebuffer.
+    pub fn color_texture(&self) -> C::Texture {
+        self.color.clone()
+    }
+
+    /// The underlying WebGL framebuffer.
+    pub fn gl_framebuffer(&self) -> C::Framebuffer {
+        self.gl_framebuffer.clone()
+    }
+
+    /// Re-allocates the attachments with the new dimensions. Does nothing if the dimensions did not
+    /// change. The previous content is lost.
+    pub fn resize(&mut self, width:i32, height:i32) {
+        if self.width != width || self.height != height {
+            self.width  = width;
+            self.height = height;
+            self.allocate();
+        }
+    }
+
+    /// Re-creates the framebuffer and its attachments after the context was lost and restored.
+    /// Does nothing if the framebuffer is still valid, so it is safe to call it multiple times.
+    pub fn restore_context(&mut self) {
+        if !self.context.is_framebuffer(Some(&self.gl_framebuffer)) {
+            self.gl_framebuffer = self.context.create_framebuffer().unwrap();
+            self.color          = self.context.create_texture().unwrap();
+            if self.depth.is_some() {
+                self.depth = self.context.create_renderbuffer();
+            }
+            self.allocate();
+        }
+    }
+
+    /// Binds the framebuffer as both read and draw target and sets the viewport to cover it.
+    pub fn bind(&self) {
+        self.context.bind_framebuffer(Context::FRAMEBUFFER,Some(&self.gl_framebuffer));
+        self.context.viewport(0,0,self.width,self.height);
+    }
+}
+
+
+// === Private API ===
+
+impl<C:GpuContext> FramebufferData<C> {
+    fn allocate(&mut self) {
+        let context         = &self.context;
+        let target          = Context::TEXTURE_2D;
+        let level           = 0;
+        let internal_format = Context::RGBA as i32;
+        let format          = Context::RGBA;
+        let elem_type       = Context::UNSIGNED_BYTE;
+        let wrap            = Context::CLAMP_TO_EDGE as i32;
+        let filter          = Context::LINEAR as i32;
+        let (width,height)  = (self.width,self.height);
+        context.bind_texture(target,Some(&self.color));
+        context.tex_image_2d
+            (target,level,internal_format,width,height,format,elem_type,None::<&[u8]>);
+        context.tex_parameteri(target,Context::TEXTURE_MIN_FILTER,filter);
+        context.tex_parameteri(target,Context::TEXTURE_MAG_FILTER,filter);
+        context.tex_parameteri(target,Context::TEXTURE_WRAP_S    ,wrap);
+        context.tex_parameteri(target,Context::TEXTURE_WRAP_T    ,wrap);
+        context.bind_texture(target,None);
+
+        context.bind_framebuffer(Context::FRAMEBUFFER,Some(&self.gl_framebuffer));
+        context.framebuffer_texture_2d
+            (Context::FRAMEBUFFER,Context::COLOR_ATTACHMENT0,target,Some(&self.color),level);
+        if let Some(depth) = &self.depth {
+            let rb_target = Context::RENDERBUFFER;
+            context.bind_renderbuffer(rb_target,Some(depth));
+            context.renderbuffer_storage
+                (rb_target,Context::DEPTH_COMPONENT16,self.width,self.height);
+            context.bind_renderbuffer(rb_target,None);
+            context.framebuffer_renderbuffer
+                (Context::FRAMEBUFFER,Context::DEPTH_ATTACHMENT,rb_target,Some(depth));
+        }
+        context.bind_framebuffer(Context::FRAMEBUFFER,None);
+    }
+}
+
+impl<C:GpuContext> Drop for FramebufferData<C> {
+    fn drop(&mut self) {
+        self.context.delete_framebuffer(Some(&self.gl_framebuffer));
+        self.context.delete_texture(Some(&self.color));
+        self.context.delete_renderbuffer(self.depth.as_ref());
+    }
+}
+
+impl<C:GpuContext> PartialEq for Framebuffer<C> {
+    fn eq(&self, other:&Self) -> bool {
+        Rc::ptr_eq(&self.rc,&other.rc)
+    }
+}
+
+impl<C:GpuContext> Framebuffer<C> {
+    /// A weak reference to this framebuffer. It does not keep the GPU resources alive.
+    pub fn downgrade(&self) -> WeakFramebuffer<C> {
+        let weak = Rc::downgrade(&self.rc);
+        WeakFramebuffer {weak}
+    }
+}
+
+
+
+// =======================
+// === WeakFramebuffer ===
+// =======================
+
+/// Weak reference to a `Framebuffer`. It allows tracking framebuffers without preventing their
+/// GPU resources from being released when the last `Framebuffer` reference is dropped.
+#[derive(Debug,Derivative)]
+#[derivative(Clone(bound=""))]
+pub struct WeakFramebuffer<C:GpuContext=Context> {
+    weak : Weak<RefCell<FramebufferData<C>>>,
+}
+
+impl<C:GpuContext> WeakFramebuffer<C> {
+    /// The framebuffer, if it is still alive.
+    pub fn upgrade(&self) -> Option<Framebuffer<C>> {
+        self.weak.upgrade().map(|rc| Framebuffer {rc})
+    }
+}
+
+
+
+// ====================
+// === RenderTarget ===
+// ====================
+
+/// The place a render pass draws into.
+#[derive(Clone,Debug,PartialEq)]
+pub enum RenderTarget {
+    /// The default framebuffer of the canvas.
+    Screen,
+    /// An offscreen framebuffer.
+    Offscreen(Framebuffer),
+}
+
+impl Default for RenderTarget {
+    fn default() -> Self {
+        Self::Screen
+    }
+}
+
+impl From<Framebuffer> for RenderTarget {
+    fn from(t:Framebuffer) -> Self {
+        Self::Offscreen(t)
+    }
+}
+
+impl From<&Framebuffer> for RenderTarget {
+    fn from(t:&Framebuffer) -> Self {
+        Self::Offscreen(t.clone_ref())
+    }
+}
+
+impl RenderTarget {
+    /// Binds the target and sets the viewport to cover it. The `screen` describes the canvas
+    /// dimensions used by the `Screen` target.
+    pub fn bind(&self, context:&Context, screen:&ShapeData) {
+        match self {
+            Self::Screen => {
+                GpuContext::bind_framebuffer(context,Context::FRAMEBUFFER,None);
+                GpuContext::viewport(context,0,0,screen.width as i32,screen.height as i32);
+            }
+            Self::Offscreen(framebuffer) => framebuffer.bind(),
+        }
+    }
+
+    /// Dimensions of the target in pixels.
+    pub fn size(&self, screen:&ShapeData) -> (i32,i32) {
+        match self {
+            Self::Screen                 => (screen.width as i32, screen.height as i32),
+            Self::Offscreen(framebuffer) => (framebuffer.width(), framebuffer.height()),
+        }
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::system::gpu::context::mock::Call;
+    use crate::system::gpu::context::mock::MockContext;
+    use crate::system::gpu::context::mock::MockId;
+
+    fn allocations(calls:&[Call]) -> Vec<(i32,i32)> {
+        calls.iter().filter_map(|call| match call {
+            Call::TexImage2D {width,height,..} => Some((*width,*height)),
+            _                                  => None,
+        }).collect()
+    }
+
+    #[test]
+    fn framebuffer_lifecycle() {
+        let context     = MockContext::new();
+        let framebuffer = Framebuffer::new(&context,4,2,true);
+        let calls       = context.take_calls();
+        assert_eq!(allocations(&calls),vec![(4,2)]);
+        assert!(calls.contains(&Call::RenderbufferStorage {
+            target          : Context::RENDERBUFFER,
+            internal_format : Context::DEPTH_COMPONENT16,
+            width           : 4,
+            height          : 2,
+        }));
+        assert_eq!(calls.last(),Some(&Call::BindFramebuffer {
+            target      : Context::FRAMEBUFFER,
+            framebuffer : None,
+        }));
+
+        framebuffer.resize(4,2);
+        assert!(context.take_calls().is_empty());
+        framebuffer.resize(8,6);
+        assert_eq!(allocations(&context.take_calls()),vec![(8,6)]);
+
+        let gl_framebuffer = framebuffer.gl_framebuffer();
+        framebuffer.bind();
+        assert_eq!(context.take_calls(),vec!
+            [ Call::BindFramebuffer {target:Context::FRAMEBUFFER, framebuffer:Some(gl_framebuffer)}
+            , Call::Viewport        {x:0, y:0, width:8, height:6}
+            ]);
+
+        let weak  = framebuffer.downgrade();
+        let color = framebuffer.color_texture();
+        drop(framebuffer);
+        assert!(weak.upgrade().is_none());
+        let calls = context.take_calls();
+        assert!(calls.contains(&Call::DeleteFramebuffer {framebuffer:Some(gl_framebuffer)}));
+        assert!(calls.contains(&Call::DeleteTexture {texture:Some(color)}));
+        assert!(calls.iter().any(|call| match call {
+            Call::DeleteRenderbuffer {renderbuffer:Some(MockId(_))} => true,
+            _                                                       => false,
+        }));
+    }
+}
@@ -13,6 +13,8 @@ use crate::display::shape::primitive::def::class::ShapeRef;
 use crate::display::shape::primitive::shader::canvas::Canvas;
 use crate::display::shape::primitive::shader::canvas::CanvasShape;
 use crate::display::shape::primitive::shader::data::ShaderData;
+use crate::system::gpu::shader::diagnostic::Origin;
+use crate::system::gpu::shader::diagnostic::with_origin;
 use crate::system::gpu::shader::glsl::Glsl;
 
 use crate::system::gpu::shader::glsl::traits::*;
@@ -155,12 +157,13 @@ macro_rules! _define_sdf_shape_immutable_part {
 
         impl SdfShape for $name {
             fn glsl_definition() -> String {
-                let name = stringify!($name).to_snake_case();
-                let body = stringify!($body);
-                let args = vec!["vec2 position".to_string(), $(
+                let name   = stringify!($name).to_snake_case();
+                let body   = stringify!($body);
+                let args   = vec!["vec2 position".to_string(), $(
                     format!("{} {}", <$field_type>::glsl_prim_type(), stringify!($field))
                 ),*].join(", ");
-                iformat!("BoundSdf {name} ({args}) {body}")
+                let origin = Origin::SdfPrimitive(name.clone());
+                with_origin(origin,iformat!("BoundSdf {name} ({args}) {body}"))
             }
         }
     }
@@ -8,6 +8,9 @@ use super::super::class::Shape;
 use crate::display::shape::primitive::def::sdf;
 use crate::display::symbol::shader::builder::CodeTemplete;
 use crate::display::shape::primitive::shader::overload;
+use crate::system::gpu::shader::diagnostic::Origin;
+use crate::system::gpu::shader::diagnostic::with_origin;
+use crate::system::gpu::shader::sources::ShaderSources;
 
 
 // ===============
@@ -22,6 +25,11 @@ const DEBUG           :&str = include_str!("../glsl/debug.glsl");
 const SHAPE           :&str = include_str!("../glsl/shape.glsl");
 const FRAGMENT_RUNNER :&str = include_str!("../glsl/fragment_runner.glsl");
 
+/// Directory of the GLSL sources, relative to the `src` directory of this crate. The sources are
+/// registered in `ShaderSources` under paths in this directory, so they can be reloaded from a
+/// development server serving the `src` directory.
+pub const GLSL_DIR : &str = "display/shape/primitive/glsl";
+
 
 // === Definition ===
 
@@ -30,8 +38,9 @@ const FRAGMENT_RUNNER :&str = include_str!("../glsl/fragment_runner.glsl");
 pub struct Builder {}
 
 impl Builder {
-    /// Returns the final GLSL code.
-    pub fn run<S:Shape>(shape:&S) -> CodeTemplete {
+    /// Returns the final GLSL code. The GLSL libraries are not embedded in the code, but they are
+    /// defined in the provided `ShaderSources` and included.
+    pub fn run<S:Shape>(shape:&S, sources:&ShaderSources) -> CodeTemplete {
         let sdf_defs     = sdf::all_shapes_glsl_definitions();
         let mut canvas   = Canvas::default();
         let shape_ref    = shape.draw(&mut canvas);
@@ -41,22 +50,51 @@ impl Builder {
         canvas.submit_shape_constructor("run");
         let defs = iformat!("{defs_header}\n\n{sdf_defs}\n\n\n\n{shape_header}\n\n{canvas.to_glsl()}");
 
-        let redirections = overload::builtin_redirections();
-        let math         = overload::allow_overloading(MATH);
-        let color        = overload::allow_overloading(COLOR);
-        let debug        = overload::allow_overloading(DEBUG);
-        let shape        = overload::allow_overloading(SHAPE);
+        let redirections = library("redirections",overload::builtin_redirections());
+        let math         = include_library(sources,"math",MATH);
+        let color        = include_library(sources,"color",COLOR);
+        let debug        = include_library(sources,"debug",DEBUG);
+        let shape        = include_library(sources,"shape",SHAPE);
+        let runner       = include_source(sources,"fragment_runner",FRAGMENT_RUNNER);
 
         let defs = overload::allow_overloading(&defs);
         let code = format!("{}\n\n{}\n\n{}\n\n{}\n\n{}\n\n{}",redirections,math,color,debug,shape,defs);
 
-        CodeTemplete::new(code,FRAGMENT_RUNNER.to_string(),default())
+        CodeTemplete::new(code,runner,default())
     }
 }
 
 
 // == Utils ===
 
+/// Marks the code as originating from the GLSL library with the provided name.
+fn library(name:&str, code:String) -> String {
+    with_origin(Origin::Library(name.into()),code)
+}
+
+/// Defines the GLSL library with the provided name in `sources`, if it was not defined yet, and
+/// returns the directive including it. The library code allows overloading and is marked with
+/// its origin.
+fn include_library(sources:&ShaderSources, name:&str, code:&str) -> String {
+    let path = source_path(name);
+    let name = name.to_string();
+    sources.define_with(&path,code,move |code| library(&name,overload::allow_overloading(code)));
+    format!("#include \"{}\"",path)
+}
+
+/// Defines the GLSL source with the provided name in `sources`, if it was not defined yet, and
+/// returns the directive including it.
+fn include_source(sources:&ShaderSources, name:&str, code:&str) -> String {
+    let path = source_path(name);
+    sources.define(&path,code);
+    format!("#include \"{}\"",path)
+}
+
+/// The path of the GLSL source with the provided name.
+fn source_path(name:&str) -> String {
+    format!("{}/{}.glsl",GLSL_DIR,name)
+}
+
 /// Defines glsl comment being a pretty printed header of a code section.
 fn header(label:&str) -> String {
     let border_len = label.len() + 8;
@@ -2,6 +2,8 @@
 
 use crate::prelude::*;
 use crate::display::shape::primitive::shader::data::ShaderData;
+use crate::system::gpu::shader::diagnostic::Origin;
+use crate::system::gpu::shader::diagnostic::with_origin;
 use crate::system::gpu::shader::glsl::Glsl;
 
 
@@ -129,9 +131,12 @@ impl Canvas {
     }
 
     /// Submits the `current_function_lines` as a new shape construction function in the GLSL code.
+    /// The function is marked with its origin, so compilation diagnostics can refer to it.
     pub fn submit_shape_constructor(&mut self, name:&str) {
-        let body = self.current_function_lines.join("\n    ");
-        let func = iformat!("Shape {name} (Env env, vec2 position) {{\n    {body}\n}}");
+        let body   = self.current_function_lines.join("\n    ");
+        let func   = iformat!("Shape {name} (Env env, vec2 position) {{\n    {body}\n}}");
+        let origin = Origin::CanvasShape(name.into());
+        let func   = with_origin(origin,func);
         self.current_function_lines = default();
         self.functions.push(func);
     }
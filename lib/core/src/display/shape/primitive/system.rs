@@ -8,6 +8,7 @@ use crate::display::world::World;
 use crate::display::symbol::material::Material;
 use crate::display::shape::primitive::shader;
 use crate::display::shape::primitive::def::class::Shape;
+use crate::system::gpu::shader::sources::ShaderSources;
 
 
 /// Defines a system containing shapes. It is a specialized `SpriteSystem` version.
@@ -21,19 +22,21 @@ pub struct ShapeSystem {
 impl ShapeSystem {
     /// Constructor.
     pub fn new<S:Shape>(world:&World, shape:&S) -> Self {
-        let mut sprite_system = SpriteSystem::new(world);
-        sprite_system.set_material(Self::material(shape));
+        let sprite_system = SpriteSystem::new(world);
+        let sources       = world.rc.borrow().workspace.symbols.sources.clone_ref();
+        let material      = Self::material(shape,&sources);
+        sprite_system.set_material(material);
         Self {sprite_system}
     }
 
-    /// Defines a default material of this system.
-    fn material<S:Shape>(shape:&S) -> Material {
+    /// Defines a default material of this system. The GLSL libraries are included from `sources`.
+    fn material<S:Shape>(shape:&S, sources:&ShaderSources) -> Material {
         let mut material = Material::new();
         material.add_input("pixel_ratio"  , 1.0);
         material.add_input("zoom"         , 1.0);
         material.add_input("time"         , 0.0);
         material.add_input("display_mode" , 0);
-        let code = shader::builder::Builder::run(shape);
+        let code = shader::builder::Builder::run(shape,sources);
         material.set_code(code);
         material
     }
@@ -136,6 +136,25 @@ impl TextComponent {
         }
     }
 
+    /// Re-creates all GPU resources after the context was lost and restored. The scroll position
+    /// and cursors are kept.
+    pub fn restore_context(&mut self, fonts:&mut Fonts) {
+        let gl_context         = &self.gl_context;
+        let display_size       = self.buffers.display_size;
+        let scroll_position    = self.buffers.scroll_offset;
+        let refresh_info       = self.content.refresh_info(fonts);
+        self.content_program   = create_content_program(gl_context);
+        self.cursors_program   = create_cursors_program(gl_context);
+        self.gl_msdf_texture   = create_msdf_texture(gl_context);
+        self.msdf_texture_rows = 0;
+        self.buffers           = TextComponentBuffers::new(gl_context,display_size,refresh_info);
+        self.cursors.buffer    = gl_context.create_buffer();
+        self.cursors.dirty     = true;
+        self.buffers.jump_to(scroll_position);
+        self.content_program.set_constant_uniforms(&self.properties);
+        self.cursors_program.set_constant_uniforms(&self.properties);
+    }
+
     pub fn navigate_cursors(&mut self, step:Step, selecting:bool, fonts:&mut Fonts) {
         let content        = &mut self.content;
         let mut navigation = CursorNavigation {content,fonts,selecting};
@@ -264,7 +283,7 @@ impl<'a,'b,Str:AsRef<str>> TextComponentBuilder<'a,'b,Str> {
         let gl_context      = self.workspace.context.clone();
         let content_program = create_content_program(&gl_context);
         let cursors_program = create_cursors_program(&gl_context);
-        let gl_msdf_texture = self.create_msdf_texture(&gl_context);
+        let gl_msdf_texture = create_msdf_texture(&gl_context);
         let display_size    = self.properties.size / self.properties.text_size;
         let mut content     = TextComponentContent::new(self.font_id,self.text.as_ref());
         let initial_refresh = content.refresh_info(self.fonts);
@@ -285,17 +304,17 @@ impl<'a,'b,Str:AsRef<str>> TextComponentBuilder<'a,'b,Str> {
         }
     }
 
-    fn create_msdf_texture(&mut self, gl_ctx:&Context)
-    -> WebGlTexture {
-        let msdf_texture = gl_ctx.create_texture().unwrap();
-        let target       = Context::TEXTURE_2D;
-        let wrap         = Context::CLAMP_TO_EDGE as i32;
-        let min_filter   = Context::LINEAR as i32;
-
-        gl_ctx.bind_texture(target,Some(&msdf_texture));
-        gl_ctx.tex_parameteri(target,Context::TEXTURE_WRAP_S,wrap);
-        gl_ctx.tex_parameteri(target,Context::TEXTURE_WRAP_T,wrap);
-        gl_ctx.tex_parameteri(target,Context::TEXTURE_MIN_FILTER,min_filter);
-        msdf_texture
-    }
+}
+
+fn create_msdf_texture(gl_ctx:&Context) -> WebGlTexture {
+    let msdf_texture = gl_ctx.create_texture().unwrap();
+    let target       = Context::TEXTURE_2D;
+    let wrap         = Context::CLAMP_TO_EDGE as i32;
+    let min_filter   = Context::LINEAR as i32;
+
+    gl_ctx.bind_texture(target,Some(&msdf_texture));
+    gl_ctx.tex_parameteri(target,Context::TEXTURE_WRAP_S,wrap);
+    gl_ctx.tex_parameteri(target,Context::TEXTURE_WRAP_T,wrap);
+    gl_ctx.tex_parameteri(target,Context::TEXTURE_MIN_FILTER,min_filter);
+    msdf_texture
 }
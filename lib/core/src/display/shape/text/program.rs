@@ -36,7 +36,7 @@ impl BasicProgram {
         let frag_type         = WebGl2RenderingContext::FRAGMENT_SHADER;
         let vert_shader       = compile_shader(gl_context,vert_type,vertex_shader_body).unwrap();
         let frag_shader       = compile_shader(gl_context,frag_type,fragment_shader_body).unwrap();
-        let gl_program        = link_program(&gl_context,&vert_shader,&frag_shader).unwrap();
+        let gl_program        = link_program(gl_context,&vert_shader,&frag_shader).unwrap();
         let to_scene_location = gl_context.get_uniform_location(&gl_program,"to_scene").unwrap();
         BasicProgram {gl_program,to_scene_location,
             gl_context : gl_context.clone(),
@@ -1,13 +1,21 @@
 #![allow(missing_docs)]
 
+#[warn(missing_docs)]
+pub mod culling;
+#[warn(missing_docs)]
+pub mod draw_mode;
 #[warn(missing_docs)]
 pub mod geometry;
 #[warn(missing_docs)]
+pub mod layer;
+#[warn(missing_docs)]
 pub mod material;
 #[warn(missing_docs)]
 pub mod registry;
 #[warn(missing_docs)]
 pub mod shader;
+#[warn(missing_docs)]
+pub mod validation;
 
 pub mod types {
     use super::*;
@@ -21,35 +29,48 @@ use crate::prelude::*;
 use crate::data::dirty::traits::*;
 use crate::data::dirty;
 use crate::debug::stats::Stats;
+use crate::system::gpu::context::GpuContext;
+use crate::system::gpu::data::buffer::Buffer;
+use crate::system::gpu::data::gl_enum::traits::*;
 use crate::system::gpu::data::uniform::AnyPrimUniformOps;
 use crate::system::gpu::data::uniform::AnyTextureUniformOps;
+use crate::system::gpu::shader::cache::CachedProgram;
+use crate::system::gpu::shader::cache::ProgramCache;
+use crate::system::gpu::shader::sources::ShaderSources;
+use crate::display::symbol::culling::BoundingBox;
+use crate::display::symbol::culling::CullingMode;
+use crate::display::symbol::culling::Frustum;
+use crate::display::symbol::draw_mode::DrawMode;
 use crate::display::symbol::geometry::primitive::mesh;
+use crate::display::symbol::shader::variant::Variant;
+use crate::display::symbol::validation::Candidate;
+use crate::display::symbol::validation::InputType;
 
 use shader::Shader;
 
-use web_sys::WebGlVertexArrayObject;
-use web_sys::WebGlProgram;
-use web_sys::WebGlUniformLocation;
-use wasm_bindgen::JsValue;
+use nalgebra::Matrix4;
+use nalgebra::Vector2;
+use nalgebra::Vector4;
+use std::cmp::Ordering;
 
 
 /// Binds input variable definition in shader to both its location and an uniform declaration.
 #[derive(Clone,Debug)]
-pub struct UniformBinding {
+pub struct UniformBinding<C:GpuContext=Context> {
     name     : String,
-    location : WebGlUniformLocation,
+    location : C::UniformLocation,
     uniform  : AnyPrimUniform,
 }
 
-impl UniformBinding {
+impl<C:GpuContext> UniformBinding<C> {
     /// Create new uniform binding.
-    pub fn new<Name:Str>(name:Name, location:WebGlUniformLocation, uniform:AnyPrimUniform) -> Self {
+    pub fn new<Name:Str>(name:Name, location:C::UniformLocation, uniform:AnyPrimUniform) -> Self {
         let name = name.into();
         Self {name,location,uniform}
     }
 
     /// Upload uniform value.
-    pub fn upload(&self, context:&Context) {
+    pub fn upload(&self, context:&C) {
         self.uniform.upload(context,&self.location);
     }
 }
@@ -58,19 +79,19 @@ type TextureUnit = u32;
 
 /// Binds input sampler definition in shader to its location, uniform declaration and texture unit.
 #[derive(Clone,Debug)]
-pub struct TextureBinding {
+pub struct TextureBinding<C:GpuContext=Context> {
     name         : String,
-    location     : WebGlUniformLocation,
-    uniform      : AnyTextureUniform,
+    location     : C::UniformLocation,
+    uniform      : AnyTextureUniform<C>,
     texture_unit : TextureUnit,
 }
 
-impl TextureBinding {
+impl<C:GpuContext> TextureBinding<C> {
     /// Create new texture binding.
     pub fn new<Name:Str>
     ( name         : Name
-    , location     : WebGlUniformLocation
-    , uniform      : AnyTextureUniform
+    , location     : C::UniformLocation
+    , uniform      : AnyTextureUniform<C>
     , texture_unit : TextureUnit
     ) -> Self {
         let name = name.into();
@@ -78,13 +99,13 @@ impl TextureBinding {
     }
 
     /// Bind texture to proper texture unit.
-    pub fn bind_texture_unit(&self, context:&Context) -> TextureBindGuard {
+    pub fn bind_texture_unit(&self, context:&C) -> TextureBindGuard<C> {
         self.uniform.bind_texture_unit(context,self.texture_unit)
     }
 
     /// Upload uniform value.
-    pub fn upload_uniform(&self, context:&Context) {
-        context.uniform1i(Some(&self.location),self.texture_unit as i32);
+    pub fn upload_uniform(&self, context:&C) {
+        context.uniform_iv(Some(&self.location),1,&[self.texture_unit as i32]);
     }
 }
 
@@ -97,18 +118,18 @@ impl TextureBinding {
 /// A safe wrapper for WebGL VertexArrayObject. It implements `drop` which deletes the VAO from
 /// GPU memory as soon as this object is dropped.
 #[derive(Debug)]
-pub struct VertexArrayObject {
-    context : Context,
-    vao     : WebGlVertexArrayObject,
+pub struct VertexArrayObject<C:GpuContext=Context> {
+    context : C,
+    vao     : C::VertexArray,
 }
 
 // === Public API ===
 
-impl VertexArrayObject {
+impl<C:GpuContext> VertexArrayObject<C> {
     /// Creates a new VAO instance.
-    pub fn new(context:&Context) -> Self {
+    pub fn new(context:&C) -> Self {
         let context = context.clone();
-        let vao     = context.create_vertex_array().unwrap();
+        let vao     = GpuContext::create_vertex_array(&context).unwrap();
         Self {context,vao}
     }
 
@@ -124,22 +145,22 @@ impl VertexArrayObject {
 
 // === Private API ===
 
-impl VertexArrayObject {
+impl<C:GpuContext> VertexArrayObject<C> {
     fn bind(&self) {
-        self.context.bind_vertex_array(Some(&self.vao));
+        GpuContext::bind_vertex_array(&self.context,Some(&self.vao));
     }
 
     fn unbind(&self) {
-        self.context.bind_vertex_array(None);
+        GpuContext::bind_vertex_array(&self.context,None);
     }
 }
 
 
 // === Instances ===
 
-impl Drop for VertexArrayObject {
+impl<C:GpuContext> Drop for VertexArrayObject<C> {
     fn drop(&mut self) {
-        self.context.delete_vertex_array(Some(&self.vao));
+        GpuContext::delete_vertex_array(&self.context,Some(&self.vao));
     }
 }
 
@@ -154,18 +175,23 @@ impl Drop for VertexArrayObject {
 /// Symbol is a surface with attached `Shader`.
 #[derive(Derivative)]
 #[derivative(Debug(bound=""))]
-pub struct Symbol {
-    pub surface        : Mesh,
-    pub shader         : Shader,
+pub struct Symbol<C:GpuContext=Context> {
+    pub surface        : Mesh<C>,
+    pub shader         : Shader<C>,
     pub surface_dirty  : GeometryDirty,
     pub shader_dirty   : ShaderDirty,
-    pub symbol_scope   : UniformScope,
-    global_scope       : UniformScope,
-    context            : Context,
+    pub symbol_scope   : UniformScope<C>,
+    global_scope       : UniformScope<C>,
+    context            : C,
     logger             : Logger,
-    vao                : Option<VertexArrayObject>,
-    uniforms           : Vec<UniformBinding>,
-    textures           : Vec<TextureBinding>,
+    vao                : Option<VertexArrayObject<C>>,
+    uniforms           : Vec<UniformBinding<C>>,
+    textures           : Vec<TextureBinding<C>>,
+    depth_sorting      : bool,
+    culling            : CullingMode,
+    draw_mode          : DrawMode,
+    culled             : bool,
+    visible_instances  : Option<usize>,
     stats              : Stats,
 }
 
@@ -176,6 +202,16 @@ pub enum ScopeType {
     Mesh(mesh::ScopeType), Symbol, Global
 }
 
+impl Display for ScopeType {
+    fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mesh(scope) => write!(f,"{} buffer",scope.to_string().to_lowercase()),
+            Self::Symbol      => write!(f,"symbol uniform"),
+            Self::Global      => write!(f,"global uniform"),
+        }
+    }
+}
+
 pub type GeometryDirty = dirty::SharedBool<Box<dyn Fn()>>;
 pub type ShaderDirty   = dirty::SharedBool<Box<dyn Fn()>>;
 
@@ -183,11 +219,18 @@ pub type ShaderDirty   = dirty::SharedBool<Box<dyn Fn()>>;
 
 // === Implementation ===
 
-impl Symbol {
+impl<C:GpuContext> Symbol<C> {
 
     /// Create new instance with the provided on-dirty callback.
     pub fn new <OnMut:Fn()+Clone+'static>
-    (global_scope:&UniformScope, logger:Logger, stats:&Stats, context:&Context, on_mut:OnMut) -> Self {
+    ( global_scope : &UniformScope<C>
+    , logger       : Logger
+    , stats        : &Stats
+    , context      : &C
+    , programs     : &ProgramCache<C>
+    , sources      : &ShaderSources
+    , on_mut       : OnMut
+    ) -> Self {
         stats.inc_symbol_count();
         let init_logger = logger.clone();
         group!(init_logger, "Initializing.", {
@@ -202,16 +245,21 @@ impl Symbol {
             let shader_dirty2   = shader_dirty.clone_ref();
             let surface_on_mut  = Box::new(move || { surface_dirty2.set() });
             let shader_on_mut   = Box::new(move || { shader_dirty2.set() });
-            let shader          = Shader::new(shader_logger,&stats,context,shader_on_mut);
+            let shader          = Shader::new(shader_logger,&stats,programs,sources,shader_on_mut);
             let surface         = Mesh::new(surface_logger,&stats,context,surface_on_mut);
             let symbol_scope    = UniformScope::new(logger.sub("uniform_scope"),context);
             let global_scope    = global_scope.clone();
             let vao             = default();
             let uniforms        = default();
             let textures        = default();
-            let stats           = stats.clone_ref();
-            let context         = context.clone();
-            Self{surface,shader,surface_dirty,shader_dirty,symbol_scope,global_scope,logger,context,vao,uniforms,textures,stats}
+            let depth_sorting     = false;
+            let culling           = default();
+            let draw_mode         = default();
+            let culled            = false;
+            let visible_instances = None;
+            let stats             = stats.clone_ref();
+            let context           = context.clone();
+            Self{surface,shader,surface_dirty,shader_dirty,symbol_scope,global_scope,logger,context,vao,uniforms,textures,depth_sorting,culling,draw_mode,culled,visible_instances,stats}
         })
     }
 
@@ -224,23 +272,46 @@ impl Symbol {
             }
             if self.shader_dirty.check() {
                 let var_bindings = self.discover_variable_bindings();
-                self.shader.update(&var_bindings);
-                self.init_variable_bindings(&var_bindings);
-                self.shader_dirty.unset();
+                let globals      = self.global_scope.block();
+                self.shader.update(&var_bindings,globals.as_ref());
+                if !self.shader.is_compiling() {
+                    if self.shader.program().is_some() {
+                        self.init_variable_bindings(&var_bindings);
+                    }
+                    self.shader_dirty.unset();
+                }
             }
         })
     }
 
+    /// Checks whether the program of this symbol is still being compiled. Such symbols are not
+    /// drawn and they need to be updated again in the next frames.
+    pub fn is_compiling(&self) -> bool {
+        self.shader.is_compiling()
+    }
+
+    /// Re-creates all GPU resources after the context was lost and restored. Buffers are uploaded,
+    /// and the program and the VAO are re-created during the next update.
+    pub fn restore_context(&mut self) {
+        group!(self.logger, "Restoring the context.", {
+            self.surface.restore_context();
+            self.shader.restore_context();
+            self.symbol_scope.restore_context();
+            self.surface_dirty.set();
+            self.shader_dirty.set();
+        })
+    }
+
     /// Creates a new VertexArrayObject, discovers all variable bindings from shader to geometry,
     /// and initializes the VAO with the bindings.
     fn init_variable_bindings(&mut self, var_bindings:&[shader::VarBinding]) {
-        let max_texture_units     = self.context.get_parameter(Context::MAX_TEXTURE_IMAGE_UNITS);
-        let max_texture_units     = max_texture_units.unwrap_or_else(|err| {
-            self.logger.error(fmt!("Cannot retrieve max texture units: {:?}. Assuming minimal \
-                texture units possible",err));
-            JsValue::from_f64(2.0)
-        });
-        let max_texture_units     = max_texture_units.as_f64().unwrap() as u32;
+        let max_texture_units     = Context::MAX_TEXTURE_IMAGE_UNITS;
+        let max_texture_units     = self.context.get_integer_parameter(max_texture_units);
+        let max_texture_units     = max_texture_units.unwrap_or_else(|| {
+            self.logger.error("Cannot retrieve max texture units. Assuming minimal texture units \
+                possible");
+            2
+        }) as u32;
         let last_texture_unit     = Context::TEXTURE0 + max_texture_units - 1;
         let mut texture_unit_iter = Context::TEXTURE0..=last_texture_unit;
         self.vao                  = Some(VertexArrayObject::new(&self.context));
@@ -257,13 +328,21 @@ impl Symbol {
                         {}
                 }
             }
+            if let Some(globals) = this.global_scope.block() {
+                globals.bind_program(&program.gl_program());
+            }
         });
     }
 
-    fn init_attribute_binding(&mut self, program:&WebGlProgram, binding:&shader::VarBinding, mesh_scope_type:mesh::ScopeType) {
+    fn init_attribute_binding
+    ( &mut self
+    , program         : &CachedProgram<C>
+    , binding         : &shader::VarBinding
+    , mesh_scope_type : mesh::ScopeType
+    ) {
         let vtx_name = shader::builder::mk_vertex_name(&binding.name);
         let scope    = self.surface.scope_by_type(mesh_scope_type);
-        let location = self.context.get_attrib_location(program, &vtx_name);
+        let location = program.attribute_location(&vtx_name);
         if location < 0 {
             self.logger.error(|| format!("Attribute '{}' not found.",vtx_name));
         } else {
@@ -276,18 +355,33 @@ impl Symbol {
     }
 
     /// Init uniform binding. This function should be run in context of `program` (used inside
-    /// closure passed as argument to `with_program`).
+    /// closure passed as argument to `with_program`). Struct uniforms do not have locations, so
+    /// each of their fields is bound separately. Uniforms are taken from the symbol scope or the
+    /// global one, depending on the scope of the binding.
     fn init_uniform_binding
     ( &mut self
-    , program:&WebGlProgram
+    , program:&CachedProgram<C>
     , binding:&shader::VarBinding
     , texture_unit_iter : &mut dyn Iterator<Item=TextureUnit>
     ) {
         let name         = &binding.name;
         let uni_name     = shader::builder::mk_uniform_name(name);
-        let opt_location = self.context.get_uniform_location(program,&uni_name);
+        let scope        = match binding.scope {
+            Some(ScopeType::Symbol) => self.symbol_scope.clone_ref(),
+            _                       => self.global_scope.clone_ref(),
+        };
+        if let Some(AnyUniform::Struct(uniform)) = scope.get(name) {
+            for (field,uniform) in uniform.fields() {
+                let field_name = format!("{}.{}",uni_name,field);
+                if let Some(location) = program.uniform_location(&field_name) {
+                    self.uniforms.push(UniformBinding::new(field_name,location,uniform));
+                }
+            }
+            return
+        }
+        let opt_location = program.uniform_location(&uni_name);
         opt_location.map(|location|{
-            let uniform = self.global_scope.get(name).unwrap_or_else(||{
+            let uniform = scope.get(name).unwrap_or_else(||{
                 panic!("Internal error. Variable {} not found in program.",name)
             });
             match uniform {
@@ -305,51 +399,67 @@ impl Symbol {
                         }
                     }
                 }
+                AnyUniform::Struct(_) => {}
             }
         });
     }
 
-    pub fn lookup_variable<S:Str>(&self, name:S) -> Option<ScopeType> {
-        let name = name.as_ref();
-        self.surface.lookup_variable(name).map(ScopeType::Mesh).or_else(|| {
-            if      self.symbol_scope.contains(name) { Some(ScopeType::Symbol) }
-            else if self.global_scope.contains(name) { Some(ScopeType::Global) }
-            else                                     { None }
-        })
+    /// Collects all the scopes defining a variable of the given name, in the lookup order.
+    pub fn variable_candidates(&self, name:&str) -> Vec<Candidate> {
+        let mesh_candidates = mesh::ScopeType::ALL.iter().filter_map(|&scope_type| {
+            let buffer = self.surface.scope_by_type(scope_type).buffer(name);
+            buffer.map(|buffer| {
+                let typ = InputType::Value(buffer.glsl_type());
+                Candidate::new(ScopeType::Mesh(scope_type),typ)
+            })
+        }).collect_vec();
+        let symbol_scope       = (ScopeType::Symbol,&self.symbol_scope);
+        let global_scope       = (ScopeType::Global,&self.global_scope);
+        let uniform_scopes     = vec![symbol_scope,global_scope];
+        let uniform_candidates = uniform_scopes.into_iter().filter_map(|(scope,uniforms)| {
+            uniforms.get(name).map(|uniform| Candidate::new(scope,(&uniform).into()))
+        });
+        mesh_candidates.into_iter().chain(uniform_candidates).collect()
     }
 
-    /// For each variable from the shader definition, looks up its position in geometry scopes.
+    /// For each variable from the shader definition, looks up its position in geometry scopes and
+    /// validates its type. Variables which are missing or have mismatched types are not bound, so
+    /// their default values are used. Variables which have no default values are reported as
+    /// errors then.
     pub fn discover_variable_bindings(&self) -> Vec<shader::VarBinding> {
         let var_decls = self.shader.collect_variables();
         var_decls.into_iter().map(|(var_name,var_decl)| {
-            let target = self.lookup_variable(&var_name);
-            if target.is_none() {
-                let msg = || format!("Unable to bind variable '{}' to geometry buffer.", var_name);
-                self.logger.warning(msg);
+            let candidates = self.variable_candidates(&var_name);
+            let report     = validation::validate(&var_name,&var_decl,&candidates);
+            for error in &report.errors {
+                match error {
+                    validation::Error::Missing {..} => self.logger.warning(|| error.to_string()),
+                    _                               => self.logger.error(|| error.to_string()),
+                }
             }
-            shader::VarBinding::new(var_name,var_decl,target)
+            shader::VarBinding::new(var_name,var_decl,report.scope)
         }).collect()
     }
 
     /// Runs the provided function in a context of active program and active VAO. After the function
     /// is executed, both program and VAO are bound to None.
-    pub fn with_program<F:FnOnce(&WebGlProgram) -> T,T>(&self, f:F) -> T {
-        let program = self.shader.program().as_ref().unwrap(); // FIXME
-        self.context.use_program(Some(&program));
+    pub fn with_program<F:FnOnce(&C::Program) -> T,T>(&self, f:F) -> T {
+        let program = self.shader.program().unwrap().gl_program(); // FIXME
+        GpuContext::use_program(&self.context,Some(&program));
         let vao = self.vao.as_ref().unwrap(); // FIXME
-        let out = vao.with(||{ f(program) });
-        self.context.use_program(None);
+        let out = vao.with(||{ f(&program) });
+        GpuContext::use_program(&self.context,None);
         out
     }
 
     /// Runs the provided function in a context of active program and active VAO. After the function
     /// is executed, both program and VAO are bound to None.
-    pub fn with_program_mut<F:FnOnce(&mut Self, &WebGlProgram) -> T,T>(&mut self, f:F) -> T {
+    pub fn with_program_mut<F:FnOnce(&mut Self, &CachedProgram<C>) -> T,T>(&mut self, f:F) -> T {
         let this:&mut Self = self;
-        let program = this.shader.program().as_ref().unwrap().clone(); // FIXME
-        this.context.use_program(Some(&program));
+        let program = this.shader.program().unwrap().clone_ref(); // FIXME
+        GpuContext::use_program(&this.context,Some(&program.gl_program()));
         let out = this.with_vao_mut(|this|{ f(this,&program) });
-        self.context.use_program(None);
+        GpuContext::use_program(&self.context,None);
         out
     }
     
@@ -360,30 +470,261 @@ impl Symbol {
         out
     }
 
+    /// Checks whether the instances are sorted by depth before rendering.
+    pub fn depth_sorting(&self) -> bool {
+        self.depth_sorting
+    }
+
+    /// Enables or disables sorting of instances by depth. Instances are drawn in the order they are
+    /// stored in buffers, so transparent instances need to be drawn back to front in order to be
+    /// blended correctly. Sorting requires the instance scope to contain the `transform` buffer.
+    pub fn set_depth_sorting(&mut self, enabled:bool) {
+        self.depth_sorting = enabled;
+    }
+
+    /// The culling strategy of this symbol.
+    pub fn culling(&self) -> CullingMode {
+        self.culling
+    }
+
+    /// Sets the culling strategy of this symbol. Culling requires the instance scope to contain
+    /// the `transform` and `bounds` buffers, describing sprites.
+    pub fn set_culling(&mut self, culling:CullingMode) {
+        self.culling           = culling;
+        self.culled            = false;
+        self.visible_instances = None;
+    }
+
+    /// Sets the primitives the points of this symbol are drawn as.
+    pub fn set_draw_mode(&mut self, draw_mode:DrawMode) {
+        self.draw_mode = draw_mode;
+    }
+
+    /// The selected variant of the shader of this symbol.
+    pub fn variant(&self) -> &Variant {
+        self.shader.variant()
+    }
+
+    /// Selects the variant of the shader of this symbol. Programs of the variants are compiled
+    /// when they are selected for the first time, so switching between them later is cheap.
+    pub fn set_variant(&mut self, variant:Variant) {
+        self.shader.set_variant(variant);
+    }
+
+    /// Checks whether the symbol was culled in the last `prepare_instances` call.
+    pub fn is_culled(&self) -> bool {
+        self.culled
+    }
+
+    /// Culls and sorts the instances according to the culling mode and depth sorting settings.
+    /// It should be called every frame after the camera was updated, but before the symbol is
+    /// updated. The buffers are not touched if the visible instances are already in place, so
+    /// nothing is uploaded in frames which do not change them. Returns the number of culled
+    /// instances. Culled symbols are counted in the stats here rather than in `render`, so they are
+    /// counted once per frame regardless of the number of passes drawing them.
+    pub fn prepare_instances(&mut self, view_projection:&Matrix4<f32>) -> usize {
+        if self.culling == CullingMode::Disabled && !self.depth_sorting {
+            return 0
+        }
+        let scope     = &mut self.surface.scopes.instance;
+        let transform = Self::scope_buffer::<Matrix4<f32>>(scope,"transform");
+        let bounds    = Self::scope_buffer::<Vector2<f32>>(scope,"bounds");
+        let transform = match transform {
+            Some(transform) => transform,
+            None => {
+                self.logger.warning("Cannot prepare instances without the 'transform' buffer.");
+                return 0
+            }
+        };
+        let frustum      = Frustum::new(*view_projection);
+        let origin       = Vector4::new(0.0,0.0,0.0,1.0);
+        let culling      = self.culling != CullingMode::Disabled;
+        let mut visible  = Vec::new();
+        let mut culled   = 0;
+        for (index,id) in scope.instance_ids().into_iter().enumerate() {
+            let matrix    = transform.get(index);
+            let is_inside = match (culling,&bounds) {
+                (true,Some(bounds)) => {
+                    let bbox = BoundingBox::from_sprite(&matrix,&bounds.get(index));
+                    frustum.intersects(&bbox)
+                }
+                _ => true
+            };
+            if is_inside {
+                let position = view_projection * matrix * origin;
+                visible.push((id, position.z / position.w));
+            } else {
+                culled += 1;
+            }
+        }
+        if self.depth_sorting {
+            visible.sort_by(|a,b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        }
+        let visible_count = visible.len();
+        let compact       = self.culling == CullingMode::Instances;
+        if self.depth_sorting || compact {
+            let order    = visible.into_iter().map(|(id,_)| id).collect_vec();
+            let in_place = order.iter().enumerate().all(|(index,id)| {
+                scope.index_of(*id) == Some(index)
+            });
+            if !in_place {
+                scope.reorder(&order);
+            }
+        }
+        self.culled            = culling && visible_count == 0;
+        self.visible_instances = if compact { Some(visible_count) } else { None };
+        if self.culled {
+            self.stats.inc_culled_symbol_count();
+        }
+        culled
+    }
+
     pub fn render(&self) {
+        if self.culled {
+            return
+        }
+        if self.shader.program().is_none() || self.vao.is_none() {
+            return
+        }
         group!(self.logger, "Rendering.", {
             self.with_program(|_|{
                 for binding in &self.uniforms {
                     binding.upload(&self.context);
                 }
-                let bind_texture_unit  = |b:&TextureBinding| b.bind_texture_unit(&self.context);
+                let bind_texture_unit  = |b:&TextureBinding<C>| b.bind_texture_unit(&self.context);
                 let _tex_unit_bindings = self.textures.iter().map(bind_texture_unit).collect_vec();
 
-                let mode           = Context::TRIANGLE_STRIP;
+                let mode           = self.draw_mode.into_gl_enum().into();
                 let first          = 0;
                 let count          = self.surface.point.size()    as i32;
-                let instance_count = self.surface.instance.size() as i32;
+                let instance_count = self.visible_instances.unwrap_or_else(|| {
+                    self.surface.instance.size()
+                }) as i32;
 
                 self.stats.inc_draw_call_count();
-                self.context.draw_arrays_instanced(mode,first,count,instance_count);
+                let context = &self.context;
+                GpuContext::draw_arrays_instanced(context,mode,first,count,instance_count);
             });
 
         })
     }
 }
 
-impl Drop for Symbol {
+
+// === Private API ===
+
+impl<C:GpuContext> Symbol<C> {
+    fn scope_buffer<T:BufferItem>(scope:&AttributeScope<C>, name:&str) -> Option<Buffer<T,C>>
+    where for<'t> &'t Buffer<T,C> : TryFrom<&'t AnyBuffer<C>> {
+        let buffer:&Buffer<T,C> = scope.buffer(name)?.try_into().ok()?;
+        Some(buffer.clone_ref())
+    }
+}
+
+impl<C:GpuContext> Drop for Symbol<C> {
     fn drop(&mut self) {
         self.stats.dec_symbol_count();
     }
 }
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::system::gpu::context::mock::Call;
+    use crate::system::gpu::context::mock::MockContext;
+
+    fn new_symbol(context:&MockContext) -> Symbol<MockContext> {
+        let stats    = Stats::default();
+        let logger   = Logger::new("test");
+        let globals  = UniformScope::new(logger.sub("globals"),context);
+        let programs = ProgramCache::new(logger.sub("programs"),&stats,context);
+        let sources  = ShaderSources::new();
+        Symbol::new(&globals,logger,&stats,context,&programs,&sources,||{})
+    }
+
+    #[test]
+    fn vertex_array_object_is_bound_for_the_scope_and_deleted_on_drop() {
+        let context = MockContext::new();
+        let vao     = VertexArrayObject::new(&context);
+        let value   = vao.with(|| 7);
+        drop(vao);
+        assert_eq!(value, 7);
+        let calls = context.calls();
+        let vao   = match calls[0] {
+            Call::CreateVertexArray {vao} => vao,
+            _                             => panic!("VAO was not created first."),
+        };
+        assert_eq!(calls[1..].to_vec(), vec!
+            [ Call::BindVertexArray   {vao:Some(vao)}
+            , Call::BindVertexArray   {vao:None}
+            , Call::DeleteVertexArray {vao:Some(vao)}
+            ]);
+    }
+
+    #[test]
+    fn changing_one_sprite_uploads_only_its_transform() {
+        let context    = MockContext::new();
+        let mut symbol = new_symbol(&context);
+        let scope      = &mut symbol.surface.scopes.instance;
+        let transform  = scope.add_buffer::<_,Matrix4<f32>>("transform");
+        let bounds     = scope.add_buffer::<_,Vector2<f32>>("bounds");
+        let ids        = (0..10).map(|_| scope.add_instance()).collect_vec();
+        symbol.update();
+        assert_eq!(context.buffer_uploads().len(), 2);
+
+        context.clear();
+        transform.at(ids[4]).set(Matrix4::new_scaling(2.0));
+        symbol.update();
+        let target          = Context::ARRAY_BUFFER;
+        let dst_byte_offset = 4 * 64;
+        let src_offset      = 4 * 16;
+        let length          = 16;
+        assert_eq!(context.buffer_uploads(), vec!
+            [Call::BufferSubData {target,dst_byte_offset,src_offset,length}]);
+
+        context.clear();
+        symbol.update();
+        assert!(context.buffer_uploads().is_empty());
+        assert_eq!(bounds.len(), 10);
+    }
+
+    #[test]
+    fn unchanged_visible_instances_are_not_uploaded_again() {
+        let context     = MockContext::new();
+        let mut symbol  = new_symbol(&context);
+        let scope       = &mut symbol.surface.scopes.instance;
+        let transform   = scope.add_buffer::<_,Matrix4<f32>>("transform");
+        let bounds      = scope.add_buffer::<_,Vector2<f32>>("bounds");
+        let ids         = (0..4).map(|_| scope.add_instance()).collect_vec();
+        let place       = |id,x:f32| {
+            transform.at(id).set(Matrix4::new_translation(&Vector3::new(x,0.0,0.0)))
+        };
+        let camera      = Matrix4::identity();
+        for (id,x) in ids.iter().zip(&[5.0,0.0,5.0,0.5]) {
+            place(*id,*x);
+            bounds.at(*id).set(Vector2::new(0.5,0.5));
+        }
+        symbol.set_culling(CullingMode::Instances);
+        assert_eq!(symbol.prepare_instances(&camera),2);
+        assert_eq!(symbol.visible_instances,Some(2));
+        symbol.update();
+
+        context.clear();
+        assert_eq!(symbol.prepare_instances(&camera),2);
+        symbol.update();
+        assert!(context.buffer_uploads().is_empty());
+
+        place(ids[0],0.0);
+        assert_eq!(symbol.prepare_instances(&camera),1);
+        assert_eq!(symbol.visible_instances,Some(3));
+        symbol.update();
+        assert!(!context.buffer_uploads().is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+//! This module defines utilities for culling, skipping the geometry which lies outside of the
+//! camera view. Symbols can be culled as a whole, or their visible instances can be compacted, so
+//! the invisible ones are not rasterized at all.
+
+use crate::prelude::*;
+
+use nalgebra::Matrix4;
+use nalgebra::Vector2;
+use nalgebra::Vector3;
+use nalgebra::Vector4;
+
+
+
+// ===================
+// === CullingMode ===
+// ===================
+
+/// Culling strategy of a symbol.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum CullingMode {
+    /// The symbol is always drawn.
+    Disabled,
+    /// The symbol is skipped if none of its instances is visible.
+    Symbol,
+    /// Visible instances are moved to the beginning of the buffers and only they are drawn. The
+    /// symbol is skipped if none of its instances is visible.
+    Instances,
+}
+
+impl Default for CullingMode {
+    fn default() -> Self {
+        Self::Disabled
+    }
+}
+
+
+
+// ===================
+// === BoundingBox ===
+// ===================
+
+/// Axis-aligned bounding box.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct BoundingBox {
+    /// The corner with the lowest coordinates.
+    pub min : Vector3<f32>,
+    /// The corner with the highest coordinates.
+    pub max : Vector3<f32>,
+}
+
+impl BoundingBox {
+    /// Constructor.
+    pub fn new(min:Vector3<f32>, max:Vector3<f32>) -> Self {
+        Self {min,max}
+    }
+
+    /// The smallest box containing all the points. Returns `None` if there are no points.
+    pub fn from_points<I:IntoIterator<Item=Vector3<f32>>>(points:I) -> Option<Self> {
+        let mut points = points.into_iter();
+        points.next().map(|first| {
+            let init = Self::new(first,first);
+            points.fold(init,|bbox,point| bbox.extended(&point))
+        })
+    }
+
+    /// Bounding box of a sprite with the provided transformation and dimensions. Sprites are
+    /// rectangles centered at their origins, lying on the local XY plane.
+    pub fn from_sprite(transform:&Matrix4<f32>, size:&Vector2<f32>) -> Self {
+        let half    = size / 2.0;
+        let corners = [(-1.0,-1.0),(-1.0,1.0),(1.0,-1.0),(1.0,1.0)];
+        let points  = corners.iter().map(|(x,y)| {
+            let local = Vector4::new(x * half.x, y * half.y, 0.0, 1.0);
+            (transform * local).xyz()
+        });
+        Self::from_points(points).unwrap()
+    }
+
+    /// The smallest box containing this box and the point.
+    pub fn extended(&self, point:&Vector3<f32>) -> Self {
+        let min = self.min.zip_map(point,f32::min);
+        let max = self.max.zip_map(point,f32::max);
+        Self {min,max}
+    }
+
+    /// All eight corners of the box.
+    pub fn corners(&self) -> [Vector3<f32>;8] {
+        let (a,b) = (&self.min,&self.max);
+        [ Vector3::new(a.x,a.y,a.z), Vector3::new(b.x,a.y,a.z)
+        , Vector3::new(a.x,b.y,a.z), Vector3::new(b.x,b.y,a.z)
+        , Vector3::new(a.x,a.y,b.z), Vector3::new(b.x,a.y,b.z)
+        , Vector3::new(a.x,b.y,b.z), Vector3::new(b.x,b.y,b.z) ]
+    }
+}
+
+
+
+// ===============
+// === Frustum ===
+// ===============
+
+/// The volume visible by the camera. It is defined by the view-projection matrix of the camera,
+/// as the region which is mapped to the clip space cube.
+#[derive(Clone,Copy,Debug)]
+pub struct Frustum {
+    view_projection : Matrix4<f32>,
+}
+
+impl Frustum {
+    /// Constructor.
+    pub fn new(view_projection:Matrix4<f32>) -> Self {
+        Self {view_projection}
+    }
+
+    /// Checks whether the box may be visible. The test is conservative: it returns `false` only if
+    /// all corners of the box lie outside of the same clip plane. The test is performed in the
+    /// homogeneous coordinates, so it handles corners behind the camera correctly.
+    pub fn intersects(&self, bbox:&BoundingBox) -> bool {
+        let corners = bbox.corners();
+        let clip    = corners.iter().map(|p| self.view_projection * Vector4::new(p.x,p.y,p.z,1.0));
+        let clip    = clip.collect_vec();
+        let outside = |f:&dyn Fn(&Vector4<f32>) -> bool| clip.iter().all(f);
+        !( outside(&|p| p.x < -p.w) || outside(&|p| p.x > p.w)
+        || outside(&|p| p.y < -p.w) || outside(&|p| p.y > p.w)
+        || outside(&|p| p.z < -p.w) || outside(&|p| p.z > p.w) )
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box(center:Vector3<f32>) -> BoundingBox {
+        let half = Vector3::new(0.5,0.5,0.5);
+        BoundingBox::new(center - half, center + half)
+    }
+
+    #[test]
+    fn frustum_intersection() {
+        let frustum = Frustum::new(Matrix4::identity());
+        assert!( frustum.intersects(&unit_box(Vector3::new(0.0,0.0,0.0))));
+        assert!( frustum.intersects(&unit_box(Vector3::new(1.2,0.0,0.0))));
+        assert!(!frustum.intersects(&unit_box(Vector3::new(2.0,0.0,0.0))));
+        assert!(!frustum.intersects(&unit_box(Vector3::new(0.0,-3.0,0.0))));
+        assert!(!frustum.intersects(&unit_box(Vector3::new(0.0,0.0,5.0))));
+    }
+
+    #[test]
+    fn frustum_with_translation() {
+        let view_projection = Matrix4::new_translation(&Vector3::new(-10.0,0.0,0.0));
+        let frustum         = Frustum::new(view_projection);
+        assert!(!frustum.intersects(&unit_box(Vector3::new(0.0,0.0,0.0))));
+        assert!( frustum.intersects(&unit_box(Vector3::new(10.0,0.0,0.0))));
+    }
+
+    #[test]
+    fn sprite_bounds() {
+        let transform = Matrix4::new_translation(&Vector3::new(5.0,1.0,0.0));
+        let bbox      = BoundingBox::from_sprite(&transform,&Vector2::new(2.0,4.0));
+        assert_eq!(bbox.min,Vector3::new(4.0,-1.0,0.0));
+        assert_eq!(bbox.max,Vector3::new(6.0, 3.0,0.0));
+    }
+
+    #[test]
+    fn bounding_box_from_points() {
+        assert_eq!(BoundingBox::from_points(vec![]),None);
+        let points = vec![Vector3::new(1.0,2.0,3.0),Vector3::new(-1.0,5.0,0.0)];
+        let bbox   = BoundingBox::from_points(points).unwrap();
+        assert_eq!(bbox.min,Vector3::new(-1.0,2.0,0.0));
+        assert_eq!(bbox.max,Vector3::new( 1.0,5.0,3.0));
+    }
+}
@@ -0,0 +1,30 @@
+//! `DrawMode` specifies the primitives the points of a symbol are drawn as.
+
+use crate::prelude::*;
+
+use crate::system::gpu::shader::Context;
+use crate::system::gpu::data::gl_enum::GlEnum;
+
+
+
+// ================
+// === DrawMode ===
+// ================
+
+crate::define_singleton_enum_gl! {
+    /// Specifies the primitives the points of a symbol are drawn as.
+    DrawMode {
+        /// Every point forms a triangle with the two preceding points. Four points form a quad,
+        /// which is used by sprites.
+        TriangleStrip = Context::TRIANGLE_STRIP,
+
+        /// Every three points form a separate triangle. Used by tessellated geometry.
+        Triangles = Context::TRIANGLES,
+    }
+}
+
+impl Default for DrawMode {
+    fn default() -> Self {
+        DrawMode::TriangleStrip
+    }
+}
@@ -1,6 +1,10 @@
 //! Root module for compound geometries. Compound geometries are defined by using primitive
 //! geometries and behave like smart constructors for commonly used shapes.
 
+pub mod edge;
+pub mod image;
+pub mod line;
+pub mod particle;
 pub mod sprite;
 
 
@@ -12,5 +16,9 @@ pub mod sprite;
 /// Common types.
 pub mod types {
     use super::*;
+    pub use edge::*;
+    pub use image::*;
+    pub use line::*;
+    pub use particle::*;
     pub use sprite::*;
 }
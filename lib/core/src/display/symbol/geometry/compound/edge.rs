@@ -0,0 +1,517 @@
+//! This module defines edges, curved connections between two display objects, like the
+//! connections between the nodes of a graph editor. Edges follow their ends automatically. All
+//! edges of an `EdgeSystem` are drawn by a single `LineSystem`, so even thousands of them are drawn
+//! with one draw call.
+
+#[warn(missing_docs)]
+pub mod curve;
+
+use crate::prelude::*;
+
+use crate::control::callback::CallbackHandle;
+use crate::display::object::*;
+use crate::display::symbol::geometry::compound::line::Line;
+use crate::display::symbol::geometry::compound::line::LineSystem;
+use crate::display::symbol::geometry::compound::line::tessellation::Cap;
+use crate::display::symbol::geometry::compound::line::tessellation::Join;
+use crate::display::symbol::geometry::compound::line::tessellation::Point;
+use crate::display::symbol::geometry::compound::line::tessellation::Style;
+use crate::display::symbol::layer::MissingLayer;
+use crate::display::symbol::material::Material;
+use crate::display::world::*;
+
+use curve::Arrow;
+use curve::Curve;
+use data::opt_vec::OptVec;
+use nalgebra::Vector2;
+use nalgebra::Vector3;
+use nalgebra::Vector4;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// The default distance from the stroke of an edge within which the edge is hovered.
+pub const DEFAULT_HOVER_DISTANCE : f32 = 4.0;
+
+
+
+// =================
+// === EdgeStyle ===
+// =================
+
+/// The look of an edge.
+#[derive(Clone,Debug,PartialEq)]
+pub struct EdgeStyle {
+    /// The width of the stroke.
+    pub width       : f32,
+    /// The color of the stroke.
+    pub color       : Vector4<f32>,
+    /// The color of the stroke while the edge is hovered.
+    pub hover_color : Vector4<f32>,
+    /// How far the curve reaches out of its ends, see `Curve::horizontal`.
+    pub curvature   : f32,
+    /// The arrowhead at the target. Edges without it end bare.
+    pub arrow       : Option<Arrow>,
+}
+
+impl Default for EdgeStyle {
+    fn default() -> Self {
+        let width       = 2.0;
+        let color       = Vector4::new(0.6,0.6,0.6,1.0);
+        let hover_color = Vector4::new(1.0,1.0,1.0,1.0);
+        let curvature   = 0.5;
+        let arrow       = default();
+        Self {width,color,hover_color,curvature,arrow}
+    }
+}
+
+
+
+// ================
+// === EdgeLine ===
+// ================
+
+/// A line drawing a part of an edge. It is implemented by `Line`, and abstracted away only to let
+/// the edges be tested without the `LineSystem`.
+trait EdgeLine : Debug {
+    fn set_points(&self, points:Vec<Point>);
+    fn set_style(&self, style:Style);
+}
+
+impl EdgeLine for Line {
+    fn set_points(&self, points:Vec<Point>) {
+        Line::set_points(self,points)
+    }
+
+    fn set_style(&self, style:Style) {
+        Line::set_style(self,style)
+    }
+}
+
+
+
+// ================
+// === EdgeData ===
+// ================
+
+/// Index of an edge in the `EdgeSystem`.
+pub type EdgeId = usize;
+
+/// An edge registered in the `EdgeRegistry`, together with the lines drawing it. The edge keeps
+/// weak references to its ends, so it does not keep them alive. Edges of dropped ends are hidden.
+#[derive(Debug)]
+struct EdgeData<L:EdgeLine=Line> {
+    id     : EdgeId,
+    source : WeakDisplayObjectData,
+    target : WeakDisplayObjectData,
+    style  : EdgeStyle,
+    ends   : Option<(Vector3<f32>,Vector3<f32>)>,
+    points : Vec<Vector2<f32>>,
+    dirty  : bool,
+    line   : L,
+    arrow  : L,
+}
+
+impl<L:EdgeLine> EdgeData<L> {
+    fn new
+    (id:EdgeId, source:&DisplayObjectData, target:&DisplayObjectData, line:L, arrow:L) -> Self {
+        let source = source.downgrade();
+        let target = target.downgrade();
+        let style  = default();
+        let ends   = default();
+        let points = default();
+        let dirty  = true;
+        Self {id,source,target,style,ends,points,dirty,line,arrow}
+    }
+
+    /// The global positions of both ends, if both of them are still alive.
+    fn end_positions(&self) -> Option<(Vector3<f32>,Vector3<f32>)> {
+        let source = self.source.upgrade()?;
+        let target = self.target.upgrade()?;
+        Some((source.global_position(),target.global_position()))
+    }
+
+    /// Samples the curve again and updates the lines if any of the ends moved, or if the edge
+    /// changed. Other edges are left untouched, so their lines are not tessellated again.
+    fn update(&mut self, hovered:bool) {
+        let ends = self.end_positions();
+        if !self.dirty && self.ends == ends { return }
+        self.dirty = false;
+        self.ends  = ends;
+        match ends {
+            None       => self.hide(),
+            Some(ends) => self.draw(ends,hovered),
+        }
+    }
+
+    fn hide(&mut self) {
+        self.points = default();
+        self.line.set_points(default());
+        self.arrow.set_points(default());
+    }
+
+    fn draw(&mut self, ends:(Vector3<f32>,Vector3<f32>), hovered:bool) {
+        let source   = Vector2::new(ends.0.x,ends.0.y);
+        let target   = Vector2::new(ends.1.x,ends.1.y);
+        let curve    = Curve::horizontal(source,target,self.style.curvature);
+        let color    = if hovered { self.style.hover_color } else { self.style.color };
+        let width    = self.style.width;
+        let to_line  = |points:&[Vector2<f32>]| -> Vec<Point> {
+            points.iter().map(|p| Point::new(*p,color)).collect()
+        };
+        self.points  = curve.sample();
+        self.line.set_points(to_line(&self.points));
+        self.line.set_style(Style {width,join:Join::Round,cap:Cap::Round,..default()});
+        let arrow = self.style.arrow.map(|arrow| arrow.points(target,curve.end_direction()));
+        self.arrow.set_points(arrow.map(|points| to_line(&points)).unwrap_or_default());
+        self.arrow.set_style(Style {width,join:Join::Miter,cap:Cap::Round,..default()});
+    }
+
+    /// The distance from the point to the stroke of the edge.
+    fn distance(&self, position:&Vector2<f32>) -> f32 {
+        let distance = curve::distance_to_polyline(&self.points,position);
+        distance - self.style.width / 2.0
+    }
+}
+
+
+
+// ====================
+// === EdgeRegistry ===
+// ====================
+
+/// All edges of an `EdgeSystem` and the hovered one.
+#[derive(Debug)]
+struct EdgeRegistry<L:EdgeLine=Line> {
+    edges          : OptVec<EdgeData<L>>,
+    hovered        : Option<EdgeId>,
+    hover_distance : f32,
+}
+
+impl<L:EdgeLine> Default for EdgeRegistry<L> {
+    fn default() -> Self {
+        let edges          = default();
+        let hovered        = default();
+        let hover_distance = DEFAULT_HOVER_DISTANCE;
+        Self {edges,hovered,hover_distance}
+    }
+}
+
+impl<L:EdgeLine> EdgeRegistry<L> {
+    fn update(&mut self) {
+        let hovered = self.hovered;
+        for edge in &mut self.edges {
+            edge.update(hovered == Some(edge.id));
+        }
+    }
+
+    /// The edge closest to the position, if its stroke is within the `hover_distance`.
+    fn edge_at(&self, position:&Vector2<f32>) -> Option<EdgeId> {
+        let distances = self.edges.iter().map(|edge| (edge.id,edge.distance(position)));
+        let hovered   = distances.filter(|(_,distance)| *distance <= self.hover_distance);
+        hovered.fold(None,|closest:Option<(EdgeId,f32)>,(id,distance)| match closest {
+            Some((_,min)) if min <= distance => closest,
+            _                                => Some((id,distance)),
+        }).map(|(id,_)| id)
+    }
+
+    fn set_hovered(&mut self, hovered:Option<EdgeId>) {
+        if hovered != self.hovered {
+            let changed = self.hovered.iter().chain(hovered.iter()).cloned().collect_vec();
+            for id in changed {
+                self.edges[id].dirty = true;
+            }
+            self.hovered = hovered;
+        }
+    }
+}
+
+type Edges = Rc<RefCell<EdgeRegistry>>;
+
+
+
+// ============
+// === Edge ===
+// ============
+
+/// A connection between two display objects drawn by the `EdgeSystem`. The edge leaves the
+/// `source` and enters the `target`, and it follows them whenever they move. The edge does not
+/// keep its ends alive, and it is hidden when any of them is dropped. The edge is removed when the
+/// handle is dropped.
+#[derive(Debug)]
+pub struct Edge {
+    id    : EdgeId,
+    edges : Edges,
+}
+
+impl Edge {
+    /// The id of the edge, as returned by `EdgeSystem::hover`.
+    pub fn id(&self) -> EdgeId {
+        self.id
+    }
+
+    /// Sets the style of the edge.
+    pub fn set_style(&self, style:EdgeStyle) {
+        self.modify(|edge| edge.style = style);
+    }
+
+    /// Modifies the style of the edge.
+    pub fn mod_style<F:FnOnce(&mut EdgeStyle)>(&self, f:F) {
+        self.modify(|edge| f(&mut edge.style));
+    }
+
+    /// Attaches the start of the edge to another display object.
+    pub fn set_source<T:DisplayObject>(&self, source:T) {
+        self.modify(|edge| edge.source = source.into().downgrade());
+    }
+
+    /// Attaches the end of the edge to another display object.
+    pub fn set_target<T:DisplayObject>(&self, target:T) {
+        self.modify(|edge| edge.target = target.into().downgrade());
+    }
+
+    fn modify<F:FnOnce(&mut EdgeData)>(&self, f:F) {
+        let mut edges = self.edges.borrow_mut();
+        let edge      = &mut edges.edges[self.id];
+        edge.dirty    = true;
+        f(edge)
+    }
+}
+
+impl Drop for Edge {
+    fn drop(&mut self) {
+        let mut edges = self.edges.borrow_mut();
+        if edges.hovered == Some(self.id) {
+            edges.hovered = None;
+        }
+        edges.edges.remove(self.id);
+    }
+}
+
+
+
+// ==================
+// === EdgeSystem ===
+// ==================
+
+/// Creates a set of edges. All edges in the edge system are drawn with a single draw call. Only the
+/// edges whose ends moved, or which were changed, are sampled and tessellated again.
+///
+/// The edge system does not listen to the mouse, as the engine can not convert the screen
+/// coordinates to the scene ones yet. Instead, call `hover` with the scene position of the mouse
+/// to highlight the edge under it.
+#[derive(Debug)]
+pub struct EdgeSystem {
+    edges     : Edges,
+    lines     : LineSystem,
+    _on_frame : CallbackHandle,
+}
+
+impl EdgeSystem {
+    /// Constructor.
+    pub fn new(world:&World) -> Self {
+        let edges     = Edges::default();
+        let edges_ref = edges.clone();
+        // Registered before the line system, so the lines are tessellated in the same frame.
+        let _on_frame = world.on_frame(move |_| edges_ref.borrow_mut().update());
+        let lines     = LineSystem::new(world);
+        Self {edges,lines,_on_frame}
+    }
+
+    /// Creates a new edge from the `source` to the `target` display object.
+    pub fn new_edge<S:DisplayObject,T:DisplayObject>(&self, source:S, target:T) -> Edge {
+        let source = source.into();
+        let target = target.into();
+        let line   = self.lines.new_line();
+        let arrow  = self.lines.new_line();
+        let data   = |id| EdgeData::new(id,&source,&target,line,arrow);
+        let id     = self.edges.borrow_mut().edges.insert_with_ix(data);
+        let edges  = self.edges.clone();
+        Edge {id,edges}
+    }
+
+    /// Highlights the edge under the provided scene position and returns its id. Edges within
+    /// the hover distance of the position are hovered.
+    pub fn hover(&self, position:Vector2<f32>) -> Option<EdgeId> {
+        let mut edges = self.edges.borrow_mut();
+        let hovered   = edges.edge_at(&position);
+        edges.set_hovered(hovered);
+        hovered
+    }
+
+    /// Removes the highlight of the hovered edge.
+    pub fn unhover(&self) {
+        self.edges.borrow_mut().set_hovered(None);
+    }
+
+    /// The id of the hovered edge.
+    pub fn hovered(&self) -> Option<EdgeId> {
+        self.edges.borrow().hovered
+    }
+}
+
+
+// === Setters ===
+
+impl EdgeSystem {
+    /// Sets the maximum distance in scene units between the mouse and the stroke of a hovered
+    /// edge.
+    pub fn set_hover_distance(&self, distance:f32) {
+        self.edges.borrow_mut().hover_distance = distance;
+    }
+
+    /// Sets the material for all edges in this system. See `LineSystem` for the inputs available
+    /// to the material.
+    pub fn set_material<M:Into<Material>>(&self, material:M) {
+        self.lines.set_material(material);
+    }
+
+    /// Moves all edges of this system to the provided layer. The layer has to be registered in
+    /// `SymbolRegistry::layers` first.
+    pub fn set_layer(&self, layer:&str) -> Result<(),MissingLayer> {
+        self.lines.set_layer(layer)
+    }
+
+    /// Sets the z-index of this system within its layer. Systems with higher z-indexes are drawn
+    /// on top of the ones with lower z-indexes.
+    pub fn set_z_index(&self, z_index:i32) {
+        self.lines.set_z_index(z_index);
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Line recording the points it was set to.
+    #[derive(Debug,Default)]
+    struct TestLine {
+        points : RefCell<Vec<Vec<Point>>>,
+    }
+
+    impl EdgeLine for TestLine {
+        fn set_points(&self, points:Vec<Point>) {
+            self.points.borrow_mut().push(points);
+        }
+
+        fn set_style(&self, _style:Style) {}
+    }
+
+    type Registry = EdgeRegistry<TestLine>;
+
+    fn node(x:f32, y:f32) -> DisplayObjectData {
+        let node = DisplayObjectData::new(Logger::new("node"));
+        node.set_position(Vector3::new(x,y,0.0));
+        node.update();
+        node
+    }
+
+    fn add_edge(registry:&mut Registry, source:&DisplayObjectData, target:&DisplayObjectData)
+    -> EdgeId {
+        registry.edges.insert_with_ix(|id| EdgeData::new(id,source,target,default(),default()))
+    }
+
+    fn tessellation_count(registry:&Registry, id:EdgeId) -> usize {
+        registry.edges[id].line.points.borrow().len()
+    }
+
+    fn last_points(registry:&Registry, id:EdgeId) -> Vec<Point> {
+        registry.edges[id].line.points.borrow().last().cloned().unwrap_or_default()
+    }
+
+    fn color(registry:&Registry, id:EdgeId) -> Vector4<f32> {
+        last_points(registry,id)[0].color
+    }
+
+    /// Two horizontal edges, 20 units apart, with strokes 2 units wide.
+    fn two_edges() -> (Registry,Vec<DisplayObjectData>,EdgeId,EdgeId) {
+        let mut registry = Registry::default();
+        let nodes        = vec![node(0.0,0.0),node(100.0,0.0),node(0.0,20.0),node(100.0,20.0)];
+        let a            = add_edge(&mut registry,&nodes[0],&nodes[1]);
+        let b            = add_edge(&mut registry,&nodes[2],&nodes[3]);
+        registry.update();
+        (registry,nodes,a,b)
+    }
+
+    #[test]
+    fn hit_distance() {
+        let (registry,_nodes,a,b) = two_edges();
+        assert_eq!(registry.edge_at(&Vector2::new(50.0,0.0)),Some(a));
+        assert_eq!(registry.edge_at(&Vector2::new(50.0,5.0)),Some(a));
+        assert_eq!(registry.edge_at(&Vector2::new(50.0,5.5)),None);
+        assert_eq!(registry.edge_at(&Vector2::new(50.0,16.0)),Some(b));
+        assert_eq!(registry.edge_at(&Vector2::new(-10.0,0.0)),None);
+    }
+
+    #[test]
+    fn nearest_edge_is_chosen() {
+        let (mut registry,_nodes,a,b) = two_edges();
+        registry.hover_distance = 20.0;
+        assert_eq!(registry.edge_at(&Vector2::new(50.0,8.0)),Some(a));
+        assert_eq!(registry.edge_at(&Vector2::new(50.0,12.0)),Some(b));
+        assert_eq!(registry.edge_at(&Vector2::new(50.0,10.0)),Some(a));
+    }
+
+    #[test]
+    fn hover_transitions() {
+        let (mut registry,_nodes,a,b) = two_edges();
+        let style = EdgeStyle::default();
+        registry.set_hovered(Some(a));
+        registry.update();
+        assert_eq!(registry.hovered,Some(a));
+        assert_eq!(color(&registry,a),style.hover_color);
+        assert_eq!(tessellation_count(&registry,b),1);
+
+        registry.set_hovered(Some(b));
+        registry.update();
+        assert_eq!(color(&registry,a),style.color);
+        assert_eq!(color(&registry,b),style.hover_color);
+        assert_eq!(tessellation_count(&registry,a),3);
+        assert_eq!(tessellation_count(&registry,b),2);
+
+        registry.set_hovered(Some(b));
+        registry.update();
+        assert_eq!(tessellation_count(&registry,b),2);
+
+        registry.set_hovered(None);
+        registry.update();
+        assert_eq!(registry.hovered,None);
+        assert_eq!(color(&registry,b),style.color);
+        assert_eq!(tessellation_count(&registry,a),3);
+    }
+
+    #[test]
+    fn only_edges_of_moved_nodes_are_tessellated_again() {
+        let (mut registry,nodes,a,b) = two_edges();
+        registry.update();
+        assert_eq!(tessellation_count(&registry,a),1);
+        assert_eq!(tessellation_count(&registry,b),1);
+        nodes[0].set_position(Vector3::new(0.0,-10.0,0.0));
+        nodes[0].update();
+        registry.update();
+        assert_eq!(tessellation_count(&registry,a),2);
+        assert_eq!(tessellation_count(&registry,b),1);
+        assert_eq!(last_points(&registry,a)[0].position,Vector2::new(0.0,-10.0));
+    }
+
+    #[test]
+    fn edges_do_not_keep_their_ends_alive() {
+        let (mut registry,mut nodes,a,b) = two_edges();
+        let weak = nodes[3].downgrade();
+        nodes.truncate(3);
+        assert!(weak.upgrade().is_none());
+        registry.update();
+        assert!(last_points(&registry,b).is_empty());
+        assert_eq!(tessellation_count(&registry,a),1);
+        assert_eq!(registry.edge_at(&Vector2::new(50.0,20.0)),None);
+    }
+}
@@ -0,0 +1,220 @@
+//! This module defines the geometry of edges. Edges are cubic Bézier curves which leave their
+//! sources and enter their targets horizontally, like the connections of node editors. Curves are
+//! sampled to polylines, which are drawn by the `LineSystem` and used to find the hovered edges.
+
+use crate::prelude::*;
+
+use nalgebra::Vector2;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// The approximate length of a single segment of a sampled curve.
+pub const SEGMENT_LENGTH : f32 = 8.0;
+
+/// The minimum number of segments of a sampled curve.
+pub const MIN_SEGMENTS : usize = 4;
+
+/// The maximum number of segments of a sampled curve.
+pub const MAX_SEGMENTS : usize = 64;
+
+/// Vectors shorter than this length have no direction.
+pub const EPSILON : f32 = 1e-5;
+
+
+
+// =============
+// === Curve ===
+// =============
+
+/// A cubic Bézier curve from the `source` to the `target` point.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Curve {
+    /// The start of the curve.
+    pub source         : Vector2<f32>,
+    /// The first control point.
+    pub source_control : Vector2<f32>,
+    /// The second control point.
+    pub target_control : Vector2<f32>,
+    /// The end of the curve.
+    pub target         : Vector2<f32>,
+}
+
+impl Curve {
+    /// Creates the curve which leaves the source to the right and enters the target from the
+    /// left. The control points are moved horizontally by the `curvature` multiple of the larger
+    /// of the horizontal and vertical distances between the ends, so a curvature of `0.0` gives a
+    /// straight line.
+    pub fn horizontal(source:Vector2<f32>, target:Vector2<f32>, curvature:f32) -> Self {
+        let distance       = target - source;
+        let reach          = curvature * distance.x.abs().max(distance.y.abs());
+        let reach          = Vector2::new(reach,0.0);
+        let source_control = source + reach;
+        let target_control = target - reach;
+        Self {source,source_control,target_control,target}
+    }
+
+    /// The point of the curve at the parameter `t` in the range `0.0 ..= 1.0`.
+    pub fn point(&self, t:f32) -> Vector2<f32> {
+        let s = 1.0 - t;
+        self.source         * (s * s * s)
+      + self.source_control * (3.0 * s * s * t)
+      + self.target_control * (3.0 * s * t * t)
+      + self.target         * (t * t * t)
+    }
+
+    /// The normalized direction in which the curve enters the target. Curves with overlapping
+    /// control points point from the source to the target, and degenerate curves point to the
+    /// right.
+    pub fn end_direction(&self) -> Vector2<f32> {
+        let candidates = [self.target - self.target_control, self.target - self.source];
+        let direction  = candidates.iter().find(|v| v.norm() > EPSILON);
+        direction.map(|v| v.normalize()).unwrap_or_else(|| Vector2::new(1.0,0.0))
+    }
+
+    /// The length of the control polygon, which is never shorter than the curve.
+    pub fn length_bound(&self) -> f32 {
+        (self.source_control - self.source).norm()
+            + (self.target_control - self.source_control).norm()
+            + (self.target - self.target_control).norm()
+    }
+
+    /// Samples the curve to a polyline. The number of segments grows with the length of the
+    /// curve, within `MIN_SEGMENTS` and `MAX_SEGMENTS`.
+    pub fn sample(&self) -> Vec<Vector2<f32>> {
+        let segments = (self.length_bound() / SEGMENT_LENGTH).ceil() as usize;
+        let segments = segments.max(MIN_SEGMENTS).min(MAX_SEGMENTS);
+        (0..=segments).map(|i| self.point(i as f32 / segments as f32)).collect()
+    }
+}
+
+
+
+// =============
+// === Arrow ===
+// =============
+
+/// The arrowhead at the target of an edge. It is drawn as an open chevron.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Arrow {
+    /// The distance between the tip and the base of the arrowhead.
+    pub length : f32,
+    /// The width of the base of the arrowhead.
+    pub width  : f32,
+}
+
+impl Default for Arrow {
+    fn default() -> Self {
+        let length = 10.0;
+        let width  = 8.0;
+        Self {length,width}
+    }
+}
+
+impl Arrow {
+    /// The points of the chevron pointing in the normalized `direction`: the first wing, the tip
+    /// and the second wing.
+    pub fn points(&self, tip:Vector2<f32>, direction:Vector2<f32>) -> Vec<Vector2<f32>> {
+        let base = tip - direction * self.length;
+        let side = Vector2::new(-direction.y,direction.x) * (self.width / 2.0);
+        vec![base + side, tip, base - side]
+    }
+}
+
+
+
+// ================
+// === Distance ===
+// ================
+
+/// The distance from the point to the closest segment of the polyline. It is infinite for empty
+/// polylines.
+pub fn distance_to_polyline(points:&[Vector2<f32>], point:&Vector2<f32>) -> f32 {
+    match points {
+        []       => std::f32::INFINITY,
+        [single] => (point - single).norm(),
+        _        => points.windows(2).map(|s| distance_to_segment(&s[0],&s[1],point))
+                          .fold(std::f32::INFINITY,f32::min),
+    }
+}
+
+/// The distance from the point to the segment between `a` and `b`.
+fn distance_to_segment(a:&Vector2<f32>, b:&Vector2<f32>, point:&Vector2<f32>) -> f32 {
+    let segment = b - a;
+    let length2 = segment.norm_squared();
+    let ratio   = if length2 < EPSILON { 0.0 } else { (point - a).dot(&segment) / length2 };
+    let closest = a + segment * ratio.max(0.0).min(1.0);
+    (point - closest).norm()
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a:f32, b:f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}",a,b);
+    }
+
+    fn assert_close_vec(a:Vector2<f32>, b:(f32,f32)) {
+        assert_close(a.x,b.0);
+        assert_close(a.y,b.1);
+    }
+
+    #[test]
+    fn horizontal_curves() {
+        let curve = Curve::horizontal(Vector2::new(0.0,0.0),Vector2::new(100.0,50.0),0.5);
+        assert_close_vec(curve.source_control,(50.0,0.0));
+        assert_close_vec(curve.target_control,(50.0,50.0));
+        assert_close_vec(curve.point(0.0),(0.0,0.0));
+        assert_close_vec(curve.point(0.5),(50.0,25.0));
+        assert_close_vec(curve.point(1.0),(100.0,50.0));
+        assert_close_vec(curve.end_direction(),(1.0,0.0));
+
+        let points = curve.sample();
+        assert_eq!(points.len(),20);
+        assert_close_vec(points[0],(0.0,0.0));
+        assert_close_vec(points[points.len()-1],(100.0,50.0));
+    }
+
+    #[test]
+    fn degenerate_curves() {
+        let curve = Curve::horizontal(Vector2::new(0.0,0.0),Vector2::new(3.0,4.0),0.0);
+        assert_close_vec(curve.end_direction(),(0.6,0.8));
+        assert_eq!(curve.sample().len(),MIN_SEGMENTS + 1);
+
+        let curve = Curve::horizontal(Vector2::new(1.0,1.0),Vector2::new(1.0,1.0),0.5);
+        assert_close_vec(curve.end_direction(),(1.0,0.0));
+        assert_eq!(curve.sample().len(),MIN_SEGMENTS + 1);
+
+        let curve = Curve::horizontal(Vector2::new(0.0,0.0),Vector2::new(10_000.0,0.0),0.5);
+        assert_eq!(curve.sample().len(),MAX_SEGMENTS + 1);
+    }
+
+    #[test]
+    fn arrows() {
+        let arrow  = Arrow {length:10.0, width:8.0};
+        let points = arrow.points(Vector2::new(10.0,0.0),Vector2::new(1.0,0.0));
+        assert_close_vec(points[0],(0.0,4.0));
+        assert_close_vec(points[1],(10.0,0.0));
+        assert_close_vec(points[2],(0.0,-4.0));
+    }
+
+    #[test]
+    fn distances() {
+        let points = vec![Vector2::new(0.0,0.0),Vector2::new(10.0,0.0),Vector2::new(10.0,10.0)];
+        assert_close(distance_to_polyline(&points,&Vector2::new(5.0,3.0)),3.0);
+        assert_close(distance_to_polyline(&points,&Vector2::new(12.0,5.0)),2.0);
+        assert_close(distance_to_polyline(&points,&Vector2::new(-3.0,-4.0)),5.0);
+        assert_close(distance_to_polyline(&points[..1],&Vector2::new(3.0,4.0)),5.0);
+        assert_eq!(distance_to_polyline(&[],&Vector2::new(0.0,0.0)),std::f32::INFINITY);
+    }
+}
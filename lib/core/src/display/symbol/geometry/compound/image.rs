@@ -0,0 +1,478 @@
+//! This module defines image sprites, sprites drawing bitmaps. All image sprites of a system share
+//! a single texture, and each of them draws its own rectangle of it. Keeping many small images,
+//! like avatars, thumbnails or icons, in one texture lets all of them be drawn with one draw call.
+
+#[warn(missing_docs)]
+pub mod fit;
+
+use crate::prelude::*;
+
+use crate::display::symbol::geometry::compound::sprite::Sprite;
+use crate::display::symbol::geometry::compound::sprite::SpriteRef;
+use crate::display::symbol::geometry::compound::sprite::SymbolRef;
+use crate::display::symbol::layer::MissingLayer;
+use crate::display::symbol::material::Material;
+use crate::display::symbol::material::VarDecl;
+use crate::system::gpu::data::texture::atlas::UvRect;
+use crate::system::gpu::data::texture::Rgba;
+use crate::system::gpu::data::texture::TextureData;
+use crate::system::gpu::data::texture::TextureFromUrl;
+use crate::system::gpu::data::uniform::IntoUniformValue;
+use crate::system::gpu::shader::glsl;
+
+use crate::display::object::*;
+use crate::display::world::*;
+
+use fit::Fit;
+use fit::Placement;
+use nalgebra::Matrix4;
+use nalgebra::Vector2;
+use nalgebra::Vector3;
+use nalgebra::Vector4;
+use std::cell::Cell;
+
+
+
+// ===================
+// === ImageLayout ===
+// ===================
+
+/// The parameters from which the drawn rectangle of an image sprite is computed.
+#[derive(Clone,Copy,Debug)]
+struct ImageLayout {
+    bounds : Vector2<f32>,
+    uv     : UvRect,
+    fit    : Fit,
+}
+
+impl Default for ImageLayout {
+    fn default() -> Self {
+        let bounds = Vector2::new(1.0,1.0);
+        let min    = Vector2::new(0.0,0.0);
+        let max    = Vector2::new(1.0,1.0);
+        let uv     = UvRect {min,max};
+        let fit    = default();
+        Self {bounds,uv,fit}
+    }
+}
+
+impl ImageLayout {
+    /// Fits the drawn part of the texture of the provided size into the bounds.
+    fn place(&self, texture_size:Vector2<f32>) -> Placement {
+        let extent = self.uv.max - self.uv.min;
+        let image  = Vector2::new(extent.x * texture_size.x, extent.y * texture_size.y);
+        fit::place(self.fit,self.bounds,self.uv,image)
+    }
+}
+
+
+
+// ==================
+// === SpriteList ===
+// ==================
+
+/// A sprite laid out according to the size of the texture of its system.
+trait LayoutTarget : Debug {
+    /// Fits the image into the bounds of the sprite, for the current size of the texture.
+    fn apply_layout(&self);
+}
+
+/// Weak references to the sprites of a system, laid out again when the size of the texture
+/// changes. The texture size is shared with the sprites.
+#[derive(Debug)]
+struct SpriteList<T:LayoutTarget=ImageSpriteData> {
+    texture_size : Rc<Cell<Vector2<f32>>>,
+    sprites      : RefCell<Vec<Weak<T>>>,
+}
+
+impl<T:LayoutTarget> SpriteList<T> {
+    /// Constructor.
+    fn new(texture_size:Vector2<f32>) -> Self {
+        let texture_size = Rc::new(Cell::new(texture_size));
+        let sprites      = default();
+        Self {texture_size,sprites}
+    }
+
+    /// Lays the sprite out and registers it. The dropped sprites are forgotten.
+    fn add(&self, sprite:&Rc<T>) {
+        sprite.apply_layout();
+        let sprites = &mut self.sprites.borrow_mut();
+        sprites.retain(|sprite| sprite.upgrade().is_some());
+        sprites.push(Rc::downgrade(sprite));
+    }
+
+    /// Sets the size of the texture and lays out again all the sprites which are still alive.
+    fn set_texture_size(&self, size:Vector2<f32>) {
+        self.texture_size.set(size);
+        for sprite in self.sprites.borrow().iter().filter_map(Weak::upgrade) {
+            sprite.apply_layout();
+        }
+    }
+
+    /// Checks whether any registered sprite is still alive.
+    fn has_alive(&self) -> bool {
+        self.sprites.borrow().iter().any(|sprite| sprite.upgrade().is_some())
+    }
+}
+
+
+
+// ===================
+// === ImageSprite ===
+// ===================
+
+/// A sprite drawing a rectangle of the texture of its `ImageSpriteSystem`. By default, the sprite
+/// draws the whole texture stretched to its bounds.
+#[derive(Debug)]
+pub struct ImageSprite {
+    data    : Rc<ImageSpriteData>,
+    tint    : Attribute<Vector4<f32>>,
+    opacity : Attribute<f32>,
+}
+
+/// The part of `ImageSprite` its system keeps a weak reference to, to lay the sprite out again
+/// when the size of the texture changes.
+#[derive(Debug)]
+struct ImageSpriteData {
+    sprite       : Sprite,
+    uv_rect      : Attribute<Vector4<f32>>,
+    layout       : RefCell<ImageLayout>,
+    texture_size : Rc<Cell<Vector2<f32>>>,
+}
+
+impl ImageSprite {
+    /// Modifies the position of the sprite.
+    pub fn mod_position<F:FnOnce(&mut Vector3<f32>)>(&self, f:F) {
+        self.data.sprite.mod_position(f);
+    }
+
+    /// Sets the position of the sprite.
+    pub fn set_position(&self, value:Vector3<f32>) {
+        self.data.sprite.set_position(value);
+    }
+
+    /// Sets the bounds of the sprite. The image is fitted into them according to the fit mode.
+    pub fn set_bbox(&self, value:Vector2<f32>) {
+        self.mod_layout(|layout| layout.bounds = value);
+    }
+
+    /// Sets the rectangle of the texture drawn by the sprite, in texture coordinates. The
+    /// coordinates grow from the top left corner of the texture, like the ones of `Atlas`.
+    pub fn set_uv(&self, value:UvRect) {
+        self.mod_layout(|layout| layout.uv = value);
+    }
+
+    /// Sets the way the image is fitted into the bounds of the sprite.
+    pub fn set_fit(&self, value:Fit) {
+        self.mod_layout(|layout| layout.fit = value);
+    }
+
+    /// Sets the color the image is multiplied by. White keeps the image unchanged.
+    pub fn set_tint(&self, value:Vector4<f32>) {
+        self.tint.set(value);
+    }
+
+    /// Sets the opacity of the sprite.
+    pub fn set_opacity(&self, value:f32) {
+        self.opacity.set(value);
+    }
+
+    /// Updates the sprite and all of its children.
+    pub fn update(&self) {
+        self.data.sprite.update();
+    }
+
+    fn mod_layout<F:FnOnce(&mut ImageLayout)>(&self, f:F) {
+        f(&mut self.data.layout.borrow_mut());
+        self.data.apply_layout();
+    }
+}
+
+impl LayoutTarget for ImageSpriteData {
+    fn apply_layout(&self) {
+        let placement = self.layout.borrow().place(self.texture_size.get());
+        let uv        = placement.uv;
+        self.sprite.set_bbox(placement.size);
+        self.uv_rect.set(Vector4::new(uv.min.x,uv.min.y,uv.max.x,uv.max.y));
+    }
+}
+
+impl From<&ImageSprite> for DisplayObjectData {
+    fn from(t:&ImageSprite) -> Self {
+        (&t.data.sprite).into()
+    }
+}
+
+
+
+// =========================
+// === ImageSpriteSystem ===
+// =========================
+
+/// Creates a set of image sprites. All sprites in the system share the same texture, loaded from
+/// an URL or from raw data, and are drawn with one draw call. Each sprite draws a rectangle of
+/// the texture, fitted into its bounds, multiplied by its tint color and faded by its opacity.
+///
+/// Dropping the system removes its symbol, so all of its sprites have to be dropped first.
+#[derive(Debug)]
+pub struct ImageSpriteSystem {
+    display_object : DisplayObjectData,
+    world          : World,
+    symbol_id      : SymbolId,
+    transform      : Buffer<Matrix4<f32>>,
+    _uv            : Buffer<Vector2<f32>>,
+    bbox           : Buffer<Vector2<f32>>,
+    uv_rect        : Buffer<Vector4<f32>>,
+    tint           : Buffer<Vector4<f32>>,
+    opacity        : Buffer<f32>,
+    sprites        : Rc<SpriteList>,
+    logger         : Logger,
+}
+
+impl ImageSpriteSystem {
+    /// Creates a system drawing the image downloaded from the URL. The image is loaded
+    /// asynchronously. Until it is loaded, its size is unknown and sprites using the `Contain`
+    /// and `Cover` fit modes are laid out as for a square image. Once the image is loaded, all
+    /// sprites of the system are laid out again with its natural size.
+    pub fn from_url<S:Str>(world:&World, url:S) -> Self {
+        let sprites      = Rc::new(SpriteList::new(Vector2::new(1.0,1.0)));
+        let weak_sprites = Rc::downgrade(&sprites);
+        let texture      = TextureFromUrl::<Rgba,u8>::from(url).with_on_load(move |width,height| {
+            if let Some(sprites) = weak_sprites.upgrade() {
+                sprites.set_texture_size(Vector2::new(width as f32,height as f32));
+            }
+        });
+        Self::new(world,texture,sprites)
+    }
+
+    /// Creates a system drawing the provided RGBA image.
+    pub fn from_data(world:&World, data:TextureData<Rgba,u8>) -> Self {
+        let size    = Vector2::new(data.width as f32,data.height as f32);
+        let sprites = Rc::new(SpriteList::new(size));
+        Self::new(world,data,sprites)
+    }
+
+    fn new<T:IntoUniformValue>(world:&World, texture:T, sprites:Rc<SpriteList>) -> Self {
+        let logger         = Logger::new("ImageSpriteSystem");
+        let display_object = DisplayObjectData::new(logger.clone());
+        let world_data     = &mut world.borrow_mut();
+        let workspace      = &mut world_data.workspace;
+        let symbol_id      = workspace.new_symbol();
+        let symbol         = &mut workspace[symbol_id];
+        let mesh           = &mut symbol.surface;
+        let uv             = mesh.scopes.point.add_buffer("uv");
+        let transform      = mesh.scopes.instance.add_buffer("transform");
+        let bbox           = mesh.scopes.instance.add_buffer("bounds");
+        let uv_rect        = mesh.scopes.instance.add_buffer("uv_rect");
+        let tint           = mesh.scopes.instance.add_buffer("tint");
+        let opacity        = mesh.scopes.instance.add_buffer("opacity");
+
+        symbol.symbol_scope.add_or_panic("image",texture);
+        symbol.shader.set_geometry_material (&Self::geometry_material());
+        symbol.shader.set_material          (&Self::material());
+
+        let p1_index = mesh.scopes.point.add_instance();
+        let p2_index = mesh.scopes.point.add_instance();
+        let p3_index = mesh.scopes.point.add_instance();
+        let p4_index = mesh.scopes.point.add_instance();
+
+        uv.at(p1_index).set(Vector2::new(0.0, 0.0));
+        uv.at(p2_index).set(Vector2::new(0.0, 1.0));
+        uv.at(p3_index).set(Vector2::new(1.0, 0.0));
+        uv.at(p4_index).set(Vector2::new(1.0, 1.0));
+
+        world_data.stats.inc_sprite_system_count();
+
+        let world = world.clone_ref();
+        let _uv   = uv;
+        Self {
+            display_object,world,symbol_id,transform,_uv,bbox,uv_rect,tint,opacity,sprites,logger
+        }
+    }
+
+    /// Creates a new image sprite instance.
+    pub fn new_instance(&self) -> ImageSprite {
+        let instance_id = {
+            let world_data = &mut self.world.borrow_mut();
+            let symbol     = &mut world_data.workspace[self.symbol_id];
+            symbol.surface.instance.add_instance()
+        };
+        let transform    = self.transform.at(instance_id);
+        let bbox         = self.bbox.at(instance_id);
+        let uv_rect      = self.uv_rect.at(instance_id);
+        let tint         = self.tint.at(instance_id);
+        let opacity      = self.opacity.at(instance_id);
+        let symbol_ref   = SymbolRef::new(self.world.clone_ref(),self.symbol_id);
+        let sprite_ref   = SpriteRef::new(symbol_ref,instance_id);
+        let sprite       = Sprite::new(sprite_ref,transform,bbox);
+        let layout       = default();
+        let texture_size = self.sprites.texture_size.clone();
+        tint.set(Vector4::new(1.0,1.0,1.0,1.0));
+        opacity.set(1.0);
+        self.add_child(&sprite);
+        let data = Rc::new(ImageSpriteData {sprite,uv_rect,layout,texture_size});
+        self.sprites.add(&data);
+        ImageSprite {data,tint,opacity}
+    }
+
+    fn geometry_material() -> Material {
+        let mut material = Material::new();
+        material.add_input_def  :: <Vector2<f32>> ("bounds");
+        material.add_input_def  :: <Vector2<f32>> ("uv");
+        material.add_input_def  :: <Vector4<f32>> ("uv_rect");
+        material.add_input_def  :: <Vector4<f32>> ("tint");
+        material.add_input_def  :: <f32>          ("opacity");
+        material.add_input_def  :: <Matrix4<f32>> ("transform");
+        material.add_input_def  :: <Matrix4<f32>> ("view_projection");
+        material.add_output_def :: <Vector3<f32>> ("local");
+        material.add_output_def :: <Vector2<f32>> ("image_uv");
+        material.set_main("
+                mat4 model_view_projection = input_view_projection * input_transform;
+                vec2 flipped_uv            = vec2(input_uv.x, 1.0 - input_uv.y);
+                input_local                = vec3((input_uv - 0.5) * input_bounds, 0.0);
+                input_image_uv             = mix(input_uv_rect.xy, input_uv_rect.zw, flipped_uv);
+                gl_Position                = model_view_projection * vec4(input_local,1.0);
+                ");
+        material
+    }
+
+    fn material() -> Material {
+        let mut material = Material::new();
+        material.add_input("image",VarDecl::new(glsl::PrimType::Sampler2d,None));
+        material.set_main("
+                output_color    = texture(input_image,input_image_uv) * input_tint;
+                output_color.a *= input_opacity;
+                ");
+        material
+    }
+}
+
+impl From<&ImageSpriteSystem> for DisplayObjectData {
+    fn from(t:&ImageSpriteSystem) -> Self {
+        t.display_object.clone_ref()
+    }
+}
+
+impl<'t> Modify<&'t DisplayObjectData> for &'t ImageSpriteSystem {
+    fn modify<F:FnOnce(&'t DisplayObjectData)>(self, f:F) {
+        f(&self.display_object)
+    }
+}
+
+impl Drop for ImageSpriteSystem {
+    fn drop(&mut self) {
+        if self.sprites.has_alive() {
+            self.logger.warning("The system was dropped before its sprites.");
+        }
+        let world_data = &mut self.world.borrow_mut();
+        world_data.stats.dec_sprite_system_count();
+        if let Some(symbol) = world_data.workspace.remove_symbol(self.symbol_id) {
+            symbol.symbol_scope.remove("image");
+        }
+    }
+}
+
+
+// === Setters ===
+
+impl ImageSpriteSystem {
+    /// Moves all sprites of this system to the provided layer. The layer has to be registered in
+    /// `SymbolRegistry::layers` first.
+    pub fn set_layer(&self, layer:&str) -> Result<(),MissingLayer> {
+        let world_data = &mut self.world.borrow_mut();
+        let layers     = &mut world_data.workspace.symbols.layers;
+        layers.set_layer(self.symbol_id,layer)
+    }
+
+    /// Sets the z-index of this system within its layer. Systems with higher z-indexes are drawn
+    /// on top of the ones with lower z-indexes.
+    pub fn set_z_index(&self, z_index:i32) {
+        let world_data = &mut self.world.borrow_mut();
+        let layers     = &mut world_data.workspace.symbols.layers;
+        layers.set_z_index(self.symbol_id,z_index);
+    }
+
+    /// Enables or disables sorting of sprites by their depth every frame. Enable it for images
+    /// with transparent pixels or for translucent sprites, so they are blended back to front.
+    pub fn set_depth_sorting(&self, enabled:bool) {
+        let world_data = &mut self.world.borrow_mut();
+        let symbol     = &mut world_data.workspace[self.symbol_id];
+        symbol.set_depth_sorting(enabled);
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sprite recording the sizes of the rectangles it was laid out with.
+    #[derive(Debug)]
+    struct TestSprite {
+        layout       : ImageLayout,
+        texture_size : Rc<Cell<Vector2<f32>>>,
+        sizes        : RefCell<Vec<Vector2<f32>>>,
+    }
+
+    impl TestSprite {
+        fn new(list:&SpriteList<TestSprite>, fit:Fit, bounds:Vector2<f32>) -> Rc<Self> {
+            let layout       = ImageLayout {fit,bounds,..default()};
+            let texture_size = list.texture_size.clone();
+            let sizes        = default();
+            let sprite       = Rc::new(Self {layout,texture_size,sizes});
+            list.add(&sprite);
+            sprite
+        }
+
+        fn last_size(&self) -> Vector2<f32> {
+            *self.sizes.borrow().last().unwrap()
+        }
+    }
+
+    impl LayoutTarget for TestSprite {
+        fn apply_layout(&self) {
+            let placement = self.layout.place(self.texture_size.get());
+            self.sizes.borrow_mut().push(placement.size);
+        }
+    }
+
+    #[test]
+    fn sprites_are_laid_out_again_when_the_natural_size_arrives() {
+        let list    = SpriteList::new(Vector2::new(1.0,1.0));
+        let bounds  = Vector2::new(100.0,100.0);
+        let contain = TestSprite::new(&list,Fit::Contain,bounds);
+        let stretch = TestSprite::new(&list,Fit::Stretch,bounds);
+        assert_eq!(contain.last_size(),Vector2::new(100.0,100.0));
+        assert_eq!(stretch.last_size(),Vector2::new(100.0,100.0));
+
+        list.set_texture_size(Vector2::new(400.0,200.0));
+        assert_eq!(contain.sizes.borrow().len(),2);
+        assert_eq!(contain.last_size(),Vector2::new(100.0,50.0));
+        assert_eq!(stretch.last_size(),Vector2::new(100.0,100.0));
+
+        let late = TestSprite::new(&list,Fit::Contain,bounds);
+        assert_eq!(late.sizes.borrow().len(),1);
+        assert_eq!(late.last_size(),Vector2::new(100.0,50.0));
+    }
+
+    #[test]
+    fn dropped_sprites_are_forgotten() {
+        let list    = SpriteList::new(Vector2::new(1.0,1.0));
+        let bounds  = Vector2::new(10.0,10.0);
+        let dropped = TestSprite::new(&list,Fit::Contain,bounds);
+        let weak    = Rc::downgrade(&dropped);
+        assert!(list.has_alive());
+        drop(dropped);
+        assert!(weak.upgrade().is_none());
+        assert!(!list.has_alive());
+        list.set_texture_size(Vector2::new(20.0,10.0));
+        let alive = TestSprite::new(&list,Fit::Contain,bounds);
+        assert_eq!(list.sprites.borrow().len(),1);
+        assert_eq!(alive.last_size(),Vector2::new(10.0,5.0));
+    }
+}
@@ -0,0 +1,141 @@
+//! This module defines how images are fitted into the bounds of image sprites. Fitting is computed
+//! on the CPU whenever the bounds or the image of a sprite change. The result is the size of the
+//! drawn rectangle and the part of the image drawn in it.
+
+use crate::prelude::*;
+
+use crate::system::gpu::data::texture::atlas::UvRect;
+
+use nalgebra::Vector2;
+
+
+
+// ===========
+// === Fit ===
+// ===========
+
+/// The way an image is fitted into the bounds of a sprite.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum Fit {
+    /// The image fills the bounds, ignoring its aspect ratio.
+    Stretch,
+    /// The whole image is drawn as large as possible within the bounds, keeping its aspect ratio.
+    Contain,
+    /// The image covers the whole bounds, keeping its aspect ratio. The parts of the image
+    /// exceeding the bounds are cropped evenly from both sides.
+    Cover,
+}
+
+impl Default for Fit {
+    fn default() -> Self {
+        Self::Stretch
+    }
+}
+
+
+
+// =================
+// === Placement ===
+// =================
+
+/// The size of the drawn rectangle, centered in the bounds of a sprite, and the texture
+/// coordinates of the part of the image drawn in it.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Placement {
+    /// The size of the drawn rectangle.
+    pub size : Vector2<f32>,
+    /// The texture coordinates of the drawn part of the image.
+    pub uv   : UvRect,
+}
+
+/// Fits the image into the bounds. The `image_size` is the size of the image in pixels, used only
+/// for its aspect ratio. Images and bounds without area are stretched.
+pub fn place(fit:Fit, bounds:Vector2<f32>, uv:UvRect, image_size:Vector2<f32>) -> Placement {
+    let degenerate = image_size.x <= 0.0 || image_size.y <= 0.0 || bounds.x <= 0.0
+        || bounds.y <= 0.0;
+    let scale_x    = bounds.x / image_size.x;
+    let scale_y    = bounds.y / image_size.y;
+    match fit {
+        _ if degenerate => Placement {size:bounds,uv},
+        Fit::Stretch    => Placement {size:bounds,uv},
+        Fit::Contain    => Placement {size:image_size * scale_x.min(scale_y),uv},
+        Fit::Cover      => {
+            let scale   = scale_x.max(scale_y);
+            let visible = Vector2::new(scale_x / scale, scale_y / scale);
+            Placement {size:bounds,uv:crop(&uv,&visible)}
+        }
+    }
+}
+
+/// The centered part of the texture coordinates. The `visible` vector is the fraction of the
+/// width and height which is kept.
+fn crop(uv:&UvRect, visible:&Vector2<f32>) -> UvRect {
+    let extent = uv.max - uv.min;
+    let margin = Vector2::new(extent.x * (1.0 - visible.x), extent.y * (1.0 - visible.y)) / 2.0;
+    let min    = uv.min + margin;
+    let max    = uv.max - margin;
+    UvRect {min,max}
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full() -> UvRect {
+        let min = Vector2::new(0.0,0.0);
+        let max = Vector2::new(1.0,1.0);
+        UvRect {min,max}
+    }
+
+    fn assert_close(a:Vector2<f32>, b:(f32,f32)) {
+        assert!((a.x - b.0).abs() < 1e-5 && (a.y - b.1).abs() < 1e-5, "{:?} != {:?}",a,b);
+    }
+
+    #[test]
+    fn stretch() {
+        let placement = place(Fit::Stretch,Vector2::new(100.0,50.0),full(),Vector2::new(10.0,10.0));
+        assert_close(placement.size,(100.0,50.0));
+        assert_eq!(placement.uv,full());
+    }
+
+    #[test]
+    fn contain() {
+        let image     = Vector2::new(20.0,10.0);
+        let placement = place(Fit::Contain,Vector2::new(100.0,100.0),full(),image);
+        assert_close(placement.size,(100.0,50.0));
+        assert_eq!(placement.uv,full());
+        let placement = place(Fit::Contain,Vector2::new(100.0,20.0),full(),image);
+        assert_close(placement.size,(40.0,20.0));
+    }
+
+    #[test]
+    fn cover() {
+        let image     = Vector2::new(20.0,10.0);
+        let placement = place(Fit::Cover,Vector2::new(100.0,100.0),full(),image);
+        assert_close(placement.size,(100.0,100.0));
+        assert_close(placement.uv.min,(0.25,0.0));
+        assert_close(placement.uv.max,(0.75,1.0));
+
+        let min       = Vector2::new(0.5,0.0);
+        let max       = Vector2::new(1.0,0.5);
+        let placement = place(Fit::Cover,Vector2::new(10.0,40.0),UvRect {min,max},image);
+        assert_close(placement.uv.min,(0.71875,0.0));
+        assert_close(placement.uv.max,(0.78125,0.5));
+    }
+
+    #[test]
+    fn degenerate() {
+        let bounds    = Vector2::new(100.0,50.0);
+        let placement = place(Fit::Cover,bounds,full(),Vector2::new(0.0,10.0));
+        assert_close(placement.size,(100.0,50.0));
+        assert_eq!(placement.uv,full());
+        let placement = place(Fit::Contain,Vector2::new(0.0,0.0),full(),Vector2::new(1.0,1.0));
+        assert_close(placement.size,(0.0,0.0));
+    }
+}
@@ -0,0 +1,359 @@
+//! This module defines lines, thick polylines with joins, caps, dashes and per-vertex colors. All
+//! lines of a `LineSystem` are tessellated on the CPU and drawn as a single mesh with one draw
+//! call. Only the changed lines are tessellated again, but the vertices of all lines are uploaded
+//! whenever the number of vertices of any line changes, so the system fits best the lines which do
+//! not change every frame, like graph edges or plot lines.
+
+#[warn(missing_docs)]
+pub mod tessellation;
+
+use crate::prelude::*;
+
+use crate::control::callback::CallbackHandle;
+use crate::display::symbol::draw_mode::DrawMode;
+use crate::display::symbol::layer::MissingLayer;
+use crate::display::symbol::material::Material;
+use crate::display::symbol::shader::variant::Variant;
+use crate::display::world::*;
+
+use data::opt_vec::OptVec;
+use nalgebra::Matrix4;
+use nalgebra::Vector2;
+use nalgebra::Vector4;
+use tessellation::Point;
+use tessellation::Polyline;
+use tessellation::Style;
+use tessellation::Vertex;
+
+
+
+// ====================
+// === LineRegistry ===
+// ====================
+
+/// A line registered in the `LineRegistry`, with the vertices of its last tessellation. The
+/// `dirty` flag is set whenever the line changes.
+#[derive(Clone,Debug,Default)]
+struct LineData {
+    polyline : Polyline,
+    style    : Style,
+    vertices : Vec<Vertex>,
+    dirty    : bool,
+}
+
+/// All lines of a `LineSystem`. The `dirty` flag is set whenever any line changes, and the
+/// `resized` flag whenever the vertices of some lines were moved in the mesh.
+#[derive(Debug,Default)]
+struct LineRegistry {
+    lines   : OptVec<LineData>,
+    dirty   : bool,
+    resized : bool,
+}
+
+impl LineRegistry {
+    /// Tessellates the changed lines again. Returns the number of vertices of all lines.
+    fn tessellate(&mut self) -> usize {
+        for line in self.lines.iter_mut().filter(|line| line.dirty) {
+            let vertices  = tessellation::tessellate(&line.polyline,&line.style);
+            self.resized |= vertices.len() != line.vertices.len();
+            line.vertices = vertices;
+        }
+        self.lines.iter().map(|line| line.vertices.len()).sum()
+    }
+
+    /// Passes the vertices of the changed lines to `f`, along with their indexes in the mesh, and
+    /// clears the dirty flags. The vertices of all lines are passed if any of them were moved.
+    fn write<F:FnMut(usize,&Vertex)>(&mut self, mut f:F) {
+        let mut index = 0;
+        for line in self.lines.iter_mut() {
+            if self.resized || line.dirty {
+                for (offset,vertex) in line.vertices.iter().enumerate() {
+                    f(index + offset,vertex);
+                }
+            }
+            index     += line.vertices.len();
+            line.dirty = false;
+        }
+        self.dirty   = false;
+        self.resized = false;
+    }
+}
+
+type Lines = Rc<RefCell<LineRegistry>>;
+
+
+
+// ============
+// === Line ===
+// ============
+
+/// A single polyline drawn by the `LineSystem`. The line is removed when the handle is dropped.
+#[derive(Debug)]
+pub struct Line {
+    id    : usize,
+    lines : Lines,
+}
+
+impl Line {
+    fn new(lines:&Lines) -> Self {
+        let id    = lines.borrow_mut().lines.insert(default());
+        let lines = lines.clone();
+        Self {id,lines}
+    }
+
+    /// Sets the points of the line.
+    pub fn set_points(&self, points:Vec<Point>) {
+        self.modify(|line| line.polyline.points = points);
+    }
+
+    /// Sets whether the last point of the line is connected with the first one.
+    pub fn set_closed(&self, closed:bool) {
+        self.modify(|line| line.polyline.closed = closed);
+    }
+
+    /// Sets the style of the line.
+    pub fn set_style(&self, style:Style) {
+        self.modify(|line| line.style = style);
+    }
+
+    /// Modifies the style of the line.
+    pub fn mod_style<F:FnOnce(&mut Style)>(&self, f:F) {
+        self.modify(|line| f(&mut line.style));
+    }
+
+    fn modify<F:FnOnce(&mut LineData)>(&self, f:F) {
+        let mut lines = self.lines.borrow_mut();
+        let line      = &mut lines.lines[self.id];
+        line.dirty    = true;
+        f(line);
+        lines.dirty   = true;
+    }
+}
+
+impl Drop for Line {
+    fn drop(&mut self) {
+        let mut lines = self.lines.borrow_mut();
+        lines.lines.remove(self.id);
+        lines.dirty   = true;
+        lines.resized = true;
+    }
+}
+
+
+
+// ===================
+// === LineBuffers ===
+// ===================
+
+/// Point buffers of the line mesh.
+#[derive(Clone,Debug)]
+struct LineBuffers {
+    position   : Buffer<Vector2<f32>>,
+    color      : Buffer<Vector4<f32>>,
+    offset     : Buffer<f32>,
+    half_width : Buffer<f32>,
+}
+
+impl LineBuffers {
+    /// Tessellates the changed lines and uploads their vertices to the buffers. The point scope is
+    /// resized to the number of vertices.
+    fn update(&self, world:&World, symbol_id:SymbolId, lines:&Lines) {
+        let registry = &mut lines.borrow_mut();
+        if !registry.dirty { return }
+        let vertex_count = registry.tessellate();
+        let world_data   = &mut world.borrow_mut();
+        let symbol     = &mut world_data.workspace[symbol_id];
+        let scope      = &mut symbol.surface.scopes.point;
+        let ids        = scope.instance_ids();
+        for id in ids.iter().skip(vertex_count).rev() {
+            scope.dispose(*id);
+        }
+        for _ in ids.len()..vertex_count {
+            scope.add_instance();
+        }
+        registry.write(|index,vertex| {
+            self.position.set(index,vertex.position);
+            self.color.set(index,vertex.color);
+            self.offset.set(index,vertex.offset);
+            self.half_width.set(index,vertex.half_width);
+        });
+    }
+}
+
+
+
+// ==================
+// === LineSystem ===
+// ==================
+
+/// Creates a set of lines. All lines in the line system share the same material and are drawn
+/// together. Colors are interpolated along the lines, and their edges are anti-aliased by the
+/// material. Custom materials can use the `stroke_color`, `stroke_offset` and `stroke_half_width`
+/// inputs to shade the lines.
+#[derive(Debug)]
+pub struct LineSystem {
+    world     : World,
+    symbol_id : SymbolId,
+    lines     : Lines,
+    _on_frame : CallbackHandle,
+}
+
+impl LineSystem {
+    /// Constructor.
+    pub fn new(world:&World) -> Self {
+        let (symbol_id,buffers) = {
+            let world_data = &mut world.borrow_mut();
+            let workspace  = &mut world_data.workspace;
+            let symbol_id  = workspace.new_symbol();
+            let symbol     = &mut workspace[symbol_id];
+            let mesh       = &mut symbol.surface;
+            let position   = mesh.scopes.point.add_buffer("position");
+            let color      = mesh.scopes.point.add_buffer("color");
+            let offset     = mesh.scopes.point.add_buffer("offset");
+            let half_width = mesh.scopes.point.add_buffer("half_width");
+            mesh.scopes.instance.add_instance();
+
+            symbol.set_draw_mode(DrawMode::Triangles);
+            symbol.shader.set_geometry_material (&Self::geometry_material());
+            symbol.shader.set_material          (&Self::material());
+            (symbol_id,LineBuffers {position,color,offset,half_width})
+        };
+
+        let lines     = Lines::default();
+        let lines_ref = lines.clone();
+        let _on_frame = world.on_frame(move |world| buffers.update(world,symbol_id,&lines_ref));
+        let world     = world.clone_ref();
+        Self {world,symbol_id,lines,_on_frame}
+    }
+
+    /// Creates a new line without points. Use the `Line` setters to define it.
+    pub fn new_line(&self) -> Line {
+        Line::new(&self.lines)
+    }
+
+    fn geometry_material() -> Material {
+        let mut material = Material::new();
+        material.add_input_def  :: <Vector2<f32>> ("position");
+        material.add_input_def  :: <Vector4<f32>> ("color");
+        material.add_input_def  :: <f32>          ("offset");
+        material.add_input_def  :: <f32>          ("half_width");
+        material.add_input_def  :: <Matrix4<f32>> ("view_projection");
+        material.add_output_def :: <Vector4<f32>> ("stroke_color");
+        material.add_output_def :: <f32>          ("stroke_offset");
+        material.add_output_def :: <f32>          ("stroke_half_width");
+        material.set_main("
+                input_stroke_color      = input_color;
+                input_stroke_offset     = input_offset;
+                input_stroke_half_width = input_half_width;
+                gl_Position             = input_view_projection * vec4(input_position,0.0,1.0);
+                ");
+        material
+    }
+
+    fn material() -> Material {
+        let mut material = Material::new();
+        material.set_main("
+                float aa     = max(fwidth(input_stroke_offset),0.0001);
+                float inside = input_stroke_half_width - abs(input_stroke_offset);
+                float alpha  = clamp(inside / aa + 0.5, 0.0, 1.0);
+                output_color = vec4(input_stroke_color.rgb, input_stroke_color.a * alpha);
+                ");
+        material
+    }
+}
+
+impl Drop for LineSystem {
+    fn drop(&mut self) {
+        self.world.borrow_mut().workspace.remove_symbol(self.symbol_id);
+    }
+}
+
+
+// === Setters ===
+
+impl LineSystem {
+    /// Sets the material for all lines in this system.
+    pub fn set_material<M:Into<Material>>(&self, material:M) {
+        let world_data = &mut self.world.borrow_mut();
+        let symbol     = &mut world_data.workspace[self.symbol_id];
+        symbol.shader.set_material(material);
+    }
+
+    /// Moves all lines of this system to the provided layer. The layer has to be registered in
+    /// `SymbolRegistry::layers` first.
+    pub fn set_layer(&self, layer:&str) -> Result<(),MissingLayer> {
+        let world_data = &mut self.world.borrow_mut();
+        let layers     = &mut world_data.workspace.symbols.layers;
+        layers.set_layer(self.symbol_id,layer)
+    }
+
+    /// Sets the z-index of this system within its layer. Systems with higher z-indexes are drawn
+    /// on top of the ones with lower z-indexes.
+    pub fn set_z_index(&self, z_index:i32) {
+        let world_data = &mut self.world.borrow_mut();
+        let layers     = &mut world_data.workspace.symbols.layers;
+        layers.set_z_index(self.symbol_id,z_index);
+    }
+
+    /// Selects the variant of the shader of this system.
+    pub fn set_variant(&self, variant:Variant) {
+        let world_data = &mut self.world.borrow_mut();
+        let symbol     = &mut world_data.workspace[self.symbol_id];
+        symbol.set_variant(variant);
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(coords:&[(f32,f32)]) -> Vec<Point> {
+        let color = Vector4::new(1.0,1.0,1.0,1.0);
+        coords.iter().map(|(x,y)| Point::new(Vector2::new(*x,*y),color)).collect()
+    }
+
+    fn new_line(lines:&Lines, coords:&[(f32,f32)]) -> Line {
+        let line = Line::new(lines);
+        line.set_points(points(coords));
+        line
+    }
+
+    /// Updates the registry and returns the number of vertices and the written indexes.
+    fn update(lines:&Lines) -> (usize,Vec<usize>) {
+        let registry     = &mut lines.borrow_mut();
+        let vertex_count = registry.tessellate();
+        let mut indexes  = Vec::new();
+        registry.write(|index,_| indexes.push(index));
+        (vertex_count,indexes)
+    }
+
+    #[test]
+    fn only_changed_lines_are_written() {
+        let lines = Lines::default();
+        let first = new_line(&lines,&[(0.0,0.0),(10.0,0.0)]);
+        let last  = new_line(&lines,&[(0.0,5.0),(10.0,5.0)]);
+        assert_eq!(update(&lines),(12,(0..12).collect()));
+        assert!(!lines.borrow().dirty);
+
+        last.set_points(points(&[(0.0,6.0),(10.0,6.0)]));
+        assert_eq!(update(&lines),(12,(6..12).collect()));
+        assert_eq!(lines.borrow().lines[first.id].vertices.len(),6);
+
+        first.set_points(points(&[(0.0,0.0),(10.0,0.0),(10.0,10.0)]));
+        let (vertex_count,indexes) = update(&lines);
+        assert!(vertex_count > 12);
+        assert_eq!(indexes,(0..vertex_count).collect_vec());
+
+        drop(first);
+        assert_eq!(update(&lines),(6,(0..6).collect()));
+        let registry = lines.borrow();
+        let vertices = &registry.lines[last.id].vertices;
+        assert!(vertices.iter().all(|vertex| (vertex.position.y - 6.0).abs() <= 1.5));
+    }
+}
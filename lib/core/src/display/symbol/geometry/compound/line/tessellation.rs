@@ -0,0 +1,667 @@
+//! This module defines the tessellation of polylines. Lines are converted to lists of triangles on
+//! the CPU, with configurable joins, caps and dashes. Triangles are extruded by a feather on both
+//! sides of the line, and every vertex remembers its distance from the center of the line, so the
+//! shader can anti-alias the edges.
+
+use crate::prelude::*;
+
+use nalgebra::Vector2;
+use nalgebra::Vector4;
+use std::f32::consts::PI;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// The maximum angle in radians between two neighbouring vertices of round joins and caps.
+pub const ROUND_STEP : f32 = PI / 12.0;
+
+/// Points closer to each other than this distance are merged.
+pub const EPSILON : f32 = 1e-5;
+
+
+
+// =============
+// === Style ===
+// =============
+
+/// The shape of the corners of a line.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum Join {
+    /// Sharp corner. It falls back to `Bevel` if it is longer than the miter limit.
+    Miter,
+    /// Circular arc around the corner.
+    Round,
+    /// The outer edges of the segments are connected with a straight edge.
+    Bevel,
+}
+
+impl Default for Join {
+    fn default() -> Self {
+        Self::Miter
+    }
+}
+
+/// The shape of the ends of a line and its dashes.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum Cap {
+    /// The line ends exactly at its end points.
+    Butt,
+    /// The line ends with half circles.
+    Round,
+    /// The line is extended by half of its width.
+    Square,
+}
+
+impl Default for Cap {
+    fn default() -> Self {
+        Self::Butt
+    }
+}
+
+/// Dash pattern of a line. Lengths of dashes and gaps alternate, starting with a dash. Patterns
+/// of odd lengths are repeated twice, like in SVG.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Dashes {
+    /// Lengths of the dashes and the gaps.
+    pub pattern : Vec<f32>,
+    /// The distance into the pattern at which the line starts.
+    pub offset  : f32,
+}
+
+impl Dashes {
+    /// Constructor.
+    pub fn new(pattern:Vec<f32>) -> Self {
+        let offset = 0.0;
+        Self {pattern,offset}
+    }
+
+    /// Checks whether the pattern can be used. It has to contain non-negative lengths only, and at
+    /// least one of them has to be positive.
+    pub fn is_valid(&self) -> bool {
+        let non_negative = self.pattern.iter().all(|length| *length >= 0.0);
+        non_negative && self.pattern.iter().sum::<f32>() > 0.0
+    }
+
+    /// The pattern with an even number of lengths.
+    fn even_pattern(&self) -> Vec<f32> {
+        let mut pattern = self.pattern.clone();
+        if pattern.len() % 2 == 1 {
+            pattern.extend(self.pattern.iter());
+        }
+        pattern
+    }
+}
+
+/// The style of a line.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Style {
+    /// The width of the line.
+    pub width       : f32,
+    /// The shape of the corners.
+    pub join        : Join,
+    /// The shape of the ends of the line and its dashes.
+    pub cap         : Cap,
+    /// The maximum ratio of the miter length to the line width. Longer miters are beveled.
+    pub miter_limit : f32,
+    /// The dash pattern. Lines without it are solid.
+    pub dashes      : Option<Dashes>,
+    /// The width of the anti-aliased edge added on both sides of the line. It should be at least
+    /// the size of a pixel.
+    pub feather     : f32,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        let width       = 1.0;
+        let join        = default();
+        let cap         = default();
+        let miter_limit = 4.0;
+        let dashes      = default();
+        let feather     = 1.0;
+        Self {width,join,cap,miter_limit,dashes,feather}
+    }
+}
+
+
+
+// ================
+// === Polyline ===
+// ================
+
+/// A point of a polyline. Colors are interpolated along the segments.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Point {
+    /// The position of the point.
+    pub position : Vector2<f32>,
+    /// The color of the line at the point.
+    pub color    : Vector4<f32>,
+}
+
+impl Point {
+    /// Constructor.
+    pub fn new(position:Vector2<f32>, color:Vector4<f32>) -> Self {
+        Self {position,color}
+    }
+
+    /// The point between this one and the `other`, at the provided ratio of the distance between
+    /// them.
+    pub fn lerp(&self, other:&Point, ratio:f32) -> Self {
+        let position = self.position + (other.position - self.position) * ratio;
+        let color    = self.color    + (other.color    - self.color)    * ratio;
+        Self {position,color}
+    }
+}
+
+/// A sequence of points connected with straight segments. The last point of a closed polyline is
+/// connected with the first one.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Polyline {
+    /// The points of the polyline.
+    pub points : Vec<Point>,
+    /// Whether the last point is connected with the first one.
+    pub closed : bool,
+}
+
+impl Polyline {
+    /// Creates an open polyline.
+    pub fn new(points:Vec<Point>) -> Self {
+        let closed = false;
+        Self {points,closed}
+    }
+
+    /// Creates a closed polyline.
+    pub fn closed(points:Vec<Point>) -> Self {
+        let closed = true;
+        Self {points,closed}
+    }
+
+    /// The points without the consecutive duplicates. The last point of a closed polyline is also
+    /// dropped if it is equal to the first one.
+    fn distinct_points(&self) -> Vec<Point> {
+        let mut points : Vec<Point> = Vec::with_capacity(self.points.len());
+        for point in &self.points {
+            let duplicate = points.last().map(|last| is_same(last,point)).unwrap_or(false);
+            if !duplicate { points.push(*point) }
+        }
+        if self.closed && points.len() > 1 && is_same(&points[0],&points[points.len()-1]) {
+            points.pop();
+        }
+        points
+    }
+}
+
+fn is_same(a:&Point, b:&Point) -> bool {
+    (a.position - b.position).norm() < EPSILON
+}
+
+
+
+// ==============
+// === Vertex ===
+// ==============
+
+/// A vertex of a tessellated line.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Vertex {
+    /// The position of the vertex.
+    pub position   : Vector2<f32>,
+    /// The color of the vertex.
+    pub color      : Vector4<f32>,
+    /// The distance of the vertex from the center of the line. It is negative on the right side
+    /// of the line. The line is drawn where its absolute value is lower than `half_width`.
+    pub offset     : f32,
+    /// Half of the width of the line.
+    pub half_width : f32,
+}
+
+
+
+// ====================
+// === Tessellation ===
+// ====================
+
+/// Tessellates the polyline into a list of triangles, three vertices each. Segments are clipped at
+/// the inner sides of the corners, so the triangles of a line do not overlap, unless a segment is
+/// too short to be clipped, which is visible only in case of translucent colors.
+pub fn tessellate(line:&Polyline, style:&Style) -> Vec<Vertex> {
+    let mut tessellator = Tessellator::new(style);
+    match &style.dashes {
+        Some(dashes) if dashes.is_valid() => {
+            for dash in split_dashes(line,dashes) {
+                tessellator.polyline(&dash)
+            }
+        }
+        _ => tessellator.polyline(line)
+    }
+    tessellator.vertices
+}
+
+/// Splits the polyline into open polylines, one per dash. The colors of the dash ends are
+/// interpolated. Returns no dashes if the pattern is not valid.
+pub fn split_dashes(line:&Polyline, dashes:&Dashes) -> Vec<Polyline> {
+    let mut points = line.distinct_points();
+    if !dashes.is_valid() || points.len() < 2 { return default() }
+    if line.closed { points.push(points[0]) }
+
+    let pattern       = dashes.even_pattern();
+    let total         = pattern.iter().sum::<f32>();
+    let mut phase     = dashes.offset.rem_euclid(total);
+    let mut index     = 0;
+    while phase >= pattern[index] {
+        phase -= pattern[index];
+        index  = (index + 1) % pattern.len();
+    }
+    let mut remaining = pattern[index] - phase;
+    let mut result    = Vec::new();
+    let mut current   = if index % 2 == 0 { vec![points[0]] } else { default() };
+
+    for (start,end) in points.iter().zip(points.iter().skip(1)) {
+        let length       = (end.position - start.position).norm();
+        let mut position = 0.0;
+        while length - position > remaining {
+            position += remaining;
+            let point = start.lerp(end,position / length);
+            if index % 2 == 0 {
+                current.push(point);
+                result.push(Polyline::new(std::mem::take(&mut current)));
+            } else {
+                current = vec![point];
+            }
+            index     = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+        remaining -= length - position;
+        if index % 2 == 0 { current.push(*end) }
+    }
+    if index % 2 == 0 && current.len() > 1 {
+        result.push(Polyline::new(current));
+    }
+    result
+}
+
+/// Accumulates the triangles of tessellated polylines.
+#[derive(Debug)]
+struct Tessellator<'t> {
+    style      : &'t Style,
+    half_width : f32,
+    extent     : f32,
+    vertices   : Vec<Vertex>,
+}
+
+impl<'t> Tessellator<'t> {
+    fn new(style:&'t Style) -> Self {
+        let half_width = style.width / 2.0;
+        let extent     = half_width + style.feather;
+        let vertices   = default();
+        Self {style,half_width,extent,vertices}
+    }
+
+    fn polyline(&mut self, line:&Polyline) {
+        let points = line.distinct_points();
+        let count  = points.len();
+        let closed = line.closed && count > 2;
+        if count < 2 { return }
+        let segment_count = if closed { count } else { count - 1 };
+        let joints        = if closed { 0..count } else { 1..count-1 };
+        let mut inner     = vec![None;count];
+        for ix in joints.clone() {
+            let prev  = &points[(ix + count - 1) % count];
+            let next  = &points[(ix + 1) % count];
+            inner[ix] = self.inner_corner(prev,&points[ix],next);
+        }
+        for ix in 0..segment_count {
+            let next = (ix + 1) % count;
+            self.segment(&points[ix],&points[next],inner[ix],inner[next]);
+        }
+        for ix in joints {
+            let prev = &points[(ix + count - 1) % count];
+            let next = &points[(ix + 1) % count];
+            self.join(prev,&points[ix],next,inner[ix]);
+        }
+        if !closed {
+            self.cap(&points[0],&points[1]);
+            self.cap(&points[count-1],&points[count-2]);
+        }
+    }
+
+    /// Adds a quad covering the segment. The ends with inner corners are clipped at them.
+    fn segment
+    (&mut self, start:&Point, end:&Point, start_inner:Option<Vector2<f32>>
+    , end_inner:Option<Vector2<f32>>) {
+        let extent      = self.extent;
+        let normal      = perpendicular(&(end.position - start.position).normalize()) * extent;
+        let mut a_left  = start.position + normal;
+        let mut a_right = start.position - normal;
+        let mut b_left  = end.position   + normal;
+        let mut b_right = end.position   - normal;
+        if let Some(inner) = start_inner {
+            let on_left = (inner - start.position).dot(&normal) > 0.0;
+            if on_left { a_left = inner } else { a_right = inner }
+        }
+        if let Some(inner) = end_inner {
+            let on_left = (inner - end.position).dot(&normal) > 0.0;
+            if on_left { b_left = inner } else { b_right = inner }
+        }
+        let a_left  = self.vertex(a_left ,start.color, extent);
+        let a_right = self.vertex(a_right,start.color,-extent);
+        let b_left  = self.vertex(b_left ,end.color  , extent);
+        let b_right = self.vertex(b_right,end.color  ,-extent);
+        self.triangle(a_left,a_right,b_left);
+        self.triangle(a_right,b_right,b_left);
+    }
+
+    /// The intersection of the inner edges of the segments meeting at the `point`. It is `None` if
+    /// the corner is straight, or if it is further from the `point` than half of either segment.
+    fn inner_corner(&self, prev:&Point, point:&Point, next:&Point) -> Option<Vector2<f32>> {
+        let dir_in  = (point.position - prev.position).normalize();
+        let dir_out = (next.position - point.position).normalize();
+        let cross   = perp_dot(&dir_in,&dir_out);
+        if cross.abs() < EPSILON { return None }
+        let side       = if cross > 0.0 { 1.0 } else { -1.0 };
+        let normal_in  = perpendicular(&dir_in) * side;
+        let miter      = normal_in + perpendicular(&dir_out) * side;
+        if miter.norm() < EPSILON { return None }
+        let miter      = miter.normalize();
+        let corner     = point.position + miter * self.extent / miter.dot(&normal_in);
+        let shift_in   = (point.position - corner).dot(&dir_in);
+        let shift_out  = (corner - point.position).dot(&dir_out);
+        let length_in  = (point.position - prev.position).norm();
+        let length_out = (next.position - point.position).norm();
+        let fits       = shift_in <= length_in / 2.0 && shift_out <= length_out / 2.0;
+        if fits { Some(corner) } else { None }
+    }
+
+    /// Fills the gap on the outer side of the corner between two segments. If the segments were
+    /// clipped at the `inner` corner, the gap between the clipped ends is filled as well.
+    fn join(&mut self, prev:&Point, point:&Point, next:&Point, inner:Option<Vector2<f32>>) {
+        let dir_in  = (point.position - prev.position).normalize();
+        let dir_out = (next.position - point.position).normalize();
+        let cross   = perp_dot(&dir_in,&dir_out);
+        if cross.abs() < EPSILON && dir_in.dot(&dir_out) > 0.0 { return }
+        let side       = if cross > 0.0 { -1.0 } else { 1.0 };
+        let normal_in  = perpendicular(&dir_in)  * side;
+        let normal_out = perpendicular(&dir_out) * side;
+        let center     = self.vertex(point.position,point.color,0.0);
+        let first      = self.rim(point,&normal_in);
+        let last       = self.rim(point,&normal_out);
+        if let Some(inner) = inner {
+            let inner = self.vertex(inner,point.color,-self.extent);
+            self.triangle(inner,first,center);
+            self.triangle(inner,center,last);
+        }
+        match self.style.join {
+            Join::Round => {
+                let angle = perp_dot(&normal_in,&normal_out).atan2(normal_in.dot(&normal_out));
+                self.arc(point,&normal_in,angle);
+            }
+            Join::Miter => match self.miter_tip(point,&normal_in,&normal_out) {
+                Some(tip) => {
+                    self.triangle(center,first,tip);
+                    self.triangle(center,tip,last);
+                }
+                None => self.triangle(center,first,last),
+            }
+            Join::Bevel => self.triangle(center,first,last),
+        }
+    }
+
+    /// The tip of the miter join, unless the miter is longer than the miter limit.
+    fn miter_tip
+    (&self, point:&Point, normal_in:&Vector2<f32>, normal_out:&Vector2<f32>) -> Option<Vertex> {
+        let miter = normal_in + normal_out;
+        if miter.norm() < EPSILON { return None }
+        let miter = miter.normalize();
+        let cos   = miter.dot(normal_in);
+        if 1.0 / cos > self.style.miter_limit { return None }
+        let position = point.position + miter * self.extent / cos;
+        Some(self.vertex(position,point.color,self.extent))
+    }
+
+    /// Adds the cap at the `point`, which is the end of the segment going from the `neighbour`.
+    fn cap(&mut self, point:&Point, neighbour:&Point) {
+        let direction = (point.position - neighbour.position).normalize();
+        let normal    = perpendicular(&direction);
+        match self.style.cap {
+            Cap::Butt   => {}
+            Cap::Round  => self.arc(point,&normal,-PI),
+            Cap::Square => {
+                let normal  = normal * self.extent;
+                let shift   = direction * self.half_width;
+                let extent  = self.extent;
+                let a_left  = self.vertex(point.position + normal        ,point.color, extent);
+                let a_right = self.vertex(point.position - normal        ,point.color,-extent);
+                let b_left  = self.vertex(point.position + normal + shift,point.color, extent);
+                let b_right = self.vertex(point.position - normal + shift,point.color,-extent);
+                self.triangle(a_left,a_right,b_left);
+                self.triangle(a_right,b_right,b_left);
+            }
+        }
+    }
+
+    /// Adds a fan of triangles around the `point`, starting at the `from` direction and rotated by
+    /// the `angle`.
+    fn arc(&mut self, point:&Point, from:&Vector2<f32>, angle:f32) {
+        let steps  = (angle.abs() / ROUND_STEP).ceil().max(1.0) as usize;
+        let center = self.vertex(point.position,point.color,0.0);
+        let rims   = (0..=steps).map(|step| {
+            let direction = rotate(from,angle * step as f32 / steps as f32);
+            self.rim(point,&direction)
+        }).collect_vec();
+        for (first,last) in rims.iter().zip(rims.iter().skip(1)) {
+            self.triangle(center,*first,*last);
+        }
+    }
+
+    /// The vertex on the outer edge of the line, in the provided direction from the point.
+    fn rim(&self, point:&Point, direction:&Vector2<f32>) -> Vertex {
+        self.vertex(point.position + direction * self.extent,point.color,self.extent)
+    }
+
+    fn vertex(&self, position:Vector2<f32>, color:Vector4<f32>, offset:f32) -> Vertex {
+        let half_width = self.half_width;
+        Vertex {position,color,offset,half_width}
+    }
+
+    fn triangle(&mut self, a:Vertex, b:Vertex, c:Vertex) {
+        self.vertices.extend(&[a,b,c]);
+    }
+}
+
+
+// === Utils ===
+
+/// The vector rotated counterclockwise by a right angle.
+fn perpendicular(v:&Vector2<f32>) -> Vector2<f32> {
+    Vector2::new(-v.y,v.x)
+}
+
+/// The z-coordinate of the cross product. It is positive if `b` points to the left of `a`.
+fn perp_dot(a:&Vector2<f32>, b:&Vector2<f32>) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/// The vector rotated counterclockwise by the angle in radians.
+fn rotate(v:&Vector2<f32>, angle:f32) -> Vector2<f32> {
+    let (sin,cos) = angle.sin_cos();
+    Vector2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(points:&[(f32,f32)]) -> Polyline {
+        let color = Vector4::new(1.0,1.0,1.0,1.0);
+        Polyline::new(points.iter().map(|(x,y)| Point::new(Vector2::new(*x,*y),color)).collect())
+    }
+
+    fn style(join:Join, cap:Cap) -> Style {
+        let width   = 2.0;
+        let feather = 0.0;
+        Style {width,join,cap,feather,..default()}
+    }
+
+    fn area(vertices:&[Vertex]) -> f32 {
+        vertices.chunks(3).map(|t| {
+            let ab = t[1].position - t[0].position;
+            let ac = t[2].position - t[0].position;
+            perp_dot(&ab,&ac).abs() / 2.0
+        }).sum()
+    }
+
+    fn bounds(vertices:&[Vertex]) -> (Vector2<f32>,Vector2<f32>) {
+        let inf = std::f32::INFINITY;
+        let min = Vector2::new(inf,inf);
+        let max = Vector2::new(-inf,-inf);
+        vertices.iter().fold((min,max),|(min,max),v| {
+            (min.zip_map(&v.position,f32::min),max.zip_map(&v.position,f32::max))
+        })
+    }
+
+    fn assert_close(a:f32, b:f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}",a,b);
+    }
+
+    fn assert_bounds(vertices:&[Vertex], min:(f32,f32), max:(f32,f32)) {
+        let bounds = bounds(vertices);
+        assert_close(bounds.0.x,min.0);
+        assert_close(bounds.0.y,min.1);
+        assert_close(bounds.1.x,max.0);
+        assert_close(bounds.1.y,max.1);
+    }
+
+    #[test]
+    fn straight_lines_and_caps() {
+        let line     = line(&[(0.0,0.0),(10.0,0.0)]);
+        let vertices = tessellate(&line,&style(Join::Miter,Cap::Butt));
+        assert_eq!(vertices.len(),6);
+        assert_close(area(&vertices),20.0);
+        assert_bounds(&vertices,(0.0,-1.0),(10.0,1.0));
+        for vertex in &vertices {
+            assert_close(vertex.half_width,1.0);
+            assert_close(vertex.offset.abs(),1.0);
+        }
+
+        let vertices = tessellate(&line,&style(Join::Miter,Cap::Square));
+        assert_close(area(&vertices),24.0);
+        assert_bounds(&vertices,(-1.0,-1.0),(11.0,1.0));
+
+        let vertices = tessellate(&line,&style(Join::Miter,Cap::Round));
+        assert!((area(&vertices) - (20.0 + PI)).abs() < 0.05);
+        assert_bounds(&vertices,(-1.0,-1.0),(11.0,1.0));
+    }
+
+    #[test]
+    fn joins() {
+        let line  = line(&[(0.0,0.0),(10.0,0.0),(10.0,10.0)]);
+        let miter = tessellate(&line,&style(Join::Miter,Cap::Butt));
+        let bevel = tessellate(&line,&style(Join::Bevel,Cap::Butt));
+        let round = tessellate(&line,&style(Join::Round,Cap::Butt));
+        assert_close(area(&miter),40.0);
+        assert_close(area(&bevel),39.5);
+        assert!((area(&round) - (39.0 + PI / 4.0)).abs() < 0.01);
+        assert!(miter.iter().any(|v| (v.position - Vector2::new(11.0,-1.0)).norm() < 1e-4));
+        let corner  = Vector2::new(10.0,0.0);
+        let centers = round.iter().filter(|v| v.offset.abs() < EPSILON).collect_vec();
+        assert!(!centers.is_empty());
+        assert!(centers.iter().all(|v| (v.position - corner).norm() < EPSILON));
+    }
+
+    #[test]
+    fn segments_are_clipped_at_inner_corners() {
+        let corner   = line(&[(0.0,0.0),(10.0,0.0),(10.0,10.0)]);
+        let vertices = tessellate(&corner,&style(Join::Bevel,Cap::Butt));
+        let has      = |x:f32, y:f32| {
+            vertices.iter().any(|v| (v.position - Vector2::new(x,y)).norm() < 1e-4)
+        };
+        assert!(has(9.0,1.0));
+        assert!(!has(10.0,1.0));
+        assert!(!has(9.0,0.0));
+
+        let short    = line(&[(0.0,0.0),(10.0,0.0),(10.0,1.0)]);
+        let vertices = tessellate(&short,&style(Join::Bevel,Cap::Butt));
+        assert_close(area(&vertices),20.0 + 2.0 + 0.5);
+    }
+
+    #[test]
+    fn miter_limit() {
+        let line      = line(&[(0.0,0.0),(10.0,0.0),(0.0,1.0)]);
+        let mut style = style(Join::Miter,Cap::Butt);
+        let (_,max)   = bounds(&tessellate(&line,&style));
+        assert!(max.x < 11.0);
+        style.miter_limit = 100.0;
+        let (_,max)       = bounds(&tessellate(&line,&style));
+        assert!(max.x > 20.0);
+    }
+
+    #[test]
+    fn closed_polylines() {
+        let mut square = line(&[(0.0,0.0),(10.0,0.0),(10.0,10.0),(0.0,10.0),(0.0,0.0)]);
+        square.closed  = true;
+        let vertices   = tessellate(&square,&style(Join::Miter,Cap::Round));
+        assert_eq!(vertices.len(),4 * 6 + 4 * 12);
+        assert_close(area(&vertices),12.0 * 12.0 - 8.0 * 8.0);
+        assert_bounds(&vertices,(-1.0,-1.0),(11.0,11.0));
+    }
+
+    #[test]
+    fn degenerate_polylines() {
+        let style = style(Join::Round,Cap::Round);
+        assert_eq!(tessellate(&line(&[]),&style),vec![]);
+        assert_eq!(tessellate(&line(&[(1.0,1.0),(1.0,1.0)]),&style),vec![]);
+        let duplicated = line(&[(0.0,0.0),(0.0,0.0),(10.0,0.0)]);
+        let single     = line(&[(0.0,0.0),(10.0,0.0)]);
+        assert_eq!(tessellate(&duplicated,&style),tessellate(&single,&style));
+    }
+
+    #[test]
+    fn dashes() {
+        let red      = Vector4::new(1.0,0.0,0.0,1.0);
+        let blue     = Vector4::new(0.0,0.0,1.0,1.0);
+        let start    = Point::new(Vector2::new(0.0,0.0),red);
+        let end      = Point::new(Vector2::new(10.0,0.0),blue);
+        let line     = Polyline::new(vec![start,end]);
+        let round    = |x:f32| (x * 1000.0).round() / 1000.0;
+        let ranges   = |dashes:&Dashes| split_dashes(&line,dashes).iter().map(|dash| {
+            let first = dash.points[0].position.x;
+            let last  = dash.points[dash.points.len()-1].position.x;
+            (round(first),round(last))
+        }).collect_vec();
+
+        let mut dashes = Dashes::new(vec![2.0,2.0]);
+        assert_eq!(ranges(&dashes),vec![(0.0,2.0),(4.0,6.0),(8.0,10.0)]);
+        dashes.offset = 1.0;
+        assert_eq!(ranges(&dashes),vec![(0.0,1.0),(3.0,5.0),(7.0,9.0)]);
+        assert_eq!(ranges(&Dashes::new(vec![3.0])),vec![(0.0,3.0),(6.0,9.0)]);
+        assert_eq!(ranges(&Dashes::new(vec![0.0,0.0])),vec![]);
+
+        let dash = &split_dashes(&line,&Dashes::new(vec![2.0,2.0]))[1];
+        assert!((dash.points[0].color - Vector4::new(0.6,0.0,0.4,1.0)).norm() < 1e-5);
+
+        let mut style = style(Join::Miter,Cap::Butt);
+        style.dashes  = Some(Dashes::new(vec![2.0,2.0]));
+        let vertices  = tessellate(&line,&style);
+        assert_eq!(vertices.len(),3 * 6);
+        assert_close(area(&vertices),12.0);
+    }
+
+    #[test]
+    fn dashes_of_closed_polylines() {
+        let mut square = line(&[(0.0,0.0),(4.0,0.0),(4.0,4.0),(0.0,4.0)]);
+        square.closed  = true;
+        let dashes     = split_dashes(&square,&Dashes::new(vec![6.0,2.0]));
+        assert_eq!(dashes.len(),2);
+        let corners = dashes.iter().map(|dash| dash.points.len()).collect_vec();
+        assert_eq!(corners,vec![3,3]);
+        assert!((dashes[1].points[2].position - Vector2::new(0.0,2.0)).norm() < 1e-5);
+    }
+}
@@ -0,0 +1,449 @@
+//! This module defines particles, small sprites spawned by emitters, moving along ballistic paths
+//! and fading out. Particles are instances of a single mesh. Their motion is integrated in the
+//! vertex shader from their spawn time and the current time, so their attributes are uploaded
+//! only once, when they are spawned. The slots of dead particles are reused by the new ones.
+
+#[warn(missing_docs)]
+pub mod emitter;
+
+use crate::prelude::*;
+
+use crate::control::callback::CallbackHandle;
+use crate::display::symbol::layer::MissingLayer;
+use crate::display::symbol::material::Material;
+use crate::system::gpu::data::AttributeInstanceIndex;
+use crate::system::gpu::data::AttributeScope;
+use crate::system::gpu::context::GpuContext;
+use crate::display::world::*;
+
+use data::opt_vec::OptVec;
+use emitter::Emitter;
+use emitter::EmitterConfig;
+use emitter::Particle;
+use nalgebra::Matrix4;
+use nalgebra::Vector2;
+use nalgebra::Vector3;
+use nalgebra::Vector4;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// The time in milliseconds after which the epoch of a `ParticleSystem` is moved to the current
+/// time. Spawn times are stored relative to the epoch, so they stay small enough for the `f32`
+/// precision of the GPU, no matter how long the page is open.
+const EPOCH_PERIOD : f32 = 60_000.0;
+
+
+
+// ========================
+// === ParticleRegistry ===
+// ========================
+
+/// All emitters of a `ParticleSystem` and the particles which are still alive.
+#[derive(Debug,Default)]
+struct ParticleRegistry {
+    emitters : OptVec<Emitter>,
+    alive    : Vec<(f32,AttributeInstanceIndex)>,
+    epoch    : f32,
+}
+
+type Particles = Rc<RefCell<ParticleRegistry>>;
+
+impl ParticleRegistry {
+    /// Moves the epoch to the provided time if it is older than `EPOCH_PERIOD`. Returns the
+    /// distance the epoch was moved by, which has to be subtracted from the stored spawn times of
+    /// the particles which are still alive.
+    fn rebase(&mut self, time:f32) -> Option<f32> {
+        let shift = time - self.epoch;
+        if shift < EPOCH_PERIOD { None } else {
+            self.epoch = time;
+            Some(shift)
+        }
+    }
+
+    /// Spawns the particles due at the provided time and frees the slots of the dead ones. Every
+    /// spawned particle is passed to `write` together with its slot.
+    fn update<C,F>(&mut self, time:f32, scope:&mut AttributeScope<C>, mut write:F)
+    where C:GpuContext, F:FnMut(AttributeInstanceIndex,&Particle) {
+        let spawned = self.emitters.iter_mut().flat_map(|e| e.emit(time)).collect_vec();
+        let alive   = std::mem::take(&mut self.alive);
+        let (expired,mut alive) : (Vec<_>,Vec<_>) = alive.into_iter().partition(|(expire,_)| {
+            *expire <= time
+        });
+        for (_,id) in expired {
+            scope.dispose(id);
+        }
+        for particle in spawned {
+            let id = scope.add_instance();
+            write(id,&particle);
+            alive.push((particle.expire_time(),id));
+        }
+        self.alive = alive;
+    }
+}
+
+
+
+// =======================
+// === ParticleEmitter ===
+// =======================
+
+/// An emitter of the `ParticleSystem`. The emitter stops spawning particles when the handle is
+/// dropped, but its particles live until the end of their lifetimes.
+#[derive(Debug)]
+pub struct ParticleEmitter {
+    id        : usize,
+    particles : Particles,
+}
+
+impl ParticleEmitter {
+    /// Sets the position at which particles are spawned.
+    pub fn set_position(&self, position:Vector3<f32>) {
+        self.modify(|emitter| emitter.position = position);
+    }
+
+    /// Sets the configuration of the spawned particles.
+    pub fn set_config(&self, config:EmitterConfig) {
+        self.modify(|emitter| emitter.config = config);
+    }
+
+    /// Modifies the configuration of the spawned particles.
+    pub fn mod_config<F:FnOnce(&mut EmitterConfig)>(&self, f:F) {
+        self.modify(|emitter| f(&mut emitter.config));
+    }
+
+    /// Starts or stops spawning particles at the configured rate.
+    pub fn set_active(&self, active:bool) {
+        self.modify(|emitter| emitter.active = active);
+    }
+
+    /// Spawns the provided number of particles during the next frame, even if the emitter is not
+    /// active.
+    pub fn burst(&self, count:usize) {
+        self.modify(|emitter| emitter.burst(count));
+    }
+
+    fn modify<F:FnOnce(&mut Emitter)>(&self, f:F) {
+        f(&mut self.particles.borrow_mut().emitters[self.id])
+    }
+}
+
+impl Drop for ParticleEmitter {
+    fn drop(&mut self) {
+        self.particles.borrow_mut().emitters.remove(self.id);
+    }
+}
+
+
+
+// =======================
+// === ParticleBuffers ===
+// =======================
+
+/// Instance buffers keeping the initial state of particles. Spawn times are stored relative to
+/// the epoch of the `ParticleRegistry`, and `local_time` is the current time relative to it.
+#[derive(Debug,Derivative)]
+#[derivative(Clone(bound=""))]
+struct ParticleBuffers<C:GpuContext=Context> {
+    origin     : Buffer<Vector3<f32>,C>,
+    velocity   : Buffer<Vector3<f32>,C>,
+    gravity    : Buffer<Vector3<f32>,C>,
+    spawn_time : Buffer<f32,C>,
+    lifetime   : Buffer<f32,C>,
+    size       : Buffer<f32,C>,
+    color      : Buffer<Vector4<f32>,C>,
+    local_time : Uniform<f32>,
+}
+
+impl<C:GpuContext> ParticleBuffers<C> {
+    /// Constructor. Adds the buffers to the instance scope and the `particle_time` uniform to the
+    /// symbol scope.
+    fn new(scope:&mut AttributeScope<C>, symbol_scope:&UniformScope<C>) -> Self {
+        let origin     = scope.add_buffer("origin");
+        let velocity   = scope.add_buffer("velocity");
+        let gravity    = scope.add_buffer("gravity");
+        let spawn_time = scope.add_buffer("spawn_time");
+        let lifetime   = scope.add_buffer("lifetime");
+        let size       = scope.add_buffer("size");
+        let color      = scope.add_buffer("color");
+        let local_time = symbol_scope.add_or_panic("particle_time",0.0);
+        Self {origin,velocity,gravity,spawn_time,lifetime,size,color,local_time}
+    }
+
+    fn write(&self, id:AttributeInstanceIndex, particle:&Particle, epoch:f32) {
+        self.origin.at(id).set(particle.origin);
+        self.velocity.at(id).set(particle.velocity);
+        self.gravity.at(id).set(particle.gravity);
+        self.spawn_time.at(id).set(particle.spawn_time - epoch);
+        self.lifetime.at(id).set(particle.lifetime);
+        self.size.at(id).set(particle.size);
+        self.color.at(id).set(particle.color);
+    }
+
+    /// Rebases the epoch if needed, spawns the particles due at the provided time and frees the
+    /// slots of the dead ones.
+    fn step(&self, time:f32, scope:&mut AttributeScope<C>, particles:&mut ParticleRegistry) {
+        if let Some(shift) = particles.rebase(time) {
+            for (_,id) in &particles.alive {
+                self.spawn_time.at(*id).modify(|spawn_time| *spawn_time -= shift);
+            }
+        }
+        let epoch = particles.epoch;
+        particles.update(time,scope,|id,particle| self.write(id,particle,epoch));
+        self.local_time.set(time - epoch);
+    }
+}
+
+impl ParticleBuffers {
+    /// Spawns the particles due in this frame and frees the slots of the dead ones.
+    fn update(&self, world:&World, symbol_id:SymbolId, particles:&Particles) {
+        let world_data = &mut world.borrow_mut();
+        let time       = world_data.time.get();
+        let scope      = &mut world_data.workspace[symbol_id].surface.scopes.instance;
+        self.step(time,scope,&mut particles.borrow_mut());
+    }
+}
+
+
+
+// ======================
+// === ParticleSystem ===
+// ======================
+
+/// Creates a set of particles. All particles in the particle system share the same material and
+/// are drawn together. Particles are spawned by `ParticleEmitter`s. The material can use the
+/// `particle_color`, `particle_uv` and `particle_life` inputs, the latter growing from `0.0` at
+/// the spawn to `1.0` at the death of a particle.
+#[derive(Debug)]
+pub struct ParticleSystem {
+    world     : World,
+    symbol_id : SymbolId,
+    particles : Particles,
+    _on_frame : CallbackHandle,
+}
+
+impl ParticleSystem {
+    /// Constructor.
+    pub fn new(world:&World) -> Self {
+        let (symbol_id,buffers) = {
+            let world_data = &mut world.borrow_mut();
+            let workspace  = &mut world_data.workspace;
+            let symbol_id  = workspace.new_symbol();
+            let symbol     = &mut workspace[symbol_id];
+            let mesh       = &mut symbol.surface;
+            let uv         = mesh.scopes.point.add_buffer("uv");
+            let buffers    = ParticleBuffers::new(&mut mesh.scopes.instance,&symbol.symbol_scope);
+
+            symbol.shader.set_geometry_material (&Self::geometry_material());
+            symbol.shader.set_material          (&Self::material());
+
+            let p1_index = mesh.scopes.point.add_instance();
+            let p2_index = mesh.scopes.point.add_instance();
+            let p3_index = mesh.scopes.point.add_instance();
+            let p4_index = mesh.scopes.point.add_instance();
+
+            uv.at(p1_index).set(Vector2::new(0.0, 0.0));
+            uv.at(p2_index).set(Vector2::new(0.0, 1.0));
+            uv.at(p3_index).set(Vector2::new(1.0, 0.0));
+            uv.at(p4_index).set(Vector2::new(1.0, 1.0));
+
+            (symbol_id,buffers)
+        };
+
+        let particles     = Particles::default();
+        let particles_ref = particles.clone();
+        let _on_frame     = world.on_frame(move |world| {
+            buffers.update(world,symbol_id,&particles_ref)
+        });
+        let world = world.clone_ref();
+        Self {world,symbol_id,particles,_on_frame}
+    }
+
+    /// Creates a new emitter at the origin of the scene.
+    pub fn new_emitter(&self, config:EmitterConfig) -> ParticleEmitter {
+        let id        = self.particles.borrow_mut().emitters.insert(Emitter::new(config));
+        let particles = self.particles.clone();
+        ParticleEmitter {id,particles}
+    }
+
+    /// The number of particles which are alive.
+    pub fn particle_count(&self) -> usize {
+        self.particles.borrow().alive.len()
+    }
+
+    fn geometry_material() -> Material {
+        let mut material = Material::new();
+        material.add_input_def  :: <Vector2<f32>> ("uv");
+        material.add_input_def  :: <Vector3<f32>> ("origin");
+        material.add_input_def  :: <Vector3<f32>> ("velocity");
+        material.add_input_def  :: <Vector3<f32>> ("gravity");
+        material.add_input_def  :: <f32>          ("spawn_time");
+        material.add_input_def  :: <f32>          ("lifetime");
+        material.add_input_def  :: <f32>          ("size");
+        material.add_input_def  :: <Vector4<f32>> ("color");
+        material.add_input_def  :: <f32>          ("particle_time");
+        material.add_input_def  :: <Matrix4<f32>> ("view_projection");
+        material.add_output_def :: <Vector4<f32>> ("particle_color");
+        material.add_output_def :: <Vector2<f32>> ("particle_uv");
+        material.add_output_def :: <f32>          ("particle_life");
+        material.set_main("
+                float age              = (input_particle_time - input_spawn_time) / 1000.0;
+                vec3  drift            = input_velocity * age + 0.5 * input_gravity * age * age;
+                vec3  local            = vec3((input_uv - 0.5) * input_size, 0.0);
+                input_particle_color   = input_color;
+                input_particle_uv      = input_uv;
+                input_particle_life    = age / input_lifetime;
+                gl_Position            = input_view_projection
+                                       * vec4(input_origin + drift + local, 1.0);
+                if (age < 0.0 || age > input_lifetime) {
+                    gl_Position = vec4(0.0, 0.0, 2.0, 1.0);
+                }
+                ");
+        material
+    }
+
+    fn material() -> Material {
+        let mut material = Material::new();
+        material.set_main("
+                float dist    = length(input_particle_uv - 0.5) * 2.0;
+                float aa      = max(fwidth(dist),0.0001);
+                float alpha   = clamp((1.0 - dist) / aa, 0.0, 1.0);
+                float fade    = 1.0 - clamp(input_particle_life, 0.0, 1.0);
+                float opacity = input_particle_color.a * alpha * fade;
+                output_color  = vec4(input_particle_color.rgb, opacity);
+                ");
+        material
+    }
+}
+
+impl Drop for ParticleSystem {
+    fn drop(&mut self) {
+        self.world.borrow_mut().workspace.remove_symbol(self.symbol_id);
+    }
+}
+
+
+// === Setters ===
+
+impl ParticleSystem {
+    /// Sets the material for all particles in this system.
+    pub fn set_material<M:Into<Material>>(&self, material:M) {
+        let world_data = &mut self.world.borrow_mut();
+        let symbol     = &mut world_data.workspace[self.symbol_id];
+        symbol.shader.set_material(material);
+    }
+
+    /// Moves all particles of this system to the provided layer. The layer has to be registered in
+    /// `SymbolRegistry::layers` first.
+    pub fn set_layer(&self, layer:&str) -> Result<(),MissingLayer> {
+        let world_data = &mut self.world.borrow_mut();
+        let layers     = &mut world_data.workspace.symbols.layers;
+        layers.set_layer(self.symbol_id,layer)
+    }
+
+    /// Sets the z-index of this system within its layer. Systems with higher z-indexes are drawn
+    /// on top of the ones with lower z-indexes.
+    pub fn set_z_index(&self, z_index:i32) {
+        let world_data = &mut self.world.borrow_mut();
+        let layers     = &mut world_data.workspace.symbols.layers;
+        layers.set_z_index(self.symbol_id,z_index);
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::debug::Stats;
+    use crate::system::gpu::context::mock::MockContext;
+
+    struct Fixture {
+        scope    : AttributeScope<MockContext>,
+        buffers  : ParticleBuffers<MockContext>,
+        registry : ParticleRegistry,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let context      = MockContext::new();
+            let stats        = Stats::default();
+            let logger       = Logger::new("test");
+            let symbol_scope = UniformScope::new(logger.sub("symbol_scope"),&context);
+            let mut scope    = AttributeScope::new(logger,&stats,&context,||{});
+            let buffers      = ParticleBuffers::new(&mut scope,&symbol_scope);
+            let registry     = default();
+            Self {scope,buffers,registry}
+        }
+
+        fn burst(&mut self, count:usize, lifetime:f32) {
+            let config = EmitterConfig {rate:0.0, lifetime, ..default()};
+            let id     = self.registry.emitters.insert(Emitter::new(config));
+            self.registry.emitters[id].burst(count);
+        }
+
+        /// Updates the particles and returns the slots of the spawned ones.
+        fn update(&mut self, time:f32) -> Vec<usize> {
+            let mut written = Vec::new();
+            self.registry.update(time,&mut self.scope,|id,_| written.push(*id));
+            written
+        }
+
+        fn live_slots(&self) -> Vec<usize> {
+            self.scope.instance_ids().into_iter().map(|id| *id).collect()
+        }
+    }
+
+    #[test]
+    fn slots_of_dead_particles_are_reused() {
+        let mut fixture = Fixture::new();
+        fixture.burst(3,1.0);
+        assert_eq!(fixture.update(0.0),vec![0,1,2]);
+        assert!(fixture.update(500.0).is_empty());
+        assert_eq!(fixture.live_slots(),vec![0,1,2]);
+        fixture.burst(2,1.0);
+        let respawned = fixture.update(1000.0);
+        assert_eq!(respawned,vec![2,1]);
+        assert_eq!(fixture.live_slots(),respawned);
+        assert_eq!(fixture.scope.capacity(),3);
+        assert_eq!(fixture.registry.alive.len(),2);
+        assert!(fixture.update(2000.0).is_empty());
+        assert_eq!(fixture.scope.size(),0);
+        assert!(fixture.registry.alive.is_empty());
+    }
+
+    #[test]
+    fn spawn_times_are_relative_to_the_epoch() {
+        let mut fixture = Fixture::new();
+        let lifetime    = 100.0;
+        let start       = 1_000_000.0;
+        fixture.burst(1,lifetime);
+        fixture.buffers.step(start,&mut fixture.scope,&mut fixture.registry);
+        let id = fixture.registry.alive[0].1;
+        assert_eq!(fixture.registry.epoch,start);
+        assert_eq!(fixture.buffers.spawn_time.at(id).get(),0.0);
+        assert_eq!(fixture.buffers.local_time.get(),0.0);
+
+        let time = start + EPOCH_PERIOD / 2.0;
+        fixture.buffers.step(time,&mut fixture.scope,&mut fixture.registry);
+        assert_eq!(fixture.registry.epoch,start);
+        assert_eq!(fixture.buffers.local_time.get(),EPOCH_PERIOD / 2.0);
+
+        let time = start + EPOCH_PERIOD;
+        fixture.burst(1,lifetime);
+        fixture.buffers.step(time,&mut fixture.scope,&mut fixture.registry);
+        let new_id = fixture.registry.alive[1].1;
+        assert_eq!(fixture.registry.epoch,time);
+        assert_eq!(fixture.buffers.local_time.get(),0.0);
+        assert_eq!(fixture.buffers.spawn_time.at(id).get(),-EPOCH_PERIOD);
+        assert_eq!(fixture.buffers.spawn_time.at(new_id).get(),0.0);
+    }
+}
@@ -0,0 +1,254 @@
+//! This module defines particle emitters. Emitters decide when particles are spawned and with
+//! which initial state. The motion of particles is fully determined by that state, so it can be
+//! integrated on the GPU, without updating the particles on the CPU every frame.
+
+use crate::prelude::*;
+
+use nalgebra::Vector3;
+use nalgebra::Vector4;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// The fractional part of the golden ratio. Multiples of it are evenly spread over the unit
+/// interval, which is used to spread the directions of particles without a random generator.
+const GOLDEN_RATIO_FRACT : f32 = 0.618_034;
+
+
+
+// ================
+// === Particle ===
+// ================
+
+/// The initial state of a particle. Times are in milliseconds, like the `time` uniform, while
+/// lifetimes, velocities and accelerations are in seconds.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Particle {
+    /// The position of the particle at the spawn time.
+    pub origin     : Vector3<f32>,
+    /// The velocity of the particle at the spawn time, in units per second.
+    pub velocity   : Vector3<f32>,
+    /// The constant acceleration of the particle, in units per second squared.
+    pub gravity    : Vector3<f32>,
+    /// The time of the spawn, in milliseconds.
+    pub spawn_time : f32,
+    /// The lifetime of the particle, in seconds.
+    pub lifetime   : f32,
+    /// The size of the particle.
+    pub size       : f32,
+    /// The color of the particle.
+    pub color      : Vector4<f32>,
+}
+
+impl Particle {
+    /// The position of the particle at the provided time in milliseconds. It mirrors the
+    /// integration done by the vertex shader of the `ParticleSystem`.
+    pub fn position_at(&self, time:f32) -> Vector3<f32> {
+        let age = (time - self.spawn_time) / 1000.0;
+        self.origin + self.velocity * age + self.gravity * (0.5 * age * age)
+    }
+
+    /// The time in milliseconds at which the particle dies.
+    pub fn expire_time(&self) -> f32 {
+        self.spawn_time + self.lifetime * 1000.0
+    }
+}
+
+
+
+// =====================
+// === EmitterConfig ===
+// =====================
+
+/// The configuration of an `Emitter`.
+#[derive(Clone,Debug,PartialEq)]
+pub struct EmitterConfig {
+    /// The number of particles spawned per second.
+    pub rate     : f32,
+    /// The lifetime of particles, in seconds.
+    pub lifetime : f32,
+    /// The initial velocity of particles, in units per second.
+    pub velocity : Vector3<f32>,
+    /// The angle in radians over which the velocities are spread around the `velocity`, in the
+    /// XY plane.
+    pub spread   : f32,
+    /// The constant acceleration of particles, in units per second squared.
+    pub gravity  : Vector3<f32>,
+    /// The size of particles.
+    pub size     : f32,
+    /// The color of particles.
+    pub color    : Vector4<f32>,
+}
+
+impl Default for EmitterConfig {
+    fn default() -> Self {
+        let rate     = 10.0;
+        let lifetime = 1.0;
+        let velocity = Vector3::new(0.0,100.0,0.0);
+        let spread   = 0.0;
+        let gravity  = Vector3::new(0.0,0.0,0.0);
+        let size     = 4.0;
+        let color    = Vector4::new(1.0,1.0,1.0,1.0);
+        Self {rate,lifetime,velocity,spread,gravity,size,color}
+    }
+}
+
+
+
+// ===============
+// === Emitter ===
+// ===============
+
+/// Spawns particles at a constant rate and in bursts. The spawn times of particles are spread
+/// evenly between the updates, so the streams of particles are smooth regardless of the frame
+/// rate.
+#[derive(Clone,Debug)]
+pub struct Emitter {
+    /// The configuration of the spawned particles.
+    pub config   : EmitterConfig,
+    /// The position at which particles are spawned.
+    pub position : Vector3<f32>,
+    /// Whether particles are spawned at the `rate`. Bursts are spawned even by inactive emitters.
+    pub active   : bool,
+    last_time    : Option<f32>,
+    pending      : f32,
+    burst        : usize,
+    emitted      : usize,
+}
+
+impl Emitter {
+    /// Constructor.
+    pub fn new(config:EmitterConfig) -> Self {
+        let position  = Vector3::new(0.0,0.0,0.0);
+        let active    = true;
+        let last_time = default();
+        let pending   = default();
+        let burst     = default();
+        let emitted   = default();
+        Self {config,position,active,last_time,pending,burst,emitted}
+    }
+
+    /// Requests a burst of particles, which are spawned during the next `emit` call.
+    pub fn burst(&mut self, count:usize) {
+        self.burst += count;
+    }
+
+    /// Spawns the particles due since the last call, up to the provided time in milliseconds.
+    /// The first call only spawns the requested bursts.
+    pub fn emit(&mut self, time:f32) -> Vec<Particle> {
+        let mut particles = Vec::new();
+        let elapsed       = self.last_time.map(|last| (time - last).max(0.0)).unwrap_or(0.0);
+        let rate          = self.config.rate;
+        if self.active && rate > 0.0 {
+            // Particles which would have already died, for example while the page was hidden,
+            // are not spawned at all.
+            let alive     = elapsed.min(self.config.lifetime * 1000.0);
+            let start     = time - alive;
+            let available = self.pending + rate * alive / 1000.0;
+            let count     = available.floor() as usize;
+            for i in 0..count {
+                let delay = ((i + 1) as f32 - self.pending) / rate * 1000.0;
+                particles.push(self.spawn(start + delay));
+            }
+            self.pending = available - count as f32;
+        }
+        for _ in 0..std::mem::take(&mut self.burst) {
+            particles.push(self.spawn(time));
+        }
+        self.last_time = Some(time);
+        particles
+    }
+
+    fn spawn(&mut self, spawn_time:f32) -> Particle {
+        let config     = &self.config;
+        let turn       = (self.emitted as f32 * GOLDEN_RATIO_FRACT).fract() - 0.5;
+        let velocity   = rotate_z(&config.velocity,turn * config.spread);
+        let origin     = self.position;
+        let gravity    = config.gravity;
+        let lifetime   = config.lifetime;
+        let size       = config.size;
+        let color      = config.color;
+        self.emitted  += 1;
+        Particle {origin,velocity,gravity,spawn_time,lifetime,size,color}
+    }
+}
+
+/// The vector rotated counterclockwise around the z-axis by the angle in radians.
+fn rotate_z(v:&Vector3<f32>, angle:f32) -> Vector3<f32> {
+    let (sin,cos) = angle.sin_cos();
+    Vector3::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos, v.z)
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a:f32, b:f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}",a,b);
+    }
+
+    #[test]
+    fn constant_rate() {
+        let rate        = 10.0;
+        let mut emitter = Emitter::new(EmitterConfig {rate,..default()});
+        assert_eq!(emitter.emit(1000.0).len(),0);
+        let particles = emitter.emit(1250.0);
+        let times     = particles.iter().map(|p| p.spawn_time).collect_vec();
+        assert_eq!(times,vec![1100.0,1200.0]);
+        let particles = emitter.emit(1310.0);
+        let times     = particles.iter().map(|p| p.spawn_time).collect_vec();
+        assert_eq!(times,vec![1300.0]);
+        assert_eq!(emitter.emit(1390.0).len(),0);
+        assert_eq!(emitter.emit(2340.0).len(),10);
+        assert_eq!(emitter.emit(100_000.0).len(),10);
+    }
+
+    #[test]
+    fn bursts_and_inactive_emitters() {
+        let mut emitter = Emitter::new(default());
+        emitter.active  = false;
+        emitter.emit(0.0);
+        emitter.burst(3);
+        let particles = emitter.emit(5000.0);
+        assert_eq!(particles.len(),3);
+        assert!(particles.iter().all(|p| p.spawn_time == 5000.0));
+        assert_eq!(emitter.emit(6000.0).len(),0);
+    }
+
+    #[test]
+    fn spread_velocities() {
+        let velocity    = Vector3::new(0.0,10.0,0.0);
+        let spread      = std::f32::consts::PI / 2.0;
+        let mut emitter = Emitter::new(EmitterConfig {velocity,spread,..default()});
+        emitter.burst(100);
+        for particle in emitter.emit(0.0) {
+            assert_close(particle.velocity.norm(),10.0);
+            assert!(particle.velocity.y >= 10.0 * (spread / 2.0).cos() - 1e-3);
+        }
+    }
+
+    #[test]
+    fn integration() {
+        let origin     = Vector3::new(1.0,2.0,0.0);
+        let velocity   = Vector3::new(10.0,0.0,0.0);
+        let gravity    = Vector3::new(0.0,-10.0,0.0);
+        let spawn_time = 1000.0;
+        let lifetime   = 2.0;
+        let size       = 1.0;
+        let color      = Vector4::new(1.0,1.0,1.0,1.0);
+        let particle   = Particle {origin,velocity,gravity,spawn_time,lifetime,size,color};
+        let position   = particle.position_at(3000.0);
+        assert_close(position.x,21.0);
+        assert_close(position.y,-18.0);
+        assert_close(particle.expire_time(),3000.0);
+    }
+}
@@ -4,7 +4,14 @@
 
 use crate::prelude::*;
 
+use crate::display::symbol::culling::CullingMode;
+use crate::display::symbol::layer::MissingLayer;
 use crate::display::symbol::material::Material;
+use crate::display::symbol::material::snippet;
+use crate::display::symbol::material::snippet::Composition;
+use crate::display::symbol::material::snippet::Snippet;
+use crate::display::symbol::Symbol;
+use crate::display::symbol::shader::variant::Variant;
 use crate::system::gpu::data::AttributeInstanceIndex;
 
 use crate::display::object::*;
@@ -102,7 +109,7 @@ impl Sprite {
 // === Private API ===
 
 impl Sprite {
-    fn new
+    pub(crate) fn new
     (sprite:SpriteRef, transform:Attribute<Matrix4<f32>>, bbox:Attribute<Vector2<f32>>) -> Self {
         let data = SpriteData::new(sprite,transform,bbox);
         let rc   = Rc::new(RefCell::new(data));
@@ -126,24 +133,45 @@ impl From<&Sprite> for DisplayObjectData {
 struct SpriteData {
     sprite_ref     : SpriteRef,
     display_object : DisplayObjectData,
-    _transform     : Attribute<Matrix4<f32>>,
+    transform      : Attribute<Matrix4<f32>>,
     bbox           : Attribute<Vector2<f32>>,
 }
 
 impl SpriteData {
     pub fn new
     ( sprite_ref:SpriteRef
-    , _transform:Attribute<Matrix4<f32>>
+    , transform:Attribute<Matrix4<f32>>
     , bbox:Attribute<Vector2<f32>>
     ) -> Self {
         let logger         = Logger::new(format!("Sprite{}",sprite_ref.instance_id));
         let display_object = DisplayObjectData::new(logger);
-        let transform_cp   = _transform.clone();
-        display_object.set_on_updated(move |t| {
-            transform_cp.set(t.matrix().clone());
-        });
         sprite_ref.symbol_ref.world.mod_stats(|stats| stats.inc_sprite_count());
-        Self {sprite_ref,display_object,_transform,bbox}
+        let this = Self {sprite_ref,display_object,transform,bbox};
+        this.bind_transform();
+        this
+    }
+
+    /// Moves the sprite to another instance, possibly of another symbol. The attributes are copied
+    /// to the new instance. The old instance has to be disposed by the caller.
+    fn rebind
+    ( &mut self
+    , sprite_ref:SpriteRef
+    , transform:Attribute<Matrix4<f32>>
+    , bbox:Attribute<Vector2<f32>>
+    ) {
+        transform.set(self.transform.get());
+        bbox.set(self.bbox.get());
+        self.sprite_ref = sprite_ref;
+        self.transform  = transform;
+        self.bbox       = bbox;
+        self.bind_transform();
+    }
+
+    fn bind_transform(&self) {
+        let transform = self.transform.clone();
+        self.display_object.set_on_updated(move |t| {
+            transform.set(t.matrix().clone());
+        });
     }
 }
 
@@ -166,7 +194,6 @@ impl Drop for SpriteData {
         let mut world = self.sprite_ref.symbol_ref.world.borrow_mut();
         let symbol    = &mut world.workspace[self.sprite_ref.symbol_ref.symbol_id];
         let mesh      = &mut symbol.surface;
-        self.bbox.set(Vector2::new(0.0,0.0));
         mesh.scopes.instance.dispose(self.sprite_ref.instance_id);
         self.display_object.unset_parent();
     }
@@ -175,39 +202,29 @@ impl Drop for SpriteData {
 
 
 // ====================
-// === SpriteSystem ===
+// === SpriteSymbol ===
 // ====================
 
-/// Creates a set of sprites. All sprites in the sprite system share the same material. Sprite
-/// system is a very efficient way to display geometry. Sprites are rendered as instances of the
-/// same mesh. Each sprite can be controlled by the instance and global attributes.
-pub struct SpriteSystem {
-    display_object : DisplayObjectData,
-    symbol_ref     : SymbolRef,
-    transform      : Buffer<Matrix4<f32>>,
-    _uv            : Buffer<Vector2<f32>>,
-    bbox           : Buffer<Vector2<f32>>,
+/// A symbol drawing sprites of a `SpriteSystem`, together with its instance buffers.
+#[derive(Clone,Debug)]
+struct SpriteSymbol {
+    symbol_id : SymbolId,
+    transform : Buffer<Matrix4<f32>>,
+    bbox      : Buffer<Vector2<f32>>,
 }
 
-impl SpriteSystem {
-    /// Constructor.
-    pub fn new(world:&World) -> Self {
-        let logger         = Logger::new("SpriteSystem");
-        let display_object = DisplayObjectData::new(logger);
-        let world_data     = &mut world.borrow_mut();
-        let workspace      = &mut world_data.workspace;
-        let symbol_id      = workspace.new_symbol();
-        let symbol         = &mut workspace[symbol_id];
-        let mesh           = &mut symbol.surface;
-        let uv             = mesh.scopes.point.add_buffer("uv");
-        let transform      = mesh.scopes.instance.add_buffer("transform");
-        let bbox           = mesh.scopes.instance.add_buffer("bounds");
-
-        let geometry_material = Self::geometry_material();
-        let material          = Self::material();
+impl SpriteSymbol {
+    /// Creates a new symbol drawing sprites with the provided material.
+    fn new(workspace:&mut Workspace, material:&Material) -> Self {
+        let symbol_id = workspace.new_symbol();
+        let symbol    = &mut workspace[symbol_id];
+        let mesh      = &mut symbol.surface;
+        let uv        = mesh.scopes.point.add_buffer("uv");
+        let transform = mesh.scopes.instance.add_buffer("transform");
+        let bbox      = mesh.scopes.instance.add_buffer("bounds");
 
-        symbol.shader.set_geometry_material (&geometry_material);
-        symbol.shader.set_material          (&material);
+        symbol.shader.set_geometry_material (&SpriteSystem::geometry_material());
+        symbol.shader.set_material          (material);
 
         let p1_index = mesh.scopes.point.add_instance();
         let p2_index = mesh.scopes.point.add_instance();
@@ -219,29 +236,90 @@ impl SpriteSystem {
         uv.at(p3_index).set(Vector2::new(1.0, 0.0));
         uv.at(p4_index).set(Vector2::new(1.0, 1.0));
 
-        world_data.stats.inc_sprite_system_count();
-
-        let world      = world.clone_ref();
-        let symbol_ref = SymbolRef::new(world,symbol_id);
-        Self {display_object,symbol_ref,transform,_uv:uv,bbox}
+        Self {symbol_id,transform,bbox}
     }
 
-    /// Creates a new sprite instance.
-    pub fn new_instance(&self) -> Sprite {
+    /// Creates a new instance and returns the attributes of the sprite drawn by it.
+    fn new_instance
+    (&self, world:&World) -> (SpriteRef,Attribute<Matrix4<f32>>,Attribute<Vector2<f32>>) {
         let instance_id = {
-            let world_data = &mut self.symbol_ref.world.borrow_mut();
-            let symbol     = &mut world_data.workspace[self.symbol_ref.symbol_id];
+            let world_data = &mut world.borrow_mut();
+            let symbol     = &mut world_data.workspace[self.symbol_id];
             symbol.surface.instance.add_instance()
         };
-        let transform    = self.transform.at(instance_id);
-        let bbox         = self.bbox.at(instance_id);
-        let sprite_ref   = SpriteRef::new(self.symbol_ref.clone(),instance_id);
+        let transform  = self.transform.at(instance_id);
+        let bbox       = self.bbox.at(instance_id);
+        let symbol_ref = SymbolRef::new(world.clone_ref(),self.symbol_id);
+        let sprite_ref = SpriteRef::new(symbol_ref,instance_id);
+        (sprite_ref,transform,bbox)
+    }
+}
+
+
+
+// ====================
+// === SpriteSystem ===
+// ====================
+
+/// Creates a set of sprites. All sprites in the sprite system share the same material. Sprite
+/// system is a very efficient way to display geometry. Sprites are rendered as instances of the
+/// same mesh. Each sprite can be controlled by the instance and global attributes.
+///
+/// Sprites are drawn in the layer of the system, unless they are moved to another layer with
+/// `set_sprite_layer`. The system draws the sprites of every such layer with a separate symbol,
+/// sharing the material and the settings of the system.
+pub struct SpriteSystem {
+    display_object : DisplayObjectData,
+    world          : World,
+    main           : SpriteSymbol,
+    layer_symbols  : RefCell<HashMap<String,SpriteSymbol>>,
+    material       : RefCell<Composition>,
+    logger         : Logger,
+}
+
+impl SpriteSystem {
+    /// Constructor.
+    pub fn new(world:&World) -> Self {
+        let logger         = Logger::new("SpriteSystem");
+        let display_object = DisplayObjectData::new(logger.clone());
+        let material       = Composition::new(Self::material());
+        let main           = {
+            let world_data = &mut world.borrow_mut();
+            world_data.stats.inc_sprite_system_count();
+            SpriteSymbol::new(&mut world_data.workspace,material.base())
+        };
+        let world         = world.clone_ref();
+        let layer_symbols = default();
+        let material      = RefCell::new(material);
+        Self {display_object,world,main,layer_symbols,material,logger}
+    }
+
+    /// Creates a new sprite instance.
+    pub fn new_instance(&self) -> Sprite {
+        let (sprite_ref,transform,bbox) = self.main.new_instance(&self.world);
         bbox.set(Vector2::new(1.0,1.0));
         let sprite = Sprite::new(sprite_ref,transform,bbox);
         self.add_child(&sprite);
         sprite
     }
 
+    /// Moves the sprite, created by this system, to the provided layer. Moving it back to the layer
+    /// of the system makes it drawn by the main symbol again. The layer has to be registered in
+    /// `SymbolRegistry::layers` first.
+    pub fn set_sprite_layer(&self, sprite:&Sprite, layer:&str) -> Result<(),MissingLayer> {
+        let target = self.layer_symbol(layer)?;
+        let data   = &mut *sprite.rc.borrow_mut();
+        let source = data.sprite_ref.symbol_ref.symbol_id;
+        if source != target.symbol_id {
+            let (sprite_ref,transform,bbox) = target.new_instance(&self.world);
+            let instance_id                 = data.sprite_ref.instance_id;
+            data.rebind(sprite_ref,transform,bbox);
+            let world_data = &mut self.world.borrow_mut();
+            world_data.workspace[source].surface.scopes.instance.dispose(instance_id);
+        }
+        Ok(())
+    }
+
     fn geometry_material() -> Material {
         let mut material = Material::new();
         material.add_input_def  :: <Vector2<f32>> ("bounds");
@@ -264,6 +342,61 @@ impl SpriteSystem {
     }
 }
 
+
+// === Private API ===
+
+impl SpriteSystem {
+    /// The symbol drawing the sprites of this system placed in the provided layer. The symbols of
+    /// layers are created when the first sprite is moved to them.
+    fn layer_symbol(&self, layer:&str) -> Result<SpriteSymbol,MissingLayer> {
+        let world_data = &mut self.world.borrow_mut();
+        let workspace  = &mut world_data.workspace;
+        let layers     = &workspace.symbols.layers;
+        if !layers.contains(layer) {
+            return Err(MissingLayer {name:layer.into()})
+        }
+        if layers.layer_of(self.main.symbol_id) == layer {
+            return Ok(self.main.clone())
+        }
+        let z_index    = layers.z_index_of(self.main.symbol_id);
+        let mut others = self.layer_symbols.borrow_mut();
+        if let Some(symbol) = others.get(layer) {
+            return Ok(symbol.clone())
+        }
+        let material  = self.material.borrow();
+        let material  = material.build().unwrap_or_else(|_| material.base().clone());
+        let symbol    = SpriteSymbol::new(workspace,&material);
+        let main      = &workspace[self.main.symbol_id];
+        let variant   = main.variant().clone();
+        let culling   = main.culling();
+        let sorting   = main.depth_sorting();
+        let new       = &mut workspace[symbol.symbol_id];
+        new.set_variant(variant);
+        new.set_culling(culling);
+        new.set_depth_sorting(sorting);
+        let layers    = &mut workspace.symbols.layers;
+        layers.set_layer(symbol.symbol_id,layer)?;
+        layers.set_z_index(symbol.symbol_id,z_index);
+        others.insert(layer.into(),symbol.clone());
+        Ok(symbol)
+    }
+
+    /// Ids of the main symbol and the symbols of all layers the sprites were moved to.
+    fn symbol_ids(&self) -> Vec<SymbolId> {
+        let others = self.layer_symbols.borrow();
+        let others = others.values().map(|symbol| symbol.symbol_id);
+        iter::once(self.main.symbol_id).chain(others).collect()
+    }
+
+    /// Modifies all the symbols of this system.
+    fn mod_symbols<F:FnMut(&mut Symbol)>(&self, mut f:F) {
+        let world_data = &mut self.world.borrow_mut();
+        for id in self.symbol_ids() {
+            f(&mut world_data.workspace[id]);
+        }
+    }
+}
+
 impl From<&SpriteSystem> for DisplayObjectData {
     fn from(t:&SpriteSystem) -> Self {
         t.display_object.clone_ref()
@@ -280,10 +413,76 @@ impl<'t> Modify<&'t DisplayObjectData> for &'t SpriteSystem {
 // === Setters ===
 
 impl SpriteSystem {
-    /// Sets the material for all sprites in this system.
-    pub fn set_material<M:Into<Material>>(&mut self, material:M) {
-        let world_data = &mut self.symbol_ref.world.borrow_mut();
-        let symbol     = &mut world_data.workspace[self.symbol_ref.symbol_id];
-        symbol.shader.set_material(material);
+    /// Sets the material for all sprites in this system. The snippets added with `add_snippet`
+    /// are stacked on top of the new material. In case they can not be composed with it, the error
+    /// is reported and the previous material is kept.
+    pub fn set_material<M:Into<Material>>(&self, material:M) {
+        let mut composition = self.material.borrow().clone();
+        composition.set_base(material);
+        if let Err(error) = self.set_composition(composition) {
+            self.logger.error(|| format!("Unable to set the material: {}",error));
+        }
+    }
+
+    /// Stacks a snippet on top of the material of this system. In case the snippet can not be
+    /// composed with the material and the previously added snippets, the material is not changed.
+    pub fn add_snippet(&self, snippet:Snippet) -> Result<(),snippet::Error> {
+        let mut composition = self.material.borrow().clone();
+        composition.add(snippet);
+        self.set_composition(composition)
+    }
+
+    /// Removes the snippet of the given name from the material of this system.
+    pub fn remove_snippet(&self, name:&str) -> Result<(),snippet::Error> {
+        let mut composition = self.material.borrow().clone();
+        composition.remove(name);
+        self.set_composition(composition)
+    }
+
+    fn set_composition(&self, composition:Composition) -> Result<(),snippet::Error> {
+        let material = composition.build()?;
+        self.mod_symbols(|symbol| symbol.shader.set_material(&material));
+        *self.material.borrow_mut() = composition;
+        Ok(())
+    }
+
+    /// Moves all sprites of this system, except the ones moved with `set_sprite_layer`, to the
+    /// provided layer. The layer has to be registered in `SymbolRegistry::layers` first. Moving
+    /// the sprites to the layer they already belong to does nothing.
+    pub fn set_layer(&self, layer:&str) -> Result<(),MissingLayer> {
+        let world_data = &mut self.world.borrow_mut();
+        let layers     = &mut world_data.workspace.symbols.layers;
+        if layers.layer_of(self.main.symbol_id) == layer {
+            return Ok(())
+        }
+        layers.set_layer(self.main.symbol_id,layer)
+    }
+
+    /// Sets the z-index of this system within its layer, and within the layers its sprites were
+    /// moved to. Systems with higher z-indexes are drawn on top of the ones with lower z-indexes.
+    pub fn set_z_index(&self, z_index:i32) {
+        let world_data = &mut self.world.borrow_mut();
+        let layers     = &mut world_data.workspace.symbols.layers;
+        for id in self.symbol_ids() {
+            layers.set_z_index(id,z_index);
+        }
+    }
+
+    /// Enables or disables sorting of sprites by their depth every frame. Enable it for systems
+    /// with transparent materials, so the sprites are blended back to front.
+    pub fn set_depth_sorting(&self, enabled:bool) {
+        self.mod_symbols(|symbol| symbol.set_depth_sorting(enabled));
+    }
+
+    /// Sets the culling strategy of this system. Use `CullingMode::Instances` for systems with many
+    /// sprites scattered over a large area, so only the sprites in the camera view are drawn.
+    pub fn set_culling(&self, culling:CullingMode) {
+        self.mod_symbols(|symbol| symbol.set_culling(culling));
+    }
+
+    /// Selects the variant of the shader of this system. The available features are declared by
+    /// the material, see `Material::add_flag` and `Material::add_enum_feature`.
+    pub fn set_variant(&self, variant:Variant) {
+        self.mod_symbols(|symbol| symbol.set_variant(variant.clone()));
     }
 }
@@ -7,6 +7,7 @@ use crate::control::callback::CallbackFn;
 use crate::data::dirty::traits::*;
 use crate::data::dirty;
 use crate::debug::stats::Stats;
+use crate::system::gpu::context::GpuContext;
 use crate::system::gpu::shader::Context;
 
 use num_enum::IntoPrimitive;
@@ -51,36 +52,36 @@ pub use types::*;
 /// definition overlapps the other one.
 #[derive(Debug,Shrinkwrap)]
 #[shrinkwrap(mutable)]
-pub struct Mesh {
+pub struct Mesh<C:GpuContext=Context> {
     /// Scope list.
     #[shrinkwrap(main_field)]
-    pub scopes   : Scopes,
+    pub scopes   : Scopes<C>,
     scopes_dirty : ScopesDirty,
     logger       : Logger,
-    context      : Context,
+    context      : C,
     stats        : Stats,
 }
 
 /// Container for all scopes owned by a mesh.
 #[derive(Debug)]
-pub struct Scopes {
+pub struct Scopes<C:GpuContext=Context> {
     /// Point Scope. A point is simply a point in space. Points are often assigned with such
     /// variables as 'position' or 'color'.
-    pub point: AttributeScope,
+    pub point: AttributeScope<C>,
 
     /// Vertex Scope. A vertex is a reference to a point. Primitives use vertices to reference
     /// points. For example, the corners of a polygon, the center of a sphere, or a control vertex
     /// of a spline curve. Primitives can share points, while vertices are unique to a primitive.
-    pub vertex: AttributeScope,
+    pub vertex: AttributeScope<C>,
 
     /// Primitive Scope. Primitives refer to a unit of geometry, lower-level than an object but
     /// above points. There are several different types of primitives, including polygon faces or
     /// Bezier/NURBS surfaces.
-    pub primitive: AttributeScope,
+    pub primitive: AttributeScope<C>,
 
     /// Instance Scope. Instances are virtual copies of the same geometry. They share point, vertex,
     /// and primitive variables.
-    pub instance: AttributeScope,
+    pub instance: AttributeScope<C>,
 }
 
 /// A singleton for each of scope types.
@@ -89,6 +90,12 @@ pub struct Scopes {
 #[repr(u8)]
 pub enum ScopeType {Point,Vertex,Primitive,Instance}
 
+impl ScopeType {
+    /// All scope types, in the order they are browsed by `Mesh::lookup_variable`.
+    pub const ALL : [ScopeType;4] =
+        [ScopeType::Point, ScopeType::Vertex, ScopeType::Primitive, ScopeType::Instance];
+}
+
 impl From<ScopeType> for usize {
     fn from(t: ScopeType) -> Self {
         Into::<u8>::into(t).into()
@@ -117,10 +124,10 @@ macro_rules! update_scopes {
     )*}
 }
 
-impl Mesh {
+impl<C:GpuContext> Mesh<C> {
     /// Creates new mesh with attached dirty callback.
     pub fn new<OnMut:CallbackFn>
-    (logger:Logger, stats:&Stats, context:&Context,on_mut:OnMut) -> Self {
+    (logger:Logger, stats:&Stats, context:&C,on_mut:OnMut) -> Self {
         stats.inc_mesh_count();
         let stats         = stats.clone();
         let scopes_logger = logger.sub("scopes_dirty");
@@ -152,6 +159,14 @@ impl Mesh {
         })
     }
 
+    /// Re-creates all buffers after the context was lost and restored.
+    pub fn restore_context(&mut self) {
+        self.scopes.point     . restore_context();
+        self.scopes.vertex    . restore_context();
+        self.scopes.primitive . restore_context();
+        self.scopes.instance  . restore_context();
+    }
+
     /// Browses all scopes and finds where a variable was defined. Scopes are browsed in a
     /// hierarchical order. To learn more about the ordering see the documentation of `Mesh`.
     pub fn lookup_variable<S:Str>(&self, name:S) -> Option<ScopeType> {
@@ -164,7 +179,7 @@ impl Mesh {
     }
 
     /// Gets reference to scope based on the scope type.
-    pub fn scope_by_type(&self, scope_type:ScopeType) -> &AttributeScope {
+    pub fn scope_by_type(&self, scope_type:ScopeType) -> &AttributeScope<C> {
         match scope_type {
             ScopeType::Point     => &self.scopes.point,
             ScopeType::Vertex    => &self.scopes.vertex,
@@ -174,7 +189,7 @@ impl Mesh {
     }
 }
 
-impl Drop for Mesh {
+impl<C:GpuContext> Drop for Mesh<C> {
     fn drop(&mut self) {
         self.stats.dec_mesh_count();
     }
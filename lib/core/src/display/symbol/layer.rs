@@ -0,0 +1,258 @@
+//! This module defines layers, named groups of symbols with an explicit drawing order. Layers are
+//! drawn in the ascending order of their `order` values. Symbols within a layer are drawn in the
+//! ascending order of their z-indexes, and symbols with equal z-indexes in the creation order.
+
+use crate::prelude::*;
+
+use crate::display::symbol::registry::SymbolId;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// Name of the layer every symbol belongs to by default. It cannot be removed.
+pub const DEFAULT_LAYER : &str = "main";
+
+
+
+// =============
+// === Error ===
+// =============
+
+/// Error raised when referring to a layer which does not exist.
+#[derive(Debug,Fail)]
+#[fail(display="Layer '{}' not found.",name)]
+pub struct MissingLayer {
+    /// The name of the missing layer.
+    pub name: String,
+}
+
+
+
+// =============
+// === Layer ===
+// =============
+
+/// A named group of symbols.
+#[derive(Clone,Debug)]
+pub struct Layer {
+    /// Name of the layer.
+    pub name  : String,
+    /// Layers with lower orders are drawn first. Layers with equal orders are drawn in the
+    /// creation order.
+    pub order : i32,
+}
+
+/// Placement of a single symbol.
+#[derive(Clone,Debug)]
+struct Placement {
+    layer   : String,
+    z_index : i32,
+}
+
+
+
+// ==============
+// === Layers ===
+// ==============
+
+/// Registry of layers and the placement of symbols within them. Symbols which were not placed
+/// explicitly belong to the `DEFAULT_LAYER` with the z-index of 0.
+#[derive(Clone,Debug)]
+pub struct Layers {
+    layers     : Vec<Layer>,
+    placements : HashMap<SymbolId,Placement>,
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        let layers     = vec![Layer {name:DEFAULT_LAYER.into(), order:0}];
+        let placements = default();
+        Self {layers,placements}
+    }
+}
+
+impl Layers {
+    /// Constructor. Creates only the default layer.
+    pub fn new() -> Self {
+        default()
+    }
+
+    /// Adds a new layer or changes the order of an existing one.
+    pub fn add<Name:Str>(&mut self, name:Name, order:i32) {
+        let name = name.as_ref();
+        match self.layers.iter_mut().find(|layer| layer.name == name) {
+            Some(layer) => layer.order = order,
+            None        => self.layers.push(Layer {name:name.into(),order})
+        }
+    }
+
+    /// Removes the layer. Its symbols are moved to the default layer. The default layer cannot be
+    /// removed.
+    pub fn remove(&mut self, name:&str) -> Result<Layer,MissingLayer> {
+        let index = self.index_of(name).filter(|_| name != DEFAULT_LAYER);
+        let index = index.ok_or_else(|| MissingLayer {name:name.into()})?;
+        for placement in self.placements.values_mut().filter(|p| p.layer == name) {
+            placement.layer = DEFAULT_LAYER.into();
+        }
+        Ok(self.layers.remove(index))
+    }
+
+    /// Gets the layer by its name.
+    pub fn get(&self, name:&str) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    /// Checks whether the layer exists.
+    pub fn contains(&self, name:&str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Names of all layers in the drawing order.
+    pub fn names(&self) -> Vec<&str> {
+        let mut layers = self.layers.iter().collect_vec();
+        layers.sort_by_key(|layer| layer.order);
+        layers.into_iter().map(|layer| layer.name.as_str()).collect()
+    }
+
+    /// Moves the symbol to the provided layer. It keeps its z-index.
+    pub fn set_layer(&mut self, symbol:SymbolId, layer:&str) -> Result<(),MissingLayer> {
+        if !self.contains(layer) {
+            return Err(MissingLayer {name:layer.into()})
+        }
+        self.placement_mut(symbol).layer = layer.into();
+        Ok(())
+    }
+
+    /// Sets the z-index of the symbol within its layer.
+    pub fn set_z_index(&mut self, symbol:SymbolId, z_index:i32) {
+        self.placement_mut(symbol).z_index = z_index;
+    }
+
+    /// Name of the layer the symbol belongs to.
+    pub fn layer_of(&self, symbol:SymbolId) -> &str {
+        self.placements.get(&symbol).map(|p| p.layer.as_str()).unwrap_or(DEFAULT_LAYER)
+    }
+
+    /// The z-index of the symbol within its layer.
+    pub fn z_index_of(&self, symbol:SymbolId) -> i32 {
+        self.placements.get(&symbol).map(|p| p.z_index).unwrap_or(0)
+    }
+
+    /// Forgets the placement of the symbol. Should be called when the symbol is removed.
+    pub fn forget(&mut self, symbol:SymbolId) {
+        self.placements.remove(&symbol);
+    }
+
+    /// Sorts the provided symbols in the drawing order.
+    pub fn sort<I:IntoIterator<Item=SymbolId>>(&self, symbols:I) -> Vec<SymbolId> {
+        let mut symbols = symbols.into_iter().map(|id| (self.sort_key(id),id)).collect_vec();
+        symbols.sort_by_key(|(key,_)| *key);
+        symbols.into_iter().map(|(_,id)| id).collect()
+    }
+
+    /// Sorts the provided symbols in the drawing order and keeps only the symbols belonging to the
+    /// provided layers.
+    pub fn sort_within<I:IntoIterator<Item=SymbolId>>
+    (&self, symbols:I, layers:&[String]) -> Vec<SymbolId> {
+        let symbols = symbols.into_iter();
+        let symbols = symbols.filter(|id| layers.iter().any(|l| l == self.layer_of(*id)));
+        self.sort(symbols)
+    }
+}
+
+
+// === Private API ===
+
+impl Layers {
+    fn index_of(&self, name:&str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+
+    fn placement_mut(&mut self, symbol:SymbolId) -> &mut Placement {
+        self.placements.entry(symbol).or_insert_with(|| {
+            let layer   = DEFAULT_LAYER.into();
+            let z_index = 0;
+            Placement {layer,z_index}
+        })
+    }
+
+    /// The key is `(layer order, layer creation index, z-index, symbol id)`. Removed layers are
+    /// treated as the default one.
+    fn sort_key(&self, symbol:SymbolId) -> (i32,usize,i32,SymbolId) {
+        let index = self.index_of(self.layer_of(symbol)).unwrap_or(0);
+        let order = self.layers[index].order;
+        (order,index,self.z_index_of(symbol),symbol)
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_order() {
+        let layers = Layers::new();
+        assert_eq!(layers.sort(vec![3,1,2]),vec![1,2,3]);
+    }
+
+    #[test]
+    fn layer_order() {
+        let mut layers = Layers::new();
+        layers.add("tooltips",10);
+        layers.add("background",-10);
+        layers.set_layer(1,"tooltips").unwrap();
+        layers.set_layer(3,"background").unwrap();
+        assert_eq!(layers.sort(vec![1,2,3]),vec![3,2,1]);
+        assert_eq!(layers.names(),vec!["background","main","tooltips"]);
+        layers.add("tooltips",-20);
+        assert_eq!(layers.sort(vec![1,2,3]),vec![1,3,2]);
+    }
+
+    #[test]
+    fn z_index_order() {
+        let mut layers = Layers::new();
+        layers.set_z_index(1,5);
+        layers.set_z_index(3,-1);
+        assert_eq!(layers.sort(vec![1,2,3]),vec![3,2,1]);
+    }
+
+    #[test]
+    fn layer_selection() {
+        let mut layers = Layers::new();
+        layers.add("overlay",1);
+        layers.set_layer(2,"overlay").unwrap();
+        assert_eq!(layers.sort_within(vec![1,2,3],&["overlay".into()]),vec![2]);
+        assert_eq!(layers.sort_within(vec![1,2,3],&[DEFAULT_LAYER.into()]),vec![1,3]);
+    }
+
+    #[test]
+    fn moving_to_the_same_layer_twice() {
+        let mut layers = Layers::new();
+        layers.add("overlay",1);
+        layers.set_layer(2,"overlay").unwrap();
+        layers.set_layer(2,"overlay").unwrap();
+        let all = vec!["overlay".into(),DEFAULT_LAYER.into()];
+        assert_eq!(layers.sort_within(vec![1,2,3],&all),vec![1,3,2]);
+        assert_eq!(layers.sort(vec![1,2,3]),vec![1,3,2]);
+    }
+
+    #[test]
+    fn missing_and_removed_layers() {
+        let mut layers = Layers::new();
+        assert!(layers.set_layer(1,"missing").is_err());
+        assert!(layers.remove(DEFAULT_LAYER).is_err());
+        layers.add("overlay",1);
+        layers.set_layer(1,"overlay").unwrap();
+        layers.remove("overlay").unwrap();
+        assert_eq!(layers.layer_of(1),DEFAULT_LAYER);
+    }
+}
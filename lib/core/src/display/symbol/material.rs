@@ -1,8 +1,11 @@
 //! This module defines `Material`, an abstraction for look and feel of a `Symbol`.
 
+pub mod snippet;
+
 use crate::prelude::*;
 
 use crate::display::symbol::shader::builder::CodeTemplete;
+use crate::display::symbol::shader::variant::Features;
 use crate::system::gpu::types::*;
 
 
@@ -17,18 +20,24 @@ use crate::system::gpu::types::*;
 #[derive(Clone,Debug)]
 pub struct VarDecl {
     /// The GLSL type of the variable.
-    pub tp : glsl::PrimType,
+    pub tp : glsl::Type,
 
     /// Default value of the variable used in case it was not bound to a real value. It's `None` in
     /// case the value does not have glsl representation (for now, only samplers are such
     /// variables).
     pub default : Option<Glsl>,
+
+    /// The declaration of the struct type of the variable. It's `None` for variables of built-in
+    /// types.
+    pub struct_decl : Option<glsl::StructDecl>,
 }
 
 impl VarDecl {
     /// Constructor.
-    pub fn new(tp:glsl::PrimType, default:Option<Glsl>) -> Self {
-        Self {tp,default}
+    pub fn new<T:Into<glsl::Type>>(tp:T, default:Option<Glsl>) -> Self {
+        let tp          = tp.into();
+        let struct_decl = None;
+        Self {tp,default,struct_decl}
     }
 }
 
@@ -39,6 +48,20 @@ From<T> for VarDecl {
     }
 }
 
+impl<T:PhantomInto<glsl::PrimType> + Into<Glsl> + Clone>
+From<UniformArray<T>> for VarDecl {
+    fn from(t:UniformArray<T>) -> Self {
+        Self::new(t.glsl_type(), Some(t.into()))
+    }
+}
+
+impl From<&UniformStruct> for VarDecl {
+    fn from(t:&UniformStruct) -> Self {
+        let struct_decl = Some(t.declaration());
+        Self {struct_decl, ..Self::new(t.glsl_type(), Some(t.into()))}
+    }
+}
+
 
 
 // ================
@@ -51,9 +74,10 @@ From<T> for VarDecl {
 #[shrinkwrap(unsafe_ignore_visibility)]
 pub struct Material {
     #[shrinkwrap(main_field)]
-    code    : CodeTemplete,
-    inputs  : BTreeMap<String,VarDecl>,
-    outputs : BTreeMap<String,VarDecl>,
+    code     : CodeTemplete,
+    inputs   : BTreeMap<String,VarDecl>,
+    outputs  : BTreeMap<String,VarDecl>,
+    features : Features,
 }
 
 /// Bounds for the material inputs.
@@ -65,8 +89,9 @@ impl Material {
         default()
     }
 
-    /// Adds a new input variable.
-    pub fn add_input<T:Input>(&mut self, name:&str, t:T) {
+    /// Adds a new input variable. Besides primitive values, inputs can be arrays (`UniformArray`)
+    /// and structs (`&UniformStruct`).
+    pub fn add_input<T:Into<VarDecl>>(&mut self, name:&str, t:T) {
         self.inputs.insert(name.into(),t.into());
     }
 
@@ -84,6 +109,18 @@ impl Material {
     pub fn add_output_def<T:Input>(&mut self, name:&str) {
         self.outputs.insert(name.into(),<T>::gpu_default().into());
     }
+
+    /// Declares a compile-time feature which can be enabled or disabled by selecting a shader
+    /// variant. The code can test it with `#ifdef`, see `shader::variant` to learn more.
+    pub fn add_flag(&mut self, name:&str) {
+        self.features.add_flag(name);
+    }
+
+    /// Declares a compile-time feature with one of several options, selected by a shader variant.
+    /// The first option is the default one.
+    pub fn add_enum_feature(&mut self, name:&str, options:&[&str]) {
+        self.features.add_enum(name,options.to_vec());
+    }
 }
 
 impl From<&Material> for Material {
@@ -110,6 +147,11 @@ impl Material {
     pub fn outputs(&self) -> &BTreeMap<String,VarDecl> {
         &self.outputs
     }
+
+    /// Gets all declared compile-time features.
+    pub fn features(&self) -> &Features {
+        &self.features
+    }
 }
 
 
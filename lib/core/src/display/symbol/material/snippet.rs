@@ -0,0 +1,416 @@
+//! This module defines material snippets, reusable pieces of materials, and their composition.
+//! Effects like tinting or highlighting are defined once as snippets and can be stacked on top of
+//! any material.
+
+use crate::prelude::*;
+
+use crate::display::symbol::material::Input;
+use crate::display::symbol::material::Material;
+use crate::display::symbol::material::VarDecl;
+use crate::system::gpu::shader::glsl;
+
+use nalgebra::Vector4;
+
+
+
+// =============
+// === Error ===
+// =============
+
+/// Errors of material composition.
+#[derive(Clone,Debug,Fail,PartialEq)]
+#[allow(missing_docs)]
+pub enum Error {
+    #[fail(display="Snippet `{}` was added more than once.",name)]
+    DuplicateSnippet {name:String},
+    #[fail(display="Snippet `{}` depends on `{}`, which was not added.",snippet,dependency)]
+    MissingDependency {snippet:String, dependency:String},
+    #[fail(display="Snippets form a dependency cycle: {}.",snippets)]
+    DependencyCycle {snippets:String},
+    #[fail(display="Input `{}` of snippet `{}` is {}, but it was already declared as {}.",
+        name,snippet,found,expected)]
+    InputClash {name:String, snippet:String, expected:glsl::Type, found:glsl::Type},
+    #[fail(display="Output `{}` of snippet `{}` is already defined.",name,snippet)]
+    OutputClash {name:String, snippet:String},
+    #[fail(display="Helper `{}` of snippet `{}` differs from its previous definition.",
+        name,snippet)]
+    HelperClash {name:String, snippet:String},
+}
+
+
+
+// ===============
+// === Snippet ===
+// ===============
+
+/// A named GLSL definition placed before the `main` function, like a helper function or a
+/// constant. Definitions with the same name are emitted only once per material.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Helper {
+    name : String,
+    code : String,
+}
+
+/// A reusable piece of a material. It declares its own inputs, outputs, helper definitions and a
+/// fragment of the `main` function. Snippets can depend on other snippets, which means that their
+/// main fragments are placed after the main fragments of their dependencies.
+#[derive(Clone,Debug,Default)]
+pub struct Snippet {
+    name         : String,
+    dependencies : Vec<String>,
+    inputs       : BTreeMap<String,VarDecl>,
+    outputs      : BTreeMap<String,VarDecl>,
+    helpers      : Vec<Helper>,
+    main         : String,
+}
+
+impl Snippet {
+    /// Constructor.
+    pub fn new<Name:Str>(name:Name) -> Self {
+        let name = name.into();
+        Self {name,..default()}
+    }
+
+    /// The name of the snippet.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Declares that the snippet has to be placed after the snippet of the given name.
+    pub fn add_dependency<Name:Str>(&mut self, name:Name) {
+        self.dependencies.push(name.into());
+    }
+
+    /// Adds a new input variable.
+    pub fn add_input<T:Into<VarDecl>>(&mut self, name:&str, t:T) {
+        self.inputs.insert(name.into(),t.into());
+    }
+
+    /// Adds a new input variable with the default value of its type.
+    pub fn add_input_def<T:Input>(&mut self, name:&str) {
+        self.inputs.insert(name.into(),<T>::gpu_default().into());
+    }
+
+    /// Adds a new output variable.
+    pub fn add_output<T:Input>(&mut self, name:&str, t:T) {
+        self.outputs.insert(name.into(),t.into());
+    }
+
+    /// Adds a new output variable with the default value of its type.
+    pub fn add_output_def<T:Input>(&mut self, name:&str) {
+        self.outputs.insert(name.into(),<T>::gpu_default().into());
+    }
+
+    /// Adds a named helper definition.
+    pub fn add_helper<Name:Str,Code:Str>(&mut self, name:Name, code:Code) {
+        let name = name.into();
+        let code = code.into();
+        self.helpers.push(Helper {name,code});
+    }
+
+    /// Sets the fragment of the `main` function. It is placed in its own block, so its local
+    /// variables do not clash with the ones of other snippets.
+    pub fn set_main<Code:Str>(&mut self, code:Code) {
+        self.main = code.into();
+    }
+}
+
+
+// === Predefined Snippets ===
+
+impl Snippet {
+    /// Multiplies the output color by the `tint` input.
+    pub fn tint() -> Self {
+        let mut snippet = Self::new("tint");
+        snippet.add_input("tint",Vector4::new(1.0,1.0,1.0,1.0));
+        snippet.set_main("output_color *= input_tint;");
+        snippet
+    }
+
+    /// Darkens the output color by the `dim` input, ranging from 0 (no change) to 1 (black).
+    pub fn dim() -> Self {
+        let mut snippet = Self::new("dim");
+        snippet.add_input("dim",0.0);
+        snippet.add_helper("mix_rgb",MIX_RGB);
+        snippet.set_main("output_color = mix_rgb(output_color,vec3(0.0),input_dim);");
+        snippet
+    }
+
+    /// Lightens the output color by the `highlight` input, ranging from 0 (no change) to 1 (white).
+    pub fn highlight() -> Self {
+        let mut snippet = Self::new("highlight");
+        snippet.add_input("highlight",0.0);
+        snippet.add_helper("mix_rgb",MIX_RGB);
+        snippet.set_main("output_color = mix_rgb(output_color,vec3(1.0),input_highlight);");
+        snippet
+    }
+}
+
+const MIX_RGB : &str = "
+vec4 mix_rgb(vec4 color, vec3 target, float amount) {
+    return vec4(mix(color.rgb,target,amount),color.a);
+}";
+
+
+
+// ===================
+// === Composition ===
+// ===================
+
+/// A material composed of a base material and a stack of snippets. Snippets are applied in the
+/// order they were added, unless their dependencies require otherwise. Inputs of the same name and
+/// type are shared between snippets and the base material, while outputs can be defined only
+/// once.
+#[derive(Clone,Debug,Default)]
+pub struct Composition {
+    base     : Material,
+    snippets : Vec<Snippet>,
+}
+
+impl Composition {
+    /// Constructor.
+    pub fn new<M:Into<Material>>(base:M) -> Self {
+        let base     = base.into();
+        let snippets = default();
+        Self {base,snippets}
+    }
+
+    /// The base material.
+    pub fn base(&self) -> &Material {
+        &self.base
+    }
+
+    /// Replaces the base material, keeping all the snippets.
+    pub fn set_base<M:Into<Material>>(&mut self, base:M) {
+        self.base = base.into();
+    }
+
+    /// All the snippets in the order they were added.
+    pub fn snippets(&self) -> &[Snippet] {
+        &self.snippets
+    }
+
+    /// Adds a new snippet on top of the stack.
+    pub fn add(&mut self, snippet:Snippet) {
+        self.snippets.push(snippet);
+    }
+
+    /// Removes the snippet of the given name, if it exists.
+    pub fn remove(&mut self, name:&str) -> Option<Snippet> {
+        let index = self.snippets.iter().position(|snippet| snippet.name == name)?;
+        Some(self.snippets.remove(index))
+    }
+
+    /// Builds the final material.
+    pub fn build(&self) -> Result<Material,Error> {
+        let mut material = self.base.clone();
+        let mut helpers  = HashMap::<&str,&str>::new();
+        let mut before   = material.code.before_main().clone();
+        let mut main     = material.code.main().clone();
+        for snippet in self.sorted_snippets()? {
+            for (name,decl) in &snippet.inputs {
+                match material.inputs.get(name) {
+                    None => {
+                        material.inputs.insert(name.clone(),decl.clone());
+                    }
+                    Some(prev) => if prev.tp != decl.tp {
+                        let name     = name.clone();
+                        let snippet  = snippet.name.clone();
+                        let expected = prev.tp.clone();
+                        let found    = decl.tp.clone();
+                        return Err(Error::InputClash {name,snippet,expected,found})
+                    }
+                }
+            }
+            for (name,decl) in &snippet.outputs {
+                if material.outputs.contains_key(name) {
+                    let name    = name.clone();
+                    let snippet = snippet.name.clone();
+                    return Err(Error::OutputClash {name,snippet})
+                }
+                material.outputs.insert(name.clone(),decl.clone());
+            }
+            for helper in &snippet.helpers {
+                match helpers.get(helper.name.as_str()) {
+                    None => {
+                        helpers.insert(&helper.name,&helper.code);
+                        before.push_str(&format!("\n{}\n",helper.code));
+                    }
+                    Some(code) => if *code != helper.code {
+                        let name    = helper.name.clone();
+                        let snippet = snippet.name.clone();
+                        return Err(Error::HelperClash {name,snippet})
+                    }
+                }
+            }
+            main.push_str(&format!("\n// Snippet `{}`.\n{{\n{}\n}}\n",snippet.name,snippet.main));
+        }
+        material.code.set_before_main(before);
+        material.code.set_main(main);
+        Ok(material)
+    }
+
+    /// The snippets ordered by their dependencies. Independent snippets keep the order in which
+    /// they were added.
+    fn sorted_snippets(&self) -> Result<Vec<&Snippet>,Error> {
+        let mut sorter = DependencySorter::default();
+        for snippet in &self.snippets {
+            if sorter.by_name.insert(&snippet.name,snippet).is_some() {
+                return Err(Error::DuplicateSnippet {name:snippet.name.clone()})
+            }
+        }
+        for snippet in &self.snippets {
+            sorter.visit(snippet)?;
+        }
+        Ok(sorter.sorted)
+    }
+}
+
+impl TryFrom<&Composition> for Material {
+    type Error = Error;
+    fn try_from(t:&Composition) -> Result<Self,Self::Error> {
+        t.build()
+    }
+}
+
+
+// === DependencySorter ===
+
+/// Depth-first topological sort of snippets.
+#[derive(Debug,Default)]
+struct DependencySorter<'a> {
+    by_name : HashMap<&'a str,&'a Snippet>,
+    visited : HashSet<&'a str>,
+    path    : Vec<&'a str>,
+    sorted  : Vec<&'a Snippet>,
+}
+
+impl<'a> DependencySorter<'a> {
+    fn visit(&mut self, snippet:&'a Snippet) -> Result<(),Error> {
+        let name = snippet.name.as_str();
+        if self.visited.contains(name) {
+            return Ok(())
+        }
+        if let Some(index) = self.path.iter().position(|t| *t == name) {
+            let snippets = self.path[index..].iter().chain(iter::once(&name)).join(" -> ");
+            return Err(Error::DependencyCycle {snippets})
+        }
+        self.path.push(name);
+        for dependency in &snippet.dependencies {
+            let target = self.by_name.get(dependency.as_str()).copied().ok_or_else(|| {
+                let snippet    = name.to_string();
+                let dependency = dependency.clone();
+                Error::MissingDependency {snippet,dependency}
+            })?;
+            self.visit(target)?;
+        }
+        self.path.pop();
+        self.visited.insert(name);
+        self.sorted.push(snippet);
+        Ok(())
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippet(name:&str, dependencies:&[&str]) -> Snippet {
+        let mut snippet = Snippet::new(name);
+        dependencies.iter().for_each(|dependency| snippet.add_dependency(*dependency));
+        snippet.set_main(format!("{}();",name));
+        snippet
+    }
+
+    fn names(composition:&Composition) -> Vec<String> {
+        composition.sorted_snippets().unwrap().iter().map(|t| t.name.clone()).collect()
+    }
+
+    #[test]
+    fn snippets_are_ordered_by_dependencies() {
+        let mut composition = Composition::default();
+        composition.add(snippet("a",&["c"]));
+        composition.add(snippet("b",&[]));
+        composition.add(snippet("c",&["b"]));
+        composition.add(snippet("d",&[]));
+        assert_eq!(names(&composition),vec!["b","c","a","d"]);
+    }
+
+    #[test]
+    fn dependency_errors() {
+        let mut composition = Composition::default();
+        composition.add(snippet("a",&["x"]));
+        let error = Error::MissingDependency {snippet:"a".into(), dependency:"x".into()};
+        assert_eq!(composition.build().unwrap_err(),error);
+
+        let mut composition = Composition::default();
+        composition.add(snippet("a",&["b"]));
+        composition.add(snippet("b",&["a"]));
+        let error = Error::DependencyCycle {snippets:"a -> b -> a".into()};
+        assert_eq!(composition.build().unwrap_err(),error);
+
+        let mut composition = Composition::default();
+        composition.add(snippet("a",&[]));
+        composition.add(snippet("a",&[]));
+        let error = Error::DuplicateSnippet {name:"a".into()};
+        assert_eq!(composition.build().unwrap_err(),error);
+    }
+
+    #[test]
+    fn helpers_are_deduplicated() {
+        let mut composition = Composition::default();
+        composition.add(Snippet::dim());
+        composition.add(Snippet::highlight());
+        let material = composition.build().unwrap();
+        assert_eq!(material.code().before_main().matches("vec4 mix_rgb").count(),1);
+        assert_eq!(material.inputs().keys().collect_vec(),vec!["dim","highlight"]);
+
+        let mut clashing = Snippet::new("clashing");
+        clashing.add_helper("mix_rgb","vec4 mix_rgb() {}");
+        composition.add(clashing);
+        let error = Error::HelperClash {name:"mix_rgb".into(), snippet:"clashing".into()};
+        assert_eq!(composition.build().unwrap_err(),error);
+    }
+
+    #[test]
+    fn inputs_are_shared_and_outputs_are_unique() {
+        let mut base = Material::new();
+        base.add_input("tint",1.0);
+        base.add_output("local",1.0);
+        let mut composition = Composition::new(base);
+        composition.add(Snippet::tint());
+        let error = Error::InputClash
+            { name     : "tint".into()
+            , snippet  : "tint".into()
+            , expected : glsl::PrimType::Float.into()
+            , found    : glsl::PrimType::Vec4.into()
+            };
+        assert_eq!(composition.build().unwrap_err(),error);
+
+        composition.remove("tint");
+        let mut shared = Snippet::new("shared");
+        shared.add_input("tint",0.5);
+        shared.add_output("local",0.0);
+        composition.add(shared);
+        let error = Error::OutputClash {name:"local".into(), snippet:"shared".into()};
+        assert_eq!(composition.build().unwrap_err(),error);
+    }
+
+    #[test]
+    fn main_fragments_are_stacked_in_blocks() {
+        let mut base = Material::new();
+        base.set_main("output_color = vec4(1.0);");
+        let mut composition = Composition::new(base);
+        composition.add(Snippet::tint());
+        let material = composition.build().unwrap();
+        let expected = "output_color = vec4(1.0);\n// Snippet `tint`.\n{\noutput_color *= \
+                        input_tint;\n}\n";
+        assert_eq!(material.code().main(),expected);
+    }
+}
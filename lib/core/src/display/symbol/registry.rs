@@ -8,9 +8,12 @@ use crate::data::dirty;
 use crate::debug::stats::Stats;
 use crate::display::camera::Camera2d;
 use crate::display::symbol::Symbol;
+use crate::display::symbol::layer::Layers;
 use crate::system::gpu::data::uniform::Uniform;
 use crate::system::gpu::data::uniform::UniformScope;
 use crate::system::gpu::shader::Context;
+use crate::system::gpu::shader::cache::ProgramCache;
+use crate::system::gpu::shader::sources::ShaderSources;
 
 use data::opt_vec::OptVec;
 use nalgebra::Matrix4;
@@ -30,9 +33,13 @@ pub struct SymbolRegistry {
     pub symbol_dirty    : SymbolDirty,
     pub logger          : Logger,
     pub view_projection : Uniform<Matrix4<f32>>,
+    pub layers          : Layers,
+    pub programs        : ProgramCache,
+    pub sources         : ShaderSources,
     variables           : UniformScope,
     context             : Context,
     stats               : Stats,
+    sources_version     : usize,
 }
 
 
@@ -62,9 +69,14 @@ impl SymbolRegistry {
         let symbols         = default();
         let variables       = variables.clone();
         let view_projection = variables.add_or_panic("view_projection", Matrix4::<f32>::identity());
+        let layers          = default();
+        let programs        = ProgramCache::new(logger.sub("program_cache"),stats,context);
+        let sources         = ShaderSources::new();
+        let sources_version = sources.version();
         let context         = context.clone();
         let stats           = stats.clone_ref();
-        Self {symbols,symbol_dirty,logger,view_projection,variables,context,stats}
+        Self {symbols,symbol_dirty,logger,view_projection,layers,programs,sources,variables,context
+             ,stats,sources_version}
     }
 
     /// Creates a new `Symbol` instance.
@@ -74,34 +86,130 @@ impl SymbolRegistry {
         let logger       = &self.logger;
         let context      = &self.context;
         let stats        = &self.stats;
+        let programs     = &self.programs;
+        let sources      = &self.sources;
         self.symbols.insert_with_ix(|ix| {
             let on_mut = move || {symbol_dirty.set(ix)};
             let logger = logger.sub(format!("symbol{}",ix));
-            Symbol::new(variables,logger,stats,context,on_mut)
+            Symbol::new(variables,logger,stats,context,programs,sources,on_mut)
         })
     }
 
-    /// Check dirty flags and update the state accordingly.
+    /// Removes the symbol and forgets its placement in layers. Its id may be reused by the symbols
+    /// created later, so all references to the symbol have to be dropped first.
+    pub fn remove_symbol(&mut self, id:SymbolId) -> Option<Symbol> {
+        self.symbol_dirty.unset(&id);
+        self.layers.forget(id);
+        self.symbols.remove(id)
+    }
+
+    /// Check dirty flags and update the state accordingly. Symbols which programs are still being
+    /// compiled are marked dirty again, so they are updated in the next frame. Shaders including
+    /// the shader sources changed since the last update are generated again.
     pub fn update(&mut self) {
         group!(self.logger, "Updating.", {
+            self.reload_changed_sources();
+            let mut compiling = Vec::new();
             for mesh_id in self.symbol_dirty.take().iter() {
-                self.symbols[*mesh_id].update()
+                if let Some(Some(symbol)) = self.symbols.items.get_mut(*mesh_id) {
+                    symbol.update();
+                    if symbol.is_compiling() {
+                        compiling.push(*mesh_id);
+                    }
+                }
             }
             self.symbol_dirty.unset_all();
+            self.programs.poll();
+            for mesh_id in compiling {
+                self.symbol_dirty.set(mesh_id);
+            }
         })
     }
 
-    pub fn render(&self, camera:&Camera2d) {
+    /// Marks the shaders including the changed shader sources dirty.
+    fn reload_changed_sources(&mut self) {
+        let version = self.sources.version();
+        if version != self.sources_version {
+            self.sources_version = version;
+            for symbol in self.symbols.iter_mut() {
+                symbol.shader.reload_changed_sources();
+            }
+        }
+    }
+
+    /// Re-creates GPU resources of all symbols after the context was lost and restored. Cached
+    /// programs are forgotten, so they are compiled again during the next update.
+    pub fn restore_context(&mut self) {
+        group!(self.logger, "Restoring the context.", {
+            self.programs.restore_context();
+            for symbol in self.symbols.iter_mut() {
+                symbol.restore_context()
+            }
+        })
+    }
+
+    /// Updates the camera and the `view_projection` uniform if the camera has changed.
+    pub fn update_camera(&self, camera:&Camera2d) {
         let changed = camera.update();
         if changed {
             self.view_projection.set(camera.view_projection_matrix());
         }
+    }
+
+    /// Updates the camera and renders all the symbols.
+    pub fn render(&self, camera:&Camera2d) {
+        self.update_camera(camera);
+        self.render_all();
+    }
+
+    /// Culls and sorts instances of all symbols, according to their settings. It should be called
+    /// after the camera was updated, but before the symbols are updated.
+    pub fn prepare_instances(&mut self, camera:&Camera2d) {
+        let view_projection = camera.view_projection_matrix();
+        for symbol in self.symbols.iter_mut() {
+            let culled = symbol.prepare_instances(&view_projection);
+            self.stats.mod_culled_instance_count(|count| count + culled);
+        }
+    }
+
+    /// Renders all the symbols in the layer order.
+    pub fn render_all(&self) {
         group!(self.logger, "Rendering.", {
-            for symbol in &self.symbols {
-                symbol.render();
+            for id in self.sorted_ids() {
+                self.symbols[id].render();
+            }
+        })
+    }
+
+    /// Renders symbols belonging to the provided layers in the layer order.
+    pub fn render_layers(&self, layers:&[String]) {
+        group!(self.logger, "Rendering selected layers.", {
+            for id in self.layers.sort_within(self.ids(),layers) {
+                self.symbols[id].render();
             }
         })
     }
+
+    /// Renders the provided symbols in the provided order. Ids of removed symbols are skipped.
+    pub fn render_by_ids(&self, ids:&[SymbolId]) {
+        group!(self.logger, "Rendering selected symbols.", {
+            for id in ids {
+                if let Some(Some(symbol)) = self.symbols.items.get(*id) {
+                    symbol.render();
+                }
+            }
+        })
+    }
+
+    /// Ids of all the symbols in the layer order.
+    pub fn sorted_ids(&self) -> Vec<SymbolId> {
+        self.layers.sort(self.ids())
+    }
+
+    /// Iterator over ids of all the symbols in the registry order.
+    pub fn ids(&self) -> impl Iterator<Item=SymbolId> + '_ {
+        self.symbols.items.iter().enumerate().filter_map(|(ix,t)| t.as_ref().map(|_| ix))
+    }
 }
 
 impl Index<usize> for SymbolRegistry {
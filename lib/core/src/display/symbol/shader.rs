@@ -2,6 +2,8 @@
 
 #[warn(missing_docs)]
 pub mod builder;
+#[warn(missing_docs)]
+pub mod variant;
 
 use crate::prelude::*;
 
@@ -12,12 +14,18 @@ use crate::display::symbol::material::Material;
 use crate::display::symbol::material::VarDecl;
 use crate::display::symbol::ScopeType;
 use crate::display::symbol::shader;
+use crate::display::symbol::shader::variant::Features;
+use crate::display::symbol::shader::variant::Variant;
+use crate::system::gpu::context::GpuContext;
+use crate::system::gpu::data::uniform::block::UniformBlock;
 use crate::system::gpu::shader::*;
-use crate::system::gpu::shader::Context;
+use crate::system::gpu::shader::cache::CachedProgram;
+use crate::system::gpu::shader::cache::ProgramCache;
+use crate::system::gpu::shader::cache::ProgramKey;
+use crate::system::gpu::shader::cache::ProgramStatus;
+use crate::system::gpu::shader::sources::ShaderSources;
 use crate::control::callback::CallbackFn;
 
-use web_sys::WebGlProgram;
-
 
 
 // ==================
@@ -40,22 +48,63 @@ impl VarBinding {
 
 
 
+// ===================
+// === BindingsKey ===
+// ===================
+
+/// The part of the bindings the generated sources depend on, in addition to the materials: the
+/// scopes the inputs are bound to and the members of the globals block.
+#[derive(Clone,Debug,Default,PartialEq)]
+struct BindingsKey {
+    scopes  : Vec<(String,Option<ScopeType>)>,
+    globals : Option<Vec<(String,glsl::Type)>>,
+}
+
+impl BindingsKey {
+    fn new<C:GpuContext>(bindings:&[VarBinding], globals:Option<&UniformBlock<C>>) -> Self {
+        let scopes  = bindings.iter().map(|binding| (binding.name.clone(),binding.scope)).collect();
+        let globals = globals.map(|block| block.members());
+        Self {scopes,globals}
+    }
+}
+
+
+
 // ==============
 // === Shader ===
 // ==============
 
 // === Definition ===
 
-/// Shader keeps track of a shader and related WebGL Program.
+/// Shader keeps track of a shader and related WebGL Program. Programs are taken from the shared
+/// `ProgramCache`, so shaders generating identical code use the same program. Programs are compiled
+/// asynchronously, so the shader needs to be updated until it stops compiling.
+///
+/// Materials can declare compile-time features. The selected `Variant` of the features is emitted
+/// as `#define`s. Sources of every used variant are cached by their `#define`s until the materials
+/// or the bindings of their inputs change, so switching back to a previously used variant does not
+/// generate the code again. Only the used variants are compiled.
+///
+/// Materials can include sources registered in `ShaderSources`. When any of them changes, for
+/// example because it was reloaded in the development mode, the shader is generated again. The
+/// last good program is used until the new one is compiled, and it is kept if the compilation
+/// fails.
 #[derive(Derivative)]
 #[derivative(Debug(bound=""))]
-pub struct Shader {
+pub struct Shader<C:GpuContext=Context> {
     geometry_material : Material,
     surface_material  : Material,
-    program           : Option<WebGlProgram>,
+    variant           : Variant,
+    variants          : HashMap<Vec<glsl::Define>,ProgramKey>,
+    bindings          : BindingsKey,
+    program           : ProgramStatus<C>,
+    sources           : Option<ProgramKey>,
+    programs          : ProgramCache<C>,
+    source_registry   : ShaderSources,
+    includes          : BTreeMap<String,usize>,
+    fallback          : Option<CachedProgram<C>>,
     dirty             : Dirty,
     logger            : Logger,
-    context           : Context,
     stats             : Stats,
 }
 
@@ -66,73 +115,196 @@ pub type Dirty = dirty::SharedBool<Box<dyn Fn()>>;
 
 // === Implementation ===
 
-impl Shader {
+impl<C:GpuContext> Shader<C> {
 
     /// Creates new shader with attached callback.
-    pub fn new<OnMut:CallbackFn>(logger:Logger, stats:&Stats, context:&Context, on_mut:OnMut) -> Self {
+    pub fn new<OnMut:CallbackFn>
+    ( logger          : Logger
+    , stats           : &Stats
+    , programs        : &ProgramCache<C>
+    , source_registry : &ShaderSources
+    , on_mut          : OnMut
+    ) -> Self {
         stats.inc_shader_count();
         let geometry_material = default();
         let surface_material  = default();
+        let variant           = default();
+        let variants          = default();
+        let bindings          = default();
         let program           = default();
+        let sources           = default();
+        let programs          = programs.clone_ref();
+        let source_registry   = source_registry.clone_ref();
+        let includes          = default();
+        let fallback          = default();
         let dirty_logger      = logger.sub("dirty");
         let dirty             = Dirty::new(dirty_logger,Box::new(on_mut));
-        let context           = context.clone();
         let stats             = stats.clone_ref();
         dirty.set();
-        Self {geometry_material,surface_material,program,dirty,logger,context,stats}
+        Self {geometry_material,surface_material,variant,variants,bindings,program,sources,programs,
+              source_registry,includes,fallback,dirty,logger,stats}
     }
 
     // TODO: this is very work-in-progress function. It should be refactored in the next PR.
-    /// Check dirty flags and update the state accordingly.
-    pub fn update(&mut self, bindings:&[VarBinding]) {
+    /// Check dirty flags and update the state accordingly. The sources of the current variant are
+    /// taken from the cache or generated. The cache is cleared if the bindings changed since the
+    /// cached sources were generated. If the program is still being compiled, its status is
+    /// requested again.
+    pub fn update(&mut self, bindings:&[VarBinding], globals:Option<&UniformBlock<C>>) {
         group!(self.logger, "Updating.", {
             if self.dirty.check_all() {
-
-                self.stats.inc_shader_compile_count();
-
-                let mut shader_cfg     = shader::builder::ShaderConfig::new();
-                let mut shader_builder = shader::builder::ShaderBuilder::new();
-
-                for binding in bindings {
-                    let name = &binding.name;
-                    let tp   = &binding.decl.tp;
-                    match binding.scope {
-                        None => todo!(),
-                        Some(scope_type) => match scope_type {
-                            ScopeType::Symbol => shader_cfg.add_uniform   (name,tp),
-                            ScopeType::Global => shader_cfg.add_uniform   (name,tp),
-                            _                 => shader_cfg.add_attribute (name,tp),
-                        }
-                    }
+                let bindings_key = BindingsKey::new(bindings,globals);
+                if bindings_key != self.bindings {
+                    self.clear_variants();
+                    self.bindings = bindings_key;
                 }
+                let defines = self.defines();
+                let sources = match self.variants.get(&defines).cloned() {
+                    Some(sources) => sources,
+                    None          => self.generate_sources(&defines,bindings,globals),
+                };
+                self.variants.insert(defines,sources.clone());
+                self.sources = Some(sources);
+                self.program = ProgramStatus::Compiling;
+                self.dirty.unset_all();
+            }
+            if self.program.is_compiling() {
+                if let Some(sources) = &self.sources {
+                    self.program = self.programs.request(&sources.vertex,&sources.fragment);
+                    self.update_fallback();
+                }
+            }
+        })
+    }
+
+    /// Generates the shader again if any of the included sources changed since it was generated.
+    /// The current program is used until the new one is compiled. If the compilation of the
+    /// outdated sources failed, the failure is forgotten, so it is not kept in the cache forever.
+    pub fn reload_changed_sources(&mut self) {
+        if self.source_registry.changed_since(&self.includes) {
+            if let Some(program) = self.program.program() {
+                self.fallback = Some(program.clone_ref());
+            }
+            if let (true,Some(sources)) = (self.program.is_failed(),&self.sources) {
+                self.programs.forget_failure(&sources.vertex,&sources.fragment);
+            }
+            self.clear_variants();
+            self.dirty.set();
+        }
+    }
 
-                self.geometry_material.outputs().iter().for_each(|(name,decl)|{
-                    shader_cfg.add_shared_attribute(name,&decl.tp);
-                });
+    /// Drops the last good program once the new one is ready. If the compilation of the new one
+    /// failed, the last good program is used further on.
+    fn update_fallback(&mut self) {
+        match self.program {
+            ProgramStatus::Ready(_) => self.fallback = None,
+            ProgramStatus::Failed if self.fallback.is_some() => {
+                self.logger.warning("Compilation failed. Using the last good program.")
+            }
+            _ => {}
+        }
+    }
 
-                shader_cfg.add_output("color", glsl::PrimType::Vec4);
+    /// Forgets the sources of all variants and the versions of the included sources.
+    fn clear_variants(&mut self) {
+        self.variants.clear();
+        self.includes.clear();
+    }
 
-                let vertex_code   = self.geometry_material.code().clone();
-                let fragment_code = self.surface_material.code().clone();
-                shader_builder.compute(&shader_cfg,vertex_code,fragment_code);
-                let shader      = shader_builder.build();
-                let vert_shader = compile_vertex_shader  (&self.context,&shader.vertex);
-                let frag_shader = compile_fragment_shader(&self.context,&shader.fragment);
-                if let Err(ref err) = frag_shader {
-                    self.logger.error(|| format!("{}", err))
+    /// Generates the sources of the variant with the given `#define`s. Global variables kept in the
+    /// `globals` block are declared as members of the block instead of separate uniforms. The
+    /// included sources are expanded and their versions are recorded.
+    fn generate_sources
+    ( &mut self
+    , defines  : &[glsl::Define]
+    , bindings : &[VarBinding]
+    , globals  : Option<&UniformBlock<C>>
+    ) -> ProgramKey {
+        let mut shader_cfg     = shader::builder::ShaderConfig::new();
+        let mut shader_builder = shader::builder::ShaderBuilder::new();
+        let mut uses_globals   = false;
+
+        for define in defines {
+            shader_cfg.add_define(define.clone());
+        }
+
+        for binding in bindings {
+            let name = &binding.name;
+            let tp   = &binding.decl.tp;
+            if let Some(decl) = &binding.decl.struct_decl {
+                shader_cfg.add_struct(decl);
+            }
+            match binding.scope {
+                // Unbound inputs without default values are reported by `validation::validate`.
+                None => if let Some(value) = &binding.decl.default {
+                    shader_cfg.add_constant(name,tp,value)
+                }
+                Some(scope_type) => match scope_type {
+                    ScopeType::Symbol => shader_cfg.add_uniform   (name,tp),
+                    ScopeType::Global => match globals {
+                        Some(block) if block.contains(name) => uses_globals = true,
+                        _ => shader_cfg.add_uniform(name,tp),
+                    }
+                    _                 => shader_cfg.add_attribute (name,tp),
                 }
+            }
+        }
+
+        if let Some(block) = globals.filter(|_| uses_globals) {
+            let qual = block.members().into_iter().collect();
+            shader_cfg.add_uniform_block(block.name(),qual);
+        }
+
+        self.geometry_material.outputs().iter().for_each(|(name,decl)|{
+            shader_cfg.add_shared_attribute(name,&decl.tp);
+        });
+
+        shader_cfg.add_output("color", glsl::PrimType::Vec4);
+
+        let vertex_code   = self.geometry_material.code().clone();
+        let fragment_code = self.surface_material.code().clone();
+        shader_builder.compute(&shader_cfg,vertex_code,fragment_code);
+        let shader   = shader_builder.build();
+        let vertex   = self.expand_includes(&shader.vertex);
+        let fragment = self.expand_includes(&shader.fragment);
+        ProgramKey::new(vertex,fragment)
+    }
 
-                let vert_shader = vert_shader.unwrap();
-                let frag_shader = frag_shader.unwrap();
-                let program     = link_program(&self.context,&vert_shader,&frag_shader);
+    /// Expands the includes of the code and records the versions of the included sources.
+    fn expand_includes(&mut self, code:&str) -> String {
+        let expansion = self.source_registry.expand(code);
+        for error in &expansion.errors {
+            self.logger.error(|| error.to_string());
+        }
+        self.includes.extend(expansion.includes);
+        expansion.code
+    }
 
-                let program     = program.unwrap();
-                self.program    = Some(program);
-                self.dirty.unset_all();
-            }
+    /// The `#define`s of the current variant. In case the variant does not match the features
+    /// declared by the materials, the default variant is used.
+    fn defines(&self) -> Vec<glsl::Define> {
+        let features = self.features();
+        features.defines(&self.variant).unwrap_or_else(|error| {
+            self.logger.error(|| format!("Invalid shader variant: {}",error));
+            features.defines(&default()).unwrap_or_default()
         })
     }
 
+    /// Forgets the program after the context was lost and restored. It will be re-compiled from
+    /// the materials during the next update.
+    pub fn restore_context(&mut self) {
+        self.program  = ProgramStatus::Compiling;
+        self.fallback = None;
+        self.dirty.set();
+    }
+
+    /// All the features declared by the materials.
+    pub fn features(&self) -> Features {
+        let mut features = self.geometry_material.features().clone();
+        features.extend(self.surface_material.features());
+        features
+    }
+
     /// Traverses the shader definition and collects all attribute names.
     pub fn collect_variables(&self) -> BTreeMap<String,VarDecl> {
         let geometry_material_inputs = self.geometry_material.inputs().clone();
@@ -141,7 +313,7 @@ impl Shader {
     }
 }
 
-impl Drop for Shader {
+impl<C:GpuContext> Drop for Shader<C> {
     fn drop(&mut self) {
         self.stats.dec_shader_count();
     }
@@ -150,23 +322,168 @@ impl Drop for Shader {
 
 // === Getters ===
 
-impl Shader {
-    pub fn program(&self) -> &Option<WebGlProgram> {
-        &self.program
+impl<C:GpuContext> Shader<C> {
+    /// The program of the shader. While the shader is compiled again after its included sources
+    /// changed, or if that compilation failed, the last good program is returned.
+    pub fn program(&self) -> Option<&CachedProgram<C>> {
+        self.program.program().or_else(|| self.fallback.as_ref())
+    }
+
+    pub fn is_compiling(&self) -> bool {
+        self.program.is_compiling()
+    }
+
+    /// The selected variant of the shader.
+    pub fn variant(&self) -> &Variant {
+        &self.variant
     }
 }
 
 
 // === Setters ===
 
-impl Shader {
+impl<C:GpuContext> Shader<C> {
     pub fn set_geometry_material<M:Into<Material>>(&mut self, material:M) {
         self.geometry_material = material.into();
+        self.fallback          = None;
+        self.clear_variants();
         self.dirty.set();
     }
 
     pub fn set_material<M:Into<Material>>(&mut self, material:M) {
         self.surface_material = material.into();
+        self.fallback         = None;
+        self.clear_variants();
         self.dirty.set();
     }
+
+    /// Selects the variant of the shader. The program of the variant is compiled during the next
+    /// update, unless it was already compiled.
+    pub fn set_variant(&mut self, variant:Variant) {
+        if variant != self.variant {
+            self.variant  = variant;
+            self.fallback = None;
+            self.dirty.set();
+        }
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::display::symbol::shader::builder::CodeTemplete;
+    use crate::system::web;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    use wasm_bindgen_test::wasm_bindgen_test_configure;
+    use web_sys::HtmlCanvasElement;
+    use web_sys::WebGlProgram;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn new_shader() -> Shader {
+        let canvas       = web::create_element("canvas").unwrap();
+        let canvas       = canvas.dyn_into::<HtmlCanvasElement>().unwrap();
+        let context      = web::get_webgl2_context(&canvas).unwrap();
+        let stats        = Stats::default();
+        let logger       = Logger::new("Test");
+        let programs     = ProgramCache::new(logger.sub("programs"),&stats,&context);
+        let sources      = ShaderSources::new();
+        let mut shader   = Shader::new(logger,&stats,&programs,&sources,||{});
+        let mut material = Material::new();
+        material.add_input("tint",1.0_f32);
+        material.add_flag("dim");
+        material.set_main("output_color = vec4(input_tint);");
+        shader.set_material(material);
+        shader
+    }
+
+    fn tint_binding(scope:Option<ScopeType>) -> Vec<VarBinding> {
+        vec![VarBinding::new("tint",VarDecl::from(1.0_f32),scope)]
+    }
+
+    /// Updates the shader until its program is compiled or the compilation fails.
+    fn compile(shader:&mut Shader, bindings:&[VarBinding]) {
+        for _ in 0..1000 {
+            shader.update(bindings,None);
+            if !shader.is_compiling() { return }
+            shader.programs.poll();
+        }
+        panic!("The test program was not compiled in time.")
+    }
+
+    fn gl_program(shader:&Shader) -> Option<WebGlProgram> {
+        shader.program().map(|program| program.gl_program())
+    }
+
+    fn variant(dim:bool) -> Variant {
+        let mut variant = Variant::new();
+        variant.set_flag("dim",dim);
+        variant
+    }
+
+    #[wasm_bindgen_test]
+    fn disabled_flags_share_the_sources_of_the_default_variant() {
+        let mut shader = new_shader();
+        let bindings   = tint_binding(Some(ScopeType::Symbol));
+        shader.update(&bindings,None);
+        let sources = shader.sources.clone();
+        shader.set_variant(variant(false));
+        shader.update(&bindings,None);
+        assert_eq!(shader.sources,sources);
+        assert_eq!(shader.variants.len(),1);
+    }
+
+    #[wasm_bindgen_test]
+    fn cached_sources_are_dropped_when_the_bindings_change() {
+        let mut shader = new_shader();
+        let uniform    = tint_binding(Some(ScopeType::Symbol));
+        let constant   = tint_binding(None);
+        shader.update(&uniform,None);
+        let uniform_sources = shader.sources.clone().unwrap();
+        shader.set_variant(variant(true));
+        shader.update(&constant,None);
+        shader.set_variant(Variant::new());
+        shader.update(&constant,None);
+        let constant_sources = shader.sources.clone().unwrap();
+        assert_ne!(constant_sources,uniform_sources);
+        assert!(constant_sources.fragment.contains("const float input_tint"));
+    }
+
+    #[wasm_bindgen_test]
+    fn last_good_program_is_used_until_the_reloaded_one_compiles() {
+        let mut shader   = new_shader();
+        let bindings     = tint_binding(Some(ScopeType::Symbol));
+        let registry     = shader.source_registry.clone_ref();
+        let before_main  = "#include \"lib.glsl\"".to_string();
+        let main         = "output_color = vec4(input_tint * lib());".to_string();
+        let mut material = Material::new();
+        registry.define("lib.glsl","float lib() { return 1.0; }");
+        material.add_input("tint",1.0_f32);
+        material.set_code(CodeTemplete::new(before_main,main,default()));
+        shader.set_material(material);
+        compile(&mut shader,&bindings);
+        let good = gl_program(&shader);
+        assert!(good.is_some());
+
+        registry.set("lib.glsl","float lib() { return undefined_value; }");
+        shader.reload_changed_sources();
+        compile(&mut shader,&bindings);
+        assert!(shader.program.program().is_none());
+        assert_eq!(gl_program(&shader),good);
+
+        registry.set("lib.glsl","float lib() { return 0.5; }");
+        shader.reload_changed_sources();
+        compile(&mut shader,&bindings);
+        assert!(shader.fallback.is_none());
+        assert!(gl_program(&shader).is_some());
+        assert_ne!(gl_program(&shader),good);
+    }
 }
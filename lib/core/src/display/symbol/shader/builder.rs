@@ -3,6 +3,8 @@
 use crate::prelude::*;
 
 use crate::data::container::Add;
+use crate::system::gpu::shader::diagnostic::Origin;
+use crate::system::gpu::shader::diagnostic::with_origin;
 use crate::system::gpu::shader::glsl;
 
 use code_builder::HasCodeRepr;
@@ -28,11 +30,15 @@ pub struct Shader {
 
 #[derive(Clone,Debug,Default)]
 pub struct ShaderConfig {
+    pub defines    : Vec<glsl::Define>,
     pub precision  : ShaderPrecision,
     pub outputs    : BTreeMap<String,AttributeQualifier>,
     pub shared     : BTreeMap<String,AttributeQualifier>,
     pub attributes : BTreeMap<String,AttributeQualifier>,
     pub uniforms   : BTreeMap<String,UniformQualifier>,
+    pub blocks     : BTreeMap<String,UniformBlockQualifier>,
+    pub structs    : BTreeMap<String,glsl::StructDecl>,
+    pub constants  : BTreeMap<String,(glsl::Type,glsl::Glsl)>,
 }
 
 impl ShaderConfig {
@@ -50,9 +56,29 @@ impl ShaderConfig {
         self.uniforms.insert(name.as_ref().to_string(), qual.into());
     }
 
+    pub fn add_uniform_block<S:Str>(&mut self, name:S, qual:UniformBlockQualifier) {
+        self.blocks.insert(name.as_ref().to_string(), qual);
+    }
+
+    /// Adds a declaration of a struct type. Declarations of the same name are added only once.
+    pub fn add_struct(&mut self, decl:&glsl::StructDecl) {
+        self.structs.insert(decl.ident.0.clone(), decl.clone());
+    }
+
     pub fn add_output<S:Str,Q:Into<AttributeQualifier>>(&mut self, name:S, qual:Q) {
         self.outputs.insert(name.as_ref().to_string(), qual.into());
     }
+
+    /// Adds a constant input, used for inputs which are not bound to any buffer or uniform.
+    pub fn add_constant<S:Str>(&mut self, name:S, typ:&glsl::Type, value:&glsl::Glsl) {
+        self.constants.insert(name.as_ref().to_string(), (typ.clone(),value.clone()));
+    }
+
+    /// Adds a preprocessor macro definition to both vertex and fragment shaders. Defines select
+    /// the variant of the shader, see the `variant` module to learn more.
+    pub fn add_define(&mut self, define:glsl::Define) {
+        self.defines.push(define);
+    }
 }
 
 
@@ -153,6 +179,12 @@ impl From<&glsl::PrimType> for AttributeQualifier {
     }
 }
 
+impl From<&glsl::Type> for AttributeQualifier {
+    fn from(typ:&glsl::Type) -> Self {
+        typ.clone().into()
+    }
+}
+
 
 // === UniformQualifier ===
 
@@ -196,6 +228,45 @@ impl From<&glsl::PrimType> for UniformQualifier {
     }
 }
 
+impl From<&glsl::Type> for UniformQualifier {
+    fn from(typ:&glsl::Type) -> Self {
+        typ.clone().into()
+    }
+}
+
+
+// === UniformBlockQualifier ===
+
+/// Uniform block declaration. Members have to be provided in the order of their offsets in the
+/// `std140` layout, including the members not used by the shader, so the declaration matches the
+/// layout of the block buffer.
+#[derive(Clone,Debug,Default)]
+pub struct UniformBlockQualifier {
+    pub members : Vec<(String,glsl::Type)>,
+}
+
+impl UniformBlockQualifier {
+    pub fn to_block<Name:Into<glsl::Identifier>>
+    (&self, name:Name) -> glsl::UniformBlock {
+        let ident   = name.into();
+        let members = self.members.iter().map(|(name,typ)| {
+            glsl::LocalVar {
+                constant : false,
+                typ      : typ.clone(),
+                ident    : mk_uniform_name(name).into()
+            }
+        }).collect();
+        glsl::UniformBlock {ident,members}
+    }
+}
+
+impl<S:Str,T:Into<glsl::Type>> FromIterator<(S,T)> for UniformBlockQualifier {
+    fn from_iter<I:IntoIterator<Item=(S,T)>>(iter:I) -> Self {
+        let members = iter.into_iter().map(|(name,typ)| (name.into(),typ.into())).collect();
+        Self {members}
+    }
+}
+
 
 
 // ====================
@@ -274,20 +345,38 @@ impl ShaderBuilder {
 
     pub fn compute
     (&mut self, cfg:&ShaderConfig, vertex_code:CodeTemplete, fragment_code:CodeTemplete) {
+        self.gen_defines_code(cfg);
         self.gen_precision_code(cfg);
         self.gen_attributes_code(cfg);
         self.gen_shared_attributes_code(cfg);
         self.gen_uniforms_code(cfg);
+        self.gen_constants_code(cfg);
         self.gen_outputs_code(cfg);
         self.add_template_code(vertex_code,fragment_code);
     }
 
+    /// Adds the code of materials. The code is marked with its origin, so compilation diagnostics
+    /// can refer to the material instead of the generated code. The generated code following the
+    /// material definitions is marked as well.
     fn add_template_code(&mut self, vertex_code:CodeTemplete, fragment_code:CodeTemplete) {
-        self.vertex.main.body.add(&vertex_code.main);
-        self.vertex.add(glsl::Statement::Raw(glsl::RawCode::new(vertex_code.before_main)));
+        let vertex_main   = with_origin(Origin::GeometryMaterial,&vertex_code.main);
+        let vertex_before = with_origin(Origin::GeometryMaterial,&vertex_code.before_main);
+        let vertex_before = format!("{}\n{}",vertex_before,Origin::Generated.marker());
+        self.vertex.main.body.add(&vertex_main);
+        self.vertex.add(glsl::Statement::Raw(glsl::RawCode::new(vertex_before)));
+
+        let fragment_main   = with_origin(Origin::SurfaceMaterial,&fragment_code.main);
+        let fragment_before = with_origin(Origin::SurfaceMaterial,&fragment_code.before_main);
+        let fragment_before = format!("{}\n{}",fragment_before,Origin::Generated.marker());
+        self.fragment.main.body.add(&fragment_main);
+        self.fragment.add(glsl::Statement::Raw(glsl::RawCode::new(fragment_before)));
+    }
 
-        self.fragment.main.body.add(&fragment_code.main);
-        self.fragment.add(glsl::Statement::Raw(glsl::RawCode::new(fragment_code.before_main)));
+    fn gen_defines_code(&mut self, cfg:&ShaderConfig) {
+        for define in &cfg.defines {
+            self.vertex  .add(define.clone());
+            self.fragment.add(define.clone());
+        }
     }
 
     fn gen_precision_code(&mut self, cfg:&ShaderConfig) {
@@ -324,6 +413,14 @@ impl ShaderBuilder {
     }
 
     fn gen_uniforms_code(&mut self, cfg:&ShaderConfig) {
+        for decl in cfg.structs.values() {
+            self.vertex  .add(decl.clone());
+            self.fragment.add(decl.clone());
+        }
+        for (name,qual) in &cfg.blocks {
+            self.vertex  .add(qual.to_block(name));
+            self.fragment.add(qual.to_block(name));
+        }
         if !cfg.uniforms.is_empty() {
             for (name,qual) in &cfg.uniforms {
                 let name = mk_uniform_name(name);
@@ -333,6 +430,15 @@ impl ShaderBuilder {
         }
     }
 
+    fn gen_constants_code(&mut self, cfg:&ShaderConfig) {
+        for (name,(typ,value)) in &cfg.constants {
+            let name = mk_uniform_name(name);
+            let code = format!("const {} {} = {};",typ,name,value);
+            self.vertex  .add(glsl::Statement::Raw(glsl::RawCode::new(code.clone())));
+            self.fragment.add(glsl::Statement::Raw(glsl::RawCode::new(code)));
+        }
+    }
+
     fn gen_outputs_code(&mut self, cfg:&ShaderConfig) {
         if !cfg.outputs.is_empty() {
             cfg.outputs.iter().enumerate().for_each(|(loc,(name,qual))|{
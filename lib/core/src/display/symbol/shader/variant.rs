@@ -0,0 +1,256 @@
+//! This module defines shader variants. Materials declare compile-time features, like shadows or
+//! a debug overlay, and symbols select their values at runtime. Every combination of the values is
+//! a separate variant of the shader, compiled with a different set of `#define`s.
+
+use crate::prelude::*;
+
+use crate::system::gpu::shader::glsl;
+
+
+
+// =============
+// === Error ===
+// =============
+
+/// Errors of selecting a shader variant.
+#[derive(Clone,Debug,Fail,PartialEq)]
+#[allow(missing_docs)]
+pub enum Error {
+    #[fail(display="Feature `{}` is not declared by the materials.",name)]
+    UnknownFeature {name:String},
+    #[fail(display="Feature `{}` is a flag, but it was set to option `{}`.",name,option)]
+    NotAnEnum {name:String, option:String},
+    #[fail(display="Feature `{}` is an enum, but it was set to a boolean value.",name)]
+    NotAFlag {name:String},
+    #[fail(display="Feature `{}` has no option `{}`.",name,option)]
+    UnknownOption {name:String, option:String},
+}
+
+
+
+// ===============
+// === Feature ===
+// ===============
+
+/// Declaration of a compile-time feature of a material.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum Feature {
+    /// A feature which is either enabled or disabled. It is disabled by default. The enabled
+    /// feature `shadows` emits `#define SHADOWS`.
+    Flag,
+    /// A feature with one of several options. The first option is the default one. The feature
+    /// `quality` set to `high` emits `#define QUALITY_HIGH`.
+    Enum(Vec<String>),
+}
+
+/// The value of a feature in a shader variant.
+#[derive(Clone,Debug,Eq,Hash,Ord,PartialEq,PartialOrd)]
+pub enum FeatureValue {
+    /// The value of a flag feature.
+    Flag(bool),
+    /// The selected option of an enum feature.
+    Option(String),
+}
+
+/// The name of the macro defined for the given feature and its option.
+pub fn mk_define_name(feature:&str, option:Option<&str>) -> String {
+    match option {
+        None         => feature.to_uppercase(),
+        Some(option) => format!("{}_{}",feature,option).to_uppercase(),
+    }
+}
+
+
+
+// ================
+// === Features ===
+// ================
+
+/// A set of features declared by a material.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Features {
+    decls : BTreeMap<String,Feature>,
+}
+
+impl Features {
+    /// Constructor.
+    pub fn new() -> Self {
+        default()
+    }
+
+    /// Declares a flag feature.
+    pub fn add_flag<Name:Str>(&mut self, name:Name) {
+        self.decls.insert(name.into(),Feature::Flag);
+    }
+
+    /// Declares an enum feature. Panics if no options are provided.
+    pub fn add_enum<Name:Str,Opt:Str>(&mut self, name:Name, options:Vec<Opt>) {
+        assert!(!options.is_empty(),"Enum features need at least one option.");
+        let options = options.into_iter().map(|option| option.into()).collect();
+        self.decls.insert(name.into(),Feature::Enum(options));
+    }
+
+    /// Looks up the feature by name.
+    pub fn get(&self, name:&str) -> Option<&Feature> {
+        self.decls.get(name)
+    }
+
+    /// Checks whether no features are declared.
+    pub fn is_empty(&self) -> bool {
+        self.decls.is_empty()
+    }
+
+    /// Adds all the features declared in `other`. Declarations of the same name are replaced.
+    pub fn extend(&mut self, other:&Features) {
+        self.decls.extend(other.decls.iter().map(|(name,decl)| (name.clone(),decl.clone())));
+    }
+
+    /// The `#define`s of the given variant. Features which are not set in the variant use their
+    /// default values.
+    pub fn defines(&self, variant:&Variant) -> Result<Vec<glsl::Define>,Error> {
+        for name in variant.values.keys() {
+            if !self.decls.contains_key(name) {
+                return Err(Error::UnknownFeature {name:name.clone()})
+            }
+        }
+        let mut defines = Vec::new();
+        for (name,decl) in &self.decls {
+            match (decl,variant.values.get(name)) {
+                (Feature::Flag,None) => {}
+                (Feature::Flag,Some(FeatureValue::Flag(enabled))) => if *enabled {
+                    defines.push(glsl::Define::new(mk_define_name(name,None)))
+                }
+                (Feature::Flag,Some(FeatureValue::Option(option))) => {
+                    let name   = name.clone();
+                    let option = option.clone();
+                    return Err(Error::NotAnEnum {name,option})
+                }
+                (Feature::Enum(_),Some(FeatureValue::Flag(_))) => {
+                    return Err(Error::NotAFlag {name:name.clone()})
+                }
+                (Feature::Enum(options),value) => {
+                    let option = match value {
+                        Some(FeatureValue::Option(option)) => option,
+                        _                                  => &options[0],
+                    };
+                    if !options.contains(option) {
+                        let name   = name.clone();
+                        let option = option.clone();
+                        return Err(Error::UnknownOption {name,option})
+                    }
+                    defines.push(glsl::Define::new(mk_define_name(name,Some(option))))
+                }
+            }
+        }
+        Ok(defines)
+    }
+}
+
+
+
+// ===============
+// === Variant ===
+// ===============
+
+/// Selection of feature values. Features which are not set use their default values, so the
+/// default variant is an empty one.
+#[derive(Clone,Debug,Default,Eq,Hash,PartialEq)]
+pub struct Variant {
+    values : BTreeMap<String,FeatureValue>,
+}
+
+impl Variant {
+    /// Constructor.
+    pub fn new() -> Self {
+        default()
+    }
+
+    /// Enables or disables a flag feature.
+    pub fn set_flag<Name:Str>(&mut self, name:Name, enabled:bool) {
+        self.values.insert(name.into(),FeatureValue::Flag(enabled));
+    }
+
+    /// Selects an option of an enum feature.
+    pub fn set_option<Name:Str,Opt:Str>(&mut self, name:Name, option:Opt) {
+        self.values.insert(name.into(),FeatureValue::Option(option.into()));
+    }
+
+    /// Resets the feature to its default value.
+    pub fn unset(&mut self, name:&str) {
+        self.values.remove(name);
+    }
+
+    /// The value of the feature, if it was set.
+    pub fn get(&self, name:&str) -> Option<&FeatureValue> {
+        self.values.get(name)
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features() -> Features {
+        let mut features = Features::new();
+        features.add_flag("shadows");
+        features.add_flag("debug_overlay");
+        features.add_enum("quality",vec!["low","high"]);
+        features
+    }
+
+    fn define_names(defines:Vec<glsl::Define>) -> Vec<String> {
+        defines.into_iter().map(|define| define.name).collect()
+    }
+
+    #[test]
+    fn default_variant() {
+        let defines = features().defines(&Variant::new()).unwrap();
+        assert_eq!(define_names(defines),vec!["QUALITY_LOW"]);
+    }
+
+    #[test]
+    fn selected_variant() {
+        let mut variant = Variant::new();
+        variant.set_flag("shadows",true);
+        variant.set_flag("debug_overlay",false);
+        variant.set_option("quality","high");
+        let defines = features().defines(&variant).unwrap();
+        assert_eq!(define_names(defines),vec!["QUALITY_HIGH","SHADOWS"]);
+    }
+
+    #[test]
+    fn disabled_flags_emit_the_defines_of_unset_ones() {
+        let mut variant = Variant::new();
+        variant.set_flag("shadows",false);
+        assert_ne!(variant,Variant::new());
+        assert_eq!(features().defines(&variant),features().defines(&Variant::new()));
+    }
+
+    #[test]
+    fn invalid_variants() {
+        let check = |variant:Variant, error:Error| {
+            assert_eq!(features().defines(&variant),Err(error));
+        };
+        let mut variant = Variant::new();
+        variant.set_flag("fog",true);
+        check(variant,Error::UnknownFeature {name:"fog".into()});
+
+        let mut variant = Variant::new();
+        variant.set_flag("quality",true);
+        check(variant,Error::NotAFlag {name:"quality".into()});
+
+        let mut variant = Variant::new();
+        variant.set_option("quality","medium");
+        check(variant,Error::UnknownOption {name:"quality".into(), option:"medium".into()});
+
+        let mut variant = Variant::new();
+        variant.set_option("shadows","medium");
+        check(variant,Error::NotAnEnum {name:"shadows".into(), option:"medium".into()});
+    }
+}
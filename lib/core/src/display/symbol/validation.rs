@@ -0,0 +1,281 @@
+//! This module defines the validation of material inputs. Inputs are bound to buffers and uniforms
+//! by their names only, so a typo or a mismatched type would otherwise be reported by the GLSL
+//! compiler at best, or silently replaced with the default value at worst.
+
+use crate::prelude::*;
+
+use crate::display::symbol::ScopeType;
+use crate::display::symbol::material::VarDecl;
+use crate::system::gpu::context::GpuContext;
+use crate::system::gpu::data::uniform::AnyPrimUniformOps;
+use crate::system::gpu::data::uniform::AnyTextureUniformOps;
+use crate::system::gpu::data::uniform::AnyUniform;
+use crate::system::gpu::shader::glsl;
+
+
+
+// =================
+// === InputType ===
+// =================
+
+/// The type of a buffer or a uniform which a material input can be bound to.
+#[derive(Clone,Debug,PartialEq)]
+pub enum InputType {
+    /// A buffer or a uniform of the given GLSL type.
+    Value(glsl::Type),
+    /// A texture which can be bound to sampler inputs of the given type only. For example,
+    /// textures of integer formats can not be sampled with `sampler2D`.
+    Texture(glsl::PrimType),
+}
+
+impl InputType {
+    /// Checks whether an input of the given type can be bound to a value of this type.
+    pub fn matches(&self, expected:&glsl::Type) -> bool {
+        match self {
+            Self::Value(typ)       => typ == expected,
+            Self::Texture(sampler) => expected.array.is_none() && expected.prim == *sampler,
+        }
+    }
+}
+
+impl<C:GpuContext> From<&AnyUniform<C>> for InputType {
+    fn from(uniform:&AnyUniform<C>) -> Self {
+        match uniform {
+            AnyUniform::Prim(uniform)    => Self::Value(uniform.glsl_type()),
+            AnyUniform::Struct(uniform)  => Self::Value(uniform.glsl_type()),
+            AnyUniform::Texture(uniform) => Self::Texture(uniform.glsl_sampler_type()),
+        }
+    }
+}
+
+impl Display for InputType {
+    fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Value(typ)       => write!(f,"{}",typ),
+            Self::Texture(sampler) => {
+                let sampler = glsl::Type::from(sampler.clone());
+                write!(f,"a texture for {}",sampler)
+            }
+        }
+    }
+}
+
+
+
+// =================
+// === Candidate ===
+// =================
+
+/// A scope defining a variable of the same name as a material input.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Candidate {
+    /// The scope defining the variable.
+    pub scope : ScopeType,
+    /// The type of the variable.
+    pub typ   : InputType,
+}
+
+impl Candidate {
+    /// Constructor.
+    pub fn new(scope:ScopeType, typ:InputType) -> Self {
+        Self {scope,typ}
+    }
+}
+
+
+
+// ==============
+// === Scopes ===
+// ==============
+
+/// A list of scopes, displayed as a comma-separated list.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Scopes(pub Vec<ScopeType>);
+
+impl Display for Scopes {
+    fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f,"{}",self.0.iter().map(|scope| scope.to_string()).join(", "))
+    }
+}
+
+
+
+// =============
+// === Error ===
+// =============
+
+/// Problems with binding a material input.
+#[derive(Clone,Debug,Fail,PartialEq)]
+#[allow(missing_docs)]
+pub enum Error {
+    #[fail(display="`{}` is not defined in any scope, its default value is used.",name)]
+    Missing {name:String},
+    #[fail(display="`{}` is defined in several scopes ({}), the {} is used.",name,scopes,chosen)]
+    Ambiguous {name:String, scopes:Scopes, chosen:ScopeType},
+    #[fail(display="`{}` expected {} but {} is {}.",name,expected,scope,found)]
+    TypeMismatch {name:String, expected:glsl::Type, scope:ScopeType, found:InputType},
+    #[fail(display="`{}` is not bound to any scope and it has no default value.",name)]
+    Unbound {name:String},
+}
+
+
+
+// ==============
+// === Report ===
+// ==============
+
+/// The result of validation of a material input.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Report {
+    /// The scope the input should be bound to. It is `None` if the input is not defined, or if
+    /// its type does not match, so that the default value is used instead. Inputs without default
+    /// values, like samplers, are reported as `Error::Unbound` then.
+    pub scope  : Option<ScopeType>,
+    /// All problems found.
+    pub errors : Vec<Error>,
+}
+
+/// Validates a material input against all the scopes defining a variable of the same name. The
+/// candidates should be provided in the lookup order, as the first one is bound to the input.
+pub fn validate(name:&str, decl:&VarDecl, candidates:&[Candidate]) -> Report {
+    let name       = name.to_string();
+    let expected   = &decl.tp;
+    let mut errors = Vec::new();
+    let scope      = match candidates.first() {
+        None         => None,
+        Some(chosen) => {
+            if candidates.len() > 1 {
+                let scopes = Scopes(candidates.iter().map(|candidate| candidate.scope).collect());
+                let chosen = chosen.scope;
+                errors.push(Error::Ambiguous {name:name.clone(),scopes,chosen});
+            }
+            if chosen.typ.matches(expected) {
+                Some(chosen.scope)
+            } else {
+                let name     = name.clone();
+                let expected = expected.clone();
+                let scope    = chosen.scope;
+                let found    = chosen.typ.clone();
+                errors.push(Error::TypeMismatch {name,expected,scope,found});
+                None
+            }
+        }
+    };
+    match (scope,&decl.default) {
+        (None,None)                              => errors.push(Error::Unbound {name}),
+        (None,Some(_)) if candidates.is_empty() => errors.push(Error::Missing {name}),
+        _                                        => {}
+    }
+    Report {scope,errors}
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::symbol::geometry::primitive::mesh;
+    use crate::system::gpu::data::texture::*;
+
+    const INSTANCE : ScopeType = ScopeType::Mesh(mesh::ScopeType::Instance);
+    const POINT    : ScopeType = ScopeType::Mesh(mesh::ScopeType::Point);
+
+    fn value(prim:glsl::PrimType) -> InputType {
+        InputType::Value(prim.into())
+    }
+
+    fn decl(prim:glsl::PrimType) -> VarDecl {
+        VarDecl::new(prim,Some("default".into()))
+    }
+
+    #[test]
+    fn matching_input_is_bound() {
+        let candidates = vec![Candidate::new(INSTANCE,value(glsl::PrimType::Vec2))];
+        let report     = validate("bounds",&decl(glsl::PrimType::Vec2),&candidates);
+        assert_eq!(report.scope,Some(INSTANCE));
+        assert!(report.errors.is_empty());
+    }
+
+    #[test]
+    fn missing_input() {
+        let report = validate("bounds",&decl(glsl::PrimType::Vec2),&[]);
+        assert_eq!(report.scope,None);
+        assert_eq!(report.errors,vec![Error::Missing {name:"bounds".into()}]);
+    }
+
+    #[test]
+    fn missing_input_without_default_is_unbound() {
+        let sampler = VarDecl::new(glsl::PrimType::Sampler2d,None);
+        let report  = validate("atlas",&sampler,&[]);
+        assert_eq!(report.scope,None);
+        assert_eq!(report.errors,vec![Error::Unbound {name:"atlas".into()}]);
+        let candidates = vec![Candidate::new(ScopeType::Symbol,value(glsl::PrimType::Vec4))];
+        let report     = validate("atlas",&sampler,&candidates);
+        assert_eq!(report.scope,None);
+        assert_eq!(report.errors.last(),Some(&Error::Unbound {name:"atlas".into()}));
+    }
+
+    #[test]
+    fn mismatched_input_is_not_bound() {
+        let candidates = vec![Candidate::new(INSTANCE,value(glsl::PrimType::Vec3))];
+        let report     = validate("bounds",&decl(glsl::PrimType::Vec2),&candidates);
+        let messages   = report.errors.iter().map(|error| error.to_string()).collect_vec();
+        assert_eq!(report.scope,None);
+        assert_eq!(messages,vec!["`bounds` expected vec2 but instance buffer is vec3."]);
+    }
+
+    #[test]
+    fn ambiguous_input_is_bound_to_the_first_scope() {
+        let candidates = vec!
+            [ Candidate::new(POINT,value(glsl::PrimType::Float))
+            , Candidate::new(ScopeType::Global,value(glsl::PrimType::Float))
+            ];
+        let report   = validate("time",&decl(glsl::PrimType::Float),&candidates);
+        let messages = report.errors.iter().map(|error| error.to_string()).collect_vec();
+        let expected = "`time` is defined in several scopes (point buffer, global uniform), the \
+                        point buffer is used.";
+        assert_eq!(report.scope,Some(POINT));
+        assert_eq!(messages,vec![expected]);
+    }
+
+    #[test]
+    fn textures_match_samplers_only() {
+        let texture = InputType::Texture(glsl::PrimType::Sampler2d);
+        assert!( texture.matches(&glsl::PrimType::Sampler2d.into()));
+        assert!(!texture.matches(&glsl::PrimType::Vec4.into()));
+        assert!(!texture.matches(&glsl::Type::array(glsl::PrimType::Sampler2d,2)));
+        assert!( value(glsl::PrimType::Float).matches(&glsl::PrimType::Float.into()));
+        assert!(!value(glsl::PrimType::Float).matches(&glsl::Type::array(glsl::PrimType::Float,2)));
+    }
+
+    #[test]
+    fn textures_match_samplers_of_their_kind() {
+        let float    = InputType::Texture(Rgba::glsl_sampler_type());
+        let signed   = InputType::Texture(R32i::glsl_sampler_type());
+        let unsigned = InputType::Texture(Rgba8ui::glsl_sampler_type());
+        assert!( float.matches(&glsl::PrimType::Sampler2d.into()));
+        assert!(!float.matches(&glsl::PrimType::ISampler2d.into()));
+        assert!(!float.matches(&glsl::PrimType::USampler2d.into()));
+        assert!(!float.matches(&glsl::PrimType::Sampler3d.into()));
+        assert!( signed.matches(&glsl::PrimType::ISampler2d.into()));
+        assert!(!signed.matches(&glsl::PrimType::Sampler2d.into()));
+        assert!( unsigned.matches(&glsl::PrimType::USampler2d.into()));
+        assert!(!unsigned.matches(&glsl::PrimType::ISampler2d.into()));
+    }
+
+    #[test]
+    fn mismatched_sampler_is_reported() {
+        let texture    = InputType::Texture(R32i::glsl_sampler_type());
+        let candidates = vec![Candidate::new(ScopeType::Symbol,texture)];
+        let sampler    = VarDecl::new(glsl::PrimType::Sampler2d,None);
+        let report     = validate("atlas",&sampler,&candidates);
+        let messages   = report.errors.iter().map(|error| error.to_string()).collect_vec();
+        assert_eq!(report.scope,None);
+        assert_eq!(messages[0],"`atlas` expected sampler2D but symbol uniform is a texture for \
+                                isampler2D.");
+    }
+}
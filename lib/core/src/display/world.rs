@@ -14,13 +14,21 @@ use crate::prelude::*;
 
 use crate::closure;
 use crate::control::callback::CallbackHandle;
+use crate::control::callback::CallbackRegistry1;
 use crate::data::dirty::traits::*;
 use crate::data::dirty;
+use crate::debug::capture::FrameCapture;
+use crate::debug::capture::FrameCapturer;
+use crate::debug::capture;
 use crate::debug::monitor::Monitor;
 use crate::debug::monitor::Panel;
 use crate::debug::monitor;
 use crate::debug::stats::Stats;
 use crate::display::shape::text::font::Fonts;
+use crate::display::world::workspace::ContextState;
+use crate::system::gpu::shader::reload::HttpFileSource;
+use crate::system::gpu::shader::reload::ShaderReloader;
+use crate::system::gpu::shader::sources::ShaderSources;
 use crate::system::web;
 use crate::control::EventLoop;
 use wasm_bindgen::prelude::Closure;
@@ -70,9 +78,78 @@ impl World {
         self.rc.borrow_mut().event_loop.add_callback(func)
     }
 
+    /// Run the provided callback whenever the WebGL context is lost or restored. Rendering is
+    /// paused while the context is lost, and all GPU resources are re-created before the
+    /// `ContextEvent::Restored` event is emitted.
+    pub fn on_context_event<F:FnMut(ContextEvent)+'static>(&self, callback:F) -> CallbackHandle {
+        self.rc.borrow().context_events.borrow_mut().add(callback)
+    }
+
+    /// Emits the event to all context event callbacks. The world is not borrowed while the
+    /// callbacks run.
+    fn emit_context_event(&self, event:ContextEvent) {
+        let context_events = self.rc.borrow().context_events.clone();
+        context_events.borrow_mut().run_all(event);
+    }
+
+    /// Captures all GPU calls issued during the next frame. The callback is run with the capture
+    /// once the frame is rendered.
+    pub fn capture_frame<F:FnOnce(&FrameCapture)+'static>(&self, callback:F) {
+        self.rc.borrow().capturer.request(callback)
+    }
+
+    /// Captures the next frame and saves it as a JSON file. Bound to the `F9` key.
+    pub fn download_frame_capture(&self) {
+        let logger = self.rc.borrow().logger.sub("capture");
+        self.capture_frame(move |capture| {
+            if let Err(err) = capture.download(capture::DEFAULT_FILE_NAME) {
+                logger.error(|| format!("Unable to save the capture: {:?}.",err))
+            }
+        })
+    }
+
+    /// Runs the callbacks of the finished frame captures. The world is not borrowed while the
+    /// callbacks run.
+    fn emit_frame_captures(&self) {
+        let capturer = self.rc.borrow().capturer.clone_ref();
+        for (callback,capture) in capturer.take_finished() {
+            callback(&capture)
+        }
+    }
+
+    /// Registry of the GLSL sources which can be included by materials. Define the sources of your
+    /// materials there in order to reload them in the development mode.
+    pub fn shader_sources(&self) -> ShaderSources {
+        self.rc.borrow().workspace.symbols.sources.clone_ref()
+    }
+
+    /// Enables the development mode, in which all shader sources are periodically loaded from the
+    /// provided URL, like a local server serving the `src` directory of this crate. The symbols
+    /// including the changed sources are compiled again. If the compilation fails, they are drawn
+    /// with their last good programs.
+    pub fn enable_shader_reload(&self, url:&str) -> web::Result<()> {
+        let mut data = self.rc.borrow_mut();
+        let logger   = data.logger.sub("shader_reloader");
+        let sources  = &data.workspace.symbols.sources;
+        let reloader = ShaderReloader::new(logger,HttpFileSource::new(url)?,sources);
+        data.shader_reloader = Some(reloader);
+        Ok(())
+    }
+
+    /// Disables the development mode enabled with `enable_shader_reload`.
+    pub fn disable_shader_reload(&self) {
+        self.rc.borrow_mut().shader_reloader = None;
+    }
+
     pub fn mod_stats<F:FnOnce(&Stats)>(&self, f:F) {
         f(&self.rc.borrow().stats);
     }
+
+    /// Adds a new symbol layer or changes the order of an existing one. Layers with lower orders
+    /// are drawn first.
+    pub fn add_layer(&self, name:&str, order:i32) {
+        self.rc.borrow_mut().workspace.symbols.layers.add(name,order);
+    }
 }
 
 impl<T> Add<T> for World where WorldData: Add<T> {
@@ -129,19 +206,21 @@ impl StatsMonitorData {
         let stats       = stats.clone_ref();
         let mut monitor = Monitor::new();
         let panels = vec![
-            monitor.add( monitor::FrameTime          :: new()       ),
-            monitor.add( monitor::Fps                :: new()       ),
-            monitor.add( monitor::WasmMemory         :: new()       ),
-            monitor.add( monitor::GpuMemoryUsage     :: new(&stats) ),
-            monitor.add( monitor::DrawCallCount      :: new(&stats) ),
-            monitor.add( monitor::DataUploadCount    :: new(&stats) ),
-            monitor.add( monitor::DataUploadSize     :: new(&stats) ),
-            monitor.add( monitor::BufferCount        :: new(&stats) ),
-            monitor.add( monitor::SymbolCount        :: new(&stats) ),
-            monitor.add( monitor::ShaderCount        :: new(&stats) ),
-            monitor.add( monitor::ShaderCompileCount :: new(&stats) ),
-            monitor.add( monitor::SpriteSystemCount  :: new(&stats) ),
-            monitor.add( monitor::SpriteCount        :: new(&stats) ),
+            monitor.add( monitor::FrameTime           :: new()       ),
+            monitor.add( monitor::Fps                 :: new()       ),
+            monitor.add( monitor::WasmMemory          :: new()       ),
+            monitor.add( monitor::GpuMemoryUsage      :: new(&stats) ),
+            monitor.add( monitor::DrawCallCount       :: new(&stats) ),
+            monitor.add( monitor::DataUploadCount     :: new(&stats) ),
+            monitor.add( monitor::DataUploadSize      :: new(&stats) ),
+            monitor.add( monitor::BufferCount         :: new(&stats) ),
+            monitor.add( monitor::SymbolCount         :: new(&stats) ),
+            monitor.add( monitor::ShaderCount         :: new(&stats) ),
+            monitor.add( monitor::ShaderCompileCount  :: new(&stats) ),
+            monitor.add( monitor::SpriteSystemCount   :: new(&stats) ),
+            monitor.add( monitor::SpriteCount         :: new(&stats) ),
+            monitor.add( monitor::CulledSymbolCount   :: new(&stats) ),
+            monitor.add( monitor::CulledInstanceCount :: new(&stats) ),
         ];
         Self {stats,monitor,panels}
     }
@@ -163,6 +242,24 @@ impl StatsMonitorData {
 
 
 
+// ====================
+// === ContextEvent ===
+// ====================
+
+/// Lifecycle events of the WebGL context.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ContextEvent {
+    /// The context was lost, for example because of a GPU reset. Rendering is paused.
+    Lost,
+    /// The context was restored and all GPU resources were re-created. Rendering is resumed.
+    Restored,
+}
+
+/// Registry of callbacks run on context lifecycle events.
+pub type ContextEvents = Rc<RefCell<CallbackRegistry1<ContextEvent>>>;
+
+
+
 // =================
 // === WorldData ===
 // =================
@@ -187,6 +284,10 @@ pub struct WorldData {
     pub update_handle   : Option<CallbackHandle>,
     pub stats           : Stats,
     pub stats_monitor   : StatsMonitor,
+    pub context_lost    : bool,
+    pub context_events  : ContextEvents,
+    pub capturer        : FrameCapturer,
+    pub shader_reloader : Option<ShaderReloader<HttpFileSource>>,
 }
 
 
@@ -215,7 +316,11 @@ impl WorldData {
         let world     = World::new(Self::new_uninitialized(dom));
         let world_ref = world.clone_ref();
         with(world.borrow_mut(), |mut data| {
-            let update          = move |_| world_ref.borrow_mut().run();
+            let update          = move |_| {
+                let event = world_ref.borrow_mut().run();
+                if let Some(event) = event { world_ref.emit_context_event(event) }
+                world_ref.emit_frame_captures();
+            };
             let update_handle   = data.event_loop.add_callback(update);
             data.update_handle  = Some(update_handle);
         });
@@ -228,6 +333,7 @@ impl WorldData {
             let key = val.key();
             if      key == "0" { world_copy.borrow_mut().display_mode.set(0) }
             else if key == "1" { world_copy.borrow_mut().display_mode.set(1) }
+            else if key == "F9" { world_copy.download_frame_capture() }
         }));
         web::document().unwrap().add_event_listener_with_callback
             ("keydown",c.as_ref().unchecked_ref()).unwrap();
@@ -257,31 +363,68 @@ impl WorldData {
         let stats_monitor          = StatsMonitor::new(&stats);
         let performance            = web::get_performance().unwrap();
         let start_time             = performance.now() as f32;
+        let context_lost           = false;
+        let context_events         = default();
+        let capturer               = FrameCapturer::new(&workspace.context,logger.sub("capturer"));
+        let shader_reloader        = None;
         let stats_monitor_cp_1     = stats_monitor.clone();
         let stats_monitor_cp_2     = stats_monitor.clone();
         event_loop.set_on_loop_started  (move || { stats_monitor_cp_1.begin(); });
         event_loop.set_on_loop_finished (move || { stats_monitor_cp_2.end();   });
         Self {workspace,workspace_dirty,logger,event_loop,performance,start_time,time,display_mode
-             ,fonts,update_handle,stats,stats_monitor}
+             ,fonts,update_handle,stats,stats_monitor,context_lost,context_events,capturer
+             ,shader_reloader}
     }
 
-    pub fn run(&mut self) {
+    /// Updates the time and the whole world. Returns the context event if the WebGL context was
+    /// lost or restored since the last run.
+    pub fn run(&mut self) -> Option<ContextEvent> {
         let relative_time = self.performance.now() as f32 - self.start_time;
         self.time.set(relative_time);
+        let event = self.update_context();
+        self.capturer.begin_frame();
         self.update();
+        self.capturer.end_frame(relative_time as f64);
+        event
     }
 
-    /// Check dirty flags and update the state accordingly.
+    /// Check dirty flags and update the state accordingly. Does nothing if the WebGL context is
+    /// lost. In the development mode, the changed shader sources are reloaded first.
     pub fn update(&mut self) {
         //TODO[WD]: Re-think when should we check the condition (uniform update):
         //          if self.workspace_dirty.check_all() {
+        if self.workspace.is_context_lost() { return }
         group!(self.logger, "Updating.", {
+            if let Some(reloader) = &mut self.shader_reloader {
+                // Symbols are updated only if any of them changed, so an idle scene has to be
+                // marked dirty for the shaders including the reloaded sources to be generated.
+                if !reloader.poll().is_empty() {
+                    self.workspace.symbols_dirty.set();
+                }
+            }
             self.workspace_dirty.unset_all();
             let fonts = &mut self.fonts;
             self.workspace.update(fonts);
         });
     }
 
+    /// Checks whether the WebGL context was lost or restored and re-creates the GPU resources
+    /// if needed.
+    fn update_context(&mut self) -> Option<ContextEvent> {
+        match self.workspace.context_state.get() {
+            ContextState::Lost if !self.context_lost => {
+                self.context_lost = true;
+                Some(ContextEvent::Lost)
+            }
+            ContextState::Restored => {
+                self.workspace.restore_context(&mut self.fonts);
+                self.context_lost = false;
+                Some(ContextEvent::Restored)
+            }
+            _ => None
+        }
+    }
+
     /// Dispose the world object, cancel all handlers and events.
     pub fn dispose(&mut self) {
         self.update_handle = None;
@@ -5,10 +5,14 @@ use crate::prelude::*;
 pub use crate::display::symbol::registry::SymbolId;
 
 use crate::closure;
+use crate::control::io::mouse::EventListener;
 use crate::data::dirty::traits::*;
 use crate::data::dirty;
 use crate::debug::stats::Stats;
+use crate::system::gpu::context::GpuContext;
 use crate::system::gpu::shader::Context;
+use crate::display::render::PostProcessStack;
+use crate::display::render::RenderPipeline;
 use crate::display::shape::text::font::Fonts;
 use crate::display::shape::text;
 use crate::display::world::scene::Scene;
@@ -18,8 +22,24 @@ use crate::display::symbol::registry::SymbolRegistry;
 use crate::system::web::resize_observer::ResizeObserver;
 use crate::system::web;
 use crate::system::gpu::data::uniform::UniformScope;
+use crate::system::gpu::data::uniform::block::UniformBlock;
 
+use std::cell::Cell;
 use wasm_bindgen::prelude::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::Event;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// The name of the uniform block keeping the global variables.
+pub const GLOBALS_BLOCK_NAME : &str = "Globals";
+
+/// The binding point of the uniform block keeping the global variables.
+pub const GLOBALS_BLOCK_BINDING : u32 = 0;
 
 
 
@@ -102,6 +122,30 @@ impl ShapeData {
 
 
 
+// ====================
+// === ContextState ===
+// ====================
+
+/// State of the WebGL context, as reported by the `webglcontextlost` and `webglcontextrestored`
+/// canvas events.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ContextState {
+    /// The context is ready to use.
+    Active,
+    /// The context was lost. Nothing can be rendered until it is restored.
+    Lost,
+    /// The context was restored, but the GPU resources were not re-created yet.
+    Restored,
+}
+
+impl Default for ContextState {
+    fn default() -> Self {
+        Self::Active
+    }
+}
+
+
+
 // =================
 // === Workspace ===
 // =================
@@ -119,6 +163,8 @@ pub struct Workspace {
     pub logger        : Logger,
     pub listeners     : Listeners,
     pub variables     : UniformScope,
+    pub pipeline      : RenderPipeline,
+    pub context_state : Rc<Cell<ContextState>>,
     // TODO[AO] this is a very temporary solution. Need to develop some general component handling.
     pub text_components : Vec<text::TextComponent>,
 }
@@ -140,9 +186,17 @@ fn symbols_on_change(dirty:SymbolRegistryDirty) -> OnSymbolRegistryChange {
 
 // === Implementation ===
 
-#[derive(Debug)]
+/// Listener of the WebGL context lifecycle events.
+pub type ContextListener = EventListener<dyn FnMut(Event)>;
+
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct Listeners {
-    resize: ResizeObserver,
+    resize           : ResizeObserver,
+    #[derivative(Debug="ignore")]
+    context_lost     : ContextListener,
+    #[derivative(Debug="ignore")]
+    context_restored : ContextListener,
 }
 
 impl Workspace {
@@ -158,42 +212,95 @@ impl Workspace {
         let sub_logger      = logger.sub("symbols_dirty");
         let dirty_flag      = SymbolRegistryDirty::new(sub_logger,Box::new(on_mut));
         let on_change       = symbols_on_change(dirty_flag.clone_ref());
+        let sub_logger      = logger.sub("globals_block");
+        let globals         = UniformBlock::new
+            (sub_logger,&context,GLOBALS_BLOCK_NAME,GLOBALS_BLOCK_BINDING);
+        let sub_logger      = logger.sub("global_variables");
+        let variables       = UniformScope::new_with_block(sub_logger,&context,globals);
         let sub_logger      = logger.sub("symbols");
-        let variables       = UniformScope::new(logger.sub("global_variables"),&context);
         let symbols         = SymbolRegistry::new(&variables,&stats,&context,sub_logger,on_change);
         let shape           = Shape::default();
-        let listeners       = Self::init_listeners(&logger,&canvas,&shape,&shape_dirty);
+        let context_state   = default();
+        let listeners       = Self::init_listeners
+            (&logger,&canvas,&shape,&shape_dirty,&context_state)?;
         let symbols_dirty   = dirty_flag;
         let scene           = Scene::new(logger.sub("scene"),&variables);
         let text_components = default();
+        let pipeline        = RenderPipeline::new_default(logger.sub("pipeline"),&context);
 
         variables.add("pixel_ratio", shape.pixel_ratio());
 
-        // FIXME: use correct blending function and rething premultiplying the alpha.
-        context.enable(Context::BLEND);
-        // context.blend_func(Context::ONE, Context::ONE_MINUS_SRC_ALPHA);
-        context.blend_func(Context::SRC_ALPHA, Context::ONE);
-
         let this = Self {canvas,context,symbols,scene,symbols_dirty,shape,shape_dirty,logger
-                        ,listeners,variables,text_components};
+                        ,listeners,variables,pipeline,context_state,text_components};
         Ok(this)
     }
 
     /// Initialize all listeners and attach them to DOM elements.
     fn init_listeners
-    (logger:&Logger, canvas:&web_sys::HtmlCanvasElement, shape:&Shape, dirty:&ShapeDirty)
-    -> Listeners {
-        let logger = logger.clone();
-        let shape  = shape.clone();
-        let dirty  = dirty.clone();
-        let on_resize = Closure::new(move |width, height| {
-            group!(logger, "Resize observer event.", {
+    ( logger        : &Logger
+    , canvas        : &web_sys::HtmlCanvasElement
+    , shape         : &Shape
+    , dirty         : &ShapeDirty
+    , context_state : &Rc<Cell<ContextState>>
+    ) -> Result<Listeners,Error> {
+        let resize_logger = logger.clone();
+        let shape         = shape.clone();
+        let dirty         = dirty.clone();
+        let on_resize     = Closure::new(move |width, height| {
+            group!(resize_logger, "Resize observer event.", {
                 shape.set_screen_dimension(width as f32,height as f32);
                 dirty.set();
             })
         });
-        let resize = ResizeObserver::new(canvas,on_resize);
-        Listeners {resize}
+        let resize           = ResizeObserver::new(canvas,on_resize);
+        let lost_logger      = logger.clone();
+        let lost_state       = context_state.clone();
+        let on_lost          = move |event:Event| {
+            lost_logger.warning("WebGL context lost.");
+            // Preventing the default behavior is required to get the context restored.
+            event.prevent_default();
+            lost_state.set(ContextState::Lost);
+        };
+        let restored_logger  = logger.clone();
+        let restored_state   = context_state.clone();
+        let on_restored      = move |_:Event| {
+            restored_logger.info("WebGL context restored.");
+            restored_state.set(ContextState::Restored);
+        };
+        let context_lost     = Self::add_context_listener(canvas,"webglcontextlost",on_lost)?;
+        let context_restored =
+            Self::add_context_listener(canvas,"webglcontextrestored",on_restored)?;
+        Ok(Listeners {resize,context_lost,context_restored})
+    }
+
+    fn add_context_listener<F:FnMut(Event)+'static>
+    (canvas:&web_sys::HtmlCanvasElement, name:&str, f:F) -> Result<ContextListener,Error> {
+        let closure : Closure<dyn FnMut(Event)> = Closure::wrap(Box::new(f));
+        canvas.add_event_listener_with_callback(name,closure.as_ref().unchecked_ref())
+            .map_err(|_| web::Error::FailedToAddEventListener)?;
+        Ok(EventListener::new(canvas.clone().into(),name,closure))
+    }
+
+    /// Checks whether the WebGL context is lost. Nothing should be rendered in such a case.
+    pub fn is_context_lost(&self) -> bool {
+        self.context_state.get() == ContextState::Lost
+    }
+
+    /// Re-creates all GPU resources after the WebGL context was restored. Buffers, textures and
+    /// framebuffers are re-created from their CPU-side data, while programs are re-compiled from
+    /// the generated code during the next update.
+    pub fn restore_context(&mut self, fonts:&mut Fonts) {
+        group!(self.logger, "Restoring the context.", {
+            self.context.forget_extensions();
+            self.variables.restore_context();
+            self.symbols.restore_context();
+            self.pipeline.restore_context();
+            for component in &mut self.text_components {
+                component.restore_context(fonts);
+            }
+            self.shape_dirty.set();
+            self.context_state.set(ContextState::Active);
+        })
     }
 
     /// Create a new `Symbol` instance.
@@ -201,6 +308,17 @@ impl Workspace {
         self.symbols.new_symbol()
     }
 
+    /// Remove the symbol and forget its placement in layers.
+    pub fn remove_symbol(&mut self, id:SymbolId) -> Option<Symbol> {
+        self.symbols.remove_symbol(id)
+    }
+
+    /// Create a new post-processing stack with effects bound to the global variables. Register it
+    /// in the `pipeline` as a `PostProcessPass` to apply it.
+    pub fn new_post_process_stack(&self) -> PostProcessStack {
+        PostProcessStack::new(self.logger.sub("post_process"),&self.context,&self.variables)
+    }
+
     /// Resize the underlying canvas. This function should rather not be called
     /// directly. If you want to change the canvas size, modify the `shape` and
     /// set the dirty flag.
@@ -221,28 +339,39 @@ impl Workspace {
                 let screen = self.shape.screen_shape();
                 self.resize_canvas(&self.shape);
                 self.scene.camera.set_screen(screen.width, screen.height);
+                self.pipeline.resize(&self.shape.canvas_shape());
                 self.shape_dirty.unset_all();
             }
-            if self.symbols_dirty.check_all() {
-                self.symbols.update();
-                self.symbols_dirty.unset_all();
-            }
+            self.symbols.update_camera(&self.scene.camera);
+            self.symbols.prepare_instances(&self.scene.camera);
+            self.update_symbols();
 
-            self.logger.info("Clearing the scene.");
-            self.context.clear_color(0.0, 0.0, 0.0, 1.0);
-            self.context.clear(Context::COLOR_BUFFER_BIT);
-            self.logger.info("Rendering meshes.");
-            self.symbols.render(&self.scene.camera);
-            if !self.text_components.is_empty() {
-                self.logger.info("Rendering text components");
-                for text_component in &mut self.text_components {
-                    text_component.display(fonts);
-                }
-            }
+            self.variables.update();
+
+            self.logger.info("Running the render pipeline.");
+            let screen          = self.shape.canvas_shape();
+            let symbols         = &self.symbols;
+            let text_components = &mut self.text_components;
+            self.pipeline.run(&screen,symbols,text_components,fonts);
         })
     }
 }
 
+
+// === Private API ===
+
+impl Workspace {
+    /// Updates the symbols if any of them changed. The flag is cleared before the update, so the
+    /// symbols marked dirty during the update, like the ones which programs are still being
+    /// compiled, are updated in the next frame.
+    fn update_symbols(&mut self) {
+        if self.symbols_dirty.check_all() {
+            self.symbols_dirty.unset_all();
+            self.symbols.update();
+        }
+    }
+}
+
 impl Index<usize> for Workspace {
     type Output = Symbol;
     fn index(&self, ix: usize) -> &Self::Output {
@@ -255,3 +384,56 @@ impl IndexMut<usize> for Workspace {
         self.symbols.index_mut(ix)
     }
 }
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::display::symbol::material::Material;
+
+    use nalgebra::Vector3;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    use wasm_bindgen_test::wasm_bindgen_test_configure;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn new_workspace(id:&str) -> Workspace {
+        let canvas = web::create_element("canvas").unwrap();
+        canvas.set_id(id);
+        web::document().unwrap().body().unwrap().append_child(&canvas).unwrap();
+        Workspace::new(id,Logger::new("Test"),&default(),||{}).unwrap()
+    }
+
+    #[wasm_bindgen_test]
+    fn compiling_symbols_over_several_frames() {
+        let mut workspace = new_workspace("compiling_symbols_over_several_frames");
+        let symbol_id     = workspace.new_symbol();
+        let symbol        = &mut workspace[symbol_id];
+        let position      = symbol.surface.scopes.point.add_buffer("position");
+        let index         = symbol.surface.scopes.point.add_instance();
+        let mut geometry  = Material::new();
+        let mut material  = Material::new();
+        position.at(index).set(Vector3::new(0.0,0.0,0.0));
+        geometry.add_input_def::<Vector3<f32>>("position");
+        geometry.set_main("gl_Position = vec4(input_position,1.0);");
+        material.set_main("output_color = vec4(1.0);");
+        symbol.shader.set_geometry_material(&geometry);
+        symbol.shader.set_material(&material);
+
+        let mut frames = 0;
+        while workspace[symbol_id].shader.program().is_none() {
+            assert!(frames < 1000, "The symbol program was not ready in time.");
+            assert!(workspace.symbols_dirty.check_all(), "The compiling symbol is not updated.");
+            workspace.update_symbols();
+            frames += 1;
+        }
+        assert!(frames > 1);
+        assert!(!workspace[symbol_id].is_compiling());
+    }
+}
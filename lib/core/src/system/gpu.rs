@@ -1,5 +1,6 @@
 //! GPU-specific types and related implementations.
 
+pub mod context;
 pub mod data;
 pub mod shader;
 
@@ -0,0 +1,825 @@
+//! This module defines the `GpuContext` trait, an abstraction over the GPU calls the engine uses.
+//! It is implemented for the WebGL2 context and for the `MockContext`, which records all the calls
+//! instead of executing them, so the GPU code can be tested natively.
+
+pub mod mock;
+
+use crate::prelude::*;
+
+use crate::debug::capture;
+use crate::system::gpu::context::mock::Call;
+use crate::system::gpu::data::buffer::item::BufferItem;
+use crate::system::gpu::data::buffer::item::JsBufferView;
+use crate::system::gpu::data::buffer::item::JsBufferViewArr;
+use crate::system::gpu::shader::Context;
+use crate::system::gpu::shader::compiler::COMPLETION_STATUS_KHR;
+use crate::system::gpu::shader::compiler::PARALLEL_COMPILE_EXTENSION;
+
+use js_sys::Float32Array;
+use js_sys::Function;
+use js_sys::Object;
+use js_sys::Reflect;
+use js_sys::WeakMap;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::JsValue;
+use web_sys::HtmlImageElement;
+use web_sys::WebGlBuffer;
+use web_sys::WebGlFramebuffer;
+use web_sys::WebGlProgram;
+use web_sys::WebGlRenderbuffer;
+use web_sys::WebGlShader;
+use web_sys::WebGlTexture;
+use web_sys::WebGlUniformLocation;
+use web_sys::WebGlVertexArrayObject;
+
+
+
+// ==================
+// === GpuContext ===
+// ==================
+
+/// Abstraction over the GPU calls the engine uses. The method names and arguments follow the WebGL2
+/// API, see its documentation to learn more about particular calls:
+/// https://developer.mozilla.org/en-US/docs/Web/API/WebGL2RenderingContext
+///
+/// Calls with variants differing only by the number of components, like `uniform2fv` and
+/// `uniform3fv`, are merged into a single method taking the number of components. Contexts are
+/// cheap to clone, all clones refer to the same underlying context.
+pub trait GpuContext : Clone + Debug + 'static {
+    /// Handle of a buffer.
+    type Buffer : Clone + Debug + 'static;
+    /// Handle of a texture.
+    type Texture : Clone + Debug + 'static;
+    /// Handle of a renderbuffer.
+    type Renderbuffer : Clone + Debug + 'static;
+    /// Handle of a framebuffer.
+    type Framebuffer : Clone + Debug + 'static;
+    /// Handle of a shader.
+    type Shader : Clone + Debug + 'static;
+    /// Handle of a program.
+    type Program : Clone + Debug + 'static;
+    /// Location of a uniform in a program.
+    type UniformLocation : Clone + Debug + 'static;
+    /// Handle of a vertex array object.
+    type VertexArray : Clone + Debug + 'static;
+
+
+    // === Buffers ===
+
+    /// Creates a new buffer.
+    fn create_buffer(&self) -> Option<Self::Buffer>;
+    /// Deletes the buffer.
+    fn delete_buffer(&self, buffer:Option<&Self::Buffer>);
+    /// Binds the buffer to the target.
+    fn bind_buffer(&self, target:u32, buffer:Option<&Self::Buffer>);
+    /// Replaces the data of the buffer bound to the target.
+    fn buffer_data<T:BufferItem>(&self, target:u32, data:&[T], usage:u32);
+    /// Replaces `length` primitive items of the data starting at `dst_byte_offset` in the buffer
+    /// bound to the target. The items are read from the `data` starting at the `src_offset`
+    /// primitive item, so for example, the second `Vector2<f32>` starts at the item 2.
+    fn buffer_sub_data<T:BufferItem>
+    (&self, target:u32, dst_byte_offset:i32, data:&[T], src_offset:u32, length:u32);
+    /// Binds the buffer to the indexed binding point of the target, like `UNIFORM_BUFFER`.
+    fn bind_buffer_base(&self, target:u32, index:u32, buffer:Option<&Self::Buffer>);
+
+
+    // === Vertex Attributes ===
+
+    /// Enables the generic vertex attribute.
+    fn enable_vertex_attrib_array(&self, index:u32);
+    /// Specifies the layout of the generic vertex attribute.
+    fn vertex_attrib_pointer
+    (&self, index:u32, size:i32, tp:u32, normalize:bool, stride:i32, offset:i32);
+    /// Sets the rate at which the generic vertex attribute advances during instanced rendering.
+    fn vertex_attrib_divisor(&self, index:u32, divisor:u32);
+    /// Creates a new vertex array object.
+    fn create_vertex_array(&self) -> Option<Self::VertexArray>;
+    /// Deletes the vertex array object.
+    fn delete_vertex_array(&self, vao:Option<&Self::VertexArray>);
+    /// Binds the vertex array object.
+    fn bind_vertex_array(&self, vao:Option<&Self::VertexArray>);
+
+
+    // === Shaders and Programs ===
+
+    /// Creates a new shader of the type, like `VERTEX_SHADER`.
+    fn create_shader(&self, tp:u32) -> Option<Self::Shader>;
+    /// Deletes the shader.
+    fn delete_shader(&self, shader:Option<&Self::Shader>);
+    /// Sets the source code of the shader.
+    fn shader_source(&self, shader:&Self::Shader, source:&str);
+    /// Issues the compilation of the shader.
+    fn compile_shader(&self, shader:&Self::Shader);
+    /// Checks whether the shader was compiled successfully. Blocks until the compilation is done.
+    fn shader_compile_status(&self, shader:&Self::Shader) -> bool;
+    /// The info log of the shader compilation.
+    fn shader_info_log(&self, shader:&Self::Shader) -> Option<String>;
+    /// Creates a new program.
+    fn create_program(&self) -> Option<Self::Program>;
+    /// Deletes the program.
+    fn delete_program(&self, program:Option<&Self::Program>);
+    /// Attaches the shader to the program.
+    fn attach_shader(&self, program:&Self::Program, shader:&Self::Shader);
+    /// Issues the linking of the program.
+    fn link_program(&self, program:&Self::Program);
+    /// Checks whether the program was linked successfully. Blocks until the linking is done.
+    fn program_link_status(&self, program:&Self::Program) -> bool;
+    /// Checks whether the compilation and linking of the program is done, so its status can be
+    /// checked without blocking. Always true if the `KHR_parallel_shader_compile` extension is not
+    /// available.
+    fn program_completion_status(&self, program:&Self::Program) -> bool;
+    /// The info log of the program linking.
+    fn program_info_log(&self, program:&Self::Program) -> Option<String>;
+
+
+    // === Programs and Uniforms ===
+
+    /// Sets the program used by the following draw calls.
+    fn use_program(&self, program:Option<&Self::Program>);
+    /// Location of the attribute, or a negative value if the program does not use it.
+    fn get_attrib_location(&self, program:&Self::Program, name:&str) -> i32;
+    /// Location of the uniform, or `None` if the program does not use it.
+    fn get_uniform_location
+    (&self, program:&Self::Program, name:&str) -> Option<Self::UniformLocation>;
+    /// Sets the integer uniform, or the array of them, with 1 to 4 components.
+    fn uniform_iv(&self, location:Option<&Self::UniformLocation>, components:usize, data:&[i32]);
+    /// Sets the float uniform, or the array of them, with 1 to 4 components.
+    fn uniform_fv(&self, location:Option<&Self::UniformLocation>, components:usize, data:&[f32]);
+    /// Sets the matrix uniform, or the array of them, with the provided dimensions. The data is in
+    /// the column-major order.
+    fn uniform_matrix_fv
+    (&self, location:Option<&Self::UniformLocation>, cols:usize, rows:usize, data:&[f32]);
+    /// Index of the uniform block, or `INVALID_INDEX` if the program does not use it.
+    fn get_uniform_block_index(&self, program:&Self::Program, name:&str) -> u32;
+    /// Connects the uniform block of the program to the binding point.
+    fn uniform_block_binding(&self, program:&Self::Program, index:u32, binding:u32);
+
+
+    // === Textures and Framebuffers ===
+
+    /// Creates a new texture.
+    fn create_texture(&self) -> Option<Self::Texture>;
+    /// Deletes the texture.
+    fn delete_texture(&self, texture:Option<&Self::Texture>);
+    /// Binds the texture to the target of the active texture unit.
+    fn bind_texture(&self, target:u32, texture:Option<&Self::Texture>);
+    /// Selects the active texture unit.
+    fn active_texture(&self, unit:u32);
+    /// Sets the parameter of the texture bound to the target.
+    fn tex_parameteri(&self, target:u32, name:u32, value:i32);
+    /// Sets the float parameter of the texture bound to the target.
+    fn tex_parameterf(&self, target:u32, name:u32, value:f32);
+    /// The texture bound to the `TEXTURE_2D` target of the active texture unit.
+    fn bound_texture_2d(&self) -> Option<Self::Texture>;
+    /// Allocates the storage of the texture bound to the target. It is filled with the data if
+    /// provided, and left uninitialized otherwise.
+    #[allow(clippy::too_many_arguments)]
+    fn tex_image_2d<T:JsBufferViewArr>
+    ( &self, target:u32, level:i32, internal_format:i32, width:i32, height:i32, format:u32
+    , elem_type:u32, data:Option<&[T]>);
+    /// Allocates the storage of the texture bound to the target and fills it with the image.
+    fn tex_image_2d_with_image
+    ( &self, target:u32, level:i32, internal_format:i32, format:u32, elem_type:u32
+    , image:&HtmlImageElement);
+    /// Replaces the region of the texture bound to the target. The data is read according to the
+    /// pixel storage modes, see `pixel_storei`.
+    #[allow(clippy::too_many_arguments)]
+    fn tex_sub_image_2d<T:JsBufferViewArr>
+    ( &self, target:u32, level:i32, x:i32, y:i32, width:i32, height:i32, format:u32
+    , elem_type:u32, data:&[T]);
+    /// Sets the pixel storage mode used by the following texture uploads.
+    fn pixel_storei(&self, name:u32, value:i32);
+    /// Generates the mipmaps of the texture bound to the target.
+    fn generate_mipmap(&self, target:u32);
+    /// Creates a new framebuffer.
+    fn create_framebuffer(&self) -> Option<Self::Framebuffer>;
+    /// Deletes the framebuffer.
+    fn delete_framebuffer(&self, framebuffer:Option<&Self::Framebuffer>);
+    /// Binds the framebuffer to the target.
+    fn bind_framebuffer(&self, target:u32, framebuffer:Option<&Self::Framebuffer>);
+    /// Checks whether the framebuffer is valid. It is not after the context was lost.
+    fn is_framebuffer(&self, framebuffer:Option<&Self::Framebuffer>) -> bool;
+    /// Attaches the texture to the framebuffer bound to the target.
+    fn framebuffer_texture_2d
+    ( &self, target:u32, attachment:u32, tex_target:u32, texture:Option<&Self::Texture>
+    , level:i32);
+    /// Copies the source rectangle of the framebuffer bound to `READ_FRAMEBUFFER` to the
+    /// destination rectangle of the framebuffer bound to `DRAW_FRAMEBUFFER`.
+    #[allow(clippy::too_many_arguments)]
+    fn blit_framebuffer
+    ( &self, src_x0:i32, src_y0:i32, src_x1:i32, src_y1:i32, dst_x0:i32, dst_y0:i32, dst_x1:i32
+    , dst_y1:i32, mask:u32, filter:u32);
+    /// Creates a new renderbuffer.
+    fn create_renderbuffer(&self) -> Option<Self::Renderbuffer>;
+    /// Deletes the renderbuffer.
+    fn delete_renderbuffer(&self, renderbuffer:Option<&Self::Renderbuffer>);
+    /// Binds the renderbuffer to the target.
+    fn bind_renderbuffer(&self, target:u32, renderbuffer:Option<&Self::Renderbuffer>);
+    /// Allocates the storage of the renderbuffer bound to the target.
+    fn renderbuffer_storage(&self, target:u32, internal_format:u32, width:i32, height:i32);
+    /// Attaches the renderbuffer to the framebuffer bound to the target.
+    fn framebuffer_renderbuffer
+    (&self, target:u32, attachment:u32, rb_target:u32, renderbuffer:Option<&Self::Renderbuffer>);
+
+
+    // === State and Drawing ===
+
+    /// Enables the capability, like `BLEND`.
+    fn enable(&self, cap:u32);
+    /// Disables the capability, like `BLEND`.
+    fn disable(&self, cap:u32);
+    /// Sets the blending factors.
+    fn blend_func(&self, src:u32, dst:u32);
+    /// Sets the blending equation.
+    fn blend_equation(&self, mode:u32);
+    /// Sets the depth comparison function.
+    fn depth_func(&self, func:u32);
+    /// Enables or disables writing to the depth buffer.
+    fn depth_mask(&self, flag:bool);
+    /// Sets the viewport.
+    fn viewport(&self, x:i32, y:i32, width:i32, height:i32);
+    /// Sets the color used when clearing the color buffer.
+    fn clear_color(&self, r:f32, g:f32, b:f32, a:f32);
+    /// Sets the value used when clearing the depth buffer.
+    fn clear_depth(&self, depth:f32);
+    /// Clears the buffers selected by the mask.
+    fn clear(&self, mask:u32);
+    /// Renders primitives from the array data.
+    fn draw_arrays(&self, mode:u32, first:i32, count:i32);
+    /// Renders multiple instances of primitives from the array data.
+    fn draw_arrays_instanced(&self, mode:u32, first:i32, count:i32, instance_count:i32);
+
+
+    // === Queries ===
+
+    /// The integer parameter of the context, like `MAX_TEXTURE_IMAGE_UNITS`.
+    fn get_integer_parameter(&self, name:u32) -> Option<i32>;
+    /// Checks whether the extension is available. The extension is enabled by the first check.
+    fn has_extension(&self, name:&str) -> bool;
+    /// Forgets the extensions checked so far. Should be called after the context was restored, as
+    /// extensions are lost together with the context.
+    fn forget_extensions(&self);
+}
+
+
+
+// ========================
+// === WebGL2 Instances ===
+// ========================
+
+// Note [Safety]
+// =============
+// Usage of `js_buffer_view` is somewhat dangerous. It is creating a raw view into the module's
+// `WebAssembly.Memory` buffer, but if we allocate more pages for ourself (aka do a memory
+// allocation in Rust) it'll cause the buffer to change, causing the resulting js array to be
+// invalid. The views are passed to WebGL immediately, without any allocations in between.
+
+// Note [Frame Capture]
+// ====================
+// Every call is reported to the frame capture, which replays it on its log when a capture of the
+// context is in progress, see `debug::capture::record`. Calls are reported after they are issued,
+// so the handles of the created objects are known.
+
+// Note [Non-square Matrices]
+// ==========================
+// The `web_sys` bindings of `uniformMatrix2x3fv` and the other non-square matrix uploads are
+// broken, see https://github.com/rustwasm/wasm-bindgen/issues/1956. The methods are looked up on
+// the context object and called dynamically instead. The lookup does not evaluate any code, so it
+// works under strict Content Security Policies as well.
+
+// Note [Extension Cache]
+// =======================
+// Checking an extension requires a call to the context, which is slow, so the results are cached.
+// WebGL objects cannot keep any Rust state, so the cache is a weak map keyed by the context
+// object. Extensions are enabled by the first `getExtension` call and lost together with the
+// context, so the cache is cleared by `forget_extensions` when the context is restored.
+
+// See Note [Frame Capture].
+impl GpuContext for Context {
+    type Buffer          = WebGlBuffer;
+    type Texture         = WebGlTexture;
+    type Renderbuffer    = WebGlRenderbuffer;
+    type Framebuffer     = WebGlFramebuffer;
+    type Shader          = WebGlShader;
+    type Program         = WebGlProgram;
+    type UniformLocation = WebGlUniformLocation;
+    type VertexArray     = WebGlVertexArrayObject;
+
+    fn create_buffer(&self) -> Option<WebGlBuffer> {
+        let buffer = Context::create_buffer(self);
+        if let Some(buffer) = &buffer {
+            capture::record(self,|log,ids| {
+                let buffer = ids.get(buffer);
+                log.record(Call::CreateBuffer {buffer})
+            });
+        }
+        buffer
+    }
+
+    fn delete_buffer(&self, buffer:Option<&WebGlBuffer>) {
+        Context::delete_buffer(self,buffer);
+        capture::record(self,|log,ids| log.delete_buffer(ids.get_opt(buffer).as_ref()));
+    }
+
+    fn bind_buffer(&self, target:u32, buffer:Option<&WebGlBuffer>) {
+        Context::bind_buffer(self,target,buffer);
+        capture::record(self,|log,ids| log.bind_buffer(target,ids.get_opt(buffer).as_ref()));
+    }
+
+    fn buffer_data<T:BufferItem>(&self, target:u32, data:&[T], usage:u32) {
+        unsafe { // Note [Safety]
+            let js_array = data.js_buffer_view();
+            self.buffer_data_with_array_buffer_view(target,&js_array,usage);
+        }
+        capture::record(self,|log,_| log.buffer_data(target,data,usage));
+    }
+
+    fn buffer_sub_data<T:BufferItem>
+    (&self, target:u32, dst_byte_offset:i32, data:&[T], src_offset:u32, length:u32) {
+        unsafe { // Note [Safety]
+            let js_array = data.js_buffer_view();
+            self.buffer_sub_data_with_i32_and_array_buffer_view_and_src_offset_and_length
+            (target,dst_byte_offset,&js_array,src_offset,length)
+        }
+        capture::record(self,|log,_| {
+            log.buffer_sub_data(target,dst_byte_offset,data,src_offset,length)
+        });
+    }
+
+    fn bind_buffer_base(&self, target:u32, index:u32, buffer:Option<&WebGlBuffer>) {
+        Context::bind_buffer_base(self,target,index,buffer);
+        capture::record(self,|log,ids| {
+            log.bind_buffer_base(target,index,ids.get_opt(buffer).as_ref())
+        });
+    }
+
+    fn enable_vertex_attrib_array(&self, index:u32) {
+        Context::enable_vertex_attrib_array(self,index);
+        capture::record(self,|log,_| log.enable_vertex_attrib_array(index));
+    }
+
+    fn vertex_attrib_pointer
+    (&self, index:u32, size:i32, tp:u32, normalize:bool, stride:i32, offset:i32) {
+        self.vertex_attrib_pointer_with_i32(index,size,tp,normalize,stride,offset);
+        capture::record(self,|log,_| {
+            log.vertex_attrib_pointer(index,size,tp,normalize,stride,offset)
+        });
+    }
+
+    fn vertex_attrib_divisor(&self, index:u32, divisor:u32) {
+        Context::vertex_attrib_divisor(self,index,divisor);
+        capture::record(self,|log,_| log.vertex_attrib_divisor(index,divisor));
+    }
+
+    fn create_vertex_array(&self) -> Option<WebGlVertexArrayObject> {
+        let vao = Context::create_vertex_array(self);
+        if let Some(vao) = &vao {
+            capture::record(self,|log,ids| {
+                let vao = ids.get(vao);
+                log.record(Call::CreateVertexArray {vao})
+            });
+        }
+        vao
+    }
+
+    fn delete_vertex_array(&self, vao:Option<&WebGlVertexArrayObject>) {
+        Context::delete_vertex_array(self,vao);
+        capture::record(self,|log,ids| log.delete_vertex_array(ids.get_opt(vao).as_ref()));
+    }
+
+    fn bind_vertex_array(&self, vao:Option<&WebGlVertexArrayObject>) {
+        Context::bind_vertex_array(self,vao);
+        capture::record(self,|log,ids| log.bind_vertex_array(ids.get_opt(vao).as_ref()));
+    }
+
+    fn create_shader(&self, tp:u32) -> Option<WebGlShader> {
+        let shader = Context::create_shader(self,tp);
+        if let Some(shader) = &shader {
+            capture::record(self,|log,ids| {
+                let shader = ids.get(shader);
+                log.record(Call::CreateShader {shader,tp})
+            });
+        }
+        shader
+    }
+
+    fn delete_shader(&self, shader:Option<&WebGlShader>) {
+        Context::delete_shader(self,shader);
+        capture::record(self,|log,ids| log.delete_shader(ids.get_opt(shader).as_ref()));
+    }
+
+    fn shader_source(&self, shader:&WebGlShader, source:&str) {
+        Context::shader_source(self,shader,source);
+        capture::record(self,|log,ids| log.shader_source(&ids.get(shader),source));
+    }
+
+    fn compile_shader(&self, shader:&WebGlShader) {
+        Context::compile_shader(self,shader);
+        capture::record(self,|log,ids| log.compile_shader(&ids.get(shader)));
+    }
+
+    fn shader_compile_status(&self, shader:&WebGlShader) -> bool {
+        let status = self.get_shader_parameter(shader,Context::COMPILE_STATUS);
+        status.as_bool().unwrap_or(false)
+    }
+
+    fn shader_info_log(&self, shader:&WebGlShader) -> Option<String> {
+        self.get_shader_info_log(shader)
+    }
+
+    fn create_program(&self) -> Option<WebGlProgram> {
+        let program = Context::create_program(self);
+        if let Some(program) = &program {
+            capture::record(self,|log,ids| {
+                let program = ids.get(program);
+                log.record(Call::CreateProgram {program})
+            });
+        }
+        program
+    }
+
+    fn delete_program(&self, program:Option<&WebGlProgram>) {
+        Context::delete_program(self,program);
+        capture::record(self,|log,ids| log.delete_program(ids.get_opt(program).as_ref()));
+    }
+
+    fn attach_shader(&self, program:&WebGlProgram, shader:&WebGlShader) {
+        Context::attach_shader(self,program,shader);
+        capture::record(self,|log,ids| log.attach_shader(&ids.get(program),&ids.get(shader)));
+    }
+
+    fn link_program(&self, program:&WebGlProgram) {
+        Context::link_program(self,program);
+        capture::record(self,|log,ids| log.link_program(&ids.get(program)));
+    }
+
+    fn program_link_status(&self, program:&WebGlProgram) -> bool {
+        let status = self.get_program_parameter(program,Context::LINK_STATUS);
+        status.as_bool().unwrap_or(false)
+    }
+
+    fn program_completion_status(&self, program:&WebGlProgram) -> bool {
+        !self.has_extension(PARALLEL_COMPILE_EXTENSION) || {
+            let status = self.get_program_parameter(program,COMPLETION_STATUS_KHR);
+            status.as_bool().unwrap_or(true)
+        }
+    }
+
+    fn program_info_log(&self, program:&WebGlProgram) -> Option<String> {
+        self.get_program_info_log(program)
+    }
+
+    fn use_program(&self, program:Option<&WebGlProgram>) {
+        Context::use_program(self,program);
+        capture::record(self,|log,ids| log.use_program(ids.get_opt(program).as_ref()));
+    }
+
+    fn get_attrib_location(&self, program:&WebGlProgram, name:&str) -> i32 {
+        Context::get_attrib_location(self,program,name)
+    }
+
+    fn get_uniform_location
+    (&self, program:&WebGlProgram, name:&str) -> Option<WebGlUniformLocation> {
+        let location = Context::get_uniform_location(self,program,name);
+        if let Some(location) = &location {
+            capture::record_uniform_location(self,location,program,name);
+        }
+        location
+    }
+
+    fn uniform_iv(&self, location:Option<&WebGlUniformLocation>, components:usize, data:&[i32]) {
+        match components {
+            1 => self.uniform1iv_with_i32_array(location,data),
+            2 => self.uniform2iv_with_i32_array(location,data),
+            3 => self.uniform3iv_with_i32_array(location,data),
+            4 => self.uniform4iv_with_i32_array(location,data),
+            n => panic!("Unsupported number of uniform components: {}.",n),
+        }
+        capture::record(self,|log,ids| {
+            log.uniform_iv(ids.location_opt(location).as_ref(),components,data)
+        });
+    }
+
+    fn uniform_fv(&self, location:Option<&WebGlUniformLocation>, components:usize, data:&[f32]) {
+        match components {
+            1 => self.uniform1fv_with_f32_array(location,data),
+            2 => self.uniform2fv_with_f32_array(location,data),
+            3 => self.uniform3fv_with_f32_array(location,data),
+            4 => self.uniform4fv_with_f32_array(location,data),
+            n => panic!("Unsupported number of uniform components: {}.",n),
+        }
+        capture::record(self,|log,ids| {
+            log.uniform_fv(ids.location_opt(location).as_ref(),components,data)
+        });
+    }
+
+    fn uniform_matrix_fv
+    (&self, location:Option<&WebGlUniformLocation>, cols:usize, rows:usize, data:&[f32]) {
+        let transpose = false;
+        match (cols,rows) {
+            (2,2) => self.uniform_matrix2fv_with_f32_array  (location,transpose,data),
+            (3,3) => self.uniform_matrix3fv_with_f32_array  (location,transpose,data),
+            (4,4) => self.uniform_matrix4fv_with_f32_array  (location,transpose,data),
+            (2,3) | (2,4) | (3,2) | (3,4) | (4,2) | (4,3) => {
+                // Note [Non-square Matrices]
+                let name = format!("uniformMatrix{}x{}fv",cols,rows);
+                call_non_square_matrix_upload(self,&name,location,data)
+            }
+            _ => panic!("Unsupported matrix uniform dimensions: {}x{}.",cols,rows),
+        }
+        capture::record(self,|log,ids| {
+            log.uniform_matrix_fv(ids.location_opt(location).as_ref(),cols,rows,data)
+        });
+    }
+
+    fn get_uniform_block_index(&self, program:&WebGlProgram, name:&str) -> u32 {
+        Context::get_uniform_block_index(self,program,name)
+    }
+
+    fn uniform_block_binding(&self, program:&WebGlProgram, index:u32, binding:u32) {
+        Context::uniform_block_binding(self,program,index,binding);
+        capture::record(self,|log,ids| log.uniform_block_binding(&ids.get(program),index,binding));
+    }
+
+    fn create_texture(&self) -> Option<WebGlTexture> {
+        let texture = Context::create_texture(self);
+        if let Some(texture) = &texture {
+            capture::record(self,|log,ids| {
+                let texture = ids.get(texture);
+                log.record(Call::CreateTexture {texture})
+            });
+        }
+        texture
+    }
+
+    fn delete_texture(&self, texture:Option<&WebGlTexture>) {
+        Context::delete_texture(self,texture);
+        capture::record(self,|log,ids| log.delete_texture(ids.get_opt(texture).as_ref()));
+    }
+
+    fn bind_texture(&self, target:u32, texture:Option<&WebGlTexture>) {
+        Context::bind_texture(self,target,texture);
+        capture::record(self,|log,ids| log.bind_texture(target,ids.get_opt(texture).as_ref()));
+    }
+
+    fn active_texture(&self, unit:u32) {
+        Context::active_texture(self,unit);
+        capture::record(self,|log,_| log.active_texture(unit));
+    }
+
+    fn tex_parameteri(&self, target:u32, name:u32, value:i32) {
+        Context::tex_parameteri(self,target,name,value);
+        capture::record(self,|log,_| log.tex_parameteri(target,name,value));
+    }
+
+    fn tex_parameterf(&self, target:u32, name:u32, value:f32) {
+        Context::tex_parameterf(self,target,name,value);
+        capture::record(self,|log,_| log.tex_parameterf(target,name,value));
+    }
+
+    fn bound_texture_2d(&self) -> Option<WebGlTexture> {
+        let texture = self.get_parameter(Context::TEXTURE_BINDING_2D).ok();
+        texture.and_then(|texture| texture.dyn_into().ok())
+    }
+
+    fn tex_image_2d<T:JsBufferViewArr>
+    ( &self, target:u32, level:i32, internal_format:i32, width:i32, height:i32, format:u32
+    , elem_type:u32, data:Option<&[T]>) {
+        let border = 0;
+        unsafe { // Note [Safety]
+            let view = data.map(|data| data.js_buffer_view());
+            self.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view
+            (target,level,internal_format,width,height,border,format,elem_type,view.as_ref())
+        }.unwrap();
+        capture::record(self,|log,_| {
+            log.tex_image_2d(target,level,internal_format,width,height,format,elem_type,data)
+        });
+    }
+
+    fn tex_image_2d_with_image
+    ( &self, target:u32, level:i32, internal_format:i32, format:u32, elem_type:u32
+    , image:&HtmlImageElement) {
+        self.tex_image_2d_with_u32_and_u32_and_html_image_element
+            (target,level,internal_format,format,elem_type,image).unwrap();
+        capture::record(self,|log,_| {
+            log.tex_image_2d_with_image(target,level,internal_format,format,elem_type,image)
+        });
+    }
+
+    fn tex_sub_image_2d<T:JsBufferViewArr>
+    ( &self, target:u32, level:i32, x:i32, y:i32, width:i32, height:i32, format:u32
+    , elem_type:u32, data:&[T]) {
+        unsafe { // Note [Safety]
+            let view = data.js_buffer_view();
+            self.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_array_buffer_view
+            (target,level,x,y,width,height,format,elem_type,Some(&view))
+        }.unwrap();
+        capture::record(self,|log,_| {
+            log.tex_sub_image_2d(target,level,x,y,width,height,format,elem_type,data)
+        });
+    }
+
+    fn pixel_storei(&self, name:u32, value:i32) {
+        Context::pixel_storei(self,name,value);
+        capture::record(self,|log,_| log.pixel_storei(name,value));
+    }
+
+    fn generate_mipmap(&self, target:u32) {
+        Context::generate_mipmap(self,target);
+        capture::record(self,|log,_| log.generate_mipmap(target));
+    }
+
+    fn create_framebuffer(&self) -> Option<WebGlFramebuffer> {
+        let framebuffer = Context::create_framebuffer(self);
+        if let Some(framebuffer) = &framebuffer {
+            capture::record(self,|log,ids| {
+                let framebuffer = ids.get(framebuffer);
+                log.record(Call::CreateFramebuffer {framebuffer})
+            });
+        }
+        framebuffer
+    }
+
+    fn delete_framebuffer(&self, framebuffer:Option<&WebGlFramebuffer>) {
+        Context::delete_framebuffer(self,framebuffer);
+        capture::record(self,|log,ids| log.delete_framebuffer(ids.get_opt(framebuffer).as_ref()));
+    }
+
+    fn bind_framebuffer(&self, target:u32, framebuffer:Option<&WebGlFramebuffer>) {
+        Context::bind_framebuffer(self,target,framebuffer);
+        capture::record(self,|log,ids| {
+            log.bind_framebuffer(target,ids.get_opt(framebuffer).as_ref())
+        });
+    }
+
+    fn is_framebuffer(&self, framebuffer:Option<&WebGlFramebuffer>) -> bool {
+        Context::is_framebuffer(self,framebuffer)
+    }
+
+    fn framebuffer_texture_2d
+    ( &self, target:u32, attachment:u32, tex_target:u32, texture:Option<&WebGlTexture>
+    , level:i32) {
+        Context::framebuffer_texture_2d(self,target,attachment,tex_target,texture,level);
+        capture::record(self,|log,ids| {
+            let texture = ids.get_opt(texture);
+            log.framebuffer_texture_2d(target,attachment,tex_target,texture.as_ref(),level)
+        });
+    }
+
+    fn blit_framebuffer
+    ( &self, src_x0:i32, src_y0:i32, src_x1:i32, src_y1:i32, dst_x0:i32, dst_y0:i32, dst_x1:i32
+    , dst_y1:i32, mask:u32, filter:u32) {
+        Context::blit_framebuffer
+            (self,src_x0,src_y0,src_x1,src_y1,dst_x0,dst_y0,dst_x1,dst_y1,mask,filter);
+        capture::record(self,|log,_| {
+            log.blit_framebuffer
+                (src_x0,src_y0,src_x1,src_y1,dst_x0,dst_y0,dst_x1,dst_y1,mask,filter)
+        });
+    }
+
+    fn create_renderbuffer(&self) -> Option<WebGlRenderbuffer> {
+        let renderbuffer = Context::create_renderbuffer(self);
+        if let Some(renderbuffer) = &renderbuffer {
+            capture::record(self,|log,ids| {
+                let renderbuffer = ids.get(renderbuffer);
+                log.record(Call::CreateRenderbuffer {renderbuffer})
+            });
+        }
+        renderbuffer
+    }
+
+    fn delete_renderbuffer(&self, renderbuffer:Option<&WebGlRenderbuffer>) {
+        Context::delete_renderbuffer(self,renderbuffer);
+        capture::record(self,|log,ids| {
+            log.delete_renderbuffer(ids.get_opt(renderbuffer).as_ref())
+        });
+    }
+
+    fn bind_renderbuffer(&self, target:u32, renderbuffer:Option<&WebGlRenderbuffer>) {
+        Context::bind_renderbuffer(self,target,renderbuffer);
+        capture::record(self,|log,ids| {
+            log.bind_renderbuffer(target,ids.get_opt(renderbuffer).as_ref())
+        });
+    }
+
+    fn renderbuffer_storage(&self, target:u32, internal_format:u32, width:i32, height:i32) {
+        Context::renderbuffer_storage(self,target,internal_format,width,height);
+        capture::record(self,|log,_| log.renderbuffer_storage(target,internal_format,width,height));
+    }
+
+    fn framebuffer_renderbuffer
+    (&self, target:u32, attachment:u32, rb_target:u32, renderbuffer:Option<&WebGlRenderbuffer>) {
+        Context::framebuffer_renderbuffer(self,target,attachment,rb_target,renderbuffer);
+        capture::record(self,|log,ids| {
+            let renderbuffer = ids.get_opt(renderbuffer);
+            log.framebuffer_renderbuffer(target,attachment,rb_target,renderbuffer.as_ref())
+        });
+    }
+
+    fn enable(&self, cap:u32) {
+        Context::enable(self,cap);
+        capture::record(self,|log,_| log.enable(cap));
+    }
+
+    fn disable(&self, cap:u32) {
+        Context::disable(self,cap);
+        capture::record(self,|log,_| log.disable(cap));
+    }
+
+    fn blend_func(&self, src:u32, dst:u32) {
+        Context::blend_func(self,src,dst);
+        capture::record(self,|log,_| log.blend_func(src,dst));
+    }
+
+    fn blend_equation(&self, mode:u32) {
+        Context::blend_equation(self,mode);
+        capture::record(self,|log,_| log.blend_equation(mode));
+    }
+
+    fn depth_func(&self, func:u32) {
+        Context::depth_func(self,func);
+        capture::record(self,|log,_| log.depth_func(func));
+    }
+
+    fn depth_mask(&self, flag:bool) {
+        Context::depth_mask(self,flag);
+        capture::record(self,|log,_| log.depth_mask(flag));
+    }
+
+    fn viewport(&self, x:i32, y:i32, width:i32, height:i32) {
+        Context::viewport(self,x,y,width,height);
+        capture::record(self,|log,_| log.viewport(x,y,width,height));
+    }
+
+    fn clear_color(&self, r:f32, g:f32, b:f32, a:f32) {
+        Context::clear_color(self,r,g,b,a);
+        capture::record(self,|log,_| log.clear_color(r,g,b,a));
+    }
+
+    fn clear_depth(&self, depth:f32) {
+        Context::clear_depth(self,depth);
+        capture::record(self,|log,_| log.clear_depth(depth));
+    }
+
+    fn clear(&self, mask:u32) {
+        Context::clear(self,mask);
+        capture::record(self,|log,_| GpuContext::clear(log,mask));
+    }
+
+    fn draw_arrays(&self, mode:u32, first:i32, count:i32) {
+        Context::draw_arrays(self,mode,first,count);
+        capture::record(self,|log,_| log.draw_arrays(mode,first,count));
+    }
+
+    fn draw_arrays_instanced(&self, mode:u32, first:i32, count:i32, instance_count:i32) {
+        Context::draw_arrays_instanced(self,mode,first,count,instance_count);
+        capture::record(self,|log,_| log.draw_arrays_instanced(mode,first,count,instance_count));
+    }
+
+    fn get_integer_parameter(&self, name:u32) -> Option<i32> {
+        let value = self.get_parameter(name).ok();
+        value.and_then(|value| value.as_f64()).map(|value| value as i32)
+    }
+
+    // See Note [Extension Cache].
+    fn has_extension(&self, name:&str) -> bool {
+        EXTENSIONS.with(|extensions| {
+            let context = self.unchecked_ref::<Object>();
+            let known   = extensions.get(context);
+            let known   = if known.is_object() { known.unchecked_into() } else {
+                let known = Object::new();
+                extensions.set(context,&known);
+                known
+            };
+            let key    = JsValue::from_str(name);
+            let cached = Reflect::get(&known,&key).ok().and_then(|value| value.as_bool());
+            cached.unwrap_or_else(|| {
+                let available = self.get_extension(name).ok().flatten().is_some();
+                Reflect::set(&known,&key,&JsValue::from_bool(available)).ok();
+                available
+            })
+        })
+    }
+
+    fn forget_extensions(&self) {
+        EXTENSIONS.with(|extensions| extensions.delete(self.unchecked_ref()));
+    }
+}
+
+thread_local! {
+    /// The availability of the extensions checked so far, per context.
+    static EXTENSIONS : WeakMap = WeakMap::new();
+}
+
+/// Calls the non-square matrix upload method of the provided name. See
+/// Note [Non-square Matrices].
+fn call_non_square_matrix_upload
+(context:&Context, name:&str, location:Option<&WebGlUniformLocation>, data:&[f32]) {
+    let method    = Reflect::get(context,&name.into()).ok();
+    let method    = method.and_then(|method| method.dyn_into::<Function>().ok());
+    let method    = method.unwrap_or_else(|| panic!("The context does not support {}.",name));
+    let location  = location.cloned().map(JsValue::from).unwrap_or(JsValue::NULL);
+    let transpose = JsValue::from_bool(false);
+    let data      = Float32Array::from(data);
+    method.call3(context,&location,&transpose,&data).unwrap();
+}
@@ -0,0 +1,715 @@
+//! This module defines `MockContext`, a native implementation of `GpuContext` which does not
+//! execute any GPU calls, but records them in an inspectable log instead.
+
+use crate::prelude::*;
+
+use crate::system::gpu::context::GpuContext;
+use crate::system::gpu::data::buffer::item::BufferItem;
+use crate::system::gpu::data::buffer::item::JsBufferViewArr;
+use crate::system::gpu::shader::Context;
+
+use serde::Serialize;
+use std::cell::Cell;
+use std::mem;
+use web_sys::HtmlImageElement;
+
+
+
+// ==============
+// === MockId ===
+// ==============
+
+/// Handle of an object created by the `MockContext`. Ids are unique within a single context.
+#[derive(Clone,Copy,Debug,Eq,Hash,PartialEq,Serialize)]
+pub struct MockId(pub usize);
+
+/// Location of a uniform in a program created by the `MockContext`.
+#[derive(Clone,Debug,Eq,Hash,PartialEq,Serialize)]
+pub struct MockUniformLocation {
+    /// The program the uniform belongs to.
+    pub program : MockId,
+    /// Name of the uniform.
+    pub name    : String,
+}
+
+
+
+// ============
+// === Call ===
+// ============
+
+/// Value set by a uniform call.
+#[derive(Clone,Debug,PartialEq,Serialize)]
+pub enum UniformData {
+    /// Integer vector, or an array of them, with 1 to 4 components.
+    Int { components:usize, data:Vec<i32> },
+    /// Float vector, or an array of them, with 1 to 4 components.
+    Float { components:usize, data:Vec<f32> },
+    /// Matrix, or an array of them, with the provided dimensions, in the column-major order.
+    Matrix { cols:usize, rows:usize, data:Vec<f32> },
+}
+
+/// A single call recorded by the `MockContext`. Buffer data is not copied, only its size on the GPU
+/// and the uploaded range are recorded.
+#[allow(missing_docs)]
+#[derive(Clone,Debug,PartialEq,Serialize)]
+pub enum Call {
+    CreateBuffer            { buffer:MockId },
+    DeleteBuffer            { buffer:Option<MockId> },
+    BindBuffer              { target:u32, buffer:Option<MockId> },
+    BufferData              { target:u32, byte_size:usize, usage:u32 },
+    BufferSubData           { target:u32, dst_byte_offset:i32, src_offset:u32, length:u32 },
+    BindBufferBase          { target:u32, index:u32, buffer:Option<MockId> },
+    EnableVertexAttribArray { index:u32 },
+    VertexAttribPointer     { index:u32, size:i32, tp:u32, normalize:bool, stride:i32, offset:i32 },
+    VertexAttribDivisor     { index:u32, divisor:u32 },
+    CreateVertexArray       { vao:MockId },
+    DeleteVertexArray       { vao:Option<MockId> },
+    BindVertexArray         { vao:Option<MockId> },
+    CreateShader            { shader:MockId, tp:u32 },
+    DeleteShader            { shader:Option<MockId> },
+    ShaderSource            { shader:MockId },
+    CompileShader           { shader:MockId },
+    CreateProgram           { program:MockId },
+    DeleteProgram           { program:Option<MockId> },
+    AttachShader            { program:MockId, shader:MockId },
+    LinkProgram             { program:MockId },
+    UseProgram              { program:Option<MockId> },
+    Uniform                 { location:Option<MockUniformLocation>, data:UniformData },
+    UniformBlockBinding     { program:MockId, index:u32, binding:u32 },
+    CreateTexture           { texture:MockId },
+    DeleteTexture           { texture:Option<MockId> },
+    BindTexture             { target:u32, texture:Option<MockId> },
+    ActiveTexture           { unit:u32 },
+    TexParameter            { target:u32, name:u32, value:i32 },
+    TexParameterf           { target:u32, name:u32, value:f32 },
+    TexImage2D              { target:u32, level:i32, internal_format:i32, width:i32, height:i32
+                            , format:u32, elem_type:u32, data_len:Option<usize> },
+    TexImage2DFromImage     { target:u32, level:i32, internal_format:i32, format:u32
+                            , elem_type:u32 },
+    TexSubImage2D           { target:u32, level:i32, x:i32, y:i32, width:i32, height:i32
+                            , format:u32, elem_type:u32 },
+    PixelStore              { name:u32, value:i32 },
+    GenerateMipmap          { target:u32 },
+    CreateFramebuffer       { framebuffer:MockId },
+    DeleteFramebuffer       { framebuffer:Option<MockId> },
+    BindFramebuffer         { target:u32, framebuffer:Option<MockId> },
+    FramebufferTexture2D    { target:u32, attachment:u32, tex_target:u32
+                            , texture:Option<MockId>, level:i32 },
+    BlitFramebuffer         { src:(i32,i32,i32,i32), dst:(i32,i32,i32,i32), mask:u32
+                            , filter:u32 },
+    CreateRenderbuffer      { renderbuffer:MockId },
+    DeleteRenderbuffer      { renderbuffer:Option<MockId> },
+    BindRenderbuffer        { target:u32, renderbuffer:Option<MockId> },
+    RenderbufferStorage     { target:u32, internal_format:u32, width:i32, height:i32 },
+    FramebufferRenderbuffer { target:u32, attachment:u32, rb_target:u32
+                            , renderbuffer:Option<MockId> },
+    Enable                  { cap:u32 },
+    Disable                 { cap:u32 },
+    BlendFunc               { src:u32, dst:u32 },
+    BlendEquation           { mode:u32 },
+    DepthFunc               { func:u32 },
+    DepthMask               { flag:bool },
+    Viewport                { x:i32, y:i32, width:i32, height:i32 },
+    ClearColor              { r:f32, g:f32, b:f32, a:f32 },
+    ClearDepth              { depth:f32 },
+    Clear                   { mask:u32 },
+    DrawArrays              { mode:u32, first:i32, count:i32 },
+    DrawArraysInstanced     { mode:u32, first:i32, count:i32, instance_count:i32 },
+}
+
+impl Call {
+    /// Checks whether the call uploads buffer data.
+    pub fn is_buffer_upload(&self) -> bool {
+        match self {
+            Self::BufferData {..} | Self::BufferSubData {..} => true,
+            _                                                => false,
+        }
+    }
+
+    /// Checks whether the call sets a uniform.
+    pub fn is_uniform(&self) -> bool {
+        match self {
+            Self::Uniform {..} => true,
+            _                  => false,
+        }
+    }
+
+    /// Checks whether the call is a draw call.
+    pub fn is_draw(&self) -> bool {
+        match self {
+            Self::DrawArrays {..} | Self::DrawArraysInstanced {..} => true,
+            _                                                      => false,
+        }
+    }
+}
+
+
+
+// ===================
+// === MockContext ===
+// ===================
+
+/// Native implementation of `GpuContext` recording all the calls. Attributes of all programs have
+/// consecutive locations assigned in the order they are queried. Clones share the log, so the
+/// calls of the objects keeping a clone of the context can be inspected.
+///
+/// Shaders containing the `#error` directive fail to compile, and programs fail to link if any of
+/// their shaders failed. No extensions are available until they are enabled with
+/// `enable_extension`.
+#[derive(Clone,Debug,Default)]
+pub struct MockContext {
+    calls               : Rc<RefCell<Vec<Call>>>,
+    next_id             : Rc<Cell<usize>>,
+    attribute_locations : Rc<RefCell<HashMap<(MockId,String),i32>>>,
+    shader_sources      : Rc<RefCell<HashMap<MockId,String>>>,
+    program_shaders     : Rc<RefCell<HashMap<MockId,Vec<MockId>>>>,
+    active_unit         : Rc<Cell<u32>>,
+    bound_textures      : Rc<RefCell<HashMap<u32,MockId>>>,
+    extensions          : Rc<RefCell<HashSet<String>>>,
+}
+
+/// The value of `MAX_TEXTURE_IMAGE_UNITS` reported by the `MockContext`.
+pub const MAX_TEXTURE_IMAGE_UNITS : i32 = 16;
+
+impl MockContext {
+    /// Constructor.
+    pub fn new() -> Self {
+        default()
+    }
+
+    /// Makes the extension available.
+    pub fn enable_extension(&self, name:&str) {
+        self.extensions.borrow_mut().insert(name.into());
+    }
+
+    /// All the calls recorded so far.
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.borrow().clone()
+    }
+
+    /// Takes all the calls recorded so far, clearing the log.
+    pub fn take_calls(&self) -> Vec<Call> {
+        mem::take(&mut *self.calls.borrow_mut())
+    }
+
+    /// Clears the log.
+    pub fn clear(&self) {
+        self.calls.borrow_mut().clear();
+    }
+
+    /// All the recorded calls uploading buffer data.
+    pub fn buffer_uploads(&self) -> Vec<Call> {
+        self.calls.borrow().iter().filter(|call| call.is_buffer_upload()).cloned().collect()
+    }
+
+    /// All the recorded calls setting uniforms.
+    pub fn uniform_calls(&self) -> Vec<Call> {
+        self.calls.borrow().iter().filter(|call| call.is_uniform()).cloned().collect()
+    }
+
+    /// All the recorded draw calls.
+    pub fn draw_calls(&self) -> Vec<Call> {
+        self.calls.borrow().iter().filter(|call| call.is_draw()).cloned().collect()
+    }
+
+    /// Appends the call to the log. Used to record the calls issued to other contexts, see the
+    /// frame capture.
+    pub fn record(&self, call:Call) {
+        self.calls.borrow_mut().push(call);
+    }
+
+    fn new_id(&self) -> MockId {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        MockId(id)
+    }
+
+    /// The info log of the shader. The error is reported at the line of the `#error` directive.
+    fn compile_error(&self, shader:&MockId) -> Option<String> {
+        let sources = self.shader_sources.borrow();
+        let source  = sources.get(shader)?;
+        let line    = source.lines().position(|line| line.trim_start().starts_with("#error"))?;
+        Some(format!("ERROR: 0:{}: '#error' : error directive\n",line + 1))
+    }
+}
+
+impl GpuContext for MockContext {
+    type Buffer          = MockId;
+    type Texture         = MockId;
+    type Renderbuffer    = MockId;
+    type Framebuffer     = MockId;
+    type Shader          = MockId;
+    type Program         = MockId;
+    type UniformLocation = MockUniformLocation;
+    type VertexArray     = MockId;
+
+    fn create_buffer(&self) -> Option<MockId> {
+        let buffer = self.new_id();
+        self.record(Call::CreateBuffer {buffer});
+        Some(buffer)
+    }
+
+    fn delete_buffer(&self, buffer:Option<&MockId>) {
+        let buffer = buffer.copied();
+        self.record(Call::DeleteBuffer {buffer});
+    }
+
+    fn bind_buffer(&self, target:u32, buffer:Option<&MockId>) {
+        let buffer = buffer.copied();
+        self.record(Call::BindBuffer {target,buffer});
+    }
+
+    fn buffer_data<T:BufferItem>(&self, target:u32, data:&[T], usage:u32) {
+        let byte_size = data.len() * T::item_count() * T::item_gpu_byte_size();
+        self.record(Call::BufferData {target,byte_size,usage});
+    }
+
+    fn buffer_sub_data<T:BufferItem>
+    (&self, target:u32, dst_byte_offset:i32, _data:&[T], src_offset:u32, length:u32) {
+        self.record(Call::BufferSubData {target,dst_byte_offset,src_offset,length});
+    }
+
+    fn bind_buffer_base(&self, target:u32, index:u32, buffer:Option<&MockId>) {
+        let buffer = buffer.copied();
+        self.record(Call::BindBufferBase {target,index,buffer});
+    }
+
+    fn enable_vertex_attrib_array(&self, index:u32) {
+        self.record(Call::EnableVertexAttribArray {index});
+    }
+
+    fn vertex_attrib_pointer
+    (&self, index:u32, size:i32, tp:u32, normalize:bool, stride:i32, offset:i32) {
+        self.record(Call::VertexAttribPointer {index,size,tp,normalize,stride,offset});
+    }
+
+    fn vertex_attrib_divisor(&self, index:u32, divisor:u32) {
+        self.record(Call::VertexAttribDivisor {index,divisor});
+    }
+
+    fn create_vertex_array(&self) -> Option<MockId> {
+        let vao = self.new_id();
+        self.record(Call::CreateVertexArray {vao});
+        Some(vao)
+    }
+
+    fn delete_vertex_array(&self, vao:Option<&MockId>) {
+        let vao = vao.copied();
+        self.record(Call::DeleteVertexArray {vao});
+    }
+
+    fn bind_vertex_array(&self, vao:Option<&MockId>) {
+        let vao = vao.copied();
+        self.record(Call::BindVertexArray {vao});
+    }
+
+    fn create_shader(&self, tp:u32) -> Option<MockId> {
+        let shader = self.new_id();
+        self.record(Call::CreateShader {shader,tp});
+        Some(shader)
+    }
+
+    fn delete_shader(&self, shader:Option<&MockId>) {
+        let shader = shader.copied();
+        self.record(Call::DeleteShader {shader});
+    }
+
+    fn shader_source(&self, shader:&MockId, source:&str) {
+        let shader = *shader;
+        self.shader_sources.borrow_mut().insert(shader,source.into());
+        self.record(Call::ShaderSource {shader});
+    }
+
+    fn compile_shader(&self, shader:&MockId) {
+        let shader = *shader;
+        self.record(Call::CompileShader {shader});
+    }
+
+    fn shader_compile_status(&self, shader:&MockId) -> bool {
+        self.compile_error(shader).is_none()
+    }
+
+    fn shader_info_log(&self, shader:&MockId) -> Option<String> {
+        self.compile_error(shader)
+    }
+
+    fn create_program(&self) -> Option<MockId> {
+        let program = self.new_id();
+        self.record(Call::CreateProgram {program});
+        Some(program)
+    }
+
+    fn delete_program(&self, program:Option<&MockId>) {
+        let program = program.copied();
+        self.record(Call::DeleteProgram {program});
+    }
+
+    fn attach_shader(&self, program:&MockId, shader:&MockId) {
+        let (program,shader) = (*program,*shader);
+        self.program_shaders.borrow_mut().entry(program).or_default().push(shader);
+        self.record(Call::AttachShader {program,shader});
+    }
+
+    fn link_program(&self, program:&MockId) {
+        let program = *program;
+        self.record(Call::LinkProgram {program});
+    }
+
+    fn program_link_status(&self, program:&MockId) -> bool {
+        let shaders = self.program_shaders.borrow();
+        let shaders = shaders.get(program).map(|shaders| shaders.as_slice()).unwrap_or(&[]);
+        shaders.iter().all(|shader| self.shader_compile_status(shader))
+    }
+
+    fn program_completion_status(&self, _program:&MockId) -> bool {
+        true
+    }
+
+    fn program_info_log(&self, program:&MockId) -> Option<String> {
+        if self.program_link_status(program) { None } else {
+            Some("ERROR: One or more attached shaders not successfully compiled\n".into())
+        }
+    }
+
+    fn use_program(&self, program:Option<&MockId>) {
+        let program = program.copied();
+        self.record(Call::UseProgram {program});
+    }
+
+    fn get_attrib_location(&self, program:&MockId, name:&str) -> i32 {
+        let mut locations = self.attribute_locations.borrow_mut();
+        let next_location = locations.keys().filter(|(p,_)| p == program).count() as i32;
+        *locations.entry((*program,name.into())).or_insert(next_location)
+    }
+
+    fn get_uniform_location(&self, program:&MockId, name:&str) -> Option<MockUniformLocation> {
+        let program = *program;
+        let name    = name.into();
+        Some(MockUniformLocation {program,name})
+    }
+
+    fn uniform_iv(&self, location:Option<&MockUniformLocation>, components:usize, data:&[i32]) {
+        let location = location.cloned();
+        let data     = data.to_vec();
+        let data     = UniformData::Int {components,data};
+        self.record(Call::Uniform {location,data});
+    }
+
+    fn uniform_fv(&self, location:Option<&MockUniformLocation>, components:usize, data:&[f32]) {
+        let location = location.cloned();
+        let data     = data.to_vec();
+        let data     = UniformData::Float {components,data};
+        self.record(Call::Uniform {location,data});
+    }
+
+    fn uniform_matrix_fv
+    (&self, location:Option<&MockUniformLocation>, cols:usize, rows:usize, data:&[f32]) {
+        let location = location.cloned();
+        let data     = data.to_vec();
+        let data     = UniformData::Matrix {cols,rows,data};
+        self.record(Call::Uniform {location,data});
+    }
+
+    fn get_uniform_block_index(&self, _program:&MockId, _name:&str) -> u32 {
+        0
+    }
+
+    fn uniform_block_binding(&self, program:&MockId, index:u32, binding:u32) {
+        let program = *program;
+        self.record(Call::UniformBlockBinding {program,index,binding});
+    }
+
+    fn create_texture(&self) -> Option<MockId> {
+        let texture = self.new_id();
+        self.record(Call::CreateTexture {texture});
+        Some(texture)
+    }
+
+    fn delete_texture(&self, texture:Option<&MockId>) {
+        let texture = texture.copied();
+        self.record(Call::DeleteTexture {texture});
+    }
+
+    fn bind_texture(&self, target:u32, texture:Option<&MockId>) {
+        let texture = texture.copied();
+        if target == Context::TEXTURE_2D {
+            let unit         = self.active_unit.get();
+            let mut textures = self.bound_textures.borrow_mut();
+            match texture {
+                Some(texture) => textures.insert(unit,texture),
+                None          => textures.remove(&unit),
+            };
+        }
+        self.record(Call::BindTexture {target,texture});
+    }
+
+    fn active_texture(&self, unit:u32) {
+        self.active_unit.set(unit.saturating_sub(Context::TEXTURE0));
+        self.record(Call::ActiveTexture {unit});
+    }
+
+    fn tex_parameteri(&self, target:u32, name:u32, value:i32) {
+        self.record(Call::TexParameter {target,name,value});
+    }
+
+    fn tex_parameterf(&self, target:u32, name:u32, value:f32) {
+        self.record(Call::TexParameterf {target,name,value});
+    }
+
+    fn bound_texture_2d(&self) -> Option<MockId> {
+        self.bound_textures.borrow().get(&self.active_unit.get()).copied()
+    }
+
+    fn tex_image_2d<T:JsBufferViewArr>
+    ( &self, target:u32, level:i32, internal_format:i32, width:i32, height:i32, format:u32
+    , elem_type:u32, data:Option<&[T]>) {
+        let data_len = data.map(|data| data.len());
+        self.record(Call::TexImage2D
+            {target,level,internal_format,width,height,format,elem_type,data_len});
+    }
+
+    fn tex_image_2d_with_image
+    ( &self, target:u32, level:i32, internal_format:i32, format:u32, elem_type:u32
+    , _image:&HtmlImageElement) {
+        self.record(Call::TexImage2DFromImage {target,level,internal_format,format,elem_type});
+    }
+
+    fn tex_sub_image_2d<T:JsBufferViewArr>
+    ( &self, target:u32, level:i32, x:i32, y:i32, width:i32, height:i32, format:u32
+    , elem_type:u32, _data:&[T]) {
+        self.record(Call::TexSubImage2D {target,level,x,y,width,height,format,elem_type});
+    }
+
+    fn pixel_storei(&self, name:u32, value:i32) {
+        self.record(Call::PixelStore {name,value});
+    }
+
+    fn generate_mipmap(&self, target:u32) {
+        self.record(Call::GenerateMipmap {target});
+    }
+
+    fn create_framebuffer(&self) -> Option<MockId> {
+        let framebuffer = self.new_id();
+        self.record(Call::CreateFramebuffer {framebuffer});
+        Some(framebuffer)
+    }
+
+    fn delete_framebuffer(&self, framebuffer:Option<&MockId>) {
+        let framebuffer = framebuffer.copied();
+        self.record(Call::DeleteFramebuffer {framebuffer});
+    }
+
+    fn bind_framebuffer(&self, target:u32, framebuffer:Option<&MockId>) {
+        let framebuffer = framebuffer.copied();
+        self.record(Call::BindFramebuffer {target,framebuffer});
+    }
+
+    fn is_framebuffer(&self, framebuffer:Option<&MockId>) -> bool {
+        framebuffer.is_some()
+    }
+
+    fn framebuffer_texture_2d
+    ( &self, target:u32, attachment:u32, tex_target:u32, texture:Option<&MockId>
+    , level:i32) {
+        let texture = texture.copied();
+        self.record(Call::FramebufferTexture2D {target,attachment,tex_target,texture,level});
+    }
+
+    fn blit_framebuffer
+    ( &self, src_x0:i32, src_y0:i32, src_x1:i32, src_y1:i32, dst_x0:i32, dst_y0:i32, dst_x1:i32
+    , dst_y1:i32, mask:u32, filter:u32) {
+        let src = (src_x0,src_y0,src_x1,src_y1);
+        let dst = (dst_x0,dst_y0,dst_x1,dst_y1);
+        self.record(Call::BlitFramebuffer {src,dst,mask,filter});
+    }
+
+    fn create_renderbuffer(&self) -> Option<MockId> {
+        let renderbuffer = self.new_id();
+        self.record(Call::CreateRenderbuffer {renderbuffer});
+        Some(renderbuffer)
+    }
+
+    fn delete_renderbuffer(&self, renderbuffer:Option<&MockId>) {
+        let renderbuffer = renderbuffer.copied();
+        self.record(Call::DeleteRenderbuffer {renderbuffer});
+    }
+
+    fn bind_renderbuffer(&self, target:u32, renderbuffer:Option<&MockId>) {
+        let renderbuffer = renderbuffer.copied();
+        self.record(Call::BindRenderbuffer {target,renderbuffer});
+    }
+
+    fn renderbuffer_storage(&self, target:u32, internal_format:u32, width:i32, height:i32) {
+        self.record(Call::RenderbufferStorage {target,internal_format,width,height});
+    }
+
+    fn framebuffer_renderbuffer
+    (&self, target:u32, attachment:u32, rb_target:u32, renderbuffer:Option<&MockId>) {
+        let renderbuffer = renderbuffer.copied();
+        self.record(Call::FramebufferRenderbuffer {target,attachment,rb_target,renderbuffer});
+    }
+
+    fn enable(&self, cap:u32) {
+        self.record(Call::Enable {cap});
+    }
+
+    fn disable(&self, cap:u32) {
+        self.record(Call::Disable {cap});
+    }
+
+    fn blend_func(&self, src:u32, dst:u32) {
+        self.record(Call::BlendFunc {src,dst});
+    }
+
+    fn blend_equation(&self, mode:u32) {
+        self.record(Call::BlendEquation {mode});
+    }
+
+    fn depth_func(&self, func:u32) {
+        self.record(Call::DepthFunc {func});
+    }
+
+    fn depth_mask(&self, flag:bool) {
+        self.record(Call::DepthMask {flag});
+    }
+
+    fn viewport(&self, x:i32, y:i32, width:i32, height:i32) {
+        self.record(Call::Viewport {x,y,width,height});
+    }
+
+    fn clear_color(&self, r:f32, g:f32, b:f32, a:f32) {
+        self.record(Call::ClearColor {r,g,b,a});
+    }
+
+    fn clear_depth(&self, depth:f32) {
+        self.record(Call::ClearDepth {depth});
+    }
+
+    fn clear(&self, mask:u32) {
+        self.record(Call::Clear {mask});
+    }
+
+    fn draw_arrays(&self, mode:u32, first:i32, count:i32) {
+        self.record(Call::DrawArrays {mode,first,count});
+    }
+
+    fn draw_arrays_instanced(&self, mode:u32, first:i32, count:i32, instance_count:i32) {
+        self.record(Call::DrawArraysInstanced {mode,first,count,instance_count});
+    }
+
+    fn get_integer_parameter(&self, name:u32) -> Option<i32> {
+        match name {
+            Context::MAX_TEXTURE_IMAGE_UNITS => Some(MAX_TEXTURE_IMAGE_UNITS),
+            _                                => None,
+        }
+    }
+
+    fn has_extension(&self, name:&str) -> bool {
+        self.extensions.borrow().contains(name)
+    }
+
+    fn forget_extensions(&self) {}
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nalgebra::Matrix4;
+
+    #[test]
+    fn calls_are_recorded_in_order() {
+        let context = MockContext::new();
+        let buffer  = context.create_buffer().unwrap();
+        context.bind_buffer(1,Some(&buffer));
+        context.buffer_data(1,&[1.0_f32,2.0,3.0][..],2);
+        context.draw_arrays(4,0,3);
+        assert_eq!(context.calls(), vec!
+            [ Call::CreateBuffer  {buffer}
+            , Call::BindBuffer    {target:1, buffer:Some(buffer)}
+            , Call::BufferData    {target:1, byte_size:12, usage:2}
+            , Call::DrawArrays    {mode:4, first:0, count:3}
+            ]);
+        assert_eq!(context.buffer_uploads().len(), 1);
+        assert_eq!(context.draw_calls().len(), 1);
+        assert_eq!(context.take_calls().len(), 4);
+        assert!(context.calls().is_empty());
+    }
+
+    #[test]
+    fn uploads_record_the_gpu_byte_size() {
+        let context = MockContext::new();
+        context.buffer_data(1,&[true,false,true][..],2);
+        context.buffer_data(1,&[Matrix4::<f32>::identity()][..],2);
+        assert_eq!(context.buffer_uploads(), vec!
+            [ Call::BufferData {target:1, byte_size:12, usage:2}
+            , Call::BufferData {target:1, byte_size:64, usage:2}
+            ]);
+    }
+
+    #[test]
+    fn clones_share_the_log() {
+        let context = MockContext::new();
+        context.clone().clear_depth(1.0);
+        assert_eq!(context.calls(), vec![Call::ClearDepth {depth:1.0}]);
+    }
+
+    #[test]
+    fn handles_are_unique() {
+        let context = MockContext::new();
+        let buffer  = context.create_buffer().unwrap();
+        let texture = context.create_texture().unwrap();
+        let program = context.create_program().unwrap();
+        assert_ne!(buffer, texture);
+        assert_ne!(texture, program);
+    }
+
+    #[test]
+    fn attribute_locations_are_consecutive_per_program() {
+        let context  = MockContext::new();
+        let program1 = context.create_program().unwrap();
+        let program2 = context.create_program().unwrap();
+        assert_eq!(context.get_attrib_location(&program1,"position"), 0);
+        assert_eq!(context.get_attrib_location(&program1,"color"), 1);
+        assert_eq!(context.get_attrib_location(&program1,"position"), 0);
+        assert_eq!(context.get_attrib_location(&program2,"color"), 0);
+    }
+
+    #[test]
+    fn shaders_with_error_directives_fail() {
+        let context = MockContext::new();
+        let valid   = context.create_shader(Context::VERTEX_SHADER).unwrap();
+        let invalid = context.create_shader(Context::FRAGMENT_SHADER).unwrap();
+        context.shader_source(&valid,"void main() {}");
+        context.shader_source(&invalid,"void main() {}\n#error broken");
+        assert!(context.shader_compile_status(&valid));
+        assert!(!context.shader_compile_status(&invalid));
+        assert!(context.shader_info_log(&invalid).unwrap().starts_with("ERROR: 0:2:"));
+        let program = context.create_program().unwrap();
+        context.attach_shader(&program,&valid);
+        assert!(context.program_link_status(&program));
+        context.attach_shader(&program,&invalid);
+        assert!(!context.program_link_status(&program));
+    }
+
+    #[test]
+    fn bound_textures_are_tracked_per_unit() {
+        let context  = MockContext::new();
+        let texture1 = context.create_texture().unwrap();
+        let texture2 = context.create_texture().unwrap();
+        context.bind_texture(Context::TEXTURE_2D,Some(&texture1));
+        context.active_texture(Context::TEXTURE1);
+        assert_eq!(context.bound_texture_2d(), None);
+        context.bind_texture(Context::TEXTURE_2D,Some(&texture2));
+        assert_eq!(context.bound_texture_2d(), Some(texture2));
+        context.active_texture(Context::TEXTURE0);
+        assert_eq!(context.bound_texture_2d(), Some(texture1));
+    }
+}
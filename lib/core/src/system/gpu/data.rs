@@ -42,6 +42,8 @@ pub mod types {
     pub use uniform::AnyTextureUniform;
     pub use uniform::AnyUniform;
     pub use uniform::Uniform;
+    pub use uniform::UniformArray;
     pub use uniform::UniformScope;
+    pub use uniform::UniformStruct;
 }
 pub use types::*;
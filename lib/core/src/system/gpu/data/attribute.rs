@@ -8,10 +8,13 @@ use crate::data::dirty;
 use crate::data::OptVec;
 use crate::debug::Stats;
 use crate::system::gpu::Context;
+use crate::system::gpu::context::GpuContext;
 
 use crate::data::dirty::traits::*;
 use crate::system::gpu::types::*;
 
+use shapely::shared;
+
 
 
 // ======================
@@ -21,20 +24,35 @@ use crate::system::gpu::types::*;
 /// Scope defines a view for geometry structure. For example, there is point
 /// scope or instance scope. Scope contains buffer of data for each item it
 /// describes.
+///
+/// Live instances are always stored at the beginning of the buffers, followed by the disposed
+/// ones, which are kept for reuse. Only the live instances are drawn. When the number of disposed
+/// instances gets big, buffers are shrunk.
 #[derive(Debug)]
-pub struct AttributeScope {
-    buffers         : OptVec<AnyBuffer>,
+pub struct AttributeScope<C:GpuContext=Context> {
+    buffers         : OptVec<AnyBuffer<C>>,
     buffer_dirty    : BufferDirty,
     shape_dirty     : ShapeDirty,
     buffer_name_map : HashMap<String,BufferIndex>,
     logger          : Logger,
-    free_ids        : Vec<AttributeInstanceIndex>,
+    instance_map    : InstanceMap,
     size            : usize,
-    context         : Context,
+    capacity        : usize,
+    context         : C,
     stats           : Stats,
 }
 
 
+// === Constants ===
+
+/// Buffers with capacity lower than this value are never shrunk automatically.
+const SHRINK_MIN_CAPACITY : usize = 1024;
+
+/// Buffers are shrunk automatically when their capacity is this many times bigger than the number
+/// of live instances.
+const SHRINK_RATIO : usize = 4;
+
+
 // === Types ===
 
 newtype_copy! {
@@ -67,10 +85,10 @@ fn buffer_on_resize(dirty:ShapeDirty) -> BufferOnResize {
 
 // === Implementation ===
 
-impl AttributeScope {
+impl<C:GpuContext> AttributeScope<C> {
     /// Create a new scope with the provided dirty callback.
     pub fn new<OnMut:CallbackFn+Clone>
-    (logger:Logger, stats:&Stats, context:&Context, on_mut:OnMut) -> Self {
+    (logger:Logger, stats:&Stats, context:&C, on_mut:OnMut) -> Self {
         info!(logger,"Initializing.",{
             let stats           = stats.clone_ref();
             let buffer_logger   = logger.sub("buffer_dirty");
@@ -79,19 +97,20 @@ impl AttributeScope {
             let shape_dirty     = ShapeDirty::new(shape_logger,Box::new(on_mut));
             let buffers         = default();
             let buffer_name_map = default();
-            let free_ids        = default();
+            let instance_map    = default();
             let size            = default();
+            let capacity        = default();
             let context         = context.clone();
-            Self {context,buffers,buffer_dirty,shape_dirty,buffer_name_map,logger,free_ids,size
-                 ,stats}
+            Self {context,buffers,buffer_dirty,shape_dirty,buffer_name_map,logger,instance_map
+                 ,size,capacity,stats}
         })
     }
 }
 
-impl AttributeScope {
+impl<C:GpuContext> AttributeScope<C> {
     /// Adds a new named buffer to the scope.
-    pub fn add_buffer<Name:Str, T:BufferItem>(&mut self, name:Name) -> Buffer<T>
-    where AnyBuffer: From<Buffer<T>> {
+    pub fn add_buffer<Name:Str, T:BufferItem>(&mut self, name:Name) -> Buffer<T,C>
+    where AnyBuffer<C>: From<Buffer<T,C>> {
         let name         = name.as_ref().to_string();
         let buffer_dirty = self.buffer_dirty.clone();
         let shape_dirty  = self.shape_dirty.clone();
@@ -103,6 +122,7 @@ impl AttributeScope {
             let context    = &self.context;
             let buffer     = Buffer::new(logger,&self.stats,context,on_set,on_resize);
             let buffer_ref = buffer.clone();
+            buffer.set_instance_map(self.instance_map.clone_ref());
             self.buffers.set(ix,AnyBuffer::from(buffer));
             self.buffer_name_map.insert(name,ix.into());
             self.shape_dirty.set();
@@ -111,7 +131,7 @@ impl AttributeScope {
     }
 
     /// Lookups buffer by a given name.
-    pub fn buffer(&self, name:&str) -> Option<&AnyBuffer> {
+    pub fn buffer(&self, name:&str) -> Option<&AnyBuffer<C>> {
         self.buffer_name_map.get(name).map(|i| &self.buffers[(*i).into()])
     }
 
@@ -120,31 +140,96 @@ impl AttributeScope {
         self.buffer_name_map.contains_key(name.as_ref())
     }
 
-    /// Adds a new instance to every buffer in the scope.
+    /// Adds a new instance to every buffer in the scope. The slot of the most recently disposed
+    /// instance is reused if possible. Please note that the reused slot contains the data of the
+    /// disposed instance.
     pub fn add_instance(&mut self) -> AttributeInstanceIndex {
         let instance_count = 1;
         group!(self.logger, "Adding {instance_count} instance(s).", {
-            match self.free_ids.pop() {
-                Some(ix) => ix,
-                None     => {
-                    let ix = self.size;
-                    self.size += instance_count;
-                    self.buffers.iter_mut().for_each(|t| t.add_element());
-                    ix.into()
-                }
+            if self.size < self.capacity {
+                let id = self.instance_map.id(self.size);
+                self.size += instance_count;
+                id
+            } else {
+                self.size     += instance_count;
+                self.capacity += instance_count;
+                self.buffers.iter_mut().for_each(|t| t.add_element());
+                self.instance_map.push()
             }
         })
     }
 
-    /// Disposes instance for reuse in the future. Please note that the disposed data still
-    /// exists in the buffer and will be used when rendering. It is yours responsibility to hide
-    /// id, fo example by degenerating vertices.
+    /// Disposes instance for reuse in the future. The last live instance is moved to the slot of
+    /// the disposed one, so the disposed instance is no longer drawn. All `Attribute` handles of
+    /// the moved instance stay valid. Buffers are shrunk if the number of disposed instances gets
+    /// big.
     pub fn dispose(&mut self, id:AttributeInstanceIndex) {
         group!(self.logger, "Disposing instance {id}.", {
-            self.free_ids.push(id);
+            let index = self.instance_map.index(id).filter(|index| *index < self.size);
+            if let Some(index) = index {
+                let last = self.size - 1;
+                if index != last {
+                    self.buffers.iter_mut().for_each(|t| t.swap(index,last));
+                    self.instance_map.swap(index,last);
+                }
+                self.size = last;
+                let big    = self.capacity >= SHRINK_MIN_CAPACITY;
+                let sparse = self.size * SHRINK_RATIO <= self.capacity;
+                if big && sparse { self.shrink() }
+            } else {
+                self.logger.warning(|| format!("Instance {} was already disposed.",id));
+            }
         })
     }
 
+    /// Releases the memory of all disposed instances. Their ids may be reused by new instances.
+    pub fn shrink(&mut self) {
+        let size = self.size;
+        group!(self.logger, "Shrinking buffers from {self.capacity} to {size} instance(s).", {
+            self.buffers.iter_mut().for_each(|t| t.truncate(size));
+            self.instance_map.truncate(size);
+            self.capacity = size;
+        })
+    }
+
+    /// Ids of all instances which are not disposed, in the order they are stored in the buffers.
+    pub fn instance_ids(&self) -> Vec<AttributeInstanceIndex> {
+        (0..self.size).map(|index| self.instance_map.id(index)).collect()
+    }
+
+    /// Index in the buffers of the instance with the provided id, if it was not removed by
+    /// `shrink`.
+    pub fn index_of(&self, id:AttributeInstanceIndex) -> Option<usize> {
+        self.instance_map.index(id)
+    }
+
+    /// Reorders the instances in all buffers, so instances with the provided ids are stored first,
+    /// in the provided order. The remaining instances follow in their current order. As instances
+    /// are drawn in the order they are stored, this function controls the drawing order. All
+    /// `Attribute` handles stay valid. Ids of disposed instances are ignored. Returns `false` if
+    /// the order did not change.
+    pub fn reorder(&mut self, ids:&[AttributeInstanceIndex]) -> bool {
+        let mut taken = vec![false;self.capacity];
+        let mut order = Vec::with_capacity(self.capacity);
+        for id in ids {
+            if let Some(index) = self.instance_map.index(*id) {
+                if index < self.size && !taken[index] {
+                    taken[index] = true;
+                    order.push(index);
+                }
+            }
+        }
+        order.extend((0..self.capacity).filter(|index| !taken[*index]));
+        let changed = order.iter().enumerate().any(|(new,old)| new != *old);
+        if changed {
+            group!(self.logger, "Reordering {self.capacity} instance(s).", {
+                self.buffers.iter_mut().for_each(|t| t.permute(&order));
+                self.instance_map.permute(&order);
+            })
+        }
+        changed
+    }
+
     /// Check dirty flags and update the state accordingly.
     pub fn update(&mut self) {
         group!(self.logger, "Updating.", {
@@ -164,10 +249,20 @@ impl AttributeScope {
         })
     }
 
-    /// Returns the size of buffers in this scope.
+    /// Re-creates all buffers after the context was lost and restored.
+    pub fn restore_context(&mut self) {
+        self.buffers.iter().for_each(|t| t.restore_context());
+    }
+
+    /// Returns the number of live instances in this scope.
     pub fn size(&self) -> usize {
         self.size
     }
+
+    /// Returns the size of buffers in this scope, including the disposed instances.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
 }
 
 
@@ -179,28 +274,34 @@ impl AttributeScope {
 /// View for a particular buffer. Allows reading and writing buffer data
 /// via the internal mutability pattern. It is implemented as a view on
 /// a selected `Buffer` element under the hood.
-#[derive(Clone,Debug,Derivative)]
-pub struct Attribute<T> {
-    index  : AttributeInstanceIndex,
-    buffer : Buffer<T>
+///
+/// The attribute refers to the instance by its id, not by its index in the buffer, so it stays
+/// valid when instances are reordered.
+#[derive(Debug,Derivative)]
+#[derivative(Clone(bound=""))]
+pub struct Attribute<T,C:GpuContext=Context> {
+    index        : AttributeInstanceIndex,
+    buffer       : Buffer<T,C>,
+    instance_map : InstanceMap,
 }
 
-impl<T> Attribute<T> {
+impl<T:BufferItem,C:GpuContext> Attribute<T,C> {
     /// Creates a new variable as an indexed view over provided buffer.
-    pub fn new(index:AttributeInstanceIndex, buffer:Buffer<T>) -> Self {
-        Self {index,buffer}
+    pub fn new(index:AttributeInstanceIndex, buffer:Buffer<T,C>) -> Self {
+        let instance_map = buffer.instance_map();
+        Self {index,buffer,instance_map}
     }
 }
 
-impl<T: BufferItem> Attribute<T> {
+impl<T:BufferItem,C:GpuContext> Attribute<T,C> {
     /// Gets a copy of the data this attribute points to.
     pub fn get(&self) -> T {
-        self.buffer.get(self.index.into())
+        self.buffer.get(self.buffer_index())
     }
 
     /// Sets the data this attribute points to.
     pub fn set(&self, value:T) {
-        self.buffer.set(self.index.into(),value);
+        self.buffer.set(self.buffer_index(),value);
     }
 
     /// Modifies the data this attribute points to.
@@ -209,4 +310,251 @@ impl<T: BufferItem> Attribute<T> {
         f(&mut value);
         self.set(value);
     }
+
+    /// Index of the instance in the buffer. Panics if the instance was removed, as the handle
+    /// would refer to another instance otherwise.
+    fn buffer_index(&self) -> usize {
+        let index = self.instance_map.index(self.index);
+        index.unwrap_or_else(|| panic!("Attribute of the removed instance {} was used.",self.index))
+    }
+}
+
+
+
+// ===================
+// === InstanceMap ===
+// ===================
+
+shared! { InstanceMap
+
+/// Mapping between instance ids and indexes of the instances in buffers. Ids never change, while
+/// indexes change whenever the instances are reordered. Ids of removed instances are reused.
+#[derive(Debug,Default)]
+pub struct InstanceMapData {
+    index_of : Vec<usize>,
+    id_at    : Vec<AttributeInstanceIndex>,
+    free_ids : Vec<AttributeInstanceIndex>,
+}
+
+impl {
+    /// Constructor.
+    pub fn new() -> Self {
+        default()
+    }
+
+    /// Number of registered instances.
+    pub fn len(&self) -> usize {
+        self.id_at.len()
+    }
+
+    /// Checks if no instance was registered.
+    pub fn is_empty(&self) -> bool {
+        self.id_at.is_empty()
+    }
+
+    /// Index of the instance with the provided id. Returns `None` if the id was never registered
+    /// or if the instance was removed.
+    pub fn index(&self, id:AttributeInstanceIndex) -> Option<usize> {
+        let id:usize = id.into();
+        self.index_of.get(id).copied().filter(|index| *index != usize::max_value())
+    }
+
+    /// Id of the instance stored at the provided index. Panics if no instance is stored there.
+    pub fn id(&self, index:usize) -> AttributeInstanceIndex {
+        self.id_at[index]
+    }
+
+    /// Registers a new instance stored just after the last one.
+    pub fn push(&mut self) -> AttributeInstanceIndex {
+        let index = self.id_at.len();
+        let id    = self.free_ids.pop().unwrap_or_else(|| {
+            self.index_of.push(index);
+            index.into()
+        });
+        self.index_of[*id] = index;
+        self.id_at.push(id);
+        id
+    }
+
+    /// Swaps instances stored at the provided indexes.
+    pub fn swap(&mut self, a:usize, b:usize) {
+        self.id_at.swap(a,b);
+        self.index_of[*self.id_at[a]] = a;
+        self.index_of[*self.id_at[b]] = b;
+    }
+
+    /// Removes all instances stored at indexes not lower than `len`. Their ids will be reused.
+    pub fn truncate(&mut self, len:usize) {
+        if len < self.id_at.len() {
+            for id in self.id_at.drain(len..) {
+                self.index_of[*id] = usize::max_value();
+                self.free_ids.push(id);
+            }
+        }
+    }
+
+    /// Reorders the instances, so the instance at index `i` is moved from the index `order[i]`.
+    pub fn permute(&mut self, order:&[usize]) {
+        self.id_at = order.iter().map(|index| self.id_at[*index]).collect();
+        for (index,id) in self.id_at.iter().enumerate() {
+            let id:usize = (*id).into();
+            self.index_of[id] = index;
+        }
+    }
+}}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::system::gpu::context::mock::MockContext;
+
+    fn new_scope() -> (AttributeScope<MockContext>,Buffer<f32,MockContext>) {
+        let context   = MockContext::new();
+        let stats     = Stats::default();
+        let logger    = Logger::new("test");
+        let mut scope = AttributeScope::new(logger,&stats,&context,||{});
+        let buffer    = scope.add_buffer("value");
+        (scope,buffer)
+    }
+
+    fn add_instances
+    (scope:&mut AttributeScope<MockContext>, buffer:&Buffer<f32,MockContext>, count:usize)
+    -> Vec<AttributeInstanceIndex> {
+        (0..count).map(|i| {
+            let id = scope.add_instance();
+            buffer.at(id).set(i as f32);
+            id
+        }).collect()
+    }
+
+    fn live_ids(scope:&AttributeScope<MockContext>) -> Vec<usize> {
+        scope.instance_ids().into_iter().map(|id| *id).collect()
+    }
+
+    #[test]
+    fn dispose_moves_last_instance_into_freed_slot() {
+        let (mut scope,buffer) = new_scope();
+        let ids = add_instances(&mut scope,&buffer,4);
+        scope.dispose(ids[1]);
+        assert_eq!(scope.size(),3);
+        assert_eq!(scope.capacity(),4);
+        assert_eq!(scope.index_of(ids[3]),Some(1));
+        assert_eq!(buffer.get(1),3.0);
+        assert_eq!(live_ids(&scope),vec![*ids[0],*ids[3],*ids[2]]);
+        scope.dispose(ids[1]);
+        assert_eq!(scope.size(),3);
+        scope.dispose(ids[2]);
+        assert_eq!(live_ids(&scope),vec![*ids[0],*ids[3]]);
+        assert_eq!(*scope.add_instance(),*ids[2]);
+    }
+
+    #[test]
+    fn small_buffers_are_never_shrunk() {
+        let (mut scope,buffer) = new_scope();
+        let count = SHRINK_MIN_CAPACITY - 1;
+        let ids   = add_instances(&mut scope,&buffer,count);
+        ids.iter().for_each(|id| scope.dispose(*id));
+        assert_eq!(scope.size(),0);
+        assert_eq!(scope.capacity(),count);
+    }
+
+    #[test]
+    fn big_sparse_buffers_are_shrunk() {
+        let (mut scope,buffer) = new_scope();
+        let ids  = add_instances(&mut scope,&buffer,SHRINK_MIN_CAPACITY);
+        let live = SHRINK_MIN_CAPACITY / SHRINK_RATIO;
+        let (kept,disposed) = ids.split_at(live);
+        let (last,disposed) = disposed.split_last().unwrap();
+        disposed.iter().for_each(|id| scope.dispose(*id));
+        assert_eq!(scope.size(),live+1);
+        assert_eq!(scope.capacity(),SHRINK_MIN_CAPACITY);
+        scope.dispose(*last);
+        assert_eq!(scope.size(),live);
+        assert_eq!(scope.capacity(),live);
+        assert_eq!(buffer.len(),live);
+        assert_eq!(scope.index_of(*last),None);
+        for (i,id) in kept.iter().enumerate() {
+            assert_eq!(buffer.at(*id).get(),i as f32);
+        }
+    }
+
+    #[test]
+    fn attributes_follow_their_instances() {
+        let (mut scope,buffer) = new_scope();
+        let ids        = add_instances(&mut scope,&buffer,6);
+        let attributes = ids.iter().map(|id| buffer.at(*id)).collect_vec();
+        scope.dispose(ids[0]);
+        scope.dispose(ids[2]);
+        scope.reorder(&[ids[5],ids[1]]);
+        for i in [1,3,4,5].iter() {
+            assert_eq!(attributes[*i].get(),*i as f32);
+        }
+        attributes[4].set(40.0);
+        assert_eq!(buffer.get(scope.index_of(ids[4]).unwrap()),40.0);
+        let id = scope.add_instance();
+        assert!(*id == *ids[0] || *id == *ids[2]);
+        assert_eq!(scope.size(),5);
+        assert_eq!(attributes[4].get(),40.0);
+    }
+
+    #[test]
+    fn instance_map_permutation() {
+        let map = InstanceMap::new();
+        let ids = (0..4).map(|_| map.push()).collect_vec();
+        assert_eq!(map.len(),4);
+        map.permute(&[2,0,3,1]);
+        assert_eq!(map.index(ids[2]),Some(0));
+        assert_eq!(map.index(ids[0]),Some(1));
+        assert_eq!(map.index(ids[3]),Some(2));
+        assert_eq!(map.index(ids[1]),Some(3));
+        map.permute(&[1,0,2,3]);
+        assert_eq!(*map.id(0),*ids[0]);
+        assert_eq!(*map.id(1),*ids[2]);
+    }
+
+    #[test]
+    fn instance_map_swap_and_truncate() {
+        let map = InstanceMap::new();
+        let ids = (0..4).map(|_| map.push()).collect_vec();
+        map.swap(1,3);
+        assert_eq!(map.index(ids[1]),Some(3));
+        assert_eq!(map.index(ids[3]),Some(1));
+        map.truncate(3);
+        assert_eq!(map.len(),3);
+        assert_eq!(map.index(ids[1]),None);
+        let id = map.push();
+        assert_eq!(*id,*ids[1]);
+        assert_eq!(map.index(id),Some(3));
+        assert_eq!(map.index(ids[3]),Some(1));
+    }
+
+    #[test]
+    fn instance_map_ids_are_stable_after_swaps() {
+        let map = InstanceMap::new();
+        let ids = (0..5).map(|_| map.push()).collect_vec();
+        map.swap(0,4);
+        map.swap(1,3);
+        map.swap(0,2);
+        for (index,id) in (0..5).map(|index| (index,map.id(index))) {
+            assert_eq!(map.index(id),Some(index));
+        }
+        let order = (0..5).map(|index| *map.id(index)).collect_vec();
+        assert_eq!(order,vec![*ids[2],*ids[3],*ids[4],*ids[1],*ids[0]]);
+    }
+
+    #[test]
+    fn instance_map_unregistered_ids() {
+        let map = InstanceMap::new();
+        let id  = AttributeInstanceIndex::from(7_usize);
+        assert_eq!(map.index(id),None);
+        map.push();
+        assert_eq!(map.index(id),None);
+    }
 }
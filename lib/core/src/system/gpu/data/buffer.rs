@@ -14,7 +14,10 @@ use crate::debug::stats::Stats;
 use crate::system::gpu::shader::Context;
 use crate::system::gpu::data::buffer::usage::BufferUsage;
 use crate::system::gpu::data::attribute::Attribute;
-use crate::system::gpu::data::buffer::item::JsBufferView;
+use crate::system::gpu::data::attribute::InstanceMap;
+use crate::system::gpu::context::GpuContext;
+use crate::system::gpu::shader::glsl::traits::PhantomIntoPrimType;
+use crate::system::gpu::shader::glsl;
 
 
 use crate::system::gpu::data::prim::*;
@@ -26,10 +29,8 @@ use nalgebra::Matrix4;
 use nalgebra::Vector2;
 use nalgebra::Vector3;
 use nalgebra::Vector4;
-use shapely::shared;
 use std::iter::Extend;
 use std::ops::RangeInclusive;
-use web_sys::WebGlBuffer;
 
 
 pub use crate::system::gpu::data::BufferItem;
@@ -44,8 +45,8 @@ pub use crate::system::gpu::data::BufferItem;
 /// A vector which fires events whenever it is modified or resized.
 pub type ObservableVec<T> = Observable<Vec<T>,OnMut,OnResize>;
 
-/// Dirty flag keeping track of the range of modified elements.
-pub type MutDirty = dirty::SharedRange<usize,Callback>;
+/// Dirty flag keeping track of the ranges of modified elements.
+pub type MutDirty = dirty::SharedRangeSet<Callback>;
 
 /// Dirty flag keeping track of whether the buffer was resized.
 pub type ResizeDirty = dirty::SharedBool<Callback>;
@@ -66,39 +67,155 @@ fn on_mut_fn(dirty:MutDirty) -> OnMut {
 // === Buffer ===
 // ==============
 
-shared! {Buffer
 /// CPU-counterpart of WebGL buffers. The buffer data is synchronised with GPU on demand, usually
 /// in the update stage before drawing the frame.
+#[derive(Debug,Derivative)]
+#[derivative(Clone(bound=""))]
+pub struct Buffer<T,C:GpuContext=Context> {
+    rc: Rc<RefCell<BufferData<T,C>>>,
+}
+
+/// The internal state of the `Buffer`.
 #[derive(Debug)]
-pub struct BufferData<T> {
+pub struct BufferData<T,C:GpuContext=Context> {
     buffer        : ObservableVec<T>,
     mut_dirty     : MutDirty,
     resize_dirty  : ResizeDirty,
-    gl_buffer     : WebGlBuffer,
+    gpu_buffer    : GpuBuffer<C>,
     usage         : BufferUsage,
-    context       : Context,
-    stats         : Stats,
-    gpu_mem_usage : u32,
+    instance_map  : InstanceMap,
     logger        : Logger,
 }
 
-impl<T:BufferItem> {
+impl<T:BufferItem,C:GpuContext> Buffer<T,C> {
     /// Constructor.
     pub fn new<OnMut:CallbackFn,OnResize:CallbackFn>
-    (logger:Logger, stats:&Stats, context:&Context, on_mut:OnMut, on_resize:OnResize) -> Self {
+    (logger:Logger, stats:&Stats, context:&C, on_mut:OnMut, on_resize:OnResize) -> Self {
+        let data = BufferData::new(logger,stats,context,on_mut,on_resize);
+        let rc   = Rc::new(RefCell::new(data));
+        Self {rc}
+    }
+
+    /// Cheap clone of the structure. Implemented as the `Rc::clone` under the hood.
+    pub fn clone_ref(&self) -> Self {
+        self.clone()
+    }
+
+    /// Returns the number of elements in the buffer.
+    pub fn len(&self) -> usize {
+        self.rc.borrow().len()
+    }
+
+    /// Checks if the buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.rc.borrow().is_empty()
+    }
+
+    /// Reads the usage pattern of the buffer.
+    pub fn usage(&self) -> BufferUsage {
+        self.rc.borrow().usage()
+    }
+
+    /// Sets the usage pattern of the buffer.
+    pub fn set_usage(&self, usage:BufferUsage) {
+        self.rc.borrow_mut().set_usage(usage)
+    }
+
+    /// Gets a copy of the data by its index.
+    pub fn get(&self, index:usize) -> T {
+        self.rc.borrow().get(index)
+    }
+
+    /// Sets data value at the given index.
+    pub fn set(&self, index:usize, value:T) {
+        self.rc.borrow_mut().set(index,value)
+    }
+
+    /// Mapping between instance ids and element indexes, used by attributes pointing to this
+    /// buffer.
+    pub fn instance_map(&self) -> InstanceMap {
+        self.rc.borrow().instance_map()
+    }
+
+    /// Sets the instance map. Buffers belonging to the same `AttributeScope` share the map of
+    /// the scope.
+    pub fn set_instance_map(&self, instance_map:InstanceMap) {
+        self.rc.borrow_mut().set_instance_map(instance_map)
+    }
+
+    /// Reorders the elements, so the element at index `i` is moved from the index `order[i]`. The
+    /// `order` has to be a permutation of all the buffer indexes.
+    pub fn permute(&self, order:&[usize]) {
+        self.rc.borrow_mut().permute(order)
+    }
+
+    /// Swaps two elements.
+    pub fn swap(&self, a:usize, b:usize) {
+        self.rc.borrow_mut().swap(a,b)
+    }
+
+    /// Shortens the buffer, keeping the first `len` elements. The GPU buffer is re-created with the
+    /// new size during the next update.
+    pub fn truncate(&self, len:usize) {
+        self.rc.borrow_mut().truncate(len)
+    }
+
+    /// Adds a single new element initialized to default value.
+    pub fn add_element(&self) {
+        self.rc.borrow_mut().add_element()
+    }
+
+    /// Adds multiple new elements initialized to default values.
+    pub fn add_elements(&self, elem_count:usize) {
+        self.rc.borrow_mut().add_elements(elem_count)
+    }
+
+    /// Check dirty flags and update the state accordingly.
+    pub fn update(&self) {
+        self.rc.borrow_mut().update()
+    }
+
+    /// Sets the maximum number of unmodified elements between two modified ranges, which are
+    /// uploaded to the GPU in a single call.
+    pub fn set_max_dirty_gap(&self, max_gap:usize) {
+        self.rc.borrow_mut().set_max_dirty_gap(max_gap)
+    }
+
+    /// Re-creates the WebGL buffer after the context was lost and restored. The data will be
+    /// uploaded during the next update.
+    pub fn restore_context(&self) {
+        self.rc.borrow_mut().restore_context()
+    }
+
+    /// The GLSL type of the buffer items.
+    pub fn glsl_type(&self) -> glsl::Type {
+        self.rc.borrow().glsl_type()
+    }
+
+    /// Binds the underlying WebGLBuffer to a given target.
+    /// https://developer.mozilla.org/docs/Web/API/WebGLRenderingContext/bindBuffer
+    pub fn bind(&self, target:u32) {
+        self.rc.borrow().bind(target)
+    }
+
+    /// Binds the buffer currently bound to gl.ARRAY_BUFFER to a generic vertex attribute of the
+    /// current vertex buffer object and specifies its layout. See
+    /// `BufferData::vertex_attrib_pointer` to learn more.
+    pub fn vertex_attrib_pointer(&self, loc:u32, instanced:bool) {
+        self.rc.borrow().vertex_attrib_pointer(loc,instanced)
+    }
+}
+
+impl<T:BufferItem,C:GpuContext> BufferData<T,C> {
+    /// Constructor.
+    pub fn new<OnMut:CallbackFn,OnResize:CallbackFn>
+    (logger:Logger, stats:&Stats, context:&C, on_mut:OnMut, on_resize:OnResize) -> Self {
         info!(logger,"Creating new {T::type_display()} buffer.",{
-            stats.inc_buffer_count();
-            let mut_dirty     = MutDirty::new(logger.sub("mut_dirty"),Callback(on_mut));
-            let resize_dirty  = ResizeDirty::new(logger.sub("resize_dirty"),Callback(on_resize));
-            let on_resize_fn  = on_resize_fn(resize_dirty.clone_ref());
-            let on_mut_fn     = on_mut_fn(mut_dirty.clone_ref());
-            let buffer        = ObservableVec::new(on_mut_fn,on_resize_fn);
-            let gl_buffer     = create_gl_buffer(&context);
-            let usage         = default();
-            let context       = context.clone();
-            let stats         = stats.clone_ref();
-            let gpu_mem_usage = default();
-            Self {buffer,mut_dirty,resize_dirty,logger,gl_buffer,usage,context,stats,gpu_mem_usage}
+            let (buffer,mut_dirty,resize_dirty) = observable_vec(&logger,on_mut,on_resize);
+            let gpu_buffer   = GpuBuffer::new(context,stats);
+            let usage        = default();
+            let instance_map = default();
+            Self {buffer,mut_dirty,resize_dirty,logger,gpu_buffer,usage,instance_map}
         })
     }
 
@@ -133,6 +250,47 @@ impl<T:BufferItem> {
         *self.buffer.index_mut(index) = value;
     }
 
+    /// Mapping between instance ids and element indexes, used by attributes pointing to this
+    /// buffer.
+    pub fn instance_map(&self) -> InstanceMap {
+        self.instance_map.clone_ref()
+    }
+
+    /// Sets the instance map. Buffers belonging to the same `AttributeScope` share the map of
+    /// the scope.
+    pub fn set_instance_map(&mut self, instance_map:InstanceMap) {
+        self.instance_map = instance_map;
+    }
+
+    /// Reorders the elements, so the element at index `i` is moved from the index `order[i]`. The
+    /// `order` has to be a permutation of all the buffer indexes.
+    pub fn permute(&mut self, order:&[usize]) {
+        let permuted = order.iter().map(|ix| self.buffer[*ix]).collect_vec();
+        for (ix,value) in permuted.into_iter().enumerate() {
+            if order[ix] != ix {
+                *self.buffer.index_mut(ix) = value;
+            }
+        }
+    }
+
+    /// Swaps two elements.
+    pub fn swap(&mut self, a:usize, b:usize) {
+        if a != b {
+            let value_a = self.get(a);
+            let value_b = self.get(b);
+            self.set(a,value_b);
+            self.set(b,value_a);
+        }
+    }
+
+    /// Shortens the buffer, keeping the first `len` elements. The GPU buffer is re-created with the
+    /// new size during the next update.
+    pub fn truncate(&mut self, len:usize) {
+        if len < self.len() {
+            self.buffer.truncate(len);
+        }
+    }
+
     /// Adds a single new element initialized to default value.
     pub fn add_element(&mut self) {
         self.add_elements(1);
@@ -146,23 +304,36 @@ impl<T:BufferItem> {
     /// Check dirty flags and update the state accordingly.
     pub fn update(&mut self) {
         info!(self.logger, "Updating.", {
-            self.context.bind_buffer(Context::ARRAY_BUFFER,Some(&self.gl_buffer));
-            if self.resize_dirty.check() {
-                self.upload_data(&None);
-            } else if self.mut_dirty.check_all() {
-                self.upload_data(&self.mut_dirty.take().range);
-            } else {
+            let data  = &self.buffer.data;
+            let usage = self.usage.into_gl_enum().into();
+            if !self.gpu_buffer.sync(data,usage,&self.mut_dirty,&self.resize_dirty) {
                 internal_warning!(self.logger,"Update requested but it was not needed.")
             }
-            self.mut_dirty.unset_all();
-            self.resize_dirty.unset();
         })
     }
 
+    /// Sets the maximum number of unmodified elements between two modified ranges, which are
+    /// uploaded to the GPU in a single call.
+    pub fn set_max_dirty_gap(&mut self, max_gap:usize) {
+        self.mut_dirty.set_max_gap(max_gap);
+    }
+
+    /// Re-creates the WebGL buffer after the context was lost and restored. The data will be
+    /// uploaded during the next update.
+    pub fn restore_context(&mut self) {
+        self.gpu_buffer.restore_context();
+        self.resize_dirty.set();
+    }
+
+    /// The GLSL type of the buffer items.
+    pub fn glsl_type(&self) -> glsl::Type {
+        T::glsl_prim_type().into()
+    }
+
     /// Binds the underlying WebGLBuffer to a given target.
     /// https://developer.mozilla.org/docs/Web/API/WebGLRenderingContext/bindBuffer
     pub fn bind(&self, target:u32) {
-        self.context.bind_buffer(target,Some(&self.gl_buffer));
+        self.gpu_buffer.bind(target);
     }
 
     /// Binds the buffer currently bound to gl.ARRAY_BUFFER to a generic vertex attribute of the
@@ -172,30 +343,14 @@ impl<T:BufferItem> {
     /// https://developer.mozilla.org/docs/Web/API/WebGLRenderingContext/vertexAttribPointer
     /// https://stackoverflow.com/questions/38853096/webgl-how-to-bind-values-to-a-mat4-attribute
     pub fn vertex_attrib_pointer(&self, loc:u32, instanced:bool) {
-        let item_byte_size = T::item_gpu_byte_size() as i32;
-        let item_type      = T::item_gl_enum().into();
-        let rows           = T::rows() as i32;
-        let cols           = T::cols() as i32;
-        let col_byte_size  = item_byte_size * rows;
-        let stride         = col_byte_size  * cols;
-        let normalize      = false;
-        for col in 0..cols {
-            let lloc = loc + col as u32;
-            let off  = col * col_byte_size;
-            self.context.enable_vertex_attrib_array(lloc);
-            self.context.vertex_attrib_pointer_with_i32(lloc,rows,item_type,normalize,stride,off);
-            if instanced {
-                let instance_count = 1;
-                self.context.vertex_attrib_divisor(lloc,instance_count);
-            }
-        }
+        self.gpu_buffer.vertex_attrib_pointer::<T>(loc,instanced);
     }
-}}
+}
 
 
 // === Private API ===
 
-impl<T: BufferItem> BufferData<T> {
+impl<T:BufferItem,C:GpuContext> BufferData<T,C> {
     /// View the data as slice of primitive elements.
     pub fn as_prim_slice(&self) -> &[item::Item<T>] {
         <T as BufferItem>::slice_to_items(&self.buffer.data)
@@ -208,73 +363,165 @@ impl<T: BufferItem> BufferData<T> {
 }
 
 
-// === Data Upload ===
+// === Utils ===
 
-// Note [Safety]
-// =============
-// Usage of `js_buffer_view` is somewhat dangerous. It is creating a raw view into the module's
-// `WebAssembly.Memory` buffer, but if we allocate more pages for ourself (aka do a memory
-// allocation in Rust) it'll cause the buffer to change, causing the resulting js array to be
-// invalid.
-
-impl<T: BufferItem> BufferData<T> {
-    /// Uploads the provided data range to the GPU buffer. In case the local buffer was resized,
-    /// it will be re-created on the GPU.
-    fn upload_data(&mut self, opt_range:&Option<RangeInclusive<usize>>) {
-        info!(self.logger,"Uploading buffer data.",{
-            self.stats.inc_data_upload_count();
-            match opt_range {
-                None        => self.replace_gpu_buffer(),
-                Some(range) => self.update_gpu_sub_buffer(range)
+/// Creates a vector which marks the modified elements and resizes in the returned dirty flags.
+fn observable_vec<T,OnMut:CallbackFn,OnResize:CallbackFn>
+(logger:&Logger, on_mut:OnMut, on_resize:OnResize) -> (ObservableVec<T>,MutDirty,ResizeDirty) {
+    let mut_dirty    = MutDirty::new(logger.sub("mut_dirty"),Callback(on_mut));
+    let resize_dirty = ResizeDirty::new(logger.sub("resize_dirty"),Callback(on_resize));
+    let on_resize_fn = on_resize_fn(resize_dirty.clone_ref());
+    let on_mut_fn    = on_mut_fn(mut_dirty.clone_ref());
+    let buffer       = ObservableVec::new(on_mut_fn,on_resize_fn);
+    (buffer,mut_dirty,resize_dirty)
+}
+
+
+
+// =================
+// === GpuBuffer ===
+// =================
+
+/// The GPU side of a `Buffer`. It owns the GPU buffer and keeps it in sync with the CPU data. It is
+/// generic over the context, so it can be tested with the `MockContext`.
+#[derive(Debug)]
+pub struct GpuBuffer<C:GpuContext> {
+    context   : C,
+    gl_buffer : C::Buffer,
+    mem_usage : u32,
+    stats     : Stats,
+}
+
+impl<C:GpuContext> GpuBuffer<C> {
+    /// Constructor.
+    pub fn new(context:&C, stats:&Stats) -> Self {
+        stats.inc_buffer_count();
+        let context   = context.clone();
+        let gl_buffer = create_gl_buffer(&context);
+        let mem_usage = default();
+        let stats     = stats.clone_ref();
+        Self {context,gl_buffer,mem_usage,stats}
+    }
+
+    /// Uploads the data marked by the dirty flags and unsets them. The whole data is uploaded if
+    /// it was resized, only the modified ranges otherwise. Returns false if nothing was uploaded.
+    pub fn sync<T:BufferItem>
+    (&mut self, data:&[T], usage:u32, mut_dirty:&MutDirty, resize_dirty:&ResizeDirty) -> bool {
+        let resized  = resize_dirty.check();
+        let modified = mut_dirty.check_all();
+        if resized || modified {
+            self.bind(Context::ARRAY_BUFFER);
+            if resized {
+                self.replace(data,usage);
+            } else {
+                for range in mut_dirty.take_ranges() {
+                    self.replace_range(data,&range);
+                }
             }
-        });
+        }
+        mut_dirty.unset_all();
+        resize_dirty.unset();
+        resized || modified
+    }
+
+    /// Binds the GPU buffer to a given target.
+    /// https://developer.mozilla.org/docs/Web/API/WebGLRenderingContext/bindBuffer
+    pub fn bind(&self, target:u32) {
+        self.context.bind_buffer(target,Some(&self.gl_buffer));
     }
 
-    /// Replaces the whole GPU buffer by the local data.
-    fn replace_gpu_buffer(&mut self) {
-        let data    = self.as_slice();
-        let gl_enum = self.usage.into_gl_enum().into();
-        unsafe { // Note [Safety]
-            let js_array = data.js_buffer_view();
-            self.context.buffer_data_with_array_buffer_view
-            (Context::ARRAY_BUFFER,&js_array,gl_enum);
+    /// Binds the buffer currently bound to gl.ARRAY_BUFFER to a generic vertex attribute. See
+    /// `BufferData::vertex_attrib_pointer` to learn more.
+    pub fn vertex_attrib_pointer<T:BufferItem>(&self, loc:u32, instanced:bool) {
+        let item_byte_size = T::item_gpu_byte_size() as i32;
+        let item_type      = T::item_gl_enum().into();
+        let rows           = T::rows() as i32;
+        let cols           = T::cols() as i32;
+        let col_byte_size  = item_byte_size * rows;
+        let stride         = col_byte_size  * cols;
+        let normalize      = false;
+        for col in 0..cols {
+            let lloc = loc + col as u32;
+            let off  = col * col_byte_size;
+            self.context.enable_vertex_attrib_array(lloc);
+            self.context.vertex_attrib_pointer(lloc,rows,item_type,normalize,stride,off);
+            if instanced {
+                let instance_count = 1;
+                self.context.vertex_attrib_divisor(lloc,instance_count);
+            }
         }
+    }
+
+    /// Re-creates the GPU buffer after the context was lost and restored. The data has to be
+    /// uploaded again.
+    pub fn restore_context(&mut self) {
+        self.gl_buffer = create_gl_buffer(&self.context);
+    }
+
+    /// Replaces the whole GPU buffer by the data.
+    fn replace<T:BufferItem>(&mut self, data:&[T], usage:u32) {
+        let new_mem_usage = upload_all(&self.context,data,usage);
+        self.stats.inc_data_upload_count();
         crate::if_compiled_with_stats! {
-            let item_byte_size    = T::item_gpu_byte_size() as u32;
-            let item_count        = T::item_count() as u32;
-            let new_gpu_mem_usage = self.len() as u32 * item_count * item_byte_size;
-            self.stats.mod_gpu_memory_usage(|s| s - self.gpu_mem_usage);
-            self.stats.mod_gpu_memory_usage(|s| s + new_gpu_mem_usage);
-            self.stats.mod_data_upload_size(|s| s + new_gpu_mem_usage);
-            self.gpu_mem_usage = new_gpu_mem_usage;
+            self.stats.mod_gpu_memory_usage(|s| s - self.mem_usage);
+            self.stats.mod_gpu_memory_usage(|s| s + new_mem_usage);
+            self.stats.mod_data_upload_size(|s| s + new_mem_usage);
         }
+        self.mem_usage = new_mem_usage;
     }
 
-    /// Updates the GPU sub-buffer data by the provided index range.
-    fn update_gpu_sub_buffer(&mut self, range:&RangeInclusive<usize>) {
-        let data            = self.as_slice();
-        let item_byte_size  = T::item_gpu_byte_size() as u32;
-        let item_count      = T::item_count() as u32;
-        let start           = *range.start() as u32;
-        let end             = *range.end() as u32;
-        let start_item      = start * item_count;
-        let length          = (end - start + 1) * item_count;
-        let dst_byte_offset = (item_byte_size * item_count * start) as i32;
-        unsafe { // Note [Safety]
-            let js_array = data.js_buffer_view();
-            self.context.buffer_sub_data_with_i32_and_array_buffer_view_and_src_offset_and_length
-            (Context::ARRAY_BUFFER,dst_byte_offset,&js_array,start_item,length)
-        }
-        self.stats.mod_data_upload_size(|s| s + length * item_byte_size);
+    /// Updates the GPU sub-buffer by the provided index range of the data.
+    fn replace_range<T:BufferItem>(&mut self, data:&[T], range:&RangeInclusive<usize>) {
+        let size = upload_range(&self.context,data,range);
+        self.stats.inc_data_upload_count();
+        self.stats.mod_data_upload_size(|s| s + size);
     }
 }
 
+impl<C:GpuContext> Drop for GpuBuffer<C> {
+    fn drop(&mut self) {
+        self.context.delete_buffer(Some(&self.gl_buffer));
+        self.stats.mod_gpu_memory_usage(|s| s - self.mem_usage);
+        self.stats.dec_buffer_count();
+    }
+}
+
+/// Creates a new GPU buffer.
+fn create_gl_buffer<C:GpuContext>(context:&C) -> C::Buffer {
+    let buffer = context.create_buffer();
+    buffer.ok_or("Failed to create WebGL buffer.").unwrap()
+}
+
+/// Replaces the data of the buffer bound to `ARRAY_BUFFER` by the provided elements. Returns the
+/// number of uploaded bytes.
+pub fn upload_all<C:GpuContext,T:BufferItem>(context:&C, data:&[T], usage:u32) -> u32 {
+    let item_byte_size = T::item_gpu_byte_size() as u32;
+    let item_count     = T::item_count() as u32;
+    context.buffer_data(Context::ARRAY_BUFFER,data,usage);
+    data.len() as u32 * item_count * item_byte_size
+}
+
+/// Uploads the provided range of elements to the buffer bound to `ARRAY_BUFFER`. Returns the number
+/// of uploaded bytes.
+pub fn upload_range<C:GpuContext,T:BufferItem>
+(context:&C, data:&[T], range:&RangeInclusive<usize>) -> u32 {
+    let item_byte_size  = T::item_gpu_byte_size() as u32;
+    let item_count      = T::item_count() as u32;
+    let start           = *range.start() as u32;
+    let end             = *range.end() as u32;
+    let start_item      = start * item_count;
+    let length          = (end - start + 1) * item_count;
+    let dst_byte_offset = (item_byte_size * item_count * start) as i32;
+    context.buffer_sub_data(Context::ARRAY_BUFFER,dst_byte_offset,data,start_item,length);
+    length * item_byte_size
+}
+
 
 // === Smart Accessors ===
 
-impl<T: BufferItem> Buffer<T> {
+impl<T:BufferItem,C:GpuContext> Buffer<T,C> {
     /// Get the attribute pointing to a given buffer index.
-    pub fn at(&self, index:AttributeInstanceIndex) -> Attribute<T> {
+    pub fn at(&self, index:AttributeInstanceIndex) -> Attribute<T,C> {
         Attribute::new(index,self.clone_ref())
     }
 }
@@ -282,34 +529,19 @@ impl<T: BufferItem> Buffer<T> {
 
 // === Instances ===
 
-impl<T> Deref for BufferData<T> {
+impl<T,C:GpuContext> Deref for BufferData<T,C> {
     type Target = ObservableVec<T>;
     fn deref(&self) -> &Self::Target {
         &self.buffer
     }
 }
 
-impl<T> DerefMut for BufferData<T> {
+impl<T,C:GpuContext> DerefMut for BufferData<T,C> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.buffer
     }
 }
 
-impl<T> Drop for BufferData<T> {
-    fn drop(&mut self) {
-        self.context.delete_buffer(Some(&self.gl_buffer));
-        self.stats.mod_gpu_memory_usage(|s| s - self.gpu_mem_usage);
-        self.stats.dec_buffer_count();
-    }
-}
-
-
-// === Utils ===
-
-fn create_gl_buffer(context:&Context) -> WebGlBuffer {
-    let buffer = context.create_buffer();
-    buffer.ok_or("Failed to create WebGL buffer.").unwrap()
-}
 
 
 
@@ -317,7 +549,6 @@ fn create_gl_buffer(context:&Context) -> WebGlBuffer {
 // === AnyBuffer ===
 // =================
 
-use enum_dispatch::*;
 use crate::system::gpu::data::AttributeInstanceIndex;
 
 // === Macros ===
@@ -332,17 +563,71 @@ macro_rules! define_any_buffer {
     /// An enum with a variant per possible buffer type (i32, f32, Vector<f32>,
     /// and many, many more). It provides a faster alternative to dyn trait one:
     /// `Buffer<dyn BufferItem, OnMut, OnResize>`.
-    #[enum_dispatch(IsBuffer)]
     #[derive(Debug)]
     #[allow(missing_docs)]
-    pub enum AnyBuffer {
-        $([<Variant $base For $param>](Buffer<$base<$param>>)),*
+    pub enum AnyBuffer<C:GpuContext=Context> {
+        $([<Variant $base For $param>](Buffer<$base<$param>,C>)),*
+    }
+
+    impl<C:GpuContext> IsBuffer for AnyBuffer<C> {
+        fn add_element(&self) {
+            match self { $(Self::[<Variant $base For $param>](t) => t.add_element(),)* }
+        }
+
+        fn len(&self) -> usize {
+            match self { $(Self::[<Variant $base For $param>](t) => t.len(),)* }
+        }
+
+        fn is_empty(&self) -> bool {
+            match self { $(Self::[<Variant $base For $param>](t) => t.is_empty(),)* }
+        }
+
+        fn permute(&self, order:&[usize]) {
+            match self { $(Self::[<Variant $base For $param>](t) => t.permute(order),)* }
+        }
+
+        fn swap(&self, a:usize, b:usize) {
+            match self { $(Self::[<Variant $base For $param>](t) => t.swap(a,b),)* }
+        }
+
+        fn truncate(&self, len:usize) {
+            match self { $(Self::[<Variant $base For $param>](t) => t.truncate(len),)* }
+        }
+
+        fn update(&self) {
+            match self { $(Self::[<Variant $base For $param>](t) => t.update(),)* }
+        }
+
+        fn restore_context(&self) {
+            match self { $(Self::[<Variant $base For $param>](t) => t.restore_context(),)* }
+        }
+
+        fn glsl_type(&self) -> glsl::Type {
+            match self { $(Self::[<Variant $base For $param>](t) => t.glsl_type(),)* }
+        }
+
+        fn bind(&self, target:u32) {
+            match self { $(Self::[<Variant $base For $param>](t) => t.bind(target),)* }
+        }
+
+        fn vertex_attrib_pointer(&self, index:u32, instanced:bool) {
+            match self {
+                $(Self::[<Variant $base For $param>](t) =>
+                    t.vertex_attrib_pointer(index,instanced),)*
+            }
+        }
     }
 
     $(
-        impl<'t> TryFrom<&'t AnyBuffer> for &'t Buffer<$base<$param>> {
+        impl<C:GpuContext> From<Buffer<$base<$param>,C>> for AnyBuffer<C> {
+            fn from(t:Buffer<$base<$param>,C>) -> Self {
+                Self::[<Variant $base For $param>](t)
+            }
+        }
+
+        impl<'t,C:GpuContext> TryFrom<&'t AnyBuffer<C>> for &'t Buffer<$base<$param>,C> {
             type Error = BadVariant;
-            fn try_from(t:&'t AnyBuffer) -> Result <&'t Buffer<$base<$param>>,Self::Error> {
+            fn try_from(t:&'t AnyBuffer<C>) -> Result <&'t Buffer<$base<$param>,C>,Self::Error> {
                 match t {
                     AnyBuffer::[<Variant $base For $param>](a) => Ok(a),
                     _ => Err(BadVariant)
@@ -350,9 +635,10 @@ macro_rules! define_any_buffer {
             }
         }
 
-        impl<'t> TryFrom<&'t mut AnyBuffer> for &'t mut Buffer<$base<$param>> {
+        impl<'t,C:GpuContext> TryFrom<&'t mut AnyBuffer<C>> for &'t mut Buffer<$base<$param>,C> {
             type Error = BadVariant;
-            fn try_from(t:&'t mut AnyBuffer) -> Result <&'t mut Buffer<$base<$param>>,Self::Error> {
+            fn try_from(t:&'t mut AnyBuffer<C>)
+            -> Result <&'t mut Buffer<$base<$param>,C>,Self::Error> {
                 match t {
                     AnyBuffer::[<Variant $base For $param>](a) => Ok(a),
                     _ => Err(BadVariant)
@@ -368,13 +654,107 @@ macro_rules! define_any_buffer {
 crate::with_all_prim_types!([[define_any_buffer] []]);
 
 /// Collection of all methods common to every buffer variant.
-#[enum_dispatch]
 #[allow(missing_docs)]
 pub trait IsBuffer {
     fn add_element(&self);
     fn len(&self) -> usize;
     fn is_empty(&self) -> bool;
+    fn permute(&self, order:&[usize]);
+    fn swap(&self, a:usize, b:usize);
+    fn truncate(&self, len:usize);
     fn update(&self);
+    fn restore_context(&self);
+    fn glsl_type(&self) -> glsl::Type;
     fn bind(&self, target:u32);
     fn vertex_attrib_pointer(&self, index:u32, instanced:bool);
 }
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::gpu::context::mock::Call;
+    use crate::system::gpu::context::mock::MockContext;
+
+    fn upload_dirty(context:&MockContext, len:usize, max_gap:usize, ixs:&[usize]) -> Vec<Call> {
+        let data      = vec![Vector2::<f32>::new(0.0,0.0);len];
+        let mut dirty = dirty::RangeSetData::default();
+        dirty.set_max_gap(max_gap);
+        ixs.iter().for_each(|ix| dirty.set(*ix));
+        for range in dirty.take_ranges() {
+            upload_range(context,&data,&range);
+        }
+        context.buffer_uploads()
+    }
+
+    fn sub_data(dst_byte_offset:i32, src_offset:u32, length:u32) -> Call {
+        let target = Context::ARRAY_BUFFER;
+        Call::BufferSubData {target,dst_byte_offset,src_offset,length}
+    }
+
+    #[test]
+    fn modifying_one_element_uploads_one_range() {
+        let context = MockContext::new();
+        let uploads = upload_dirty(&context,100,0,&[42]);
+        assert_eq!(uploads,vec![sub_data(42*8,84,2)]);
+    }
+
+    #[test]
+    fn close_modifications_are_uploaded_together() {
+        let context = MockContext::new();
+        let uploads = upload_dirty(&context,100,4,&[10,12,50]);
+        assert_eq!(uploads,vec![sub_data(10*8,20,6),sub_data(50*8,100,2)]);
+    }
+
+    #[test]
+    fn syncing_buffer_changes() {
+        let context     = MockContext::new();
+        let stats       = Stats::default();
+        let logger      = Logger::new("test");
+        let mut gpu     = GpuBuffer::new(&context,&stats);
+        let (mut data,mut_dirty,resize_dirty) = observable_vec(&logger,||{},||{});
+        let usage       = Context::DYNAMIC_DRAW;
+        let target      = Context::ARRAY_BUFFER;
+        let sync        = |gpu:&mut GpuBuffer<MockContext>, data:&ObservableVec<Vector2<f32>>| {
+            gpu.sync(&data.data,usage,&mut_dirty,&resize_dirty)
+        };
+        data.extend(iter::repeat(Vector2::new(0.0,0.0)).take(100));
+        context.clear();
+        assert!(sync(&mut gpu,&data));
+        assert_eq!(context.buffer_uploads(),vec![Call::BufferData {target,byte_size:800,usage}]);
+        assert_eq!(stats.gpu_memory_usage(),800);
+
+        context.clear();
+        *data.index_mut(42) = Vector2::new(1.0,1.0);
+        assert!(sync(&mut gpu,&data));
+        assert_eq!(context.buffer_uploads(),vec![sub_data(42*8,84,2)]);
+
+        context.clear();
+        assert!(!sync(&mut gpu,&data));
+        assert!(context.calls().is_empty());
+
+        drop(gpu);
+        assert_eq!(stats.gpu_memory_usage(),0);
+        assert_eq!(stats.buffer_count(),0);
+        assert!(context.calls().iter().any(|call| match call {
+            Call::DeleteBuffer {..} => true,
+            _                       => false,
+        }));
+    }
+
+    #[test]
+    fn whole_buffer_upload() {
+        let context = MockContext::new();
+        let data    = vec![Vector4::<f32>::new(0.0,0.0,0.0,0.0);10];
+        let size    = upload_all(&context,&data,Context::DYNAMIC_DRAW);
+        let target  = Context::ARRAY_BUFFER;
+        let usage   = Context::DYNAMIC_DRAW;
+        assert_eq!(size,160);
+        assert_eq!(context.buffer_uploads(),vec![Call::BufferData {target,byte_size:160,usage}]);
+    }
+}
@@ -2,16 +2,25 @@
 //! Follow the link to learn more about many assumptions this module was built upon:
 //! https://developer.mozilla.org/en-US/docs/Web/API/WebGLRenderingContext/texImage2D
 
+pub mod atlas;
+pub mod parameters;
+
 use crate::prelude::*;
 
+use crate::system::gpu::context::GpuContext;
 use crate::system::gpu::data::buffer::item::JsBufferViewArr;
+use crate::system::gpu::shader::glsl;
 use crate::system::gpu::types::*;
 use crate::system::web;
 use nalgebra::*;
 use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::Closure;
 use web_sys::HtmlImageElement;
-use web_sys::WebGlTexture;
+
+pub use parameters::MagFilter;
+pub use parameters::MinFilter;
+pub use parameters::Parameters;
+pub use parameters::Wrap;
 
 
 
@@ -184,6 +193,22 @@ pub mod internal_format {
 }
 pub use internal_format::*;
 
+impl AnyInternalFormat {
+    /// The GLSL sampler type of 2D textures of this format. Textures of integer formats can be
+    /// sampled only with integer samplers of the same signedness.
+    pub fn sampler_2d(self) -> glsl::PrimType {
+        match self {
+            Self::R8ui | Self::R16ui | Self::R32ui | Self::Rg8ui | Self::Rg16ui | Self::Rg32ui
+            | Self::Rgb8ui | Self::Rgb16ui | Self::Rgb32ui | Self::Rgba8ui | Self::Rgb10A2ui
+            | Self::Rgba16ui | Self::Rgba32ui => glsl::PrimType::USampler2d,
+            Self::R8i | Self::R16i | Self::R32i | Self::Rg8i | Self::Rg16i | Self::Rg32i
+            | Self::Rgb8i | Self::Rgb16i | Self::Rgb32i | Self::Rgba8i | Self::Rgba16i
+            | Self::Rgba32i => glsl::PrimType::ISampler2d,
+            _ => glsl::PrimType::Sampler2d,
+        }
+    }
+}
+
 
 
 // ======================
@@ -218,6 +243,12 @@ pub trait InternalFormat : Default + Into<AnyInternalFormat> +'static {
     fn filterable() -> bool {
         <Self::Filterable as Value>::value()
     }
+
+    /// The GLSL sampler type of 2D textures of this format.
+    fn glsl_sampler_type() -> glsl::PrimType {
+        let format:AnyInternalFormat = Self::default().into();
+        format.sampler_2d()
+    }
 }
 
 
@@ -326,6 +357,72 @@ with_texture_format_relations!(generate_internal_format_instances []);
 
 
 
+// ==============
+// === Region ===
+// ==============
+
+/// Rectangle in pixel coordinates of a texture.
+#[derive(Clone,Copy,Debug,Default,Eq,Hash,PartialEq)]
+#[allow(missing_docs)]
+pub struct Rect {
+    pub x      : usize,
+    pub y      : usize,
+    pub width  : usize,
+    pub height : usize,
+}
+
+impl Rect {
+    /// Constructor.
+    pub fn new(x:usize, y:usize, width:usize, height:usize) -> Self {
+        Self {x,y,width,height}
+    }
+
+    /// The x coordinate right after the rectangle.
+    pub fn right(&self) -> usize {
+        self.x + self.width
+    }
+
+    /// The y coordinate right after the rectangle.
+    pub fn bottom(&self) -> usize {
+        self.y + self.height
+    }
+
+    /// The number of pixels in the rectangle.
+    pub fn area(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// Checks whether the rectangles have any common pixels.
+    pub fn intersects(&self, other:&Rect) -> bool {
+        self.x < other.right() && other.x < self.right() &&
+        self.y < other.bottom() && other.y < self.bottom()
+    }
+}
+
+/// Error of a texture region update.
+#[derive(Debug,Fail)]
+#[allow(missing_docs)]
+pub enum RegionError {
+    #[fail(display="Region {:?} exceeds the texture of size {}x{}.",rect,width,height)]
+    OutOfBounds {rect:Rect, width:usize, height:usize},
+    #[fail(display="Region {:?} should consist of {} elements, but it has {} elements.",
+        rect,expected,got)]
+    InvalidData {rect:Rect, expected:usize, got:usize},
+}
+
+/// Copies the image into the `rect` of the destination image of the provided width. Both images
+/// consist of rows of `channels` elements per pixel.
+pub fn blit<T:Copy>(dst:&mut [T], dst_width:usize, src:&[T], rect:&Rect, channels:usize) {
+    let row_len = rect.width * channels;
+    for row in 0..rect.height {
+        let dst_start = ((rect.y + row) * dst_width + rect.x) * channels;
+        let src_start = row * row_len;
+        dst[dst_start..dst_start + row_len].copy_from_slice(&src[src_start..src_start + row_len]);
+    }
+}
+
+
+
 // =======================
 // === TextureProvider ===
 // =======================
@@ -341,26 +438,52 @@ pub trait TextureProvider : Sized {
     type ElemType       : TextureItemType;
 
     /// Create new texture.
-    fn new_texture(self, context:&Context) -> Texture<Self>;
+    fn new_texture<C:GpuContext>(self, context:&C) -> Texture<Self,C>;
+
+    /// Sampling parameters applied when the texture is created.
+    fn parameters(&self) -> Parameters;
 }
 
 // === Texture from URL ===
 
+/// Callback run with the natural width and height of a downloaded image.
+pub type OnImageLoad = Rc<dyn Fn(i32,i32)>;
+
 /// Texture downloaded from URL. This source implies asynchronous loading.
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct TextureFromUrl<InternalFormat,ElemType> {
     /// An url from where the texture is downloaded.
-    pub url : String,
-    phantom : PhantomData2<InternalFormat,ElemType>,
+    pub url        : String,
+    /// Sampling parameters.
+    pub parameters : Parameters,
+    /// Callback run every time the image is loaded and uploaded to the texture.
+    #[derivative(Debug="ignore")]
+    pub on_load    : Option<OnImageLoad>,
+    phantom        : PhantomData2<InternalFormat,ElemType>,
 }
 
 impl<I,T> TextureFromUrl<I,T> {
     fn new<S:Str>(url:S) -> Self {
         Self {
-            url     : url.into(),
-            phantom : PhantomData
+            url        : url.into(),
+            parameters : default(),
+            on_load    : default(),
+            phantom    : PhantomData
         }
     }
+
+    /// Sets the sampling parameters.
+    pub fn with_parameters(self, parameters:Parameters) -> Self {
+        Self {parameters,..self}
+    }
+
+    /// Sets the callback run with the natural size of the image every time it is loaded, for
+    /// example to lay out the images whose size is not known up front.
+    pub fn with_on_load<F:Fn(i32,i32)+'static>(self, f:F) -> Self {
+        let on_load = Some(Rc::new(f) as OnImageLoad);
+        Self {on_load,..self}
+    }
 }
 
 impl<I,T> TextureProvider for TextureFromUrl<I,T>
@@ -369,16 +492,22 @@ where I : InternalFormat,
     type InternalFormat = I;
     type ElemType       = T;
 
-    fn new_texture(self, context:&Context) -> Texture<Self> {
+    fn new_texture<C:GpuContext>(self, context:&C) -> Texture<Self,C> {
         Texture::new_from_url(context,self)
     }
+
+    fn parameters(&self) -> Parameters {
+        self.parameters
+    }
 }
 
 impl<S:Str,I,T> From<S> for TextureFromUrl<I,T> {
     fn from(s:S) -> Self {
-        let url     = s.into();
-        let phantom = PhantomData;
-        TextureFromUrl{url,phantom}
+        let url        = s.into();
+        let parameters = default();
+        let on_load    = default();
+        let phantom    = PhantomData;
+        TextureFromUrl{url,parameters,on_load,phantom}
     }
 }
 
@@ -391,14 +520,22 @@ pub struct TextureWithSize<InternalFormat,ElemType> {
     /// Texture width.
     pub width  : i32,
     /// Texture height.
-    pub height : i32,
-    phantom    : PhantomData2<InternalFormat,ElemType>,
+    pub height     : i32,
+    /// Sampling parameters.
+    pub parameters : Parameters,
+    phantom        : PhantomData2<InternalFormat,ElemType>,
 }
 
 impl<I,T> TextureWithSize<I,T> {
     fn new(width:i32, height:i32) -> Self {
-        let phantom = PhantomData;
-        Self {width,height,phantom}
+        let parameters = default();
+        let phantom    = PhantomData;
+        Self {width,height,parameters,phantom}
+    }
+
+    /// Sets the sampling parameters.
+    pub fn with_parameters(self, parameters:Parameters) -> Self {
+        Self {parameters,..self}
     }
 }
 
@@ -408,9 +545,13 @@ where I : InternalFormat,
     type InternalFormat = I;
     type ElemType       = T;
 
-    fn new_texture(self, context:&Context) -> Texture<Self> {
+    fn new_texture<C:GpuContext>(self, context:&C) -> Texture<Self,C> {
         Texture::new_from_size(context,self)
     }
+
+    fn parameters(&self) -> Parameters {
+        self.parameters
+    }
 }
 
 
@@ -424,14 +565,23 @@ pub struct TextureData<InternalFormat,ElemType> {
     /// Texture width.
     pub width  : i32,
     /// Texture height.
-    pub height : i32,
-    phantom   : PhantomData<InternalFormat>,
+    pub height     : i32,
+    /// Sampling parameters.
+    pub parameters : Parameters,
+    phantom        : PhantomData<InternalFormat>,
 }
 
 impl<I,T> TextureData<I,T> {
-    fn new(data:Vec<T>, width:i32, height:i32) -> Self {
-        let phantom = PhantomData;
-        Self {data,width,height,phantom}
+    /// Constructor.
+    pub fn new(data:Vec<T>, width:i32, height:i32) -> Self {
+        let parameters = default();
+        let phantom    = PhantomData;
+        Self {data,width,height,parameters,phantom}
+    }
+
+    /// Sets the sampling parameters.
+    pub fn with_parameters(self, parameters:Parameters) -> Self {
+        Self {parameters,..self}
     }
 }
 
@@ -441,9 +591,13 @@ where I : InternalFormat,
     type InternalFormat = I;
     type ElemType       = T;
 
-    fn new_texture(self, context:&Context) -> Texture<Self> {
+    fn new_texture<C:GpuContext>(self, context:&C) -> Texture<Self,C> {
         Texture::new_from_data(context,self)
     }
+
+    fn parameters(&self) -> Parameters {
+        self.parameters
+    }
 }
 
 
@@ -453,16 +607,17 @@ where I : InternalFormat,
 
 /// Texture bound to GL context.
 #[derive(Debug)]
-pub struct Texture<Provider> {
+pub struct Texture<Provider,C:GpuContext=Context> {
     provider   : Provider,
-    gl_texture : WebGlTexture,
-    context    : Context,
+    gl_texture : C::Texture,
+    parameters : Parameters,
+    context    : C,
 }
 
 
 // === Type Level Utils ===
 
-impl<Provider:TextureProvider> Texture<Provider> {
+impl<Provider:TextureProvider,C:GpuContext> Texture<Provider,C> {
 
     /// Internal format instance of this texture. Please note, that this value could be computed
     /// without taking self reference, however it was defined in such way for convenient usage.
@@ -499,19 +654,36 @@ impl<Provider:TextureProvider> Texture<Provider> {
 
 // === Getters ===
 
-impl<Provider> Texture<Provider> {
+impl<Provider,C:GpuContext> Texture<Provider,C> {
     /// Getter.
-    pub fn gl_texture(&self) -> &WebGlTexture {
+    pub fn gl_texture(&self) -> &C::Texture {
         &self.gl_texture
     }
+
+    /// Getter.
+    pub fn parameters(&self) -> Parameters {
+        self.parameters
+    }
+
+    /// Changes the sampling parameters of the texture. Mipmaps are generated from the current
+    /// texture data if they were enabled. The texture bound to the `TEXTURE_2D` target before is
+    /// bound again afterwards.
+    pub fn set_parameters(&mut self, parameters:Parameters) {
+        let target      = Context::TEXTURE_2D;
+        let previous    = self.context.bound_texture_2d();
+        self.parameters = parameters;
+        self.context.bind_texture(target,Some(&self.gl_texture));
+        Self::apply_parameters(&self.context,&self.parameters);
+        self.context.bind_texture(target,previous.as_ref());
+    }
 }
 
 
 // === API ===
 
-impl<I:InternalFormat,T:TextureItemType> Texture<TextureFromUrl<I,T>> {
+impl<I:InternalFormat,T:TextureItemType,C:GpuContext> Texture<TextureFromUrl<I,T>,C> {
     /// Constructor.
-    pub fn new_from_url<P:Into<TextureFromUrl<I,T>>>(context:&Context, provider:P) -> Self {
+    pub fn new_from_url<P:Into<TextureFromUrl<I,T>>>(context:&C, provider:P) -> Self {
         let out = Self::new_unitialized(context,provider);
         out.reload();
         out
@@ -528,11 +700,17 @@ impl<I:InternalFormat,T:TextureItemType> Texture<TextureFromUrl<I,T>> {
         let elem_type       = Self::gl_elem_type();
         let width           = 1;
         let height          = 1;
-        let border          = 0;
-        let color           = vec![0,0,255,255];
+        let color:&[u8]     = &[0,0,255,255];
         self.context.bind_texture(Context::TEXTURE_2D,Some(&self.gl_texture));
-        self.context.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array
-        (target,level,internal_format,width,height,border,format,elem_type,Some(&color)).unwrap();
+        self.context.tex_image_2d
+            (target,level,internal_format,width,height,format,elem_type,Some(color));
+    }
+
+    /// Re-creates the texture after the context was lost and restored, and re-loads its data from
+    /// the provider.
+    pub fn restore_context(&mut self) {
+        self.recreate_gl_texture();
+        self.reload();
     }
 
     /// Loads or re-loads the texture data from the provided url. This action will be performed
@@ -547,6 +725,8 @@ impl<I:InternalFormat,T:TextureItemType> Texture<TextureFromUrl<I,T>> {
         let image_ref_opt = image_ref.clone();
         let context       = self.context.clone();
         let gl_texture    = self.gl_texture.clone();
+        let parameters    = self.parameters;
+        let on_load       = self.provider.on_load.clone();
         let callback: Closure<dyn FnMut()> = Closure::once(move || {
             let _keep_alive     = callback_ref2;
             let image           = image_ref_opt.borrow();
@@ -556,10 +736,13 @@ impl<I:InternalFormat,T:TextureItemType> Texture<TextureFromUrl<I,T>> {
             let format          = Self::gl_format().into();
             let elem_type       = Self::gl_elem_type();
             context.bind_texture(target,Some(&gl_texture));
-            context.tex_image_2d_with_u32_and_u32_and_html_image_element
-                (target,level,internal_format,format,elem_type,&image).unwrap();
+            context.tex_image_2d_with_image
+                (target,level,internal_format,format,elem_type,&image);
 
-            Self::set_texture_parameters(&context);
+            Self::apply_parameters(&context,&parameters);
+            if let Some(on_load) = on_load {
+                on_load(image.natural_width() as i32,image.natural_height() as i32)
+            }
         });
         let js_callback = callback.as_ref().unchecked_ref();
         let image       = image_ref.borrow();
@@ -570,69 +753,160 @@ impl<I:InternalFormat,T:TextureItemType> Texture<TextureFromUrl<I,T>> {
     }
 }
 
-impl<I:InternalFormat,T:TextureItemType> Texture<TextureWithSize<I,T>> {
+impl<I:InternalFormat,T:TextureItemType,C:GpuContext> Texture<TextureWithSize<I,T>,C> {
     /// Constructor.
-    pub fn new_from_size<P:Into<TextureWithSize<I,T>>>(context:&Context, provider:P) -> Self {
+    pub fn new_from_size<P:Into<TextureWithSize<I,T>>>(context:&C, provider:P) -> Self {
         let out = Self::new_unitialized(context,provider);
         out.reload();
         out
     }
 
+    /// Re-creates the texture after the context was lost and restored, and re-loads its data from
+    /// the provider.
+    pub fn restore_context(&mut self) {
+        self.recreate_gl_texture();
+        self.reload();
+    }
+
     /// Loads or re-loads the texture data.
     pub fn reload(&self) {
         let width           = self.provider.width;
         let height          = self.provider.height;
         let target          = Context::TEXTURE_2D;
         let level           = 0;
-        let border          = 0;
         let internal_format = Self::gl_internal_format();
         let format          = Self::gl_format().into();
         let elem_type       = Self::gl_elem_type();
+        let data            = None::<&[u8]>;
 
         self.context.bind_texture(target,Some(&self.gl_texture));
-        self.context.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array
-        (target,level,internal_format,width,height,border,format,elem_type,None).unwrap();
+        self.context.tex_image_2d
+            (target,level,internal_format,width,height,format,elem_type,data);
 
-        Self::set_texture_parameters(&self.context);
+        Self::apply_parameters(&self.context,&self.parameters);
     }
 }
 
-impl<I:InternalFormat,T:TextureItemType + JsBufferViewArr> Texture<TextureData<I,T>> {
+impl<I,T,C> Texture<TextureData<I,T>,C>
+where I:InternalFormat, T:TextureItemType+JsBufferViewArr, C:GpuContext {
     /// Constructor.
-    pub fn new_from_data<P:Into<TextureData<I,T>>>(context:&Context, provider:P)
-    -> Self {
+    pub fn new_from_data<P:Into<TextureData<I,T>>>(context:&C, provider:P) -> Self {
         let out = Self::new_unitialized(context,provider);
         out.reload();
         out
     }
 
+    /// The texture data. Changes are uploaded to the GPU by `reload`.
+    pub fn data(&self) -> &TextureData<I,T> {
+        &self.provider
+    }
+
+    /// Mutable texture data. Changes are uploaded to the GPU by `reload`.
+    pub fn data_mut(&mut self) -> &mut TextureData<I,T> {
+        &mut self.provider
+    }
+
+    /// Uploads the region of the texture data to the GPU. It is much cheaper than `reload` for
+    /// small regions, so use it after modifying a part of `data_mut`.
+    pub fn reload_region(&self, region:&Rect) {
+        self.reload_regions(&[*region])
+    }
+
+    /// Uploads the regions of the texture data to the GPU. The mipmaps, if enabled, are generated
+    /// once all the regions are uploaded, so prefer it over many `reload_region` calls.
+    pub fn reload_regions(&self, regions:&[Rect]) {
+        let target    = Context::TEXTURE_2D;
+        let level     = 0;
+        let format    = Self::gl_format().into();
+        let elem_type = Self::gl_elem_type();
+
+        let data      = &self.provider.data;
+
+        self.context.bind_texture(target,Some(&self.gl_texture));
+        self.context.pixel_storei(Context::UNPACK_ROW_LENGTH,self.provider.width);
+        for region in regions {
+            let (x,y)          = (region.x as i32, region.y as i32);
+            let (width,height) = (region.width as i32, region.height as i32);
+            self.context.pixel_storei(Context::UNPACK_SKIP_PIXELS,x);
+            self.context.pixel_storei(Context::UNPACK_SKIP_ROWS,y);
+            self.context.tex_sub_image_2d(target,level,x,y,width,height,format,elem_type,data);
+        }
+        self.context.pixel_storei(Context::UNPACK_ROW_LENGTH  , 0);
+        self.context.pixel_storei(Context::UNPACK_SKIP_PIXELS , 0);
+        self.context.pixel_storei(Context::UNPACK_SKIP_ROWS   , 0);
+
+        self.parameters.generate_mipmaps(&self.context);
+    }
+
+    /// Replaces the region of the texture by the provided data, consisting of the region pixels
+    /// stored row by row. Both the texture data and the GPU texture are updated. Fails if the
+    /// region exceeds the texture or the data size does not match the region.
+    pub fn set_region(&mut self, region:&Rect, data:&[T]) -> Result<(),RegionError> where T:Copy {
+        let width       = self.provider.width  as usize;
+        let height      = self.provider.height as usize;
+        let pixel_count = width * height;
+        let channels    = self.provider.data.len() / pixel_count.max(1);
+        let expected    = region.area() * channels;
+        let got         = data.len();
+        let rect        = *region;
+        if region.right() > width || region.bottom() > height {
+            return Err(RegionError::OutOfBounds {rect,width,height})
+        }
+        if got != expected {
+            return Err(RegionError::InvalidData {rect,expected,got})
+        }
+        blit(&mut self.provider.data,width,data,region,channels);
+        self.reload_region(region);
+        Ok(())
+    }
+
     /// Loads or re-loads the texture data from provided source.
     pub fn reload(&self) {
         let width           = self.provider.width;
         let height          = self.provider.height;
         let target          = Context::TEXTURE_2D;
         let level           = 0;
-        let border          = 0;
         let internal_format = Self::gl_internal_format();
         let format          = Self::gl_format().into();
         let elem_type       = Self::gl_elem_type();
+        let data            = Some(self.provider.data.as_slice());
 
         self.context.bind_texture(target,Some(&self.gl_texture));
-        unsafe {
-            // We use unsafe array view which is used immediately, so no allocations should happen
-            // until we drop the view.
-            let view = self.provider.data.js_buffer_view();
-            let result = self.context
-                .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view
-                (target,level,internal_format,width,height,border,format,elem_type,Some(&view));
-            result.unwrap();
-        }
+        self.context.tex_image_2d
+            (target,level,internal_format,width,height,format,elem_type,data);
 
-        Self::set_texture_parameters(&self.context);
+        Self::apply_parameters(&self.context,&self.parameters);
     }
 }
 
-impl<Provider> Drop for Texture<Provider> {
+impl<I:InternalFormat,T:TextureItemType,C:GpuContext> Texture<TextureData<I,T>,C> {
+    /// Re-creates the texture after the context was lost and restored, and re-loads its data from
+    /// the provider.
+    pub fn restore_context(&mut self) {
+        self.recreate_gl_texture();
+        self.reload_data();
+    }
+}
+
+/// Uploads the data of a texture whose element type can be viewed as a JS array. Element types
+/// which are not bound to Rust types cannot hold any data, so there is nothing to upload.
+trait ReloadData {
+    fn reload_data(&self);
+}
+
+impl<I,T,C> ReloadData for Texture<TextureData<I,T>,C>
+where I:InternalFormat, T:TextureItemType, C:GpuContext {
+    default fn reload_data(&self) {}
+}
+
+impl<I,T,C> ReloadData for Texture<TextureData<I,T>,C>
+where I:InternalFormat, T:TextureItemType+JsBufferViewArr, C:GpuContext {
+    fn reload_data(&self) {
+        self.reload()
+    }
+}
+
+impl<Provider,C:GpuContext> Drop for Texture<Provider,C> {
     fn drop(&mut self) {
         self.context.delete_texture(Some(&self.gl_texture));
     }
@@ -641,20 +915,27 @@ impl<Provider> Drop for Texture<Provider> {
 
 // === Private API ===
 
-impl<Provider> Texture<Provider> {
-    fn new_unitialized<P:Into<Provider>>(context:&Context, provider:P) -> Self {
+impl<Provider:TextureProvider,C:GpuContext> Texture<Provider,C> {
+    fn new_unitialized<P:Into<Provider>>(context:&C, provider:P) -> Self {
         let context    = context.clone();
         let provider   = provider.into();
         let gl_texture = context.create_texture().unwrap();
-        Self {provider,gl_texture,context}
+        let parameters = provider.parameters();
+        Self {provider,gl_texture,parameters,context}
+    }
+}
+
+impl<Provider,C:GpuContext> Texture<Provider,C> {
+
+    fn recreate_gl_texture(&mut self) {
+        self.gl_texture = self.context.create_texture().unwrap();
     }
 
-    fn set_texture_parameters(context:&Context) {
-        let target = Context::TEXTURE_2D;
-        let wrap   = Context::CLAMP_TO_EDGE as i32;
-        context.tex_parameteri(target,Context::TEXTURE_MIN_FILTER,Context::LINEAR as i32);
-        context.tex_parameteri(target,Context::TEXTURE_WRAP_S    ,wrap);
-        context.tex_parameteri(target,Context::TEXTURE_WRAP_T    ,wrap);
+    /// Applies the parameters to the bound texture and generates its mipmaps if needed. Should be
+    /// called after every upload of the whole texture.
+    fn apply_parameters(context:&C, parameters:&Parameters) {
+        parameters.apply(context);
+        parameters.generate_mipmaps(context);
     }
 }
 
@@ -696,10 +977,10 @@ pub trait ContextTextureOps<Texture> {
     fn bind_texture_unit(&self, texture:&Texture, unit:TextureUnit) -> Self::Guard;
 }
 
-impl<Provider> ContextTextureOps<Texture<Provider>> for Context {
-    type Guard = TextureBindGuard;
+impl<Provider,C:GpuContext> ContextTextureOps<Texture<Provider,C>> for C {
+    type Guard = TextureBindGuard<C>;
 
-    fn bind_texture_unit(&self, texture:&Texture<Provider>, unit:TextureUnit) -> Self::Guard {
+    fn bind_texture_unit(&self, texture:&Texture<Provider,C>, unit:TextureUnit) -> Self::Guard {
         let context    = self.clone();
         let target     = Context::TEXTURE_2D;
         let gl_texture = &texture.gl_texture;
@@ -711,13 +992,14 @@ impl<Provider> ContextTextureOps<Texture<Provider>> for Context {
 }
 
 /// Guard which unbinds texture in specific texture unit on drop.
-pub struct TextureBindGuard {
-    context : Context,
+#[derive(Debug)]
+pub struct TextureBindGuard<C:GpuContext=Context> {
+    context : C,
     target  : u32,
     unit    : TextureUnit,
 }
 
-impl Drop for TextureBindGuard {
+impl<C:GpuContext> Drop for TextureBindGuard<C> {
     fn drop(&mut self) {
         self.context.active_texture(self.unit);
         self.context.bind_texture(self.target,None);
@@ -765,3 +1047,56 @@ macro_rules! with_all_texture_types {
         $crate::with_texture_format_relations! { with_all_texture_types_impl [$f] }
     }
 }
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::system::gpu::context::mock::Call;
+    use crate::system::gpu::context::mock::MockContext;
+    use wasm_bindgen_test::wasm_bindgen_test;
+    use wasm_bindgen_test::wasm_bindgen_test_configure;
+    use web_sys::HtmlCanvasElement;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn set_region_checks_the_region() {
+        let canvas      = web::create_element("canvas").unwrap();
+        let canvas      = canvas.dyn_into::<HtmlCanvasElement>().unwrap();
+        let context     = web::get_webgl2_context(&canvas).unwrap();
+        let data        = TextureData::<Rgba,u8>::new(vec![0;2 * 2 * 4],2,2);
+        let mut texture = Texture::new_from_data(&context,data);
+        let region      = Rect::new(1,0,1,2);
+        let pixels      = [1,1,1,1, 2,2,2,2];
+        assert!(texture.set_region(&Rect::new(1,1,2,1),&pixels).is_err());
+        assert!(texture.set_region(&region,&pixels[..4]).is_err());
+        assert!(texture.set_region(&region,&pixels).is_ok());
+        assert_eq!(texture.data().data,vec![0,0,0,0, 1,1,1,1, 0,0,0,0, 2,2,2,2]);
+    }
+
+    #[test]
+    fn set_parameters_restores_the_bound_texture() {
+        let context     = MockContext::new();
+        let data        = TextureData::<Rgba,u8>::new(vec![0;4],1,1);
+        let mut texture = Texture::new_from_data(&context,data);
+        let own         = *texture.gl_texture();
+        let other       = context.create_texture().unwrap();
+        let target      = Context::TEXTURE_2D;
+        context.bind_texture(target,Some(&other));
+        context.clear();
+        texture.set_parameters(Parameters::mipmapped());
+        assert_eq!(texture.parameters(),Parameters::mipmapped());
+        assert_eq!(context.bound_texture_2d(),Some(other));
+        let calls = context.take_calls();
+        assert_eq!(calls.first(),Some(&Call::BindTexture {target,texture:Some(own)}));
+        assert_eq!(calls.last(),Some(&Call::BindTexture {target,texture:Some(other)}));
+        assert!(calls.contains(&Call::GenerateMipmap {target}));
+    }
+}
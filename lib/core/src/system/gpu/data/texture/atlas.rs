@@ -0,0 +1,855 @@
+//! This module implements texture atlases. An atlas packs many small images into a few large
+//! textures, called pages, so the images can be drawn without re-binding textures between them.
+//! The packing is performed by `ShelfPacker`, which places images next to each other on horizontal
+//! shelves. When a page is full, it is grown, and when it reaches the maximum size, a new page is
+//! added.
+
+use crate::prelude::*;
+
+use crate::system::gpu::context::GpuContext;
+use crate::system::gpu::data::buffer::item::JsBufferViewArr;
+use crate::system::gpu::data::texture::*;
+use crate::system::gpu::data::uniform::AnyUniform;
+use crate::system::gpu::data::uniform::Uniform;
+use crate::system::gpu::data::uniform::UniformScope;
+
+use nalgebra::Vector2;
+use std::mem;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// The default size of a newly created atlas page.
+pub const DEFAULT_PAGE_SIZE : usize = 256;
+
+/// The default maximum size of an atlas page. Pages are grown up to this size before new pages
+/// are added.
+pub const DEFAULT_MAX_PAGE_SIZE : usize = 4096;
+
+/// The default number of empty pixels around every image, preventing colors of neighbouring
+/// images from bleeding into each other when the texture is filtered.
+pub const DEFAULT_PADDING : usize = 1;
+
+
+
+// =============
+// === Error ===
+// =============
+
+/// Atlas error.
+#[derive(Debug,Fail)]
+#[allow(missing_docs)]
+pub enum Error {
+    #[fail(display="Image of size {}x{} does not fit into an atlas page of size {}x{}.",
+        width,height,max_size,max_size)]
+    TooLarge {width:usize, height:usize, max_size:usize},
+    #[fail(display="Image of size {}x{} should consist of {} elements, but it has {} elements.",
+        width,height,expected,got)]
+    InvalidData {width:usize, height:usize, expected:usize, got:usize},
+}
+
+
+
+// =============
+// === Shelf ===
+// =============
+
+/// Horizontal range of free pixels on a shelf.
+#[derive(Clone,Copy,Debug,PartialEq)]
+struct Span {
+    x     : usize,
+    width : usize,
+}
+
+/// A horizontal strip of a page. Images are placed on the shelf from the left to the right, and
+/// the gaps left by freed images are kept in a sorted list of free spans.
+#[derive(Clone,Debug)]
+struct Shelf {
+    y      : usize,
+    height : usize,
+    free   : Vec<Span>,
+}
+
+impl Shelf {
+    fn new(y:usize, height:usize, width:usize) -> Self {
+        let free = vec![Span {x:0,width}];
+        Self {y,height,free}
+    }
+
+    fn can_allocate(&self, width:usize) -> bool {
+        self.free.iter().any(|span| span.width >= width)
+    }
+
+    /// Allocates a span of the provided width in the first gap it fits in. Returns its position.
+    fn allocate(&mut self, width:usize) -> Option<usize> {
+        let index = self.free.iter().position(|span| span.width >= width)?;
+        let span  = &mut self.free[index];
+        let x     = span.x;
+        span.x     += width;
+        span.width -= width;
+        if span.width == 0 {
+            self.free.remove(index);
+        }
+        Some(x)
+    }
+
+    /// Returns the span to the free list, merging it with the adjacent free spans.
+    fn free(&mut self, x:usize, width:usize) {
+        let index = self.free.iter().position(|span| span.x > x).unwrap_or(self.free.len());
+        self.free.insert(index,Span {x,width});
+        if index + 1 < self.free.len() && x + width == self.free[index + 1].x {
+            self.free[index].width += self.free.remove(index + 1).width;
+        }
+        if index > 0 && self.free[index - 1].x + self.free[index - 1].width == x {
+            self.free[index - 1].width += self.free.remove(index).width;
+        }
+    }
+
+    fn is_empty(&self, page_width:usize) -> bool {
+        self.free.len() == 1 && self.free[0].width == page_width
+    }
+}
+
+
+
+// ===================
+// === ShelfPacker ===
+// ===================
+
+/// Rectangle packer using the shelf algorithm. An image is placed on the lowest shelf it fits in,
+/// unless that shelf is much higher than the image, in which case a new shelf is opened. Freed
+/// space is reused by images of the same or smaller height, and shelves which become empty are
+/// merged together, so they can be reused by images of any height.
+#[derive(Clone,Debug)]
+pub struct ShelfPacker {
+    width     : usize,
+    height    : usize,
+    shelves   : Vec<Shelf>,
+    used_area : usize,
+}
+
+impl ShelfPacker {
+    /// Constructor.
+    pub fn new(width:usize, height:usize) -> Self {
+        let shelves   = default();
+        let used_area = default();
+        Self {width,height,shelves,used_area}
+    }
+
+    /// The width of the packed area.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The height of the packed area.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The number of allocated pixels.
+    pub fn used_area(&self) -> usize {
+        self.used_area
+    }
+
+    /// Checks whether nothing is allocated.
+    pub fn is_empty(&self) -> bool {
+        self.used_area == 0
+    }
+
+    /// Allocates a rectangle of the provided size. Returns `None` if there is no space left.
+    pub fn allocate(&mut self, width:usize, height:usize) -> Option<Rect> {
+        let width      = width.max(1);
+        let height     = height.max(1);
+        let page_width = self.width;
+        if width > self.width || height > self.height { return None }
+        let tight      = |shelf:&Shelf| {
+            shelf.height - height <= height / 2 || shelf.is_empty(page_width)
+        };
+        let index = self.find_shelf(width,height,tight)
+            .or_else(|| self.add_shelf(height))
+            .or_else(|| self.find_shelf(width,height,|_| true))?;
+        self.split_shelf(index,height);
+        let shelf = &mut self.shelves[index];
+        let x     = shelf.allocate(width)?;
+        let rect  = Rect::new(x,shelf.y,width,height);
+        self.used_area += rect.area();
+        Some(rect)
+    }
+
+    /// Frees a rectangle returned by `allocate`.
+    pub fn free(&mut self, rect:&Rect) {
+        if let Some(index) = self.shelves.iter().position(|shelf| shelf.y == rect.y) {
+            self.shelves[index].free(rect.x,rect.width);
+            self.used_area -= rect.area();
+            self.merge_empty_shelves();
+        }
+    }
+
+    /// Enlarges the packed area. The allocated rectangles are not moved.
+    pub fn grow(&mut self, width:usize, height:usize) {
+        let old_width = self.width;
+        self.width    = width.max(old_width);
+        self.height   = height.max(self.height);
+        if self.width > old_width {
+            for shelf in &mut self.shelves {
+                shelf.free(old_width,self.width - old_width);
+            }
+        }
+    }
+}
+
+
+// === Private API ===
+
+impl ShelfPacker {
+    /// The lowest accepted shelf with enough space for the rectangle.
+    fn find_shelf<F:Fn(&Shelf)->bool>(&self, width:usize, height:usize, accept:F) -> Option<usize> {
+        let fits = self.shelves.iter().enumerate().filter(|(_,shelf)| {
+            shelf.height >= height && shelf.can_allocate(width) && accept(shelf)
+        });
+        fits.min_by_key(|(_,shelf)| shelf.height).map(|(index,_)| index)
+    }
+
+    /// Opens a new shelf above the existing ones, if there is enough space for it.
+    fn add_shelf(&mut self, height:usize) -> Option<usize> {
+        let y = self.shelves.last().map(|shelf| shelf.y + shelf.height).unwrap_or(0);
+        if y + height > self.height { return None }
+        self.shelves.push(Shelf::new(y,height,self.width));
+        Some(self.shelves.len() - 1)
+    }
+
+    /// Splits an empty shelf higher than needed, so the remaining part can be used by other
+    /// images.
+    fn split_shelf(&mut self, index:usize, height:usize) {
+        let shelf = &mut self.shelves[index];
+        if shelf.height > height && shelf.is_empty(self.width) {
+            let rest = Shelf::new(shelf.y + height,shelf.height - height,self.width);
+            shelf.height = height;
+            self.shelves.insert(index + 1,rest);
+        }
+    }
+
+    /// Merges adjacent empty shelves and removes the topmost shelf if it is empty.
+    fn merge_empty_shelves(&mut self) {
+        let width     = self.width;
+        let mut index = 0;
+        while index + 1 < self.shelves.len() {
+            if self.shelves[index].is_empty(width) && self.shelves[index + 1].is_empty(width) {
+                let next = self.shelves.remove(index + 1);
+                self.shelves[index].height += next.height;
+            } else {
+                index += 1;
+            }
+        }
+        if self.shelves.last().map(|shelf| shelf.is_empty(width)) == Some(true) {
+            self.shelves.pop();
+        }
+    }
+}
+
+
+
+// ======================
+// === AtlasAllocator ===
+// ======================
+
+/// Handle to an image stored in an atlas.
+#[derive(Clone,Copy,Debug,Eq,Hash,Ord,PartialEq,PartialOrd)]
+pub struct AtlasId(usize);
+
+/// Location of an image in an atlas. The `rect` does not include the padding.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+#[allow(missing_docs)]
+pub struct Region {
+    pub page : usize,
+    pub rect : Rect,
+}
+
+/// Texture coordinates of an image in its page.
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[allow(missing_docs)]
+pub struct UvRect {
+    pub min : Vector2<f32>,
+    pub max : Vector2<f32>,
+}
+
+/// Atlas parameters.
+#[derive(Clone,Copy,Debug)]
+pub struct AtlasConfig {
+    /// The size of a newly created page. Pages are square.
+    pub page_size     : usize,
+    /// The maximum size of a page.
+    pub max_page_size : usize,
+    /// The number of empty pixels around every image.
+    pub padding       : usize,
+}
+
+impl Default for AtlasConfig {
+    fn default() -> Self {
+        let page_size     = DEFAULT_PAGE_SIZE;
+        let max_page_size = DEFAULT_MAX_PAGE_SIZE;
+        let padding       = DEFAULT_PADDING;
+        Self {page_size,max_page_size,padding}
+    }
+}
+
+/// Allocates regions of atlas pages. When no page has space for a new image, the pages are grown
+/// by doubling their size, up to `max_page_size`, and then a new page is added. Please note that
+/// growing a page changes the texture coordinates of all its images. Use `take_invalidated` to
+/// learn which images have to have their texture coordinates updated.
+#[derive(Clone,Debug)]
+pub struct AtlasAllocator {
+    config      : AtlasConfig,
+    pages       : Vec<ShelfPacker>,
+    regions     : HashMap<AtlasId,Region>,
+    invalidated : HashSet<AtlasId>,
+    next_id     : usize,
+    version     : usize,
+}
+
+impl AtlasAllocator {
+    /// Constructor.
+    pub fn new(config:AtlasConfig) -> Self {
+        let pages       = default();
+        let regions     = default();
+        let invalidated = default();
+        let next_id     = default();
+        let version     = default();
+        Self {config,pages,regions,invalidated,next_id,version}
+    }
+
+    /// Allocates a region for an image of the provided size.
+    pub fn allocate(&mut self, width:usize, height:usize) -> Result<(AtlasId,Region),Error> {
+        let padding       = self.config.padding;
+        let max_size      = self.config.max_page_size;
+        let padded_width  = width.max(1)  + 2 * padding;
+        let padded_height = height.max(1) + 2 * padding;
+        if padded_width > max_size || padded_height > max_size {
+            return Err(Error::TooLarge {width,height,max_size})
+        }
+        let (page,padded) = self.allocate_padded(padded_width,padded_height);
+        let rect          = Rect::new(padded.x + padding,padded.y + padding,width,height);
+        let region        = Region {page,rect};
+        let id            = AtlasId(self.next_id);
+        self.next_id += 1;
+        self.regions.insert(id,region);
+        Ok((id,region))
+    }
+
+    /// Frees the region of the image. Returns `None` if the id was already freed.
+    pub fn free(&mut self, id:AtlasId) -> Option<Region> {
+        let region  = self.regions.remove(&id)?;
+        let padding = self.config.padding;
+        self.invalidated.remove(&id);
+        let rect    = &region.rect;
+        let width   = rect.width.max(1)  + 2 * padding;
+        let height  = rect.height.max(1) + 2 * padding;
+        let padded  = Rect::new(rect.x - padding,rect.y - padding,width,height);
+        self.pages[region.page].free(&padded);
+        Some(region)
+    }
+
+    /// The region of the image.
+    pub fn region(&self, id:AtlasId) -> Option<Region> {
+        self.regions.get(&id).copied()
+    }
+
+    /// The texture coordinates of the image in its page.
+    pub fn uv(&self, id:AtlasId) -> Option<UvRect> {
+        let region = self.region(id)?;
+        let page   = &self.pages[region.page];
+        let width  = page.width()  as f32;
+        let height = page.height() as f32;
+        let rect   = region.rect;
+        let min    = Vector2::new(rect.x as f32 / width, rect.y as f32 / height);
+        let max    = Vector2::new(rect.right() as f32 / width, rect.bottom() as f32 / height);
+        Some(UvRect {min,max})
+    }
+
+    /// The number of pages.
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// The width and height of the page.
+    pub fn page_size(&self, page:usize) -> Option<(usize,usize)> {
+        self.pages.get(page).map(|page| (page.width(),page.height()))
+    }
+
+    /// The number of stored images.
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Checks whether no images are stored.
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Counter incremented every time a page grows and the texture coordinates of its images
+    /// change.
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// Takes the images whose texture coordinates changed since the last call, because their page
+    /// grew. The images are sorted by their ids. Removed images are not reported.
+    pub fn take_invalidated(&mut self) -> Vec<AtlasId> {
+        let mut ids = mem::take(&mut self.invalidated).into_iter().collect_vec();
+        ids.sort();
+        ids
+    }
+}
+
+
+// === Private API ===
+
+impl AtlasAllocator {
+    fn allocate_padded(&mut self, width:usize, height:usize) -> (usize,Rect) {
+        for (index,page) in self.pages.iter_mut().enumerate() {
+            if let Some(rect) = page.allocate(width,height) { return (index,rect) }
+        }
+        let max_size = self.config.max_page_size;
+        for index in 0..self.pages.len() {
+            let page = &mut self.pages[index];
+            if page.width() < max_size || page.height() < max_size {
+                let mut rect = None;
+                while rect.is_none() && (page.width() < max_size || page.height() < max_size) {
+                    let new_width  = (page.width()  * 2).min(max_size);
+                    let new_height = (page.height() * 2).min(max_size);
+                    page.grow(new_width,new_height);
+                    self.version += 1;
+                    rect = page.allocate(width,height);
+                }
+                self.invalidate_page(index);
+                if let Some(rect) = rect { return (index,rect) }
+            }
+        }
+        let mut size = self.config.page_size.max(1);
+        while size < width.max(height) { size *= 2 }
+        let size     = size.min(max_size);
+        let mut page = ShelfPacker::new(size,size);
+        let rect     = page.allocate(width,height).expect("Image does not fit into a new page.");
+        self.pages.push(page);
+        (self.pages.len() - 1, rect)
+    }
+
+    fn invalidate_page(&mut self, page:usize) {
+        let ids = self.regions.iter().filter(|(_,region)| region.page == page).map(|(id,_)| *id);
+        self.invalidated.extend(ids);
+    }
+}
+
+
+
+// ====================
+// === TextureAtlas ===
+// ====================
+
+/// Texture page of an atlas.
+pub type PageTexture<I,T,C=Context> = Uniform<Texture<TextureData<I,T>,C>>;
+
+/// Atlas of images stored in textures. Every page is registered as a texture uniform named
+/// `<name>_<page_index>` in the provided scope. The image data is kept in memory, so pages can be
+/// grown and restored after the context was lost. Call `update` to upload modified pages.
+///
+/// Growing a page changes the texture coordinates of the images it already contains. The
+/// callback set by `set_on_uv_changed` is run for every such image with its new coordinates.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct TextureAtlas<I,T,C:GpuContext=Context> {
+    name          : String,
+    allocator     : AtlasAllocator,
+    pages         : Vec<PageTexture<I,T,C>>,
+    page_sizes    : Vec<(usize,usize)>,
+    dirty         : HashSet<usize>,
+    dirty_rects   : Vec<(usize,Rect)>,
+    channels      : usize,
+    scope         : UniformScope<C>,
+    #[derivative(Debug="ignore")]
+    on_uv_changed : Option<Box<dyn Fn(AtlasId,UvRect)>>,
+}
+
+impl<I,T,C> TextureAtlas<I,T,C>
+where I : InternalFormat,
+      T : TextureItemType + JsBufferViewArr + Copy + Default,
+      C : GpuContext,
+      PageTexture<I,T,C> : Into<AnyUniform<C>> {
+    /// Constructor. The `channels` is the number of elements describing a single pixel, like 4 for
+    /// `Rgba` images with `u8` elements.
+    pub fn new<S:Str>(name:S, scope:&UniformScope<C>, channels:usize, config:AtlasConfig) -> Self {
+        let name          = name.into();
+        let allocator     = AtlasAllocator::new(config);
+        let pages         = default();
+        let page_sizes    = default();
+        let dirty         = default();
+        let dirty_rects   = default();
+        let scope         = scope.clone_ref();
+        let on_uv_changed = default();
+        Self {name,allocator,pages,page_sizes,dirty,dirty_rects,channels,scope,on_uv_changed}
+    }
+
+    /// Sets the callback run with the new texture coordinates of every image whose page grew.
+    /// The callback is run during `insert`, for the images inserted before.
+    pub fn set_on_uv_changed<F:Fn(AtlasId,UvRect)+'static>(&mut self, f:F) {
+        self.on_uv_changed = Some(Box::new(f));
+    }
+
+    /// Copies the image into the atlas. The image becomes visible on the GPU after `update`.
+    pub fn insert(&mut self, image:&TextureData<I,T>) -> Result<AtlasId,Error> {
+        let width    = image.width  as usize;
+        let height   = image.height as usize;
+        let expected = width * height * self.channels;
+        let got      = image.data.len();
+        if got != expected {
+            return Err(Error::InvalidData {width,height,expected,got})
+        }
+        let (id,region) = self.allocator.allocate(width,height)?;
+        self.sync_pages();
+        self.emit_uv_changes();
+        let channels       = self.channels;
+        let (page_width,_) = self.page_sizes[region.page];
+        self.pages[region.page].modify(|texture| {
+            blit(&mut texture.data_mut().data,page_width,&image.data,&region.rect,channels)
+        });
+        self.dirty_rects.push((region.page,region.rect));
+        Ok(id)
+    }
+
+    /// Frees the space of the image, so it can be used by other images. Returns `false` if the
+    /// image was already removed.
+    pub fn remove(&mut self, id:AtlasId) -> bool {
+        self.allocator.free(id).is_some()
+    }
+
+    /// The texture coordinates of the image in its page.
+    pub fn uv(&self, id:AtlasId) -> Option<UvRect> {
+        self.allocator.uv(id)
+    }
+
+    /// The index of the page containing the image.
+    pub fn page_of(&self, id:AtlasId) -> Option<usize> {
+        self.allocator.region(id).map(|region| region.page)
+    }
+
+    /// The texture of the page.
+    pub fn page(&self, page:usize) -> Option<&PageTexture<I,T,C>> {
+        self.pages.get(page)
+    }
+
+    /// The name of the uniform of the page.
+    pub fn page_name(&self, page:usize) -> String {
+        format!("{}_{}",self.name,page)
+    }
+
+    /// The number of pages.
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// The allocator keeping the layout of the atlas.
+    pub fn allocator(&self) -> &AtlasAllocator {
+        &self.allocator
+    }
+
+    /// Uploads the modified pages to the GPU. New and grown pages are uploaded entirely, while for
+    /// other pages only the regions of the inserted images are uploaded.
+    pub fn update(&mut self) {
+        let dirty       = mem::take(&mut self.dirty);
+        let mut regions = HashMap::<usize,Vec<Rect>>::new();
+        for (page,rect) in mem::take(&mut self.dirty_rects) {
+            if !dirty.contains(&page) { regions.entry(page).or_default().push(rect) }
+        }
+        for (page,rects) in regions {
+            self.pages[page].modify(|texture| texture.reload_regions(&rects));
+        }
+        for page in dirty {
+            self.pages[page].modify(|texture| texture.reload());
+        }
+    }
+}
+
+
+// === Private API ===
+
+impl<I,T,C> TextureAtlas<I,T,C>
+where I : InternalFormat,
+      T : TextureItemType + JsBufferViewArr + Copy + Default,
+      C : GpuContext,
+      PageTexture<I,T,C> : Into<AnyUniform<C>> {
+    /// Runs the `on_uv_changed` callback for the images whose page grew.
+    fn emit_uv_changes(&mut self) {
+        let ids = self.allocator.take_invalidated();
+        if let Some(callback) = &self.on_uv_changed {
+            for id in ids {
+                if let Some(uv) = self.allocator.uv(id) { callback(id,uv) }
+            }
+        }
+    }
+
+    /// Creates textures for new pages and resizes the textures of grown pages.
+    fn sync_pages(&mut self) {
+        let channels = self.channels;
+        for page in 0..self.allocator.page_count() {
+            let size = self.allocator.page_size(page).unwrap_or_default();
+            let (width,height) = size;
+            if page >= self.pages.len() {
+                let data    = vec![T::default();width * height * channels];
+                let data    = TextureData::new(data,width as i32,height as i32);
+                let texture = self.scope.add_or_panic(self.page_name(page),data);
+                self.pages.push(texture);
+                self.page_sizes.push(size);
+                self.dirty.insert(page);
+            } else if self.page_sizes[page] != size {
+                let (old_width,_) = self.page_sizes[page];
+                self.pages[page].modify(|texture| {
+                    let data = texture.data_mut();
+                    data.data   = resize_image(&data.data,old_width,width,height,channels);
+                    data.width  = width  as i32;
+                    data.height = height as i32;
+                });
+                self.page_sizes[page] = size;
+                self.dirty.insert(page);
+            }
+        }
+    }
+}
+
+
+
+// =============
+// === Utils ===
+// =============
+
+/// Copies the image into a new, larger one. The new pixels are set to the default value.
+pub fn resize_image<T:Copy+Default>
+(data:&[T], width:usize, new_width:usize, new_height:usize, channels:usize) -> Vec<T> {
+    let mut out = vec![T::default();new_width * new_height * channels];
+    let height  = if width == 0 { 0 } else { data.len() / (width * channels) };
+    let rect    = Rect::new(0,0,width,height);
+    blit(&mut out,new_width,data,&rect,channels);
+    out
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::system::gpu::context::mock::Call;
+    use crate::system::gpu::context::mock::MockContext;
+
+    fn assert_disjoint(rects:&[Rect]) {
+        for (i,a) in rects.iter().enumerate() {
+            for b in &rects[i+1..] {
+                assert!(!a.intersects(b),"{:?} intersects {:?}",a,b);
+            }
+        }
+    }
+
+    #[test]
+    fn packed_rects_do_not_overlap() {
+        let mut packer = ShelfPacker::new(64,64);
+        let sizes      = [(10,10),(20,8),(5,12),(30,10),(12,12),(8,8),(40,4),(3,3)];
+        let rects      = sizes.iter().map(|(w,h)| packer.allocate(*w,*h).unwrap()).collect_vec();
+        assert_disjoint(&rects);
+        for rect in &rects {
+            assert!(rect.right() <= 64 && rect.bottom() <= 64);
+        }
+        assert_eq!(packer.used_area(),rects.iter().map(|rect| rect.area()).sum::<usize>());
+    }
+
+    #[test]
+    fn full_packer_rejects_allocation() {
+        let mut packer = ShelfPacker::new(16,16);
+        for _ in 0..4 {
+            assert!(packer.allocate(8,8).is_some());
+        }
+        assert!(packer.allocate(1,1).is_none());
+        assert!(packer.allocate(17,1).is_none());
+    }
+
+    #[test]
+    fn freed_space_is_reused() {
+        let mut packer = ShelfPacker::new(16,16);
+        let rects      = (0..4).map(|_| packer.allocate(8,8).unwrap()).collect_vec();
+        packer.free(&rects[1]);
+        assert_eq!(packer.allocate(8,8),Some(rects[1]));
+        packer.free(&rects[2]);
+        packer.free(&rects[3]);
+        assert_eq!(packer.allocate(16,8),Some(Rect::new(0,8,16,8)));
+    }
+
+    #[test]
+    fn empty_shelves_are_merged() {
+        let mut packer = ShelfPacker::new(16,16);
+        let a          = packer.allocate(16,4).unwrap();
+        let b          = packer.allocate(16,4).unwrap();
+        let c          = packer.allocate(16,4).unwrap();
+        packer.free(&a);
+        packer.free(&b);
+        assert_eq!(packer.allocate(16,8),Some(Rect::new(0,0,16,8)));
+        packer.free(&c);
+        assert_eq!(packer.allocate(16,8),Some(Rect::new(0,8,16,8)));
+        packer.free(&Rect::new(0,0,16,8));
+        packer.free(&Rect::new(0,8,16,8));
+        assert!(packer.is_empty());
+        assert_eq!(packer.allocate(16,16),Some(Rect::new(0,0,16,16)));
+    }
+
+    #[test]
+    fn grown_packer_extends_shelves() {
+        let mut packer = ShelfPacker::new(8,8);
+        packer.allocate(8,8).unwrap();
+        assert!(packer.allocate(8,8).is_none());
+        packer.grow(16,16);
+        assert_eq!(packer.allocate(8,8),Some(Rect::new(8,0,8,8)));
+        assert_eq!(packer.allocate(16,8),Some(Rect::new(0,8,16,8)));
+    }
+
+    #[test]
+    fn allocator_applies_padding() {
+        let config        = AtlasConfig {page_size:32, max_page_size:32, padding:1};
+        let mut allocator = AtlasAllocator::new(config);
+        let (_,a)         = allocator.allocate(6,6).unwrap();
+        let (b_id,b)      = allocator.allocate(6,6).unwrap();
+        assert_eq!(a.rect,Rect::new(1,1,6,6));
+        assert_eq!(b.rect,Rect::new(9,1,6,6));
+        let uv = allocator.uv(b_id).unwrap();
+        assert_eq!(uv.min,Vector2::new(9.0/32.0,1.0/32.0));
+        assert_eq!(uv.max,Vector2::new(15.0/32.0,7.0/32.0));
+        assert_eq!(allocator.free(b_id),Some(b));
+        assert_eq!(allocator.free(b_id),None);
+        assert_eq!(allocator.allocate(6,6).unwrap().1,b);
+    }
+
+    #[test]
+    fn allocator_grows_pages_and_adds_new_ones() {
+        let config        = AtlasConfig {page_size:16, max_page_size:32, padding:0};
+        let mut allocator = AtlasAllocator::new(config);
+        allocator.allocate(16,16).unwrap();
+        assert_eq!(allocator.page_size(0),Some((16,16)));
+        let (_,region) = allocator.allocate(16,16).unwrap();
+        assert_eq!(region.page,0);
+        assert_eq!(allocator.page_size(0),Some((32,32)));
+        assert_eq!(allocator.version(),1);
+        assert_eq!(allocator.take_invalidated(),vec![AtlasId(0)]);
+        assert!(allocator.take_invalidated().is_empty());
+        allocator.allocate(16,16).unwrap();
+        allocator.allocate(16,16).unwrap();
+        let (_,region) = allocator.allocate(16,16).unwrap();
+        assert_eq!(region.page,1);
+        assert_eq!(allocator.page_count(),2);
+        assert_eq!(allocator.len(),5);
+    }
+
+    #[test]
+    fn growing_a_page_invalidates_the_uvs_of_its_images() {
+        let config        = AtlasConfig {page_size:16, max_page_size:64, padding:0};
+        let mut allocator = AtlasAllocator::new(config);
+        let (a,_)         = allocator.allocate(8,8).unwrap();
+        let (b,_)         = allocator.allocate(8,8).unwrap();
+        let (c,_)         = allocator.allocate(16,8).unwrap();
+        let uv            = allocator.uv(c).unwrap();
+        allocator.free(b);
+        assert!(allocator.take_invalidated().is_empty());
+        allocator.allocate(32,32).unwrap();
+        assert_eq!(allocator.page_size(0),Some((64,64)));
+        assert_ne!(allocator.uv(c),Some(uv));
+        allocator.free(a);
+        assert_eq!(allocator.take_invalidated(),vec![c]);
+    }
+
+    #[test]
+    fn allocator_rejects_too_large_images() {
+        let config        = AtlasConfig {page_size:16, max_page_size:32, padding:1};
+        let mut allocator = AtlasAllocator::new(config);
+        assert!(allocator.allocate(30,30).is_ok());
+        assert!(allocator.allocate(31,1).is_err());
+    }
+
+    #[test]
+    fn blit_and_resize() {
+        let mut page = vec![0;4 * 3 * 2];
+        blit(&mut page,4,&[1,1,2,2,3,3,4,4],&Rect::new(1,1,2,2),2);
+        assert_eq!(page,vec![0,0,0,0,0,0,0,0, 0,0,1,1,2,2,0,0, 0,0,3,3,4,4,0,0]);
+        let resized = resize_image(&[1,2,3,4],2,3,3,1);
+        assert_eq!(resized,vec![1,2,0, 3,4,0, 0,0,0]);
+    }
+
+    type TestAtlas = TextureAtlas<Rgba,u8,MockContext>;
+
+    fn new_atlas(context:&MockContext) -> TestAtlas {
+        let scope  = UniformScope::new(Logger::new("test"),context);
+        let config = AtlasConfig {page_size:16, max_page_size:64, padding:0};
+        TextureAtlas::new("atlas",&scope,4,config)
+    }
+
+    fn image(width:usize, height:usize, value:u8) -> TextureData<Rgba,u8> {
+        TextureData::new(vec![value;width * height * 4],width as i32,height as i32)
+    }
+
+    fn pixel(atlas:&TestAtlas, id:AtlasId) -> u8 {
+        let region   = atlas.allocator().region(id).unwrap();
+        let mut page = atlas.page(region.page).unwrap().clone_ref();
+        let mut out  = 0;
+        page.modify(|texture| {
+            let width = texture.data().width as usize;
+            out = texture.data().data[(region.rect.y * width + region.rect.x) * 4];
+        });
+        out
+    }
+
+    fn uploads(context:&MockContext) -> (Vec<(i32,i32)>,Vec<(i32,i32)>) {
+        let mut full    = Vec::new();
+        let mut regions = Vec::new();
+        for call in context.take_calls() {
+            match call {
+                Call::TexImage2D    {width,height,..} => full.push((width,height)),
+                Call::TexSubImage2D {width,height,..} => regions.push((width,height)),
+                _ => {}
+            }
+        }
+        (full,regions)
+    }
+
+    #[test]
+    fn growing_a_page_reports_the_new_uvs_of_its_images() {
+        let context     = MockContext::new();
+        let mut atlas   = new_atlas(&context);
+        let changes     = Rc::new(RefCell::new(Vec::new()));
+        let changes_ref = changes.clone();
+        atlas.set_on_uv_changed(move |id,uv| changes_ref.borrow_mut().push((id,uv)));
+
+        let a = atlas.insert(&image(16,16,1)).unwrap();
+        assert_eq!(atlas.uv(a).unwrap().max,Vector2::new(1.0,1.0));
+        context.clear();
+        atlas.update();
+        assert_eq!(uploads(&context),(vec![(16,16)],vec![]));
+
+        let b = atlas.insert(&image(8,8,2)).unwrap();
+        assert_eq!(atlas.page_count(),1);
+        assert_eq!(atlas.allocator().page_size(0),Some((32,32)));
+        let uv = UvRect {min:Vector2::new(0.0,0.0), max:Vector2::new(0.5,0.5)};
+        assert_eq!(*changes.borrow(),vec![(a,uv)]);
+        assert_eq!(atlas.uv(a),Some(uv));
+        assert_eq!(pixel(&atlas,a),1);
+        assert_eq!(pixel(&atlas,b),2);
+        atlas.update();
+        assert_eq!(uploads(&context),(vec![(32,32)],vec![]));
+
+        let c = atlas.insert(&image(8,8,3)).unwrap();
+        assert_eq!(changes.borrow().len(),1);
+        assert_eq!(pixel(&atlas,c),3);
+        atlas.update();
+        assert_eq!(uploads(&context),(vec![],vec![(8,8)]));
+    }
+}
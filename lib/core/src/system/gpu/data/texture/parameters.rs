@@ -0,0 +1,282 @@
+//! This module defines texture sampling parameters, like filtering, wrapping and mipmap generation.
+//! Follow the link to learn more:
+//! https://developer.mozilla.org/en-US/docs/Web/API/WebGLRenderingContext/texParameter
+
+use crate::prelude::*;
+
+use crate::system::gpu::context::GpuContext;
+use crate::system::gpu::data::gl_enum::GlEnum;
+use crate::system::gpu::shader::Context;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// The name of the extension providing anisotropic filtering.
+pub const ANISOTROPIC_EXTENSION : &str = "EXT_texture_filter_anisotropic";
+
+/// The `TEXTURE_MAX_ANISOTROPY_EXT` parameter of the anisotropic filtering extension.
+pub const TEXTURE_MAX_ANISOTROPY : u32 = 0x84FE;
+
+
+
+// ===============
+// === Filters ===
+// ===============
+
+/// Texture minification filters.
+pub mod min_filter {
+    use super::*;
+    crate::define_singleton_enum_gl! {
+        /// Filter used when the texture is displayed smaller than its size. The mipmap variants
+        /// require mipmaps, see `Parameters::mipmaps`.
+        #[derive(Eq,PartialEq)]
+        MinFilter {
+            /// The value of the nearest texel.
+            Nearest = Context::NEAREST,
+            /// The weighted average of the four nearest texels.
+            Linear = Context::LINEAR,
+            /// The nearest texel of the nearest mipmap.
+            NearestMipmapNearest = Context::NEAREST_MIPMAP_NEAREST,
+            /// The weighted average of the texels of the nearest mipmap.
+            LinearMipmapNearest = Context::LINEAR_MIPMAP_NEAREST,
+            /// The nearest texels of the two nearest mipmaps, interpolated.
+            NearestMipmapLinear = Context::NEAREST_MIPMAP_LINEAR,
+            /// The weighted averages of the texels of the two nearest mipmaps, interpolated.
+            LinearMipmapLinear = Context::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+}
+pub use min_filter::MinFilter;
+
+impl MinFilter {
+    /// Checks whether the filter samples mipmaps.
+    pub fn uses_mipmaps(self) -> bool {
+        match self {
+            Self::Nearest | Self::Linear => false,
+            _                            => true,
+        }
+    }
+
+    /// The filter sampling only the base level of the texture, which is the closest to this one.
+    pub fn without_mipmaps(self) -> Self {
+        match self {
+            Self::NearestMipmapNearest | Self::NearestMipmapLinear => Self::Nearest,
+            Self::LinearMipmapNearest  | Self::LinearMipmapLinear  => Self::Linear,
+            other                                                  => other,
+        }
+    }
+}
+
+/// Texture magnification filters.
+pub mod mag_filter {
+    use super::*;
+    crate::define_singleton_enum_gl! {
+        /// Filter used when the texture is displayed larger than its size.
+        #[derive(Eq,PartialEq)]
+        MagFilter {
+            /// The value of the nearest texel.
+            Nearest = Context::NEAREST,
+            /// The weighted average of the four nearest texels.
+            Linear = Context::LINEAR,
+        }
+    }
+}
+pub use mag_filter::MagFilter;
+
+
+
+// ============
+// === Wrap ===
+// ============
+
+/// Texture wrapping modes.
+pub mod wrap {
+    use super::*;
+    crate::define_singleton_enum_gl! {
+        /// Specifies how the texture coordinates outside of the [0,1] range are handled.
+        #[derive(Eq,PartialEq)]
+        Wrap {
+            /// The coordinates are clamped to the texture edge.
+            ClampToEdge = Context::CLAMP_TO_EDGE,
+            /// The texture is repeated.
+            Repeat = Context::REPEAT,
+            /// The texture is repeated, every second repetition being mirrored.
+            MirroredRepeat = Context::MIRRORED_REPEAT,
+        }
+    }
+}
+pub use wrap::Wrap;
+
+
+
+// ==================
+// === Parameters ===
+// ==================
+
+/// Sampling parameters of a texture.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Parameters {
+    /// The minification filter.
+    pub min_filter : MinFilter,
+    /// The magnification filter.
+    pub mag_filter : MagFilter,
+    /// The wrapping of the horizontal coordinate.
+    pub wrap_s     : Wrap,
+    /// The wrapping of the vertical coordinate.
+    pub wrap_t     : Wrap,
+    /// The maximum anisotropy used for filtering. The value of 1 disables anisotropic filtering.
+    /// It is ignored if the anisotropic filtering extension is not available.
+    pub anisotropy : f32,
+    /// Checks whether mipmaps are generated every time the texture data is uploaded.
+    pub mipmaps    : bool,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        let min_filter = MinFilter::Linear;
+        let mag_filter = MagFilter::Linear;
+        let wrap_s     = Wrap::ClampToEdge;
+        let wrap_t     = Wrap::ClampToEdge;
+        let anisotropy = 1.0;
+        let mipmaps    = false;
+        Self {min_filter,mag_filter,wrap_s,wrap_t,anisotropy,mipmaps}
+    }
+}
+
+impl Parameters {
+    /// Parameters of a texture with generated mipmaps, filtered with trilinear filtering.
+    pub fn mipmapped() -> Self {
+        let min_filter = MinFilter::LinearMipmapLinear;
+        let mipmaps    = true;
+        Self {min_filter,mipmaps,..default()}
+    }
+
+    /// Sets both wrapping modes.
+    pub fn with_wrap(mut self, wrap:Wrap) -> Self {
+        self.wrap_s = wrap;
+        self.wrap_t = wrap;
+        self
+    }
+
+    /// Sets both filters. The mipmap filtering of `Parameters::mipmapped` is preserved.
+    pub fn with_filter(mut self, filter:MagFilter) -> Self {
+        self.mag_filter = filter;
+        self.min_filter = match (filter, self.min_filter.uses_mipmaps()) {
+            (MagFilter::Nearest , false) => MinFilter::Nearest,
+            (MagFilter::Nearest , true)  => MinFilter::NearestMipmapNearest,
+            (MagFilter::Linear  , false) => MinFilter::Linear,
+            (MagFilter::Linear  , true)  => MinFilter::LinearMipmapLinear,
+        };
+        self
+    }
+
+    /// The minification filter which can be used with these parameters. A texture sampled with a
+    /// mipmap filter but without mipmaps is incomplete and renders black, so the mipmap filters
+    /// are replaced by their base level counterparts if the mipmaps are not generated.
+    pub fn effective_min_filter(&self) -> MinFilter {
+        if self.mipmaps { self.min_filter } else { self.min_filter.without_mipmaps() }
+    }
+
+    /// Applies the parameters to the texture bound to the `TEXTURE_2D` target.
+    pub fn apply<C:GpuContext>(&self, context:&C) {
+        let target     = Context::TEXTURE_2D;
+        let min_filter = gl_int(self.effective_min_filter());
+        let mag_filter = gl_int(self.mag_filter);
+        context.tex_parameteri(target,Context::TEXTURE_MIN_FILTER,min_filter);
+        context.tex_parameteri(target,Context::TEXTURE_MAG_FILTER,mag_filter);
+        context.tex_parameteri(target,Context::TEXTURE_WRAP_S,gl_int(self.wrap_s));
+        context.tex_parameteri(target,Context::TEXTURE_WRAP_T,gl_int(self.wrap_t));
+        if self.anisotropy > 1.0 && context.has_extension(ANISOTROPIC_EXTENSION) {
+            context.tex_parameterf(target,TEXTURE_MAX_ANISOTROPY,self.anisotropy);
+        }
+    }
+
+    /// Generates the mipmaps of the texture bound to the `TEXTURE_2D` target if they are enabled.
+    /// Should be called after every upload of the texture data.
+    pub fn generate_mipmaps<C:GpuContext>(&self, context:&C) {
+        if self.mipmaps {
+            context.generate_mipmap(Context::TEXTURE_2D);
+        }
+    }
+}
+
+fn gl_int<T:Into<GlEnum>>(t:T) -> i32 {
+    let GlEnum(value) = t.into();
+    value as i32
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::system::gpu::context::mock::Call;
+    use crate::system::gpu::context::mock::MockContext;
+
+    #[test]
+    fn mipmap_filters_require_mipmaps() {
+        let parameters = Parameters {min_filter:MinFilter::LinearMipmapNearest, ..default()};
+        assert_eq!(parameters.effective_min_filter(),MinFilter::Linear);
+        let parameters = Parameters {mipmaps:true, ..parameters};
+        assert_eq!(parameters.effective_min_filter(),MinFilter::LinearMipmapNearest);
+        assert!(!MinFilter::Nearest.uses_mipmaps());
+        assert_eq!(MinFilter::NearestMipmapLinear.without_mipmaps(),MinFilter::Nearest);
+    }
+
+    #[test]
+    fn filter_preserves_mipmapping() {
+        let parameters = Parameters::mipmapped().with_filter(MagFilter::Nearest);
+        assert_eq!(parameters.min_filter,MinFilter::NearestMipmapNearest);
+        assert_eq!(parameters.mag_filter,MagFilter::Nearest);
+        let parameters = Parameters::default().with_filter(MagFilter::Nearest);
+        assert_eq!(parameters.min_filter,MinFilter::Nearest);
+    }
+
+    #[test]
+    fn gl_values() {
+        assert_eq!(gl_int(MinFilter::LinearMipmapLinear),Context::LINEAR_MIPMAP_LINEAR as i32);
+        assert_eq!(gl_int(Wrap::MirroredRepeat),Context::MIRRORED_REPEAT as i32);
+    }
+
+    #[test]
+    fn mipmaps_are_generated_only_if_enabled() {
+        let context = MockContext::new();
+        Parameters::default().generate_mipmaps(&context);
+        assert_eq!(context.calls(), vec![]);
+        Parameters::mipmapped().generate_mipmaps(&context);
+        assert_eq!(context.calls(), vec![Call::GenerateMipmap {target:Context::TEXTURE_2D}]);
+    }
+
+    #[test]
+    fn parameters_are_applied_to_the_bound_texture() {
+        let context    = MockContext::new();
+        let target     = Context::TEXTURE_2D;
+        let parameters = Parameters {anisotropy:4.0, ..Parameters::mipmapped()};
+        let parameter  = |name,value:u32| Call::TexParameter {target,name,value:value as i32};
+        parameters.apply(&context);
+        assert_eq!(context.take_calls(), vec!
+            [ parameter(Context::TEXTURE_MIN_FILTER , Context::LINEAR_MIPMAP_LINEAR)
+            , parameter(Context::TEXTURE_MAG_FILTER , Context::LINEAR)
+            , parameter(Context::TEXTURE_WRAP_S     , Context::CLAMP_TO_EDGE)
+            , parameter(Context::TEXTURE_WRAP_T     , Context::CLAMP_TO_EDGE)
+            ]);
+
+        context.enable_extension(ANISOTROPIC_EXTENSION);
+        parameters.apply(&context);
+        let anisotropy = Call::TexParameterf {target,name:TEXTURE_MAX_ANISOTROPY,value:4.0};
+        assert_eq!(context.take_calls().last(),Some(&anisotropy));
+
+        Parameters::default().apply(&context);
+        let calls = context.take_calls();
+        assert_eq!(calls.len(),4);
+        assert_eq!(calls[0],parameter(Context::TEXTURE_MIN_FILTER,Context::LINEAR));
+    }
+}
@@ -1,15 +1,27 @@
 #![allow(missing_docs)]
 
+pub mod array;
+pub mod block;
+pub mod structure;
 pub mod upload;
 
+pub use array::UniformArray;
+pub use structure::UniformStruct;
+
 use crate::prelude::*;
 
-use enum_dispatch::*;
+use block::Std140Type;
+use block::Std140Value;
+use block::UniformBlock;
 use shapely::shared;
+use std::ops::Range;
 use upload::UniformUpload;
-use web_sys::WebGlUniformLocation;
 
+use crate::system::gpu::context::GpuContext;
 use crate::system::gpu::shader::Context;
+use crate::system::gpu::shader::glsl::Glsl;
+use crate::system::gpu::shader::glsl::traits::PhantomIntoPrimType;
+use crate::system::gpu::shader::glsl;
 use crate::system::gpu::data::texture::*;
 use crate::system::gpu::data::prim::*;
 
@@ -25,36 +37,80 @@ pub trait UniformValue = UniformUpload;
 /// Some values need to be initialized before they can be used as uniforms. Textures, for example,
 /// need to allocate memory on GPU and if used with remote source, need to download images.
 /// For primitive types, like numbers or matrices, the binding operation does nothing.
-pub trait IntoUniformValue = IntoUniformValueImpl where
-    Uniform<AsUniformValue<Self>>: Into<AnyUniform>;
+pub trait IntoUniformValue<C=Context> = IntoUniformValueImpl<C> where
+    C                                : GpuContext,
+    Uniform<AsUniformValue<Self,C>> : Into<AnyUniform<C>>;
 
 /// Internal helper for `IntoUniformValue`.
-pub trait IntoUniformValueImpl {
+pub trait IntoUniformValueImpl<C:GpuContext=Context> {
     type Result;
-    fn into_uniform_value(self, context:&Context) -> Self::Result;
+    fn into_uniform_value(self, context:&C) -> Self::Result;
 }
 
 /// Result of the binding operation.
-pub type AsUniformValue<T> = <T as IntoUniformValueImpl>::Result;
+pub type AsUniformValue<T,C=Context> = <T as IntoUniformValueImpl<C>>::Result;
+
+/// Uniform values which can be represented in GLSL code.
+pub trait GlslValue {
+    /// The GLSL type of the value.
+    fn glsl_type(&self) -> glsl::Type;
+
+    /// The GLSL expression evaluating to the value.
+    fn to_glsl(&self) -> Glsl;
+}
 
 
 // === Instances ===
 
 macro_rules! define_identity_uniform_value_impl {
     ( [] [$([$t1:ident $t2:ident])*] ) => {$(
-        impl IntoUniformValueImpl for $t1<$t2> {
+        impl<C:GpuContext> IntoUniformValueImpl<C> for $t1<$t2> {
             type Result = $t1<$t2>;
-            fn into_uniform_value(self, _context:&Context) -> Self::Result {
+            fn into_uniform_value(self, _context:&C) -> Self::Result {
+                self
+            }
+        }
+
+        impl<C:GpuContext> IntoUniformValueImpl<C> for UniformArray<$t1<$t2>> {
+            type Result = UniformArray<$t1<$t2>>;
+            fn into_uniform_value(self, _context:&C) -> Self::Result {
                 self
             }
         }
+
+        impl GlslValue for $t1<$t2> {
+            fn glsl_type(&self) -> glsl::Type {
+                <$t1<$t2>>::glsl_prim_type().into()
+            }
+
+            fn to_glsl(&self) -> Glsl {
+                (*self).into()
+            }
+        }
+
+        impl GlslValue for UniformArray<$t1<$t2>> {
+            fn glsl_type(&self) -> glsl::Type {
+                self.glsl_type()
+            }
+
+            fn to_glsl(&self) -> Glsl {
+                self.clone().into()
+            }
+        }
     )*}
 }
 crate::with_all_prim_types!([[define_identity_uniform_value_impl][]]);
 
-impl<Provider:TextureProvider> IntoUniformValueImpl for Provider {
-    type Result = Texture<Provider>;
-    fn into_uniform_value(self, context:&Context) -> Self::Result {
+impl<C:GpuContext> IntoUniformValueImpl<C> for UniformStruct {
+    type Result = UniformStruct;
+    fn into_uniform_value(self, _context:&C) -> Self::Result {
+        self
+    }
+}
+
+impl<Provider:TextureProvider,C:GpuContext> IntoUniformValueImpl<C> for Provider {
+    type Result = Texture<Provider,C>;
+    fn into_uniform_value(self, context:&C) -> Self::Result {
         self.new_texture(context)
     }
 }
@@ -65,26 +121,116 @@ impl<Provider:TextureProvider> IntoUniformValueImpl for Provider {
 // === UniformScope ===
 // ====================
 
-shared! { UniformScope
+/// A scope containing set of uniform values. The scope can be backed by an `UniformBlock`, which
+/// keeps all primitive uniforms of the scope, so they are uploaded once for all programs.
+#[derive(Clone,Debug)]
+pub struct UniformScope<C:GpuContext=Context> {
+    rc: Rc<RefCell<UniformScopeData<C>>>,
+}
+
+impl<C:GpuContext> UniformScope<C> {
+    /// Constructor.
+    pub fn new(logger:Logger, context:&C) -> Self {
+        let data = UniformScopeData::new(logger,context);
+        let rc   = Rc::new(RefCell::new(data));
+        Self {rc}
+    }
+
+    /// Constructor of a scope backed by the provided uniform block.
+    pub fn new_with_block(logger:Logger, context:&C, block:UniformBlock<C>) -> Self {
+        let data = UniformScopeData::new_with_block(logger,context,block);
+        let rc   = Rc::new(RefCell::new(data));
+        Self {rc}
+    }
+
+    /// Cheap clone of the structure. Implemented as the `Rc::clone` under the hood.
+    pub fn clone_ref(&self) -> Self {
+        self.clone()
+    }
+
+    /// The uniform block keeping the primitive uniforms of this scope, if any.
+    pub fn block(&self) -> Option<UniformBlock<C>> {
+        self.rc.borrow().block()
+    }
+
+    /// Look up uniform by name.
+    pub fn get<Name:Str>(&self, name:Name) -> Option<AnyUniform<C>> {
+        self.rc.borrow().get(name)
+    }
+
+    /// Checks if uniform of a given name was defined in this scope.
+    pub fn contains<Name:Str>(&self, name:Name) -> bool {
+        self.rc.borrow().contains(name)
+    }
+
+    /// Removes the uniform of a given name from this scope and returns it. Please note that
+    /// uniforms kept in the uniform block of the scope are only forgotten, and the block keeps
+    /// their space.
+    pub fn remove<Name:Str>(&self, name:Name) -> Option<AnyUniform<C>> {
+        self.rc.borrow_mut().remove(name)
+    }
+
+    /// Add a new uniform with a given name and initial value. Returns `None` if the name is in use.
+    /// Please note that the value will be bound to the context before it becomes the uniform.
+    /// Refer to the docs of `IntoUniformValue` to learn more.
+    pub fn add<Name:Str, Value:IntoUniformValue<C>>
+    (&self, name:Name, value:Value) -> Option<Uniform<AsUniformValue<Value,C>>> {
+        self.rc.borrow_mut().add(name,value)
+    }
+
+    /// Add a new uniform with a given name and initial value. Panics if the name is in use.
+    /// Please note that the value will be bound to the context before it becomes the uniform.
+    /// Refer to the docs of `IntoUniformValue` to learn more.
+    pub fn add_or_panic<Name:Str, Value:IntoUniformValue<C>>
+    (&self, name:Name, value:Value) -> Uniform<AsUniformValue<Value,C>> {
+        self.rc.borrow_mut().add_or_panic(name,value)
+    }
+
+    /// Uploads the modified uniforms of the uniform block. It should be called once per frame,
+    /// before rendering. Scopes without blocks are uploaded by programs and do nothing here.
+    pub fn update(&self) {
+        self.rc.borrow().update()
+    }
+
+    /// Re-creates all textures after the context was lost and restored.
+    pub fn restore_context(&self) {
+        self.rc.borrow().restore_context()
+    }
+}
 
-/// A scope containing set of uniform values.
+/// The internal state of the `UniformScope`.
 #[derive(Debug)]
-pub struct UniformScopeData {
-    map     : HashMap<String,AnyUniform>,
+pub struct UniformScopeData<C:GpuContext=Context> {
+    map     : HashMap<String,AnyUniform<C>>,
+    block   : Option<UniformBlock<C>>,
     logger  : Logger,
-    context : Context,
+    context : C,
 }
 
-impl {
+impl<C:GpuContext> UniformScopeData<C> {
     /// Constructor.
-    pub fn new(logger:Logger, context:&Context) -> Self {
+    pub fn new(logger:Logger, context:&C) -> Self {
         let map     = default();
+        let block   = default();
         let context = context.clone();
-        Self {map,logger,context}
+        Self {map,block,logger,context}
+    }
+
+    /// Constructor of a scope backed by the provided uniform block.
+    pub fn new_with_block(logger:Logger, context:&C, block:UniformBlock<C>) -> Self {
+        let map     = default();
+        let block   = Some(block);
+        let context = context.clone();
+        Self {map,block,logger,context}
+    }
+
+    /// The uniform block keeping the primitive uniforms of this scope, if any.
+    pub fn block(&self) -> Option<UniformBlock<C>> {
+        self.block.as_ref().map(|block| block.clone_ref())
     }
 
     /// Look up uniform by name.
-    pub fn get<Name:Str>(&self, name:Name) -> Option<AnyUniform> {
+    pub fn get<Name:Str>(&self, name:Name) -> Option<AnyUniform<C>> {
         self.map.get(name.as_ref()).cloned()
     }
 
@@ -93,37 +239,65 @@ impl {
         self.map.contains_key(name.as_ref())
     }
 
+    /// Removes the uniform of a given name from this scope and returns it. Please note that
+    /// uniforms kept in the uniform block of the scope are only forgotten, and the block keeps
+    /// their space.
+    pub fn remove<Name:Str>(&mut self, name:Name) -> Option<AnyUniform<C>> {
+        self.map.remove(name.as_ref())
+    }
+
     /// Add a new uniform with a given name and initial value. Returns `None` if the name is in use.
     /// Please note that the value will be bound to the context before it becomes the uniform.
     /// Refer to the docs of `IntoUniformValue` to learn more.
-    pub fn add<Name:Str, Value:IntoUniformValue>
-    (&mut self, name:Name, value:Value) -> Option<Uniform<AsUniformValue<Value>>> {
+    pub fn add<Name:Str, Value:IntoUniformValue<C>>
+    (&mut self, name:Name, value:Value) -> Option<Uniform<AsUniformValue<Value,C>>> {
         self.add_or_else(name,value,Some,|_|None)
     }
 
     /// Add a new uniform with a given name and initial value. Panics if the name is in use.
     /// Please note that the value will be bound to the context before it becomes the uniform.
     /// Refer to the docs of `IntoUniformValue` to learn more.
-    pub fn add_or_panic<Name:Str, Value:IntoUniformValue>
-    (&mut self, name:Name, value:Value) -> Uniform<AsUniformValue<Value>> {
+    pub fn add_or_panic<Name:Str, Value:IntoUniformValue<C>>
+    (&mut self, name:Name, value:Value) -> Uniform<AsUniformValue<Value,C>> {
         self.add_or_else(name,value,|t|{t},|name| {
             panic!("Trying to override uniform '{}'.", name.as_ref())
         })
     }
-}}
 
-impl UniformScopeData {
+    /// Uploads the modified uniforms of the uniform block. It should be called once per frame,
+    /// before rendering. Scopes without blocks are uploaded by programs and do nothing here.
+    pub fn update(&self) {
+        if let Some(block) = &self.block {
+            block.update()
+        }
+    }
+
+    /// Re-creates all textures after the context was lost and restored.
+    pub fn restore_context(&self) {
+        for uniform in self.map.values() {
+            if let AnyUniform::Texture(texture) = uniform {
+                texture.restore_context()
+            }
+        }
+        if let Some(block) = &self.block {
+            block.restore_context()
+        }
+    }
+
     /// Adds a new uniform with a given name and initial value. In case the name was already in use,
     /// it fires the `fail` function. Otherwise, it fires the `ok` function on the newly created
     /// uniform.
-    pub fn add_or_else<Name:Str,Value:IntoUniformValue,Ok,Fail,T>
+    pub fn add_or_else<Name:Str,Value:IntoUniformValue<C>,Ok,Fail,T>
     (&mut self, name:Name, value:Value, ok:Ok, fail:Fail) -> T
-    where Ok   : Fn(Uniform<AsUniformValue<Value>>)->T,
+    where Ok   : Fn(Uniform<AsUniformValue<Value,C>>)->T,
           Fail : Fn(Name)->T {
         if self.map.contains_key(name.as_ref()) { fail(name) } else {
             let bound_value = value.into_uniform_value(&self.context);
             let uniform     = Uniform::new(bound_value);
             let any_uniform = uniform.clone().into();
+            if let (Some(block),AnyUniform::Prim(prim)) = (&self.block,&any_uniform) {
+                block.add(name.as_ref(),prim.clone());
+            }
             self.map.insert(name.into(),any_uniform);
             ok(uniform)
         }
@@ -136,24 +310,50 @@ impl UniformScopeData {
 // === Uniform ===
 // ===============
 
+/// Uniform values whose shape is a part of their GLSL type and their std140 layout, like arrays.
+/// Such values can be replaced only with values of the same shape.
+pub trait FixedShape {
+    /// Panics if the shape of the provided value differs from the shape of this one.
+    fn assert_same_shape(&self, value:&Self);
+}
+
+impl<T> FixedShape for T {
+    default fn assert_same_shape(&self, _value:&Self) {}
+}
+
+impl<T> FixedShape for UniformArray<T> {
+    fn assert_same_shape(&self, value:&Self) {
+        let len     = self.len();
+        let new_len = value.len();
+        if len != new_len {
+            panic!("The uniform array of {} elements was set to {} elements.",len,new_len)
+        }
+    }
+}
+
 shared! { Uniform
 
-/// An uniform value.
+/// An uniform value. Array elements modified with `set_element` are tracked separately, so only
+/// they are written to uniform blocks.
 #[derive(Debug)]
 pub struct UniformData<Value> {
-    value: Value,
-    dirty: bool,
+    value          : Value,
+    dirty          : bool,
+    dirty_elements : Option<Range<usize>>,
 }
 
 impl<Value> {
     /// Constructor.
     pub fn new(value:Value) -> Self {
-        let dirty = true;
-        Self {value,dirty}
+        let dirty          = true;
+        let dirty_elements = None;
+        Self {value,dirty,dirty_elements}
     }
 
-    /// Sets the value of this uniform.
+    /// Sets the value of this uniform. Panics if the value is an array of a different length than
+    /// the current one, as the length is a part of the declared GLSL type.
     pub fn set(&mut self, value:Value) {
+        self.value.assert_same_shape(&value);
         self.set_dirty();
         self.value = value;
     }
@@ -166,7 +366,7 @@ impl<Value> {
 
     /// Checks whether the uniform was changed and not yet updated.
     pub fn check_dirty(&self) -> bool {
-        self.dirty
+        self.dirty || self.dirty_elements.is_some()
     }
 
     /// Sets the dirty flag.
@@ -176,32 +376,158 @@ impl<Value> {
 
     /// Clears the dirty flag.
     pub fn unset_dirty(&mut self) {
-        self.dirty = false;
+        self.dirty          = false;
+        self.dirty_elements = None;
     }
 }}
 
+impl<Value> UniformData<Value> {
+    /// Marks the array element of the given index as changed.
+    fn set_element_dirty(&mut self, index:usize) {
+        self.dirty_elements = Some(match self.dirty_elements.take() {
+            None           => index .. index + 1,
+            Some(elements) => elements.start.min(index) .. elements.end.max(index + 1),
+        })
+    }
+}
+
 impl<Value:UniformValue> UniformData<Value> {
     /// Uploads the uniform data to the provided location of the currently bound shader program.
-    pub fn upload(&self, context:&Context, location:&WebGlUniformLocation) {
+    pub fn upload<C:GpuContext>(&self, context:&C, location:&C::UniformLocation) {
         self.value.upload_uniform(context,location);
     }
 }
 
 impl<Value:UniformValue> Uniform<Value> {
     /// Uploads the uniform data to the provided location of the currently bound shader program.
-    pub fn upload(&self, context:&Context, location:&WebGlUniformLocation) {
+    pub fn upload<C:GpuContext>(&self, context:&C, location:&C::UniformLocation) {
         self.rc.borrow().upload(context,location)
     }
 }
 
-impl<Value> Uniform<Value> where Context : ContextTextureOps<Value,Guard=TextureBindGuard> {
+impl<Value:Std140Value+GlslValue> Uniform<Value> {
+    /// The shape of the uniform value in uniform blocks.
+    pub fn std140_type(&self) -> Std140Type {
+        self.rc.borrow().value.std140_type()
+    }
+
+    /// The GLSL type of the uniform value.
+    pub fn glsl_type(&self) -> glsl::Type {
+        self.rc.borrow().value.glsl_type()
+    }
+
+    /// The GLSL expression evaluating to the current uniform value.
+    pub fn to_glsl(&self) -> Glsl {
+        self.rc.borrow().value.to_glsl()
+    }
+
+    /// Writes the uniform value to the uniform block data at the provided offset in bytes.
+    pub fn write_std140(&self, offset:usize, words:&mut [u32]) {
+        self.rc.borrow().value.write_std140(offset,words)
+    }
+
+    /// Writes the changed part of the uniform value to the uniform block data at the provided
+    /// offset in bytes. If only some array elements were changed, only they are written. Returns
+    /// the range of the written bytes.
+    pub fn write_std140_changes(&self, offset:usize, words:&mut [u32]) -> Range<usize> {
+        let data = self.rc.borrow();
+        let typ  = data.value.std140_type();
+        match &data.dirty_elements {
+            Some(elements) if !data.dirty => {
+                let stride = typ.element_stride();
+                data.value.write_std140_elements(offset,elements.clone(),words);
+                offset + elements.start * stride .. offset + elements.end * stride
+            }
+            _ => {
+                data.value.write_std140(offset,words);
+                offset .. offset + typ.size()
+            }
+        }
+    }
+}
+
+impl<Value:Clone> Uniform<Value> {
+    /// The current value of the uniform.
+    pub fn get(&self) -> Value {
+        self.rc.borrow().value.clone()
+    }
+}
+
+impl<T:Clone> Uniform<UniformArray<T>> {
+    /// The element of the given index, if it exists.
+    pub fn get_element(&self, index:usize) -> Option<T> {
+        self.rc.borrow().value.get(index).cloned()
+    }
+
+    /// Sets the element of the given index, leaving other elements untouched. Only the element is
+    /// written to the uniform block during the next update. Panics if the index is out of bounds.
+    pub fn set_element(&self, index:usize, value:T) {
+        self.modify_element(index,|element| *element = value)
+    }
+
+    /// Modifies the element of the given index, leaving other elements untouched. Only the element
+    /// is written to the uniform block during the next update. Panics if the index is out of
+    /// bounds.
+    pub fn modify_element<F:FnOnce(&mut T)>(&self, index:usize, f:F) {
+        let mut data = self.rc.borrow_mut();
+        data.value.modify(index,f);
+        data.set_element_dirty(index);
+    }
+}
+
+impl Uniform<UniformStruct> {
+    /// The GLSL type of the struct.
+    pub fn glsl_type(&self) -> glsl::Type {
+        self.rc.borrow().value.glsl_type()
+    }
+
+    /// The GLSL declaration of the struct type.
+    pub fn declaration(&self) -> glsl::StructDecl {
+        self.rc.borrow().value.declaration()
+    }
+
+    /// All the fields of the struct in the order of their declaration.
+    pub fn fields(&self) -> Vec<(String,AnyPrimUniform)> {
+        self.rc.borrow().value.fields().to_vec()
+    }
+}
+
+impl<Value> Uniform<Value> {
     /// Bind texture in this WebGl context.
-    pub fn bind_texture_unit(&self, context:&Context, unit:u32) -> TextureBindGuard {
+    pub fn bind_texture_unit<C>(&self, context:&C, unit:u32) -> C::Guard
+    where C:ContextTextureOps<Value> {
         let value = &self.rc.borrow().value;
         context.bind_texture_unit(value,unit)
     }
 }
 
+impl<I,T,C> Uniform<Texture<TextureData<I,T>,C>>
+where I:InternalFormat, T:TextureItemType, C:GpuContext {
+    /// Re-creates the texture after the context was lost and restored.
+    pub fn restore_context(&self) {
+        self.rc.borrow_mut().value.restore_context()
+    }
+
+    /// The GLSL sampler type the texture can be bound to.
+    pub fn glsl_sampler_type(&self) -> glsl::PrimType {
+        I::glsl_sampler_type()
+    }
+}
+
+impl<I,T,C> Uniform<Texture<TextureFromUrl<I,T>,C>>
+where I:InternalFormat, T:TextureItemType, C:GpuContext {
+    /// Re-creates the texture after the context was lost and restored, and downloads its image
+    /// again.
+    pub fn restore_context(&self) {
+        self.rc.borrow_mut().value.restore_context()
+    }
+
+    /// The GLSL sampler type the texture can be bound to.
+    pub fn glsl_sampler_type(&self) -> glsl::PrimType {
+        I::glsl_sampler_type()
+    }
+}
+
 
 
 // ======================
@@ -212,19 +538,105 @@ macro_rules! define_any_prim_uniform {
     ( [] [$([$t1:ident $t2:ident])*] ) => { paste::item! {
         /// Existentially typed uniform value.
         #[allow(non_camel_case_types)]
-        #[enum_dispatch(AnyPrimUniformOps)]
         #[derive(Clone,Debug)]
         pub enum AnyPrimUniform {
-            $([<Variant_ $t1 _ $t2>](Uniform<$t1<$t2>>)),*
+            $([<Variant_ $t1 _ $t2>](Uniform<$t1<$t2>>),)*
+            $([<Array_ $t1 _ $t2>](Uniform<UniformArray<$t1<$t2>>>),)*
         }
+
+        impl AnyPrimUniformOps for AnyPrimUniform {
+            fn upload<C:GpuContext>(&self, context:&C, location:&C::UniformLocation) {
+                match self {
+                    $(Self::[<Variant_ $t1 _ $t2>](t) => t.upload(context,location),)*
+                    $(Self::[<Array_ $t1 _ $t2>](t)   => t.upload(context,location),)*
+                }
+            }
+
+            fn check_dirty(&self) -> bool {
+                match self {
+                    $(Self::[<Variant_ $t1 _ $t2>](t) => t.check_dirty(),)*
+                    $(Self::[<Array_ $t1 _ $t2>](t)   => t.check_dirty(),)*
+                }
+            }
+
+            fn set_dirty(&self) {
+                match self {
+                    $(Self::[<Variant_ $t1 _ $t2>](t) => t.set_dirty(),)*
+                    $(Self::[<Array_ $t1 _ $t2>](t)   => t.set_dirty(),)*
+                }
+            }
+
+            fn unset_dirty(&self) {
+                match self {
+                    $(Self::[<Variant_ $t1 _ $t2>](t) => t.unset_dirty(),)*
+                    $(Self::[<Array_ $t1 _ $t2>](t)   => t.unset_dirty(),)*
+                }
+            }
+
+            fn std140_type(&self) -> Std140Type {
+                match self {
+                    $(Self::[<Variant_ $t1 _ $t2>](t) => t.std140_type(),)*
+                    $(Self::[<Array_ $t1 _ $t2>](t)   => t.std140_type(),)*
+                }
+            }
+
+            fn glsl_type(&self) -> glsl::Type {
+                match self {
+                    $(Self::[<Variant_ $t1 _ $t2>](t) => t.glsl_type(),)*
+                    $(Self::[<Array_ $t1 _ $t2>](t)   => t.glsl_type(),)*
+                }
+            }
+
+            fn to_glsl(&self) -> Glsl {
+                match self {
+                    $(Self::[<Variant_ $t1 _ $t2>](t) => t.to_glsl(),)*
+                    $(Self::[<Array_ $t1 _ $t2>](t)   => t.to_glsl(),)*
+                }
+            }
+
+            fn write_std140(&self, offset:usize, words:&mut [u32]) {
+                match self {
+                    $(Self::[<Variant_ $t1 _ $t2>](t) => t.write_std140(offset,words),)*
+                    $(Self::[<Array_ $t1 _ $t2>](t)   => t.write_std140(offset,words),)*
+                }
+            }
+
+            fn write_std140_changes(&self, offset:usize, words:&mut [u32]) -> Range<usize> {
+                match self {
+                    $(Self::[<Variant_ $t1 _ $t2>](t) => t.write_std140_changes(offset,words),)*
+                    $(Self::[<Array_ $t1 _ $t2>](t)   => t.write_std140_changes(offset,words),)*
+                }
+            }
+        }
+
+        $(
+            impl From<Uniform<$t1<$t2>>> for AnyPrimUniform {
+                fn from(t:Uniform<$t1<$t2>>) -> Self {
+                    Self::[<Variant_ $t1 _ $t2>](t)
+                }
+            }
+
+            impl From<Uniform<UniformArray<$t1<$t2>>>> for AnyPrimUniform {
+                fn from(t:Uniform<UniformArray<$t1<$t2>>>) -> Self {
+                    Self::[<Array_ $t1 _ $t2>](t)
+                }
+            }
+        )*
     }}
 }
 crate::with_all_prim_types!([[define_any_prim_uniform][]]);
 
 /// Set of operations exposed by the `AnyPrimUniform` value.
-#[enum_dispatch]
 pub trait AnyPrimUniformOps {
-    fn upload(&self, context:&Context, location:&WebGlUniformLocation);
+    fn upload<C:GpuContext>(&self, context:&C, location:&C::UniformLocation);
+    fn check_dirty          (&self) -> bool;
+    fn set_dirty            (&self);
+    fn unset_dirty          (&self);
+    fn std140_type          (&self) -> Std140Type;
+    fn glsl_type            (&self) -> glsl::Type;
+    fn to_glsl              (&self) -> Glsl;
+    fn write_std140         (&self, offset:usize, words:&mut [u32]);
+    fn write_std140_changes (&self, offset:usize, words:&mut [u32]) -> Range<usize>;
 }
 
 
@@ -237,40 +649,82 @@ macro_rules! gen_any_texture_uniform {
     ( $([$internal_format:tt $type:tt])* ) => { paste::item! {
         #[allow(missing_docs)]
         #[allow(non_camel_case_types)]
-        #[enum_dispatch(AnyTextureUniformOps)]
         #[derive(Clone,Debug)]
-        pub enum AnyTextureUniform {
-            $( [< $internal_format _ $type >] (Uniform<Texture<TextureData<$internal_format,$type>>>) ),*
+        pub enum AnyTextureUniform<C:GpuContext=Context> {
+            $( [< $internal_format _ $type >]
+                (Uniform<Texture<TextureData<$internal_format,$type>,C>>), )*
+            $( [< Url_ $internal_format _ $type >]
+                (Uniform<Texture<TextureFromUrl<$internal_format,$type>,C>>), )*
+        }
+
+        impl<C:GpuContext> AnyTextureUniformOps<C> for AnyTextureUniform<C> {
+            fn bind_texture_unit(&self, context:&C, unit:u32) -> TextureBindGuard<C> {
+                match self {
+                    $(Self::[< $internal_format _ $type >](t) =>
+                        t.bind_texture_unit(context,unit),)*
+                    $(Self::[< Url_ $internal_format _ $type >](t) =>
+                        t.bind_texture_unit(context,unit),)*
+                }
+            }
+
+            fn restore_context(&self) {
+                match self {
+                    $(Self::[< $internal_format _ $type >](t)     => t.restore_context(),)*
+                    $(Self::[< Url_ $internal_format _ $type >](t) => t.restore_context(),)*
+                }
+            }
+
+            fn glsl_sampler_type(&self) -> glsl::PrimType {
+                match self {
+                    $(Self::[< $internal_format _ $type >](t)     => t.glsl_sampler_type(),)*
+                    $(Self::[< Url_ $internal_format _ $type >](t) => t.glsl_sampler_type(),)*
+                }
+            }
         }
     }}
 }
 
 macro_rules! gen_prim_conversions {
     ( [] [$([$t1:ident $t2:ident])*] ) => {$(
-        impl From<Uniform<$t1<$t2>>> for AnyUniform {
+        impl<C:GpuContext> From<Uniform<$t1<$t2>>> for AnyUniform<C> {
             fn from(t:Uniform<$t1<$t2>>) -> Self {
                 Self::Prim(t.into())
             }
         }
+
+        impl<C:GpuContext> From<Uniform<UniformArray<$t1<$t2>>>> for AnyUniform<C> {
+            fn from(t:Uniform<UniformArray<$t1<$t2>>>) -> Self {
+                Self::Prim(t.into())
+            }
+        }
     )*}
 }
 
 macro_rules! gen_texture_conversions {
-    ( $([$internal_format:tt $type:tt])* ) => {$(
-        impl From<Uniform<Texture<TextureData<$internal_format,$type>>>> for AnyUniform {
-            fn from(t:Uniform<Texture<TextureData<$internal_format,$type>>>) -> Self {
-                Self::Texture(t.into())
+    ( $([$internal_format:tt $type:tt])* ) => { paste::item! {$(
+        impl<C:GpuContext> From<Uniform<Texture<TextureData<$internal_format,$type>,C>>>
+        for AnyUniform<C> {
+            fn from(t:Uniform<Texture<TextureData<$internal_format,$type>,C>>) -> Self {
+                Self::Texture(AnyTextureUniform::[< $internal_format _ $type >](t))
             }
         }
-    )*}
+
+        impl<C:GpuContext> From<Uniform<Texture<TextureFromUrl<$internal_format,$type>,C>>>
+        for AnyUniform<C> {
+            fn from(t:Uniform<Texture<TextureFromUrl<$internal_format,$type>,C>>) -> Self {
+                Self::Texture(AnyTextureUniform::[< Url_ $internal_format _ $type >](t))
+            }
+        }
+    )*}}
 }
 
 crate::with_all_texture_types!(gen_any_texture_uniform);
 
 
-#[enum_dispatch]
-pub trait AnyTextureUniformOps {
-    fn bind_texture_unit(&self, context:&Context, unit:u32) -> TextureBindGuard;
+pub trait AnyTextureUniformOps<C:GpuContext=Context> {
+    fn bind_texture_unit (&self, context:&C, unit:u32) -> TextureBindGuard<C>;
+    fn restore_context   (&self);
+    fn glsl_sampler_type (&self) -> glsl::PrimType;
 }
 
 
@@ -280,10 +734,75 @@ pub trait AnyTextureUniformOps {
 // ==================
 
 #[derive(Clone,Debug)]
-pub enum AnyUniform {
+pub enum AnyUniform<C:GpuContext=Context> {
     Prim(AnyPrimUniform),
-    Texture(AnyTextureUniform)
+    Texture(AnyTextureUniform<C>),
+    Struct(Uniform<UniformStruct>),
+}
+
+impl<C:GpuContext> From<Uniform<UniformStruct>> for AnyUniform<C> {
+    fn from(t:Uniform<UniformStruct>) -> Self {
+        Self::Struct(t)
+    }
 }
 
 crate::with_all_prim_types!([[gen_prim_conversions][]]);
 crate::with_all_texture_types!(gen_texture_conversions);
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_element_writes_only_the_changed_elements() {
+        let uniform   = Uniform::new(UniformArray::new(vec![1_i32,2,3]));
+        let mut words = vec![0;16];
+        assert_eq!(uniform.write_std140_changes(16,&mut words), 16..64);
+        assert_eq!((words[4],words[8],words[12]), (1,2,3));
+        uniform.unset_dirty();
+        assert!(!uniform.check_dirty());
+
+        let mut words = vec![0;16];
+        uniform.set_element(1,7);
+        assert!(uniform.check_dirty());
+        assert_eq!(uniform.write_std140_changes(16,&mut words), 32..48);
+        assert_eq!((words[4],words[8],words[12]), (0,7,0));
+        uniform.unset_dirty();
+
+        uniform.set_element(2,8);
+        uniform.modify_element(0,|element| *element += 1);
+        assert_eq!(uniform.write_std140_changes(16,&mut words), 16..64);
+        assert_eq!((words[4],words[8],words[12]), (2,7,8));
+    }
+
+    #[test]
+    fn setting_the_value_writes_the_whole_array() {
+        let uniform   = Uniform::new(UniformArray::new(vec![1_i32,2]));
+        let mut words = vec![0;8];
+        uniform.unset_dirty();
+        uniform.set_element(1,5);
+        uniform.set(UniformArray::new(vec![3,4]));
+        assert_eq!(uniform.write_std140_changes(0,&mut words), 0..32);
+        assert_eq!((words[0],words[4]), (3,4));
+    }
+
+    #[test]
+    #[should_panic(expected="The uniform array of 2 elements was set to 3 elements.")]
+    fn longer_arrays_can_not_be_set() {
+        let uniform = Uniform::new(UniformArray::new(vec![1_i32,2]));
+        uniform.set(UniformArray::new(vec![3,4,5]));
+    }
+
+    #[test]
+    #[should_panic(expected="The uniform array of 2 elements was set to 1 elements.")]
+    fn shorter_arrays_can_not_be_set() {
+        let uniform = Uniform::new(UniformArray::new(vec![1_i32,2]));
+        uniform.set(UniformArray::new(vec![3]));
+    }
+}
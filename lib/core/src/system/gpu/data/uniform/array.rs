@@ -0,0 +1,116 @@
+//! This module defines fixed-size arrays of primitive uniform values, like gradient stops or
+//! palette tables.
+
+use crate::prelude::*;
+
+use crate::system::gpu::shader::glsl::Glsl;
+use crate::system::gpu::shader::glsl::traits::PhantomIntoPrimType;
+use crate::system::gpu::shader::glsl;
+
+
+
+// ====================
+// === UniformArray ===
+// ====================
+
+/// A fixed-size array of uniform values. The length is defined on creation and it is a part of the
+/// GLSL type of the array, so it can not be changed. Elements can be modified one by one with
+/// `Uniform::set_element`, without replacing the whole array.
+#[derive(Clone,Debug,PartialEq)]
+pub struct UniformArray<T> {
+    items : Vec<T>,
+}
+
+impl<T> UniformArray<T> {
+    /// Constructor. Panics if the array is empty, as GLSL does not support empty arrays.
+    pub fn new(items:Vec<T>) -> Self {
+        assert!(!items.is_empty(),"Uniform arrays can not be empty.");
+        Self {items}
+    }
+
+    /// Creates an array of the given length filled with the provided value.
+    pub fn filled(len:usize, value:T) -> Self where T:Clone {
+        Self::new(vec![value;len])
+    }
+
+    /// The number of elements.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Checks if the array is empty. It is always false, as empty arrays can not be created.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// All the elements of the array.
+    pub fn as_slice(&self) -> &[T] {
+        &self.items
+    }
+
+    /// The element of the given index, if it exists.
+    pub fn get(&self, index:usize) -> Option<&T> {
+        self.items.get(index)
+    }
+
+    /// Sets the element of the given index. Panics if the index is out of bounds.
+    pub fn set(&mut self, index:usize, value:T) {
+        self.items[index] = value;
+    }
+
+    /// Modifies the element of the given index. Panics if the index is out of bounds.
+    pub fn modify<F:FnOnce(&mut T)>(&mut self, index:usize, f:F) {
+        f(&mut self.items[index])
+    }
+}
+
+impl<T:PhantomInto<glsl::PrimType>> UniformArray<T> {
+    /// The GLSL type of the array.
+    pub fn glsl_type(&self) -> glsl::Type {
+        glsl::Type::array(T::glsl_prim_type(),self.len())
+    }
+}
+
+impl<T> From<Vec<T>> for UniformArray<T> {
+    fn from(items:Vec<T>) -> Self {
+        Self::new(items)
+    }
+}
+
+impl<T:PhantomInto<glsl::PrimType>+Into<Glsl>+Clone> From<UniformArray<T>> for Glsl {
+    fn from(t:UniformArray<T>) -> Self {
+        let typ    = t.glsl_type();
+        let values = t.items.into_iter().map(|item| {
+            let glsl:Glsl = item.into();
+            glsl.str
+        }).collect_vec();
+        format!("{}({})",typ,values.join(",")).into()
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elements_are_modified_in_place() {
+        let mut array = UniformArray::filled(3,0);
+        array.set(1,5);
+        array.modify(2,|t| *t += 2);
+        assert_eq!(array.as_slice(),&[0,5,2]);
+        assert_eq!(array.get(3),None);
+    }
+
+    #[test]
+    fn glsl_representation() {
+        let array = UniformArray::new(vec![1.0,2.5_f32]);
+        assert_eq!(format!("{}",array.glsl_type()),"float[2]");
+        assert_eq!(Glsl::from(array).str,"float[2](1.0,2.5)");
+    }
+}
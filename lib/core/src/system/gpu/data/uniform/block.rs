@@ -0,0 +1,582 @@
+//! This module defines uniform blocks, groups of uniforms kept in a GPU buffer in the `std140`
+//! layout. A block is uploaded once and then shared by every program declaring it, so the cost of
+//! updating its values does not depend on the number of programs using them. Follow the link to
+//! learn more about the layout rules:
+//! https://www.khronos.org/opengl/wiki/Interface_Block_(GLSL)#Memory_layout
+
+use crate::prelude::*;
+
+use crate::system::gpu::data::buffer::item::BufferItem;
+use crate::system::gpu::data::buffer::item::Item;
+use crate::system::gpu::data::uniform::AnyPrimUniform;
+use crate::system::gpu::data::uniform::array::UniformArray;
+use crate::system::gpu::data::uniform::AnyPrimUniformOps;
+use crate::system::gpu::context::GpuContext;
+use crate::system::gpu::shader::Context;
+use crate::system::gpu::shader::glsl;
+
+use std::ops::Range;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// The size in bytes of the basic machine units of the layout, like `float`, `int` or `bool`.
+pub const WORD_SIZE : usize = 4;
+
+/// The alignment in bytes of `vec4`. It is also the alignment of matrix columns and block sizes.
+pub const VEC4_ALIGNMENT : usize = 16;
+
+
+
+// ==================
+// === Std140Type ===
+// ==================
+
+/// The shape of a value kept in a uniform block, described as a matrix of basic machine units.
+/// Scalars are 1x1 matrices and vectors are matrices of a single column. Arrays of such values
+/// have the `len` defined.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub struct Std140Type {
+    /// The number of rows.
+    pub rows : usize,
+    /// The number of columns.
+    pub cols : usize,
+    /// The number of array elements, if the value is an array.
+    pub len  : Option<usize>,
+}
+
+impl Std140Type {
+    /// Constructor.
+    pub fn new(rows:usize, cols:usize) -> Self {
+        let len = None;
+        Self {rows,cols,len}
+    }
+
+    /// The shape of the provided type.
+    pub fn of<T:BufferItem>() -> Self {
+        Self::new(T::rows(),T::cols())
+    }
+
+    /// The shape of an array of `len` values of this shape.
+    pub fn array(self, len:usize) -> Self {
+        let len = Some(len);
+        Self {len,..self}
+    }
+
+    /// Checks whether the type is a matrix of more than one column.
+    pub fn is_matrix(&self) -> bool {
+        self.cols > 1
+    }
+
+    /// The base alignment in bytes. Two-component vectors are aligned to two words, while the
+    /// three- and four-component ones are aligned to four words. Matrices are stored as arrays of
+    /// column vectors, and array elements are always aligned to `vec4`.
+    pub fn alignment(&self) -> usize {
+        if self.is_matrix() || self.len.is_some() { VEC4_ALIGNMENT } else {
+            match self.rows {
+                1 => WORD_SIZE,
+                2 => 2 * WORD_SIZE,
+                _ => VEC4_ALIGNMENT,
+            }
+        }
+    }
+
+    /// The distance in bytes between the beginnings of consecutive columns.
+    pub fn column_stride(&self) -> usize {
+        if self.is_matrix() { VEC4_ALIGNMENT } else { self.rows * WORD_SIZE }
+    }
+
+    /// The size in bytes of a single element. Please note that `vec3` occupies three words, so the
+    /// following scalar can be placed in its padding.
+    pub fn element_size(&self) -> usize {
+        if self.is_matrix() { self.cols * VEC4_ALIGNMENT } else { self.rows * WORD_SIZE }
+    }
+
+    /// The distance in bytes between the beginnings of consecutive array elements. Elements are
+    /// padded to the alignment of `vec4`, so an array of floats takes four times more space than
+    /// the floats themselves.
+    pub fn element_stride(&self) -> usize {
+        align_to(self.element_size(),VEC4_ALIGNMENT)
+    }
+
+    /// The size in bytes.
+    pub fn size(&self) -> usize {
+        match self.len {
+            None      => self.element_size(),
+            Some(len) => len * self.element_stride(),
+        }
+    }
+}
+
+/// Rounds the offset up to the nearest multiple of the alignment.
+pub fn align_to(offset:usize, alignment:usize) -> usize {
+    (offset + alignment - 1) / alignment * alignment
+}
+
+
+
+// ==============
+// === Layout ===
+// ==============
+
+/// A field of a uniform block layout.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct Field {
+    /// The name of the field.
+    pub name   : String,
+    /// The shape of the field value.
+    pub typ    : Std140Type,
+    /// The offset in bytes from the beginning of the block.
+    pub offset : usize,
+}
+
+/// The `std140` layout of a uniform block. Fields are placed in the order they were added, so
+/// adding a new field never moves the existing ones.
+#[derive(Clone,Debug,Default)]
+pub struct Layout {
+    fields : Vec<Field>,
+    end    : usize,
+}
+
+impl Layout {
+    /// Constructor.
+    pub fn new() -> Self {
+        default()
+    }
+
+    /// Adds a new field at the end of the layout. Returns its offset in bytes.
+    pub fn push<Name:Str>(&mut self, name:Name, typ:Std140Type) -> usize {
+        let name   = name.into();
+        let offset = align_to(self.end,typ.alignment());
+        self.end   = offset + typ.size();
+        self.fields.push(Field {name,typ,offset});
+        offset
+    }
+
+    /// All the fields in the order of their offsets.
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Looks up the field by name.
+    pub fn field(&self, name:&str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// The size in bytes of the block. It is rounded up to the alignment of `vec4`.
+    pub fn size(&self) -> usize {
+        align_to(self.end,VEC4_ALIGNMENT)
+    }
+}
+
+
+
+// ===================
+// === Std140Value ===
+// ===================
+
+/// Basic machine units of the layout. Booleans are stored as integers.
+pub trait Std140Word {
+    /// The bits of the value.
+    fn std140_word(&self) -> u32;
+}
+
+impl Std140Word for f32 {
+    fn std140_word(&self) -> u32 {
+        self.to_bits()
+    }
+}
+
+impl Std140Word for i32 {
+    fn std140_word(&self) -> u32 {
+        *self as u32
+    }
+}
+
+impl Std140Word for bool {
+    fn std140_word(&self) -> u32 {
+        if *self {1} else {0}
+    }
+}
+
+/// Values which can be kept in uniform blocks.
+pub trait Std140Value {
+    /// The shape of the value.
+    fn std140_type(&self) -> Std140Type;
+
+    /// Writes the value to the block data, starting at the provided offset in bytes.
+    fn write_std140(&self, offset:usize, words:&mut [u32]);
+
+    /// Writes the elements of the provided range to the block data, where the offset in bytes is
+    /// the offset of the whole value. Values other than arrays are written whole.
+    fn write_std140_elements(&self, offset:usize, _elements:Range<usize>, words:&mut [u32]) {
+        self.write_std140(offset,words)
+    }
+}
+
+impl<T:BufferItem> Std140Value for T where Item<T>:Std140Word {
+    fn std140_type(&self) -> Std140Type {
+        Std140Type::of::<T>()
+    }
+
+    fn write_std140(&self, offset:usize, words:&mut [u32]) {
+        let typ   = self.std140_type();
+        let items = T::slice_to_items(std::slice::from_ref(self));
+        for (col,column) in items.chunks(typ.rows).enumerate() {
+            let start = (offset + col * typ.column_stride()) / WORD_SIZE;
+            for (row,item) in column.iter().enumerate() {
+                words[start + row] = item.std140_word();
+            }
+        }
+    }
+}
+
+impl<T:Std140Value> Std140Value for UniformArray<T> {
+    fn std140_type(&self) -> Std140Type {
+        self.as_slice()[0].std140_type().array(self.len())
+    }
+
+    fn write_std140(&self, offset:usize, words:&mut [u32]) {
+        self.write_std140_elements(offset,0..self.len(),words)
+    }
+
+    fn write_std140_elements(&self, offset:usize, elements:Range<usize>, words:&mut [u32]) {
+        let stride = self.std140_type().element_stride();
+        for index in elements {
+            self.as_slice()[index].write_std140(offset + index * stride, words);
+        }
+    }
+}
+
+
+
+// ====================
+// === UniformBlock ===
+// ====================
+
+/// A uniform block backed by a GPU buffer. Uniforms added to the block are written to the buffer
+/// during `update` if they were modified, and the modified bytes are uploaded at most once per
+/// update. Array uniforms modified with `Uniform::set_element` write only the changed elements.
+/// The buffer is bound to the `binding` point, and programs declaring the block are connected to
+/// it with `bind_program`.
+#[derive(Clone,Debug)]
+pub struct UniformBlock<C:GpuContext=Context> {
+    rc: Rc<RefCell<UniformBlockData<C>>>,
+}
+
+impl<C:GpuContext> UniformBlock<C> {
+    /// Constructor.
+    pub fn new<Name:Str>(logger:Logger, context:&C, name:Name, binding:u32) -> Self {
+        let data = UniformBlockData::new(logger,context,name,binding);
+        let rc   = Rc::new(RefCell::new(data));
+        Self {rc}
+    }
+
+    /// Cheap clone of the structure. Implemented as the `Rc::clone` under the hood.
+    pub fn clone_ref(&self) -> Self {
+        self.clone()
+    }
+
+    /// The name of the block used in shaders.
+    pub fn name(&self) -> String {
+        self.rc.borrow().name()
+    }
+
+    /// The index of the binding point of the block.
+    pub fn binding(&self) -> u32 {
+        self.rc.borrow().binding()
+    }
+
+    /// The layout of the block.
+    pub fn layout(&self) -> Layout {
+        self.rc.borrow().layout()
+    }
+
+    /// Checks if the uniform of a given name is kept in this block.
+    pub fn contains<Name:Str>(&self, name:Name) -> bool {
+        self.rc.borrow().contains(name)
+    }
+
+    /// Adds the uniform at the end of the block.
+    pub fn add<Name:Str>(&self, name:Name, uniform:AnyPrimUniform) {
+        self.rc.borrow_mut().add(name,uniform)
+    }
+
+    /// The names and types of the block members in the order of their offsets.
+    pub fn members(&self) -> Vec<(String,glsl::Type)> {
+        self.rc.borrow().members()
+    }
+
+    /// Connects the block declared in the program to the binding point of this block. Programs
+    /// which do not declare the block are left untouched.
+    pub fn bind_program(&self, program:&C::Program) {
+        self.rc.borrow().bind_program(program)
+    }
+
+    /// Writes the modified uniforms to the block data, uploads the modified bytes if anything was
+    /// changed and binds the buffer to the binding point. It should be called once per frame,
+    /// before rendering.
+    pub fn update(&self) {
+        self.rc.borrow_mut().update()
+    }
+
+    /// Re-creates the buffer after the context was lost and restored.
+    pub fn restore_context(&self) {
+        self.rc.borrow_mut().restore_context()
+    }
+}
+
+/// The internal state of the `UniformBlock`.
+#[derive(Debug)]
+pub struct UniformBlockData<C:GpuContext=Context> {
+    name     : String,
+    binding  : u32,
+    layout   : Layout,
+    members  : Vec<Member>,
+    words    : Vec<u32>,
+    buffer   : C::Buffer,
+    capacity : usize,
+    dirty    : Option<Range<usize>>,
+    logger   : Logger,
+    context  : C,
+}
+
+impl<C:GpuContext> UniformBlockData<C> {
+    /// Constructor.
+    pub fn new<Name:Str>(logger:Logger, context:&C, name:Name, binding:u32) -> Self {
+        let name     = name.into();
+        let layout   = default();
+        let members  = default();
+        let words    = default();
+        let buffer   = create_gl_buffer(context);
+        let capacity = 0;
+        let dirty    = None;
+        let context  = context.clone();
+        Self {name,binding,layout,members,words,buffer,capacity,dirty,logger,context}
+    }
+
+    /// The name of the block used in shaders.
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// The index of the binding point of the block.
+    pub fn binding(&self) -> u32 {
+        self.binding
+    }
+
+    /// The layout of the block.
+    pub fn layout(&self) -> Layout {
+        self.layout.clone()
+    }
+
+    /// Checks if the uniform of a given name is kept in this block.
+    pub fn contains<Name:Str>(&self, name:Name) -> bool {
+        self.layout.field(name.as_ref()).is_some()
+    }
+
+    /// Adds the uniform at the end of the block.
+    pub fn add<Name:Str>(&mut self, name:Name, uniform:AnyPrimUniform) {
+        let name   = name.as_ref();
+        let offset = self.layout.push(name,uniform.std140_type());
+        let typ    = uniform.glsl_type();
+        self.logger.info(|| format!("Adding '{}' at offset {}.",name,offset));
+        self.members.push(Member {offset,typ,uniform});
+        self.words.resize(self.layout.size() / WORD_SIZE, 0);
+        self.dirty = Some(0..self.layout.size());
+    }
+
+    /// The names and types of the block members in the order of their offsets.
+    pub fn members(&self) -> Vec<(String,glsl::Type)> {
+        let names = self.layout.fields().iter().map(|field| field.name.clone());
+        names.zip(self.members.iter().map(|member| member.typ.clone())).collect()
+    }
+
+    /// Connects the block declared in the program to the binding point of this block. Programs
+    /// which do not declare the block are left untouched.
+    pub fn bind_program(&self, program:&C::Program) {
+        let index = self.context.get_uniform_block_index(program,&self.name);
+        if index != Context::INVALID_INDEX {
+            self.context.uniform_block_binding(program,index,self.binding);
+        }
+    }
+
+    /// Writes the modified uniforms to the block data, uploads the modified bytes if anything was
+    /// changed and binds the buffer to the binding point. It should be called once per frame,
+    /// before rendering.
+    pub fn update(&mut self) {
+        let words = &mut self.words;
+        for member in &self.members {
+            if member.uniform.check_dirty() {
+                let changed = member.uniform.write_std140_changes(member.offset,words);
+                member.uniform.unset_dirty();
+                self.dirty = Some(merge_ranges(self.dirty.take(),changed));
+            }
+        }
+        if let Some(range) = self.dirty.take() {
+            self.upload(range);
+        }
+        let target = Context::UNIFORM_BUFFER;
+        self.context.bind_buffer_base(target,self.binding,Some(&self.buffer));
+    }
+
+    /// Re-creates the buffer after the context was lost and restored.
+    pub fn restore_context(&mut self) {
+        self.buffer   = create_gl_buffer(&self.context);
+        self.capacity = 0;
+        for member in &self.members {
+            member.uniform.set_dirty();
+        }
+    }
+}
+
+
+// === Private API ===
+
+impl<C:GpuContext> UniformBlockData<C> {
+    /// Uploads the provided range of bytes. The whole block is uploaded if the buffer is too small.
+    fn upload(&mut self, range:Range<usize>) {
+        if self.words.is_empty() {
+            return
+        }
+        let target = Context::UNIFORM_BUFFER;
+        let size   = self.words.len() * WORD_SIZE;
+        let words  = as_signed_words(&self.words);
+        self.context.bind_buffer(target,Some(&self.buffer));
+        if size > self.capacity {
+            self.context.buffer_data(target,words,Context::DYNAMIC_DRAW);
+            self.capacity = size;
+        } else {
+            let offset = range.start as i32;
+            let start  = (range.start / WORD_SIZE) as u32;
+            let length = (range.len() / WORD_SIZE) as u32;
+            self.context.buffer_sub_data(target,offset,words,start,length);
+        }
+        self.context.bind_buffer(target,None);
+    }
+}
+
+/// Views the words as signed integers, which can be uploaded to buffers. Only the bits matter, as
+/// the block data is interpreted according to the types declared in shaders.
+fn as_signed_words(words:&[u32]) -> &[i32] {
+    // Safety: `u32` and `i32` have the same size and alignment, and every bit pattern is valid.
+    unsafe { std::slice::from_raw_parts(words.as_ptr() as *const i32, words.len()) }
+}
+
+/// The smallest range containing both the ranges.
+fn merge_ranges(range:Option<Range<usize>>, other:Range<usize>) -> Range<usize> {
+    match range {
+        None        => other,
+        Some(range) => range.start.min(other.start) .. range.end.max(other.end),
+    }
+}
+
+/// A uniform kept in the block.
+#[derive(Clone,Debug)]
+struct Member {
+    offset  : usize,
+    typ     : glsl::Type,
+    uniform : AnyPrimUniform,
+}
+
+fn create_gl_buffer<C:GpuContext>(context:&C) -> C::Buffer {
+    let buffer = context.create_buffer();
+    buffer.ok_or("Failed to create WebGL buffer.").unwrap()
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nalgebra::Matrix2;
+    use nalgebra::Matrix4;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn type_alignments() {
+        assert_eq!(Std140Type::of::<f32>().alignment(),4);
+        assert_eq!(Std140Type::new(2,1).alignment(),8);
+        assert_eq!(Std140Type::of::<Vector3<f32>>().alignment(),16);
+        assert_eq!(Std140Type::of::<Vector3<f32>>().size(),12);
+        assert_eq!(Std140Type::of::<Matrix2<f32>>().size(),32);
+        assert_eq!(Std140Type::of::<Matrix4<f32>>().size(),64);
+        assert_eq!(Std140Type::new(2,3).column_stride(),16);
+        assert_eq!(Std140Type::new(2,3).size(),48);
+    }
+
+    #[test]
+    fn fields_are_aligned() {
+        let mut layout = Layout::new();
+        assert_eq!(layout.push("time"           , Std140Type::new(1,1)), 0);
+        assert_eq!(layout.push("position"       , Std140Type::new(3,1)), 16);
+        assert_eq!(layout.push("zoom"           , Std140Type::new(1,1)), 28);
+        assert_eq!(layout.push("view_projection", Std140Type::new(4,4)), 32);
+        assert_eq!(layout.push("pixel_ratio"    , Std140Type::new(1,1)), 96);
+        assert_eq!(layout.push("size"           , Std140Type::new(2,1)), 104);
+        assert_eq!(layout.push("display_mode"   , Std140Type::new(1,1)), 112);
+        assert_eq!(layout.size(),128);
+        assert_eq!(layout.field("zoom").map(|field| field.offset), Some(28));
+        assert_eq!(layout.field("unknown"), None);
+    }
+
+    #[test]
+    fn array_elements_are_padded() {
+        let floats = Std140Type::new(1,1).array(3);
+        assert_eq!(floats.alignment(),16);
+        assert_eq!(floats.element_stride(),16);
+        assert_eq!(floats.size(),48);
+        let matrices = Std140Type::new(3,3).array(2);
+        assert_eq!(matrices.element_stride(),48);
+        assert_eq!(matrices.size(),96);
+        let mut layout = Layout::new();
+        assert_eq!(layout.push("count" , Std140Type::new(1,1)), 0);
+        assert_eq!(layout.push("stops" , floats), 16);
+        assert_eq!(layout.push("offset", Std140Type::new(1,1)), 64);
+        assert_eq!(layout.size(),80);
+    }
+
+    #[test]
+    fn array_values_are_written_at_strides() {
+        let mut words = vec![0;12];
+        let array     = UniformArray::new(vec![1_i32,2,3]);
+        array.write_std140(0,&mut words);
+        assert_eq!(words,vec![1,0,0,0,2,0,0,0,3,0,0,0]);
+        let array = UniformArray::new(vec![Vector3::new(1.0,2.0,3.0_f32);2]);
+        assert_eq!(array.std140_type().size(),32);
+    }
+
+    #[test]
+    fn empty_layout() {
+        assert_eq!(Layout::new().size(),0);
+    }
+
+    #[test]
+    fn values_are_written_at_offsets() {
+        let mut words = vec![0;16];
+        Vector3::new(1.0,2.0,3.0_f32).write_std140(4,&mut words);
+        7_i32.write_std140(0,&mut words);
+        true.write_std140(60,&mut words);
+        assert_eq!(words[0],7);
+        assert_eq!(&words[1..4],&[1.0_f32.to_bits(),2.0_f32.to_bits(),3.0_f32.to_bits()]);
+        assert_eq!(words[15],1);
+    }
+
+    #[test]
+    fn matrix_columns_are_padded() {
+        let mut words = vec![0;8];
+        Matrix2::new(1.0,2.0,3.0,4.0_f32).write_std140(0,&mut words);
+        let bits = |v:f32| v.to_bits();
+        assert_eq!(&words[0..2],&[bits(1.0),bits(3.0)]);
+        assert_eq!(&words[4..6],&[bits(2.0),bits(4.0)]);
+        assert_eq!(&words[2..4],&[0,0]);
+    }
+}
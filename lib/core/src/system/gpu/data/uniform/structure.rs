@@ -0,0 +1,149 @@
+//! This module defines uniforms of user-defined GLSL struct types, like light descriptions.
+
+use crate::prelude::*;
+
+use crate::system::gpu::data::uniform::AnyPrimUniform;
+use crate::system::gpu::data::uniform::AnyPrimUniformOps;
+use crate::system::gpu::data::uniform::Uniform;
+use crate::system::gpu::shader::glsl::Glsl;
+use crate::system::gpu::shader::glsl;
+
+
+
+// =====================
+// === UniformStruct ===
+// =====================
+
+/// A uniform of a user-defined GLSL struct type. Every field is a separate uniform of a primitive
+/// or an array type, so fields can be updated independently using the handles returned by
+/// `add_field`. Cloning the struct does not copy the fields, the clones share them.
+///
+/// ```compile_fail
+/// let mut light = UniformStruct::new("Light");
+/// let color     = light.add_field("color",Vector3::new(1.0,1.0,1.0));
+/// let intensity = light.add_field("intensity",1.0);
+/// scope.add_or_panic("light",light);
+/// intensity.set(0.5);
+/// ```
+#[derive(Clone,Debug)]
+pub struct UniformStruct {
+    name   : String,
+    fields : Vec<(String,AnyPrimUniform)>,
+}
+
+impl UniformStruct {
+    /// Constructor. The name is the name of the struct type in GLSL.
+    pub fn new<Name:Str>(name:Name) -> Self {
+        let name   = name.into();
+        let fields = default();
+        Self {name,fields}
+    }
+
+    /// The name of the struct type in GLSL.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Adds a new field with a given name and initial value. Panics if the name is in use.
+    pub fn add_field<Name:Str,Value>(&mut self, name:Name, value:Value) -> Uniform<Value>
+    where Uniform<Value> : Into<AnyPrimUniform> {
+        let name = name.into();
+        if self.field(&name).is_some() {
+            panic!("Trying to override field '{}' of struct '{}'.",name,self.name)
+        }
+        let uniform = Uniform::new(value);
+        self.fields.push((name,uniform.clone().into()));
+        uniform
+    }
+
+    /// Looks up the field by name.
+    pub fn field(&self, name:&str) -> Option<&AnyPrimUniform> {
+        self.fields.iter().find(|(field,_)| field == name).map(|(_,uniform)| uniform)
+    }
+
+    /// All the fields in the order of their declaration.
+    pub fn fields(&self) -> &[(String,AnyPrimUniform)] {
+        &self.fields
+    }
+
+    /// The GLSL type of the struct.
+    pub fn glsl_type(&self) -> glsl::Type {
+        glsl::PrimType::Struct(self.name.as_str().into()).into()
+    }
+
+    /// The GLSL declaration of the struct type.
+    pub fn declaration(&self) -> glsl::StructDecl {
+        let ident  = self.name.as_str().into();
+        let fields = self.fields.iter().map(|(name,uniform)| {
+            glsl::LocalVar {
+                constant : false,
+                typ      : uniform.glsl_type(),
+                ident    : name.into(),
+            }
+        }).collect();
+        glsl::StructDecl {ident,fields}
+    }
+}
+
+impl From<&UniformStruct> for Glsl {
+    fn from(t:&UniformStruct) -> Self {
+        let values = t.fields.iter().map(|(_,uniform)| uniform.to_glsl().str).collect_vec();
+        format!("{}({})",t.name,values.join(",")).into()
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nalgebra::Vector3;
+
+    fn light() -> (UniformStruct,Uniform<Vector3<f32>>,Uniform<f32>) {
+        let mut light = UniformStruct::new("Light");
+        let color     = light.add_field("color",Vector3::new(1.0,0.5,0.0));
+        let intensity = light.add_field("intensity",1.0);
+        (light,color,intensity)
+    }
+
+    #[test]
+    fn fields_are_shared_with_their_handles() {
+        let (light,_,intensity) = light();
+        let clone               = light.clone();
+        intensity.set(0.5);
+        let value = |light:&UniformStruct| light.field("intensity").map(|f| f.to_glsl().str);
+        assert_eq!(value(&light), Some("0.5".into()));
+        assert_eq!(value(&clone), Some("0.5".into()));
+        assert!(light.field("radius").is_none());
+    }
+
+    #[test]
+    fn fields_keep_the_declaration_order() {
+        let (light,_,_) = light();
+        let names       = light.fields().iter().map(|(name,_)| name.clone()).collect_vec();
+        let declaration = light.declaration();
+        let types       = declaration.fields.iter().map(|f| f.typ.to_string()).collect_vec();
+        assert_eq!(names, vec!["color","intensity"]);
+        assert_eq!(types, vec!["vec3","float"]);
+        assert_eq!(light.glsl_type().to_string(), "Light");
+    }
+
+    #[test]
+    fn glsl_representation() {
+        let (light,color,_) = light();
+        color.set(Vector3::new(0.0,0.0,1.0));
+        assert_eq!(Glsl::from(&light).str, "Light(vec3(0.0,0.0,1.0),1.0)");
+    }
+
+    #[test]
+    #[should_panic]
+    fn fields_can_not_be_overridden() {
+        let (mut light,_,_) = light();
+        light.add_field("intensity",2.0_f32);
+    }
+}
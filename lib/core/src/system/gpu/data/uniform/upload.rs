@@ -2,10 +2,10 @@
 //! uploading particular data shapes. Fortunately, Rust is strongly typed, so we can establish a
 //! single abstraction for data uploading.
 
-use web_sys::WebGlUniformLocation;
-
-use crate::system::gpu::Context;
+use crate::system::gpu::context::GpuContext;
+use crate::system::gpu::data::buffer::item::BufferItem;
 use crate::system::gpu::data::prim::*;
+use crate::system::gpu::data::uniform::array::UniformArray;
 
 
 
@@ -15,141 +15,214 @@ use crate::system::gpu::data::prim::*;
 
 /// Abstraction for uploading uniforms to GPU based on their types.
 pub trait UniformUpload {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation);
+    fn upload_uniform<C:GpuContext>(&self, context:&C, location:&C::UniformLocation);
 }
 
 impl UniformUpload for bool {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
+    fn upload_uniform<C:GpuContext>(&self, context:&C, location:&C::UniformLocation) {
         let value = if *self {1} else {0};
-        context.uniform1i(Some(location),value);
+        context.uniform_iv(Some(location),1,&[value]);
     }
 }
 
 impl UniformUpload for i32 {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
-        context.uniform1i(Some(location),*self);
+    fn upload_uniform<C:GpuContext>(&self, context:&C, location:&C::UniformLocation) {
+        context.uniform_iv(Some(location),1,&[*self]);
     }
 }
 
 impl UniformUpload for f32 {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
-        context.uniform1f(Some(location),*self);
+    fn upload_uniform<C:GpuContext>(&self, context:&C, location:&C::UniformLocation) {
+        context.uniform_fv(Some(location),1,&[*self]);
     }
 }
 
-impl UniformUpload for Vector2<f32> {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
-        context.uniform2fv_with_f32_array(Some(location),self.data.as_slice());
-    }
+macro_rules! define_vector_upload {
+    ($($t:ty => $f:ident);* $(;)?) => {$(
+        impl UniformUpload for $t {
+            fn upload_uniform<C:GpuContext>(&self, context:&C, location:&C::UniformLocation) {
+                let components = <$t as BufferItem>::rows();
+                context.$f(Some(location),components,self.data.as_slice());
+            }
+        }
+    )*}
 }
 
-impl UniformUpload for Vector3<f32> {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
-        context.uniform3fv_with_f32_array(Some(location),self.data.as_slice());
-    }
+macro_rules! define_bool_vector_upload {
+    ($($t:ty),* $(,)?) => {$(
+        impl UniformUpload for $t {
+            fn upload_uniform<C:GpuContext>(&self, context:&C, location:&C::UniformLocation) {
+                let components = <$t as BufferItem>::rows();
+                let v:Vec<i32> = self.data.as_slice().iter().map(|t| if *t {1} else {0}).collect();
+                context.uniform_iv(Some(location),components,&v);
+            }
+        }
+    )*}
 }
-
-impl UniformUpload for Vector4<f32> {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
-        context.uniform4fv_with_f32_array(Some(location),self.data.as_slice());
-    }
+
+/// The dimensions of the GLSL matrix type the matrix is uploaded to. The `MatrixRxC` types are
+/// uploaded to the `matRxC` GLSL types, so they are passed to the `uniformMatrixRxCfv` calls.
+fn glsl_matrix_dims<T:BufferItem>() -> (usize,usize) {
+    (T::rows(),T::cols())
 }
 
-impl UniformUpload for Vector2<i32> {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
-        context.uniform2iv_with_i32_array(Some(location),self.data.as_slice());
-    }
+macro_rules! define_matrix_upload {
+    ($($t:ty),* $(,)?) => {$(
+        impl UniformUpload for $t {
+            fn upload_uniform<C:GpuContext>(&self, context:&C, location:&C::UniformLocation) {
+                let (cols,rows) = glsl_matrix_dims::<$t>();
+                context.uniform_matrix_fv(Some(location),cols,rows,self.data.as_slice());
+            }
+        }
+    )*}
 }
 
-impl UniformUpload for Vector3<i32> {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
-        context.uniform3iv_with_i32_array(Some(location),self.data.as_slice());
-    }
+define_vector_upload! {
+    Vector2<f32> => uniform_fv;
+    Vector3<f32> => uniform_fv;
+    Vector4<f32> => uniform_fv;
+    Vector2<i32> => uniform_iv;
+    Vector3<i32> => uniform_iv;
+    Vector4<i32> => uniform_iv;
 }
 
-impl UniformUpload for Vector4<i32> {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
-        context.uniform4iv_with_i32_array(Some(location),self.data.as_slice());
-    }
+define_bool_vector_upload! {
+    Vector2<bool>, Vector3<bool>, Vector4<bool>
 }
 
-impl UniformUpload for Vector2<bool> {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
-        let v:Vec<i32> = self.data.as_slice().iter().cloned().map(|t| if t {1} else {0}).collect();
-        context.uniform2iv_with_i32_array(Some(location),&v);
-    }
+define_matrix_upload! {
+    Matrix2<f32>, Matrix3<f32>, Matrix4<f32>,
+    Matrix2x3<f32>, Matrix2x4<f32>, Matrix3x2<f32>, Matrix3x4<f32>, Matrix4x2<f32>, Matrix4x3<f32>
 }
 
-impl UniformUpload for Vector3<bool> {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
-        let v:Vec<i32> = self.data.as_slice().iter().cloned().map(|t| if t {1} else {0}).collect();
-        context.uniform3iv_with_i32_array(Some(location),&v);
-    }
-}
 
-impl UniformUpload for Vector4<bool> {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
-        let v:Vec<i32> = self.data.as_slice().iter().cloned().map(|t| if t {1} else {0}).collect();
-        context.uniform4iv_with_i32_array(Some(location),&v);
-    }
-}
 
-impl UniformUpload for Matrix2<f32> {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
-        context.uniform_matrix2fv_with_f32_array(Some(location),false,self.data.as_slice());
-    }
-}
+// ==========================
+// === UniformArrayUpload ===
+// ==========================
 
-impl UniformUpload for Matrix3<f32> {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
-        context.uniform_matrix3fv_with_f32_array(Some(location),false,self.data.as_slice());
-    }
+/// Abstraction for uploading arrays of uniforms. The location should point to the first element of
+/// the array.
+pub trait UniformArrayUpload: Sized {
+    fn upload_uniform_array<C:GpuContext>
+    (items:&[Self], context:&C, location:&C::UniformLocation);
 }
 
-impl UniformUpload for Matrix4<f32> {
-    fn upload_uniform(&self, context:&Context, location:&WebGlUniformLocation) {
-        context.uniform_matrix4fv_with_f32_array(Some(location),false,self.data.as_slice());
+impl<T:UniformArrayUpload> UniformUpload for UniformArray<T> {
+    fn upload_uniform<C:GpuContext>(&self, context:&C, location:&C::UniformLocation) {
+        T::upload_uniform_array(self.as_slice(),context,location)
     }
 }
 
-impl UniformUpload for Matrix2x3<f32> {
-    fn upload_uniform(&self, _context:&Context, _location:&WebGlUniformLocation) {
-        todo!() // FIXME: https://github.com/rustwasm/wasm-bindgen/issues/1956
-        // context.uniform_matrix2x3fv_with_f32_array(Some(location),false,self.data.as_slice());
-    }
+macro_rules! define_array_upload {
+    ($($t:ty => $item:ty, $f:ident);* $(;)?) => {$(
+        impl UniformArrayUpload for $t {
+            fn upload_uniform_array<C:GpuContext>
+            (items:&[Self], context:&C, location:&C::UniformLocation) {
+                let components    = <$t as BufferItem>::rows();
+                let data:&[$item] = <$t as BufferItem>::slice_to_items(items);
+                context.$f(Some(location),components,data);
+            }
+        }
+    )*}
 }
 
-impl UniformUpload for Matrix2x4<f32> {
-    fn upload_uniform(&self, _context:&Context, _location:&WebGlUniformLocation) {
-        todo!() // FIXME: https://github.com/rustwasm/wasm-bindgen/issues/1956
-        // context.uniform_matrix2x4fv_with_f32_array(Some(location),false,self.data.as_slice());
-    }
+macro_rules! define_bool_array_upload {
+    ($($t:ty),* $(,)?) => {$(
+        impl UniformArrayUpload for $t {
+            fn upload_uniform_array<C:GpuContext>
+            (items:&[Self], context:&C, location:&C::UniformLocation) {
+                let components = <$t as BufferItem>::rows();
+                let data       = <$t as BufferItem>::slice_to_items(items);
+                let v:Vec<i32> = data.iter().cloned().map(|t| if t {1} else {0}).collect();
+                context.uniform_iv(Some(location),components,&v);
+            }
+        }
+    )*}
+}
+
+macro_rules! define_matrix_array_upload {
+    ($($t:ty),* $(,)?) => {$(
+        impl UniformArrayUpload for $t {
+            fn upload_uniform_array<C:GpuContext>
+            (items:&[Self], context:&C, location:&C::UniformLocation) {
+                let (cols,rows) = glsl_matrix_dims::<$t>();
+                let data        = <$t as BufferItem>::slice_to_items(items);
+                context.uniform_matrix_fv(Some(location),cols,rows,data);
+            }
+        }
+    )*}
 }
 
-impl UniformUpload for Matrix3x2<f32> {
-    fn upload_uniform(&self, _context:&Context, _location:&WebGlUniformLocation) {
-        todo!() // FIXME: https://github.com/rustwasm/wasm-bindgen/issues/1956
-        // context.uniform_matrix3x2fv_with_f32_array(Some(location),false,self.data.as_slice());
-    }
+define_array_upload! {
+    f32          => f32, uniform_fv;
+    Vector2<f32> => f32, uniform_fv;
+    Vector3<f32> => f32, uniform_fv;
+    Vector4<f32> => f32, uniform_fv;
+    i32          => i32, uniform_iv;
+    Vector2<i32> => i32, uniform_iv;
+    Vector3<i32> => i32, uniform_iv;
+    Vector4<i32> => i32, uniform_iv;
 }
 
-impl UniformUpload for Matrix3x4<f32> {
-    fn upload_uniform(&self, _context:&Context, _location:&WebGlUniformLocation) {
-        todo!() // FIXME: https://github.com/rustwasm/wasm-bindgen/issues/1956
-        // context.uniform_matrix3x4fv_with_f32_array(Some(location),false,self.data.as_slice());
-    }
+define_bool_array_upload! {
+    bool, Vector2<bool>, Vector3<bool>, Vector4<bool>
 }
 
-impl UniformUpload for Matrix4x2<f32> {
-    fn upload_uniform(&self, _context:&Context, _location:&WebGlUniformLocation) {
-        todo!() // FIXME: https://github.com/rustwasm/wasm-bindgen/issues/1956
-        // context.uniform_matrix4x2fv_with_f32_array(Some(location),false,self.data.as_slice());
-    }
+define_matrix_array_upload! {
+    Matrix2<f32>, Matrix3<f32>, Matrix4<f32>,
+    Matrix2x3<f32>, Matrix2x4<f32>, Matrix3x2<f32>, Matrix3x4<f32>, Matrix4x2<f32>, Matrix4x3<f32>
 }
 
-impl UniformUpload for Matrix4x3<f32> {
-    fn upload_uniform(&self, _context:&Context, _location:&WebGlUniformLocation) {
-        todo!() // FIXME: https://github.com/rustwasm/wasm-bindgen/issues/1956
-        // context.uniform_matrix4x3fv_with_f32_array(Some(location),false,self.data.as_slice());
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::system::gpu::context::mock::Call;
+    use crate::system::gpu::context::mock::MockContext;
+    use crate::system::gpu::context::mock::MockUniformLocation;
+    use crate::system::gpu::context::mock::UniformData;
+
+    fn uploaded_data(upload:impl FnOnce(&MockContext,&MockUniformLocation)) -> Vec<UniformData> {
+        let context  = MockContext::new();
+        let program  = context.create_program().unwrap();
+        let location = context.get_uniform_location(&program,"input").unwrap();
+        upload(&context,&location);
+        context.uniform_calls().into_iter().filter_map(|call| match call {
+            Call::Uniform {data,..} => Some(data),
+            _                       => None,
+        }).collect()
+    }
+
+    #[test]
+    fn vectors_are_uploaded_with_their_component_count() {
+        let data = uploaded_data(|c,l| Vector2::new(1.0_f32,2.0).upload_uniform(c,l));
+        assert_eq!(data, vec![UniformData::Float {components:2, data:vec![1.0,2.0]}]);
+        let data = uploaded_data(|c,l| Vector3::new(true,false,true).upload_uniform(c,l));
+        assert_eq!(data, vec![UniformData::Int {components:3, data:vec![1,0,1]}]);
+    }
+
+    #[test]
+    fn arrays_are_uploaded_in_a_single_call() {
+        let items = [Vector2::new(1.0_f32,2.0),Vector2::new(3.0,4.0)];
+        let data  = uploaded_data(|c,l| Vector2::upload_uniform_array(&items,c,l));
+        let flat  = vec![1.0,2.0,3.0,4.0];
+        assert_eq!(data, vec![UniformData::Float {components:2, data:flat}]);
+    }
+
+    #[test]
+    fn non_square_matrices_use_the_glsl_dimensions() {
+        let matrix = Matrix2x3::<f32>::zeros();
+        let data   = uploaded_data(|c,l| matrix.upload_uniform(c,l));
+        assert_eq!(data, vec![UniformData::Matrix {cols:2, rows:3, data:vec![0.0;6]}]);
+        let items = [Matrix4x2::<f32>::zeros();2];
+        let data  = uploaded_data(|c,l| Matrix4x2::upload_uniform_array(&items,c,l));
+        assert_eq!(data, vec![UniformData::Matrix {cols:4, rows:2, data:vec![0.0;16]}]);
     }
 }
@@ -1,10 +1,26 @@
 #![allow(missing_docs)]
 
+#[warn(missing_docs)]
+pub mod cache;
+#[warn(missing_docs)]
+pub mod compiler;
+#[warn(missing_docs)]
+pub mod diagnostic;
 #[warn(missing_docs)]
 pub mod glsl;
+#[warn(missing_docs)]
+pub mod reload;
+#[warn(missing_docs)]
+pub mod sources;
 
 use basegl_prelude::*;
 
+use crate::system::gpu::context::GpuContext;
+use diagnostic::Diagnostic;
+use diagnostic::Diagnostics;
+use diagnostic::Severity;
+use diagnostic::Stage;
+
 use js_sys::Float32Array;
 use web_sys::WebGlBuffer;
 use web_sys::WebGlProgram;
@@ -47,8 +63,8 @@ type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     #[fail(display="Unable to create {}.",target)]
     Create { target:ErrorTarget },
-    #[fail(display="Unable to compile {}.\n{}\n\n{}",target,message,code)]
-    Compile { target:ErrorTarget, message:String, code:String },
+    #[fail(display="Unable to compile {}.\n{}",target,diagnostics)]
+    Compile { target:ErrorTarget, diagnostics:Diagnostics },
 }
 
 #[derive(Copy, Clone, Debug, Fail)]
@@ -61,95 +77,93 @@ pub enum ErrorTarget {
 
 
 
-// ==================
-// === HasInfoLog ===
-// ==================
-
-pub trait CompilationTarget {
-    fn check(&self, ctx: &Context) -> bool;
-    fn logs(&self, ctx: &Context) -> String;
-}
-
-impl CompilationTarget for Shader {
-    fn check(&self, ctx: &Context) -> bool {
-        ctx.get_shader_parameter(&self, Context::COMPILE_STATUS).as_bool().unwrap_or(false)
-    }
-
-    fn logs(&self, ctx: &Context) -> String {
-        unwrap_error(ctx.get_shader_info_log(&self))
-    }
-}
-
-impl CompilationTarget for Program {
-    fn check(&self, ctx: &Context) -> bool {
-        ctx.get_program_parameter(&self, Context::LINK_STATUS).as_bool().unwrap_or(false)
-    }
-
-    fn logs(&self, ctx: &Context) -> String {
-        unwrap_error(ctx.get_program_info_log(&self))
-    }
-}
-
-fn unwrap_error(opt_err: Option<String>) -> String {
-    opt_err.unwrap_or_else(|| "Unknown error.".to_string())
-}
-
-
-
 // ======================
 // === Compile / Link ===
 // ======================
 
-pub fn compile_vertex_shader(ctx:&Context, src:&str) -> Result<Shader> {
+pub fn compile_vertex_shader<C:GpuContext>(ctx:&C, src:&str) -> Result<C::Shader> {
     compile_shader(ctx,Context::VERTEX_SHADER,src)
 }
 
-pub fn compile_fragment_shader(ctx:&Context, src:&str) -> Result<Shader> {
+pub fn compile_fragment_shader<C:GpuContext>(ctx:&C, src:&str) -> Result<C::Shader> {
     compile_shader(ctx,Context::FRAGMENT_SHADER,src)
 }
 
-// TODO: This is a very work-in-progress function. It should be refactored into helper functions.
-pub fn compile_shader(ctx:&Context, tp:u32, src:&str) -> Result<Shader> {
+pub fn compile_shader<C:GpuContext>(ctx:&C, tp:u32, src:&str) -> Result<C::Shader> {
+    let shader = start_shader_compilation(ctx,tp,src)?;
+    check_shader(ctx,tp,shader,src)
+}
+
+/// Creates the shader and issues its compilation without waiting for the result. Use
+/// `check_shader` to get the result.
+pub fn start_shader_compilation<C:GpuContext>(ctx:&C, tp:u32, src:&str) -> Result<C::Shader> {
     let target = ErrorTarget::Shader;
     let shader = ctx.create_shader(tp).ok_or(Error::Create {target})?;
-    ctx.shader_source(&shader, src);
+    ctx.shader_source(&shader,src);
     ctx.compile_shader(&shader);
-    if shader.check(ctx) {
+    Ok(shader)
+}
+
+/// Checks the compilation status of the shader of the provided type. Blocks until the compilation
+/// is done. The info log is parsed into diagnostics mapped to the origins of the code.
+pub fn check_shader<C:GpuContext>
+(ctx:&C, tp:u32, shader:C::Shader, src:&str) -> Result<C::Shader> {
+    if ctx.shader_compile_status(&shader) {
         Ok(shader)
     } else {
-        let code: String   = src.into();
-        let lines          = code.split('\n').collect::<Vec<&str>>();
-        let lines_num      = lines.len();
-        let lines_str_len  = (lines_num as f32).log10().ceil() as usize;
-        let lines_enum     = lines.into_iter().enumerate();
-        let lines_with_num = lines_enum.map(|(n,l)| format!("{1:0$} : {2}",lines_str_len,n+1,l));
-        let lines_with_num = lines_with_num.collect::<Vec<String>>();
-        let code_with_num  = lines_with_num.join("\n");
-        let message        = shader.logs(ctx);
-        let error_loc_pfx  = "ERROR: 0:";
-        let out = if message.starts_with(error_loc_pfx) {
-            let msg                = &message[error_loc_pfx.len()..];
-            let line_num:String    = msg.chars().take_while(|c| c.is_digit(10)).collect();
-            let line_num           = line_num.parse::<usize>().unwrap() - 1;
-            let preview_radius     = 5;
-            let preview_line_start = std::cmp::max(0         , line_num - preview_radius);
-            let preview_line_end   = std::cmp::min(lines_num , line_num + preview_radius);
-            lines_with_num[preview_line_start..preview_line_end].join("\n")
-        } else { code_with_num };
-        Err(Error::Compile {target,message,code:out})
+        let target      = ErrorTarget::Shader;
+        let is_vertex   = tp == Context::VERTEX_SHADER;
+        let stage       = if is_vertex { Stage::Vertex } else { Stage::Fragment };
+        let log         = unwrap_error(ctx.shader_info_log(&shader));
+        let diagnostics = diagnostics(stage,src,&log);
+        Err(Error::Compile {target,diagnostics})
     }
 }
 
-pub fn link_program(ctx:&Context, vert_shader:&Shader, frag_shader:&Shader) -> Result<Program> {
-    let target = ErrorTarget::Program;
+/// Links the program and checks the link status. Blocks until the linking is done.
+pub fn link_program<C:GpuContext>
+(ctx:&C, vert_shader:&C::Shader, frag_shader:&C::Shader) -> Result<C::Program> {
+    let program = start_program_linking(ctx,vert_shader,frag_shader)?;
+    check_program(ctx,program)
+}
+
+/// Creates the program and issues its linking without waiting for the result. Use
+/// `check_program` to get the result.
+pub fn start_program_linking<C:GpuContext>
+(ctx:&C, vert_shader:&C::Shader, frag_shader:&C::Shader) -> Result<C::Program> {
+    let target  = ErrorTarget::Program;
     let program = ctx.create_program().ok_or(Error::Create {target})?;
-    ctx.attach_shader(&program, vert_shader);
-    ctx.attach_shader(&program, frag_shader);
+    ctx.attach_shader(&program,vert_shader);
+    ctx.attach_shader(&program,frag_shader);
     ctx.link_program(&program);
-    // TODO: handler errors
     Ok(program)
 }
 
+/// Checks the link status of the program. Blocks until the linking is done.
+pub fn check_program<C:GpuContext>(ctx:&C, program:C::Program) -> Result<C::Program> {
+    if ctx.program_link_status(&program) {
+        Ok(program)
+    } else {
+        let target      = ErrorTarget::Program;
+        let log         = unwrap_error(ctx.program_info_log(&program));
+        let diagnostics = diagnostics(Stage::Link,"",&log);
+        Err(Error::Compile {target,diagnostics})
+    }
+}
+
+fn unwrap_error(opt_err: Option<String>) -> String {
+    opt_err.unwrap_or_else(|| "Unknown error.".to_string())
+}
+
+/// Parses the info log. If it does not contain any errors, a generic error is added, as the
+/// function is used only when the compilation or linking failed.
+fn diagnostics(stage:Stage, src:&str, log:&str) -> Diagnostics {
+    let mut items = diagnostic::diagnose(stage,src,log);
+    if !items.iter().any(|item| item.is_error()) {
+        items.push(Diagnostic::new(stage,Severity::Error,"Unknown error."));
+    }
+    Diagnostics::new(items)
+}
 
 
 // ========================
@@ -0,0 +1,513 @@
+//! This module defines a cache of linked shader programs. Programs are keyed by their final vertex
+//! and fragment sources, so all symbols generating identical code share a single program, which
+//! is compiled only once. Programs are compiled asynchronously by the `Compiler`.
+
+use crate::prelude::*;
+
+use crate::debug::stats::Stats;
+use crate::system::gpu::context::GpuContext;
+use crate::system::gpu::shader::Context;
+use crate::system::gpu::shader::compiler::CompileStatus;
+use crate::system::gpu::shader::compiler::Compiler;
+use crate::system::gpu::shader::compiler::CompilerBackend;
+use crate::system::gpu::shader::compiler::GpuCompiler;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// The default maximum number of programs kept in the cache while not being used by anyone.
+pub const DEFAULT_MAX_UNUSED : usize = 32;
+
+
+
+// ==================
+// === ProgramKey ===
+// ==================
+
+/// Identifies a program by its final sources.
+#[derive(Clone,Debug,Eq,Hash,PartialEq)]
+pub struct ProgramKey {
+    /// The vertex shader source.
+    pub vertex   : String,
+    /// The fragment shader source.
+    pub fragment : String,
+}
+
+impl ProgramKey {
+    /// Constructor.
+    pub fn new<V:Str,F:Str>(vertex:V, fragment:F) -> Self {
+        let vertex   = vertex.into();
+        let fragment = fragment.into();
+        Self {vertex,fragment}
+    }
+}
+
+
+
+// =====================
+// === CachedProgram ===
+// =====================
+
+/// Shared handle to a linked program. Attribute and uniform locations are resolved lazily, once
+/// per program, and are shared between all the handles. The program is kept alive in the cache as
+/// long as at least one handle exists.
+#[derive(Clone,Debug)]
+pub struct CachedProgram<C:GpuContext=Context> {
+    rc: Rc<RefCell<CachedProgramData<C>>>,
+}
+
+impl<C:GpuContext> CachedProgram<C> {
+    /// Constructor.
+    pub fn new(context:&C, program:C::Program) -> Self {
+        let rc = Rc::new(RefCell::new(CachedProgramData::new(context,program)));
+        Self {rc}
+    }
+
+    /// Cheap clone of the structure. Implemented as the `Rc::clone` under the hood.
+    pub fn clone_ref(&self) -> Self {
+        self.clone()
+    }
+
+    /// The underlying GPU program.
+    pub fn gl_program(&self) -> C::Program {
+        self.rc.borrow().program.clone()
+    }
+
+    /// Location of the attribute, or a negative value if the program does not use it.
+    pub fn attribute_location(&self, name:&str) -> i32 {
+        self.rc.borrow_mut().attribute_location(name)
+    }
+
+    /// Location of the uniform, or `None` if the program does not use it.
+    pub fn uniform_location(&self, name:&str) -> Option<C::UniformLocation> {
+        self.rc.borrow_mut().uniform_location(name)
+    }
+
+    /// Number of handles of this program, excluding the one kept by the cache.
+    fn user_count(&self) -> usize {
+        Rc::strong_count(&self.rc) - 1
+    }
+}
+
+/// The internal state of the `CachedProgram`.
+#[derive(Debug)]
+pub struct CachedProgramData<C:GpuContext=Context> {
+    program             : C::Program,
+    attribute_locations : HashMap<String,i32>,
+    uniform_locations   : HashMap<String,Option<C::UniformLocation>>,
+    context             : C,
+}
+
+impl<C:GpuContext> CachedProgramData<C> {
+    /// Constructor.
+    pub fn new(context:&C, program:C::Program) -> Self {
+        let attribute_locations = default();
+        let uniform_locations   = default();
+        let context             = context.clone();
+        Self {program,attribute_locations,uniform_locations,context}
+    }
+
+    /// Location of the attribute, or a negative value if the program does not use it.
+    pub fn attribute_location(&mut self, name:&str) -> i32 {
+        let context = &self.context;
+        let program = &self.program;
+        *self.attribute_locations.entry(name.into()).or_insert_with(|| {
+            context.get_attrib_location(program,name)
+        })
+    }
+
+    /// Location of the uniform, or `None` if the program does not use it.
+    pub fn uniform_location(&mut self, name:&str) -> Option<C::UniformLocation> {
+        let context = &self.context;
+        let program = &self.program;
+        self.uniform_locations.entry(name.into()).or_insert_with(|| {
+            context.get_uniform_location(program,name)
+        }).clone()
+    }
+}
+
+
+
+// =====================
+// === ProgramStatus ===
+// =====================
+
+/// Result of requesting a program from the `ProgramCache`.
+#[derive(Clone,Debug)]
+pub enum ProgramStatus<C:GpuContext=Context> {
+    /// The program is being compiled, or it was not requested yet.
+    Compiling,
+    /// The program is linked and ready to be used.
+    Ready(CachedProgram<C>),
+    /// The compilation or linking failed. The error is reported by the cache logger.
+    Failed,
+}
+
+impl<C:GpuContext> Default for ProgramStatus<C> {
+    fn default() -> Self {
+        Self::Compiling
+    }
+}
+
+impl<C:GpuContext> ProgramStatus<C> {
+    /// The program, if it is ready.
+    pub fn program(&self) -> Option<&CachedProgram<C>> {
+        match self {
+            Self::Ready(program) => Some(program),
+            _                    => None,
+        }
+    }
+
+    /// Checks whether the program is still being compiled.
+    pub fn is_compiling(&self) -> bool {
+        match self {
+            Self::Compiling => true,
+            _               => false,
+        }
+    }
+
+    /// Checks whether the compilation or linking failed.
+    pub fn is_failed(&self) -> bool {
+        match self {
+            Self::Failed => true,
+            _            => false,
+        }
+    }
+}
+
+
+
+// ====================
+// === ProgramCache ===
+// ====================
+
+/// Entry of the `ProgramCache`.
+#[derive(Debug)]
+struct CacheEntry<C:GpuContext> {
+    program   : CachedProgram<C>,
+    last_used : usize,
+}
+
+/// Cache of linked programs. A program is reference counted by its `CachedProgram` handles. When
+/// the last handle is dropped, the program stays in the cache, so it can be reused if the same
+/// code is generated again. When the number of such unused programs exceeds `max_unused`, the
+/// least recently used ones are evicted and deleted from the GPU memory. Programs missing in the
+/// cache are compiled asynchronously. Call `poll` once per frame in order to progress their
+/// compilation.
+#[derive(Derivative)]
+#[derivative(Clone(bound=""))]
+#[derivative(Debug(bound="B:Debug, B::Job:Debug"))]
+pub struct ProgramCache<C=Context,B=GpuCompiler<C>>
+where C:GpuContext, B:CompilerBackend<Program=C::Program> {
+    rc: Rc<RefCell<ProgramCacheData<C,B>>>,
+}
+
+impl<C:GpuContext> ProgramCache<C> {
+    /// Constructor.
+    pub fn new(logger:Logger, stats:&Stats, context:&C) -> Self {
+        Self::new_with_backend(logger,stats,context,GpuCompiler::new(context))
+    }
+}
+
+impl<C:GpuContext, B:CompilerBackend<Program=C::Program>> ProgramCache<C,B> {
+    /// Constructor of a cache compiling programs with the provided backend.
+    pub fn new_with_backend(logger:Logger, stats:&Stats, context:&C, backend:B) -> Self {
+        let data = ProgramCacheData::new(logger,stats,context,backend);
+        let rc   = Rc::new(RefCell::new(data));
+        Self {rc}
+    }
+
+    /// Cheap clone of the structure. Implemented as the `Rc::clone` under the hood.
+    pub fn clone_ref(&self) -> Self {
+        self.clone()
+    }
+
+    /// Gets the program with the provided sources from the cache. If it was not cached yet, its
+    /// compilation is queued and the program becomes ready after a few polls.
+    pub fn request(&self, vertex:&str, fragment:&str) -> ProgramStatus<C> {
+        self.rc.borrow_mut().request(vertex,fragment)
+    }
+
+    /// Issues queued compilations within the time budget and fetches results of the completed
+    /// ones. Should be called once per frame.
+    pub fn poll(&self) {
+        self.rc.borrow_mut().poll()
+    }
+
+    /// Checks whether any programs are being compiled.
+    pub fn is_compiling(&self) -> bool {
+        self.rc.borrow().compiler.is_busy()
+    }
+
+    /// Sets the time in milliseconds which can be spent on issuing compilations during one frame.
+    pub fn set_compile_budget(&self, budget:f64) {
+        self.rc.borrow_mut().compiler.set_budget(budget)
+    }
+
+    /// Number of live handles of the program with the provided sources.
+    pub fn ref_count(&self, vertex:&str, fragment:&str) -> usize {
+        self.rc.borrow().ref_count(vertex,fragment)
+    }
+
+    /// Number of cached programs, both used and unused.
+    pub fn len(&self) -> usize {
+        self.rc.borrow().entries.len()
+    }
+
+    /// Checks whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.rc.borrow().entries.is_empty()
+    }
+
+    /// Sets the maximum number of unused programs kept in the cache.
+    pub fn set_max_unused(&self, max_unused:usize) {
+        let mut data = self.rc.borrow_mut();
+        data.max_unused = max_unused;
+        data.evict(max_unused);
+    }
+
+    /// Deletes all programs which are not used by anyone.
+    pub fn evict_unused(&self) {
+        self.rc.borrow_mut().evict(0)
+    }
+
+    /// Forgets the failed compilation of the program with the provided sources, so it is compiled
+    /// again when requested. Should be called when the sources are replaced, so failures of the
+    /// outdated sources are not kept forever.
+    pub fn forget_failure(&self, vertex:&str, fragment:&str) {
+        let key = ProgramKey::new(vertex,fragment);
+        self.rc.borrow_mut().compiler.forget_failure(&key)
+    }
+
+    /// Forgets all programs and compilation jobs without deleting them. Should be called after the
+    /// context was lost and restored, as all the programs are invalid then.
+    pub fn restore_context(&self) {
+        self.rc.borrow_mut().restore_context()
+    }
+}
+
+/// The internal state of the `ProgramCache`.
+#[derive(Derivative)]
+#[derivative(Debug(bound="B:Debug, B::Job:Debug"))]
+pub struct ProgramCacheData<C,B>
+where C:GpuContext, B:CompilerBackend<Program=C::Program> {
+    entries    : HashMap<ProgramKey,CacheEntry<C>>,
+    compiler   : Compiler<B>,
+    max_unused : usize,
+    tick       : usize,
+    context    : C,
+    stats      : Stats,
+    logger     : Logger,
+}
+
+impl<C:GpuContext, B:CompilerBackend<Program=C::Program>> ProgramCacheData<C,B> {
+    /// Constructor.
+    pub fn new(logger:Logger, stats:&Stats, context:&C, backend:B) -> Self {
+        let entries    = default();
+        let compiler   = Compiler::new(logger.sub("compiler"),backend);
+        let max_unused = DEFAULT_MAX_UNUSED;
+        let tick       = default();
+        let context    = context.clone();
+        let stats      = stats.clone_ref();
+        Self {entries,compiler,max_unused,tick,context,stats,logger}
+    }
+
+    /// Gets the program with the provided sources from the cache. If it was not cached yet, its
+    /// compilation is queued and the program becomes ready after a few polls.
+    pub fn request(&mut self, vertex:&str, fragment:&str) -> ProgramStatus<C> {
+        self.tick += 1;
+        let key = ProgramKey::new(vertex,fragment);
+        if let Some(entry) = self.entries.get_mut(&key) {
+            self.stats.inc_program_cache_hit_count();
+            entry.last_used = self.tick;
+            return ProgramStatus::Ready(entry.program.clone_ref())
+        }
+        if let Some(program) = self.compiler.take_ready(&key) {
+            let program   = CachedProgram::new(&self.context,program);
+            let last_used = self.tick;
+            self.entries.insert(key,CacheEntry {program:program.clone_ref(),last_used});
+            self.evict(self.max_unused);
+            self.stats.set_cached_program_count(self.entries.len());
+            return ProgramStatus::Ready(program)
+        }
+        match self.compiler.status(&key) {
+            CompileStatus::Failed  => ProgramStatus::Failed,
+            CompileStatus::Unknown => {
+                self.stats.inc_program_cache_miss_count();
+                self.stats.inc_shader_compile_count();
+                self.compiler.submit(key);
+                self.stats.set_compiling_program_count(self.compiler.pending_count());
+                ProgramStatus::Compiling
+            }
+            _ => ProgramStatus::Compiling
+        }
+    }
+
+    /// Issues queued compilations within the time budget and fetches results of the completed
+    /// ones. Should be called once per frame.
+    pub fn poll(&mut self) {
+        self.compiler.poll();
+        self.stats.set_compiling_program_count(self.compiler.pending_count());
+    }
+
+    /// Number of live handles of the program with the provided sources.
+    pub fn ref_count(&self, vertex:&str, fragment:&str) -> usize {
+        let key = ProgramKey::new(vertex,fragment);
+        self.entries.get(&key).map(|entry| entry.program.user_count()).unwrap_or(0)
+    }
+
+    /// Forgets all programs and compilation jobs without deleting them. Should be called after the
+    /// context was lost and restored, as all the programs are invalid then.
+    pub fn restore_context(&mut self) {
+        self.entries.clear();
+        self.compiler.clear();
+        self.stats.set_cached_program_count(0);
+        self.stats.set_compiling_program_count(0);
+    }
+}
+
+
+// === Private API ===
+
+impl<C:GpuContext, B:CompilerBackend<Program=C::Program>> ProgramCacheData<C,B> {
+    /// Evicts the least recently used unused programs, until at most `max_unused` of them are left.
+    fn evict(&mut self, max_unused:usize) {
+        let unused     = self.entries.iter().filter(|(_,entry)| entry.program.user_count() == 0);
+        let mut unused = unused.map(|(key,entry)| (entry.last_used,key.clone())).collect_vec();
+        if unused.len() > max_unused {
+            unused.sort_by_key(|(last_used,_)| *last_used);
+            let count = unused.len() - max_unused;
+            group!(self.logger, "Evicting {count} unused program(s).", {
+                for (_,key) in unused.into_iter().take(count) {
+                    if let Some(entry) = self.entries.remove(&key) {
+                        self.context.delete_program(Some(&entry.program.gl_program()));
+                    }
+                }
+                self.stats.set_cached_program_count(self.entries.len());
+            })
+        }
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::system::gpu::context::mock::Call;
+    use crate::system::gpu::context::mock::MockContext;
+    use crate::system::gpu::shader::compiler::mock::MockBackend;
+
+    const VERTEX : &str = "vertex";
+
+    type TestCache = ProgramCache<MockContext,MockBackend>;
+
+    fn fragment(color:f32) -> String {
+        format!("color = vec4({:.1});",color)
+    }
+
+    fn new_cache() -> (TestCache,Stats,MockContext) {
+        let context = MockContext::new();
+        let stats   = Stats::default();
+        let backend = MockBackend::new(0.0,0);
+        let cache   = ProgramCache::new_with_backend(Logger::new("Test"),&stats,&context,backend);
+        (cache,stats,context)
+    }
+
+    /// Requests the program until it is compiled.
+    fn request_ready(cache:&TestCache, fragment:&str) -> CachedProgram<MockContext> {
+        for _ in 0..10 {
+            match cache.request(VERTEX,fragment) {
+                ProgramStatus::Ready(program) => return program,
+                ProgramStatus::Failed         => panic!("The test program failed to compile."),
+                ProgramStatus::Compiling      => cache.poll(),
+            }
+        }
+        panic!("The test program was not compiled in time.")
+    }
+
+    fn deleted_programs(context:&MockContext) -> usize {
+        context.calls().iter().filter(|call| match call {
+            Call::DeleteProgram {..} => true,
+            _                        => false,
+        }).count()
+    }
+
+    #[test]
+    fn missing_program_is_compiled() {
+        let (cache,stats,_) = new_cache();
+        assert!(cache.request(VERTEX,&fragment(1.0)).is_compiling());
+        assert!(cache.request(VERTEX,&fragment(1.0)).is_compiling());
+        assert_eq!(stats.program_cache_miss_count(),1);
+        assert_eq!(stats.shader_compile_count(),1);
+        assert!(cache.is_compiling());
+        cache.poll();
+        cache.poll();
+        assert!(!cache.is_compiling());
+        assert!(cache.request(VERTEX,&fragment(1.0)).program().is_some());
+        assert_eq!(stats.program_cache_hit_count(),0);
+        assert_eq!(cache.len(),1);
+    }
+
+    #[test]
+    fn cached_program_is_shared() {
+        let (cache,stats,_) = new_cache();
+        let first           = request_ready(&cache,&fragment(1.0));
+        let second          = request_ready(&cache,&fragment(1.0));
+        assert!(Rc::ptr_eq(&first.rc,&second.rc));
+        assert_eq!(stats.program_cache_hit_count(),1);
+        assert_eq!(stats.shader_compile_count(),1);
+        assert_eq!(cache.ref_count(VERTEX,&fragment(1.0)),2);
+        drop(second);
+        assert_eq!(cache.ref_count(VERTEX,&fragment(1.0)),1);
+        let other = request_ready(&cache,&fragment(0.5));
+        assert!(!Rc::ptr_eq(&first.rc,&other.rc));
+        assert_eq!(stats.shader_compile_count(),2);
+        assert_eq!(cache.len(),2);
+    }
+
+    #[test]
+    fn least_recently_used_programs_are_evicted() {
+        let (cache,stats,context) = new_cache();
+        cache.set_max_unused(1);
+        let used = request_ready(&cache,&fragment(0.0));
+        drop(request_ready(&cache,&fragment(0.1)));
+        drop(request_ready(&cache,&fragment(0.2)));
+        assert_eq!(cache.len(),3);
+        assert_eq!(deleted_programs(&context),0);
+        let last = request_ready(&cache,&fragment(0.3));
+        assert_eq!(cache.len(),3);
+        assert_eq!(deleted_programs(&context),1);
+        let hits = stats.program_cache_hit_count();
+        assert!(cache.request(VERTEX,&fragment(0.2)).program().is_some());
+        assert_eq!(stats.program_cache_hit_count(),hits + 1);
+        assert!(cache.request(VERTEX,&fragment(0.1)).is_compiling());
+        drop(last);
+        cache.evict_unused();
+        assert_eq!(cache.len(),1);
+        assert_eq!(deleted_programs(&context),3);
+        assert_eq!(cache.ref_count(VERTEX,&fragment(0.0)),1);
+        drop(used);
+    }
+
+    #[test]
+    fn forgotten_failure_is_compiled_again() {
+        let (cache,stats,_) = new_cache();
+        cache.request(VERTEX,"");
+        cache.poll();
+        cache.poll();
+        assert!(cache.request(VERTEX,"").is_failed());
+        assert_eq!(stats.shader_compile_count(),1);
+        cache.forget_failure(VERTEX,"");
+        assert!(cache.request(VERTEX,"").is_compiling());
+        assert_eq!(stats.shader_compile_count(),2);
+    }
+}
@@ -0,0 +1,549 @@
+//! This module defines a non-blocking program compiler. Compilation requests are queued and issued
+//! across frames within a time budget. If the `KHR_parallel_shader_compile` extension is available,
+//! the driver compiles programs in the background and their results are fetched only after they
+//! are ready, so the main thread is never blocked waiting for them.
+
+use crate::prelude::*;
+
+use crate::system::gpu::context::GpuContext;
+use crate::system::gpu::shader::Context;
+use crate::system::gpu::shader::Error;
+use crate::system::gpu::shader::cache::ProgramKey;
+use crate::system::gpu::shader::check_program;
+use crate::system::gpu::shader::check_shader;
+use crate::system::gpu::shader::start_program_linking;
+use crate::system::gpu::shader::start_shader_compilation;
+use crate::system::web;
+
+use std::collections::VecDeque;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// The default time in milliseconds which can be spent on issuing compilations during one frame.
+pub const DEFAULT_BUDGET_MS : f64 = 4.0;
+
+/// Name of the WebGL extension allowing querying the compilation status without blocking.
+pub const PARALLEL_COMPILE_EXTENSION : &str = "KHR_parallel_shader_compile";
+
+/// The `COMPLETION_STATUS_KHR` parameter defined by the `KHR_parallel_shader_compile` extension.
+pub const COMPLETION_STATUS_KHR : u32 = 0x91B1;
+
+
+
+// =======================
+// === CompilerBackend ===
+// =======================
+
+/// Abstraction over the GPU operations performed by the `Compiler`. It allows testing the compiler
+/// state machine without the WebGL context.
+pub trait CompilerBackend {
+    /// Program which compilation was issued, but its result was not checked yet.
+    type Job;
+    /// Successfully linked program.
+    type Program;
+
+    /// Issues the compilation and linking of the program without waiting for the result.
+    fn start(&mut self, key:&ProgramKey) -> Result<Self::Job,Error>;
+
+    /// Checks whether the result of the job can be fetched without blocking.
+    fn is_complete(&self, job:&Self::Job) -> bool;
+
+    /// Fetches the result of the job.
+    fn finish(&mut self, job:Self::Job) -> Result<Self::Program,Error>;
+
+    /// Current time in milliseconds.
+    fn now(&self) -> f64;
+}
+
+
+
+// =====================
+// === CompileStatus ===
+// =====================
+
+/// Status of the program compilation.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum CompileStatus {
+    /// The program was never submitted, or it was already taken from the compiler.
+    Unknown,
+    /// The program waits for its compilation to be issued.
+    Queued,
+    /// The compilation was issued, but the result is not ready yet.
+    Pending,
+    /// The program is linked and waits to be taken from the compiler.
+    Ready,
+    /// The compilation or linking failed.
+    Failed,
+}
+
+/// Internal state of a single compilation job.
+#[derive(Debug)]
+enum JobState<Job,Program> {
+    Queued,
+    Pending(Job),
+    Ready(Program),
+    Failed(String),
+}
+
+
+
+// ================
+// === Compiler ===
+// ================
+
+/// Queue of compilation jobs. Call `poll` once per frame in order to issue queued compilations and
+/// to fetch the results of the completed ones. Issuing compilations stops when the time budget is
+/// exceeded, however, at least one compilation is issued per poll, so the queue always progresses.
+#[derive(Derivative)]
+#[derivative(Debug(bound="B:Debug, B::Job:Debug, B::Program:Debug"))]
+pub struct Compiler<B:CompilerBackend> {
+    backend : B,
+    jobs    : HashMap<ProgramKey,JobState<B::Job,B::Program>>,
+    queue   : VecDeque<ProgramKey>,
+    budget  : f64,
+    logger  : Logger,
+}
+
+impl<B:CompilerBackend> Compiler<B> {
+    /// Constructor.
+    pub fn new(logger:Logger, backend:B) -> Self {
+        let jobs   = default();
+        let queue  = default();
+        let budget = DEFAULT_BUDGET_MS;
+        Self {backend,jobs,queue,budget,logger}
+    }
+
+    /// The backend used to compile programs.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// The mutable backend used to compile programs.
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    /// Sets the time in milliseconds which can be spent on issuing compilations during one poll.
+    pub fn set_budget(&mut self, budget:f64) {
+        self.budget = budget;
+    }
+
+    /// Queues the compilation of the program. Does nothing if the program is already known.
+    pub fn submit(&mut self, key:ProgramKey) {
+        if !self.jobs.contains_key(&key) {
+            self.queue.push_back(key.clone());
+            self.jobs.insert(key,JobState::Queued);
+        }
+    }
+
+    /// Status of the program compilation.
+    pub fn status(&self, key:&ProgramKey) -> CompileStatus {
+        match self.jobs.get(key) {
+            None                       => CompileStatus::Unknown,
+            Some(JobState::Queued)     => CompileStatus::Queued,
+            Some(JobState::Pending(_)) => CompileStatus::Pending,
+            Some(JobState::Ready(_))   => CompileStatus::Ready,
+            Some(JobState::Failed(_))  => CompileStatus::Failed,
+        }
+    }
+
+    /// The error message of the failed compilation.
+    pub fn failure(&self, key:&ProgramKey) -> Option<&str> {
+        match self.jobs.get(key) {
+            Some(JobState::Failed(message)) => Some(message),
+            _                               => None,
+        }
+    }
+
+    /// Takes the linked program out of the compiler. Returns `None` if it is not ready yet.
+    pub fn take_ready(&mut self, key:&ProgramKey) -> Option<B::Program> {
+        match self.jobs.remove(key) {
+            Some(JobState::Ready(program)) => Some(program),
+            Some(state)                    => { self.jobs.insert(key.clone(),state); None }
+            None                           => None,
+        }
+    }
+
+    /// Number of programs which are queued or which compilation was issued.
+    pub fn pending_count(&self) -> usize {
+        self.jobs.values().filter(|state| match state {
+            JobState::Queued | JobState::Pending(_) => true,
+            _                                       => false,
+        }).count()
+    }
+
+    /// Checks whether there are any programs which are not compiled yet.
+    pub fn is_busy(&self) -> bool {
+        self.pending_count() > 0
+    }
+
+    /// Fetches the results of the completed compilations and issues the queued ones within the
+    /// time budget.
+    pub fn poll(&mut self) {
+        self.finish_completed();
+        let start       = self.backend.now();
+        let mut started = false;
+        while let Some(key) = self.queue.pop_front() {
+            if started && self.backend.now() - start >= self.budget {
+                self.queue.push_front(key);
+                break
+            }
+            if let Some(JobState::Queued) = self.jobs.get(&key) {
+                let state = match self.backend.start(&key) {
+                    Ok(job)  => JobState::Pending(job),
+                    Err(err) => self.failed(err),
+                };
+                self.jobs.insert(key,state);
+                started = true;
+            }
+        }
+    }
+
+    /// Forgets the failed compilation of the program, so it is compiled again when submitted.
+    /// Programs which did not fail are left untouched.
+    pub fn forget_failure(&mut self, key:&ProgramKey) {
+        if let Some(JobState::Failed(_)) = self.jobs.get(key) {
+            self.jobs.remove(key);
+        }
+    }
+
+    /// Forgets all jobs, including the failed ones.
+    pub fn clear(&mut self) {
+        self.jobs.clear();
+        self.queue.clear();
+    }
+}
+
+
+// === Private API ===
+
+impl<B:CompilerBackend> Compiler<B> {
+    fn finish_completed(&mut self) {
+        let backend   = &self.backend;
+        let completed = self.jobs.iter().filter_map(|(key,state)| match state {
+            JobState::Pending(job) if backend.is_complete(job) => Some(key.clone()),
+            _                                                   => None,
+        }).collect_vec();
+        for key in completed {
+            if let Some(JobState::Pending(job)) = self.jobs.remove(&key) {
+                let state = match self.backend.finish(job) {
+                    Ok(program) => JobState::Ready(program),
+                    Err(err)    => self.failed(err),
+                };
+                self.jobs.insert(key,state);
+            }
+        }
+    }
+
+    /// Reports the error through the logger. Compilation errors are reported as separate
+    /// diagnostics, mapped to the origins of the code.
+    fn failed(&self, err:Error) -> JobState<B::Job,B::Program> {
+        match &err {
+            Error::Compile {target,diagnostics} => {
+                group!(self.logger, "Unable to compile {target}.", {
+                    diagnostics.report(&self.logger)
+                })
+            }
+            _ => self.logger.error(|| format!("{}", err)),
+        }
+        JobState::Failed(err.to_string())
+    }
+}
+
+
+
+// ===================
+// === GpuCompiler ===
+// ===================
+
+/// Compiler backend using the GPU context. If the `KHR_parallel_shader_compile` extension is not
+/// available, all jobs are reported as complete and fetching their results blocks.
+#[derive(Debug)]
+pub struct GpuCompiler<C:GpuContext=Context> {
+    context : C,
+}
+
+/// Shaders and the program which compilation was issued by the `GpuCompiler`.
+#[derive(Debug)]
+pub struct GpuJob<C:GpuContext=Context> {
+    key      : ProgramKey,
+    vertex   : C::Shader,
+    fragment : C::Shader,
+    program  : C::Program,
+}
+
+impl<C:GpuContext> GpuCompiler<C> {
+    /// Constructor.
+    pub fn new(context:&C) -> Self {
+        let context = context.clone();
+        Self {context}
+    }
+
+    /// Checks whether the `KHR_parallel_shader_compile` extension is used.
+    pub fn is_parallel(&self) -> bool {
+        self.context.has_extension(PARALLEL_COMPILE_EXTENSION)
+    }
+
+    fn check(&self, job:&GpuJob<C>) -> Result<(),Error> {
+        let context = &self.context;
+        check_shader(context,Context::VERTEX_SHADER,job.vertex.clone(),&job.key.vertex)?;
+        check_shader(context,Context::FRAGMENT_SHADER,job.fragment.clone(),&job.key.fragment)?;
+        check_program(context,job.program.clone())?;
+        Ok(())
+    }
+}
+
+impl<C:GpuContext> CompilerBackend for GpuCompiler<C> {
+    type Job     = GpuJob<C>;
+    type Program = C::Program;
+
+    fn start(&mut self, key:&ProgramKey) -> Result<GpuJob<C>,Error> {
+        let context  = &self.context;
+        let key      = key.clone();
+        let vertex   = start_shader_compilation(context,Context::VERTEX_SHADER,&key.vertex)?;
+        let fragment = start_shader_compilation(context,Context::FRAGMENT_SHADER,&key.fragment)?;
+        let program  = start_program_linking(context,&vertex,&fragment)?;
+        Ok(GpuJob {key,vertex,fragment,program})
+    }
+
+    fn is_complete(&self, job:&GpuJob<C>) -> bool {
+        self.context.program_completion_status(&job.program)
+    }
+
+    fn finish(&mut self, job:GpuJob<C>) -> Result<C::Program,Error> {
+        let result = self.check(&job);
+        self.context.delete_shader(Some(&job.vertex));
+        self.context.delete_shader(Some(&job.fragment));
+        if result.is_err() {
+            self.context.delete_program(Some(&job.program));
+        }
+        result.map(|_| job.program)
+    }
+
+    /// The clock is looked up on every call, so the compiler can be created outside of the
+    /// browser, for example in native tests.
+    fn now(&self) -> f64 {
+        let performance = web::get_performance().ok();
+        performance.map(|performance| performance.now()).unwrap_or_else(js_sys::Date::now)
+    }
+}
+
+
+
+// ============
+// === Mock ===
+// ============
+
+/// A compiler backend for native tests.
+#[cfg(test)]
+pub mod mock {
+    use super::*;
+    use crate::system::gpu::context::mock::MockId;
+    use crate::system::gpu::shader::ErrorTarget;
+    use crate::system::gpu::shader::diagnostic::Diagnostic;
+    use crate::system::gpu::shader::diagnostic::Diagnostics;
+    use crate::system::gpu::shader::diagnostic::Severity;
+    use crate::system::gpu::shader::diagnostic::Stage;
+
+    use std::cell::Cell;
+
+    /// Backend compiling programs in a number of polls and advancing the time by a fixed amount
+    /// with every issued compilation. Programs with an empty fragment source fail.
+    #[derive(Debug,Default)]
+    pub struct MockBackend {
+        /// The current time. Tests advance it to simulate the next frames.
+        pub time    : Cell<f64>,
+        /// The time taken by issuing a compilation.
+        pub cost    : f64,
+        /// The number of frames a compilation takes.
+        pub polls   : usize,
+        /// The keys of all the issued compilations.
+        pub started : Vec<ProgramKey>,
+        next_id     : usize,
+    }
+
+    /// Compilation issued by the `MockBackend`.
+    #[derive(Debug)]
+    pub struct MockJob {
+        id       : usize,
+        key      : ProgramKey,
+        ready_at : usize,
+    }
+
+    impl MockBackend {
+        /// Constructor.
+        pub fn new(cost:f64, polls:usize) -> Self {
+            Self {cost,polls,..default()}
+        }
+    }
+
+    impl CompilerBackend for MockBackend {
+        type Job     = MockJob;
+        type Program = MockId;
+
+        fn start(&mut self, key:&ProgramKey) -> Result<MockJob,Error> {
+            self.time.set(self.time.get() + self.cost);
+            self.started.push(key.clone());
+            let id       = self.next_id;
+            let key      = key.clone();
+            let ready_at = self.time.get() as usize + self.polls;
+            self.next_id += 1;
+            Ok(MockJob {id,key,ready_at})
+        }
+
+        fn is_complete(&self, job:&MockJob) -> bool {
+            self.time.get() as usize >= job.ready_at
+        }
+
+        fn finish(&mut self, job:MockJob) -> Result<MockId,Error> {
+            if job.key.fragment.is_empty() {
+                let target      = ErrorTarget::Shader;
+                let message     = "empty fragment shader";
+                let diagnostic  = Diagnostic::new(Stage::Fragment,Severity::Error,message);
+                let diagnostics = Diagnostics::new(vec![diagnostic]);
+                Err(Error::Compile {target,diagnostics})
+            } else {
+                Ok(MockId(job.id))
+            }
+        }
+
+        fn now(&self) -> f64 {
+            self.time.get()
+        }
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::mock::MockBackend;
+    use crate::system::gpu::context::mock::MockId;
+
+    fn compiler(cost:f64, polls:usize) -> Compiler<MockBackend> {
+        Compiler::new(Logger::new("test"),MockBackend::new(cost,polls))
+    }
+
+    /// Advances the mock time to the next frame.
+    fn next_frame(compiler:&Compiler<MockBackend>) {
+        let time = &compiler.backend().time;
+        time.set(time.get().floor() + 1.0);
+    }
+
+    #[test]
+    fn submitted_program_goes_through_all_states() {
+        let mut compiler = compiler(0.0,1);
+        let key          = ProgramKey::new("vertex","fragment");
+        assert_eq!(compiler.status(&key), CompileStatus::Unknown);
+        compiler.submit(key.clone());
+        assert_eq!(compiler.status(&key), CompileStatus::Queued);
+        compiler.poll();
+        assert_eq!(compiler.status(&key), CompileStatus::Pending);
+        assert_eq!(compiler.take_ready(&key), None);
+        compiler.poll();
+        assert_eq!(compiler.status(&key), CompileStatus::Pending);
+        next_frame(&compiler);
+        compiler.poll();
+        assert_eq!(compiler.status(&key), CompileStatus::Ready);
+        assert_eq!(compiler.take_ready(&key), Some(MockId(0)));
+        assert_eq!(compiler.status(&key), CompileStatus::Unknown);
+        assert!(!compiler.is_busy());
+    }
+
+    #[test]
+    fn failed_program_is_remembered() {
+        let mut compiler = compiler(0.0,0);
+        let key          = ProgramKey::new("vertex","");
+        compiler.submit(key.clone());
+        compiler.poll();
+        compiler.poll();
+        assert_eq!(compiler.status(&key), CompileStatus::Failed);
+        assert!(compiler.failure(&key).unwrap().contains("empty fragment shader"));
+        assert_eq!(compiler.take_ready(&key), None);
+        compiler.submit(key.clone());
+        compiler.poll();
+        assert_eq!(compiler.status(&key), CompileStatus::Failed);
+        assert_eq!(compiler.backend().started.len(), 1);
+    }
+
+    #[test]
+    fn forgotten_failure_is_compiled_again() {
+        let mut compiler = compiler(0.0,0);
+        let failed       = ProgramKey::new("vertex","");
+        let ready        = ProgramKey::new("vertex","fragment");
+        compiler.submit(failed.clone());
+        compiler.submit(ready.clone());
+        compiler.poll();
+        compiler.poll();
+        compiler.forget_failure(&failed);
+        compiler.forget_failure(&ready);
+        assert_eq!(compiler.status(&failed), CompileStatus::Unknown);
+        assert_eq!(compiler.status(&ready), CompileStatus::Ready);
+        compiler.submit(failed.clone());
+        compiler.poll();
+        assert_eq!(compiler.backend().started.len(), 3);
+    }
+
+    #[test]
+    fn duplicated_submissions_are_compiled_once() {
+        let mut compiler = compiler(0.0,0);
+        let key          = ProgramKey::new("vertex","fragment");
+        compiler.submit(key.clone());
+        compiler.submit(key.clone());
+        compiler.poll();
+        assert_eq!(compiler.backend().started, vec![key]);
+    }
+
+    #[test]
+    fn compilations_are_spread_across_frames_within_budget() {
+        let mut compiler = compiler(0.4,0);
+        compiler.set_budget(1.0);
+        let keys = (0..5).map(|i| ProgramKey::new(format!("vertex{}",i),"fragment")).collect_vec();
+        for key in &keys {
+            compiler.submit(key.clone());
+        }
+        compiler.poll();
+        assert_eq!(compiler.backend().started.len(), 3);
+        assert_eq!(compiler.pending_count(), 5);
+        next_frame(&compiler);
+        compiler.poll();
+        assert_eq!(compiler.backend().started, keys);
+        assert_eq!(compiler.pending_count(), 2);
+        next_frame(&compiler);
+        compiler.poll();
+        assert!(!compiler.is_busy());
+        assert!(keys.iter().all(|key| compiler.status(key) == CompileStatus::Ready));
+    }
+
+    #[test]
+    fn at_least_one_compilation_is_issued_per_poll() {
+        let mut compiler = compiler(10.0,0);
+        compiler.set_budget(1.0);
+        compiler.submit(ProgramKey::new("vertex1","fragment"));
+        compiler.submit(ProgramKey::new("vertex2","fragment"));
+        compiler.poll();
+        assert_eq!(compiler.backend().started.len(), 1);
+        compiler.poll();
+        assert_eq!(compiler.backend().started.len(), 2);
+    }
+
+    #[test]
+    fn clear_forgets_all_jobs() {
+        let mut compiler = compiler(0.0,0);
+        let key          = ProgramKey::new("vertex","fragment");
+        compiler.submit(key.clone());
+        compiler.clear();
+        compiler.poll();
+        assert_eq!(compiler.status(&key), CompileStatus::Unknown);
+        assert!(compiler.backend().started.is_empty());
+    }
+}
@@ -0,0 +1,471 @@
+//! This module defines structured diagnostics of shader compilation and program linking. The info
+//! logs reported by WebGL are parsed into separate entries, and the line numbers of the generated
+//! code are mapped back to the place the code originates from, like a material, an SDF primitive,
+//! or a shape function defined on `Canvas`.
+//!
+//! The origin of the code is recorded by marker comments inserted by code generators. Each marker
+//! describes all the lines following it, until the next marker is found. Use `with_origin` to mark
+//! a code chunk.
+
+use crate::prelude::*;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// Prefix of the comment marking the origin of the following lines.
+pub const ORIGIN_MARKER : &str = "// @origin";
+
+/// Number of lines shown before and after the line the diagnostic refers to.
+pub const PREVIEW_RADIUS : usize = 5;
+
+
+
+// ==============
+// === Origin ===
+// ==============
+
+/// The place a chunk of the generated code originates from.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum Origin {
+    /// Code generated by the shader builder, like attribute and uniform declarations.
+    Generated,
+    /// Code of the geometry material.
+    GeometryMaterial,
+    /// Code of the surface material.
+    SurfaceMaterial,
+    /// A GLSL library included in the shader, like the math utilities.
+    Library(String),
+    /// Definition of an SDF primitive.
+    SdfPrimitive(String),
+    /// Shape function generated by the `Canvas`.
+    CanvasShape(String),
+}
+
+impl Default for Origin {
+    fn default() -> Self {
+        Self::Generated
+    }
+}
+
+impl Origin {
+    /// The marker comment describing this origin.
+    pub fn marker(&self) -> String {
+        match self {
+            Self::Generated          => format!("{} generated"         , ORIGIN_MARKER),
+            Self::GeometryMaterial   => format!("{} geometry_material" , ORIGIN_MARKER),
+            Self::SurfaceMaterial    => format!("{} surface_material"  , ORIGIN_MARKER),
+            Self::Library(name)      => format!("{} library {}"        , ORIGIN_MARKER, name),
+            Self::SdfPrimitive(name) => format!("{} sdf {}"            , ORIGIN_MARKER, name),
+            Self::CanvasShape(name)  => format!("{} canvas {}"         , ORIGIN_MARKER, name),
+        }
+    }
+
+    /// Parses the marker comment. Returns `None` if the line is not a valid marker.
+    pub fn parse_marker(line:&str) -> Option<Self> {
+        let line = line.trim();
+        if !line.starts_with(ORIGIN_MARKER) {
+            return None
+        }
+        let mut args = line[ORIGIN_MARKER.len()..].split_whitespace();
+        let kind     = args.next()?;
+        let name     = args.next().map(|name| name.to_string());
+        match (kind,name) {
+            ("generated"         , None)       => Some(Self::Generated),
+            ("geometry_material" , None)       => Some(Self::GeometryMaterial),
+            ("surface_material"  , None)       => Some(Self::SurfaceMaterial),
+            ("library"           , Some(name)) => Some(Self::Library(name)),
+            ("sdf"               , Some(name)) => Some(Self::SdfPrimitive(name)),
+            ("canvas"            , Some(name)) => Some(Self::CanvasShape(name)),
+            _                                  => None,
+        }
+    }
+}
+
+impl Display for Origin {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Generated          => write!(f,"generated code"),
+            Self::GeometryMaterial   => write!(f,"geometry material"),
+            Self::SurfaceMaterial    => write!(f,"surface material"),
+            Self::Library(name)      => write!(f,"GLSL library '{}'",name),
+            Self::SdfPrimitive(name) => write!(f,"SDF primitive '{}'",name),
+            Self::CanvasShape(name)  => write!(f,"canvas shape function '{}'",name),
+        }
+    }
+}
+
+/// Prepends the code with the marker of the provided origin.
+pub fn with_origin<S:AsRef<str>>(origin:Origin, code:S) -> String {
+    format!("{}\n{}",origin.marker(),code.as_ref())
+}
+
+/// Finds the origin of the line with the provided number (counted from 1) and the number of the
+/// line relative to the marker of the origin (also counted from 1).
+pub fn find_origin(source:&str, line:usize) -> (Origin,usize) {
+    let lines  = source.lines().take(line).enumerate().collect_vec();
+    let marker = lines.into_iter().rev().find_map(|(ix,text)| {
+        Origin::parse_marker(text).map(|origin| (origin,ix+1))
+    });
+    match marker {
+        Some((origin,marker_line)) => (origin, line.saturating_sub(marker_line)),
+        None                       => (default(), line),
+    }
+}
+
+
+
+// ================
+// === Severity ===
+// ================
+
+/// Severity of a diagnostic.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum Severity {
+    /// The code could not be compiled or linked.
+    Error,
+    /// The code was compiled, but it may not behave as expected.
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Error   => write!(f,"error"),
+            Self::Warning => write!(f,"warning"),
+        }
+    }
+}
+
+
+
+// =============
+// === Stage ===
+// =============
+
+/// The step of the program creation the diagnostic was reported by.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum Stage {
+    /// Compilation of the vertex shader.
+    Vertex,
+    /// Compilation of the fragment shader.
+    Fragment,
+    /// Linking of the program.
+    Link,
+}
+
+impl Display for Stage {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Vertex   => write!(f,"vertex shader"),
+            Self::Fragment => write!(f,"fragment shader"),
+            Self::Link     => write!(f,"program linking"),
+        }
+    }
+}
+
+
+
+// ================
+// === LogEntry ===
+// ================
+
+/// Single entry of the info log reported by WebGL, like `ERROR: 0:12: 'x' : undeclared identifier`.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct LogEntry {
+    /// Severity of the entry.
+    pub severity : Severity,
+    /// Line of the source code the entry refers to, counted from 1.
+    pub line     : Option<usize>,
+    /// The message, without the severity and location prefix.
+    pub message  : String,
+}
+
+/// Parses the info log. Lines which do not start with a severity prefix are appended to the
+/// message of the previous entry.
+pub fn parse_log(log:&str) -> Vec<LogEntry> {
+    let mut entries:Vec<LogEntry> = default();
+    for text in log.lines().map(|text| text.trim()).filter(|text| !text.is_empty()) {
+        match parse_log_line(text) {
+            Some(entry) => entries.push(entry),
+            None        => match entries.last_mut() {
+                Some(entry) => { entry.message.push('\n'); entry.message.push_str(text) }
+                None        => {
+                    let severity = Severity::Error;
+                    let line     = None;
+                    let message  = text.into();
+                    entries.push(LogEntry {severity,line,message})
+                }
+            }
+        }
+    }
+    entries
+}
+
+fn parse_log_line(text:&str) -> Option<LogEntry> {
+    let (severity,rest) = if text.starts_with("ERROR:") {
+        (Severity::Error, &text["ERROR:".len()..])
+    } else if text.starts_with("WARNING:") {
+        (Severity::Warning, &text["WARNING:".len()..])
+    } else {
+        return None
+    };
+    let (line,message) = parse_location(rest.trim_start());
+    let message        = message.trim().into();
+    Some(LogEntry {severity,line,message})
+}
+
+/// Parses the `<source>:<line>:` location prefix, if present.
+fn parse_location(text:&str) -> (Option<usize>,&str) {
+    let mut parts = text.splitn(3,':');
+    let source    = parts.next().map(|t| t.trim());
+    let line      = parts.next().map(|t| t.trim());
+    let rest      = parts.next();
+    match (source,line,rest) {
+        (Some(source),Some(line),Some(rest)) if is_number(source) && is_number(line) =>
+            (line.parse().ok(),rest),
+        _ => (None,text),
+    }
+}
+
+fn is_number(text:&str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_digit(10))
+}
+
+
+
+// ==================
+// === Diagnostic ===
+// ==================
+
+/// Diagnostic of the shader compilation or the program linking, with the line number mapped back
+/// to the origin of the code.
+#[derive(Clone,Debug)]
+pub struct Diagnostic {
+    /// The step which reported the diagnostic.
+    pub stage       : Stage,
+    /// Severity of the diagnostic.
+    pub severity    : Severity,
+    /// Line of the generated code, counted from 1.
+    pub line        : Option<usize>,
+    /// The origin of the line.
+    pub origin      : Option<Origin>,
+    /// Line number relative to the beginning of the origin code chunk, counted from 1.
+    pub origin_line : Option<usize>,
+    /// The message reported by WebGL.
+    pub message     : String,
+    /// The code surrounding the line, with line numbers.
+    pub preview     : String,
+}
+
+impl Diagnostic {
+    /// Constructor of a diagnostic not related to any code line.
+    pub fn new<S:Str>(stage:Stage, severity:Severity, message:S) -> Self {
+        let line        = None;
+        let origin      = None;
+        let origin_line = None;
+        let message     = message.into();
+        let preview     = default();
+        Self {stage,severity,line,origin,origin_line,message,preview}
+    }
+
+    /// Checks whether the diagnostic is an error.
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f,"{} {}",self.stage,self.severity)?;
+        if let Some(line) = self.line {
+            write!(f," at line {}",line)?;
+        }
+        if let Some(origin) = &self.origin {
+            write!(f," in {}",origin)?;
+            if let Some(origin_line) = self.origin_line {
+                write!(f," (line {})",origin_line)?;
+            }
+        }
+        write!(f,": {}",self.message)?;
+        if !self.preview.is_empty() {
+            write!(f,"\n{}",self.preview)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the info log and maps all its entries to the origins found in the source code.
+pub fn diagnose(stage:Stage, source:&str, log:&str) -> Vec<Diagnostic> {
+    parse_log(log).into_iter().map(|entry| {
+        let mut diagnostic = Diagnostic::new(stage,entry.severity,entry.message);
+        if let Some(line) = entry.line {
+            let (origin,origin_line) = find_origin(source,line);
+            diagnostic.line          = Some(line);
+            diagnostic.origin        = Some(origin);
+            diagnostic.origin_line   = Some(origin_line);
+            diagnostic.preview       = preview(source,line);
+        }
+        diagnostic
+    }).collect()
+}
+
+/// The code surrounding the line with the provided number (counted from 1), with line numbers.
+/// The line itself is marked with an arrow.
+pub fn preview(source:&str, line:usize) -> String {
+    let lines     = source.lines().collect_vec();
+    let num_width = lines.len().to_string().len();
+    let start     = line.saturating_sub(PREVIEW_RADIUS).max(1);
+    let end       = (line + PREVIEW_RADIUS).min(lines.len());
+    (start..=end).map(|num| {
+        let arrow = if num == line { ">" } else { " " };
+        format!("{} {:>width$} : {}",arrow,num,lines[num-1],width=num_width)
+    }).collect_vec().join("\n")
+}
+
+
+
+// ===================
+// === Diagnostics ===
+// ===================
+
+/// All diagnostics reported while creating a program.
+#[derive(Clone,Debug,Default)]
+pub struct Diagnostics {
+    /// The diagnostics, in the order they were reported.
+    pub items : Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    /// Constructor.
+    pub fn new(items:Vec<Diagnostic>) -> Self {
+        Self {items}
+    }
+
+    /// Iterator over diagnostics which are errors.
+    pub fn errors(&self) -> impl Iterator<Item=&Diagnostic> {
+        self.items.iter().filter(|diagnostic| diagnostic.is_error())
+    }
+
+    /// Iterator over diagnostics which are warnings.
+    pub fn warnings(&self) -> impl Iterator<Item=&Diagnostic> {
+        self.items.iter().filter(|diagnostic| !diagnostic.is_error())
+    }
+
+    /// Reports all diagnostics through the logger.
+    pub fn report(&self, logger:&Logger) {
+        for diagnostic in &self.items {
+            match diagnostic.severity {
+                Severity::Error   => logger.error  (|| diagnostic.to_string()),
+                Severity::Warning => logger.warning(|| diagnostic.to_string()),
+            }
+        }
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        let items = self.items.iter().map(|diagnostic| diagnostic.to_string()).collect_vec();
+        write!(f,"{}",items.join("\n\n"))
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_log_entries() {
+        let log = "ERROR: 0:12: 'foo' : undeclared identifier\n\
+                   WARNING: 0:3: 'bar' : unused variable\n\
+                   ERROR: too many uniforms\n\
+                   see the documentation\n";
+        let entries = parse_log(log);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].severity, Severity::Error);
+        assert_eq!(entries[0].line, Some(12));
+        assert_eq!(entries[0].message, "'foo' : undeclared identifier");
+        assert_eq!(entries[1].severity, Severity::Warning);
+        assert_eq!(entries[1].line, Some(3));
+        assert_eq!(entries[2].line, None);
+        assert_eq!(entries[2].message, "too many uniforms\nsee the documentation");
+    }
+
+    #[test]
+    fn parse_log_without_prefix() {
+        let entries = parse_log("Link failed.");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].severity, Severity::Error);
+        assert_eq!(entries[0].message, "Link failed.");
+        assert!(parse_log("").is_empty());
+    }
+
+    #[test]
+    fn origin_markers_round_trip() {
+        let origins = vec!
+            [ Origin::Generated
+            , Origin::GeometryMaterial
+            , Origin::SurfaceMaterial
+            , Origin::Library("math".into())
+            , Origin::SdfPrimitive("circle".into())
+            , Origin::CanvasShape("shape_1".into())
+            ];
+        for origin in origins {
+            assert_eq!(Origin::parse_marker(&origin.marker()), Some(origin));
+        }
+        assert_eq!(Origin::parse_marker("// @origin sdf"), None);
+        assert_eq!(Origin::parse_marker("// a comment"), None);
+    }
+
+    #[test]
+    fn lines_are_mapped_to_origins() {
+        let source = [ "#version 300 es"
+                     , &Origin::SdfPrimitive("circle".into()).marker()
+                     , "BoundSdf circle (vec2 position, float radius) {"
+                     , "    return bound_sdf(length(position) - radius);"
+                     , "}"
+                     , &Origin::CanvasShape("shape_0".into()).marker()
+                     , "Shape shape_0 (Env env, vec2 position) {"
+                     ].join("\n");
+        assert_eq!(find_origin(&source,1), (Origin::Generated,1));
+        assert_eq!(find_origin(&source,4), (Origin::SdfPrimitive("circle".into()),2));
+        assert_eq!(find_origin(&source,7), (Origin::CanvasShape("shape_0".into()),1));
+    }
+
+    #[test]
+    fn preview_does_not_underflow() {
+        let source  = (1..=20).map(|i| format!("line{}",i)).collect_vec().join("\n");
+        let preview = preview(&source,2);
+        assert_eq!(preview.lines().count(), 7);
+        assert!(preview.starts_with("   1 : line1"));
+        assert!(preview.contains(">  2 : line2"));
+        let preview = super::preview(&source,20);
+        assert!(preview.ends_with("> 20 : line20"));
+    }
+
+    #[test]
+    fn diagnose_maps_log_to_origins() {
+        let source = [ "#version 300 es"
+                     , &Origin::SurfaceMaterial.marker()
+                     , "void main() {"
+                     , "    output_color = foo;"
+                     , "}"
+                     ].join("\n");
+        let log         = "ERROR: 0:4: 'foo' : undeclared identifier";
+        let diagnostics = diagnose(Stage::Fragment,&source,log);
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic  = &diagnostics[0];
+        assert_eq!(diagnostic.origin, Some(Origin::SurfaceMaterial));
+        assert_eq!(diagnostic.origin_line, Some(2));
+        let message = diagnostic.to_string();
+        assert!(message.starts_with("fragment shader error at line 4 in surface material (line 2)"));
+    }
+}
@@ -14,6 +14,14 @@ use nalgebra::*;
 use shapely::derive_clone_plus;
 
 
+#[warn(missing_docs)]
+pub mod lexer;
+#[warn(missing_docs)]
+pub mod parser;
+#[warn(missing_docs)]
+pub mod preprocessor;
+
+
 
 // =================================================================================================
 // === Glsl ========================================================================================
@@ -162,7 +170,9 @@ macro_rules! mk_expr_unboxed { ($($variant:ident),*) => {
     }
 };}
 
-mk_expr_unboxed!(RawCode,Identifier,Block,Assignment);
+mk_expr_unboxed!(RawCode,Identifier,Block,Assignment,Literal,Call,Parens,UnaryOp,BinaryOp,
+    Conditional,FieldAccess,IndexAccess,Declaration,ExprStatement,Return,Jump,Scope,If,Loop,Switch,
+    Case);
 
 impl From<&String> for ExprUnboxed {
     fn from(t: &String) -> Self {
@@ -286,6 +296,438 @@ impl HasCodeRepr for Assignment {
 
 
 
+// ===============
+// === Literal ===
+// ===============
+
+/// Literal value, like `1`, `2.5` or `true`.
+#[derive(Clone,Debug)]
+pub struct Literal {
+    pub str: String
+}
+
+impl HasCodeRepr for Literal {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add(&self.str);
+    }
+}
+
+
+
+// ============
+// === Call ===
+// ============
+
+/// Function or constructor call (`f(a,b)`).
+#[derive(Clone,Debug)]
+pub struct Call {
+    pub func : Identifier,
+    pub args : Vec<Expr>,
+}
+
+impl HasCodeRepr for Call {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add(&self.func);
+        builder.write("(");
+        builder.spaced = true;
+        for (index,arg) in self.args.iter().enumerate() {
+            if index > 0 {
+                builder.write(",");
+            }
+            builder.add(arg);
+        }
+        builder.write(")");
+        builder.spaced = false;
+    }
+}
+
+
+
+// ==============
+// === Parens ===
+// ==============
+
+/// Parenthesized expression (`(a)`). Parentheses are kept in the tree, so the printed code groups
+/// operations exactly as the parsed one.
+#[derive(Clone,Debug)]
+pub struct Parens {
+    pub expr: Expr
+}
+
+impl HasCodeRepr for Parens {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add_spaced("(");
+        builder.add(&self.expr);
+        builder.write(")");
+    }
+}
+
+
+
+// ===============
+// === UnaryOp ===
+// ===============
+
+/// Prefix or postfix unary operation (`-a`, `!a`, `a++`).
+#[derive(Clone,Debug)]
+pub struct UnaryOp {
+    pub op      : String,
+    pub expr    : Expr,
+    pub postfix : bool,
+}
+
+impl HasCodeRepr for UnaryOp {
+    fn build(&self, builder:&mut CodeBuilder) {
+        if self.postfix {
+            builder.add(&self.expr);
+            builder.write(&self.op);
+        } else {
+            builder.add(&self.op);
+            // Nested prefix operations are separated, so `- -a` is not printed as `--a`.
+            builder.spaced = match &*self.expr.0 {
+                ExprUnboxed::UnaryOp(t) => t.postfix,
+                _                       => true,
+            };
+            builder.add(&self.expr);
+        }
+    }
+}
+
+
+
+// ================
+// === BinaryOp ===
+// ================
+
+/// Binary operation, including assignments and the comma operator (`a + b`, `a *= b`, `a, b`).
+#[derive(Clone,Debug)]
+pub struct BinaryOp {
+    pub op    : String,
+    pub left  : Expr,
+    pub right : Expr,
+}
+
+impl HasCodeRepr for BinaryOp {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add(&self.left);
+        if self.op == "," {
+            builder.write(",");
+            builder.spaced = false;
+        } else {
+            builder.add(&self.op);
+        }
+        builder.add(&self.right);
+    }
+}
+
+
+
+// ===================
+// === Conditional ===
+// ===================
+
+/// Conditional expression (`cond ? a : b`).
+#[derive(Clone,Debug)]
+pub struct Conditional {
+    pub cond     : Expr,
+    pub if_true  : Expr,
+    pub if_false : Expr,
+}
+
+impl HasCodeRepr for Conditional {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add(&self.cond).add("?").add(&self.if_true).add(":").add(&self.if_false);
+    }
+}
+
+
+
+// ===================
+// === FieldAccess ===
+// ===================
+
+/// Struct field access or vector swizzle (`a.field`, `v.xy`).
+#[derive(Clone,Debug)]
+pub struct FieldAccess {
+    pub expr  : Expr,
+    pub field : Identifier,
+}
+
+impl HasCodeRepr for FieldAccess {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add(&self.expr);
+        builder.write(".");
+        builder.spaced = true;
+        builder.add(&self.field);
+    }
+}
+
+
+
+// ===================
+// === IndexAccess ===
+// ===================
+
+/// Array, vector or matrix indexing (`a[i]`).
+#[derive(Clone,Debug)]
+pub struct IndexAccess {
+    pub expr  : Expr,
+    pub index : Expr,
+}
+
+impl HasCodeRepr for IndexAccess {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add(&self.expr);
+        builder.write("[");
+        builder.spaced = true;
+        builder.add(&self.index);
+        builder.write("]");
+    }
+}
+
+
+
+// ===================
+// === Declaration ===
+// ===================
+
+/// Variable declaration with an optional initializer (`const float a = 1.0;`). Used for local
+/// variables and for global variables without storage qualifiers.
+#[derive(Clone,Debug)]
+pub struct Declaration {
+    pub constant : bool,
+    pub prec     : Option<Precision>,
+    pub typ      : Type,
+    pub ident    : Identifier,
+    pub init     : Option<Expr>,
+}
+
+impl HasCodeRepr for Declaration {
+    fn build(&self, builder:&mut CodeBuilder) {
+        if self.constant {
+            builder.add("const");
+        }
+        builder.add(&self.prec).add(&self.typ).add(&self.ident);
+        if let Some(init) = &self.init {
+            builder.add("=").add(init);
+        }
+        builder.terminator();
+    }
+}
+
+
+
+// =====================
+// === ExprStatement ===
+// =====================
+
+/// Expression evaluated for its side effects (`f(a);`, `i++;`).
+#[derive(Clone,Debug)]
+pub struct ExprStatement {
+    pub expr: Expr
+}
+
+impl HasCodeRepr for ExprStatement {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add(&self.expr);
+        builder.terminator();
+    }
+}
+
+
+
+// ==============
+// === Return ===
+// ==============
+
+/// Return statement (`return a;`).
+#[derive(Clone,Debug)]
+pub struct Return {
+    pub value: Option<Expr>
+}
+
+impl HasCodeRepr for Return {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add("return").add(&self.value);
+        builder.terminator();
+    }
+}
+
+
+
+// ============
+// === Jump ===
+// ============
+
+/// One of the `break`, `continue` and `discard` statements.
+#[derive(Clone,Debug)]
+pub struct Jump {
+    pub keyword: String
+}
+
+impl HasCodeRepr for Jump {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add(&self.keyword);
+        builder.terminator();
+    }
+}
+
+
+
+// =============
+// === Scope ===
+// =============
+
+/// Block of statements in braces, introducing a new scope of variables.
+#[derive(Clone,Debug,Default)]
+pub struct Scope {
+    pub body: Block
+}
+
+impl HasCodeRepr for Scope {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add("{");
+        builder.inc_indent();
+        builder.add(&self.body);
+        builder.dec_indent();
+        builder.newline();
+        builder.add("}");
+    }
+}
+
+
+
+// ==========
+// === If ===
+// ==========
+
+/// Conditional statement. Chains of `else if` are represented as nested statements.
+#[derive(Clone,Debug)]
+pub struct If {
+    pub cond      : Expr,
+    pub then      : Scope,
+    pub otherwise : Option<Scope>,
+}
+
+impl HasCodeRepr for If {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add("if");
+        builder.add_spaced("(");
+        builder.add(&self.cond);
+        builder.write(")");
+        builder.add(&self.then);
+        if let Some(otherwise) = &self.otherwise {
+            builder.add("else");
+            let else_if = match otherwise.body.exprs.as_slice() {
+                [expr] => match &*expr.0 {
+                    ExprUnboxed::If(_) => Some(expr),
+                    _                  => None,
+                },
+                _ => None,
+            };
+            match else_if {
+                Some(expr) => builder.add(expr),
+                None       => builder.add(otherwise),
+            };
+        }
+    }
+}
+
+
+
+// ============
+// === Loop ===
+// ============
+
+/// One of the `for`, `while` and `do-while` loops.
+#[derive(Clone,Debug)]
+pub enum Loop {
+    /// Loop `for (init; cond; step)`. The `init` is a declaration or an expression statement.
+    For {init:Option<Expr>, cond:Option<Expr>, step:Option<Expr>, body:Scope},
+    While {cond:Expr, body:Scope},
+    DoWhile {body:Scope, cond:Expr},
+}
+
+impl HasCodeRepr for Loop {
+    fn build(&self, builder:&mut CodeBuilder) {
+        match self {
+            Self::For {init,cond,step,body} => {
+                builder.add("for");
+                builder.add_spaced("(");
+                match init {
+                    Some(init) => {
+                        builder.add(init);
+                    }
+                    None => {
+                        builder.write(";");
+                        builder.spaced = false;
+                    }
+                }
+                builder.add(cond);
+                builder.write(";");
+                builder.add(step);
+                builder.write(")");
+                builder.add(body);
+            }
+            Self::While {cond,body} => {
+                builder.add("while");
+                builder.add_spaced("(");
+                builder.add(cond);
+                builder.write(")");
+                builder.add(body);
+            }
+            Self::DoWhile {body,cond} => {
+                builder.add("do").add(body).add("while");
+                builder.add_spaced("(");
+                builder.add(cond);
+                builder.write(")");
+                builder.terminator();
+            }
+        }
+    }
+}
+
+
+
+// ==============
+// === Switch ===
+// ==============
+
+/// Switch statement. The `case` labels are statements of the body.
+#[derive(Clone,Debug)]
+pub struct Switch {
+    pub expr : Expr,
+    pub body : Scope,
+}
+
+impl HasCodeRepr for Switch {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add("switch");
+        builder.add_spaced("(");
+        builder.add(&self.expr);
+        builder.write(")");
+        builder.add(&self.body);
+    }
+}
+
+/// The `case value:` label, or the `default:` one if the value is `None`.
+#[derive(Clone,Debug)]
+pub struct Case {
+    pub value: Option<Expr>
+}
+
+impl HasCodeRepr for Case {
+    fn build(&self, builder:&mut CodeBuilder) {
+        match &self.value {
+            Some(value) => builder.add("case").add(value),
+            None        => builder.add("default"),
+        };
+        builder.write(":");
+    }
+}
+
+
+
 // =================================================================================================
 // === Statement ===================================================================================
 // =================================================================================================
@@ -294,7 +736,10 @@ impl HasCodeRepr for Assignment {
 #[derive(Clone,Debug)]
 pub enum Statement {
     Function      (Function),
+    Prototype     (Prototype),
     PrecisionDecl (PrecisionDecl),
+    UniformBlock  (UniformBlock),
+    Declaration   (Declaration),
     Raw           (RawCode)
 }
 
@@ -302,18 +747,45 @@ impl HasCodeRepr for Statement {
     fn build(&self, builder:&mut CodeBuilder) {
         match self {
             Self::Function       (t) => builder.add(t),
+            Self::Prototype      (t) => builder.add(t),
             Self::PrecisionDecl  (t) => builder.add(t),
+            Self::UniformBlock   (t) => builder.add(t),
+            Self::Declaration    (t) => builder.add(t),
             Self::Raw            (t) => builder.add(t),
         };
     }
 }
 
+impl From<Function> for Statement {
+    fn from(t: Function) -> Self {
+        Self::Function(t)
+    }
+}
+
+impl From<Prototype> for Statement {
+    fn from(t: Prototype) -> Self {
+        Self::Prototype(t)
+    }
+}
+
+impl From<Declaration> for Statement {
+    fn from(t: Declaration) -> Self {
+        Self::Declaration(t)
+    }
+}
+
 impl From<PrecisionDecl> for Statement {
     fn from(t: PrecisionDecl) -> Self {
         Self::PrecisionDecl(t)
     }
 }
 
+impl From<UniformBlock> for Statement {
+    fn from(t: UniformBlock) -> Self {
+        Self::UniformBlock(t)
+    }
+}
+
 
 
 // ================
@@ -323,14 +795,17 @@ impl From<PrecisionDecl> for Statement {
 /// Top-level function declaration.
 #[derive(Clone,Debug)]
 pub struct Function {
-    pub typ   : Type,
-    pub ident : Identifier,
-    pub body  : Block
+    pub prec   : Option<Precision>,
+    pub typ    : Type,
+    pub ident  : Identifier,
+    pub params : Vec<Param>,
+    pub body   : Block
 }
 
 impl HasCodeRepr for Function {
     fn build(&self, builder:&mut CodeBuilder) {
-        builder.add(&self.typ).add(&self.ident).add("() {");
+        build_signature(builder,&self.prec,&self.typ,&self.ident,&self.params);
+        builder.add("{");
         builder.inc_indent();
         builder.add(&self.body);
         builder.dec_indent();
@@ -346,6 +821,135 @@ impl<T:Into<Expr>> Add<T> for Function {
     }
 }
 
+/// Builds the function return type, name and parameters, shared by functions and prototypes.
+fn build_signature
+(builder:&mut CodeBuilder, prec:&Option<Precision>, typ:&Type, ident:&Identifier, params:&[Param]) {
+    builder.add(prec).add(typ).add(ident);
+    builder.write("(");
+    builder.spaced = true;
+    for (index,param) in params.iter().enumerate() {
+        if index > 0 {
+            builder.write(",");
+        }
+        builder.add(param);
+    }
+    builder.write(")");
+    builder.spaced = false;
+}
+
+
+// === Prototype ===
+
+/// Top-level function prototype, declaring a function defined later (`float f(float a);`).
+#[derive(Clone,Debug)]
+pub struct Prototype {
+    pub prec   : Option<Precision>,
+    pub typ    : Type,
+    pub ident  : Identifier,
+    pub params : Vec<Param>,
+}
+
+impl HasCodeRepr for Prototype {
+    fn build(&self, builder:&mut CodeBuilder) {
+        build_signature(builder,&self.prec,&self.typ,&self.ident,&self.params);
+        builder.terminator();
+    }
+}
+
+
+
+// === Param ===
+
+/// Function parameter declaration (`const in highp float a`).
+#[derive(Clone,Debug)]
+pub struct Param {
+    pub constant : bool,
+    pub storage  : Option<ParamStorage>,
+    pub prec     : Option<Precision>,
+    pub typ      : Type,
+    pub ident    : Identifier,
+}
+
+/// Parameter storage qualifier.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum ParamStorage {In, Out, InOut}
+
+impl HasCodeRepr for ParamStorage {
+    fn build(&self, builder:&mut CodeBuilder) {
+        match self {
+            Self::In    => builder.add("in"),
+            Self::Out   => builder.add("out"),
+            Self::InOut => builder.add("inout"),
+        };
+    }
+}
+
+impl HasCodeRepr for Param {
+    fn build(&self, builder:&mut CodeBuilder) {
+        if self.constant {
+            builder.add("const");
+        }
+        builder.add(&self.storage).add(&self.prec).add(&self.typ).add(&self.ident);
+    }
+}
+
+
+
+// ==================
+// === StructDecl ===
+// ==================
+
+/// Top-level declaration of a struct type.
+#[derive(Clone,Debug)]
+pub struct StructDecl {
+    pub ident  : Identifier,
+    pub fields : Vec<LocalVar>,
+}
+
+impl HasCodeRepr for StructDecl {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add("struct").add(&self.ident).add("{");
+        builder.inc_indent();
+        for field in &self.fields {
+            builder.newline();
+            builder.add(field);
+            builder.terminator();
+        }
+        builder.dec_indent();
+        builder.newline();
+        builder.add("};");
+    }
+}
+
+
+
+// ====================
+// === UniformBlock ===
+// ====================
+
+/// Top-level declaration of a uniform block using the `std140` layout. Blocks are declared without
+/// an instance name, so their members are accessible as global variables.
+#[derive(Clone,Debug)]
+pub struct UniformBlock {
+    pub ident   : Identifier,
+    pub members : Vec<LocalVar>,
+}
+
+impl HasCodeRepr for UniformBlock {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add("layout(std140) uniform").add(&self.ident).add("{");
+        builder.inc_indent();
+        for member in &self.members {
+            builder.newline();
+            builder.add(member);
+            builder.terminator();
+        }
+        builder.dec_indent();
+        builder.newline();
+        builder.add("};");
+    }
+}
+
 
 
 // =====================
@@ -389,6 +993,45 @@ impl HasCodeRepr for PrecisionDecl {
 
 
 
+// ==============
+// === Define ===
+// ==============
+
+/// Preprocessor macro definition, like `#define SHADOWS` or `#define SAMPLES 4`.
+#[derive(Clone,Debug,Eq,Hash,PartialEq)]
+pub struct Define {
+    pub name  : String,
+    pub value : Option<String>,
+}
+
+impl Define {
+    /// Constructor of a macro without a value.
+    pub fn new<Name:Str>(name:Name) -> Self {
+        let name  = name.into();
+        let value = None;
+        Self {name,value}
+    }
+
+    /// Constructor of a macro with a value.
+    pub fn with_value<Name:Str,Value:Str>(name:Name, value:Value) -> Self {
+        let name  = name.into();
+        let value = Some(value.into());
+        Self {name,value}
+    }
+}
+
+impl HasCodeRepr for Define {
+    fn build(&self, builder:&mut CodeBuilder) {
+        builder.add("#define");
+        builder.add(&self.name);
+        if let Some(value) = &self.value {
+            builder.add(value);
+        }
+    }
+}
+
+
+
 // =================================================================================================
 // === AST Elements ================================================================================
 // =================================================================================================
@@ -399,7 +1042,7 @@ impl HasCodeRepr for PrecisionDecl {
 // ============
 
 /// Abstraction for any GLSL type, including array types.
-#[derive(Clone,Debug)]
+#[derive(Clone,Debug,Eq,PartialEq)]
 pub struct Type {
     pub prim  : PrimType,
     pub array : Option<usize>
@@ -412,9 +1055,27 @@ impl From<PrimType> for Type {
     }
 }
 
+impl Type {
+    /// Constructor of an array type.
+    pub fn array<P:Into<PrimType>>(prim:P, len:usize) -> Self {
+        let prim  = prim.into();
+        let array = Some(len);
+        Self {prim,array}
+    }
+}
+
 impl HasCodeRepr for Type {
     fn build(&self, builder:&mut CodeBuilder) {
-        builder.add(&self.prim).add(&self.array);
+        builder.add(&self.prim);
+        if let Some(len) = self.array {
+            builder.write(format!("[{}]",len));
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f,"{}",self.to_code())
     }
 }
 
@@ -479,8 +1140,8 @@ impl HasCodeRepr for PrimType {
             Self::UVec2                => builder.add("uvec2"),
             Self::UVec3                => builder.add("uvec3"),
             Self::UVec4                => builder.add("uvec4"),
-            Self::Sampler2d            => builder.add("sampler2d"),
-            Self::Sampler3d            => builder.add("sampler3d"),
+            Self::Sampler2d            => builder.add("sampler2D"),
+            Self::Sampler3d            => builder.add("sampler3D"),
             Self::SamplerCube          => builder.add("samplerCube"),
             Self::Sampler2dShadow      => builder.add("sampler2DShadow"),
             Self::SamplerCubeShadow    => builder.add("samplerCubeShadow"),
@@ -499,6 +1160,80 @@ impl HasCodeRepr for PrimType {
     }
 }
 
+impl PrimType {
+    /// The type of the given name. Names which are not names of built-in types are treated as
+    /// names of structs.
+    pub fn from_name(name:&str) -> Self {
+        match name {
+            "float"                => Self::Float,
+            "int"                  => Self::Int,
+            "void"                 => Self::Void,
+            "bool"                 => Self::Bool,
+            "mat2"                 => Self::Mat2,
+            "mat3"                 => Self::Mat3,
+            "mat4"                 => Self::Mat4,
+            "mat2x2"               => Self::Mat2x2,
+            "mat2x3"               => Self::Mat2x3,
+            "mat2x4"               => Self::Mat2x4,
+            "mat3x2"               => Self::Mat3x2,
+            "mat3x3"               => Self::Mat3x3,
+            "mat3x4"               => Self::Mat3x4,
+            "mat4x2"               => Self::Mat4x2,
+            "mat4x3"               => Self::Mat4x3,
+            "mat4x4"               => Self::Mat4x4,
+            "vec2"                 => Self::Vec2,
+            "vec3"                 => Self::Vec3,
+            "vec4"                 => Self::Vec4,
+            "ivec2"                => Self::IVec2,
+            "ivec3"                => Self::IVec3,
+            "ivec4"                => Self::IVec4,
+            "bvec2"                => Self::BVec2,
+            "bvec3"                => Self::BVec3,
+            "bvec4"                => Self::BVec4,
+            "uint"                 => Self::UInt,
+            "uvec2"                => Self::UVec2,
+            "uvec3"                => Self::UVec3,
+            "uvec4"                => Self::UVec4,
+            "sampler2D"            => Self::Sampler2d,
+            "sampler3D"            => Self::Sampler3d,
+            "samplerCube"          => Self::SamplerCube,
+            "sampler2DShadow"      => Self::Sampler2dShadow,
+            "samplerCubeShadow"    => Self::SamplerCubeShadow,
+            "sampler2DArray"       => Self::Sampler2dArray,
+            "sampler2DArrayShadow" => Self::Sampler2dArrayShadow,
+            "isampler2D"           => Self::ISampler2d,
+            "isampler3D"           => Self::ISampler3d,
+            "isamplerCube"         => Self::ISamplerCube,
+            "isampler2DArray"      => Self::ISampler2dArray,
+            "usampler2D"           => Self::USampler2d,
+            "usampler3D"           => Self::USampler3d,
+            "usamplerCube"         => Self::USamplerCube,
+            "usampler2DArray"      => Self::USampler2dArray,
+            _                      => Self::Struct(name.into()),
+        }
+    }
+
+    /// Checks whether the type is a built-in one.
+    pub fn is_builtin(&self) -> bool {
+        match self {
+            Self::Struct(_) => false,
+            _               => true,
+        }
+    }
+
+    /// Checks whether the type is an opaque sampler type, which can be bound to textures only.
+    pub fn is_sampler(&self) -> bool {
+        match self {
+            Self::Sampler2d       | Self::Sampler3d         | Self::SamplerCube          |
+            Self::Sampler2dShadow | Self::SamplerCubeShadow | Self::Sampler2dArray       |
+            Self::ISampler2d      | Self::ISampler3d        | Self::Sampler2dArrayShadow |
+            Self::ISamplerCube    | Self::ISampler2dArray   | Self::USampler2d           |
+            Self::USampler3d      | Self::USamplerCube      | Self::USampler2dArray      => true,
+            _ => false
+        }
+    }
+}
+
 impl From<PrimType> for String {
     fn from(t:PrimType) -> Self {
         t.to_code()
@@ -575,8 +1310,8 @@ impl HasCodeRepr for InterpolationStorage {
 
 impl HasCodeRepr for LinkageStorage {
     fn build(&self, builder:&mut CodeBuilder) {
+        builder.add(&self.interpolation);
         if self.centroid { builder.add("centroid"); };
-
     }
 }
 
@@ -585,15 +1320,16 @@ impl HasCodeRepr for GlobalVarStorage {
         match self {
             Self::ConstStorage        => builder.add("const"),
             Self::UniformStorage      => builder.add("uniform"),
-            Self::InStorage    (qual) => builder.add("in").add(qual),
-            Self::OutStorage   (qual) => builder.add("out").add(qual),
+            Self::InStorage    (qual) => builder.add(qual).add("in"),
+            Self::OutStorage   (qual) => builder.add(qual).add("out"),
         };
     }
 }
 
 impl HasCodeRepr for GlobalVar {
     fn build(&self, builder:&mut CodeBuilder) {
-        builder.add(&self.layout).add(&self.storage).add(&self.typ).add(&self.ident);
+        builder.add(&self.layout).add(&self.storage).add(&self.prec).add(&self.typ);
+        builder.add(&self.ident);
     }
 }
 
@@ -667,23 +1403,29 @@ impl From<&Precision> for Precision {
 /// Translation unit definition. It represents the whole GLSL file.
 #[derive(Clone,Debug)]
 pub struct Module {
-    pub prec_decls  : Vec<PrecisionDecl>,
-    pub global_vars : Vec<GlobalVar>,
-    pub statements  : Vec<Statement>,
-    pub main        : Function
+    pub defines      : Vec<Define>,
+    pub prec_decls   : Vec<PrecisionDecl>,
+    pub struct_decls : Vec<StructDecl>,
+    pub global_vars  : Vec<GlobalVar>,
+    pub statements   : Vec<Statement>,
+    pub main         : Function
 }
 
 impl Default for Module {
     fn default() -> Self {
-        let prec_decls  = default();
-        let global_vars = default();
-        let statements  = default();
-        let main        = Function {
-            typ   : PrimType::Void.into(),
-            ident : "main".into(),
-            body  : default()
+        let defines      = default();
+        let prec_decls   = default();
+        let struct_decls = default();
+        let global_vars  = default();
+        let statements   = default();
+        let main         = Function {
+            prec   : None,
+            typ    : PrimType::Void.into(),
+            ident  : "main".into(),
+            params : default(),
+            body   : default()
         };
-        Self {prec_decls,global_vars,statements,main}
+        Self {defines,prec_decls,struct_decls,global_vars,statements,main}
     }
 }
 
@@ -701,6 +1443,13 @@ impl Add<Statement> for Module {
     }
 }
 
+impl Add<Define> for Module {
+    type Result = ();
+    fn add(&mut self, t: Define) {
+        self.defines.push(t);
+    }
+}
+
 impl Add<PrecisionDecl> for Module {
     type Result = ();
     fn add(&mut self, t: PrecisionDecl) {
@@ -708,6 +1457,20 @@ impl Add<PrecisionDecl> for Module {
     }
 }
 
+impl Add<StructDecl> for Module {
+    type Result = ();
+    fn add(&mut self, t: StructDecl) {
+        self.struct_decls.push(t);
+    }
+}
+
+impl Add<UniformBlock> for Module {
+    type Result = ();
+    fn add(&mut self, t: UniformBlock) {
+        self.statements.push(t.into());
+    }
+}
+
 impl Add<Expr> for Module {
     type Result = ();
     fn add(&mut self, t: Expr) {
@@ -719,6 +1482,10 @@ impl HasCodeRepr for Module {
     fn build(&self, builder:&mut CodeBuilder) {
         builder.add("#version 300 es");
         builder.newline();
+        for t in &self.defines {
+            builder.add(t);
+            builder.newline();
+        }
         builder.newline();
 
         for t in &self.prec_decls {
@@ -727,6 +1494,14 @@ impl HasCodeRepr for Module {
         }
         builder.newline();
 
+        for t in &self.struct_decls {
+            builder.add(t);
+            builder.newline();
+        }
+        if !self.struct_decls.is_empty() {
+            builder.newline();
+        }
+
         for t in &self.global_vars {
             builder.add(t);
             builder.terminator();
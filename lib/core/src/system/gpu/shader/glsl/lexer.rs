@@ -0,0 +1,303 @@
+//! This module defines the GLSL lexer. It splits the source code into logical lines of tokens, so
+//! the preprocessor can recognize its directives. Line continuations are spliced and comments are
+//! skipped, but every token remembers the line it was written in, so errors can point to it.
+
+use crate::prelude::*;
+
+use super::parser::Error;
+
+
+
+// =============
+// === Token ===
+// =============
+
+/// The kind of a token.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum TokenKind {
+    /// Identifier or keyword.
+    Ident,
+    /// Integer literal, like `1`, `0xff` or `2u`.
+    Int,
+    /// Floating point literal, like `1.0`, `.5` or `1e10`.
+    Float,
+    /// Operator or punctuation mark, like `+=` or `;`.
+    Punct,
+}
+
+/// A single token of the GLSL source.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct Token {
+    /// The kind of the token.
+    pub kind   : TokenKind,
+    /// The source code of the token.
+    pub text   : String,
+    /// The line of the source the token was written in, starting from 1.
+    pub line   : usize,
+    /// Whether the token was preceded by a whitespace or a comment.
+    pub spaced : bool,
+}
+
+impl Token {
+    /// Constructor.
+    pub fn new<Text:Str>(kind:TokenKind, text:Text, line:usize) -> Self {
+        let text   = text.into();
+        let spaced = true;
+        Self {kind,text,line,spaced}
+    }
+
+    /// Checks whether this token is the given punctuation mark or keyword.
+    pub fn is(&self, text:&str) -> bool {
+        self.text == text
+    }
+
+    /// Checks whether this token is an identifier.
+    pub fn is_ident(&self) -> bool {
+        self.kind == TokenKind::Ident
+    }
+
+    /// The value of an integer literal. Literals starting with `0x` are hexadecimal, and other
+    /// literals starting with `0` are octal.
+    pub fn int_value(&self) -> Option<i64> {
+        if self.kind != TokenKind::Int { return None }
+        let text  = self.text.trim_end_matches(|c| c == 'u' || c == 'U');
+        let value = if text.starts_with("0x") {
+            i64::from_str_radix(&text[2..],16)
+        } else if text.starts_with('0') && text.len() > 1 {
+            i64::from_str_radix(&text[1..],8)
+        } else {
+            text.parse()
+        };
+        value.ok()
+    }
+}
+
+
+
+// =============
+// === Lexer ===
+// =============
+
+/// Punctuation marks, the longest ones first, so they are matched greedily.
+const PUNCTUATION : &[&str] =
+    &[ "<<=", ">>="
+     , "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "^^", "+=", "-=", "*=", "/="
+     , "%=", "&=", "^=", "|=", "##"
+     , "(", ")", "[", "]", "{", "}", ".", ",", "+", "-", "!", "~", "*", "/", "%", "<", ">", "&"
+     , "^", "|", "?", ":", "=", ";", "#"
+     ];
+
+/// Splits the source into logical lines of tokens. Lines ended with a backslash are joined with
+/// the following ones, and lines without any tokens are skipped.
+pub fn lex(source:&str) -> Result<Vec<Vec<Token>>,Error> {
+    Lexer::new(source).run()
+}
+
+/// The lexer state. The source is stored after line continuations are spliced, together with the
+/// original line of every character.
+#[derive(Debug)]
+struct Lexer {
+    chars  : Vec<char>,
+    lines  : Vec<usize>,
+    offset : usize,
+}
+
+impl Lexer {
+    fn new(source:&str) -> Self {
+        let mut chars  = Vec::new();
+        let mut lines  = Vec::new();
+        let mut line   = 1;
+        let mut source = source.chars().peekable();
+        while let Some(char) = source.next() {
+            if char == '\\' {
+                if source.peek() == Some(&'\r') {
+                    source.next();
+                }
+                if source.peek() == Some(&'\n') {
+                    source.next();
+                    line += 1;
+                    continue
+                }
+            }
+            chars.push(char);
+            lines.push(line);
+            if char == '\n' {
+                line += 1;
+            }
+        }
+        let offset = 0;
+        Self {chars,lines,offset}
+    }
+
+    fn peek(&self, distance:usize) -> Option<char> {
+        self.chars.get(self.offset + distance).copied()
+    }
+
+    fn line(&self) -> usize {
+        let last = self.lines.last().copied().unwrap_or(1);
+        self.lines.get(self.offset).copied().unwrap_or(last)
+    }
+
+    fn run(mut self) -> Result<Vec<Vec<Token>>,Error> {
+        let mut result = Vec::new();
+        let mut tokens = Vec::new();
+        let mut spaced = true;
+        while let Some(char) = self.peek(0) {
+            if char == '\n' {
+                self.offset += 1;
+                spaced       = true;
+                if !tokens.is_empty() {
+                    result.push(std::mem::take(&mut tokens));
+                }
+            } else if char.is_whitespace() {
+                self.offset += 1;
+                spaced       = true;
+            } else if char == '/' && self.peek(1) == Some('/') {
+                while self.peek(0).map(|char| char != '\n').unwrap_or(false) {
+                    self.offset += 1;
+                }
+            } else if char == '/' && self.peek(1) == Some('*') {
+                let line     = self.line();
+                self.offset += 2;
+                loop {
+                    match (self.peek(0),self.peek(1)) {
+                        (Some('*'),Some('/')) => break,
+                        (Some(_),_)           => self.offset += 1,
+                        (None,_)              => {
+                            let message = "unterminated comment".into();
+                            return Err(Error::Preprocessor {line,message})
+                        }
+                    }
+                }
+                self.offset += 2;
+                spaced       = true;
+            } else {
+                let mut token = self.token(char)?;
+                token.spaced  = spaced;
+                spaced        = false;
+                tokens.push(token);
+            }
+        }
+        if !tokens.is_empty() {
+            result.push(tokens);
+        }
+        Ok(result)
+    }
+
+    fn token(&mut self, char:char) -> Result<Token,Error> {
+        let line       = self.line();
+        let is_digit   = |char:Option<char>| char.map(|c| c.is_ascii_digit()).unwrap_or(false);
+        let is_number  = char.is_ascii_digit() || (char == '.' && is_digit(self.peek(1)));
+        if is_number {
+            Ok(self.number(line))
+        } else if char.is_ascii_alphabetic() || char == '_' {
+            let text = self.take_while(|char| char.is_ascii_alphanumeric() || char == '_');
+            Ok(Token::new(TokenKind::Ident,text,line))
+        } else {
+            for punct in PUNCTUATION {
+                let matches = punct.chars().enumerate().all(|(i,c)| self.peek(i) == Some(c));
+                if matches {
+                    self.offset += punct.len();
+                    return Ok(Token::new(TokenKind::Punct,*punct,line))
+                }
+            }
+            Err(Error::InvalidCharacter {line,character:char})
+        }
+    }
+
+    fn number(&mut self, line:usize) -> Token {
+        let is_x     = |char:Option<char>| char == Some('x') || char == Some('X');
+        let is_hex   = self.peek(0) == Some('0') && is_x(self.peek(1));
+        let mut text = String::new();
+        let mut kind = TokenKind::Int;
+        if is_hex {
+            self.offset += 2;
+            text.push_str("0x");
+            text.push_str(&self.take_while(|char| char.is_ascii_hexdigit()));
+        } else {
+            text.push_str(&self.take_while(|char| char.is_ascii_digit()));
+            if self.peek(0) == Some('.') {
+                self.offset += 1;
+                kind         = TokenKind::Float;
+                text.push('.');
+                text.push_str(&self.take_while(|char| char.is_ascii_digit()));
+            }
+            if self.peek(0) == Some('e') || self.peek(0) == Some('E') {
+                let sign       = self.peek(1) == Some('+') || self.peek(1) == Some('-');
+                let digit_at   = if sign { 2 } else { 1 };
+                let has_digits = self.peek(digit_at).map(|c| c.is_ascii_digit()).unwrap_or(false);
+                if has_digits {
+                    kind = TokenKind::Float;
+                    for _ in 0..digit_at {
+                        text.push(self.chars[self.offset]);
+                        self.offset += 1;
+                    }
+                    text.push_str(&self.take_while(|char| char.is_ascii_digit()));
+                }
+            }
+        }
+        let suffix = if kind == TokenKind::Int { ['u','U'] } else { ['f','F'] };
+        if let Some(char) = self.peek(0) {
+            if suffix.contains(&char) {
+                self.offset += 1;
+                text.push(char);
+            }
+        }
+        Token::new(kind,text,line)
+    }
+
+    fn take_while(&mut self, f:impl Fn(char)->bool) -> String {
+        let mut text = String::new();
+        while let Some(char) = self.peek(0) {
+            if !f(char) { break }
+            text.push(char);
+            self.offset += 1;
+        }
+        text
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(source:&str) -> Vec<Vec<String>> {
+        let lines = lex(source).unwrap();
+        lines.into_iter().map(|line| line.into_iter().map(|token| token.text).collect()).collect()
+    }
+
+    #[test]
+    fn numbers_and_punctuation() {
+        let source = "a<<=1.5e-3+.5*0x1Fu>>=1.;";
+        let tokens = lex(source).unwrap().remove(0);
+        let kinds  = tokens.iter().map(|token| token.kind).collect_vec();
+        let texts  = tokens.iter().map(|token| token.text.as_str()).collect_vec();
+        assert_eq!(texts,vec!["a","<<=","1.5e-3","+",".5","*","0x1Fu",">>=","1.",";"]);
+        assert_eq!(kinds[2],TokenKind::Float);
+        assert_eq!(kinds[6],TokenKind::Int);
+        assert_eq!(kinds[8],TokenKind::Float);
+    }
+
+    #[test]
+    fn comments_and_continuations() {
+        let source = "#define A(x) \\\n  x /* multi\nline */ + 1 // note\n\nb;";
+        let expected = vec![vec!["#","define","A","(","x",")","x","+","1"],vec!["b",";"]];
+        assert_eq!(texts(source),expected);
+        let lines = lex(source).unwrap();
+        assert_eq!(lines[0][6].line,2);
+        assert_eq!(lines[1][0].line,5);
+        assert!(!lines[0][3].spaced);
+        assert!( lines[0][6].spaced);
+    }
+
+    #[test]
+    fn invalid_character() {
+        assert_eq!(lex("a = $;"),Err(Error::InvalidCharacter {line:1,character:'$'}));
+    }
+}
@@ -0,0 +1,936 @@
+//! This module defines the GLSL ES 3.00 parser. It builds the AST defined in the parent module, so
+//! shader sources can be inspected, transformed, and printed back. Comments and macros are not
+//! preserved, as macros are expanded by the preprocessor before parsing.
+
+use crate::prelude::*;
+
+use crate::data::container::Add;
+use crate::system::gpu::shader::glsl::BinaryOp;
+use crate::system::gpu::shader::glsl::Block;
+use crate::system::gpu::shader::glsl::Call;
+use crate::system::gpu::shader::glsl::Case;
+use crate::system::gpu::shader::glsl::Conditional;
+use crate::system::gpu::shader::glsl::Declaration;
+use crate::system::gpu::shader::glsl::Expr;
+use crate::system::gpu::shader::glsl::ExprStatement;
+use crate::system::gpu::shader::glsl::FieldAccess;
+use crate::system::gpu::shader::glsl::Function;
+use crate::system::gpu::shader::glsl::GlobalVar;
+use crate::system::gpu::shader::glsl::GlobalVarStorage;
+use crate::system::gpu::shader::glsl::Identifier;
+use crate::system::gpu::shader::glsl::If;
+use crate::system::gpu::shader::glsl::IndexAccess;
+use crate::system::gpu::shader::glsl::InterpolationStorage;
+use crate::system::gpu::shader::glsl::Jump;
+use crate::system::gpu::shader::glsl::Layout;
+use crate::system::gpu::shader::glsl::LinkageStorage;
+use crate::system::gpu::shader::glsl::Literal;
+use crate::system::gpu::shader::glsl::LocalVar;
+use crate::system::gpu::shader::glsl::Loop;
+use crate::system::gpu::shader::glsl::Module;
+use crate::system::gpu::shader::glsl::Param;
+use crate::system::gpu::shader::glsl::ParamStorage;
+use crate::system::gpu::shader::glsl::Parens;
+use crate::system::gpu::shader::glsl::Precision;
+use crate::system::gpu::shader::glsl::PrecisionDecl;
+use crate::system::gpu::shader::glsl::PrimType;
+use crate::system::gpu::shader::glsl::Prototype;
+use crate::system::gpu::shader::glsl::Return;
+use crate::system::gpu::shader::glsl::Scope;
+use crate::system::gpu::shader::glsl::Statement;
+use crate::system::gpu::shader::glsl::StructDecl;
+use crate::system::gpu::shader::glsl::Switch;
+use crate::system::gpu::shader::glsl::Type;
+use crate::system::gpu::shader::glsl::UnaryOp;
+use crate::system::gpu::shader::glsl::UniformBlock;
+use crate::system::gpu::shader::glsl::lexer::Token;
+use crate::system::gpu::shader::glsl::lexer::TokenKind;
+use crate::system::gpu::shader::glsl::preprocessor::Preprocessor;
+
+
+
+// =============
+// === Error ===
+// =============
+
+/// Errors of preprocessing and parsing GLSL sources.
+#[derive(Clone,Debug,Fail,PartialEq)]
+#[allow(missing_docs)]
+pub enum Error {
+    #[fail(display="Line {}: expected {}, found {}.",line,expected,found)]
+    Unexpected {line:usize, found:String, expected:String},
+    #[fail(display="Line {}: {}.",line,message)]
+    Preprocessor {line:usize, message:String},
+    #[fail(display="Line {}: invalid character `{}`.",line,character)]
+    InvalidCharacter {line:usize, character:char},
+    #[fail(display="Line {}: {} is not supported.",line,feature)]
+    Unsupported {line:usize, feature:String},
+}
+
+
+
+// ===========
+// === API ===
+// ===========
+
+/// Parses a whole shader. Top-level declarations are grouped the way the `Module` is printed, so
+/// the printed code may order them differently than the source. A module without the `main`
+/// function gets an empty one.
+pub fn parse_module(source:&str) -> Result<Module,Error> {
+    Parser::from_source(source)?.module()
+}
+
+/// Parses a sequence of statements, like a body of a function.
+pub fn parse_block(source:&str) -> Result<Block,Error> {
+    Parser::from_source(source)?.block()
+}
+
+/// Parses a single expression.
+pub fn parse_expr(source:&str) -> Result<Expr,Error> {
+    Parser::from_source(source)?.expression()
+}
+
+
+
+// ==============
+// === Parser ===
+// ==============
+
+/// Binary operators and their precedences. Assignments, conditionals and the comma operator are
+/// handled separately, as they are right-associative or have the lowest precedence.
+fn binary_precedence(op:&str) -> Option<usize> {
+    match op {
+        "||"                    => Some(1),
+        "^^"                    => Some(2),
+        "&&"                    => Some(3),
+        "|"                     => Some(4),
+        "^"                     => Some(5),
+        "&"                     => Some(6),
+        "==" | "!="             => Some(7),
+        "<"  | ">" | "<=" | ">=" => Some(8),
+        "<<" | ">>"             => Some(9),
+        "+"  | "-"              => Some(10),
+        "*"  | "/" | "%"        => Some(11),
+        _                       => None,
+    }
+}
+
+const ASSIGNMENT_OPS : &[&str] = &["=","+=","-=","*=","/=","%=","<<=",">>=","&=","^=","|="];
+const PREFIX_OPS     : &[&str] = &["++","--","+","-","!","~"];
+
+/// Recursive descent parser of preprocessed GLSL tokens.
+#[derive(Clone,Debug)]
+pub struct Parser {
+    tokens : Vec<Token>,
+    offset : usize,
+}
+
+impl Parser {
+    /// Constructor.
+    pub fn new(tokens:Vec<Token>) -> Self {
+        let offset = 0;
+        Self {tokens,offset}
+    }
+
+    /// Constructor. Preprocesses the source with the default preprocessor.
+    pub fn from_source(source:&str) -> Result<Self,Error> {
+        Ok(Self::new(Preprocessor::new().run(source)?))
+    }
+
+    /// Parses all tokens as a module.
+    pub fn module(mut self) -> Result<Module,Error> {
+        let mut module = Module::default();
+        while let Some(keyword) = self.peek().map(|token| token.text.clone()) {
+            match keyword.as_str() {
+                ";"         => self.offset += 1,
+                "precision" => module.add(self.precision_decl()?),
+                "struct"    => module.add(self.struct_decl()?),
+                "layout" | "in" | "out" | "uniform" | "smooth" | "flat" | "centroid" => {
+                    self.global(&mut module)?
+                }
+                "invariant" => return self.unsupported("the `invariant` qualifier"),
+                _           => self.function_or_declaration(&mut module)?,
+            }
+        }
+        Ok(module)
+    }
+
+    /// Parses all tokens as a sequence of statements.
+    pub fn block(mut self) -> Result<Block,Error> {
+        let mut block = Block::default();
+        while !self.at_end() {
+            self.statement(&mut block)?;
+        }
+        Ok(block)
+    }
+
+    /// Parses all tokens as a single expression.
+    pub fn expression(mut self) -> Result<Expr,Error> {
+        let expr = self.expr()?;
+        if !self.at_end() {
+            return self.unexpected("the end of the input")
+        }
+        Ok(expr)
+    }
+}
+
+
+// === Helpers ===
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.offset)
+    }
+
+    fn peek_at(&self, distance:usize) -> Option<&Token> {
+        self.tokens.get(self.offset + distance)
+    }
+
+    fn peek_is(&self, text:&str) -> bool {
+        self.peek_is_at(0,text)
+    }
+
+    fn peek_is_at(&self, distance:usize, text:&str) -> bool {
+        self.peek_at(distance).map(|token| token.is(text)).unwrap_or(false)
+    }
+
+    fn at_end(&self) -> bool {
+        self.offset >= self.tokens.len()
+    }
+
+    fn line(&self) -> usize {
+        let token = self.peek().or_else(|| self.tokens.last());
+        token.map(|token| token.line).unwrap_or(1)
+    }
+
+    fn eat(&mut self, text:&str) -> bool {
+        let matches = self.peek_is(text);
+        if matches {
+            self.offset += 1;
+        }
+        matches
+    }
+
+    /// Consumes the next token if it is one of the given punctuation marks or keywords.
+    fn eat_any(&mut self, texts:&[&str]) -> Option<String> {
+        let text = self.peek().map(|token| token.text.clone())?;
+        if !texts.contains(&text.as_str()) {
+            return None
+        }
+        self.offset += 1;
+        Some(text)
+    }
+
+    fn expect(&mut self, text:&str) -> Result<(),Error> {
+        if self.eat(text) { Ok(()) } else { self.unexpected(&format!("`{}`",text)) }
+    }
+
+    fn unexpected<T>(&self, expected:&str) -> Result<T,Error> {
+        let line     = self.line();
+        let found    = self.peek().map(|token| format!("`{}`",token.text));
+        let found    = found.unwrap_or_else(|| "the end of the input".into());
+        let expected = expected.into();
+        Err(Error::Unexpected {line,found,expected})
+    }
+
+    fn unsupported<T>(&self, feature:&str) -> Result<T,Error> {
+        let line    = self.line();
+        let feature = feature.into();
+        Err(Error::Unsupported {line,feature})
+    }
+
+    fn ident(&mut self) -> Result<Identifier,Error> {
+        match self.peek() {
+            Some(token) if token.is_ident() => {
+                let ident = token.text.clone().into();
+                self.offset += 1;
+                Ok(ident)
+            }
+            _ => self.unexpected("an identifier"),
+        }
+    }
+
+    fn int(&mut self) -> Result<usize,Error> {
+        match self.peek().and_then(|token| token.int_value()) {
+            Some(value) => {
+                self.offset += 1;
+                Ok(value as usize)
+            }
+            None => self.unexpected("an integer literal"),
+        }
+    }
+}
+
+
+// === Types and Qualifiers ===
+
+impl Parser {
+    fn precision(&mut self) -> Option<Precision> {
+        let precision = match self.peek()?.text.as_str() {
+            "lowp"    => Precision::Low,
+            "mediump" => Precision::Medium,
+            "highp"   => Precision::High,
+            _         => return None,
+        };
+        self.offset += 1;
+        Some(precision)
+    }
+
+    fn typ(&mut self) -> Result<Type,Error> {
+        let prim = PrimType::from_name(&self.ident()?.0);
+        let typ  = prim.into();
+        self.array_suffix(typ)
+    }
+
+    /// Parses the optional array size, like in `float[4] a` or `float a[4]`.
+    fn array_suffix(&mut self, typ:Type) -> Result<Type,Error> {
+        if !self.eat("[") {
+            return Ok(typ)
+        }
+        if typ.array.is_some() {
+            return self.unsupported("arrays of arrays")
+        }
+        let is_int = self.peek().map(|token| token.kind == TokenKind::Int).unwrap_or(false);
+        if !is_int {
+            return self.unsupported("array sizes other than integer literals")
+        }
+        let len = self.int()?;
+        self.expect("]")?;
+        Ok(Type {array:Some(len),..typ})
+    }
+
+    fn is_declaration(&self) -> bool {
+        match self.peek() {
+            Some(token) if token.is_ident() => match token.text.as_str() {
+                "const" | "lowp" | "mediump" | "highp" => true,
+                name if PrimType::from_name(name).is_builtin() => !self.peek_is_at(1,"("),
+                _ => self.peek_at(1).map(|token| token.is_ident()).unwrap_or(false),
+            }
+            _ => false,
+        }
+    }
+
+    /// Parses a declaration of variables, like `const float a = 1.0, b[2];`. Every variable is
+    /// declared separately.
+    fn declaration(&mut self) -> Result<Vec<Declaration>,Error> {
+        let constant = self.eat("const");
+        let prec     = self.precision();
+        let typ      = self.typ()?;
+        let ident    = self.ident()?;
+        self.declarators(constant,prec,typ,ident)
+    }
+
+    fn declarators
+    (&mut self, constant:bool, prec:Option<Precision>, typ:Type, ident:Identifier)
+    -> Result<Vec<Declaration>,Error> {
+        let mut declarations = Vec::new();
+        let mut ident        = ident;
+        loop {
+            let typ  = self.array_suffix(typ.clone())?;
+            let init = if self.eat("=") { Some(self.assignment()?) } else { None };
+            let prec = prec.clone();
+            declarations.push(Declaration {constant,prec,typ,ident,init});
+            if self.eat(";") { break }
+            if !self.eat(",") { return self.unexpected("`;`") }
+            ident = self.ident()?;
+        }
+        Ok(declarations)
+    }
+
+    /// Parses members of structs and uniform blocks, until the closing brace.
+    fn members(&mut self) -> Result<Vec<LocalVar>,Error> {
+        self.expect("{")?;
+        let mut members = Vec::new();
+        while !self.eat("}") {
+            if self.precision().is_some() {
+                return self.unsupported("precision qualifiers of struct members")
+            }
+            let typ = self.typ()?;
+            loop {
+                let ident = self.ident()?;
+                let typ   = self.array_suffix(typ.clone())?;
+                members.push(LocalVar {constant:false,typ,ident});
+                if self.eat(";") { break }
+                if !self.eat(",") { return self.unexpected("`;`") }
+            }
+        }
+        Ok(members)
+    }
+}
+
+
+// === Top-Level Declarations ===
+
+impl Parser {
+    fn precision_decl(&mut self) -> Result<PrecisionDecl,Error> {
+        self.expect("precision")?;
+        let prec = match self.precision() {
+            Some(prec) => prec,
+            None       => return self.unexpected("a precision qualifier"),
+        };
+        let typ = self.typ()?;
+        self.expect(";")?;
+        Ok(PrecisionDecl {prec,typ})
+    }
+
+    fn struct_decl(&mut self) -> Result<StructDecl,Error> {
+        self.expect("struct")?;
+        let ident  = self.ident()?;
+        let fields = self.members()?;
+        if !self.peek_is(";") {
+            return self.unsupported("declaring variables together with structs")
+        }
+        self.expect(";")?;
+        Ok(StructDecl {ident,fields})
+    }
+
+    /// Parses global variables with storage qualifiers and uniform blocks.
+    fn global(&mut self, module:&mut Module) -> Result<(),Error> {
+        let mut layout = None;
+        let mut std140 = false;
+        if self.eat("layout") {
+            self.expect("(")?;
+            loop {
+                let qualifier = self.ident()?;
+                match qualifier.0.as_str() {
+                    "location" => {
+                        self.expect("=")?;
+                        let location = self.int()?;
+                        layout = Some(Layout {location});
+                    }
+                    "std140" => std140 = true,
+                    other    => return self.unsupported(&format!("the `{}` layout",other)),
+                }
+                if self.eat(")") { break }
+                self.expect(",")?;
+            }
+        }
+        let interpolation = match self.eat_any(&["smooth","flat"]) {
+            Some(qualifier) if qualifier == "flat" => Some(InterpolationStorage::Flat),
+            Some(_)                                => Some(InterpolationStorage::Smooth),
+            None                                   => None,
+        };
+        let centroid = self.eat("centroid");
+        let linkage  = LinkageStorage {centroid,interpolation};
+        let storage  = match self.eat_any(&["in","out","uniform"]) {
+            Some(storage) if storage == "in"  => GlobalVarStorage::InStorage(linkage),
+            Some(storage) if storage == "out" => GlobalVarStorage::OutStorage(linkage),
+            Some(_)                           => GlobalVarStorage::UniformStorage,
+            None => return self.unexpected("`in`, `out` or `uniform`"),
+        };
+        let is_block = self.peek_at(1).map(|token| token.is("{")).unwrap_or(false);
+        if is_block {
+            if !std140 {
+                return self.unsupported("uniform blocks without the std140 layout")
+            }
+            let ident   = self.ident()?;
+            let members = self.members()?;
+            if !self.peek_is(";") {
+                return self.unsupported("named uniform blocks")
+            }
+            self.expect(";")?;
+            module.add(UniformBlock {ident,members});
+            return Ok(())
+        }
+        if std140 {
+            return self.unsupported("the std140 layout of variables")
+        }
+        let prec = self.precision();
+        let typ  = self.typ()?;
+        loop {
+            let ident   = self.ident()?;
+            let typ     = self.array_suffix(typ.clone())?;
+            let layout  = layout.clone();
+            let storage = Some(storage.clone());
+            let prec    = prec.clone();
+            module.add(GlobalVar {layout,storage,prec,typ,ident});
+            if self.eat(";") { break }
+            if !self.eat(",") { return self.unexpected("`;`") }
+        }
+        Ok(())
+    }
+
+    fn function_or_declaration(&mut self, module:&mut Module) -> Result<(),Error> {
+        let constant = self.eat("const");
+        let prec     = self.precision();
+        let typ      = self.typ()?;
+        let ident    = self.ident()?;
+        if !constant && self.peek_is("(") {
+            let params = self.params()?;
+            if self.eat(";") {
+                module.add(Statement::from(Prototype {prec,typ,ident,params}));
+            } else {
+                let body     = self.scope()?.body;
+                let function = Function {prec,typ,ident,params,body};
+                if function.ident.0 == "main" && function.params.is_empty() {
+                    module.main = function;
+                } else {
+                    module.add(Statement::from(function));
+                }
+            }
+        } else {
+            for declaration in self.declarators(constant,prec,typ,ident)? {
+                module.add(Statement::from(declaration));
+            }
+        }
+        Ok(())
+    }
+
+    fn params(&mut self) -> Result<Vec<Param>,Error> {
+        self.expect("(")?;
+        let mut params = Vec::new();
+        if self.peek_is("void") && self.peek_is_at(1,")") {
+            self.offset += 1;
+        }
+        if !self.eat(")") {
+            loop {
+                params.push(self.param()?);
+                if self.eat(")") { break }
+                if !self.eat(",") { return self.unexpected("`,` or `)`") }
+            }
+        }
+        Ok(params)
+    }
+
+    fn param(&mut self) -> Result<Param,Error> {
+        let constant = self.eat("const");
+        let storage  = self.eat_any(&["in","out","inout"]).map(|storage| match storage.as_str() {
+            "in"  => ParamStorage::In,
+            "out" => ParamStorage::Out,
+            _     => ParamStorage::InOut,
+        });
+        let prec  = self.precision();
+        let typ   = self.typ()?;
+        let ident = self.ident()?;
+        let typ   = self.array_suffix(typ)?;
+        Ok(Param {constant,storage,prec,typ,ident})
+    }
+}
+
+
+// === Statements ===
+
+impl Parser {
+    fn statement(&mut self, block:&mut Block) -> Result<(),Error> {
+        let keyword = match self.peek() {
+            Some(token) => token.text.clone(),
+            None        => return self.unexpected("a statement"),
+        };
+        match keyword.as_str() {
+            ";"      => self.offset += 1,
+            "{"      => block.add(self.scope()?),
+            "if"     => block.add(self.if_statement()?),
+            "for"    => block.add(self.for_loop()?),
+            "while"  => {
+                self.offset += 1;
+                let cond = self.parenthesized()?;
+                let body = self.statement_scope()?;
+                block.add(Loop::While {cond,body});
+            }
+            "do" => {
+                self.offset += 1;
+                let body = self.statement_scope()?;
+                self.expect("while")?;
+                let cond = self.parenthesized()?;
+                self.expect(";")?;
+                block.add(Loop::DoWhile {body,cond});
+            }
+            "switch" => {
+                self.offset += 1;
+                let expr = self.parenthesized()?;
+                let body = self.scope()?;
+                block.add(Switch {expr,body});
+            }
+            "case" => {
+                self.offset += 1;
+                let value = Some(self.expr()?);
+                self.expect(":")?;
+                block.add(Case {value});
+            }
+            "default" => {
+                self.offset += 1;
+                self.expect(":")?;
+                block.add(Case {value:None});
+            }
+            "return" => {
+                self.offset += 1;
+                let value = if self.peek_is(";") { None } else { Some(self.expr()?) };
+                self.expect(";")?;
+                block.add(Return {value});
+            }
+            "break" | "continue" | "discard" => {
+                self.offset += 1;
+                self.expect(";")?;
+                block.add(Jump {keyword});
+            }
+            "struct"    => return self.unsupported("local struct declarations"),
+            "precision" => return self.unsupported("local precision declarations"),
+            _ if self.is_declaration() => {
+                for declaration in self.declaration()? {
+                    block.add(declaration);
+                }
+            }
+            _ => {
+                let expr = self.expr()?;
+                self.expect(";")?;
+                block.add(ExprStatement {expr});
+            }
+        }
+        Ok(())
+    }
+
+    fn scope(&mut self) -> Result<Scope,Error> {
+        self.expect("{")?;
+        let mut body = Block::default();
+        while !self.eat("}") {
+            if self.at_end() {
+                return self.unexpected("`}`")
+            }
+            self.statement(&mut body)?;
+        }
+        Ok(Scope {body})
+    }
+
+    /// Parses a body of a conditional or a loop. A single statement is wrapped in a scope.
+    fn statement_scope(&mut self) -> Result<Scope,Error> {
+        if self.peek_is("{") {
+            self.scope()
+        } else {
+            let mut body = Block::default();
+            self.statement(&mut body)?;
+            Ok(Scope {body})
+        }
+    }
+
+    fn parenthesized(&mut self) -> Result<Expr,Error> {
+        self.expect("(")?;
+        let expr = self.expr()?;
+        self.expect(")")?;
+        Ok(expr)
+    }
+
+    fn if_statement(&mut self) -> Result<If,Error> {
+        self.expect("if")?;
+        let cond      = self.parenthesized()?;
+        let then      = self.statement_scope()?;
+        let otherwise = if self.eat("else") { Some(self.statement_scope()?) } else { None };
+        Ok(If {cond,then,otherwise})
+    }
+
+    fn for_loop(&mut self) -> Result<Loop,Error> {
+        self.expect("for")?;
+        self.expect("(")?;
+        let init = if self.eat(";") {
+            None
+        } else if self.is_declaration() {
+            let mut declarations = self.declaration()?;
+            if declarations.len() > 1 {
+                return self.unsupported("declaring several variables in a `for` loop")
+            }
+            Some(declarations.remove(0).into())
+        } else {
+            let expr = self.expr()?;
+            self.expect(";")?;
+            Some(ExprStatement {expr}.into())
+        };
+        let cond = if self.peek_is(";") { None } else { Some(self.expr()?) };
+        self.expect(";")?;
+        let step = if self.peek_is(")") { None } else { Some(self.expr()?) };
+        self.expect(")")?;
+        let body = self.statement_scope()?;
+        Ok(Loop::For {init,cond,step,body})
+    }
+}
+
+
+// === Expressions ===
+
+impl Parser {
+    fn expr(&mut self) -> Result<Expr,Error> {
+        let mut left = self.assignment()?;
+        while self.eat(",") {
+            let op    = ",".into();
+            let right = self.assignment()?;
+            left      = BinaryOp {op,left,right}.into();
+        }
+        Ok(left)
+    }
+
+    fn assignment(&mut self) -> Result<Expr,Error> {
+        let left = self.conditional()?;
+        match self.eat_any(ASSIGNMENT_OPS) {
+            None     => Ok(left),
+            Some(op) => {
+                let right = self.assignment()?;
+                Ok(BinaryOp {op,left,right}.into())
+            }
+        }
+    }
+
+    fn conditional(&mut self) -> Result<Expr,Error> {
+        let cond = self.binary(1)?;
+        if !self.eat("?") {
+            return Ok(cond)
+        }
+        let if_true = self.expr()?;
+        self.expect(":")?;
+        let if_false = self.assignment()?;
+        Ok(Conditional {cond,if_true,if_false}.into())
+    }
+
+    /// Parses binary operations using precedence climbing. All binary operators are
+    /// left-associative.
+    fn binary(&mut self, min_precedence:usize) -> Result<Expr,Error> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(token) if token.kind == TokenKind::Punct => token.text.clone(),
+                _                                             => break,
+            };
+            let precedence = match binary_precedence(&op) {
+                Some(precedence) if precedence >= min_precedence => precedence,
+                _                                                => break,
+            };
+            self.offset += 1;
+            let right    = self.binary(precedence + 1)?;
+            left         = BinaryOp {op,left,right}.into();
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr,Error> {
+        match self.eat_any(PREFIX_OPS) {
+            None     => self.postfix(),
+            Some(op) => {
+                let expr    = self.unary()?;
+                let postfix = false;
+                Ok(UnaryOp {op,expr,postfix}.into())
+            }
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr,Error> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat("[") {
+                let index = self.expr()?;
+                self.expect("]")?;
+                expr = IndexAccess {expr,index}.into();
+            } else if self.eat(".") {
+                let field = self.ident()?;
+                if self.peek_is("(") {
+                    return self.unsupported("method calls")
+                }
+                expr = FieldAccess {expr,field}.into();
+            } else if let Some(op) = self.eat_any(&["++","--"]) {
+                let postfix = true;
+                expr = UnaryOp {op,expr,postfix}.into();
+            } else {
+                break
+            }
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr,Error> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None        => return self.unexpected("an expression"),
+        };
+        match token.kind {
+            TokenKind::Int | TokenKind::Float => {
+                self.offset += 1;
+                Ok(Literal {str:token.text}.into())
+            }
+            TokenKind::Ident if token.is("true") || token.is("false") => {
+                self.offset += 1;
+                Ok(Literal {str:token.text}.into())
+            }
+            TokenKind::Ident => {
+                self.offset += 1;
+                let func = Identifier(token.text);
+                if !self.eat("(") {
+                    return Ok(func.into())
+                }
+                let mut args = Vec::new();
+                if self.peek_is("void") && self.peek_is_at(1,")") {
+                    self.offset += 1;
+                }
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.assignment()?);
+                        if self.eat(")") { break }
+                        if !self.eat(",") { return self.unexpected("`,` or `)`") }
+                    }
+                }
+                Ok(Call {func,args}.into())
+            }
+            TokenKind::Punct if token.is("(") => {
+                self.offset += 1;
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(Parens {expr}.into())
+            }
+            _ => self.unexpected("an expression"),
+        }
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::system::gpu::shader::glsl::ExprUnboxed;
+
+    use code_builder::HasCodeRepr;
+
+    use std::path::Path;
+    use std::path::PathBuf;
+
+    const COLOR : &str = include_str!("../../../../display/shape/primitive/glsl/color.glsl");
+
+    /// Paths of all GLSL files in the sources of the crate.
+    fn repository_shaders() -> Vec<PathBuf> {
+        fn visit(dir:&Path, files:&mut Vec<PathBuf>) {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    visit(&path,files)
+                } else if path.extension().map_or(false,|ext| ext == "glsl") {
+                    files.push(path)
+                }
+            }
+        }
+        let mut files = Vec::new();
+        visit(&Path::new(env!("CARGO_MANIFEST_DIR")).join("src"),&mut files);
+        files.sort();
+        files
+    }
+
+    /// Shader files are either whole modules or snippets of code inserted into function bodies,
+    /// like the fragment runner.
+    #[test]
+    fn repository_shaders_round_trip() {
+        let shaders = repository_shaders();
+        assert!(!shaders.is_empty(),"No GLSL files found.");
+        for path in shaders {
+            let name   = path.display();
+            let source = std::fs::read_to_string(&path).unwrap();
+            let code   = match parse_module(&source) {
+                Ok(module) => module.to_code(),
+                Err(error) => match parse_block(&source) {
+                    Ok(block) => block.to_code(),
+                    Err(_)    => panic!("{}: {}",name,error),
+                }
+            };
+            let printed = parse_module(&code).map(|module| module.to_code());
+            let printed = printed.or_else(|_| parse_block(&code).map(|block| block.to_code()));
+            let printed = printed.unwrap_or_else(|e| panic!("{}: {}\n{}",name,e,code));
+            assert_eq!(printed,code,"{} is not printed stably.",name);
+        }
+    }
+
+    #[test]
+    fn module_structure() {
+        let module  = parse_module(COLOR).unwrap();
+        let structs = module.struct_decls.iter().map(|decl| decl.ident.0.as_str()).collect_vec();
+        assert_eq!(structs,vec!["RGB","RGBA","HSV","HSVA","LCH","LCHA"]);
+        let source = "#version 300 es\n\
+                      precision highp float;\n\
+                      layout(location = 0) in vec3 position;\n\
+                      flat out int id;\n\
+                      layout(std140) uniform Globals { mat4 view; float time; };\n\
+                      void main() { gl_Position = view * vec4(position,1.0); }";
+        let module = parse_module(source).unwrap();
+        assert_eq!(module.prec_decls.len(),1);
+        assert_eq!(module.global_vars.len(),2);
+        assert_eq!(module.statements.len(),1);
+        assert_eq!(module.main.body.exprs.len(),1);
+    }
+
+    #[test]
+    fn function_prototypes() {
+        let source = "float f(float a);\n\
+                      void main() { float b = f(1.0); }\n\
+                      float f(float a) { return a; }";
+        let module = parse_module(source).unwrap();
+        let code   = module.to_code();
+        assert_eq!(module.statements.len(),2);
+        match &module.statements[0] {
+            Statement::Prototype(prototype) => assert_eq!(prototype.ident.0,"f"),
+            other                           => panic!("Unexpected {:?}.",other),
+        }
+        assert!(code.contains("float f(float a);"));
+        assert_eq!(parse_module(&code).unwrap().to_code(),code);
+    }
+
+    #[test]
+    fn expressions_keep_their_grouping() {
+        let check = |source:&str, expected:&str| {
+            assert_eq!(parse_expr(source).unwrap().to_code(),expected);
+        };
+        check("a+b*c"                   , "a + b * c");
+        check("(a+b)*c"                 , "(a + b) * c");
+        check("-a.xy[i]++"              , "-a.xy[i]++");
+        check("- -a"                    , "- -a");
+        check("f()+g(void)"             , "f() + g()");
+        check("x = y += c ? f(a,b) : 1.", "x = y += c ? f(a, b) : 1.");
+        match &*parse_expr("a - b - c").unwrap().0 {
+            ExprUnboxed::BinaryOp(op) => match &*op.left.0 {
+                ExprUnboxed::BinaryOp(left) => assert_eq!(left.op,"-"),
+                other                       => panic!("Unexpected {:?}.",other),
+            }
+            other => panic!("Unexpected {:?}.",other),
+        }
+    }
+
+    #[test]
+    fn statements() {
+        let source = "if (a) b = 1; else if (c) { d(); } else discard;\n\
+                      for (int i = 0; i < 4; i++) continue;\n\
+                      do { i--; } while (i > 0);\n\
+                      switch (i) { case 1: break; default: return; }";
+        let expected = "
+if (a) {
+    b = 1;
+} else if (c) {
+    d();
+} else {
+    discard;
+}
+for (int i = 0; i < 4; i++) {
+    continue;
+}
+do {
+    i--;
+} while (i > 0);
+switch (i) {
+    case 1:
+    break;
+    default:
+    return;
+}";
+        assert_eq!(parse_block(source).unwrap().to_code(),expected);
+    }
+
+    #[test]
+    fn errors() {
+        let unexpected = |line, found:&str, expected:&str| {
+            let found    = found.into();
+            let expected = expected.into();
+            Err(Error::Unexpected {line,found,expected})
+        };
+        let source = "#define EMPTY\n\nvoid main() {\n    float a = EMPTY;\n}";
+        assert_eq!(parse_module(source).map(|_|()),unexpected(4,"`;`","an expression"));
+        assert_eq!(parse_expr("f(a").map(|_|()),unexpected(1,"the end of the input","`,` or `)`"));
+        let feature = "method calls".into();
+        let error   = Err(Error::Unsupported {line:2,feature});
+        assert_eq!(parse_expr("\na.length()").map(|_|()),error);
+    }
+}
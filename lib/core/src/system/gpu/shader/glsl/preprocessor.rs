@@ -0,0 +1,551 @@
+//! This module defines the GLSL preprocessor. It expands macros and evaluates conditional
+//! directives, so the parser works on plain GLSL tokens. Macros are expanded recursively, but the
+//! expansion of a macro is never rescanned together with the tokens following it, so a
+//! function-like macro cannot be invoked with arguments placed outside of the expanded body.
+
+use crate::prelude::*;
+
+use super::lexer;
+use super::lexer::Token;
+use super::lexer::TokenKind;
+use super::parser::Error;
+
+
+
+// =============
+// === Macro ===
+// =============
+
+/// Definition of a preprocessor macro.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct Macro {
+    /// Names of the parameters of a function-like macro, or `None` for object-like macros.
+    pub params : Option<Vec<String>>,
+    /// Tokens the macro is replaced with.
+    pub body   : Vec<Token>,
+}
+
+
+
+// ===================
+// === Conditional ===
+// ===================
+
+/// State of a single `#if` ... `#endif` section.
+#[derive(Clone,Copy,Debug)]
+struct Conditional {
+    /// Whether the section is in an active region of the source.
+    parent_active : bool,
+    /// Whether the current branch of the section is active.
+    active        : bool,
+    /// Whether any of the branches of the section was already active.
+    taken         : bool,
+    /// Whether the `#else` branch was reached.
+    seen_else     : bool,
+    /// The line of the opening directive.
+    line          : usize,
+}
+
+
+
+// ====================
+// === Preprocessor ===
+// ====================
+
+/// The GLSL preprocessor. It predefines the `GL_ES` and `__VERSION__` macros. The `#version`,
+/// `#pragma` and `#line` directives are ignored, as the printed code always uses GLSL ES 3.00.
+#[derive(Clone,Debug)]
+pub struct Preprocessor {
+    macros : HashMap<String,Macro>,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        let macros = default();
+        let mut preprocessor = Self {macros};
+        preprocessor.define_object("GL_ES",1);
+        preprocessor.define_object("__VERSION__",300);
+        preprocessor
+    }
+}
+
+impl Preprocessor {
+    /// Constructor.
+    pub fn new() -> Self {
+        default()
+    }
+
+    /// Defines an object-like macro, like a `#define` directive in the source would.
+    pub fn define<Name:Str>(&mut self, name:Name, value:&str) -> Result<(),Error> {
+        let body   = lexer::lex(value)?.into_iter().flatten().collect();
+        let params = None;
+        self.macros.insert(name.into(),Macro {params,body});
+        Ok(())
+    }
+
+    /// Removes the macro definition.
+    pub fn undefine(&mut self, name:&str) {
+        self.macros.remove(name);
+    }
+
+    /// Checks whether the macro is defined.
+    pub fn is_defined(&self, name:&str) -> bool {
+        self.macros.contains_key(name)
+    }
+
+    /// Looks up the macro definition.
+    pub fn get(&self, name:&str) -> Option<&Macro> {
+        self.macros.get(name)
+    }
+
+    /// Preprocesses the source. Macros defined in the source stay defined afterwards.
+    pub fn run(&mut self, source:&str) -> Result<Vec<Token>,Error> {
+        let mut output       = Vec::new();
+        let mut pending      = Vec::new();
+        let mut conditionals = Vec::new();
+        for line in lexer::lex(source)? {
+            if line[0].is("#") {
+                output.extend(self.expand(std::mem::take(&mut pending),&[])?);
+                self.directive(&line,&mut conditionals)?;
+            } else if conditionals.iter().all(|cond:&Conditional| cond.active) {
+                pending.extend(line);
+            }
+        }
+        if let Some(cond) = conditionals.last() {
+            let line    = cond.line;
+            let message = "unterminated #if directive".into();
+            return Err(Error::Preprocessor {line,message})
+        }
+        output.extend(self.expand(pending,&[])?);
+        Ok(output)
+    }
+
+    fn define_object(&mut self, name:&str, value:usize) {
+        let body   = vec![Token::new(TokenKind::Int,value.to_string(),0)];
+        let params = None;
+        self.macros.insert(name.into(),Macro {params,body});
+    }
+}
+
+
+// === Directives ===
+
+impl Preprocessor {
+    fn directive
+    (&mut self, tokens:&[Token], conditionals:&mut Vec<Conditional>) -> Result<(),Error> {
+        let line   = tokens[0].line;
+        let name   = match tokens.get(1) {
+            Some(name) => name.text.as_str(),
+            None       => return Ok(()),
+        };
+        let args   = &tokens[2..];
+        let active = conditionals.iter().all(|cond| cond.active);
+        let error  = |message:String| Err(Error::Preprocessor {line,message});
+        match name {
+            "if" | "ifdef" | "ifndef" => {
+                let parent_active = active;
+                let active        = parent_active && match name {
+                    "if"    => self.evaluate(args,line)? != 0,
+                    "ifdef" => self.is_defined(&Self::macro_name(args,line)?),
+                    _       => !self.is_defined(&Self::macro_name(args,line)?),
+                };
+                let taken     = active;
+                let seen_else = false;
+                conditionals.push(Conditional {parent_active,active,taken,seen_else,line});
+            }
+            "elif" | "else" | "endif" => {
+                let cond = match conditionals.last_mut() {
+                    Some(cond) => cond,
+                    None       => return error(format!("#{} without #if",name)),
+                };
+                if cond.seen_else && name != "endif" {
+                    return error(format!("#{} after #else",name))
+                }
+                match name {
+                    "endif" => {
+                        conditionals.pop();
+                    }
+                    "else" => {
+                        cond.active    = cond.parent_active && !cond.taken;
+                        cond.taken     = true;
+                        cond.seen_else = true;
+                    }
+                    _ => {
+                        let enabled = cond.parent_active && !cond.taken;
+                        let cond    = *cond;
+                        let active  = enabled && self.evaluate(args,line)? != 0;
+                        let cond    = Conditional {active,taken:cond.taken || active,..cond};
+                        *conditionals.last_mut().unwrap() = cond;
+                    }
+                }
+            }
+            _ if !active => {}
+            "define" => {
+                let name = Self::macro_name(args,line)?;
+                let rest = &args[1..];
+                let (params,body) = match rest.first() {
+                    Some(paren) if paren.is("(") && !paren.spaced => {
+                        let end = match rest.iter().position(|token| token.is(")")) {
+                            Some(end) => end,
+                            None      => return error("unterminated macro parameters".into()),
+                        };
+                        let params = rest[1..end].iter().filter(|token| !token.is(","));
+                        let params = params.map(|token| token.text.clone()).collect();
+                        (Some(params),&rest[end+1..])
+                    }
+                    _ => (None,rest)
+                };
+                if body.iter().any(|token| token.is("#") || token.is("##")) {
+                    let feature = "the `#` and `##` operators".into();
+                    return Err(Error::Unsupported {line,feature})
+                }
+                let body = body.to_vec();
+                self.macros.insert(name,Macro {params,body});
+            }
+            "undef" => {
+                let name = Self::macro_name(args,line)?;
+                self.undefine(&name);
+            }
+            "version" | "pragma" | "line" => {}
+            "extension" => {
+                let feature = "the #extension directive".into();
+                return Err(Error::Unsupported {line,feature})
+            }
+            "error" => return error(args.iter().map(|token| &token.text).join(" ")),
+            _       => return error(format!("unknown directive #{}",name)),
+        }
+        Ok(())
+    }
+
+    fn macro_name(args:&[Token], line:usize) -> Result<String,Error> {
+        match args.first() {
+            Some(name) if name.is_ident() => Ok(name.text.clone()),
+            _ => Err(Error::Preprocessor {line,message:"macro name expected".into()}),
+        }
+    }
+}
+
+
+// === Expansion ===
+
+impl Preprocessor {
+    /// Expands all macros in the tokens, except the disabled ones. Macros are disabled during the
+    /// expansion of their own bodies, so recursive macros are not expanded infinitely.
+    fn expand(&self, tokens:Vec<Token>, disabled:&[&str]) -> Result<Vec<Token>,Error> {
+        let mut output = Vec::new();
+        let mut index  = 0;
+        while index < tokens.len() {
+            let token = &tokens[index];
+            index    += 1;
+            let mac   = match self.macros.get(&token.text) {
+                Some(mac) if token.is_ident() && !disabled.contains(&token.text.as_str()) => mac,
+                _ => {
+                    output.push(token.clone());
+                    continue
+                }
+            };
+            let body = match &mac.params {
+                None         => mac.body.clone(),
+                Some(params) => {
+                    if !tokens.get(index).map(|token| token.is("(")).unwrap_or(false) {
+                        output.push(token.clone());
+                        continue
+                    }
+                    let (args,end) = Self::collect_args(&tokens,index+1,token)?;
+                    index          = end;
+                    let no_args    = params.is_empty() && args.len() == 1 && args[0].is_empty();
+                    let args       = if no_args { default() } else { args };
+                    if args.len() != params.len() {
+                        let line    = token.line;
+                        let message = format!("macro `{}` expects {} arguments, but {} were given",
+                            token.text,params.len(),args.len());
+                        return Err(Error::Preprocessor {line,message})
+                    }
+                    let mut expanded_args = Vec::with_capacity(args.len());
+                    for arg in args {
+                        expanded_args.push(self.expand(arg,disabled)?);
+                    }
+                    let mut body = Vec::new();
+                    for body_token in &mac.body {
+                        let param = params.iter().position(|param| param == &body_token.text);
+                        match param {
+                            Some(param) if body_token.is_ident() => {
+                                body.extend(expanded_args[param].iter().cloned())
+                            }
+                            _ => body.push(body_token.clone()),
+                        }
+                    }
+                    body
+                }
+            };
+            let body = body.into_iter().enumerate().map(|(i,body_token)| {
+                let line   = token.line;
+                let spaced = if i == 0 { token.spaced } else { body_token.spaced };
+                Token {line,spaced,..body_token}
+            }).collect();
+            let mut disabled = disabled.to_vec();
+            disabled.push(&token.text);
+            output.extend(self.expand(body,&disabled)?);
+        }
+        Ok(output)
+    }
+
+    /// Collects the arguments of a function-like macro invocation, starting after the opening
+    /// parenthesis. Returns the arguments and the index of the token following the invocation.
+    fn collect_args
+    (tokens:&[Token], start:usize, name:&Token) -> Result<(Vec<Vec<Token>>,usize),Error> {
+        let mut args  = vec![Vec::new()];
+        let mut depth = 0;
+        for (index,token) in tokens.iter().enumerate().skip(start) {
+            match token.text.as_str() {
+                ")" if depth == 0 => return Ok((args,index+1)),
+                "," if depth == 0 => {
+                    args.push(Vec::new());
+                    continue
+                }
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                _   => {}
+            }
+            args.last_mut().unwrap().push(token.clone());
+        }
+        let line    = name.line;
+        let message = format!("unterminated invocation of macro `{}`",name.text);
+        Err(Error::Preprocessor {line,message})
+    }
+}
+
+
+// === Conditions ===
+
+impl Preprocessor {
+    /// Evaluates the condition of an `#if` or `#elif` directive.
+    fn evaluate(&self, tokens:&[Token], line:usize) -> Result<i64,Error> {
+        let mut resolved = Vec::new();
+        let mut index    = 0;
+        while index < tokens.len() {
+            let token = &tokens[index];
+            index    += 1;
+            if !token.is("defined") {
+                resolved.push(token.clone());
+                continue
+            }
+            let parens = tokens.get(index).map(|token| token.is("(")).unwrap_or(false);
+            let name   = Self::macro_name(&tokens[index + parens as usize..],line)?;
+            if parens {
+                let closed = tokens.get(index + 2).map(|token| token.is(")")).unwrap_or(false);
+                if !closed {
+                    let message = format!("missing `)` after `defined({}`",name);
+                    return Err(Error::Preprocessor {line,message})
+                }
+            }
+            index     += if parens { 3 } else { 1 };
+            let value  = if self.is_defined(&name) { "1" } else { "0" };
+            resolved.push(Token::new(TokenKind::Int,value,line));
+        }
+        let tokens    = self.expand(resolved,&[])?;
+        let offset    = 0;
+        let mut eval  = Evaluator {tokens,offset,line};
+        let value     = eval.binary(0)?;
+        match eval.tokens.get(eval.offset) {
+            None        => Ok(value),
+            Some(token) => eval.error(format!("unexpected `{}` in the condition",token.text)),
+        }
+    }
+}
+
+
+
+// =================
+// === Evaluator ===
+// =================
+
+/// Evaluator of integer expressions used in conditional directives. Identifiers which are not
+/// names of macros evaluate to 0. Overflows and shifts out of range are reported as errors.
+#[derive(Debug)]
+struct Evaluator {
+    tokens : Vec<Token>,
+    offset : usize,
+    line   : usize,
+}
+
+impl Evaluator {
+    fn error<T>(&self, message:String) -> Result<T,Error> {
+        Err(Error::Preprocessor {line:self.line,message})
+    }
+
+    fn checked<T>(&self, value:Option<T>, op:&str) -> Result<T,Error> {
+        match value {
+            Some(value) => Ok(value),
+            None        => self.error(format!("integer overflow in `{}`",op)),
+        }
+    }
+
+    fn shift_amount(&self, right:i64) -> Result<u32,Error> {
+        if (0..64).contains(&right) { Ok(right as u32) } else {
+            self.error(format!("shift by {} is out of range",right))
+        }
+    }
+
+    fn precedence(op:&str) -> Option<usize> {
+        match op {
+            "||"                    => Some(1),
+            "&&"                    => Some(2),
+            "|"                     => Some(3),
+            "^"                     => Some(4),
+            "&"                     => Some(5),
+            "==" | "!="             => Some(6),
+            "<"  | ">" | "<=" | ">=" => Some(7),
+            "<<" | ">>"             => Some(8),
+            "+"  | "-"              => Some(9),
+            "*"  | "/" | "%"        => Some(10),
+            _                       => None,
+        }
+    }
+
+    fn binary(&mut self, min_precedence:usize) -> Result<i64,Error> {
+        let mut left = self.unary()?;
+        loop {
+            let op         = match self.tokens.get(self.offset) {
+                Some(token) => token.text.clone(),
+                None        => break,
+            };
+            let precedence = match Self::precedence(&op) {
+                Some(precedence) if precedence >= min_precedence => precedence,
+                _                                                => break,
+            };
+            self.offset += 1;
+            let right    = self.binary(precedence + 1)?;
+            left = match op.as_str() {
+                "||" => ((left != 0) || (right != 0)) as i64,
+                "&&" => ((left != 0) && (right != 0)) as i64,
+                "|"  => left | right,
+                "^"  => left ^ right,
+                "&"  => left & right,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<"  => (left <  right) as i64,
+                ">"  => (left >  right) as i64,
+                "<=" => (left <= right) as i64,
+                ">=" => (left >= right) as i64,
+                "<<" => left << self.shift_amount(right)?,
+                ">>" => left >> self.shift_amount(right)?,
+                "+"  => self.checked(left.checked_add(right),&op)?,
+                "-"  => self.checked(left.checked_sub(right),&op)?,
+                "*"  => self.checked(left.checked_mul(right),&op)?,
+                _ if right == 0 => return self.error("division by zero".into()),
+                "/"  => self.checked(left.checked_div(right),&op)?,
+                _    => self.checked(left.checked_rem(right),&op)?,
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i64,Error> {
+        let token = match self.tokens.get(self.offset) {
+            Some(token) => token.clone(),
+            None        => return self.error("unexpected end of the condition".into()),
+        };
+        self.offset += 1;
+        match token.kind {
+            TokenKind::Ident => Ok(0),
+            TokenKind::Int   => match token.int_value() {
+                Some(value) => Ok(value),
+                None        => self.error(format!("invalid integer `{}`",token.text)),
+            }
+            _ => match token.text.as_str() {
+                "!" => Ok((self.unary()? == 0) as i64),
+                "-" => {
+                    let value = self.unary()?;
+                    self.checked(value.checked_neg(),"-")
+                }
+                "+" => self.unary(),
+                "~" => Ok(!self.unary()?),
+                "(" => {
+                    let value = self.binary(0)?;
+                    match self.tokens.get(self.offset) {
+                        Some(token) if token.is(")") => {
+                            self.offset += 1;
+                            Ok(value)
+                        }
+                        _ => self.error("unclosed parenthesis in the condition".into()),
+                    }
+                }
+                _ => self.error(format!("unexpected `{}` in the condition",token.text)),
+            }
+        }
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(source:&str) -> Result<String,Error> {
+        let tokens = Preprocessor::new().run(source)?;
+        Ok(tokens.iter().map(|token| &token.text).join(" "))
+    }
+
+    #[test]
+    fn macros_are_expanded() {
+        let source = "#define PI 3.14\n\
+                      #define TAU (2.0*PI)\n\
+                      #define NEG(x) -x\n\
+                      #define TWICE(f,x) f(f(x))\n\
+                      float a = TAU + TWICE(NEG,PI) + NEG;";
+        assert_eq!(run(source).unwrap(),"float a = ( 2.0 * 3.14 ) + - - 3.14 + NEG ;");
+    }
+
+    #[test]
+    fn recursive_macros_are_expanded_once() {
+        let source = "#define a a + b\n#define b a\na;";
+        assert_eq!(run(source).unwrap(),"a + a ;");
+    }
+
+    #[test]
+    fn conditionals() {
+        let source = "#define QUALITY 2\n\
+                      #if defined(GL_ES) && QUALITY > 1\n\
+                      #ifdef SHADOWS\n\
+                      shadows;\n\
+                      #else\n\
+                      no_shadows;\n\
+                      #endif\n\
+                      #elif QUALITY == 1\n\
+                      low;\n\
+                      #else\n\
+                      #error unreachable\n\
+                      #endif";
+        assert_eq!(run(source).unwrap(),"no_shadows ;");
+    }
+
+    #[test]
+    fn errors() {
+        let error = |line,message:&str| Err(Error::Preprocessor {line,message:message.into()});
+        assert_eq!(run("a;\n#if 1\nb;"),error(2,"unterminated #if directive"));
+        assert_eq!(run("#endif"),error(1,"#endif without #if"));
+        assert_eq!(run("#error no support"),error(1,"no support"));
+        let message = "macro `F` expects 2 arguments, but 1 were given";
+        assert_eq!(run("#define F(a,b) a+b\n\nF(1);"),error(3,message));
+        assert_eq!(run("#if defined(A\n#endif"),error(1,"missing `)` after `defined(A`"));
+        assert_eq!(run("#if defined(A B)\n#endif"),error(1,"missing `)` after `defined(A`"));
+    }
+
+    #[test]
+    fn overflows_are_errors() {
+        let error = |message:&str| Err(Error::Preprocessor {line:1,message:message.into()});
+        assert_eq!(run("#if 9223372036854775807 + 1\n#endif"),error("integer overflow in `+`"));
+        assert_eq!(run("#if -9223372036854775807 - 2\n#endif"),error("integer overflow in `-`"));
+        assert_eq!(run("#if 4294967296 * 4294967296\n#endif"),error("integer overflow in `*`"));
+        assert_eq!(run("#if 1 << 64\n#endif"),error("shift by 64 is out of range"));
+        assert_eq!(run("#if 1 >> -1\n#endif"),error("shift by -1 is out of range"));
+        assert_eq!(run("#if 1 % 0\n#endif"),error("division by zero"));
+        assert_eq!(run("#if 1 << 62\nbig;\n#endif").unwrap(),"big ;");
+    }
+}
@@ -0,0 +1,347 @@
+//! This module defines the hot reloading of shader sources in the development mode. The
+//! `ShaderReloader` periodically loads all the sources registered in `ShaderSources` from a
+//! `FileSource`, like a local development server, and updates the ones which were changed. Shaders
+//! including the changed sources are then generated and compiled again, without rebuilding the
+//! application.
+
+use crate::prelude::*;
+
+use crate::system::gpu::shader::sources::ShaderSources;
+use crate::system::web;
+
+use wasm_bindgen::prelude::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::Performance;
+use web_sys::XmlHttpRequest;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// The default time in milliseconds between two checks of the sources.
+pub const DEFAULT_POLL_INTERVAL_MS : f64 = 500.0;
+
+
+
+// ==================
+// === FileSource ===
+// ==================
+
+/// A file loaded by the `FileSource`. The content is the error message if the file could not be
+/// loaded.
+#[derive(Clone,Debug,PartialEq)]
+pub struct LoadedFile {
+    /// The path of the file, as it was requested.
+    pub path    : String,
+    /// The content of the file or the error message.
+    pub content : Result<String,String>,
+}
+
+impl LoadedFile {
+    /// Constructor.
+    pub fn new<P:Str>(path:P, content:Result<String,String>) -> Self {
+        let path = path.into();
+        Self {path,content}
+    }
+}
+
+/// Asynchronous source of files. It allows testing the `ShaderReloader` without the browser.
+pub trait FileSource {
+    /// Starts loading the file. It is returned by one of the next `take_loaded` calls.
+    fn request(&mut self, path:&str);
+
+    /// Takes the files loaded since the last call.
+    fn take_loaded(&mut self) -> Vec<LoadedFile>;
+
+    /// Current time in milliseconds.
+    fn now(&self) -> f64;
+}
+
+
+
+// ======================
+// === HttpFileSource ===
+// ======================
+
+/// Loads files from the provided base URL, like a local development server serving the sources of
+/// the library. Every request bypasses the browser cache.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct HttpFileSource {
+    base_url    : String,
+    #[derivative(Debug="ignore")]
+    requests    : HashMap<String,(XmlHttpRequest,Closure<dyn FnMut()>)>,
+    finished    : Rc<RefCell<Vec<String>>>,
+    failed      : Vec<LoadedFile>,
+    performance : Performance,
+}
+
+impl HttpFileSource {
+    /// Constructor. Fails if the browser does not provide the `performance` API, which is used to
+    /// bypass the cache.
+    pub fn new<Url:Str>(base_url:Url) -> web::Result<Self> {
+        let base_url    = base_url.as_ref().trim_end_matches('/').to_string();
+        let requests    = default();
+        let finished    = default();
+        let failed      = default();
+        let performance = web::get_performance()?;
+        Ok(Self {base_url,requests,finished,failed,performance})
+    }
+
+    /// Sends the request of the file. Returns the error message if it could not be sent.
+    fn send(&mut self, path:&str) -> Result<(),String> {
+        let url      = format!("{}/{}?t={}",self.base_url,path,self.now());
+        let request  = XmlHttpRequest::new().map_err(|err| format!("{:?}",err))?;
+        let finished = self.finished.clone();
+        let name     = path.to_string();
+        let on_end   = move || finished.borrow_mut().push(name.clone());
+        let on_end   = Closure::wrap(Box::new(on_end) as Box<dyn FnMut()>);
+        request.set_onloadend(Some(on_end.as_ref().unchecked_ref()));
+        request.open_with_async("GET",&url,true).map_err(|err| format!("{:?}",err))?;
+        request.send().map_err(|err| format!("{:?}",err))?;
+        self.requests.insert(path.into(),(request,on_end));
+        Ok(())
+    }
+
+    /// The content of the finished request.
+    fn response(request:&XmlHttpRequest) -> Result<String,String> {
+        match request.status() {
+            Ok(200)    => request.response_text().ok().and_then(|text| text)
+                .ok_or_else(|| "the response is not a text".to_string()),
+            Ok(0)      => Err("the server is not reachable".to_string()),
+            Ok(status) => Err(format!("HTTP status {}",status)),
+            Err(err)   => Err(format!("{:?}",err)),
+        }
+    }
+}
+
+impl FileSource for HttpFileSource {
+    fn request(&mut self, path:&str) {
+        if let Err(message) = self.send(path) {
+            self.failed.push(LoadedFile::new(path,Err(message)));
+        }
+    }
+
+    fn take_loaded(&mut self) -> Vec<LoadedFile> {
+        let mut loaded = std::mem::take(&mut self.failed);
+        let finished   = std::mem::take(&mut *self.finished.borrow_mut());
+        for path in finished {
+            if let Some((request,_)) = self.requests.remove(&path) {
+                loaded.push(LoadedFile::new(path,Self::response(&request)));
+            }
+        }
+        loaded
+    }
+
+    fn now(&self) -> f64 {
+        self.performance.now()
+    }
+}
+
+impl Drop for HttpFileSource {
+    fn drop(&mut self) {
+        // The callbacks are dropped with the source, so the pending requests must not call them.
+        for (request,_) in self.requests.values() {
+            request.set_onloadend(None);
+            request.abort().ok();
+        }
+    }
+}
+
+
+
+// ======================
+// === ShaderReloader ===
+// ======================
+
+/// State of a source watched by the `ShaderReloader`.
+#[derive(Clone,Copy,Debug,Default)]
+struct WatchState {
+    /// Whether the source was requested and was not loaded yet.
+    pending : bool,
+    /// Whether the last load of the source failed. Failures are reported only once, until the
+    /// source is loaded again.
+    failed  : bool,
+}
+
+/// Coordinates the hot reloading of shader sources. Every `interval` milliseconds, all sources
+/// registered in `ShaderSources` are requested from the `FileSource`, unless the previous request
+/// of the source is still pending. Loaded sources which differ from the registered ones replace
+/// them, which bumps their versions, so the shaders including them are compiled again. Call `poll`
+/// once per frame.
+#[derive(Debug)]
+pub struct ShaderReloader<S:FileSource> {
+    source    : S,
+    sources   : ShaderSources,
+    watched   : BTreeMap<String,WatchState>,
+    interval  : f64,
+    last_poll : Option<f64>,
+    logger    : Logger,
+}
+
+impl<S:FileSource> ShaderReloader<S> {
+    /// Constructor.
+    pub fn new(logger:Logger, source:S, sources:&ShaderSources) -> Self {
+        let sources   = sources.clone_ref();
+        let watched   = default();
+        let interval  = DEFAULT_POLL_INTERVAL_MS;
+        let last_poll = default();
+        Self {source,sources,watched,interval,last_poll,logger}
+    }
+
+    /// Sets the time in milliseconds between two checks of the sources.
+    pub fn set_interval(&mut self, interval:f64) {
+        self.interval = interval;
+    }
+
+    /// The file source.
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// The mutable file source.
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// Requests the sources if the interval elapsed and updates the loaded ones. Returns paths of
+    /// the sources which were changed.
+    pub fn poll(&mut self) -> Vec<String> {
+        let now = self.source.now();
+        let due = self.last_poll.map(|last_poll| now - last_poll >= self.interval).unwrap_or(true);
+        if due {
+            self.last_poll = Some(now);
+            self.request_all();
+        }
+        let mut changed = Vec::new();
+        for file in self.source.take_loaded() {
+            let path = file.path;
+            if let Some(state) = self.watched.get_mut(&path) {
+                state.pending = false;
+                match file.content {
+                    Ok(code) => {
+                        state.failed = false;
+                        if self.sources.set(&path,&code) {
+                            self.logger.info(|| format!("Reloaded '{}'.",path));
+                            changed.push(path);
+                        }
+                    }
+                    Err(message) => if !state.failed {
+                        state.failed = true;
+                        self.logger.warning(|| format!("Unable to load '{}': {}.",path,message));
+                    }
+                }
+            }
+        }
+        changed
+    }
+}
+
+
+// === Private API ===
+
+impl<S:FileSource> ShaderReloader<S> {
+    /// Requests all the registered sources which are not pending.
+    fn request_all(&mut self) {
+        for path in self.sources.paths() {
+            let state = self.watched.entry(path.clone()).or_default();
+            if !state.pending {
+                state.pending = true;
+                self.source.request(&path);
+            }
+        }
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// File source serving the files from memory. Requested files are loaded in the next
+    /// `take_loaded` call, and missing files fail.
+    #[derive(Debug,Default)]
+    struct MockSource {
+        files     : HashMap<String,String>,
+        requested : Vec<String>,
+        time      : f64,
+    }
+
+    impl FileSource for MockSource {
+        fn request(&mut self, path:&str) {
+            self.requested.push(path.into());
+        }
+
+        fn take_loaded(&mut self) -> Vec<LoadedFile> {
+            let files = &self.files;
+            std::mem::take(&mut self.requested).into_iter().map(|path| {
+                let content = files.get(&path).cloned().ok_or_else(|| "not found".to_string());
+                LoadedFile::new(path,content)
+            }).collect()
+        }
+
+        fn now(&self) -> f64 {
+            self.time
+        }
+    }
+
+    fn reloader(sources:&ShaderSources) -> ShaderReloader<MockSource> {
+        ShaderReloader::new(Logger::new("test"),MockSource::default(),sources)
+    }
+
+    #[test]
+    fn unchanged_sources_are_kept() {
+        let sources      = ShaderSources::new();
+        let mut reloader = reloader(&sources);
+        sources.define("a","float a;");
+        reloader.source_mut().files.insert("a".into(),"float a;".into());
+        assert_eq!(reloader.poll(),Vec::<String>::new());
+        assert_eq!(sources.source_version("a"),1);
+    }
+
+    #[test]
+    fn changed_sources_are_reloaded_after_interval() {
+        let sources      = ShaderSources::new();
+        let mut reloader = reloader(&sources);
+        sources.define("a","float a;");
+        sources.define("b","float b;");
+        reloader.source_mut().files.insert("a".into(),"float a;".into());
+        reloader.source_mut().files.insert("b".into(),"float b;".into());
+        reloader.poll();
+        reloader.source_mut().files.insert("b".into(),"float b = 1.0;".into());
+        reloader.source_mut().time = DEFAULT_POLL_INTERVAL_MS / 2.0;
+        assert_eq!(reloader.poll(),Vec::<String>::new());
+        reloader.source_mut().time = DEFAULT_POLL_INTERVAL_MS;
+        assert_eq!(reloader.poll(),vec!["b".to_string()]);
+        assert_eq!(sources.get("b"),Some("float b = 1.0;".into()));
+    }
+
+    #[test]
+    fn failed_loads_keep_the_sources() {
+        let sources      = ShaderSources::new();
+        let mut reloader = reloader(&sources);
+        sources.define("a","float a;");
+        assert_eq!(reloader.poll(),Vec::<String>::new());
+        assert_eq!(sources.get("a"),Some("float a;".into()));
+        reloader.source_mut().files.insert("a".into(),"float a = 1.0;".into());
+        reloader.source_mut().time = DEFAULT_POLL_INTERVAL_MS;
+        assert_eq!(reloader.poll(),vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn pending_sources_are_not_requested_again() {
+        let sources      = ShaderSources::new();
+        let mut reloader = reloader(&sources);
+        sources.define("a","float a;");
+        reloader.request_all();
+        reloader.request_all();
+        assert_eq!(reloader.source().requested,vec!["a".to_string()]);
+    }
+}
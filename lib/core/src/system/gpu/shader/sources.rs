@@ -0,0 +1,318 @@
+//! This module defines a registry of named GLSL sources. Materials do not need to embed the code
+//! of the libraries they use. Instead, they can refer to registered sources with the
+//! `#include "path"` directive, which is expanded when the final shader code is generated. Every
+//! change of a source bumps its version, so shaders can find out that they need to be generated
+//! again. It is the basis of the hot reloading of shaders in the development mode.
+
+use crate::prelude::*;
+
+use shapely::shared;
+
+
+
+// =================
+// === Constants ===
+// =================
+
+/// The directive including a registered source, like `#include "path"`.
+pub const INCLUDE_DIRECTIVE : &str = "#include";
+
+/// The maximum depth of nested includes. Deeper includes are reported as recursive.
+pub const MAX_INCLUDE_DEPTH : usize = 16;
+
+
+
+// =============
+// === Error ===
+// =============
+
+/// Errors reported while expanding the includes.
+#[derive(Clone,Debug,Fail,PartialEq)]
+#[allow(missing_docs)]
+pub enum Error {
+    #[fail(display="The included source '{}' is not registered.",path)]
+    MissingSource { path:String },
+
+    #[fail(display="The source '{}' includes itself.",path)]
+    RecursiveInclude { path:String },
+
+    #[fail(display="Malformed include directive '{}'.",line)]
+    MalformedInclude { line:String },
+}
+
+
+
+// =================
+// === Expansion ===
+// =================
+
+/// Code with expanded includes.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Expansion {
+    /// The code with all the includes replaced with the included sources.
+    pub code     : String,
+    /// Versions of all the included sources, including the missing ones, which have version `0`.
+    pub includes : BTreeMap<String,usize>,
+    /// Errors of the includes which could not be expanded. Their directives are left intact.
+    pub errors   : Vec<Error>,
+}
+
+/// Parses the include directive. Returns `None` if the line is not a directive, and the error if
+/// the path is not quoted.
+pub fn parse_include(line:&str) -> Option<Result<String,Error>> {
+    let line = line.trim();
+    if !line.starts_with(INCLUDE_DIRECTIVE) { return None }
+    let path = line[INCLUDE_DIRECTIVE.len()..].trim();
+    let path = if path.len() >= 2 && path.starts_with('"') && path.ends_with('"') {
+        Ok(path[1..path.len()-1].to_string())
+    } else {
+        Err(Error::MalformedInclude {line:line.into()})
+    };
+    Some(path)
+}
+
+
+
+// ==============
+// === Source ===
+// ==============
+
+/// Function transforming the registered code before it is included, like marking its origin.
+pub trait TransformFn = Fn(&str)->String + 'static;
+
+/// Shared `TransformFn`.
+pub type Transform = Rc<dyn TransformFn>;
+
+/// A single registered source.
+#[derive(Derivative)]
+#[derivative(Debug)]
+struct Source {
+    raw       : String,
+    code      : String,
+    version   : usize,
+    #[derivative(Debug="ignore")]
+    transform : Option<Transform>,
+}
+
+impl Source {
+    fn new(raw:String, transform:Option<Transform>, version:usize) -> Self {
+        let code = String::new();
+        let mut source = Self {raw,code,version,transform};
+        source.update_code();
+        source
+    }
+
+    fn update_code(&mut self) {
+        self.code = match &self.transform {
+            Some(transform) => transform(&self.raw),
+            None            => self.raw.clone(),
+        }
+    }
+}
+
+
+
+// =====================
+// === ShaderSources ===
+// =====================
+
+shared! { ShaderSources
+
+/// Registry of named GLSL sources. Sources are defined with their builtin code, which can be
+/// replaced at runtime, for example by the `ShaderReloader`. The version of the registry is bumped
+/// with every change, and the version of a source is the version of the registry after its last
+/// change.
+#[derive(Debug,Default)]
+pub struct ShaderSourcesData {
+    sources : BTreeMap<String,Source>,
+    version : usize,
+}
+
+impl {
+    /// Constructor.
+    pub fn new() -> Self {
+        default()
+    }
+
+    /// Defines the source with the builtin code. If the source was already defined or set, its
+    /// code is kept, so the sources replaced at runtime are not overwritten.
+    pub fn define(&mut self, path:&str, code:&str) {
+        if !self.sources.contains_key(path) {
+            self.version += 1;
+            let source = Source::new(code.into(),None,self.version);
+            self.sources.insert(path.into(),source);
+        }
+    }
+
+    /// Defines the source like `define`, but the code is transformed every time it is changed,
+    /// before it is included.
+    pub fn define_with<F:TransformFn>(&mut self, path:&str, code:&str, transform:F) {
+        let transform = Some(Rc::new(transform) as Transform);
+        match self.sources.get_mut(path) {
+            Some(source) => if source.transform.is_none() {
+                self.version     += 1;
+                source.version    = self.version;
+                source.transform  = transform;
+                source.update_code();
+            }
+            None => {
+                self.version += 1;
+                let source = Source::new(code.into(),transform,self.version);
+                self.sources.insert(path.into(),source);
+            }
+        }
+    }
+
+    /// Sets the code of the source. Returns `true` if the code was changed.
+    pub fn set(&mut self, path:&str, code:&str) -> bool {
+        match self.sources.get_mut(path) {
+            Some(source) => {
+                let changed = source.raw != code;
+                if changed {
+                    self.version   += 1;
+                    source.version  = self.version;
+                    source.raw      = code.into();
+                    source.update_code();
+                }
+                changed
+            }
+            None => {
+                self.version += 1;
+                let source = Source::new(code.into(),None,self.version);
+                self.sources.insert(path.into(),source);
+                true
+            }
+        }
+    }
+
+    /// The code of the source, after it was transformed.
+    pub fn get(&self, path:&str) -> Option<String> {
+        self.sources.get(path).map(|source| source.code.clone())
+    }
+
+    /// Paths of all the sources.
+    pub fn paths(&self) -> Vec<String> {
+        self.sources.keys().cloned().collect()
+    }
+
+    /// The version of the registry. It is bumped with every change of any source.
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// The version of the source, or `0` if it was not defined.
+    pub fn source_version(&self, path:&str) -> usize {
+        self.sources.get(path).map(|source| source.version).unwrap_or(0)
+    }
+
+    /// Checks whether any of the sources changed since the provided versions were recorded.
+    pub fn changed_since(&self, includes:&BTreeMap<String,usize>) -> bool {
+        includes.iter().any(|(path,version)| self.source_version(path) != *version)
+    }
+
+    /// Replaces all include directives with the included sources, recursively.
+    pub fn expand(&self, code:&str) -> Expansion {
+        let mut expansion = Expansion::default();
+        let mut stack     = Vec::new();
+        expansion.code    = self.expand_into(code,&mut stack,&mut expansion);
+        expansion
+    }
+}}
+
+
+// === Private API ===
+
+impl ShaderSourcesData {
+    fn expand_into
+    (&self, code:&str, stack:&mut Vec<String>, expansion:&mut Expansion) -> String {
+        let mut lines = Vec::new();
+        for line in code.lines() {
+            match parse_include(line) {
+                None            => lines.push(line.to_string()),
+                Some(Err(err))  => {
+                    expansion.errors.push(err);
+                    lines.push(line.to_string());
+                }
+                Some(Ok(path)) => {
+                    let version = self.source_version(&path);
+                    expansion.includes.insert(path.clone(),version);
+                    let recursive = stack.contains(&path) || stack.len() >= MAX_INCLUDE_DEPTH;
+                    match self.sources.get(&path) {
+                        _ if recursive => {
+                            expansion.errors.push(Error::RecursiveInclude {path});
+                            lines.push(line.to_string());
+                        }
+                        None => {
+                            expansion.errors.push(Error::MissingSource {path});
+                            lines.push(line.to_string());
+                        }
+                        Some(source) => {
+                            stack.push(path);
+                            lines.push(self.expand_into(&source.code,stack,expansion));
+                            stack.pop();
+                        }
+                    }
+                }
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_includes() {
+        assert_eq!(parse_include("  #include \"lib/math.glsl\" "),Some(Ok("lib/math.glsl".into())));
+        assert_eq!(parse_include("float x = 1.0;"),None);
+        let line = "#include math".to_string();
+        assert_eq!(parse_include(&line),Some(Err(Error::MalformedInclude {line})));
+    }
+
+    #[test]
+    fn nested_includes() {
+        let sources = ShaderSources::new();
+        sources.define("a","float a;\n#include \"b\"");
+        sources.define("b","float b;");
+        let expansion = sources.expand("#include \"a\"\nvoid main() {}");
+        assert_eq!(expansion.code,"float a;\nfloat b;\nvoid main() {}");
+        assert_eq!(expansion.errors,vec![]);
+        let versions = expansion.includes.values().cloned().collect_vec();
+        assert_eq!(versions,vec![1,2]);
+    }
+
+    #[test]
+    fn invalid_includes() {
+        let sources = ShaderSources::new();
+        sources.define("a","#include \"a\"");
+        let expansion = sources.expand("#include \"a\"\n#include \"b\"");
+        assert_eq!(expansion.code,"#include \"a\"\n#include \"b\"");
+        let recursive = Error::RecursiveInclude {path:"a".into()};
+        let missing   = Error::MissingSource    {path:"b".into()};
+        assert_eq!(expansion.errors,vec![recursive,missing]);
+        assert_eq!(expansion.includes.get("b"),Some(&0));
+    }
+
+    #[test]
+    fn versions_and_transforms() {
+        let sources = ShaderSources::new();
+        sources.define_with("lib","x",|code| format!("// lib\n{}",code));
+        let expansion = sources.expand("#include \"lib\"");
+        assert_eq!(expansion.code,"// lib\nx");
+        assert!(!sources.changed_since(&expansion.includes));
+        sources.define("lib","ignored");
+        assert!(!sources.set("lib","x"));
+        assert!(!sources.changed_since(&expansion.includes));
+        assert!(sources.set("lib","y"));
+        assert!(sources.changed_since(&expansion.includes));
+        assert_eq!(sources.get("lib"),Some("// lib\ny".into()));
+    }
+}